@@ -0,0 +1,300 @@
+/// Checks that the static addresses a container is about to get are not
+/// already in use
+///
+/// Two holders of one address make ARP flap between them, which shows up as
+/// intermittent packet loss rather than as an error. Addresses are checked
+/// against every managed container's config, running or not, the addresses
+/// handed out from `network.ip_range` and the host's own interfaces. With
+/// `network.probe_address_conflicts` an `arping` duplicate address probe runs
+/// on the bridge too, for hosts outside this node's control.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use container_manager::ContainerManager;
+use models::{ContainerConfig, ContainerNetworkInterface, NetworkInterface};
+use network::{InterfaceManager, Ipam};
+
+/// How long the live probe listens for an answer
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// An address that is already held by something else
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AddressConflict {
+    pub address: IpAddr,
+    /// What holds it, e.g. `container web`
+    pub holder: String,
+}
+
+impl fmt::Display for AddressConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Address {} is already used by {}",
+            self.address, self.holder
+        )
+    }
+}
+
+/// `?skip_conflict_check=true`, for addresses shared on purpose (VRRP and
+/// the like)
+#[derive(Debug, Default, Deserialize)]
+pub struct ConflictCheckQuery {
+    #[serde(default)]
+    pub skip_conflict_check: bool,
+}
+
+/// Everything already holding addresses on this node
+#[derive(Debug, Default)]
+pub struct AddressHolders {
+    pub containers: Vec<(String, ContainerConfig)>,
+    pub allocations: BTreeMap<Ipv4Addr, String>,
+    pub host_interfaces: Vec<NetworkInterface>,
+}
+
+impl AddressHolders {
+    /// Collect the holders; sources that cannot be read are skipped
+    pub async fn collect(ipam: Option<&Ipam>) -> Self {
+        let mut containers = Vec::new();
+        match ContainerManager::list().await {
+            Ok(names) => {
+                for name in names {
+                    match ContainerManager::effective_config(&name).await {
+                        Ok((_, config)) => containers.push((name, config)),
+                        Err(e) => debug!("Could not read config of container {}: {}", name, e),
+                    }
+                }
+            }
+            Err(e) => warn!("Address conflict check could not list containers: {}", e),
+        }
+        let host_interfaces = InterfaceManager::list(false).await.unwrap_or_else(|e| {
+            warn!(
+                "Address conflict check could not list host interfaces: {}",
+                e
+            );
+            vec![]
+        });
+
+        Self {
+            containers,
+            allocations: ipam.map(Ipam::allocations).unwrap_or_default(),
+            host_interfaces,
+        }
+    }
+
+    /// The first address of `interfaces` that is already held; the
+    /// container's own `ip_range` allocations do not count
+    pub fn conflict(
+        &self,
+        container: &str,
+        interfaces: &[ContainerNetworkInterface],
+    ) -> Option<AddressConflict> {
+        static_addresses(interfaces)
+            .into_iter()
+            .find_map(|address| {
+                self.holder(container, address)
+                    .map(|holder| AddressConflict { address, holder })
+            })
+    }
+
+    fn holder(&self, container: &str, address: IpAddr) -> Option<String> {
+        if let Some((name, _)) = self
+            .containers
+            .iter()
+            .find(|(_, config)| static_addresses(&config.network_interfaces).contains(&address))
+        {
+            return Some(format!("container {}", name));
+        }
+        if let IpAddr::V4(v4) = address {
+            if let Some(owner) = self
+                .allocations
+                .get(&v4)
+                .filter(|owner| *owner != container)
+            {
+                return Some(format!(
+                    "container {} (allocated from network.ip_range)",
+                    owner
+                ));
+            }
+        }
+        self.host_interfaces
+            .iter()
+            .find(|interface| addresses(&interface.ip_addresses).contains(&address))
+            .map(|interface| format!("host interface {}", interface.name))
+    }
+}
+
+/// Check the static addresses of `interfaces`, about to be given to
+/// `container`, against everything else on the node
+pub async fn check(
+    container: &str,
+    interfaces: &[ContainerNetworkInterface],
+    ipam: Option<&Ipam>,
+    probe: bool,
+) -> Result<(), AddressConflict> {
+    if static_addresses(interfaces).is_empty() {
+        return Ok(());
+    }
+    if let Some(conflict) = AddressHolders::collect(ipam)
+        .await
+        .conflict(container, interfaces)
+    {
+        info!("Refusing address for container {}: {}", container, conflict);
+        return Err(conflict);
+    }
+    if probe {
+        for interface in interfaces {
+            for address in static_addresses(std::slice::from_ref(interface)) {
+                if let IpAddr::V4(v4) = address {
+                    if answered_on(&interface.bridge, v4).await {
+                        return Err(AddressConflict {
+                            address,
+                            holder: format!("a host on bridge {}", interface.bridge),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Static addresses without their prefix length; `auto`, `dhcp` and the
+/// like are skipped
+pub fn static_addresses(interfaces: &[ContainerNetworkInterface]) -> Vec<IpAddr> {
+    addresses(
+        interfaces
+            .iter()
+            .flat_map(|interface| [&interface.ipv4, &interface.ipv6])
+            .flatten(),
+    )
+}
+
+fn addresses<'a>(cidrs: impl IntoIterator<Item = &'a String>) -> Vec<IpAddr> {
+    cidrs
+        .into_iter()
+        .filter_map(|address| address.split('/').next()?.parse().ok())
+        .collect()
+}
+
+/// Whether anything answers ARP for `address` on `bridge`, per `arping`'s
+/// duplicate address detection; a probe that cannot run finds nothing
+async fn answered_on(bridge: &str, address: Ipv4Addr) -> bool {
+    let output = tokio::process::Command::new("arping")
+        .args(["-D", "-q", "-c", "2", "-w"])
+        .arg(PROBE_TIMEOUT.as_secs().to_string())
+        .args(["-I", bridge])
+        .arg(address.to_string())
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(PROBE_TIMEOUT + Duration::from_secs(1), output).await {
+        // 1 means a reply came back; 2 is an error such as a missing bridge
+        Ok(Ok(output)) => output.status.code() == Some(1),
+        Ok(Err(e)) => {
+            warn!("Could not probe {} on {}: {}", address, bridge, e);
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use models::{InterfaceStatus, InterfaceType};
+
+    fn interface(ipv4: Option<&str>, ipv6: Option<&str>) -> ContainerNetworkInterface {
+        ContainerNetworkInterface {
+            name: "eth0".to_string(),
+            bridge: "lxcbr0".to_string(),
+            ipv4: ipv4.map(str::to_string),
+            ipv6: ipv6.map(str::to_string),
+            mac: None,
+            gateway_v4: None,
+            gateway_v6: None,
+            routes: vec![],
+        }
+    }
+
+    fn config(interfaces: Vec<ContainerNetworkInterface>) -> ContainerConfig {
+        ContainerConfig {
+            network_interfaces: interfaces,
+            ..Default::default()
+        }
+    }
+
+    fn holders() -> AddressHolders {
+        AddressHolders {
+            containers: vec![(
+                "web".to_string(),
+                config(vec![interface(Some("10.0.3.10/24"), Some("fd00::10/64"))]),
+            )],
+            allocations: BTreeMap::from([
+                ("10.0.3.20".parse().unwrap(), "db".to_string()),
+                ("10.0.3.21".parse().unwrap(), "cache".to_string()),
+            ]),
+            host_interfaces: vec![NetworkInterface {
+                name: "lxcbr0".to_string(),
+                interface_type: InterfaceType::Bridge,
+                status: InterfaceStatus::Up,
+                ip_addresses: vec!["10.0.3.1/24".to_string()],
+                mac_address: None,
+                master: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_conflicting_holders_are_named() {
+        let holders = holders();
+        let conflict = |ipv4: &str, ipv6: Option<&str>| {
+            holders
+                .conflict("cache", &[interface(Some(ipv4), ipv6)])
+                .map(|conflict| conflict.to_string())
+        };
+
+        assert_eq!(
+            conflict("10.0.3.10/16", None).as_deref(),
+            Some("Address 10.0.3.10 is already used by container web")
+        );
+        assert_eq!(
+            conflict("10.0.3.99/24", Some("fd00::10/64")).as_deref(),
+            Some("Address fd00::10 is already used by container web")
+        );
+        assert_eq!(
+            conflict("10.0.3.20/24", None).as_deref(),
+            Some(
+                "Address 10.0.3.20 is already used by container db \
+                 (allocated from network.ip_range)"
+            )
+        );
+        assert_eq!(
+            conflict("10.0.3.1/24", None).as_deref(),
+            Some("Address 10.0.3.1 is already used by host interface lxcbr0")
+        );
+    }
+
+    #[test]
+    fn test_free_addresses_pass() {
+        let holders = holders();
+        // The container's own allocation is what it is being given
+        assert_eq!(
+            holders.conflict("cache", &[interface(Some("10.0.3.21/24"), None)]),
+            None
+        );
+        assert_eq!(
+            holders.conflict("cache", &[interface(Some("10.0.3.99/24"), None)]),
+            None
+        );
+        assert_eq!(
+            holders.conflict("cache", &[interface(Some("auto"), Some("dhcp"))]),
+            None
+        );
+    }
+}
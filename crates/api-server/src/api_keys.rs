@@ -0,0 +1,331 @@
+/// Scoped API keys
+///
+/// A key is presented in the `X-API-Key` header and grants a fixed set of
+/// permissions, like a role. Keys come from `security.api_keys` in the config
+/// or are created through `POST /api/v1/auth/api-keys`; either way only the
+/// SHA-256 of the key is kept, so the raw key is shown once, at creation.
+/// Keys created through the API are saved to `paths.api_keys`; when each key
+/// was last used is only kept in memory.
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::config::ApiKeyConfig;
+use crate::json_file;
+use crate::rbac::Permission;
+
+/// Prefix of generated keys, so they are recognisable in secret scanners
+pub const API_KEY_PREFIX: &str = "hvk_";
+
+/// Hex SHA-256 of a key, as stored in `key_hash`
+pub fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Short, non-reversible identifier of a key for listings and logs
+pub fn fingerprint(key_hash: &str) -> &str {
+    &key_hash[..key_hash.len().min(16)]
+}
+
+/// Whether `value` has the shape of a `key_hash`
+pub fn is_key_hash(value: &str) -> bool {
+    value.len() == 64
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let key: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}{}", API_KEY_PREFIX, key)
+}
+
+/// A key created through the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    pub key_hash: String,
+    pub permissions: Vec<Permission>,
+    /// Cleared when the key is revoked
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// A configured or created key as listed by `GET /api/v1/auth/api-keys`
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKeySummary {
+    /// Set on keys created through the API, which can be revoked by id
+    pub id: Option<Uuid>,
+    pub name: String,
+    pub fingerprint: String,
+    pub permissions: Vec<Permission>,
+    pub enabled: bool,
+    pub created_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// "config" or "api"
+    pub source: &'static str,
+}
+
+impl From<ApiKey> for ApiKeySummary {
+    fn from(key: ApiKey) -> Self {
+        Self {
+            id: Some(key.id),
+            fingerprint: fingerprint(&key.key_hash).to_string(),
+            name: key.name,
+            permissions: key.permissions,
+            enabled: key.enabled,
+            created_at: Some(key.created_at),
+            expires_at: key.expires_at,
+            last_used_at: None,
+            source: "api",
+        }
+    }
+}
+
+/// What an accepted key grants
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyGrant {
+    pub name: String,
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ApiKeyError {
+    #[error("API key not found")]
+    NotFound,
+
+    #[error("API key is disabled")]
+    Disabled,
+
+    #[error("API key has expired")]
+    Expired,
+
+    #[error("An API key named '{0}' already exists")]
+    NameTaken(String),
+
+    #[error("Failed to save API keys: {0}")]
+    Save(String),
+}
+
+/// Created keys and when each key was last used; the default store keeps
+/// created keys in memory only
+#[derive(Default)]
+pub struct ApiKeyStore {
+    keys: Mutex<BTreeMap<Uuid, ApiKey>>,
+    /// Last use by key hash
+    last_used: Mutex<HashMap<String, DateTime<Utc>>>,
+    /// Where created keys are saved; None keeps them in memory
+    path: Option<PathBuf>,
+}
+
+impl ApiKeyStore {
+    /// Load the keys saved at `path`; a missing or unreadable file starts
+    /// with none
+    pub fn load(path: &Path) -> Self {
+        Self {
+            keys: Mutex::new(json_file::load(path, "API keys")),
+            last_used: Mutex::new(HashMap::new()),
+            path: Some(path.to_path_buf()),
+        }
+    }
+
+    /// Create a key named `name`; returns the raw key, which is not kept
+    pub fn create(
+        &self,
+        name: &str,
+        permissions: Vec<Permission>,
+        expires_at: Option<DateTime<Utc>>,
+        configured: &[ApiKeyConfig],
+    ) -> Result<(String, ApiKey), ApiKeyError> {
+        let mut keys = self.keys.lock().unwrap();
+        if configured.iter().any(|key| key.name == name)
+            || keys.values().any(|key| key.name == name)
+        {
+            return Err(ApiKeyError::NameTaken(name.to_string()));
+        }
+
+        let key = generate_key();
+        let record = ApiKey {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            key_hash: hash_key(&key),
+            permissions,
+            enabled: true,
+            created_at: Utc::now(),
+            expires_at,
+        };
+        keys.insert(record.id, record.clone());
+        if let Err(e) = self.save(&keys) {
+            keys.remove(&record.id);
+            return Err(e);
+        }
+        Ok((key, record))
+    }
+
+    /// Disable a created key; revoking it again is a no-op
+    pub fn revoke(&self, id: Uuid) -> Result<ApiKey, ApiKeyError> {
+        let mut keys = self.keys.lock().unwrap();
+        let key = keys.get_mut(&id).ok_or(ApiKeyError::NotFound)?;
+        if !key.enabled {
+            return Ok(key.clone());
+        }
+        key.enabled = false;
+        let revoked = key.clone();
+        if let Err(e) = self.save(&keys) {
+            if let Some(key) = keys.get_mut(&id) {
+                key.enabled = true;
+            }
+            return Err(e);
+        }
+        Ok(revoked)
+    }
+
+    /// Configured keys followed by created keys, oldest first
+    pub fn list(&self, configured: &[ApiKeyConfig]) -> Vec<ApiKeySummary> {
+        let last_used = self.last_used.lock().unwrap();
+        let mut created: Vec<_> = self.keys.lock().unwrap().values().cloned().collect();
+        created.sort_by_key(|key| key.created_at);
+
+        let configured = configured.iter().map(|key| ApiKeySummary {
+            id: None,
+            name: key.name.clone(),
+            fingerprint: fingerprint(&key.key_hash).to_string(),
+            permissions: key.permissions.clone(),
+            enabled: key.enabled,
+            created_at: None,
+            expires_at: key.expires_at,
+            last_used_at: last_used.get(&key.key_hash).copied(),
+            source: "config",
+        });
+        let created = created.into_iter().map(|key| ApiKeySummary {
+            last_used_at: last_used.get(&key.key_hash).copied(),
+            ..ApiKeySummary::from(key)
+        });
+        configured.chain(created).collect()
+    }
+
+    /// Check a presented key against the configured and created keys,
+    /// recording its use
+    pub fn authenticate(
+        &self,
+        configured: &[ApiKeyConfig],
+        key: &str,
+        now: DateTime<Utc>,
+    ) -> Result<ApiKeyGrant, ApiKeyError> {
+        let key_hash = hash_key(key);
+        let (enabled, expires_at, grant) = match configured.iter().find(|k| k.key_hash == key_hash)
+        {
+            Some(k) => (k.enabled, k.expires_at, grant(&k.name, &k.permissions)),
+            None => {
+                let keys = self.keys.lock().unwrap();
+                let k = keys
+                    .values()
+                    .find(|k| k.key_hash == key_hash)
+                    .ok_or(ApiKeyError::NotFound)?;
+                (k.enabled, k.expires_at, grant(&k.name, &k.permissions))
+            }
+        };
+        if !enabled {
+            return Err(ApiKeyError::Disabled);
+        }
+        if expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(ApiKeyError::Expired);
+        }
+
+        self.last_used.lock().unwrap().insert(key_hash, now);
+        Ok(grant)
+    }
+
+    fn save(&self, keys: &BTreeMap<Uuid, ApiKey>) -> Result<(), ApiKeyError> {
+        match &self.path {
+            Some(path) => json_file::save(path, keys).map_err(|e| ApiKeyError::Save(e.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+fn grant(name: &str, permissions: &[Permission]) -> ApiKeyGrant {
+    ApiKeyGrant {
+        name: name.to_string(),
+        permissions: permissions.to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_created_keys_are_stored_hashed_and_revocable() {
+        let path = std::env::temp_dir()
+            .join(format!("orchestrator_api_keys_{}", Uuid::new_v4()))
+            .join("api-keys.json");
+        let store = ApiKeyStore::load(&path);
+        let (key, record) = store
+            .create("ci", vec![Permission::ContainerRead], None, &[])
+            .unwrap();
+        assert!(key.starts_with(API_KEY_PREFIX));
+        assert_eq!(record.key_hash, hash_key(&key));
+        assert!(!std::fs::read_to_string(&path).unwrap().contains(&key));
+        assert_eq!(
+            store.create("ci", vec![], None, &[]).unwrap_err(),
+            ApiKeyError::NameTaken("ci".to_string())
+        );
+
+        // Created keys survive a restart
+        let store = ApiKeyStore::load(&path);
+        let grant = store.authenticate(&[], &key, Utc::now()).unwrap();
+        assert_eq!(grant.permissions, [Permission::ContainerRead]);
+        assert!(store.list(&[])[0].last_used_at.is_some());
+
+        store.revoke(record.id).unwrap();
+        assert_eq!(
+            store.authenticate(&[], &key, Utc::now()),
+            Err(ApiKeyError::Disabled)
+        );
+        assert!(!ApiKeyStore::load(&path).list(&[])[0].enabled);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_configured_keys_expire() {
+        let now = Utc::now();
+        let configured = [ApiKeyConfig {
+            name: "monitoring".to_string(),
+            key_hash: hash_key("monitoring-key"),
+            permissions: vec![Permission::SystemRead],
+            enabled: true,
+            expires_at: Some(now + Duration::days(1)),
+        }];
+        let store = ApiKeyStore::default();
+
+        assert!(store
+            .authenticate(&configured, "monitoring-key", now)
+            .is_ok());
+        assert_eq!(
+            store.authenticate(&configured, "monitoring-key", now + Duration::days(2)),
+            Err(ApiKeyError::Expired)
+        );
+        assert_eq!(
+            store.authenticate(&configured, "guessed-key", now),
+            Err(ApiKeyError::NotFound)
+        );
+        assert_eq!(store.list(&configured)[0].source, "config");
+    }
+}
@@ -1,9 +1,23 @@
 /// Audit logging module for tracking all system operations
-use chrono::Utc;
+use actix_web::HttpRequest;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
 use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+use crate::auth::AuthenticatedUser;
+use crate::config::{AuditForwardTarget, AuditForwarderConfig, AuditRetentionConfig};
+use crate::observability::MetricsCollector;
+use crate::tasks::TaskManager;
+use crate::{client_ip, request_tracing};
+
+/// Upper bound for the delay between forwarding attempts
+const MAX_FORWARD_BACKOFF: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AuditAction {
     // Container actions
@@ -16,6 +30,9 @@ pub enum AuditAction {
     ContainerSnapshotRestored,
     ContainerSnapshotDeleted,
     ContainerCloned,
+    ContainerFrozen,
+    ContainerUnfrozen,
+    ContainerHealthChanged,
 
     // User actions
     UserCreated,
@@ -23,6 +40,10 @@ pub enum AuditAction {
     UserDeleted,
     UserLogin,
     UserLogout,
+    ServiceTokenCreated,
+    ServiceTokenRevoked,
+    ApiKeyCreated,
+    ApiKeyRevoked,
 
     // Cluster actions
     ClusterJoined,
@@ -33,6 +54,8 @@ pub enum AuditAction {
     // Storage actions
     StoragePoolCreated,
     StoragePoolDeleted,
+    StoragePoolUsageChanged,
+    StoragePoolStateChanged,
     VolumeCreated,
     VolumeDeleted,
 
@@ -42,10 +65,15 @@ pub enum AuditAction {
     NetworkInterfaceCreated,
     NetworkInterfaceDeleted,
 
+    // Image cache actions
+    ImagePulled,
+    ImageDeleted,
+
     // System actions
     ConfigurationChanged,
     SystemStarted,
     SystemStopped,
+    AuditLogsPurged,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,6 +87,10 @@ pub struct AuditLog {
     pub id: Uuid,
     pub timestamp: chrono::DateTime<chrono::Utc>,
     pub user: Option<String>,
+    /// Service token the request authenticated with, so a leaked token can
+    /// be traced and revoked on its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_id: Option<Uuid>,
     pub action: AuditAction,
     pub resource_type: String,
     pub resource_id: Option<String>,
@@ -68,10 +100,20 @@ pub struct AuditLog {
     pub details: Option<String>,
 }
 
+impl AuditLog {
+    /// Whether the entry is part of `username`'s own trail: their actions,
+    /// or actions on their account
+    pub fn concerns(&self, username: &str) -> bool {
+        self.user.as_deref() == Some(username)
+            || (self.resource_type == "user" && self.resource_id.as_deref() == Some(username))
+    }
+}
+
 /// In-memory audit log storage (in production, use a persistent store)
 pub struct AuditLogger {
     logs: Mutex<Vec<AuditLog>>,
     max_logs: usize,
+    forwarder: OnceLock<AuditForwarder>,
 }
 
 /// Builder for creating audit log entries
@@ -79,6 +121,7 @@ pub struct AuditLogger {
 #[allow(dead_code)]
 pub struct AuditLogBuilder {
     user: Option<String>,
+    token_id: Option<Uuid>,
     action: Option<AuditAction>,
     resource_type: Option<String>,
     resource_id: Option<String>,
@@ -99,6 +142,29 @@ impl AuditLogBuilder {
         self
     }
 
+    /// Record the authenticated caller, including the service token used
+    /// and the request's correlation ID
+    pub fn actor(mut self, actor: &AuthenticatedUser) -> Self {
+        self.user = Some(actor.username.clone());
+        self.token_id = actor.token_id;
+        self.correlation_id = actor.correlation_id.or(self.correlation_id);
+        self.ip_address = actor
+            .ip_address
+            .map(|ip| ip.to_string())
+            .or(self.ip_address);
+        self
+    }
+
+    /// Tag the entry with the correlation ID and client address of `req`,
+    /// for requests made without an authenticated caller
+    pub fn request(mut self, req: &HttpRequest) -> Self {
+        self.correlation_id = request_tracing::correlation_id(req).or(self.correlation_id);
+        self.ip_address = client_ip::client_ip(req)
+            .map(|ip| ip.to_string())
+            .or(self.ip_address);
+        self
+    }
+
     pub fn action(mut self, action: AuditAction) -> Self {
         self.action = Some(action);
         self
@@ -139,12 +205,17 @@ impl AuditLogBuilder {
             id: Uuid::new_v4(),
             timestamp: Utc::now(),
             user: self.user,
+            token_id: self.token_id,
             action: self.action.ok_or("Action is required")?,
             resource_type: self.resource_type.ok_or("Resource type is required")?,
             resource_id: self.resource_id,
             result: self.result.ok_or("Result is required")?,
             ip_address: self.ip_address,
-            correlation_id: self.correlation_id,
+            // Entries made while serving a request belong to it even when
+            // the caller did not pass the request in
+            correlation_id: self
+                .correlation_id
+                .or_else(request_tracing::current_correlation_id),
             details: self.details,
         })
     }
@@ -155,6 +226,15 @@ impl AuditLogger {
         Self {
             logs: Mutex::new(Vec::new()),
             max_logs,
+            forwarder: OnceLock::new(),
+        }
+    }
+
+    /// Also hand every new entry to `forwarder`; only the first call has an
+    /// effect
+    pub fn set_forwarder(&self, forwarder: AuditForwarder) {
+        if self.forwarder.set(forwarder).is_err() {
+            warn!("Audit forwarder already configured");
         }
     }
 
@@ -167,6 +247,10 @@ impl AuditLogger {
     /// Log an audit event using the builder pattern
     #[allow(dead_code)]
     pub fn log_entry(&self, log: AuditLog) {
+        if let Some(forwarder) = self.forwarder.get() {
+            forwarder.forward(log.clone());
+        }
+
         let mut logs = self.logs.lock().unwrap();
         logs.push(log);
 
@@ -182,6 +266,7 @@ impl AuditLogger {
         user: Option<String>,
         action: Option<AuditAction>,
         resource_type: Option<String>,
+        correlation_id: Option<Uuid>,
         limit: Option<usize>,
     ) -> Vec<AuditLog> {
         let logs = self.logs.lock().unwrap();
@@ -203,13 +288,16 @@ impl AuditLogger {
                         return false;
                     }
                 }
+                if correlation_id.is_some() && log.correlation_id != correlation_id {
+                    return false;
+                }
                 true
             })
             .cloned()
             .collect();
 
         // Sort by timestamp descending (most recent first)
-        filtered.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        filtered.sort_by_key(|log| std::cmp::Reverse(log.timestamp));
 
         // Limit results
         if let Some(limit) = limit {
@@ -219,10 +307,333 @@ impl AuditLogger {
         filtered
     }
 
+    /// Get the most recent audit logs for a single resource
+    pub fn get_resource_logs(
+        &self,
+        resource_type: &str,
+        resource_id: &str,
+        limit: usize,
+    ) -> Vec<AuditLog> {
+        let logs = self.logs.lock().unwrap();
+        let mut filtered: Vec<AuditLog> = logs
+            .iter()
+            .filter(|log| {
+                log.resource_type == resource_type
+                    && log.resource_id.as_deref() == Some(resource_id)
+            })
+            .cloned()
+            .collect();
+
+        filtered.sort_by_key(|log| std::cmp::Reverse(log.timestamp));
+        filtered.truncate(limit);
+        filtered
+    }
+
     /// Get the total number of logs
     pub fn count(&self) -> usize {
         self.logs.lock().unwrap().len()
     }
+
+    /// Number of logs in `username`'s own trail
+    pub fn count_concerning(&self, username: &str) -> usize {
+        self.logs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|log| log.concerns(username))
+            .count()
+    }
+
+    /// Remove entries older than `cutoff`; returns how many were removed
+    pub fn purge_before(&self, cutoff: DateTime<Utc>) -> usize {
+        let mut logs = self.logs.lock().unwrap();
+        let before = logs.len();
+        logs.retain(|log| log.timestamp >= cutoff);
+        before - logs.len()
+    }
+
+    /// Remove the oldest entries until the remaining ones, serialized as JSON
+    /// lines, fit in `max_bytes`; returns how many were removed
+    pub fn purge_to_size(&self, max_bytes: u64) -> usize {
+        let mut logs = self.logs.lock().unwrap();
+        logs.sort_by_key(|log| log.timestamp);
+
+        let sizes: Vec<u64> = logs.iter().map(Self::entry_size).collect();
+        let mut total: u64 = sizes.iter().sum();
+        let mut excess = 0;
+        for size in sizes {
+            if total <= max_bytes {
+                break;
+            }
+            total -= size;
+            excess += 1;
+        }
+
+        logs.drain(..excess);
+        excess
+    }
+
+    /// Apply a retention policy as of `now`; returns how many entries were removed
+    pub fn apply_retention(&self, retention: &AuditRetentionConfig, now: DateTime<Utc>) -> usize {
+        let mut removed = 0;
+        if let Some(days) = retention.max_age_days {
+            removed += self.purge_before(now - chrono::Duration::days(i64::from(days)));
+        }
+        if let Some(mb) = retention.max_total_size_mb {
+            removed += self.purge_to_size(mb * 1024 * 1024);
+        }
+        removed
+    }
+
+    fn entry_size(log: &AuditLog) -> u64 {
+        // One JSON line per entry, as an on-disk audit log would store it
+        serde_json::to_vec(log).map_or(0, |bytes| bytes.len() as u64 + 1)
+    }
+}
+
+/// Periodically prune the in-memory audit entries according to the
+/// retention policy; forwarded copies are out of its reach
+pub fn register_retention(
+    tasks: &TaskManager,
+    logger: Arc<AuditLogger>,
+    retention: AuditRetentionConfig,
+) {
+    info!(
+        "Audit retention enabled: max age {:?} days, max size {:?} MiB, every {}s",
+        retention.max_age_days, retention.max_total_size_mb, retention.purge_interval_secs
+    );
+
+    let interval = Duration::from_secs(retention.purge_interval_secs.max(1));
+    let retention = Arc::new(retention);
+    tasks.register_interval("audit-retention", interval, move || {
+        purge_by_retention(logger.clone(), retention.clone())
+    });
+}
+
+/// Apply the retention policy once, auditing what it removed
+async fn purge_by_retention(
+    logger: Arc<AuditLogger>,
+    retention: Arc<AuditRetentionConfig>,
+) -> Result<(), String> {
+    let removed = logger.apply_retention(&retention, Utc::now());
+    if removed == 0 {
+        return Ok(());
+    }
+    info!("Audit retention removed {} entries", removed);
+
+    match AuditLogger::builder()
+        .user("audit-retention".to_string())
+        .action(AuditAction::AuditLogsPurged)
+        .resource_type("audit".to_string())
+        .result(AuditResult::Success)
+        .details(format!("Removed {} entries by retention policy", removed))
+        .build()
+    {
+        Ok(log) => logger.log_entry(log),
+        Err(e) => warn!("Failed to audit retention purge: {}", e),
+    }
+    Ok(())
+}
+
+/// Destination for forwarded audit entries
+pub trait AuditSink: Send + Sync {
+    fn send<'a>(&'a self, log: &'a AuditLog) -> BoxFuture<'a, Result<(), String>>;
+}
+
+/// POSTs each entry as JSON to a URL
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: String) -> Self {
+        let client = crate::outbound::client_builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("failed to build HTTP client");
+        Self { client, url }
+    }
+}
+
+impl AuditSink for WebhookSink {
+    fn send<'a>(&'a self, log: &'a AuditLog) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(&self.url)
+                .json(log)
+                .send()
+                .await
+                .map_err(|e| crate::outbound::describe_error(&self.url, &e))?;
+            if !response.status().is_success() {
+                return Err(format!("webhook answered {}", response.status()));
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Writes each entry as an RFC 5424 message to a UDP syslog server or the
+/// local /dev/log socket
+pub struct SyslogSink {
+    address: Option<String>,
+    hostname: String,
+}
+
+impl SyslogSink {
+    pub fn new(address: Option<String>) -> Self {
+        Self {
+            address,
+            hostname: gethostname::gethostname().to_string_lossy().to_string(),
+        }
+    }
+}
+
+impl AuditSink for SyslogSink {
+    fn send<'a>(&'a self, log: &'a AuditLog) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let message = syslog_message(log, &self.hostname);
+            match self.address {
+                Some(ref address) => {
+                    let socket = tokio::net::UdpSocket::bind("0.0.0.0:0")
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    socket
+                        .send_to(message.as_bytes(), address.as_str())
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+                None => {
+                    let socket = tokio::net::UnixDatagram::unbound().map_err(|e| e.to_string())?;
+                    socket
+                        .send_to(message.as_bytes(), "/dev/log")
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Format an entry for syslog: facility authpriv, warning severity for
+/// failed operations, the entry itself as JSON
+fn syslog_message(log: &AuditLog, hostname: &str) -> String {
+    let severity = match log.result {
+        AuditResult::Success => 6,
+        AuditResult::Failure(_) => 4,
+    };
+    format!(
+        "<{}>1 {} {} arm-hypervisor - audit - {}",
+        10 * 8 + severity,
+        log.timestamp
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        hostname,
+        serde_json::to_string(log).unwrap_or_default()
+    )
+}
+
+/// Build the sink selected in the config, if any
+pub fn sink_from_config(config: &AuditForwarderConfig) -> Option<Arc<dyn AuditSink>> {
+    match config.target {
+        AuditForwardTarget::None => None,
+        AuditForwardTarget::Webhook => config
+            .webhook_url
+            .clone()
+            .map(|url| Arc::new(WebhookSink::new(url)) as Arc<dyn AuditSink>),
+        AuditForwardTarget::Syslog => {
+            Some(Arc::new(SyslogSink::new(config.syslog_address.clone())))
+        }
+    }
+}
+
+/// Handle for queueing entries to the forwarding task
+///
+/// Queueing never blocks; when the queue is full the entry is dropped and
+/// counted as a forwarding failure.
+#[derive(Clone)]
+pub struct AuditForwarder {
+    queue: mpsc::Sender<AuditLog>,
+    metrics: Option<Arc<MetricsCollector>>,
+}
+
+impl AuditForwarder {
+    /// Create a forwarder and the receiving end to pass to [`run_forwarder`]
+    pub fn channel(
+        queue_size: usize,
+        metrics: Option<Arc<MetricsCollector>>,
+    ) -> (Self, mpsc::Receiver<AuditLog>) {
+        let (queue, receiver) = mpsc::channel(queue_size);
+        (Self { queue, metrics }, receiver)
+    }
+
+    pub fn forward(&self, log: AuditLog) {
+        if let Err(e) = self.queue.try_send(log) {
+            let reason = match e {
+                mpsc::error::TrySendError::Full(_) => "queue full",
+                mpsc::error::TrySendError::Closed(_) => "forwarder stopped",
+            };
+            warn!("Dropping audit entry for forwarding: {}", reason);
+            if let Some(ref metrics) = self.metrics {
+                metrics.record_audit_forward(false);
+            }
+        }
+    }
+}
+
+/// Deliver queued entries in order, retrying each with exponential backoff
+pub async fn run_forwarder(
+    mut queue: mpsc::Receiver<AuditLog>,
+    sink: Arc<dyn AuditSink>,
+    config: AuditForwarderConfig,
+    metrics: Option<Arc<MetricsCollector>>,
+) {
+    info!("Forwarding audit entries ({:?})", config.target);
+
+    while let Some(log) = queue.recv().await {
+        let delivered = deliver(
+            sink.as_ref(),
+            &log,
+            config.max_retries,
+            Duration::from_millis(config.initial_backoff_ms),
+        )
+        .await;
+        if let Some(ref metrics) = metrics {
+            metrics.record_audit_forward(delivered);
+        }
+    }
+}
+
+async fn deliver(
+    sink: &dyn AuditSink,
+    log: &AuditLog,
+    max_retries: u32,
+    initial_backoff: Duration,
+) -> bool {
+    let mut backoff = initial_backoff;
+    for attempt in 0..=max_retries {
+        match sink.send(log).await {
+            Ok(()) => return true,
+            Err(e) if attempt < max_retries => {
+                debug!(
+                    "Forwarding audit entry {} failed (attempt {}): {}",
+                    log.id,
+                    attempt + 1,
+                    e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_FORWARD_BACKOFF);
+            }
+            Err(e) => warn!(
+                "Giving up forwarding audit entry {} after {} attempt(s): {}",
+                log.id,
+                attempt + 1,
+                e
+            ),
+        }
+    }
+    false
 }
 
 impl Default for AuditLogger {
@@ -269,23 +680,50 @@ mod tests {
         assert_eq!(logger.count(), 2);
 
         // Get all logs
-        let all_logs = logger.get_logs(None, None, None, None);
+        let all_logs = logger.get_logs(None, None, None, None, None);
         assert_eq!(all_logs.len(), 2);
 
         // Filter by user
-        let admin_logs = logger.get_logs(Some("admin".to_string()), None, None, None);
+        let admin_logs = logger.get_logs(Some("admin".to_string()), None, None, None, None);
         assert_eq!(admin_logs.len(), 1);
         assert_eq!(admin_logs[0].user, Some("admin".to_string()));
 
         // Filter by resource type
-        let container_logs = logger.get_logs(None, None, Some("container".to_string()), None);
+        let container_logs = logger.get_logs(None, None, Some("container".to_string()), None, None);
         assert_eq!(container_logs.len(), 2);
 
         // Limit results
-        let limited = logger.get_logs(None, None, None, Some(1));
+        let limited = logger.get_logs(None, None, None, None, Some(1));
         assert_eq!(limited.len(), 1);
     }
 
+    #[test]
+    fn test_filter_by_correlation_id() {
+        let logger = AuditLogger::new(100);
+        let request_id = Uuid::new_v4();
+        for (action, correlation_id) in [
+            (AuditAction::ContainerStopped, request_id),
+            (AuditAction::ContainerDeleted, request_id),
+            (AuditAction::ContainerCreated, Uuid::new_v4()),
+        ] {
+            let log = AuditLogger::builder()
+                .user("admin".to_string())
+                .action(action)
+                .resource_type("container".to_string())
+                .result(AuditResult::Success)
+                .correlation_id(correlation_id)
+                .build()
+                .unwrap();
+            logger.log_entry(log);
+        }
+
+        let logs = logger.get_logs(None, None, None, Some(request_id), None);
+        assert_eq!(logs.len(), 2);
+        assert!(logs
+            .iter()
+            .all(|log| log.correlation_id == Some(request_id)));
+    }
+
     #[test]
     fn test_max_logs() {
         let logger = AuditLogger::new(5);
@@ -306,6 +744,83 @@ mod tests {
         assert_eq!(logger.count(), 5);
     }
 
+    fn dated_entry(user: &str, timestamp: DateTime<Utc>) -> AuditLog {
+        let mut log = AuditLogger::builder()
+            .user(user.to_string())
+            .action(AuditAction::UserLogin)
+            .resource_type("user".to_string())
+            .result(AuditResult::Success)
+            .build()
+            .unwrap();
+        log.timestamp = timestamp;
+        log
+    }
+
+    #[test]
+    fn test_retention_purges_entries_before_cutoff() {
+        let logger = AuditLogger::new(100);
+        let now = Utc::now();
+        for (user, days_ago) in [("old", 40), ("older", 60), ("recent", 5), ("today", 0)] {
+            logger.log_entry(dated_entry(user, now - chrono::Duration::days(days_ago)));
+        }
+
+        let retention = AuditRetentionConfig {
+            max_age_days: Some(30),
+            max_total_size_mb: None,
+            purge_interval_secs: 3600,
+            self_service: true,
+        };
+        assert_eq!(logger.apply_retention(&retention, now), 2);
+
+        assert_eq!(logger.count(), 2);
+        let survivors: Vec<String> = logger
+            .get_logs(None, None, None, None, None)
+            .into_iter()
+            .filter_map(|log| log.user)
+            .collect();
+        assert_eq!(survivors, ["today", "recent"]);
+
+        // Nothing left to purge
+        assert_eq!(logger.apply_retention(&retention, now), 0);
+    }
+
+    #[test]
+    fn test_purge_to_size_keeps_newest_entries() {
+        let logger = AuditLogger::new(100);
+        let now = Utc::now();
+        for i in 0..10 {
+            logger.log_entry(dated_entry(
+                &format!("user{}", i),
+                now - chrono::Duration::minutes(10 - i),
+            ));
+        }
+        let entry_size = AuditLogger::entry_size(&logger.get_logs(None, None, None, None, None)[0]);
+
+        // Room for roughly three entries
+        let removed = logger.purge_to_size(entry_size * 3 + entry_size / 2);
+        assert_eq!(removed, 7);
+        let newest = logger.get_logs(None, None, None, None, Some(1));
+        assert_eq!(newest[0].user.as_deref(), Some("user9"));
+    }
+
+    #[tokio::test]
+    async fn test_builder_picks_up_request_correlation_id() {
+        let request_id = Uuid::new_v4();
+        let build = || {
+            AuditLogger::builder()
+                .user("admin".to_string())
+                .action(AuditAction::ContainerStopped)
+                .resource_type("container".to_string())
+                .result(AuditResult::Success)
+                .build()
+                .unwrap()
+        };
+
+        let log = request_tracing::with_correlation_id(request_id, async { build() }).await;
+        assert_eq!(log.correlation_id, Some(request_id));
+        assert_eq!(build().correlation_id, None);
+    }
+
     #[test]
     fn test_audit_log_builder() {
         let log = AuditLogger::builder()
@@ -331,4 +846,116 @@ mod tests {
 
         assert!(log.is_err());
     }
+
+    /// Minimal HTTP server answering each request with the next status in
+    /// `statuses` and passing request bodies back through the channel
+    async fn mock_webhook(statuses: Vec<u16>) -> (String, mpsc::Receiver<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/audit", listener.local_addr().unwrap());
+        let (bodies, received) = mpsc::channel(8);
+
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                let body = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|l| {
+                                l.to_lowercase()
+                                    .strip_prefix("content-length:")
+                                    .map(|v| v.trim().parse::<usize>().unwrap())
+                            })
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            break body.to_string();
+                        }
+                    }
+                };
+                let response = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+                bodies.send(body).await.unwrap();
+            }
+        });
+
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn test_logged_event_is_forwarded_to_webhook() {
+        // The first attempt fails and must be retried
+        let (url, mut received) = mock_webhook(vec![503, 200]).await;
+        let metrics = Arc::new(MetricsCollector::new());
+        let config = AuditForwarderConfig {
+            target: AuditForwardTarget::Webhook,
+            webhook_url: Some(url.clone()),
+            initial_backoff_ms: 10,
+            ..Default::default()
+        };
+
+        let logger = AuditLogger::new(100);
+        let (forwarder, queue) = AuditForwarder::channel(config.queue_size, Some(metrics.clone()));
+        logger.set_forwarder(forwarder);
+        tokio::spawn(run_forwarder(
+            queue,
+            sink_from_config(&config).unwrap(),
+            config,
+            Some(metrics.clone()),
+        ));
+
+        let log = AuditLogger::builder()
+            .user("admin".to_string())
+            .action(AuditAction::ContainerDeleted)
+            .resource_type("container".to_string())
+            .resource_id("web".to_string())
+            .result(AuditResult::Success)
+            .build()
+            .unwrap();
+        let id = log.id;
+        logger.log_entry(log);
+
+        for _ in 0..2 {
+            let body = tokio::time::timeout(Duration::from_secs(5), received.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            let forwarded: AuditLog = serde_json::from_str(&body).unwrap();
+            assert_eq!(forwarded.id, id);
+        }
+
+        // The entry is counted once delivered, not per attempt
+        for _ in 0..100 {
+            if metrics.audit_forwarded_total.get() == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(metrics.audit_forwarded_total.get(), 1);
+        assert_eq!(metrics.audit_forward_failures_total.get(), 0);
+        // Forwarding does not replace the local store
+        assert_eq!(logger.count(), 1);
+    }
+
+    #[test]
+    fn test_syslog_message_format() {
+        let log = AuditLogger::builder()
+            .action(AuditAction::UserLogin)
+            .resource_type("user".to_string())
+            .result(AuditResult::Failure("bad password".to_string()))
+            .build()
+            .unwrap();
+        let message = syslog_message(&log, "node-a");
+        assert!(message.starts_with("<84>1 "));
+        assert!(message.contains(" node-a arm-hypervisor - audit - {"));
+    }
 }
@@ -0,0 +1,234 @@
+/// Field-level differences between the state before and after an update,
+/// kept in `AuditLog.details` as JSON
+///
+/// Objects are compared field by field and reported under dotted paths such
+/// as `config.memory_limit`; lists are compared as a whole. Values under
+/// sensitive keys (passwords, tokens, secrets, ...) and sensitive environment
+/// variables are replaced with a redaction marker before they are recorded.
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use container_manager::config::{LxcConfig, REDACTED};
+
+/// Changes recorded per entry; the rest are only counted
+pub const MAX_CHANGES: usize = 50;
+
+/// Characters of a value kept before it is cut short
+pub const MAX_VALUE_CHARS: usize = 256;
+
+/// Appended to values that were cut short
+pub const TRUNCATED: &str = "...[truncated]";
+
+/// One changed field
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Diff {
+    pub changes: Vec<FieldChange>,
+    /// Changes left out past `MAX_CHANGES`
+    #[serde(skip_serializing_if = "is_zero")]
+    pub truncated: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+impl Diff {
+    /// The fields that differ between `before` and `after`
+    pub fn between<T: Serialize>(before: &T, after: &T) -> Self {
+        let before = serde_json::to_value(before).unwrap_or(Value::Null);
+        let after = serde_json::to_value(after).unwrap_or(Value::Null);
+        let mut diff = Self::default();
+        diff.walk("", None, &before, &after);
+        diff
+    }
+
+    /// Record that a write-only field such as a password changed, without
+    /// any of its value
+    pub fn redacted_change(mut self, field: &str) -> Self {
+        self.push(FieldChange {
+            field: field.to_string(),
+            before: Value::String(REDACTED.to_string()),
+            after: Value::String(REDACTED.to_string()),
+        });
+        self
+    }
+
+    /// JSON for `AuditLog.details`
+    pub fn to_details(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    fn walk(&mut self, path: &str, key: Option<&str>, before: &Value, after: &Value) {
+        if let (Value::Object(before), Value::Object(after)) = (before, after) {
+            let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let field = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                self.walk(
+                    &field,
+                    Some(key),
+                    before.get(key).unwrap_or(&Value::Null),
+                    after.get(key).unwrap_or(&Value::Null),
+                );
+            }
+            return;
+        }
+        if before == after {
+            return;
+        }
+        let sensitive = key.is_some_and(LxcConfig::is_sensitive_key);
+        let record = |value: &Value| match sensitive {
+            true if !value.is_null() => Value::String(REDACTED.to_string()),
+            _ => truncate(redact(value.clone())),
+        };
+        self.push(FieldChange {
+            field: path.to_string(),
+            before: record(before),
+            after: record(after),
+        });
+    }
+
+    fn push(&mut self, change: FieldChange) {
+        if self.changes.len() < MAX_CHANGES {
+            self.changes.push(change);
+        } else {
+            self.truncated += 1;
+        }
+    }
+}
+
+/// Redact sensitive keys anywhere in `value`, and the values of `[name,
+/// value]` pairs with a sensitive name, as environment lists are
+fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| match LxcConfig::is_sensitive_key(&key) {
+                    true => (key, Value::String(REDACTED.to_string())),
+                    false => (key, redact(value)),
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Array(items) => match items.as_slice() {
+            [Value::String(name), _] if LxcConfig::is_sensitive_key(name) => {
+                Value::Array(vec![items[0].clone(), Value::String(REDACTED.to_string())])
+            }
+            _ => Value::Array(items.into_iter().map(redact).collect()),
+        },
+        value => value,
+    }
+}
+
+/// Cut values longer than `MAX_VALUE_CHARS` down to a marked string
+fn truncate(value: Value) -> Value {
+    let text = match value {
+        Value::String(ref text) => text.clone(),
+        ref value => value.to_string(),
+    };
+    if text.chars().count() <= MAX_VALUE_CHARS {
+        return value;
+    }
+    let kept: String = text.chars().take(MAX_VALUE_CHARS).collect();
+    Value::String(kept + TRUNCATED)
+}
+
+/// `details` as JSON when it holds a recorded diff or other JSON, for
+/// `?parse_details=true`; free text stays a string
+pub fn parse_details(details: &str) -> Value {
+    match serde_json::from_str(details) {
+        Ok(value @ (Value::Object(_) | Value::Array(_))) => value,
+        _ => Value::String(details.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_memory_limit_change_is_a_diff_entry() {
+        let before = json!({"name": "web", "config": {"memory_limit": 536870912, "cpu_limit": 2}});
+        let after = json!({"name": "web", "config": {"memory_limit": 1073741824, "cpu_limit": 2}});
+
+        let diff = Diff::between(&before, &after);
+        assert_eq!(
+            diff.changes,
+            [FieldChange {
+                field: "config.memory_limit".to_string(),
+                before: json!(536870912),
+                after: json!(1073741824),
+            }]
+        );
+        assert_eq!(
+            serde_json::from_str::<Value>(&diff.to_details()).unwrap(),
+            json!({"changes": [{
+                "field": "config.memory_limit",
+                "before": 536870912,
+                "after": 1073741824
+            }]})
+        );
+        assert_eq!(Diff::between(&before, &before), Diff::default());
+    }
+
+    #[test]
+    fn test_passwords_never_appear() {
+        let before = json!({
+            "email": "a@example.com",
+            "password": "hunter2",
+            "environment": [["DB_PASSWORD", "hunter2"], ["MODE", "dev"]]
+        });
+        let after = json!({
+            "email": "b@example.com",
+            "password": "correct horse",
+            "environment": [["DB_PASSWORD", "correct horse"], ["MODE", "prod"]]
+        });
+
+        let details = Diff::between(&before, &after)
+            .redacted_change("password_hash")
+            .to_details();
+        assert!(!details.contains("hunter2"), "{}", details);
+        assert!(!details.contains("correct horse"), "{}", details);
+        let details: Value = serde_json::from_str(&details).unwrap();
+        let fields: Vec<&str> = details["changes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|change| change["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            fields,
+            ["email", "environment", "password", "password_hash"]
+        );
+        assert_eq!(details["changes"][1]["after"][1][1], "prod");
+    }
+
+    #[test]
+    fn test_large_diffs_are_truncated() {
+        let before: Map<String, Value> = (0..MAX_CHANGES + 5)
+            .map(|i| (format!("f{:03}", i), json!(0)))
+            .collect();
+        let after: Map<String, Value> = (0..MAX_CHANGES + 5)
+            .map(|i| (format!("f{:03}", i), json!(1)))
+            .collect();
+        let diff = Diff::between(&before, &after);
+        assert_eq!(diff.changes.len(), MAX_CHANGES);
+        assert_eq!(diff.truncated, 5);
+
+        let diff = Diff::between(&json!({"note": ""}), &json!({"note": "x".repeat(1000)}));
+        let after = diff.changes[0].after.as_str().unwrap();
+        assert!(after.ends_with(TRUNCATED));
+        assert_eq!(after.len(), MAX_VALUE_CHARS + TRUNCATED.len());
+    }
+}
@@ -0,0 +1,393 @@
+/// Request authentication and permission checks
+///
+/// Handlers that need an identity take an `AuthenticatedUser` argument. With
+/// `security.auth_enabled` the request must carry `Authorization: Bearer <jwt>`
+/// signed with `security.jwt_secret`, whose subject is an enabled user in the
+/// `UserStore`, or `X-API-Key` holding a key known to the `ApiKeyStore` (see
+/// [`crate::api_keys`]), which grants that key's permissions. With auth
+/// disabled every request acts as a local admin. Until the initial admin is
+/// created (see [`crate::setup`]) authenticated requests fail with 503.
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use chrono::Utc;
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use std::future::{ready, Ready};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::api_keys::ApiKeyStore;
+use crate::config::{ApiKeyConfig, AppConfig};
+use crate::rbac::{Permission, Role, UserKind, UserStore};
+use crate::service_tokens::ServiceTokenStore;
+use crate::setup::AdminSetup;
+
+/// Username recorded for requests when authentication is disabled
+pub const ANONYMOUS_USER: &str = "anonymous";
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub exp: usize,
+    /// Set on service tokens, which are tracked and revocable server-side
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<Uuid>,
+}
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("Authentication required")]
+    MissingCredentials,
+
+    #[error("Invalid or expired token")]
+    InvalidToken,
+
+    #[error("User is unknown or disabled")]
+    UnknownUser,
+
+    #[error("Permission denied: {0:?} required")]
+    Forbidden(Permission),
+
+    #[error("Authentication is not configured")]
+    NotConfigured,
+
+    #[error("Setup required: create the initial admin with POST /api/v1/auth/setup")]
+    SetupRequired,
+}
+
+impl ResponseError for AuthError {
+    fn error_response(&self) -> HttpResponse {
+        let body = serde_json::json!({ "error": self.to_string() });
+        match self {
+            AuthError::Forbidden(_) => HttpResponse::Forbidden().json(body),
+            AuthError::NotConfigured => HttpResponse::InternalServerError().json(body),
+            AuthError::SetupRequired => HttpResponse::ServiceUnavailable().json(body),
+            _ => HttpResponse::Unauthorized().json(body),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub username: String,
+    pub permissions: Vec<Permission>,
+    /// Service token the request was made with
+    pub token_id: Option<Uuid>,
+    /// Correlation ID of the request, carried into audit entries
+    pub correlation_id: Option<Uuid>,
+    /// Client address, resolved through trusted proxies
+    pub ip_address: Option<IpAddr>,
+}
+
+impl AuthenticatedUser {
+    fn anonymous() -> Self {
+        Self {
+            username: ANONYMOUS_USER.to_string(),
+            permissions: Role::Admin.permissions(),
+            token_id: None,
+            correlation_id: None,
+            ip_address: None,
+        }
+    }
+
+    /// Fail with 403 unless the user holds `permission`
+    pub fn require(&self, permission: Permission) -> Result<(), AuthError> {
+        if self.permissions.contains(&permission) {
+            Ok(())
+        } else {
+            Err(AuthError::Forbidden(permission))
+        }
+    }
+
+    fn from_request_sync(req: &HttpRequest) -> Result<Self, AuthError> {
+        let config = req
+            .app_data::<web::Data<AppConfig>>()
+            .ok_or(AuthError::NotConfigured)?;
+        if !config.security.auth_enabled {
+            return Ok(Self::anonymous());
+        }
+        if req
+            .app_data::<web::Data<Arc<AdminSetup>>>()
+            .is_some_and(|setup| setup.is_pending())
+        {
+            return Err(AuthError::SetupRequired);
+        }
+
+        if let Some(key) = req
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            return Self::from_api_key(req, &config.security.api_keys, key);
+        }
+
+        let secret = config
+            .security
+            .jwt_secret
+            .as_deref()
+            .ok_or(AuthError::NotConfigured)?;
+        let token = req
+            .headers()
+            .get(actix_web::http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(AuthError::MissingCredentials)?;
+
+        let claims = decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(secret.as_bytes()),
+            &Validation::new(Algorithm::HS256),
+        )
+        .map_err(|_| AuthError::InvalidToken)?
+        .claims;
+
+        let store = req
+            .app_data::<web::Data<Arc<Mutex<UserStore>>>>()
+            .ok_or(AuthError::NotConfigured)?;
+        let store = store.lock().unwrap();
+        let user = store
+            .get_user(&claims.sub)
+            .filter(|user| user.enabled)
+            .ok_or(AuthError::UnknownUser)?;
+
+        match claims.jti {
+            Some(token_id) => req
+                .app_data::<web::Data<Arc<ServiceTokenStore>>>()
+                .ok_or(AuthError::NotConfigured)?
+                .authenticate(token_id, token, user.id, chrono::Utc::now())
+                .map_err(|_| AuthError::InvalidToken)?,
+            // Service accounts have no sessions, only tracked tokens
+            None if user.kind == UserKind::Service => return Err(AuthError::InvalidToken),
+            None => {}
+        }
+
+        let mut permissions = user.role.permissions();
+        permissions.extend(user.custom_permissions.iter().cloned());
+        Ok(Self {
+            username: user.username.clone(),
+            permissions,
+            token_id: claims.jti,
+            correlation_id: None,
+            ip_address: None,
+        })
+    }
+}
+
+impl AuthenticatedUser {
+    /// API keys carry the permissions they were created with
+    fn from_api_key(
+        req: &HttpRequest,
+        configured: &[ApiKeyConfig],
+        key: &str,
+    ) -> Result<Self, AuthError> {
+        let grant = req
+            .app_data::<web::Data<Arc<ApiKeyStore>>>()
+            .ok_or(AuthError::NotConfigured)?
+            .authenticate(configured, key, Utc::now())
+            .map_err(|_| AuthError::InvalidToken)?;
+        Ok(Self {
+            username: format!("api-key:{}", grant.name),
+            permissions: grant.permissions,
+            token_id: None,
+            correlation_id: None,
+            ip_address: None,
+        })
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Self::from_request_sync(req).map(|user| Self {
+            correlation_id: crate::request_tracing::correlation_id(req),
+            ip_address: crate::client_ip::client_ip(req),
+            ..user
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    fn config(auth_enabled: bool) -> AppConfig {
+        let mut config = AppConfig::default();
+        config.security.auth_enabled = auth_enabled;
+        config.security.jwt_secret = Some("test-secret".to_string());
+        config
+    }
+
+    fn token(sub: &str, secret: &str) -> String {
+        let claims = Claims {
+            sub: sub.to_string(),
+            exp: (chrono::Utc::now().timestamp() + 3600) as usize,
+            jti: None,
+        };
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn users() -> UserStore {
+        let mut users = UserStore::new();
+        users.create_admin("admin", "admin-password").unwrap();
+        users
+    }
+
+    fn request(config: AppConfig, bearer: Option<String>) -> HttpRequest {
+        let mut req = TestRequest::default()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(Arc::new(Mutex::new(users()))));
+        if let Some(token) = bearer {
+            req = req.insert_header(("Authorization", format!("Bearer {}", token)));
+        }
+        req.to_http_request()
+    }
+
+    #[test]
+    fn test_auth_disabled_acts_as_admin() {
+        let user = AuthenticatedUser::from_request_sync(&request(config(false), None)).unwrap();
+        assert_eq!(user.username, ANONYMOUS_USER);
+        assert!(user.require(Permission::SystemAdmin).is_ok());
+    }
+
+    #[test]
+    fn test_valid_token_resolves_user() {
+        let req = request(config(true), Some(token("admin", "test-secret")));
+        let user = AuthenticatedUser::from_request_sync(&req).unwrap();
+        assert_eq!(user.username, "admin");
+        assert!(user.require(Permission::SystemAdmin).is_ok());
+    }
+
+    #[test]
+    fn test_missing_or_forged_token_is_rejected() {
+        let req = request(config(true), None);
+        assert!(matches!(
+            AuthenticatedUser::from_request_sync(&req),
+            Err(AuthError::MissingCredentials)
+        ));
+
+        let req = request(config(true), Some(token("admin", "wrong-secret")));
+        assert!(matches!(
+            AuthenticatedUser::from_request_sync(&req),
+            Err(AuthError::InvalidToken)
+        ));
+
+        let req = request(config(true), Some(token("nobody", "test-secret")));
+        assert!(matches!(
+            AuthenticatedUser::from_request_sync(&req),
+            Err(AuthError::UnknownUser)
+        ));
+    }
+
+    #[test]
+    fn test_pending_setup_rejects_requests() {
+        let setup = Arc::new(AdminSetup::new(&UserStore::new(), None));
+        let req = TestRequest::default()
+            .app_data(web::Data::new(config(true)))
+            .app_data(web::Data::new(setup))
+            .insert_header((
+                "Authorization",
+                format!("Bearer {}", token("admin", "test-secret")),
+            ))
+            .to_http_request();
+        let err = AuthenticatedUser::from_request_sync(&req).unwrap_err();
+        assert!(matches!(err, AuthError::SetupRequired));
+        assert_eq!(err.error_response().status(), 503);
+    }
+
+    #[test]
+    fn test_api_key_grants_its_permissions() {
+        let mut config = config(true);
+        config.security.api_keys = vec![ApiKeyConfig {
+            name: "monitoring".to_string(),
+            key_hash: crate::api_keys::hash_key("monitoring-key"),
+            permissions: vec![Permission::SystemRead],
+            enabled: true,
+            expires_at: None,
+        }];
+        let store = Arc::new(ApiKeyStore::default());
+        let (expired, _) = store
+            .create(
+                "expired",
+                vec![Permission::SystemRead],
+                Some(Utc::now() - chrono::Duration::minutes(1)),
+                &[],
+            )
+            .unwrap();
+        let request = |key: &str| {
+            TestRequest::default()
+                .app_data(web::Data::new(config.clone()))
+                .app_data(web::Data::new(store.clone()))
+                .insert_header((API_KEY_HEADER, key))
+                .to_http_request()
+        };
+
+        let user = AuthenticatedUser::from_request_sync(&request("monitoring-key")).unwrap();
+        assert_eq!(user.username, "api-key:monitoring");
+        assert!(user.require(Permission::SystemRead).is_ok());
+        assert!(user.require(Permission::SystemAdmin).is_err());
+        assert!(store.list(&config.security.api_keys)[0]
+            .last_used_at
+            .is_some());
+
+        for key in ["guessed-key", expired.as_str()] {
+            assert!(matches!(
+                AuthenticatedUser::from_request_sync(&request(key)),
+                Err(AuthError::InvalidToken)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_service_token_is_revocable() {
+        let mut users = users();
+        let mut ci = users.get_user("admin").unwrap().clone();
+        ci.id = Uuid::new_v4();
+        ci.username = "ci".to_string();
+        ci.role = Role::Operator;
+        ci.kind = UserKind::Service;
+        users.add_user(ci.clone()).unwrap();
+        let tokens = Arc::new(ServiceTokenStore::default());
+        let (service_token, record) = tokens
+            .issue("ci", ci.id, "deploy", 30, "test-secret")
+            .unwrap();
+
+        let users = Arc::new(Mutex::new(users));
+        let request = |bearer: String| {
+            TestRequest::default()
+                .app_data(web::Data::new(config(true)))
+                .app_data(web::Data::new(users.clone()))
+                .app_data(web::Data::new(tokens.clone()))
+                .insert_header(("Authorization", format!("Bearer {}", bearer)))
+                .to_http_request()
+        };
+
+        let user = AuthenticatedUser::from_request_sync(&request(service_token.clone())).unwrap();
+        assert_eq!(user.username, "ci");
+        assert_eq!(user.token_id, Some(record.id));
+        assert!(user.require(Permission::SystemAdmin).is_err());
+
+        // Untracked session tokens are never accepted for service accounts
+        assert!(matches!(
+            AuthenticatedUser::from_request_sync(&request(token("ci", "test-secret"))),
+            Err(AuthError::InvalidToken)
+        ));
+
+        tokens.revoke("ci", record.id).unwrap();
+        assert!(matches!(
+            AuthenticatedUser::from_request_sync(&request(service_token)),
+            Err(AuthError::InvalidToken)
+        ));
+    }
+}
@@ -0,0 +1,514 @@
+/// Joining the cluster through the seed nodes in `cluster.join_addresses`
+///
+/// Seeds are the API addresses (`host:port`) of existing members and are
+/// tried in order with `POST /api/v1/cluster/join`. A full pass over the
+/// seeds is one round; rounds are separated by an exponential backoff. Once a
+/// seed admits this node the membership is recorded on disk, so a restarted
+/// node keeps its identity and does not join again.
+///
+/// The seed answers with the cluster's id and its leader, which the node
+/// adopts. A node started with `cluster.bootstrap` founds the cluster
+/// instead and leads it.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use cluster::ClusterState;
+use models::JoinClusterRequest;
+
+use crate::config::AppConfig;
+use crate::paths::Paths;
+
+/// Name sent with join requests; the cluster has no configurable name yet
+const CLUSTER_NAME: &str = "default";
+
+/// How long a single seed may take to answer
+const SEED_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// This node's cluster membership, kept across restarts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MembershipRecord {
+    pub node_id: Uuid,
+    /// Seed that admitted the node
+    pub seed: String,
+    pub joined_at: DateTime<Utc>,
+    /// Cluster and leader the seed reported; absent in records written
+    /// before seeds reported them
+    #[serde(default)]
+    pub cluster_id: Option<Uuid>,
+    #[serde(default)]
+    pub leader_id: Option<Uuid>,
+}
+
+impl MembershipRecord {
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+/// This node's id, kept in `path` from its first start on
+pub fn load_node_id(path: &Path) -> io::Result<Uuid> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => content
+            .trim()
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let node_id = Uuid::new_v4();
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, format!("{}\n", node_id))?;
+            std::fs::rename(&tmp, path)?;
+            info!("Generated node id {}", node_id);
+            Ok(node_id)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Cluster state of a clustered node, `None` when standalone
+///
+/// A bootstrapping node leads the cluster it founds, which takes the node's
+/// id. A joining node starts without a leader, so it is not ready, until a
+/// seed admits it; after a restart it starts from its membership record.
+pub fn cluster_state(
+    config: &AppConfig,
+    node_id: Uuid,
+    record: Option<&MembershipRecord>,
+) -> Option<Arc<RwLock<ClusterState>>> {
+    if !config.cluster.is_clustered() {
+        return None;
+    }
+    let cluster_id = record.and_then(|record| record.cluster_id);
+    let mut state = ClusterState::new(cluster_id.unwrap_or(node_id));
+    let leader_id = match record {
+        _ if config.cluster.bootstrap => Some(node_id),
+        Some(record) => record.leader_id,
+        None => None,
+    };
+    if let Some(leader_id) = leader_id {
+        state.set_leader(leader_id);
+    }
+    Some(Arc::new(RwLock::new(state)))
+}
+
+/// What a seed answers when it admits a node
+#[derive(Debug, Default, Deserialize)]
+struct Admission {
+    cluster_id: Option<Uuid>,
+    leader_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JoinStatus {
+    /// Still trying the seeds; `attempts` counts failed requests
+    Joining {
+        attempts: u32,
+        last_error: Option<String>,
+    },
+    Joined {
+        seed: String,
+        joined_at: DateTime<Utc>,
+    },
+}
+
+/// Delay between rounds, doubling from `initial` up to `max`
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+        }
+    }
+}
+
+pub struct AutoJoin {
+    seeds: Vec<String>,
+    request: JoinClusterRequest,
+    scheme: &'static str,
+    node_id: Uuid,
+    record_path: PathBuf,
+    backoff: Backoff,
+    status: RwLock<JoinStatus>,
+    state: Option<Arc<RwLock<ClusterState>>>,
+    client: reqwest::Client,
+}
+
+impl AutoJoin {
+    /// `record` is the membership loaded at startup, if the node already joined
+    pub fn new(
+        config: &AppConfig,
+        paths: &Paths,
+        node_id: Uuid,
+        record: Option<&MembershipRecord>,
+    ) -> Self {
+        let cluster = &config.cluster;
+        let status = match record {
+            Some(record) => JoinStatus::Joined {
+                seed: record.seed.clone(),
+                joined_at: record.joined_at,
+            },
+            None => JoinStatus::Joining {
+                attempts: 0,
+                last_error: None,
+            },
+        };
+
+        Self {
+            seeds: cluster.join_addresses.clone(),
+            request: JoinClusterRequest {
+                cluster_name: CLUSTER_NAME.to_string(),
+                // Seeds reach members through their API
+                node_address: cluster
+                    .advertise_address
+                    .clone()
+                    .unwrap_or_else(|| cluster.node_name.clone()),
+                node_port: config.server.port,
+                join_token: cluster.join_token.clone(),
+            },
+            scheme: match config.server.tls {
+                Some(_) => "https",
+                None => "http",
+            },
+            node_id,
+            record_path: paths.cluster_membership.clone(),
+            backoff: Backoff::default(),
+            status: RwLock::new(status),
+            state: None,
+            client: reqwest::Client::builder()
+                .timeout(SEED_REQUEST_TIMEOUT)
+                .build()
+                .expect("failed to build HTTP client"),
+        }
+    }
+
+    /// Cluster state that takes the leader the admitting seed reports
+    pub fn with_cluster_state(mut self, state: Arc<RwLock<ClusterState>>) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    #[cfg(test)]
+    fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn status(&self) -> JoinStatus {
+        self.status.read().unwrap().clone()
+    }
+
+    pub fn is_joined(&self) -> bool {
+        matches!(self.status(), JoinStatus::Joined { .. })
+    }
+
+    /// Try every seed in order; returns the seed that admitted the node
+    pub async fn try_seeds(&self) -> Result<String, String> {
+        let mut errors = Vec::new();
+        for seed in &self.seeds {
+            match self.request_join(seed).await {
+                Ok(admission) => {
+                    self.joined(seed, admission);
+                    return Ok(seed.clone());
+                }
+                Err(e) => {
+                    warn!("Cluster seed {} did not admit this node: {}", seed, e);
+                    let mut status = self.status.write().unwrap();
+                    if let JoinStatus::Joining {
+                        ref mut attempts,
+                        ref mut last_error,
+                    } = *status
+                    {
+                        *attempts += 1;
+                        *last_error = Some(format!("{}: {}", seed, e));
+                    }
+                    errors.push(format!("{}: {}", seed, e));
+                }
+            }
+        }
+        Err(errors.join("; "))
+    }
+
+    /// Try the seeds for up to `rounds` rounds, or until joined when `None`
+    pub async fn join(&self, rounds: Option<u32>) -> Result<String, String> {
+        let mut delay = self.backoff.initial;
+        let mut round = 0;
+        loop {
+            round += 1;
+            let error = match self.try_seeds().await {
+                Ok(seed) => return Ok(seed),
+                Err(e) => e,
+            };
+            if rounds.is_some_and(|rounds| round >= rounds) {
+                return Err(format!("no seed admitted this node: {}", error));
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(self.backoff.max);
+        }
+    }
+
+    async fn request_join(&self, seed: &str) -> Result<Admission, String> {
+        let base_url = if seed.contains("://") {
+            seed.to_string()
+        } else {
+            format!("{}://{}", self.scheme, seed)
+        };
+        let response = self
+            .client
+            .post(format!("{}/api/v1/cluster/join", base_url))
+            .json(&self.request)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            // Seeds that predate reporting the leader answer without one
+            return Ok(response.json().await.unwrap_or_default());
+        }
+
+        let status = response.status();
+        let body: serde_json::Value = response.json().await.unwrap_or_default();
+        Err(match body["error"].as_str() {
+            Some(message) => format!("{} ({})", status, message),
+            None => status.to_string(),
+        })
+    }
+
+    fn joined(&self, seed: &str, admission: Admission) {
+        let record = MembershipRecord {
+            node_id: self.node_id,
+            seed: seed.to_string(),
+            joined_at: Utc::now(),
+            cluster_id: admission.cluster_id,
+            leader_id: admission.leader_id,
+        };
+        if let Some(ref state) = self.state {
+            let mut state = state.write().unwrap();
+            if let Some(cluster_id) = record.cluster_id {
+                state.cluster_id = cluster_id;
+            }
+            if let Some(leader_id) = record.leader_id {
+                state.set_leader(leader_id);
+            }
+        }
+        // Without the record the node joins again after a restart, which
+        // the seeds tolerate
+        if let Err(e) = record.save(&self.record_path) {
+            warn!(
+                "Failed to record cluster membership in {}: {}",
+                self.record_path.display(),
+                e
+            );
+        }
+        info!("Joined the cluster through seed {}", seed);
+        *self.status.write().unwrap() = JoinStatus::Joined {
+            seed: record.seed,
+            joined_at: record.joined_at,
+        };
+    }
+}
+
+/// Join at startup as configured
+///
+/// With `join_before_serving` or `require_join` the seeds are tried for
+/// `join_retries` rounds before returning. Only a node that requires the join
+/// gets an error back; everyone else keeps retrying in the background.
+pub async fn start(auto_join: Arc<AutoJoin>, config: &AppConfig) -> Result<(), String> {
+    let cluster = &config.cluster;
+    if auto_join.is_joined() {
+        info!("Already a cluster member; not contacting the seeds");
+        return Ok(());
+    }
+
+    if cluster.require_join || cluster.join_before_serving {
+        match auto_join.join(Some(cluster.join_retries)).await {
+            Ok(_) => return Ok(()),
+            Err(e) if cluster.require_join => return Err(e),
+            Err(e) => warn!("{}; retrying in the background", e),
+        }
+    }
+
+    // Without a round limit this only returns once joined
+    actix_rt::spawn(async move {
+        let _ = auto_join.join(None).await;
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Seed answering each join with the next status, then closing
+    async fn mock_seed(statuses: Vec<u16>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).await;
+                let body = r#"{"error":"No cluster leader elected"}"#;
+                let response = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        address
+    }
+
+    fn auto_join(seeds: Vec<String>, data_dir: &Path) -> AutoJoin {
+        let mut config = AppConfig::default();
+        config.cluster.join_addresses = seeds;
+        config.paths.data_dir = data_dir.to_path_buf();
+        AutoJoin::new(&config, &config.paths(), Uuid::new_v4(), None).with_backoff(Backoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(20),
+        })
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("auto_join_{}", Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_seeds_are_tried_in_order_until_one_admits() {
+        let dir = temp_dir();
+        // Nothing listens on the first seed
+        let unreachable = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        let busy = mock_seed(vec![503, 503]).await;
+        let good = mock_seed(vec![200]).await;
+        let join = auto_join(vec![unreachable, busy.clone(), good.clone()], &dir);
+
+        assert_eq!(join.join(Some(1)).await.unwrap(), good);
+        match join.status() {
+            JoinStatus::Joined { seed, .. } => assert_eq!(seed, good),
+            other => panic!("unexpected status {:?}", other),
+        }
+
+        let record = MembershipRecord::load(&dir.join("cluster-membership.json"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.seed, good);
+        assert_eq!(record.node_id, join.node_id);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rounds_retry_after_backoff() {
+        let dir = temp_dir();
+        let seed = mock_seed(vec![503, 503, 200]).await;
+        let join = auto_join(vec![seed.clone()], &dir);
+
+        assert!(join.join(Some(2)).await.is_err());
+        match join.status() {
+            JoinStatus::Joining {
+                attempts,
+                last_error,
+            } => {
+                assert_eq!(attempts, 2);
+                assert!(last_error.unwrap().contains("No cluster leader elected"));
+            }
+            other => panic!("unexpected status {:?}", other),
+        }
+
+        assert_eq!(join.join(None).await.unwrap(), seed);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recorded_membership_skips_joining() {
+        let dir = temp_dir();
+        let path = dir.join("cluster-membership.json");
+        assert_eq!(MembershipRecord::load(&path).unwrap(), None);
+
+        let record = MembershipRecord {
+            node_id: Uuid::new_v4(),
+            seed: "10.0.0.1:8080".to_string(),
+            joined_at: Utc::now(),
+            cluster_id: Some(Uuid::new_v4()),
+            leader_id: Some(Uuid::new_v4()),
+        };
+        record.save(&path).unwrap();
+        let loaded = MembershipRecord::load(&path).unwrap().unwrap();
+        assert_eq!(loaded, record);
+
+        let mut config = AppConfig::default();
+        let join = AutoJoin::new(&config, &config.paths(), loaded.node_id, Some(&loaded));
+        assert!(join.is_joined());
+
+        // The restarted node follows the leader it recorded
+        config.cluster.join_addresses = vec![record.seed.clone()];
+        let state = cluster_state(&config, loaded.node_id, Some(&loaded)).unwrap();
+        let state = state.read().unwrap();
+        assert_eq!(Some(state.cluster_id), record.cluster_id);
+        assert_eq!(state.leader_id, record.leader_id);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_node_id_is_kept_across_starts() {
+        let dir = temp_dir();
+        let path = dir.join("node-id");
+        let node_id = load_node_id(&path).unwrap();
+        assert_eq!(load_node_id(&path).unwrap(), node_id);
+
+        std::fs::write(&path, "not-a-uuid").unwrap();
+        assert!(load_node_id(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bootstrapping_node_leads() {
+        let node_id = Uuid::new_v4();
+        let mut config = AppConfig::default();
+        assert!(cluster_state(&config, node_id, None).is_none());
+
+        config.cluster.join_addresses = vec!["10.0.0.1:8080".to_string()];
+        let state = cluster_state(&config, node_id, None).unwrap();
+        assert_eq!(state.read().unwrap().leader_id, None);
+
+        config.cluster.join_addresses.clear();
+        config.cluster.bootstrap = true;
+        let state = cluster_state(&config, node_id, None).unwrap();
+        let state = state.read().unwrap();
+        assert_eq!(state.leader_id, Some(node_id));
+        assert_eq!(state.cluster_id, node_id);
+    }
+}
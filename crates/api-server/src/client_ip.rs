@@ -0,0 +1,166 @@
+/// The address a request really came from, when the server may sit behind
+/// reverse proxies listed in `security.trusted_proxies`
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use actix_web::{http::header, web, HttpRequest};
+
+/// Networks whose `X-Forwarded-For` headers are believed
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// Parse CIDRs such as `10.0.0.0/8`; a bare address trusts only itself
+    pub fn parse(cidrs: &[String]) -> Result<Self, String> {
+        let networks = cidrs
+            .iter()
+            .map(|cidr| parse_cidr(cidr).ok_or_else(|| format!("Invalid CIDR: {}", cidr)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { networks })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.networks
+            .iter()
+            .any(|(network, prefix)| in_network(ip, *network, *prefix))
+    }
+
+    /// The client behind `peer`: a trusted peer's `X-Forwarded-For` is
+    /// walked from the right and the first hop that is not a trusted proxy
+    /// wins, so entries a client prepends itself are never reached
+    pub fn resolve(&self, peer: IpAddr, forwarded_for: Option<&str>) -> IpAddr {
+        let mut client = peer.to_canonical();
+        if !self.contains(client) {
+            return client;
+        }
+        for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+            match hop.trim().parse::<IpAddr>() {
+                Ok(ip) => client = ip.to_canonical(),
+                // Garbage in the header; the last hop we could read is it
+                Err(_) => break,
+            }
+            if !self.contains(client) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// The client address of `req`, or `None` when the peer is unknown
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let Some(trusted) = req.app_data::<web::Data<Arc<TrustedProxies>>>() else {
+        return Some(peer.to_canonical());
+    };
+    let forwarded_for = req
+        .headers()
+        .get_all(header::X_FORWARDED_FOR)
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+    Some(trusted.resolve(peer, Some(&forwarded_for)))
+}
+
+fn parse_cidr(cidr: &str) -> Option<(IpAddr, u8)> {
+    let (address, prefix) = match cidr.split_once('/') {
+        Some((address, prefix)) => (address.parse::<IpAddr>().ok()?, Some(prefix.parse().ok()?)),
+        None => (cidr.parse::<IpAddr>().ok()?, None),
+    };
+    let address = address.to_canonical();
+    let max = if address.is_ipv4() { 32 } else { 128 };
+    match prefix {
+        Some(prefix) if prefix > max => None,
+        Some(prefix) => Some((address, prefix)),
+        None => Some((address, max)),
+    }
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trusted(cidrs: &[&str]) -> TrustedProxies {
+        TrustedProxies::parse(&cidrs.iter().map(|c| c.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn test_trusted_proxy_forwards_client() {
+        let proxies = trusted(&["10.0.0.0/8", "fd00::/8"]);
+        assert_eq!(
+            proxies.resolve(ip("10.0.0.5"), Some("203.0.113.7")),
+            ip("203.0.113.7")
+        );
+        // Chained proxies: the rightmost hop outside the trusted range
+        assert_eq!(
+            proxies.resolve(ip("10.0.0.5"), Some("198.51.100.1, 203.0.113.7, 10.1.2.3")),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            proxies.resolve(ip("fd00::1"), Some("2001:db8::9")),
+            ip("2001:db8::9")
+        );
+        // IPv4 peers seen through a dual-stack socket
+        assert_eq!(
+            proxies.resolve(ip("::ffff:10.0.0.5"), Some("203.0.113.7")),
+            ip("203.0.113.7")
+        );
+        // No header: the proxy itself is the client
+        assert_eq!(proxies.resolve(ip("10.0.0.5"), None), ip("10.0.0.5"));
+    }
+
+    #[test]
+    fn test_forwarded_for_cannot_be_spoofed() {
+        let proxies = trusted(&["10.0.0.0/8"]);
+        // Untrusted peers' headers are ignored
+        assert_eq!(
+            proxies.resolve(ip("203.0.113.7"), Some("10.0.0.1")),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            TrustedProxies::default().resolve(ip("10.0.0.5"), Some("198.51.100.1")),
+            ip("10.0.0.5")
+        );
+        // A client prepending hops through a trusted proxy gains nothing
+        assert_eq!(
+            proxies.resolve(ip("10.0.0.5"), Some("198.51.100.1, 203.0.113.7")),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            proxies.resolve(ip("10.0.0.5"), Some("198.51.100.1, not-an-ip, 10.0.0.9")),
+            ip("10.0.0.9")
+        );
+    }
+
+    #[test]
+    fn test_parse_cidrs() {
+        let proxies = trusted(&["192.168.1.10", "172.16.0.0/12"]);
+        assert!(proxies.contains(ip("192.168.1.10")));
+        assert!(!proxies.contains(ip("192.168.1.11")));
+        assert!(proxies.contains(ip("172.31.255.1")));
+        assert!(trusted(&["0.0.0.0/0"]).contains(ip("8.8.8.8")));
+        assert!(TrustedProxies::parse(&["10.0.0.0/33".to_string()]).is_err());
+        assert!(TrustedProxies::parse(&["proxy.local".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,397 @@
+/// Leaving the cluster cleanly
+///
+/// Runs as a job with one step per stage. The node is marked `Leaving` first
+/// so nothing new is placed on it, then leadership moves to another online
+/// member if this node holds it, and the containers assigned to the node are
+/// spread over the remaining members. Only then does the node drop out of
+/// membership and tell its peers, which apply the same handoff to their own
+/// view of the cluster.
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use cluster::{ClusterNetwork, ClusterState, LeaveNotice, MembershipManager};
+use container_manager::ContainerManager;
+use models::NodeStatus;
+
+use crate::audit::{AuditAction, AuditLogger, AuditResult};
+use crate::auth::AuthenticatedUser;
+use crate::jobs::{JobManager, JobStatus};
+use crate::peer_probe;
+
+pub const LEAVE_JOB: &str = "cluster-leave";
+
+const MARK_STEP: &str = "mark-leaving";
+const HANDOFF_STEP: &str = "hand-off-leadership";
+const CONTAINERS_STEP: &str = "containers";
+const LEAVE_STEP: &str = "leave";
+const NOTIFY_STEP: &str = "notify-peers";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LeaveRequest {
+    /// Also stop the containers running on this node
+    pub stop_containers: bool,
+    /// Graceful stop timeout per container before it is killed
+    pub timeout_seconds: u64,
+}
+
+impl Default for LeaveRequest {
+    fn default() -> Self {
+        Self {
+            stop_containers: false,
+            timeout_seconds: 120,
+        }
+    }
+}
+
+/// The cluster this node belongs to, as the leave job needs it
+pub struct ClusterMembership {
+    pub membership: Arc<RwLock<MembershipManager>>,
+    pub state: Arc<RwLock<ClusterState>>,
+    pub network: ClusterNetwork,
+    /// Port peers listen on for cluster traffic
+    pub cluster_port: u16,
+    /// Membership record removed on leaving, so a restart does not rejoin
+    pub record_path: PathBuf,
+}
+
+/// Online members other than `node`, lowest id first
+fn remaining_members(membership: &MembershipManager, node: &Uuid) -> Vec<Uuid> {
+    let mut members: Vec<Uuid> = membership
+        .list_nodes()
+        .into_iter()
+        .filter(|n| n.id != *node && n.status == NodeStatus::Online)
+        .map(|n| n.id)
+        .collect();
+    members.sort();
+    members
+}
+
+/// Apply a peer's leave notice to this node's view of the cluster
+pub fn apply_notice(
+    membership: &RwLock<MembershipManager>,
+    state: &RwLock<ClusterState>,
+    notice: &LeaveNotice,
+) {
+    let mut membership = membership.write().unwrap();
+    let mut state = state.write().unwrap();
+    state.hand_off_leadership(&notice.node_id, notice.leader_id);
+    let remaining = remaining_members(&membership, &notice.node_id);
+    state.reassign_containers(&notice.node_id, &remaining);
+    membership.remove_node(&notice.node_id);
+    let members: Vec<Uuid> = membership.list_nodes().iter().map(|n| n.id).collect();
+    state.retain_node_reports(&members);
+}
+
+/// Work through the leave; the job fails only if containers could not be
+/// stopped, since peers that miss the notice drop the node once it stops
+/// answering their pings
+pub async fn run(
+    job_id: Uuid,
+    request: LeaveRequest,
+    cluster: Arc<ClusterMembership>,
+    user: AuthenticatedUser,
+    jobs: Arc<JobManager>,
+    audit_logger: Arc<AuditLogger>,
+) {
+    info!("Cluster leave job {} started: {:?}", job_id, request);
+    jobs.set_steps(
+        job_id,
+        [
+            MARK_STEP,
+            HANDOFF_STEP,
+            CONTAINERS_STEP,
+            LEAVE_STEP,
+            NOTIFY_STEP,
+        ]
+        .map(str::to_string)
+        .to_vec(),
+    );
+    let local = cluster.membership.read().unwrap().local_node_id();
+
+    jobs.update_step(job_id, MARK_STEP, JobStatus::Running, None);
+    cluster
+        .membership
+        .write()
+        .unwrap()
+        .update_node_status(&local, NodeStatus::Leaving);
+    jobs.update_step(job_id, MARK_STEP, JobStatus::Succeeded, None);
+
+    jobs.update_step(job_id, HANDOFF_STEP, JobStatus::Running, None);
+    let successor = cluster.membership.read().unwrap().successor(&local);
+    let handed_off = cluster
+        .state
+        .write()
+        .unwrap()
+        .hand_off_leadership(&local, successor);
+    let message = match (handed_off, successor) {
+        (false, _) => "not the leader".to_string(),
+        (true, Some(successor)) => format!("leadership handed to {}", successor),
+        (true, None) => "no online member to take over".to_string(),
+    };
+    jobs.update_step(job_id, HANDOFF_STEP, JobStatus::Succeeded, Some(message));
+
+    jobs.update_step(job_id, CONTAINERS_STEP, JobStatus::Running, None);
+    let remaining = remaining_members(&cluster.membership.read().unwrap(), &local);
+    let reassigned = cluster
+        .state
+        .write()
+        .unwrap()
+        .reassign_containers(&local, &remaining);
+    let mut failures = Vec::new();
+    let stopped = if request.stop_containers {
+        let timeout = Duration::from_secs(request.timeout_seconds);
+        match ContainerManager::stop_all(timeout).await {
+            Ok(summary) => {
+                failures.extend(
+                    summary
+                        .failed
+                        .iter()
+                        .map(|(name, e)| format!("{}: {}", name, e)),
+                );
+                Some(summary)
+            }
+            Err(e) => {
+                failures.push(format!("{}: {}", CONTAINERS_STEP, e));
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let message = format!("{} assignment(s) moved", reassigned);
+    if failures.is_empty() {
+        jobs.update_step(job_id, CONTAINERS_STEP, JobStatus::Succeeded, Some(message));
+    } else {
+        jobs.update_step(
+            job_id,
+            CONTAINERS_STEP,
+            JobStatus::Failed,
+            Some(failures.join("; ")),
+        );
+    }
+
+    jobs.update_step(job_id, LEAVE_STEP, JobStatus::Running, None);
+    let peers: Vec<(Uuid, String)> = {
+        let mut membership = cluster.membership.write().unwrap();
+        let peers = membership
+            .list_nodes()
+            .into_iter()
+            .filter(|n| n.id != local)
+            .map(|n| (n.id, n.address.clone()))
+            .collect();
+        membership.remove_node(&local);
+        peers
+    };
+    if let Err(e) = std::fs::remove_file(&cluster.record_path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!(
+                "Failed to remove membership record {}: {}",
+                cluster.record_path.display(),
+                e
+            );
+        }
+    }
+    jobs.update_step(job_id, LEAVE_STEP, JobStatus::Succeeded, None);
+
+    jobs.update_step(job_id, NOTIFY_STEP, JobStatus::Running, None);
+    let notice = LeaveNotice {
+        node_id: local,
+        leader_id: successor.filter(|_| handed_off),
+    };
+    let mut unreachable = Vec::new();
+    for (id, address) in &peers {
+        let result = match peer_probe::resolve(address, cluster.cluster_port).await {
+            Ok(addr) => cluster.network.announce_leave(addr, &notice).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("Could not tell node {} about the leave: {}", id, e);
+            unreachable.push(id.to_string());
+        }
+    }
+    // This node is on its own now
+    {
+        let mut membership = cluster.membership.write().unwrap();
+        for (id, _) in &peers {
+            membership.remove_node(id);
+        }
+        cluster.state.write().unwrap().retain_node_reports(&[]);
+    }
+    let message = match unreachable.len() {
+        0 => format!("{} peer(s) notified", peers.len()),
+        _ => format!("not acknowledged by {}", unreachable.join(", ")),
+    };
+    jobs.update_step(job_id, NOTIFY_STEP, JobStatus::Succeeded, Some(message));
+
+    let summary = serde_json::json!({
+        "node_id": local,
+        "leader_id": notice.leader_id,
+        "reassigned": reassigned,
+        "stopped": stopped,
+        "unreachable_peers": unreachable,
+        "failures": failures,
+    });
+    if let Ok(log) = AuditLogger::builder()
+        .actor(&user)
+        .action(AuditAction::ClusterLeft)
+        .resource_type("cluster".to_string())
+        .resource_id(local.to_string())
+        .result(match failures.is_empty() {
+            true => AuditResult::Success,
+            false => AuditResult::Failure(failures.join("; ")),
+        })
+        .details(summary.to_string())
+        .build()
+    {
+        audit_logger.log_entry(log);
+    }
+
+    info!("Node {} left the cluster", local);
+    if failures.is_empty() {
+        jobs.succeed(job_id, summary);
+    } else {
+        jobs.fail(
+            job_id,
+            format!("{} container(s) could not be stopped", failures.len()),
+            Some(summary),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use models::{Node, NodeResources};
+    use tokio::net::TcpListener;
+
+    fn node(id: Uuid, status: NodeStatus) -> Node {
+        Node {
+            id,
+            name: id.to_string(),
+            address: "127.0.0.1".to_string(),
+            port: 8080,
+            status,
+            cluster_id: None,
+            resources: NodeResources {
+                cpu_cores: 4,
+                memory_total: 0,
+                memory_used: 0,
+                disk_total: 0,
+                disk_used: 0,
+                exclusive_cpus_allocated: 0,
+                arch: None,
+            },
+            joined_at: Utc::now(),
+            last_seen: Utc::now(),
+            labels: Default::default(),
+            cordoned: false,
+            latency: None,
+            storage_pools: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_leader_hands_off_before_leaving() {
+        let (local, peer, offline) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        // The peer applies notices to its own view, where the leaver leads
+        let peer_membership = Arc::new(RwLock::new(MembershipManager::new(peer)));
+        let peer_state = Arc::new(RwLock::new(ClusterState::new(Uuid::new_v4())));
+        for (id, status) in [(local, NodeStatus::Online), (peer, NodeStatus::Online)] {
+            peer_membership.write().unwrap().add_node(node(id, status));
+        }
+        let container = Uuid::new_v4();
+        peer_state.write().unwrap().set_leader(local);
+        peer_state
+            .write()
+            .unwrap()
+            .assign_container(local, container);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let cluster_port = listener.local_addr().unwrap().port();
+        let peer_network =
+            ClusterNetwork::new(listener.local_addr().unwrap()).with_leave_listener(Arc::new({
+                let (membership, state) = (peer_membership.clone(), peer_state.clone());
+                move |notice| apply_notice(&membership, &state, &notice)
+            }));
+        tokio::spawn(async move { peer_network.serve(listener).await });
+
+        let membership = Arc::new(RwLock::new(MembershipManager::new(local)));
+        for (id, status) in [
+            (local, NodeStatus::Online),
+            (peer, NodeStatus::Online),
+            (offline, NodeStatus::Offline),
+        ] {
+            membership.write().unwrap().add_node(node(id, status));
+        }
+        let state = Arc::new(RwLock::new(ClusterState::new(Uuid::new_v4())));
+        state.write().unwrap().set_leader(local);
+        state.write().unwrap().assign_container(local, container);
+        let record_path = std::env::temp_dir().join(format!("leave-{}.json", Uuid::new_v4()));
+        std::fs::write(&record_path, "{}").unwrap();
+        let cluster = Arc::new(ClusterMembership {
+            membership: membership.clone(),
+            state: state.clone(),
+            network: ClusterNetwork::new("127.0.0.1:0".parse().unwrap()),
+            cluster_port,
+            record_path: record_path.clone(),
+        });
+
+        let jobs = Arc::new(JobManager::new(10));
+        let job = jobs.create(LEAVE_JOB);
+        let user = AuthenticatedUser {
+            username: "admin".to_string(),
+            permissions: vec![],
+            token_id: None,
+            correlation_id: None,
+            ip_address: None,
+        };
+        run(
+            job.id,
+            LeaveRequest::default(),
+            cluster,
+            user,
+            jobs.clone(),
+            Arc::new(AuditLogger::new(10)),
+        )
+        .await;
+
+        let job = jobs.get(job.id).unwrap();
+        assert_eq!(job.status, JobStatus::Succeeded, "{:?}", job);
+        let steps: Vec<&str> = job.steps.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            steps,
+            [
+                MARK_STEP,
+                HANDOFF_STEP,
+                CONTAINERS_STEP,
+                LEAVE_STEP,
+                NOTIFY_STEP
+            ]
+        );
+        assert_eq!(
+            job.steps[1].message,
+            Some(format!("leadership handed to {}", peer))
+        );
+
+        // The offline node is not eligible; the peer took over everywhere
+        assert_eq!(state.read().unwrap().leader_id, Some(peer));
+        assert_eq!(
+            state.read().unwrap().get_node_containers(&peer),
+            [container]
+        );
+        assert_eq!(membership.read().unwrap().node_count(), 0);
+        assert!(!record_path.exists());
+        assert_eq!(peer_state.read().unwrap().leader_id, Some(peer));
+        assert_eq!(
+            peer_state.read().unwrap().get_node_containers(&peer),
+            [container]
+        );
+        assert!(!peer_membership.read().unwrap().is_member(&local));
+    }
+}
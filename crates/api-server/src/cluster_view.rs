@@ -0,0 +1,306 @@
+/// Cluster-wide container listing
+///
+/// The leader lists the containers every member reported on its heartbeats
+/// (see `cluster::summaries`) and merges them with the assignment map in
+/// `ClusterState`. On request it asks every member over the HTTP API
+/// instead. A node whose report is missing, too old or did not arrive in
+/// time is reported as stale instead of failing the whole listing; without
+/// a report its assigned containers are listed without a status.
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use cluster::NodeContainers;
+use container_manager::ContainerManager;
+use models::{Container, ContainerListResponse, ContainerStatus, ContainerSummary, HealthStatus};
+
+/// How long a single node may take to return its containers
+pub const NODE_REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+/// Heartbeat intervals after which a node's reported containers are stale
+pub const STALE_AFTER_HEARTBEATS: u32 = 3;
+
+pub(crate) static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(NODE_REQUEST_TIMEOUT)
+        .build()
+        .expect("failed to build HTTP client")
+});
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ClusterContainer {
+    /// Known only for containers listed from the assignment map
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    /// Unknown for containers only known from the assignment map
+    pub name: Option<String>,
+    /// None when the hosting node could not be queried
+    pub status: Option<ContainerStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<HealthStatus>,
+    pub node_id: Uuid,
+    /// False when the node's containers are stale
+    pub node_reachable: bool,
+}
+
+/// Where a node's containers were taken from
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ListingSource {
+    /// The node's heartbeats, as cached on the leader
+    Heartbeat,
+    /// The node's API, asked for this listing
+    Live,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct NodeListing {
+    pub node_id: Uuid,
+    pub name: Option<String>,
+    pub source: ListingSource,
+    /// True when the node's containers could not be fetched, or were last
+    /// reported too long ago
+    pub stale: bool,
+    /// When the heartbeat the containers came with arrived
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub received_at: Option<DateTime<Utc>>,
+    /// Seconds since `received_at`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What a single node reported
+pub type NodeResult = Result<Vec<ContainerSummary>, String>;
+
+fn summary(container: Container) -> ContainerSummary {
+    ContainerSummary {
+        name: container.name,
+        status: container.status,
+        health: container.health,
+    }
+}
+
+/// Containers on this node with their live status
+pub async fn local_containers() -> NodeResult {
+    let names = ContainerManager::list().await.map_err(|e| e.to_string())?;
+    let mut containers = Vec::with_capacity(names.len());
+    for name in names {
+        containers.push(summary(
+            ContainerManager::get(&name)
+                .await
+                .map_err(|e| e.to_string())?,
+        ));
+    }
+    Ok(containers)
+}
+
+/// Containers a node last reported on its heartbeats
+pub fn heartbeat_containers(cached: Option<&NodeContainers>) -> NodeResult {
+    cached
+        .map(|cached| cached.containers.clone())
+        .ok_or_else(|| "no containers reported on heartbeats yet".to_string())
+}
+
+/// Listing of a node whose containers were fetched or cached at
+/// `received_at`; stale when that is longer ago than `stale_after`
+pub fn node_listing(
+    node_id: Uuid,
+    name: Option<String>,
+    source: ListingSource,
+    result: &NodeResult,
+    received_at: Option<DateTime<Utc>>,
+    stale_after: Duration,
+) -> NodeListing {
+    let age = received_at.map(|at| (Utc::now() - at).to_std().unwrap_or_default());
+    NodeListing {
+        node_id,
+        name,
+        source,
+        stale: result.is_err() || age.is_some_and(|age| age > stale_after),
+        received_at,
+        age_secs: age.map(|age| age.as_secs()),
+        error: result.as_ref().err().cloned(),
+    }
+}
+
+/// Fetch a member's containers from its API, forwarding the caller's credentials
+pub async fn remote_containers(base_url: &str, authorization: Option<&str>) -> NodeResult {
+    let mut request = CLIENT.get(format!("{}/api/v1/containers", base_url));
+    if let Some(authorization) = authorization {
+        request = request.header("Authorization", authorization);
+    }
+
+    let response = request.send().await.map_err(|e| {
+        if e.is_timeout() {
+            format!("no response within {}s", NODE_REQUEST_TIMEOUT.as_secs())
+        } else {
+            e.to_string()
+        }
+    })?;
+    if !response.status().is_success() {
+        return Err(format!("node answered {}", response.status()));
+    }
+    response
+        .json::<ContainerListResponse>()
+        .await
+        .map(|list| list.containers.into_iter().map(summary).collect())
+        .map_err(|e| e.to_string())
+}
+
+/// Combine per-node results with the cluster assignment map
+///
+/// Nodes that reported are authoritative for what they run, even when
+/// their report is stale. For nodes without a report, and nodes that were
+/// not asked at all, the assigned container ids are listed without a name
+/// or status.
+pub fn merge(
+    results: Vec<(Uuid, NodeResult)>,
+    stale: &HashSet<Uuid>,
+    assignments: &HashMap<Uuid, Vec<Uuid>>,
+) -> Vec<ClusterContainer> {
+    let mut containers = Vec::new();
+    let mut reported = HashSet::new();
+
+    for (node_id, result) in results {
+        reported.insert(node_id);
+        match result {
+            Ok(live) => containers.extend(live.into_iter().map(|c| ClusterContainer {
+                id: None,
+                name: Some(c.name),
+                status: Some(c.status),
+                health: Some(c.health),
+                node_id,
+                node_reachable: !stale.contains(&node_id),
+            })),
+            Err(_) => containers.extend(assigned(assignments, node_id)),
+        }
+    }
+
+    let mut unreported: Vec<_> = assignments
+        .keys()
+        .filter(|id| !reported.contains(id))
+        .copied()
+        .collect();
+    unreported.sort();
+    for node_id in unreported {
+        containers.extend(assigned(assignments, node_id));
+    }
+
+    containers
+}
+
+fn assigned(
+    assignments: &HashMap<Uuid, Vec<Uuid>>,
+    node_id: Uuid,
+) -> impl Iterator<Item = ClusterContainer> + '_ {
+    assignments
+        .get(&node_id)
+        .into_iter()
+        .flatten()
+        .map(move |id| ClusterContainer {
+            id: Some(*id),
+            name: None,
+            status: None,
+            health: None,
+            node_id,
+            node_reachable: false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(name: &str, status: ContainerStatus) -> ContainerSummary {
+        ContainerSummary {
+            name: name.to_string(),
+            status,
+            health: HealthStatus::Healthy,
+        }
+    }
+
+    #[test]
+    fn test_merge_marks_stale_nodes() {
+        let live_node = Uuid::new_v4();
+        let stale_node = Uuid::new_v4();
+        let silent_node = Uuid::new_v4();
+        let lagging_node = Uuid::new_v4();
+        let assigned_id = Uuid::new_v4();
+        let orphan_id = Uuid::new_v4();
+        let assignments = HashMap::from([
+            (stale_node, vec![assigned_id]),
+            (silent_node, vec![orphan_id]),
+        ]);
+
+        let merged = merge(
+            vec![
+                (
+                    live_node,
+                    Ok(vec![container("web", ContainerStatus::Running)]),
+                ),
+                (stale_node, Err("no response within 3s".to_string())),
+                (
+                    lagging_node,
+                    Ok(vec![container("db", ContainerStatus::Stopped)]),
+                ),
+            ],
+            &HashSet::from([stale_node, lagging_node]),
+            &assignments,
+        );
+
+        assert_eq!(merged.len(), 4);
+        assert_eq!(merged[0].name.as_deref(), Some("web"));
+        assert_eq!(merged[0].status, Some(ContainerStatus::Running));
+        assert_eq!(merged[0].health, Some(HealthStatus::Healthy));
+        assert!(merged[0].node_reachable);
+        assert_eq!(merged[1].id, Some(assigned_id));
+        assert_eq!(merged[1].node_id, stale_node);
+        assert_eq!(merged[1].status, None);
+        assert!(!merged[1].node_reachable);
+        // A stale report still says what the node last ran
+        assert_eq!(merged[2].name.as_deref(), Some("db"));
+        assert!(!merged[2].node_reachable);
+        assert_eq!(merged[3].id, Some(orphan_id));
+        assert_eq!(merged[3].node_id, silent_node);
+    }
+
+    #[test]
+    fn test_old_heartbeat_reports_are_stale() {
+        let cached = NodeContainers {
+            version: 1,
+            received_at: Utc::now() - chrono::Duration::seconds(10),
+            containers: vec![container("web", ContainerStatus::Running)],
+        };
+        let result = heartbeat_containers(Some(&cached));
+        let listing = |stale_after| {
+            node_listing(
+                Uuid::nil(),
+                None,
+                ListingSource::Heartbeat,
+                &result,
+                Some(cached.received_at),
+                stale_after,
+            )
+        };
+        assert!(!listing(Duration::from_secs(30)).stale);
+        let old = listing(Duration::from_secs(3));
+        assert!(old.stale);
+        assert!(old.age_secs.unwrap() >= 10);
+
+        let missing = heartbeat_containers(None);
+        let listing = node_listing(
+            Uuid::nil(),
+            None,
+            ListingSource::Heartbeat,
+            &missing,
+            None,
+            Duration::from_secs(3),
+        );
+        assert!(listing.stale && listing.error.is_some());
+    }
+}
@@ -1,5 +1,13 @@
+use crate::paths::{Paths, DEFAULT_DATA_DIR, DEFAULT_LOG_DIR};
+use crate::rbac::Permission;
+use chrono::{DateTime, Utc};
+use cluster::ListenerLimits;
+use container_manager::downloads::DownloadSettings;
+use container_manager::DefaultLimits;
+use models::{StorageType, VolumeProvisioning};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
@@ -10,6 +18,58 @@ pub struct AppConfig {
     pub network: NetworkConfig,
     pub logging: LoggingConfig,
     pub security: SecurityConfig,
+    #[serde(default)]
+    pub memory_watchdog: MemoryWatchdogConfig,
+    #[serde(default)]
+    pub audit: AuditRetentionConfig,
+    #[serde(default)]
+    pub audit_forwarder: AuditForwarderConfig,
+    #[serde(default)]
+    pub readiness: ReadinessConfig,
+    #[serde(default)]
+    pub usage_history: UsageHistoryConfig,
+    #[serde(default)]
+    pub container: ContainerOpsConfig,
+    #[serde(default)]
+    pub health_checks: HealthChecksConfig,
+    /// Proxy, CA bundle and limits for template downloads and webhooks
+    #[serde(default)]
+    pub downloads: DownloadSettings,
+    #[serde(default)]
+    pub paths: PathsConfig,
+    #[serde(default)]
+    pub dev: DevConfig,
+}
+
+/// Development aids; none of these belong on a real host
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DevConfig {
+    /// Run containers and bridges as in-memory records instead of through
+    /// LXC and `ip`, so the API works without LXC or root
+    pub fake_backend: bool,
+}
+
+/// Root directories the remaining paths are derived from; see [`Paths`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathsConfig {
+    /// State written by the server (default /var/lib/arm-hypervisor)
+    pub data_dir: PathBuf,
+    /// Log files (default /var/log/arm-hypervisor)
+    pub log_dir: PathBuf,
+    /// LXC container directory (default /var/lib/lxc); `LXC_ROOT` overrides it
+    pub lxc_root: Option<PathBuf>,
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from(DEFAULT_DATA_DIR),
+            log_dir: PathBuf::from(DEFAULT_LOG_DIR),
+            lxc_root: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +81,27 @@ pub struct ServerConfig {
     pub keepalive: Option<u64>,
     pub client_timeout: Option<u64>,
     pub tls: Option<TlsConfig>,
+    /// Refuse to start when LXC can be run neither as root nor through sudo
+    #[serde(default)]
+    pub require_privileges: bool,
+    /// Offer HTTP/2 to TLS clients over ALPN; plain HTTP is always HTTP/1.1
+    #[serde(default = "default_http2_enabled")]
+    pub http2_enabled: bool,
+    /// Longest a `/wait` request may hold its connection open, in seconds
+    #[serde(default = "default_max_wait_secs")]
+    pub max_wait_secs: u64,
+    /// Threads each runtime may start for blocking work (directory walks,
+    /// LXC commands); tokio's default of 512 when unset. See [`crate::runtime`]
+    #[serde(default)]
+    pub blocking_threads: Option<usize>,
+}
+
+fn default_http2_enabled() -> bool {
+    true
+}
+
+fn default_max_wait_secs() -> u64 {
+    300
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,38 +128,215 @@ pub struct ClusterConfig {
     pub bind_port: u16,
     pub advertise_address: Option<String>,
     pub join_addresses: Vec<String>,
+    /// Start a new cluster led by this node instead of joining one through
+    /// `join_addresses`; the other nodes list this one as their seed
+    #[serde(default)]
+    pub bootstrap: bool,
     pub election_timeout: Option<u64>,
     pub heartbeat_interval: Option<u64>,
+    /// Shared secret joining nodes present; generated at first start when
+    /// unset. Ignored once a token has been stored in `join_token_path`
+    #[serde(default)]
+    pub join_token: Option<String>,
+    /// Where the cluster join token is kept, including after rotation
+    /// (default <data_dir>/cluster-join-token)
+    #[serde(default)]
+    pub join_token_path: Option<PathBuf>,
+    /// Exit at startup when no seed in `join_addresses` admits this node
+    #[serde(default)]
+    pub require_join: bool,
+    /// Try the seeds before serving traffic instead of in the background
+    #[serde(default)]
+    pub join_before_serving: bool,
+    /// Rounds over all seeds before startup continues (or fails with
+    /// `require_join`)
+    #[serde(default = "default_join_retries")]
+    pub join_retries: u32,
+    /// Peer connections the cluster listener handles at once; further ones
+    /// are closed on accept
+    #[serde(default = "default_max_peer_connections")]
+    pub max_peer_connections: usize,
+    /// Seconds a peer connection may stay silent before it is closed
+    #[serde(default = "default_peer_read_timeout")]
+    pub peer_read_timeout: u64,
+    /// Seconds open peer connections get to finish at shutdown
+    #[serde(default = "default_peer_drain_timeout")]
+    pub peer_drain_timeout: u64,
+}
+
+fn default_join_retries() -> u32 {
+    5
+}
+
+fn default_max_peer_connections() -> usize {
+    64
+}
+
+fn default_peer_read_timeout() -> u64 {
+    30
+}
+
+fn default_peer_drain_timeout() -> u64 {
+    5
+}
+
+impl ClusterConfig {
+    /// Bounds for the cluster listener
+    pub fn listener_limits(&self) -> ListenerLimits {
+        ListenerLimits {
+            max_peers: self.max_peer_connections,
+            read_timeout: Duration::from_secs(self.peer_read_timeout),
+            drain_timeout: Duration::from_secs(self.peer_drain_timeout),
+        }
+    }
+
+    /// Whether this node is part of a cluster, either founding it or
+    /// joining one
+    pub fn is_clustered(&self) -> bool {
+        self.bootstrap || !self.join_addresses.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StorageConfig {
-    pub base_path: PathBuf,
+    /// Storage root (default <data_dir>/storage)
+    #[serde(default)]
+    pub base_path: Option<PathBuf>,
     pub default_pool: String,
     pub pool_configs: Vec<PoolConfig>,
+    /// Size cap for the downloaded image cache in MiB (default 10240)
+    #[serde(default)]
+    pub image_cache_max_mb: Option<u64>,
+    /// Directory for downloaded images (default <base_path>/images)
+    #[serde(default)]
+    pub image_cache_dir: Option<PathBuf>,
+    /// Image server or local mirror for the download template
+    #[serde(default)]
+    pub image_server: Option<String>,
+    #[serde(default)]
+    pub usage_alerts: PoolUsageAlertConfig,
+}
+
+impl StorageConfig {
+    pub fn image_cache_max_bytes(&self) -> u64 {
+        self.image_cache_max_mb.unwrap_or(10240) * 1024 * 1024
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PoolConfig {
     pub name: String,
     pub storage_type: String,
+    /// Where the pool lives on this node; shared pools are mounted here
     pub path: String,
+    /// Share mounted on `path` at startup for nfs (`server:/export`) and cifs
+    /// (`//server/share`) pools; without it the share must be mounted already
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Mount options, an empty value for flags such as `hard`
     pub options: std::collections::HashMap<String, String>,
+    /// Overrides `usage_alerts.warning_percent` for this pool
+    #[serde(default)]
+    pub warning_percent: Option<f64>,
+    /// Overrides `usage_alerts.critical_percent` for this pool
+    #[serde(default)]
+    pub critical_percent: Option<f64>,
+    /// Default for volumes created in this pool
+    #[serde(default)]
+    pub provisioning: VolumeProvisioning,
+}
+
+impl PoolConfig {
+    pub fn storage_type(&self) -> Option<StorageType> {
+        match self.storage_type.as_str() {
+            "local" => Some(StorageType::Local),
+            "nfs" => Some(StorageType::Nfs),
+            "cifs" => Some(StorageType::Cifs),
+            _ => None,
+        }
+    }
+
+    /// `options` as `key=value` or `key`, in key order
+    pub fn mount_options(&self) -> Vec<String> {
+        let mut options: Vec<String> = self
+            .options
+            .iter()
+            .map(|(key, value)| match value.as_str() {
+                "" => key.clone(),
+                value => format!("{}={}", key, value),
+            })
+            .collect();
+        options.sort();
+        options
+    }
+}
+
+/// Alerts raised when a configured pool fills up
+///
+/// A pool enters warning or critical as soon as its usage reaches the
+/// threshold, but only leaves it once usage has dropped `hysteresis_percent`
+/// below, so a pool hovering around a threshold alerts once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PoolUsageAlertConfig {
+    pub enabled: bool,
+    pub warning_percent: f64,
+    pub critical_percent: f64,
+    pub hysteresis_percent: f64,
+    pub check_interval_secs: u64,
+    /// Alerts are POSTed here as audit entries, in addition to the audit log
+    pub webhook_url: Option<String>,
+}
+
+impl Default for PoolUsageAlertConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            warning_percent: 80.0,
+            critical_percent: 90.0,
+            hysteresis_percent: 5.0,
+            check_interval_secs: 60,
+            webhook_url: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkConfig {
     pub default_bridge: String,
     pub bridge_prefix: String,
+    /// Addresses handed to interfaces created with `ipv4 = "auto"`; empty
+    /// disables automatic addressing
     pub ip_range: String,
     pub dns_servers: Vec<String>,
     pub firewall_enabled: bool,
+    /// Where managed firewall rules are saved on shutdown and restored on startup
+    /// (default <data_dir>/firewall.rules)
+    #[serde(default)]
+    pub firewall_rules_path: Option<PathBuf>,
+    /// Bridges created through the API, recreated by `?fix=true` on start
+    /// (default <data_dir>/bridges.json)
+    #[serde(default)]
+    pub bridge_state_path: Option<PathBuf>,
+    /// Addresses allocated from `ip_range`
+    /// (default <data_dir>/ipam.json)
+    #[serde(default)]
+    pub ipam_state_path: Option<PathBuf>,
+    /// Start containers without checking their bridges, mount sources and
+    /// addresses first, for hosts whose networking is managed elsewhere
+    #[serde(default)]
+    pub skip_start_checks: bool,
+    /// Also probe the bridge with `arping` before giving a container a
+    /// static IPv4 address, for hosts on the segment this node does not manage
+    #[serde(default)]
+    pub probe_address_conflicts: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoggingConfig {
     pub level: String,
     pub format: Option<String>,
+    /// Log file (default <log_dir>/hypervisor.log)
     pub file: Option<PathBuf>,
     pub rotate: Option<bool>,
     pub max_files: Option<u32>,
@@ -90,9 +348,63 @@ pub struct SecurityConfig {
     pub auth_enabled: bool,
     pub jwt_secret: Option<String>,
     pub jwt_expiry: Option<u64>,
-    pub api_keys: Vec<String>,
+    /// Keys accepted in `X-API-Key`; more can be created through the API
+    pub api_keys: Vec<ApiKeyConfig>,
     pub cors_origins: Vec<String>,
     pub rate_limit: Option<RateLimitConfig>,
+    /// File holding the key container secrets are encrypted under; secrets are
+    /// disabled when unset
+    #[serde(default)]
+    pub secrets_master_key_file: Option<String>,
+    /// Directory for encrypted secret values (default <data_dir>/secrets)
+    #[serde(default)]
+    pub secrets_dir: Option<String>,
+    #[serde(default)]
+    pub login_lockout: LoginLockoutConfig,
+    /// Reverse proxies (CIDRs) whose `X-Forwarded-For` names the client;
+    /// other peers are taken as the client themselves
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+}
+
+/// An API key defined in the config file; only its hash is configured
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyConfig {
+    /// Shown in listings and recorded as the actor (`api-key:<name>`)
+    pub name: String,
+    /// Hex SHA-256 of the key, e.g. `printf %s "$KEY" | sha256sum`
+    pub key_hash: String,
+    pub permissions: Vec<Permission>,
+    #[serde(default = "default_api_key_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn default_api_key_enabled() -> bool {
+    true
+}
+
+/// Brute-force protection for password logins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoginLockoutConfig {
+    /// Consecutive failed logins before the account is locked
+    pub max_failed_attempts: u32,
+    /// How long the account stays locked; 0 locks it until an admin unlocks it
+    pub lockout_secs: u64,
+    /// Lock duration for admins, who are never locked indefinitely
+    pub admin_lockout_secs: u64,
+}
+
+impl Default for LoginLockoutConfig {
+    fn default() -> Self {
+        Self {
+            max_failed_attempts: 5,
+            lockout_secs: 900,
+            admin_lockout_secs: 60,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -101,6 +413,223 @@ pub struct RateLimitConfig {
     pub burst_size: u32,
 }
 
+/// Host memory protection: freeze the largest containers when memory runs low
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryWatchdogConfig {
+    pub enabled: bool,
+    /// Freeze containers when host available memory drops below this (MiB)
+    pub min_available_mb: u64,
+    /// Unfreeze containers once available memory is back above this (MiB)
+    pub recovery_available_mb: u64,
+    pub check_interval_secs: u64,
+    /// Containers that must never be frozen
+    pub critical_containers: Vec<String>,
+}
+
+impl Default for MemoryWatchdogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_available_mb: 256,
+            recovery_available_mb: 512,
+            check_interval_secs: 10,
+            critical_containers: vec![],
+        }
+    }
+}
+
+/// Per-container usage history kept in memory for the UI and CLI
+///
+/// Each container keeps `retention_secs / sample_interval_secs` samples; long
+/// term storage is left to Prometheus.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageHistoryConfig {
+    pub enabled: bool,
+    pub sample_interval_secs: u64,
+    pub retention_secs: u64,
+    /// Containers with history; the least recently sampled one is dropped
+    /// to make room for another
+    pub max_containers: usize,
+}
+
+impl Default for UsageHistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sample_interval_secs: 60,
+            retention_secs: 86400,
+            max_containers: 256,
+        }
+    }
+}
+
+/// Memory cap for containers created without one, unless configured
+pub const DEFAULT_CONTAINER_MEMORY_LIMIT: u64 = 512 * 1024 * 1024;
+
+/// Limits on container operations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContainerOpsConfig {
+    /// Heavy operations (create, clone, snapshot, restore, destroy) run at
+    /// once; more requests wait. Starts, stops and reads are never queued.
+    #[serde(alias = "max_concurrent_ops")]
+    pub max_concurrent_heavy_ops: usize,
+    /// Seconds a heavy operation may wait for a slot before it fails
+    pub heavy_op_queue_timeout_secs: u64,
+    /// CPU cores for containers created without `cpu_limit`
+    pub default_cpu_limit: Option<u32>,
+    /// Memory (bytes) for containers created without `memory_limit`
+    pub default_memory_limit: Option<u64>,
+    /// Disk (bytes) for containers created without `disk_limit`
+    pub default_disk_limit: Option<u64>,
+    /// `lxc.arch` for generated configs and the architecture this node
+    /// reports; detected from the host when unset
+    pub arch: Option<String>,
+    /// Lifecycle events kept per container; older ones are dropped
+    pub event_history_size: usize,
+    /// Keep event histories in `paths.data_dir` across restarts
+    pub persist_events: bool,
+}
+
+impl ContainerOpsConfig {
+    pub fn default_limits(&self) -> DefaultLimits {
+        DefaultLimits {
+            cpu_limit: self.default_cpu_limit,
+            memory_limit: self.default_memory_limit,
+            disk_limit: self.default_disk_limit,
+        }
+    }
+}
+
+impl Default for ContainerOpsConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_heavy_ops: container_manager::locks::DEFAULT_MAX_CONCURRENT_OPS,
+            heavy_op_queue_timeout_secs: container_manager::locks::DEFAULT_QUEUE_TIMEOUT.as_secs(),
+            default_cpu_limit: None,
+            default_memory_limit: Some(DEFAULT_CONTAINER_MEMORY_LIMIT),
+            default_disk_limit: None,
+            arch: None,
+            event_history_size: container_manager::events::DEFAULT_EVENTS_PER_CONTAINER,
+            persist_events: false,
+        }
+    }
+}
+
+/// Background probes of containers with a `health_check`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthChecksConfig {
+    pub enabled: bool,
+    /// Probes running at once across all containers
+    pub max_concurrent_probes: usize,
+}
+
+impl Default for HealthChecksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_concurrent_probes: 4,
+        }
+    }
+}
+
+/// How long entries are kept in the server's in-memory audit log; `None`
+/// disables a limit
+///
+/// The server keeps no audit records on disk. Entries already sent through
+/// the audit forwarder are not touched; the receiving system keeps them by
+/// its own policy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditRetentionConfig {
+    pub max_age_days: Option<u32>,
+    /// Upper bound on the total size of retained entries (MiB)
+    pub max_total_size_mb: Option<u64>,
+    pub purge_interval_secs: u64,
+    /// Let users without SystemAdmin read their own entries; when off only
+    /// admins can read the audit log
+    pub self_service: bool,
+}
+
+impl Default for AuditRetentionConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: Some(90),
+            max_total_size_mb: None,
+            purge_interval_secs: 3600,
+            self_service: true,
+        }
+    }
+}
+
+/// External destination for audit events
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditForwardTarget {
+    #[default]
+    None,
+    Webhook,
+    Syslog,
+}
+
+/// Forwarding of audit events to an external collector such as a SIEM
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditForwarderConfig {
+    pub target: AuditForwardTarget,
+    /// Receives one JSON audit entry per POST
+    pub webhook_url: Option<String>,
+    /// Syslog server as host:port over UDP; the local /dev/log socket if unset
+    pub syslog_address: Option<String>,
+    /// Entries waiting to be forwarded; beyond this new entries are dropped
+    pub queue_size: usize,
+    /// Delivery attempts after the first before an entry is given up on
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every further one
+    pub initial_backoff_ms: u64,
+}
+
+impl Default for AuditForwarderConfig {
+    fn default() -> Self {
+        Self {
+            target: AuditForwardTarget::None,
+            webhook_url: None,
+            syslog_address: None,
+            queue_size: 1000,
+            max_retries: 5,
+            initial_backoff_ms: 500,
+        }
+    }
+}
+
+/// Which gates `/ready` checks besides LXC and bridges
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReadinessConfig {
+    /// The configured database accepts connections
+    pub check_database: bool,
+    /// The default storage pool is mounted and writable
+    pub check_storage: bool,
+    /// In cluster mode, this node is the leader or the leader is reachable
+    pub check_cluster_leader: bool,
+    /// Time each gate may take before it counts as failed (ms)
+    pub gate_timeout_ms: u64,
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        Self {
+            check_database: false,
+            check_storage: true,
+            check_cluster_leader: true,
+            gate_timeout_ms: 2000,
+        }
+    }
+}
+
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
@@ -112,6 +641,10 @@ impl Default for AppConfig {
                 keepalive: Some(30),
                 client_timeout: Some(60),
                 tls: None,
+                require_privileges: false,
+                http2_enabled: true,
+                max_wait_secs: default_max_wait_secs(),
+                blocking_threads: None,
             },
             database: DatabaseConfig {
                 url: "sqlite:///var/lib/arm-hypervisor/database.db".to_string(),
@@ -129,16 +662,33 @@ impl Default for AppConfig {
                 join_addresses: vec![],
                 election_timeout: Some(5000),
                 heartbeat_interval: Some(1000),
+                join_token: None,
+                join_token_path: None,
+                require_join: false,
+                join_before_serving: false,
+                bootstrap: false,
+                join_retries: default_join_retries(),
+                max_peer_connections: default_max_peer_connections(),
+                peer_read_timeout: default_peer_read_timeout(),
+                peer_drain_timeout: default_peer_drain_timeout(),
             },
             storage: StorageConfig {
-                base_path: PathBuf::from("/var/lib/arm-hypervisor/storage"),
+                base_path: None,
                 default_pool: "default".to_string(),
                 pool_configs: vec![PoolConfig {
                     name: "default".to_string(),
                     storage_type: "local".to_string(),
-                    path: "/var/lib/arm-hypervisor/storage/default".to_string(),
+                    path: "default".to_string(),
+                    source: None,
                     options: std::collections::HashMap::new(),
+                    warning_percent: None,
+                    critical_percent: None,
+                    provisioning: Default::default(),
                 }],
+                image_cache_max_mb: None,
+                image_cache_dir: None,
+                image_server: None,
+                usage_alerts: PoolUsageAlertConfig::default(),
             },
             network: NetworkConfig {
                 default_bridge: "lxcbr0".to_string(),
@@ -146,11 +696,16 @@ impl Default for AppConfig {
                 ip_range: "192.168.100.0/24".to_string(),
                 dns_servers: vec!["8.8.8.8".to_string(), "8.8.4.4".to_string()],
                 firewall_enabled: true,
+                firewall_rules_path: None,
+                bridge_state_path: None,
+                ipam_state_path: None,
+                skip_start_checks: false,
+                probe_address_conflicts: false,
             },
             logging: LoggingConfig {
                 level: "info".to_string(),
                 format: Some("json".to_string()),
-                file: Some(PathBuf::from("/var/log/arm-hypervisor/hypervisor.log")),
+                file: None,
                 rotate: Some(true),
                 max_files: Some(10),
                 max_size: Some("100MB".to_string()),
@@ -165,11 +720,42 @@ impl Default for AppConfig {
                     requests_per_minute: 60,
                     burst_size: 10,
                 }),
+                secrets_master_key_file: None,
+                secrets_dir: None,
+                login_lockout: LoginLockoutConfig::default(),
+                trusted_proxies: vec![],
             },
+            memory_watchdog: MemoryWatchdogConfig::default(),
+            audit: AuditRetentionConfig::default(),
+            audit_forwarder: AuditForwarderConfig::default(),
+            readiness: ReadinessConfig::default(),
+            usage_history: UsageHistoryConfig::default(),
+            container: ContainerOpsConfig::default(),
+            health_checks: HealthChecksConfig::default(),
+            downloads: DownloadSettings::default(),
+            paths: PathsConfig::default(),
+            dev: DevConfig::default(),
         }
     }
 }
 
+fn is_loopback(host: &str) -> bool {
+    host == "localhost"
+        || host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Why a config file was not merged
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigMergeError {
+    #[error("{0}")]
+    Load(Box<dyn std::error::Error>),
+
+    #[error("invalid configuration: {}", .0.join("; "))]
+    Invalid(Vec<String>),
+}
+
 impl AppConfig {
     pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let content = std::fs::read_to_string(path)?;
@@ -195,6 +781,11 @@ impl AppConfig {
                 config.server.port = port;
             }
         }
+        if let Ok(threads) = std::env::var("SERVER_BLOCKING_THREADS") {
+            if let Ok(threads) = threads.parse() {
+                config.server.blocking_threads = Some(threads);
+            }
+        }
 
         // Database config from env
         if let Ok(url) = std::env::var("DATABASE_URL") {
@@ -214,6 +805,10 @@ impl AppConfig {
             }
         }
 
+        if let Ok(data_dir) = std::env::var("DATA_DIR") {
+            config.paths.data_dir = PathBuf::from(data_dir);
+        }
+
         // Logging config from env
         if let Ok(level) = std::env::var("LOG_LEVEL") {
             config.logging.level = level;
@@ -227,74 +822,134 @@ impl AppConfig {
             config.security.auth_enabled = auth.parse().unwrap_or(true);
         }
 
+        if let Ok(fake) = std::env::var("DEV_FAKE_BACKEND") {
+            config.dev.fake_backend = fake.parse().unwrap_or(false);
+        }
+
         config
     }
 
-    pub fn merge_with_file(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let file_config = Self::from_file(path)?;
+    /// Locations derived from `paths.data_dir` and the per-path overrides
+    pub fn paths(&self) -> Paths {
+        Paths::resolve(self)
+    }
+
+    /// Merge the config file at `path` into this config
+    ///
+    /// The merged config is validated before it replaces this one; on any
+    /// error this config is left exactly as it was.
+    pub fn merge_with_file(&mut self, path: &str) -> Result<(), ConfigMergeError> {
+        let file_config = Self::from_file(path).map_err(ConfigMergeError::Load)?;
+        let merged = self.merged_with(file_config);
+        merged.validate().map_err(ConfigMergeError::Invalid)?;
+        *self = merged;
+        Ok(())
+    }
+
+    /// This config with `file_config` applied over it
+    fn merged_with(&self, file_config: AppConfig) -> AppConfig {
+        let mut merged = self.clone();
 
         // Merge configurations (file overrides defaults, env overrides file)
-        self.server.host = file_config.server.host;
-        self.server.port = file_config.server.port;
-        self.server.workers = file_config.server.workers.or(self.server.workers);
-        self.server.max_connections = file_config
+        merged.server.host = file_config.server.host;
+        merged.server.port = file_config.server.port;
+        merged.server.workers = file_config.server.workers.or(merged.server.workers);
+        merged.server.max_connections = file_config
             .server
             .max_connections
-            .or(self.server.max_connections);
-        self.server.keepalive = file_config.server.keepalive.or(self.server.keepalive);
-        self.server.client_timeout = file_config
+            .or(merged.server.max_connections);
+        merged.server.keepalive = file_config.server.keepalive.or(merged.server.keepalive);
+        merged.server.client_timeout = file_config
             .server
             .client_timeout
-            .or(self.server.client_timeout);
-        self.server.tls = file_config.server.tls.or(self.server.tls.clone());
+            .or(merged.server.client_timeout);
+        merged.server.tls = file_config.server.tls.or(merged.server.tls.clone());
+        merged.server.require_privileges = file_config.server.require_privileges;
+        merged.server.http2_enabled = file_config.server.http2_enabled;
+        merged.server.max_wait_secs = file_config.server.max_wait_secs;
+        merged.server.blocking_threads = file_config
+            .server
+            .blocking_threads
+            .or(merged.server.blocking_threads);
 
-        self.database.url = file_config.database.url;
-        self.database.max_connections = file_config
+        merged.database.url = file_config.database.url;
+        merged.database.max_connections = file_config
             .database
             .max_connections
-            .or(self.database.max_connections);
-        self.database.min_connections = file_config
+            .or(merged.database.max_connections);
+        merged.database.min_connections = file_config
             .database
             .min_connections
-            .or(self.database.min_connections);
-        self.database.acquire_timeout = file_config
+            .or(merged.database.min_connections);
+        merged.database.acquire_timeout = file_config
             .database
             .acquire_timeout
-            .or(self.database.acquire_timeout);
-        self.database.idle_timeout = file_config
+            .or(merged.database.acquire_timeout);
+        merged.database.idle_timeout = file_config
             .database
             .idle_timeout
-            .or(self.database.idle_timeout);
+            .or(merged.database.idle_timeout);
 
-        self.cluster = file_config.cluster;
+        merged.cluster = file_config.cluster;
 
-        self.storage = file_config.storage;
-        self.network = file_config.network;
+        merged.storage = file_config.storage;
+        merged.network = file_config.network;
 
-        self.logging.level = file_config.logging.level;
-        self.logging.format = file_config.logging.format.or(self.logging.format.clone());
-        self.logging.file = file_config.logging.file.or(self.logging.file.clone());
-        self.logging.rotate = file_config.logging.rotate.or(self.logging.rotate);
-        self.logging.max_files = file_config.logging.max_files.or(self.logging.max_files);
-        self.logging.max_size = file_config
+        merged.logging.level = file_config.logging.level;
+        merged.logging.format = file_config.logging.format.or(merged.logging.format.clone());
+        merged.logging.file = file_config.logging.file.or(merged.logging.file.clone());
+        merged.logging.rotate = file_config.logging.rotate.or(merged.logging.rotate);
+        merged.logging.max_files = file_config.logging.max_files.or(merged.logging.max_files);
+        merged.logging.max_size = file_config
             .logging
             .max_size
-            .or(self.logging.max_size.clone());
+            .or(merged.logging.max_size.clone());
 
-        self.security.auth_enabled = file_config.security.auth_enabled;
-        self.security.jwt_secret = file_config
+        merged.security.auth_enabled = file_config.security.auth_enabled;
+        merged.security.jwt_secret = file_config
             .security
             .jwt_secret
-            .or(self.security.jwt_secret.clone());
-        self.security.jwt_expiry = file_config.security.jwt_expiry.or(self.security.jwt_expiry);
-        self.security.api_keys = file_config.security.api_keys;
-        self.security.cors_origins = file_config.security.cors_origins;
-        self.security.rate_limit = file_config
+            .or(merged.security.jwt_secret.clone());
+        merged.security.jwt_expiry = file_config
+            .security
+            .jwt_expiry
+            .or(merged.security.jwt_expiry);
+        merged.security.api_keys = file_config.security.api_keys;
+        merged.security.cors_origins = file_config.security.cors_origins;
+        merged.security.rate_limit = file_config
             .security
             .rate_limit
-            .or(self.security.rate_limit.clone());
+            .or(merged.security.rate_limit.clone());
+        merged.security.secrets_master_key_file = file_config
+            .security
+            .secrets_master_key_file
+            .or(merged.security.secrets_master_key_file.clone());
+        merged.security.secrets_dir = file_config
+            .security
+            .secrets_dir
+            .or(merged.security.secrets_dir.clone());
+        merged.security.login_lockout = file_config.security.login_lockout;
+        merged.security.trusted_proxies = file_config.security.trusted_proxies;
 
-        Ok(())
+        merged.memory_watchdog = file_config.memory_watchdog;
+        merged.audit = file_config.audit;
+        merged.audit_forwarder = file_config.audit_forwarder;
+        merged.readiness = file_config.readiness;
+        merged.usage_history = file_config.usage_history;
+        merged.container = file_config.container;
+        merged.health_checks = file_config.health_checks;
+        merged.downloads = file_config.downloads;
+        merged.paths = file_config.paths;
+        // An explicitly set DATA_DIR wins over the file, as documented
+        if std::env::var_os("DATA_DIR").is_some() {
+            merged.paths.data_dir = self.paths.data_dir.clone();
+        }
+        merged.dev = file_config.dev;
+        if std::env::var_os("DEV_FAKE_BACKEND").is_some() {
+            merged.dev.fake_backend = self.dev.fake_backend;
+        }
+
+        merged
     }
 
     pub fn validate(&self) -> Result<(), Vec<String>> {
@@ -307,6 +962,27 @@ impl AppConfig {
         if self.server.port == 0 {
             errors.push("Server port must be greater than 0".to_string());
         }
+        if self.server.max_wait_secs == 0 {
+            errors.push("server.max_wait_secs must be greater than 0".to_string());
+        }
+        if self.server.blocking_threads == Some(0) {
+            errors.push("server.blocking_threads must be greater than 0".to_string());
+        }
+        // Fake containers without auth are fine for a laptop, not a network
+        if self.dev.fake_backend && !self.security.auth_enabled && !is_loopback(&self.server.host) {
+            errors.push(format!(
+                "dev.fake_backend with auth disabled may only bind a loopback address, not {}",
+                self.server.host
+            ));
+        }
+
+        // Validate paths config; relative roots would depend on the working directory
+        if !self.paths.data_dir.is_absolute() {
+            errors.push("paths.data_dir must be an absolute path".to_string());
+        }
+        if !self.paths.log_dir.is_absolute() {
+            errors.push("paths.log_dir must be an absolute path".to_string());
+        }
 
         // Validate database config
         if self.database.url.is_empty() {
@@ -320,6 +996,23 @@ impl AppConfig {
         if self.cluster.bind_address.is_empty() {
             errors.push("Cluster bind address cannot be empty".to_string());
         }
+        if self.cluster.require_join && self.cluster.join_addresses.is_empty() {
+            errors.push("cluster.require_join needs at least one join address".to_string());
+        }
+        if self.cluster.bootstrap && self.cluster.require_join {
+            errors.push(
+                "cluster.bootstrap starts a new cluster and cannot require a join".to_string(),
+            );
+        }
+        if self.cluster.join_retries == 0 {
+            errors.push("Cluster join retries must be greater than 0".to_string());
+        }
+        if self.cluster.max_peer_connections == 0 {
+            errors.push("Cluster max peer connections must be greater than 0".to_string());
+        }
+        if self.cluster.peer_read_timeout == 0 {
+            errors.push("Cluster peer read timeout must be greater than 0".to_string());
+        }
 
         // Validate storage config
         if self.storage.default_pool.is_empty() {
@@ -328,11 +1021,73 @@ impl AppConfig {
         if self.storage.pool_configs.is_empty() {
             errors.push("At least one storage pool configuration is required".to_string());
         }
+        for pool in &self.storage.pool_configs {
+            let Some(storage_type) = pool.storage_type() else {
+                errors.push(format!(
+                    "Storage pool '{}' has unknown storage_type '{}'",
+                    pool.name, pool.storage_type
+                ));
+                continue;
+            };
+            if let Err(e) = storage::validate_mount_options(&storage_type, &pool.mount_options()) {
+                errors.push(format!("Storage pool '{}': {}", pool.name, e));
+            }
+            let source_valid = match (&storage_type, pool.source.as_deref()) {
+                (_, None) => true,
+                (StorageType::Nfs, Some(source)) => models::parse_nfs_path(source).is_some(),
+                (StorageType::Cifs, Some(source)) => models::parse_cifs_path(source).is_some(),
+                (StorageType::Local, Some(_)) => false,
+            };
+            if !source_valid {
+                errors.push(format!(
+                    "Storage pool '{}' source must be server:/export for nfs or //server/share for cifs, and is not used for local pools",
+                    pool.name
+                ));
+            }
+        }
 
         // Validate network config
         if self.network.default_bridge.is_empty() {
             errors.push("Default bridge name cannot be empty".to_string());
         }
+        for server in &self.network.dns_servers {
+            if server.parse::<std::net::IpAddr>().is_err() {
+                errors.push(format!("DNS server must be an IP address: {}", server));
+            }
+        }
+        if !self.network.ip_range.is_empty() {
+            if let Err(e) = network::validate_range(&self.network.ip_range) {
+                errors.push(e.to_string());
+            }
+        }
+
+        // Validate API keys; a raw key in key_hash would be accepted by nobody
+        for (i, key) in self.security.api_keys.iter().enumerate() {
+            if key.name.trim().is_empty() {
+                errors.push(format!("security.api_keys[{}] needs a name", i));
+            }
+            if !crate::api_keys::is_key_hash(&key.key_hash) {
+                errors.push(format!(
+                    "security.api_keys '{}': key_hash must be the lowercase hex SHA-256 of the key",
+                    key.name
+                ));
+            }
+            if key.permissions.is_empty() {
+                errors.push(format!(
+                    "security.api_keys '{}' must grant at least one permission",
+                    key.name
+                ));
+            }
+            if self.security.api_keys[..i]
+                .iter()
+                .any(|other| other.name == key.name || other.key_hash == key.key_hash)
+            {
+                errors.push(format!(
+                    "security.api_keys '{}' duplicates the name or hash of another key",
+                    key.name
+                ));
+            }
+        }
 
         // Validate logging config
         let valid_levels = ["trace", "debug", "info", "warn", "error"];
@@ -340,6 +1095,117 @@ impl AppConfig {
             errors.push(format!("Invalid log level: {}", self.logging.level));
         }
 
+        // Validate memory watchdog config
+        if self.memory_watchdog.enabled {
+            if self.memory_watchdog.check_interval_secs == 0 {
+                errors.push("Memory watchdog check interval must be greater than 0".to_string());
+            }
+            if self.memory_watchdog.recovery_available_mb < self.memory_watchdog.min_available_mb {
+                errors.push(
+                    "Memory watchdog recovery threshold must not be below the freeze threshold"
+                        .to_string(),
+                );
+            }
+        }
+
+        // Validate usage history config
+        if self.usage_history.enabled {
+            if self.usage_history.sample_interval_secs == 0 {
+                errors.push("Usage history sample interval must be greater than 0".to_string());
+            }
+            if self.usage_history.retention_secs < self.usage_history.sample_interval_secs {
+                errors.push(
+                    "Usage history retention must be at least one sample interval".to_string(),
+                );
+            }
+        }
+
+        if self.container.max_concurrent_heavy_ops == 0 {
+            errors.push(
+                "Container max concurrent heavy operations must be greater than 0".to_string(),
+            );
+        }
+        if self.container.heavy_op_queue_timeout_secs == 0 {
+            errors
+                .push("Container heavy operation queue timeout must be greater than 0".to_string());
+        }
+        if self.container.default_cpu_limit == Some(0)
+            || self.container.default_memory_limit == Some(0)
+            || self.container.default_disk_limit == Some(0)
+        {
+            errors.push("Container default limits must be greater than 0".to_string());
+        }
+        if self
+            .container
+            .arch
+            .as_deref()
+            .is_some_and(|arch| arch.trim().is_empty())
+        {
+            errors.push("Container arch must not be empty".to_string());
+        }
+        if self.health_checks.enabled && self.health_checks.max_concurrent_probes == 0 {
+            errors.push("Health check max concurrent probes must be greater than 0".to_string());
+        }
+        errors.extend(self.downloads.validate());
+
+        // Validate audit retention config
+        if self.audit.purge_interval_secs == 0 {
+            errors.push("Audit purge interval must be greater than 0".to_string());
+        }
+        if self.audit.max_age_days == Some(0) {
+            errors.push("Audit max age must be at least 1 day".to_string());
+        }
+
+        // Validate audit forwarder config
+        if self.audit_forwarder.target == AuditForwardTarget::Webhook
+            && self.audit_forwarder.webhook_url.is_none()
+        {
+            errors.push("Audit webhook forwarding requires webhook_url".to_string());
+        }
+        if self.audit_forwarder.queue_size == 0 {
+            errors.push("Audit forwarder queue size must be greater than 0".to_string());
+        }
+
+        let alerts = &self.storage.usage_alerts;
+        if alerts.enabled {
+            if alerts.check_interval_secs == 0 {
+                errors.push("Pool usage check interval must be greater than 0".to_string());
+            }
+            if !(0.0..alerts.warning_percent).contains(&alerts.hysteresis_percent) {
+                errors.push(
+                    "Pool usage hysteresis must be at least 0 and below the warning threshold"
+                        .to_string(),
+                );
+            }
+            for pool in &self.storage.pool_configs {
+                let warning = pool.warning_percent.unwrap_or(alerts.warning_percent);
+                let critical = pool.critical_percent.unwrap_or(alerts.critical_percent);
+                if !(warning > 0.0 && warning < critical && critical <= 100.0) {
+                    errors.push(format!(
+                        "Storage pool '{}' needs 0 < warning_percent < critical_percent <= 100",
+                        pool.name
+                    ));
+                }
+            }
+        }
+
+        // Validate readiness config
+        if self.readiness.gate_timeout_ms == 0 {
+            errors.push("Readiness gate timeout must be greater than 0".to_string());
+        }
+        if self.readiness.check_storage
+            && !self
+                .storage
+                .pool_configs
+                .iter()
+                .any(|pool| pool.name == self.storage.default_pool)
+        {
+            errors.push(format!(
+                "Readiness storage check needs the default pool '{}' in pool_configs",
+                self.storage.default_pool
+            ));
+        }
+
         // Validate security config
         if self.security.auth_enabled {
             if let Some(ref secret) = self.security.jwt_secret {
@@ -364,6 +1230,21 @@ impl AppConfig {
                 errors.push("JWT secret is required when authentication is enabled".to_string());
             }
         }
+        if self.security.login_lockout.max_failed_attempts == 0 {
+            errors.push("Login lockout needs at least 1 failed attempt".to_string());
+        }
+        if self.security.login_lockout.admin_lockout_secs == 0 {
+            errors.push("Admin login lockout must last longer than 0 seconds".to_string());
+        }
+        if let Err(e) = crate::client_ip::TrustedProxies::parse(&self.security.trusted_proxies) {
+            errors.push(format!("Trusted proxies: {}", e));
+        }
+        if let Some(ref rate_limit) = self.security.rate_limit {
+            if rate_limit.requests_per_minute == 0 || rate_limit.burst_size == 0 {
+                errors
+                    .push("Rate limit requests and burst size must be greater than 0".to_string());
+            }
+        }
 
         // Warn about permissive CORS
         if self.security.cors_origins.contains(&"*".to_string()) {
@@ -439,6 +1320,62 @@ mod tests {
         std::env::remove_var("SERVER_PORT");
     }
 
+    #[test]
+    fn test_data_dir_env_wins_over_file() {
+        let mut file_config = AppConfig::default();
+        file_config.paths.data_dir = PathBuf::from("/srv/from-file");
+        file_config.paths.log_dir = PathBuf::from("/srv/from-file/log");
+        file_config.security.auth_enabled = false;
+        let path = std::env::temp_dir().join(format!("orchestrator_{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, toml::to_string(&file_config).unwrap()).unwrap();
+        let path = path.to_str().unwrap();
+
+        std::env::set_var("DATA_DIR", "/srv/from-env");
+        let mut config = AppConfig::from_env();
+        config.merge_with_file(path).unwrap();
+        std::env::remove_var("DATA_DIR");
+        assert_eq!(config.paths.data_dir, std::path::Path::new("/srv/from-env"));
+        // Settings the variable does not cover still come from the file
+        assert_eq!(
+            config.paths.log_dir,
+            std::path::Path::new("/srv/from-file/log")
+        );
+
+        // Without the variable the file decides
+        let mut config = AppConfig::from_env();
+        config.merge_with_file(path).unwrap();
+        assert_eq!(
+            config.paths.data_dir,
+            std::path::Path::new("/srv/from-file")
+        );
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_fake_backend_env_wins_over_file() {
+        let mut file_config = AppConfig::default();
+        file_config.dev.fake_backend = true;
+        file_config.security.auth_enabled = false;
+        file_config.server.host = "127.0.0.1".to_string();
+        let path = std::env::temp_dir().join(format!("orchestrator_{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, toml::to_string(&file_config).unwrap()).unwrap();
+        let path = path.to_str().unwrap();
+
+        std::env::set_var("DEV_FAKE_BACKEND", "false");
+        let mut config = AppConfig::from_env();
+        config.merge_with_file(path).unwrap();
+        std::env::remove_var("DEV_FAKE_BACKEND");
+        assert!(!config.dev.fake_backend);
+
+        // Without the variable the file decides
+        let mut config = AppConfig::from_env();
+        config.merge_with_file(path).unwrap();
+        assert!(config.dev.fake_backend);
+
+        let _ = std::fs::remove_file(path);
+    }
+
     #[test]
     fn test_jwt_secret_validation() {
         let mut config = AppConfig::default();
@@ -470,4 +1407,107 @@ mod tests {
             Some("a-very-long-and-secure-jwt-secret-key-that-is-definitely-not-weak".to_string());
         assert!(config.validate().is_ok());
     }
+
+    #[test]
+    fn test_fake_backend_without_auth_needs_loopback() {
+        let mut config = AppConfig::default();
+        config.security.auth_enabled = false;
+        config.dev.fake_backend = true;
+        assert!(config.validate().unwrap_err()[0].contains("dev.fake_backend"));
+
+        for host in ["127.0.0.1", "::1", "localhost"] {
+            config.server.host = host.to_string();
+            assert!(config.validate().is_ok(), "{} refused", host);
+        }
+
+        config.server.host = "0.0.0.0".to_string();
+        config.security.auth_enabled = true;
+        config.security.jwt_secret =
+            Some("a-very-long-secure-jwt-secret-that-is-at-least-32-characters".to_string());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_pool_mount_options_are_checked() {
+        let mut config = AppConfig::default();
+        config.security.jwt_secret =
+            Some("a-very-long-secure-jwt-secret-that-is-at-least-32-characters".to_string());
+        let mut pool = config.storage.pool_configs[0].clone();
+        pool.name = "shared".to_string();
+        pool.storage_type = "nfs".to_string();
+        pool.source = Some("nas:/exports/pool".to_string());
+        pool.options = [("vers", "4.1"), ("hard", "")]
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .into();
+        assert_eq!(pool.mount_options(), ["hard", "vers=4.1"]);
+        config.storage.pool_configs.push(pool);
+        assert!(config.validate().is_ok());
+
+        let pool = &mut config.storage.pool_configs[1];
+        pool.options.insert("suid".to_string(), String::new());
+        pool.source = Some("//nas/pool".to_string());
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("\"suid\" is not allowed"));
+        assert!(errors[1].contains("source"));
+    }
+
+    #[test]
+    fn test_api_keys_must_be_configured_hashed() {
+        let security: SecurityConfig = toml::from_str(&format!(
+            r#"
+            auth_enabled = true
+            cors_origins = []
+            [[api_keys]]
+            name = "monitoring"
+            key_hash = "{}"
+            permissions = ["SystemRead"]
+            "#,
+            crate::api_keys::hash_key("monitoring-key")
+        ))
+        .unwrap();
+        assert!(security.api_keys[0].enabled);
+        let mut config = AppConfig {
+            security,
+            ..Default::default()
+        };
+        config.security.jwt_secret =
+            Some("a-very-long-secure-jwt-secret-that-is-at-least-32-characters".to_string());
+        assert!(config.validate().is_ok());
+
+        config.security.api_keys[0].key_hash = "monitoring-key".to_string();
+        assert!(config.validate().unwrap_err()[0].contains("key_hash"));
+    }
+
+    #[test]
+    fn test_invalid_merge_leaves_config_untouched() {
+        let mut config = AppConfig::default();
+        config.security.jwt_secret =
+            Some("a-very-long-secure-jwt-secret-that-is-at-least-32-characters".to_string());
+        let before = serde_json::to_value(&config).unwrap();
+
+        let mut file_config = config.clone();
+        file_config.server.host = "127.0.0.1".to_string();
+        file_config.server.port = 9443;
+        file_config.logging.level = "loud".to_string();
+        let path = std::env::temp_dir().join(format!("orchestrator_{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, toml::to_string(&file_config).unwrap()).unwrap();
+        let path = path.to_str().unwrap();
+
+        match config.merge_with_file(path) {
+            Err(ConfigMergeError::Invalid(errors)) => {
+                assert_eq!(errors, ["Invalid log level: loud"]);
+            }
+            other => panic!("expected the merge to be refused, got {:?}", other),
+        }
+        assert_eq!(serde_json::to_value(&config).unwrap(), before);
+
+        file_config.logging.level = "debug".to_string();
+        std::fs::write(path, toml::to_string(&file_config).unwrap()).unwrap();
+        config.merge_with_file(path).unwrap();
+        assert_eq!(config.server.port, 9443);
+        assert_eq!(config.logging.level, "debug");
+
+        let _ = std::fs::remove_file(path);
+    }
 }
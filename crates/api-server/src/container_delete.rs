@@ -0,0 +1,252 @@
+/// Deleting containers that other resources still depend on
+///
+/// Snapshots, attached volumes and port forwards outlive a plain
+/// `lxc-destroy` or vanish with it unnoticed, so a delete is refused while
+/// any exist. A cascading delete cleans each of them up first, under the
+/// container's operation lock, and only destroys the container once every
+/// cleanup succeeded. Volumes are detached, never deleted. This node keeps
+/// no DNS records for containers, so there are none to remove.
+use std::net::Ipv4Addr;
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use container_manager::{ContainerError, ContainerManager, SnapshotManager};
+use network::{FirewallManager, Ipam, NatRule};
+
+use crate::audit::{AuditAction, AuditLogBuilder, AuditLogger, AuditResult};
+use crate::egress;
+
+/// What would be lost or orphaned by destroying a container
+#[derive(Debug, Default, Serialize)]
+pub struct Dependents {
+    pub snapshots: Vec<String>,
+    /// Host directories bind-mounted into the container
+    pub volumes: Vec<String>,
+    pub port_forwards: Vec<NatRule>,
+}
+
+impl Dependents {
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty() && self.volumes.is_empty() && self.port_forwards.is_empty()
+    }
+}
+
+/// Find the dependents of container `name`
+pub async fn dependents(name: &str, ipam: Option<&Ipam>) -> Result<Dependents, ContainerError> {
+    let snapshots = SnapshotManager::list(name, false)
+        .await?
+        .into_iter()
+        .map(|snapshot| snapshot.name)
+        .collect();
+    let volumes = ContainerManager::mounts(name)
+        .await?
+        .into_iter()
+        .map(|mount| mount.source)
+        .collect();
+
+    let addresses = addresses(name, ipam).await?;
+    let port_forwards = match FirewallManager::nat_rules().await {
+        Ok(rules) => rules
+            .into_iter()
+            .filter(|rule| forwards_to(rule, &addresses))
+            .collect(),
+        Err(e) => {
+            warn!("Could not list port forwards of container {}: {}", name, e);
+            Vec::new()
+        }
+    };
+
+    Ok(Dependents {
+        snapshots,
+        volumes,
+        port_forwards,
+    })
+}
+
+/// Addresses the container holds in IPAM or has configured statically
+async fn addresses(name: &str, ipam: Option<&Ipam>) -> Result<Vec<Ipv4Addr>, ContainerError> {
+    let mut addresses: Vec<Ipv4Addr> = ipam
+        .map(|ipam| {
+            ipam.allocations()
+                .into_iter()
+                .filter(|(_, owner)| owner == name)
+                .map(|(address, _)| address)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let (_, config) = ContainerManager::effective_config(name).await?;
+    for interface in &config.network_interfaces {
+        let address = interface
+            .ipv4
+            .as_deref()
+            .and_then(|cidr| cidr.split('/').next())
+            .and_then(|address| address.parse().ok());
+        if let Some(address) = address {
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+    }
+    Ok(addresses)
+}
+
+/// Whether `rule` is a DNAT port forward to one of `addresses`
+fn forwards_to(rule: &NatRule, addresses: &[Ipv4Addr]) -> bool {
+    rule.to_destination
+        .as_deref()
+        .and_then(|destination| destination.split(':').next())
+        .and_then(|address| address.parse::<Ipv4Addr>().ok())
+        .is_some_and(|address| addresses.contains(&address))
+}
+
+/// A cleanup that did not complete
+#[derive(Debug, Serialize)]
+pub struct CleanupStep {
+    pub step: String,
+    /// Why it failed; steps never attempted have none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Outcome of a cascading delete
+#[derive(Debug, Default, Serialize)]
+pub struct CascadeReport {
+    pub completed: Vec<String>,
+    pub remaining: Vec<CleanupStep>,
+}
+
+impl CascadeReport {
+    fn record(&mut self, step: String, result: Result<(), String>) {
+        match result {
+            Ok(()) => self.completed.push(step),
+            Err(error) => self.remaining.push(CleanupStep {
+                step,
+                error: Some(error),
+            }),
+        }
+    }
+}
+
+/// Step name of the container's own destruction
+pub const DESTROY_STEP: &str = "destroy";
+
+/// Remove every dependent of `name`, then destroy it
+///
+/// Every cleanup is attempted even after one fails, so the report lists
+/// exactly what is left; the container is destroyed only when none is.
+/// Each step is audited through builders from `audit`, which carry the
+/// caller and request.
+pub async fn cascade<F>(
+    name: &str,
+    ipam: Option<&Ipam>,
+    audit_logger: Option<&AuditLogger>,
+    audit: F,
+) -> Result<CascadeReport, ContainerError>
+where
+    F: Fn() -> AuditLogBuilder,
+{
+    let mut report = CascadeReport::default();
+    let log = |action: AuditAction, step: &str, result: &Result<(), String>| {
+        let Some(audit_logger) = audit_logger else {
+            return;
+        };
+        let result = match result {
+            Ok(()) => AuditResult::Success,
+            Err(e) => AuditResult::Failure(e.clone()),
+        };
+        if let Ok(entry) = audit()
+            .action(action)
+            .resource_type("container".to_string())
+            .resource_id(name.to_string())
+            .result(result)
+            .details(format!("Cascading delete: {}", step))
+            .build()
+        {
+            audit_logger.log_entry(entry);
+        }
+    };
+
+    let destroyed = ContainerManager::delete_after(name, || async {
+        // Looked up again under the lock, in case anything changed since
+        // the caller last checked
+        let dependents = match dependents(name, ipam).await {
+            Ok(dependents) => dependents,
+            Err(e) => {
+                report.record("dependents".to_string(), Err(e.to_string()));
+                return false;
+            }
+        };
+
+        for snapshot in &dependents.snapshots {
+            let step = format!("snapshot:{}", snapshot);
+            let result = SnapshotManager::delete(name, snapshot)
+                .await
+                .map_err(|e| e.to_string());
+            log(AuditAction::ContainerSnapshotDeleted, &step, &result);
+            report.record(step, result);
+        }
+        for volume in &dependents.volumes {
+            let step = format!("volume:{}", volume);
+            let result = ContainerManager::detach_mount(name, volume).map_err(|e| e.to_string());
+            log(AuditAction::ContainerUpdated, &step, &result);
+            report.record(step, result);
+        }
+        for rule in &dependents.port_forwards {
+            let step = format!("port-forward:{} {}", rule.chain, rule.rule);
+            let result = FirewallManager::delete_nat_rule(rule)
+                .await
+                .map_err(|e| e.to_string());
+            log(AuditAction::ContainerUpdated, &step, &result);
+            report.record(step, result);
+        }
+
+        egress::remove(name).await;
+        if let Some(ipam) = ipam {
+            let step = "addresses".to_string();
+            let result = ipam.release(name).map(|_| ()).map_err(|e| e.to_string());
+            log(AuditAction::ContainerUpdated, &step, &result);
+            report.record(step, result);
+        }
+
+        report.remaining.is_empty()
+    })
+    .await?;
+
+    if destroyed {
+        info!("Destroyed container {} after cascading cleanup", name);
+        log(AuditAction::ContainerDeleted, DESTROY_STEP, &Ok(()));
+        report.completed.push(DESTROY_STEP.to_string());
+    } else {
+        report.remaining.push(CleanupStep {
+            step: DESTROY_STEP.to_string(),
+            error: None,
+        });
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forwards_to() {
+        let rule = |destination: Option<&str>| NatRule {
+            chain: "PREROUTING".to_string(),
+            rule: "-p tcp --dport 8080 -j DNAT".to_string(),
+            in_interface: None,
+            out_interface: None,
+            source: None,
+            destination: None,
+            to_destination: destination.map(str::to_string),
+        };
+        let addresses = ["10.0.3.5".parse().unwrap()];
+
+        assert!(forwards_to(&rule(Some("10.0.3.5:80")), &addresses));
+        assert!(forwards_to(&rule(Some("10.0.3.5")), &addresses));
+        assert!(!forwards_to(&rule(Some("10.0.3.50:80")), &addresses));
+        assert!(!forwards_to(&rule(None), &addresses));
+    }
+}
@@ -0,0 +1,415 @@
+/// Health-check probes of running containers
+///
+/// LXC reporting a container as running says nothing about the application
+/// inside, so a container may carry a `health_check`: a command run with
+/// `lxc-attach`, or a TCP connect to the container's address. The prober
+/// wakes every [`SWEEP_INTERVAL`] and starts the probes that are due. All
+/// probes share one semaphore, so however many containers a host runs at
+/// most `health_checks.max_concurrent_probes` are in flight; the rest wait
+/// for a slot and run late rather than piling up.
+///
+/// A container turns unhealthy after `failure_threshold` consecutive failed
+/// probes and healthy again after one success. Containers without a check,
+/// not running, or not probed yet are unknown.
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+
+use container_manager::config::LxcConfig;
+use container_manager::{ContainerManager, LxcMonitor};
+use models::{ContainerStatus, HealthCheck, HealthProbe, HealthStatus};
+
+use crate::audit::{AuditAction, AuditLogger, AuditResult};
+use crate::config::HealthChecksConfig;
+use crate::tasks::TaskManager;
+
+/// How often the prober looks for probes that are due
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Probe results of one container
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ProbeState {
+    pub status: HealthStatus,
+    pub consecutive_failures: u32,
+    pub last_checked: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    #[serde(skip)]
+    in_flight: bool,
+}
+
+pub struct ContainerHealth {
+    states: Mutex<HashMap<String, ProbeState>>,
+    probes: Arc<Semaphore>,
+}
+
+impl ContainerHealth {
+    pub fn new(config: &HealthChecksConfig) -> Self {
+        Self {
+            states: Mutex::new(HashMap::new()),
+            probes: Arc::new(Semaphore::new(config.max_concurrent_probes.max(1))),
+        }
+    }
+
+    pub fn status(&self, name: &str) -> HealthStatus {
+        self.states
+            .lock()
+            .unwrap()
+            .get(name)
+            .map_or(HealthStatus::Unknown, |state| state.status)
+    }
+
+    pub fn state(&self, name: &str) -> Option<ProbeState> {
+        self.states.lock().unwrap().get(name).cloned()
+    }
+
+    /// Claim a probe of `name` when one is due at `now` and none is running
+    pub fn claim(&self, name: &str, check: &HealthCheck, now: DateTime<Utc>) -> bool {
+        let mut states = self.states.lock().unwrap();
+        let state = states.entry(name.to_string()).or_default();
+        let interval = chrono::Duration::seconds(check.interval_secs as i64);
+        let due = state.last_checked.is_none_or(|at| now - at >= interval);
+        if state.in_flight || !due {
+            return false;
+        }
+        state.in_flight = true;
+        true
+    }
+
+    /// Record the outcome of a claimed probe; returns the new status when
+    /// it changed
+    pub fn record(
+        &self,
+        name: &str,
+        check: &HealthCheck,
+        result: Result<(), String>,
+        at: DateTime<Utc>,
+    ) -> Option<HealthStatus> {
+        let mut states = self.states.lock().unwrap();
+        let state = states.entry(name.to_string()).or_default();
+        state.in_flight = false;
+        state.last_checked = Some(at);
+        let status = match result {
+            Ok(()) => {
+                state.consecutive_failures = 0;
+                state.last_error = None;
+                HealthStatus::Healthy
+            }
+            Err(e) => {
+                state.consecutive_failures += 1;
+                state.last_error = Some(e);
+                if state.consecutive_failures >= check.failure_threshold {
+                    HealthStatus::Unhealthy
+                } else {
+                    state.status
+                }
+            }
+        };
+        let changed = status != state.status;
+        state.status = status;
+        changed.then_some(status)
+    }
+
+    /// Forget containers that are no longer probed, i.e. stopped, deleted or
+    /// without a check
+    pub fn retain(&self, probed: &HashSet<String>) {
+        self.states
+            .lock()
+            .unwrap()
+            .retain(|name, state| state.in_flight || probed.contains(name));
+    }
+
+    /// Set the health gauge of every probed container; unknown ones are
+    /// left out
+    pub fn export(&self, metrics: &crate::observability::MetricsCollector) {
+        metrics.container_healthy.reset();
+        for (name, state) in self.states.lock().unwrap().iter() {
+            let healthy = match state.status {
+                HealthStatus::Healthy => 1,
+                HealthStatus::Unhealthy => 0,
+                HealthStatus::Unknown => continue,
+            };
+            metrics
+                .container_healthy
+                .with_label_values(&[name])
+                .set(healthy);
+        }
+    }
+}
+
+/// Probe every running container with a health check, sweeping every
+/// [`SWEEP_INTERVAL`]
+pub fn register(
+    tasks: &TaskManager,
+    health: Arc<ContainerHealth>,
+    monitor: Arc<LxcMonitor>,
+    audit_logger: Arc<AuditLogger>,
+) {
+    info!("Container health checks enabled");
+
+    tasks.register_interval("container-health", SWEEP_INTERVAL, move || {
+        sweep(health.clone(), monitor.clone(), audit_logger.clone())
+    });
+}
+
+/// Start the probes that are due
+async fn sweep(
+    health: Arc<ContainerHealth>,
+    monitor: Arc<LxcMonitor>,
+    audit_logger: Arc<AuditLogger>,
+) -> Result<(), String> {
+    // The monitor already knows which containers run, unless it is down
+    let running: Vec<String> = if monitor.health().is_current() {
+        monitor
+            .states()
+            .into_iter()
+            .filter(|(_, status)| *status == ContainerStatus::Running)
+            .map(|(name, _)| name)
+            .collect()
+    } else {
+        running_containers()
+            .await
+            .map_err(|e| format!("could not list containers: {}", e))?
+    };
+
+    let mut probed = HashSet::new();
+    let now = Utc::now();
+    for name in running {
+        let Some(check) = LxcConfig::read(&name)
+            .ok()
+            .and_then(|raw| LxcConfig::parse(&name, &raw).health_check)
+        else {
+            continue;
+        };
+        probed.insert(name.clone());
+        if !health.claim(&name, &check, now) {
+            continue;
+        }
+
+        let (health, audit_logger) = (health.clone(), audit_logger.clone());
+        tokio::spawn(async move {
+            let permit = health.probes.clone().acquire_owned().await.unwrap();
+            let result = probe(&name, &check, permit).await;
+            if let Some(status) = health.record(&name, &check, result, Utc::now()) {
+                let state = health.state(&name).unwrap_or_default();
+                changed(&name, &check, status, &state, &audit_logger).await;
+            }
+        });
+    }
+    health.retain(&probed);
+    Ok(())
+}
+
+async fn running_containers() -> Result<Vec<String>, String> {
+    let mut running = Vec::new();
+    for name in ContainerManager::list().await.map_err(|e| e.to_string())? {
+        if let Ok(ContainerStatus::Running) = ContainerManager::status(&name).await {
+            running.push(name);
+        }
+    }
+    Ok(running)
+}
+
+/// Probe `name` once, giving up after the check's timeout
+///
+/// `permit` is held until the probe is over; a command that outlives the
+/// timeout keeps its slot until it exits, so hung probes cannot pile up.
+async fn probe(
+    name: &str,
+    check: &HealthCheck,
+    permit: OwnedSemaphorePermit,
+) -> Result<(), String> {
+    let timeout = Duration::from_secs(check.timeout_secs);
+    let timed_out = || format!("timed out after {}s", check.timeout_secs);
+    match &check.probe {
+        HealthProbe::Exec { command } => {
+            let (name, command) = (name.to_string(), command.clone());
+            // Spawned so the permit stays with the command, not the timeout
+            let run = tokio::spawn(async move {
+                let _permit = permit;
+                ContainerManager::run_command(&name, &command).await
+            });
+            match tokio::time::timeout(timeout, run).await {
+                Ok(Ok(result)) => result.map(|_| ()).map_err(|e| e.to_string()),
+                Ok(Err(e)) => Err(format!("probe task failed: {}", e)),
+                Err(_) => Err(timed_out()),
+            }
+        }
+        HealthProbe::Tcp { port } => {
+            let _permit = permit;
+            let name = name.to_string();
+            let addresses = ContainerManager::addresses(&name)
+                .await
+                .map_err(|e| e.to_string())?;
+            let address = addresses
+                .first()
+                .ok_or_else(|| "container has no address".to_string())?;
+            match tokio::time::timeout(timeout, tokio::net::TcpStream::connect((*address, *port)))
+                .await
+            {
+                Ok(Ok(_)) => Ok(()),
+                Ok(Err(e)) => Err(format!("{}:{}: {}", address, port, e)),
+                Err(_) => Err(timed_out()),
+            }
+        }
+    }
+}
+
+/// Log and audit a health change, restarting the container when it turned
+/// unhealthy and its check asks for that
+async fn changed(
+    name: &str,
+    check: &HealthCheck,
+    status: HealthStatus,
+    state: &ProbeState,
+    audit_logger: &AuditLogger,
+) {
+    let details = match status {
+        HealthStatus::Unhealthy => {
+            warn!(
+                "Container {} is unhealthy after {} failed probes: {}",
+                name,
+                state.consecutive_failures,
+                state.last_error.as_deref().unwrap_or_default()
+            );
+            format!(
+                "Unhealthy after {} failed probes: {}",
+                state.consecutive_failures,
+                state.last_error.as_deref().unwrap_or_default()
+            )
+        }
+        _ => {
+            info!("Container {} is {:?}", name, status);
+            format!("{:?}", status)
+        }
+    };
+    audit(
+        name,
+        AuditAction::ContainerHealthChanged,
+        AuditResult::Success,
+        details,
+        audit_logger,
+    );
+
+    if status != HealthStatus::Unhealthy || !check.restart_on_unhealthy {
+        return;
+    }
+    warn!("Restarting unhealthy container {}", name);
+    let container = name.to_string();
+    let result =
+        ContainerManager::restart(&container, Some("failed its health check".to_string())).await;
+    let result = match result {
+        Ok(()) => AuditResult::Success,
+        Err(e) => {
+            warn!("Failed to restart unhealthy container {}: {}", name, e);
+            AuditResult::Failure(e.to_string())
+        }
+    };
+    audit(
+        name,
+        AuditAction::ContainerStarted,
+        result,
+        "Restarted after failing its health check".to_string(),
+        audit_logger,
+    );
+}
+
+fn audit(
+    name: &str,
+    action: AuditAction,
+    result: AuditResult,
+    details: String,
+    audit_logger: &AuditLogger,
+) {
+    if let Ok(log) = AuditLogger::builder()
+        .user("health-checker".to_string())
+        .action(action)
+        .resource_type("container".to_string())
+        .resource_id(name.to_string())
+        .result(result)
+        .details(details)
+        .build()
+    {
+        audit_logger.log_entry(log);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(failure_threshold: u32) -> HealthCheck {
+        HealthCheck {
+            probe: HealthProbe::Tcp { port: 80 },
+            interval_secs: 10,
+            timeout_secs: 2,
+            failure_threshold,
+            restart_on_unhealthy: false,
+        }
+    }
+
+    fn health(max_concurrent_probes: usize) -> ContainerHealth {
+        ContainerHealth::new(&HealthChecksConfig {
+            enabled: true,
+            max_concurrent_probes,
+        })
+    }
+
+    #[test]
+    fn test_unhealthy_only_after_threshold() {
+        let health = health(2);
+        let check = check(3);
+        let now = Utc::now();
+        assert_eq!(health.status("web"), HealthStatus::Unknown);
+
+        assert_eq!(
+            health.record("web", &check, Ok(()), now),
+            Some(HealthStatus::Healthy)
+        );
+        for _ in 0..2 {
+            assert_eq!(
+                health.record("web", &check, Err("refused".to_string()), now),
+                None
+            );
+        }
+        assert_eq!(health.status("web"), HealthStatus::Healthy);
+        assert_eq!(
+            health.record("web", &check, Err("refused".to_string()), now),
+            Some(HealthStatus::Unhealthy)
+        );
+        let state = health.state("web").unwrap();
+        assert_eq!(state.consecutive_failures, 3);
+        assert_eq!(state.last_error.as_deref(), Some("refused"));
+
+        // One success is enough to recover
+        assert_eq!(
+            health.record("web", &check, Ok(()), now),
+            Some(HealthStatus::Healthy)
+        );
+    }
+
+    #[test]
+    fn test_claim_respects_interval_and_in_flight_probes() {
+        let health = health(2);
+        let check = check(1);
+        let now = Utc::now();
+
+        assert!(health.claim("web", &check, now));
+        // Still running
+        assert!(!health.claim("web", &check, now + chrono::Duration::seconds(60)));
+
+        health.record("web", &check, Ok(()), now);
+        assert!(!health.claim("web", &check, now + chrono::Duration::seconds(5)));
+        assert!(health.claim("web", &check, now + chrono::Duration::seconds(10)));
+
+        // Stopped containers are forgotten, except while a probe runs
+        health.retain(&HashSet::new());
+        assert!(health.state("web").is_some());
+        health.record("web", &check, Ok(()), now);
+        health.retain(&HashSet::new());
+        assert_eq!(health.status("web"), HealthStatus::Unknown);
+    }
+}
@@ -0,0 +1,174 @@
+/// Container egress policies on the host firewall
+///
+/// A policy is enforced on the container's host-side veth interfaces, which
+/// only exist while it runs, so it is applied after every start and removed
+/// before stop and delete. The interfaces each container's chains were
+/// applied to are recorded, and removal works from that record: by the time
+/// a crashed or stopped container is cleaned up its links are gone, and
+/// `lxc-info` no longer names them.
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use container_manager::{ContainerError, ContainerManager};
+use network::{FirewallManager, NetworkError};
+
+#[derive(Debug, Error)]
+pub enum EgressError {
+    #[error("Container error: {0}")]
+    Container(#[from] ContainerError),
+
+    #[error("Failed to apply egress policy: {0}")]
+    Firewall(#[from] NetworkError),
+}
+
+/// Host interfaces carrying an egress chain, by container
+struct Record {
+    interfaces: BTreeMap<String, BTreeSet<String>>,
+    path: Option<PathBuf>,
+}
+
+static RECORD: Mutex<Record> = Mutex::new(Record {
+    interfaces: BTreeMap::new(),
+    path: None,
+});
+
+impl Record {
+    fn save(&self) {
+        let Some(ref path) = self.path else {
+            return;
+        };
+        let result = serde_json::to_vec_pretty(&self.interfaces)
+            .map_err(std::io::Error::other)
+            .and_then(|content| {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                let tmp = path.with_extension("tmp");
+                std::fs::write(&tmp, content)?;
+                std::fs::rename(&tmp, path)
+            });
+        if let Err(e) = result {
+            warn!("Failed to save egress chains to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Keep the record in `path`, loading what an earlier run left there, so
+/// chains of containers that stopped while the server was down are still
+/// removed
+pub fn configure(path: &Path) -> std::io::Result<()> {
+    let interfaces = match std::fs::read(path) {
+        Ok(content) => serde_json::from_slice(&content).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid egress chain record {}: {}", path.display(), e),
+            )
+        })?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => return Err(e),
+    };
+    let mut record = RECORD.lock().unwrap();
+    record.interfaces = interfaces;
+    record.path = Some(path.to_path_buf());
+    Ok(())
+}
+
+/// Host interfaces the container's egress chains are recorded on
+pub fn recorded(container: &str) -> Vec<String> {
+    RECORD
+        .lock()
+        .unwrap()
+        .interfaces
+        .get(container)
+        .map(|interfaces| interfaces.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Record that `interface` of `container` now has an egress chain
+pub fn record(container: &str, interface: &str) {
+    let mut record = RECORD.lock().unwrap();
+    let added = record
+        .interfaces
+        .entry(container.to_string())
+        .or_default()
+        .insert(interface.to_string());
+    if added {
+        record.save();
+    }
+}
+
+/// Drop `interface` of `container` from the record once its chain is gone
+pub fn forget(container: &str, interface: &str) {
+    let mut record = RECORD.lock().unwrap();
+    let Some(interfaces) = record.interfaces.get_mut(container) else {
+        return;
+    };
+    if !interfaces.remove(interface) {
+        return;
+    }
+    if interfaces.is_empty() {
+        record.interfaces.remove(container);
+    }
+    record.save();
+}
+
+/// Apply the container's egress policy, if it has one, to all of its links
+///
+/// Chains recorded for links of an earlier run, which the container no
+/// longer has, are removed first.
+pub async fn apply(container: &str) -> Result<(), EgressError> {
+    let (_, config) = ContainerManager::effective_config(container).await?;
+    let Some(policy) = config.egress_policy else {
+        return Ok(());
+    };
+
+    let interfaces = ContainerManager::host_interfaces(container).await?;
+    for stale in recorded(container)
+        .into_iter()
+        .filter(|interface| !interfaces.contains(interface))
+    {
+        remove_chain(container, &stale).await;
+    }
+    for interface in &interfaces {
+        FirewallManager::apply_egress(interface, &policy).await?;
+        record(container, interface);
+    }
+    info!(
+        "Applied egress policy to container {} on {:?}",
+        container, interfaces
+    );
+    Ok(())
+}
+
+/// Remove the container's recorded egress chains; failures are logged, not
+/// returned, since they must never block a stop or delete
+///
+/// A chain that could not be removed stays recorded for the next attempt.
+pub async fn remove(container: &str) {
+    for interface in recorded(container) {
+        remove_chain(container, &interface).await;
+    }
+}
+
+async fn remove_chain(container: &str, interface: &str) {
+    // Host interface names are reused; a chain another container now has
+    // recorded belongs to that container
+    let reused = RECORD
+        .lock()
+        .unwrap()
+        .interfaces
+        .iter()
+        .any(|(other, interfaces)| other != container && interfaces.contains(interface));
+    if reused {
+        forget(container, interface);
+        return;
+    }
+
+    match FirewallManager::remove_egress(interface).await {
+        Ok(()) => forget(container, interface),
+        Err(e) => warn!("Failed to remove egress policy from {}: {}", interface, e),
+    }
+}
@@ -1,14 +1,60 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
 use serde::Deserialize;
-use tracing::{error, info};
+use std::sync::{Arc, RwLock};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
-use ::network::{BridgeManager, NetworkError};
-use ::storage::{LocalStorageManager, SharedStorageManager, StorageError};
-use container_manager::{ContainerError, ContainerManager, SnapshotManager};
+use ::cluster::{
+    ClusterSnapshot, ClusterState, MembershipManager, PeerHealth, PlacementRequest, Scheduler,
+};
+use ::network::{BridgeManager, FirewallManager, InterfaceManager, Ipam, NetworkError};
+use ::storage::StorageError;
+use container_manager::config::{LxcConfig, REDACTED};
+use container_manager::{
+    ContainerError, ContainerManager, ImageCache, IntegrityStatus, LxcMonitor, SnapshotManager,
+};
 use models::*;
 
-pub async fn list_containers() -> impl Responder {
+use crate::address_conflicts::{self, ConflictCheckQuery};
+use crate::api_keys::{ApiKeyError, ApiKeyStore, ApiKeySummary};
+use crate::audit::{AuditAction, AuditLogBuilder, AuditLogger, AuditResult};
+use crate::audit_diff::{self, Diff};
+use crate::auth::{AuthError, AuthenticatedUser, Claims};
+use crate::auto_join::{AutoJoin, JoinStatus};
+use crate::client_ip::client_ip;
+use crate::cluster_leave::{self, ClusterMembership, LeaveRequest, LEAVE_JOB};
+use crate::cluster_view;
+use crate::config::AppConfig;
+use crate::container_delete;
+use crate::container_health::ContainerHealth;
+use crate::egress;
+use crate::hotplug::{self, HotplugError};
+use crate::inventory::{self, ReportFormat};
+use crate::jobs::{self, JobManager, JobStatus};
+use crate::join_tokens::{JoinCredential, JoinTokenManager, MAX_JOIN_TOKEN_TTL_SECS};
+use crate::network_overview;
+use crate::observability::MetricsCollector;
+use crate::pool_usage::{PoolStateError, PoolUsageMonitor};
+use crate::privileges;
+use crate::rate_limit::RateLimiter;
+use crate::rbac::{Permission, UserKind};
+use crate::secrets::{self, SecretError, SecretStore};
+use crate::service_tokens::{
+    ServiceTokenError, ServiceTokenStore, DEFAULT_SERVICE_TOKEN_TTL_DAYS,
+    MAX_SERVICE_TOKEN_TTL_DAYS,
+};
+use crate::setup::{AdminSetup, SetupError};
+use crate::snapshot_batch::{self, BatchSnapshotRequest};
+use crate::start_checks::{self, StartCheckError};
+use crate::system::{
+    self, ShutdownRequest, StopAllRequest, SHUTDOWN_JOB, START_ALL_JOB, SYSTEM_JOBS,
+};
+use crate::tasks::{TaskError, TaskManager};
+use crate::totp::{self, TotpEnrollment};
+use crate::usage_history::{self, UsageHistory};
+use crate::wait::{self, WaitQuery};
+
+pub async fn list_containers(health: Option<web::Data<Arc<ContainerHealth>>>) -> impl Responder {
     info!("Listing containers");
 
     match ContainerManager::list().await {
@@ -19,20 +65,22 @@ pub async fn list_containers() -> impl Responder {
                 .map(|name| {
                     // Simplified - in production, get from database
                     Container {
-                        id: Uuid::new_v4(),
+                        id: ContainerManager::container_id(&name),
                         name: name.clone(),
                         status: ContainerStatus::Stopped,
                         template: "unknown".to_string(),
                         node_id: None,
                         created_at: chrono::Utc::now(),
                         updated_at: chrono::Utc::now(),
+                        uptime_seconds: None,
+                        template_options: Default::default(),
+                        arch: None,
+                        health: health
+                            .as_ref()
+                            .map_or_else(Default::default, |health| health.status(&name)),
                         config: ContainerConfig {
-                            cpu_limit: None,
-                            memory_limit: None,
-                            disk_limit: None,
-                            network_interfaces: vec![],
                             rootfs_path: format!("/var/lib/lxc/{}/rootfs", name),
-                            environment: vec![],
+                            ..Default::default()
                         },
                     }
                 })
@@ -49,16 +97,133 @@ pub async fn list_containers() -> impl Responder {
     }
 }
 
-pub async fn create_container(req: web::Json<CreateContainerRequest>) -> impl Responder {
+/// 400 naming every invalid field of a request
+fn validation_error_response(errors: ValidationErrors) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "error": errors.to_string(),
+        "fields": errors.errors
+    }))
+}
+
+/// Create a container; limits above the configured defaults need
+/// `SystemAdmin`
+///
+/// Static addresses already in use elsewhere are refused with 409 unless
+/// `?skip_conflict_check=true`.
+pub async fn create_container(
+    req: web::Json<CreateContainerRequest>,
+    query: web::Query<ConflictCheckQuery>,
+    user: Option<AuthenticatedUser>,
+    image_cache: Option<web::Data<Arc<ImageCache>>>,
+    metrics: Option<web::Data<Arc<MetricsCollector>>>,
+    ipam: Option<web::Data<Arc<Ipam>>>,
+    config: Option<web::Data<AppConfig>>,
+) -> impl Responder {
     info!("Creating container: {}", req.name);
 
-    match ContainerManager::create(req.into_inner()).await {
+    if let Err(errors) = req.validate() {
+        return validation_error_response(errors);
+    }
+    let above_defaults = ContainerManager::default_limits().exceeded_by(&req.config);
+    if !above_defaults.is_empty() {
+        let allowed = match &user {
+            Some(user) => user.require(Permission::SystemAdmin),
+            None => Err(AuthError::MissingCredentials),
+        };
+        if let Err(e) = allowed {
+            warn!(
+                "Refusing {} above the default for container {}",
+                above_defaults.join(", "),
+                req.name
+            );
+            return e.error_response();
+        }
+    }
+    if let Err(e) = LxcConfig::validate(&req.config) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+    }
+    if let Some(ref policy) = req.config.egress_policy {
+        if let Err(e) = FirewallManager::validate_egress(policy) {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e.to_string()
+            }));
+        }
+    }
+
+    if let Err(response) = check_address_conflicts(
+        &req.name,
+        &req.config.network_interfaces,
+        &query,
+        ipam.as_ref().map(|ipam| ipam.as_ref().as_ref()),
+        config.as_ref().map(|config| config.get_ref()),
+    )
+    .await
+    {
+        return response;
+    }
+
+    let mut request = req.into_inner();
+    let allocated = match allocate_addresses(
+        &mut request,
+        ipam.as_ref().map(|ipam| ipam.as_ref().as_ref()),
+    ) {
+        Ok(allocated) => allocated,
+        Err(NetworkError::InvalidRequest(message)) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": message }))
+        }
+        Err(e @ NetworkError::AddressPoolExhausted(_)) => {
+            return HttpResponse::Conflict().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        Err(e) => {
+            error!("Address allocation failed: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }));
+        }
+    };
+    let image = request.image.clone();
+    let cache = image_cache.as_ref().map(|cache| cache.get_ref().clone());
+    if let (Some(ref image), Some(ref cache), Some(ref metrics)) = (&image, &cache, &metrics) {
+        metrics.record_image_cache_lookup(cache.is_cached(image));
+    }
+
+    let result = ContainerManager::create_with_image_cache(request, cache.as_deref()).await;
+    if let (Err(_), Some(ipam)) = (&result, &ipam) {
+        if let Err(e) = ipam.free(&allocated) {
+            warn!("Failed to free addresses {:?}: {}", allocated, e);
+        }
+    }
+    if let (Ok(_), Some(image), Some(cache)) = (&result, &image, &cache) {
+        if let Err(e) = cache.record_use(image) {
+            warn!("Failed to record image cache use: {}", e);
+        }
+        if let Err(e) = cache.evict() {
+            warn!("Image cache eviction failed: {}", e);
+        }
+    }
+
+    match result {
         Ok(container) => HttpResponse::Created().json(ContainerResponse { container }),
         Err(ContainerError::AlreadyExists(name)) => {
             HttpResponse::Conflict().json(serde_json::json!({
                 "error": format!("Container already exists: {}", name)
             }))
         }
+        Err(ContainerError::ImageNotFound(id)) => {
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Image not available: {}", id)
+            }))
+        }
+        Err(e @ ContainerError::DownloadFailed { .. }) => {
+            error!("Failed to create container: {}", e);
+            HttpResponse::BadGateway().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        Err(ContainerError::Dependency(e)) => {
+            HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        Err(e @ ContainerError::QueueTimeout { .. }) => {
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": e.to_string() }))
+        }
         Err(e) => {
             error!("Failed to create container: {}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
@@ -68,32 +233,411 @@ pub async fn create_container(req: web::Json<CreateContainerRequest>) -> impl Re
     }
 }
 
-pub async fn get_container(path: web::Path<String>) -> impl Responder {
+/// Refuse static addresses of `interfaces` that something else on the node
+/// already holds, unless the caller asked to skip the check
+async fn check_address_conflicts(
+    container: &str,
+    interfaces: &[ContainerNetworkInterface],
+    query: &ConflictCheckQuery,
+    ipam: Option<&Ipam>,
+    config: Option<&AppConfig>,
+) -> Result<(), HttpResponse> {
+    if query.skip_conflict_check {
+        return Ok(());
+    }
+    let probe = config.is_some_and(|config| config.network.probe_address_conflicts);
+    address_conflicts::check(container, interfaces, ipam, probe)
+        .await
+        .map_err(|conflict| {
+            HttpResponse::Conflict().json(serde_json::json!({
+                "error": conflict.to_string(),
+                "conflict": conflict
+            }))
+        })
+}
+
+/// Replace `auto` IPv4 addresses of `request` with free addresses of the
+/// node's range, with the range's gateway unless one was given, returning
+/// the addresses allocated
+fn allocate_addresses(
+    request: &mut CreateContainerRequest,
+    ipam: Option<&Ipam>,
+) -> Result<Vec<std::net::Ipv4Addr>, NetworkError> {
+    let mut interfaces = request
+        .config
+        .network_interfaces
+        .iter_mut()
+        .filter(|interface| interface.ipv4.as_deref() == Some(AUTO_ADDRESS))
+        .peekable();
+    if interfaces.peek().is_none() {
+        return Ok(vec![]);
+    }
+    let Some(ipam) = ipam else {
+        return Err(NetworkError::InvalidRequest(
+            "Automatic addressing is not configured (network.ip_range)".to_string(),
+        ));
+    };
+
+    let mut allocated = Vec::new();
+    for interface in interfaces {
+        match ipam.allocate(&request.name) {
+            Ok(address) => {
+                interface.ipv4 = Some(format!("{}/{}", address, ipam.prefix()));
+                interface
+                    .gateway_v4
+                    .get_or_insert_with(|| ipam.gateway().to_string());
+                allocated.push(address);
+            }
+            Err(e) => {
+                if let Err(e) = ipam.free(&allocated) {
+                    warn!("Failed to free addresses {:?}: {}", allocated, e);
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(allocated)
+}
+
+/// Number of recent events included in an expanded container response
+const EXPANDED_EVENT_LIMIT: usize = 10;
+
+#[derive(Debug, Deserialize)]
+pub struct ContainerDetailQuery {
+    /// Comma-separated list of sections: snapshots, volumes, stats, events, disk
+    pub expand: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ContainerExpand {
+    snapshots: bool,
+    volumes: bool,
+    stats: bool,
+    events: bool,
+    disk: bool,
+}
+
+impl ContainerExpand {
+    fn parse(expand: &str) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        for section in expand.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match section {
+                "snapshots" => parsed.snapshots = true,
+                "volumes" => parsed.volumes = true,
+                "stats" => parsed.stats = true,
+                "events" => parsed.events = true,
+                "disk" => parsed.disk = true,
+                other => return Err(format!("Unknown expand section: {}", other)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Disk used by the container's rootfs and the directories bind-mounted into
+/// it, from the usage cache; `stale` is set while any part is being measured
+async fn container_disk_usage(name: &str) -> Result<serde_json::Value, String> {
+    let mounts = ContainerManager::mounts(name)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut total_bytes = 0;
+    let mut stale = false;
+    let mut measure = |path: &std::path::Path| {
+        let usage = ::storage::disk_usage(path);
+        total_bytes += usage.used_bytes.unwrap_or(0);
+        stale |= usage.stale;
+        serde_json::json!({
+            "path": path,
+            "used_bytes": usage.used_bytes,
+            "measured_at": usage.measured_at,
+            "stale": usage.stale,
+        })
+    };
+
+    let rootfs = measure(&LxcConfig::lxc_root().join(name).join("rootfs"));
+    let mut volumes = Vec::new();
+    for mount in mounts {
+        let source = std::path::Path::new(&mount.source);
+        let bind = mount
+            .options
+            .split(',')
+            .any(|option| option == "bind" || option == "rbind");
+        if bind && source.is_dir() {
+            let mut volume = measure(source);
+            volume["target"] = mount.target.into();
+            volumes.push(volume);
+        }
+    }
+
+    Ok(serde_json::json!({
+        "rootfs": rootfs,
+        "volumes": volumes,
+        "total_bytes": total_bytes,
+        "stale": stale,
+    }))
+}
+
+/// Get a container, with `?expand=` sections fetched alongside
+///
+/// `events` are audit entries, so they follow the same rules as
+/// [`get_audit_logs`]: admins see every entry on the container, other users
+/// only their own, and anonymous callers none.
+pub async fn get_container(
+    path: web::Path<String>,
+    query: web::Query<ContainerDetailQuery>,
+    user: Option<AuthenticatedUser>,
+    config: Option<web::Data<AppConfig>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+    health: Option<web::Data<Arc<ContainerHealth>>>,
+) -> impl Responder {
     let name = path.into_inner();
     info!("Getting container: {}", name);
 
-    match ContainerManager::get(&name).await {
-        Ok(container) => HttpResponse::Ok().json(ContainerResponse { container }),
-        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": format!("Container not found: {}", name)
-        })),
+    let expand = match query.expand.as_deref().map(ContainerExpand::parse) {
+        None => None,
+        Some(Ok(expand)) => Some(expand),
+        Some(Err(e)) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+        }
+    };
+
+    let mut container = match ContainerManager::get(&name).await {
+        Ok(container) => container,
+        Err(ContainerError::NotFound(name)) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("Container not found: {}", name)
+            }))
+        }
         Err(e) => {
             error!("Failed to get container: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
+            return HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": e.to_string()
-            }))
+            }));
+        }
+    };
+
+    if let Some(health) = health {
+        container.health = health.status(&name);
+    }
+
+    let Some(expand) = expand else {
+        return HttpResponse::Ok().json(ContainerResponse { container });
+    };
+
+    // Each section is fetched independently; a failure yields null plus an
+    // entry in `errors` rather than failing the whole request.
+    let (snapshots, volumes, stats, events, disk) = tokio::join!(
+        async {
+            if !expand.snapshots {
+                return None;
+            }
+            Some(
+                SnapshotManager::list(&name, false)
+                    .await
+                    .map(|snapshots| {
+                        snapshots
+                            .into_iter()
+                            .map(|s| {
+                                serde_json::json!({
+                                    "name": s.name,
+                                    "created_at": s.created_at,
+                                    "size_bytes": s.size_bytes,
+                                    "size_state": s.size_state,
+                                })
+                            })
+                            .collect::<Vec<_>>()
+                            .into()
+                    })
+                    .map_err(|e| e.to_string()),
+            )
+        },
+        async {
+            if !expand.volumes {
+                return None;
+            }
+            Some(
+                ContainerManager::mounts(&name)
+                    .await
+                    .map(|mounts| serde_json::json!(mounts))
+                    .map_err(|e| e.to_string()),
+            )
+        },
+        async {
+            if !expand.stats {
+                return None;
+            }
+            Some(
+                ContainerManager::usage(&name)
+                    .await
+                    .map(|usage| serde_json::json!(usage))
+                    .map_err(|e| e.to_string()),
+            )
+        },
+        async {
+            if !expand.events {
+                return None;
+            }
+            let Some(logger) = &audit_logger else {
+                return Some(Err("Audit log is not available".to_string()));
+            };
+            let Some(user) = &user else {
+                return Some(Err(AuthError::MissingCredentials.to_string()));
+            };
+            let mut logs = logger.get_resource_logs("container", &name, EXPANDED_EVENT_LIMIT);
+            if user.require(Permission::SystemAdmin).is_err() {
+                if !config.as_ref().is_some_and(|c| c.audit.self_service) {
+                    return Some(Err(
+                        AuthError::Forbidden(Permission::SystemAdmin).to_string()
+                    ));
+                }
+                logs.retain(|log| log.concerns(&user.username));
+            }
+            Some(Ok(serde_json::json!(logs)))
+        },
+        async {
+            if !expand.disk {
+                return None;
+            }
+            Some(container_disk_usage(&name).await)
+        },
+    );
+
+    let mut body = serde_json::json!({ "container": container });
+    let mut errors = serde_json::Map::new();
+    for (section, result) in [
+        ("snapshots", snapshots),
+        ("volumes", volumes),
+        ("stats", stats),
+        ("events", events),
+        ("disk", disk),
+    ] {
+        match result {
+            None => {}
+            Some(Ok(value)) => body[section] = value,
+            Some(Err(e)) => {
+                warn!("Failed to fetch {} for container {}: {}", section, name, e);
+                body[section] = serde_json::Value::Null;
+                errors.insert(section.to_string(), e.into());
+            }
         }
     }
+    if !errors.is_empty() {
+        body["errors"] = errors.into();
+    }
+
+    HttpResponse::Ok().json(body)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartContainerQuery {
+    /// Recreate missing bridges that were created through the API
+    #[serde(default)]
+    pub fix: bool,
+}
+
+fn start_check_error_response(e: StartCheckError) -> HttpResponse {
+    let prerequisite = match &e {
+        StartCheckError::Container(ContainerError::NotFound(name)) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("Container not found: {}", name)
+            }))
+        }
+        StartCheckError::Network(NetworkError::PermissionDenied(reason)) => {
+            return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": reason
+            }))
+        }
+        StartCheckError::MissingBridge { bridge, .. } => {
+            serde_json::json!({ "kind": "bridge", "name": bridge })
+        }
+        StartCheckError::MissingMountSource { path, .. } => {
+            serde_json::json!({ "kind": "mount_source", "name": path })
+        }
+        StartCheckError::MissingWritablePath { path } => {
+            serde_json::json!({ "kind": "writable_path", "name": path })
+        }
+        StartCheckError::AddressInUse { address, container } => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": e.to_string(),
+                "prerequisite": { "kind": "address", "name": address, "container": container }
+            }))
+        }
+        _ => {
+            error!("Failed to check start prerequisites: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }));
+        }
+    };
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "error": e.to_string(),
+        "prerequisite": prerequisite
+    }))
 }
 
-pub async fn start_container(path: web::Path<String>) -> impl Responder {
+pub async fn start_container(
+    path: web::Path<String>,
+    query: web::Query<StartContainerQuery>,
+    secret_store: Option<web::Data<Arc<SecretStore>>>,
+    config: Option<web::Data<AppConfig>>,
+) -> impl Responder {
     let name = path.into_inner();
     info!("Starting container: {}", name);
 
+    let network = config.as_ref().map(|config| &config.network);
+    if !network.is_some_and(|network| network.skip_start_checks) {
+        let bridge_state = query.fix.then(|| {
+            config
+                .as_ref()
+                .map_or_else(|| AppConfig::default().paths(), |config| config.paths())
+                .bridge_state
+        });
+        match start_checks::check(&name, bridge_state.as_deref()).await {
+            Ok(created) if !created.is_empty() => {
+                info!("Recreated bridges {:?} for container {}", created, name)
+            }
+            Ok(_) => {}
+            Err(e) => return start_check_error_response(e),
+        }
+    }
+
+    let store = secret_store.as_ref().map(|store| store.as_ref().as_ref());
+    if let Err(e) = secrets::inject(store, &name).await {
+        return secret_error_response(e);
+    }
+
+    let default_dns = network
+        .map(|network| network.dns_servers.clone())
+        .unwrap_or_default();
+    if let Err(e) = ContainerManager::write_resolv_conf(&name, &default_dns).await {
+        return match e {
+            ContainerError::NotFound(name) => HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("Container not found: {}", name)
+            })),
+            e => {
+                error!("Failed to write resolv.conf for {}: {}", name, e);
+                HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": e.to_string()
+                }))
+            }
+        };
+    }
+
     match ContainerManager::start(&name).await {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": format!("Container {} started", name)
-        })),
+        Ok(_) => {
+            // Never leave a container running with unrestricted egress it asked to restrict
+            if let Err(e) = egress::apply(&name).await {
+                error!("Failed to apply egress policy to {}: {}", name, e);
+                let _ = ContainerManager::stop(&name).await;
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": format!("{}; container {} was stopped", e, name)
+                }));
+            }
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": format!("Container {} started", name)
+            }))
+        }
         Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
             "error": format!("Container not found: {}", name)
         })),
@@ -110,6 +654,7 @@ pub async fn stop_container(path: web::Path<String>) -> impl Responder {
     let name = path.into_inner();
     info!("Stopping container: {}", name);
 
+    egress::remove(&name).await;
     match ContainerManager::stop(&name).await {
         Ok(_) => HttpResponse::Ok().json(serde_json::json!({
             "message": format!("Container {} stopped", name)
@@ -126,19 +671,19 @@ pub async fn stop_container(path: web::Path<String>) -> impl Responder {
     }
 }
 
-pub async fn delete_container(path: web::Path<String>) -> impl Responder {
+pub async fn freeze_container(path: web::Path<String>) -> impl Responder {
     let name = path.into_inner();
-    info!("Deleting container: {}", name);
+    info!("Freezing container: {}", name);
 
-    match ContainerManager::delete(&name).await {
+    match ContainerManager::freeze(&name).await {
         Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": format!("Container {} deleted", name)
+            "message": format!("Container {} frozen", name)
         })),
         Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
             "error": format!("Container not found: {}", name)
         })),
         Err(e) => {
-            error!("Failed to delete container: {}", e);
+            error!("Failed to freeze container: {}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": e.to_string()
             }))
@@ -146,165 +691,629 @@ pub async fn delete_container(path: web::Path<String>) -> impl Responder {
     }
 }
 
-pub async fn list_nodes() -> impl Responder {
-    info!("Listing cluster nodes");
+pub async fn unfreeze_container(path: web::Path<String>) -> impl Responder {
+    let name = path.into_inner();
+    info!("Unfreezing container: {}", name);
 
-    // In production, get from cluster manager
-    HttpResponse::Ok().json(NodeListResponse { nodes: vec![] })
+    match ContainerManager::unfreeze(&name).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "message": format!("Container {} unfrozen", name)
+        })),
+        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Container not found: {}", name)
+        })),
+        Err(e) => {
+            error!("Failed to unfreeze container: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
 }
 
-pub async fn join_cluster(req: web::Json<JoinClusterRequest>) -> impl Responder {
-    info!("Joining cluster: {}", req.cluster_name);
+pub async fn get_container_usage(path: web::Path<String>) -> impl Responder {
+    let name = path.into_inner();
+    info!("Getting usage for container: {}", name);
 
-    // In production, implement cluster join logic
-    HttpResponse::Ok().json(serde_json::json!({
-        "message": "Cluster join initiated"
-    }))
+    match ContainerManager::usage(&name).await {
+        Ok(usage) => HttpResponse::Ok().json(serde_json::json!({
+            "container": name,
+            "usage": usage
+        })),
+        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Container not found: {}", name)
+        })),
+        Err(e) => {
+            error!("Failed to get container usage: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
 }
 
-pub async fn cluster_status() -> impl Responder {
-    info!("Getting cluster status");
+#[derive(Debug, Deserialize)]
+pub struct UsageHistoryQuery {
+    /// How far back to go, e.g. `6h` (default 1h)
+    pub window: Option<String>,
+    /// Width of each point, e.g. `1m` (default the sample interval)
+    pub step: Option<String>,
+}
 
-    // In production, get from cluster manager
+/// Lifecycle events of a container, oldest first
+///
+/// A deleted container keeps its history, ending in `deleted`, until its
+/// events are rotated out.
+pub async fn get_container_events(path: web::Path<String>) -> impl Responder {
+    let name = path.into_inner();
+    let events = ContainerManager::events(&name);
+    if events.is_empty() && !ContainerManager::exists(&name) {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Container not found: {}", name)
+        }));
+    }
     HttpResponse::Ok().json(serde_json::json!({
-        "cluster": {
-            "id": "00000000-0000-0000-0000-000000000000",
-            "name": "default",
-            "node_count": 0
-        }
+        "container": name,
+        "events": events,
     }))
 }
 
-pub async fn list_storage_pools() -> impl Responder {
-    info!("Listing storage pools");
-
-    // In production, get from storage manager
-    HttpResponse::Ok().json(StoragePoolListResponse { pools: vec![] })
-}
-
-pub async fn create_storage_pool(req: web::Json<CreateStoragePoolRequest>) -> impl Responder {
-    info!("Creating storage pool: {}", req.name);
+/// Usage of a container over time, from the in-memory sampler
+pub async fn get_container_usage_history(
+    path: web::Path<String>,
+    query: web::Query<UsageHistoryQuery>,
+    history: Option<web::Data<Arc<UsageHistory>>>,
+) -> impl Responder {
+    let name = path.into_inner();
+    let Some(history) = history else {
+        return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "Usage history is disabled"
+        }));
+    };
 
-    let result: Result<StoragePool, StorageError> = match req.storage_type {
-        StorageType::Local => LocalStorageManager::create_pool(&req.name, &req.path).await,
-        StorageType::Nfs => {
-            // Parse NFS path (server:path)
-            let parts: Vec<&str> = req.path.split(':').collect();
-            if parts.len() != 2 {
-                return HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Invalid NFS path format. Expected server:path"
-                }));
-            }
-            SharedStorageManager::create_nfs_pool(&req.name, parts[0], parts[1]).await
-        }
-        StorageType::Cifs => {
-            // Parse CIFS path (//server/share)
-            if !req.path.starts_with("//") {
-                return HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Invalid CIFS path format. Expected //server/share"
-                }));
-            }
-            let path = req.path.trim_start_matches("//");
-            let parts: Vec<&str> = path.split('/').collect();
-            if parts.len() != 2 {
-                return HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Invalid CIFS path format. Expected //server/share"
-                }));
-            }
-            SharedStorageManager::create_cifs_pool(&req.name, parts[0], parts[1], None).await
+    let window = match query.window.as_deref().map(usage_history::parse_duration) {
+        None => std::time::Duration::from_secs(3600),
+        Some(Some(window)) => window,
+        Some(None) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid window; use e.g. 90s, 15m, 6h or 7d"
+            }))
         }
     };
-
-    match result {
-        Ok(pool) => HttpResponse::Created().json(pool),
-        Err(e) => {
-            error!("Failed to create storage pool: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": e.to_string()
+    let step = match query.step.as_deref().map(usage_history::parse_duration) {
+        None => history.interval(),
+        Some(Some(step)) => step,
+        Some(None) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid step; use e.g. 90s, 15m, 6h or 7d"
             }))
         }
+    };
+    if window.as_secs() / step.as_secs() > usage_history::MAX_POINTS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!(
+                "A {}s window at {}s steps exceeds {} points",
+                window.as_secs(),
+                step.as_secs(),
+                usage_history::MAX_POINTS
+            )
+        }));
     }
-}
 
-pub async fn list_network_interfaces() -> impl Responder {
-    info!("Listing network interfaces");
+    if !ContainerManager::exists(&name) {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Container not found: {}", name)
+        }));
+    }
 
-    // In production, get from network manager
-    HttpResponse::Ok().json(NetworkListResponse { interfaces: vec![] })
+    let points = history.query(&name, window, step, chrono::Utc::now());
+    HttpResponse::Ok().json(serde_json::json!({
+        "container": name,
+        "window_secs": window.as_secs(),
+        "step_secs": step.as_secs(),
+        "sample_interval_secs": history.interval().as_secs(),
+        "points": points
+    }))
 }
 
-pub async fn list_bridges() -> impl Responder {
-    info!("Listing bridges");
+pub async fn get_container_config(path: web::Path<String>) -> impl Responder {
+    let name = path.into_inner();
+    info!("Getting effective config for container: {}", name);
 
-    match BridgeManager::list().await {
-        Ok(bridge_names) => {
-            // In production, get full bridge details
+    match ContainerManager::effective_config(&name).await {
+        Ok((raw, mut config)) => {
+            for (key, value) in config.environment.iter_mut() {
+                if LxcConfig::is_sensitive_key(key) {
+                    *value = REDACTED.to_string();
+                }
+            }
             HttpResponse::Ok().json(serde_json::json!({
-                "bridges": bridge_names
+                "container": name,
+                "raw": LxcConfig::redact(&raw),
+                "config": config
             }))
         }
+        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Container not found: {}", name)
+        })),
         Err(e) => {
-            error!("Failed to list bridges: {}", e);
+            error!("Failed to get container config: {}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("{}", e)
+                "error": e.to_string()
             }))
         }
     }
 }
 
-pub async fn create_bridge(req: web::Json<CreateBridgeRequest>) -> impl Responder {
-    info!("Creating bridge: {}", req.name);
+// ============================================================================
+// Container Interface Handlers
+// ============================================================================
 
-    match BridgeManager::create(req.into_inner()).await {
-        Ok(bridge) => HttpResponse::Created().json(bridge),
-        Err(NetworkError::BridgeExists(name)) => HttpResponse::Conflict().json(serde_json::json!({
-            "error": format!("Bridge already exists: {}", name)
-        })),
-        Err(e) => {
-            error!("Failed to create bridge: {}", e);
+fn hotplug_error_response(e: HotplugError) -> HttpResponse {
+    match e {
+        HotplugError::Container(ContainerError::NotFound(name)) => {
+            HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("Container not found: {}", name)
+            }))
+        }
+        HotplugError::Container(ContainerError::InterfaceNotFound(_)) => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        HotplugError::Container(ContainerError::InterfaceExists(_)) => {
+            HttpResponse::Conflict().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        HotplugError::Network(NetworkError::InterfaceNotFound(bridge)) => {
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Bridge not found: {}", bridge)
+            }))
+        }
+        HotplugError::Network(NetworkError::PermissionDenied(reason)) => {
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": reason }))
+        }
+        e => {
+            error!("Interface hot-plug failed: {}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("{}", e)
+                "error": e.to_string()
             }))
         }
     }
 }
 
-// ============================================================================
-// Container Snapshot Handlers
-// ============================================================================
-
-#[derive(Debug, Deserialize)]
-pub struct CreateSnapshotRequest {
-    pub name: Option<String>,
-    pub comment: Option<String>,
+fn audit_interface_change(
+    audit_logger: &AuditLogger,
+    actor: &AuthenticatedUser,
+    http: &HttpRequest,
+    container: &str,
+    details: String,
+) {
+    if let Ok(log) = AuditLogger::builder()
+        .actor(actor)
+        .request(http)
+        .action(AuditAction::ContainerUpdated)
+        .resource_type("container".to_string())
+        .resource_id(container.to_string())
+        .result(AuditResult::Success)
+        .details(details)
+        .build()
+    {
+        audit_logger.log_entry(log);
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct RestoreSnapshotRequest {
-    pub snapshot_name: String,
+/// Record a successful update with the fields it changed as the details
+fn audit_changes(audit_logger: &AuditLogger, entry: AuditLogBuilder, diff: &Diff) {
+    if let Ok(log) = entry
+        .result(AuditResult::Success)
+        .details(diff.to_details())
+        .build()
+    {
+        audit_logger.log_entry(log);
+    }
+}
+
+/// Add a network interface to a container, live when it is running
+///
+/// Addresses are checked for conflicts as on create.
+#[allow(clippy::too_many_arguments)]
+pub async fn attach_container_interface(
+    user: AuthenticatedUser,
+    http: HttpRequest,
+    path: web::Path<String>,
+    req: web::Json<ContainerNetworkInterface>,
+    query: web::Query<ConflictCheckQuery>,
+    ipam: Option<web::Data<Arc<Ipam>>>,
+    config: Option<web::Data<AppConfig>>,
+    audit_logger: web::Data<Arc<AuditLogger>>,
+) -> impl Responder {
+    for permission in [Permission::ContainerUpdate, Permission::NetworkWrite] {
+        if let Err(e) = user.require(permission) {
+            return e.error_response();
+        }
+    }
+    let name = path.into_inner();
+    info!(
+        "{} attaching interface {} to container: {}",
+        user.username, req.name, name
+    );
+
+    if let Err(errors) = req.validate() {
+        return validation_error_response(errors);
+    }
+    if !ContainerManager::exists(&name) {
+        return hotplug_error_response(ContainerError::NotFound(name).into());
+    }
+    if let Err(response) = check_address_conflicts(
+        &name,
+        std::slice::from_ref(&*req),
+        &query,
+        ipam.as_ref().map(|ipam| ipam.as_ref().as_ref()),
+        config.as_ref().map(|config| config.get_ref()),
+    )
+    .await
+    {
+        return response;
+    }
+    let mut interface = req.into_inner();
+
+    let mut allocated = None;
+    if interface.ipv4.as_deref() == Some(AUTO_ADDRESS) {
+        let Some(ref ipam) = ipam else {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Automatic addressing is not configured (network.ip_range)"
+            }));
+        };
+        match ipam.allocate(&name) {
+            Ok(address) => {
+                interface.ipv4 = Some(format!("{}/{}", address, ipam.prefix()));
+                interface
+                    .gateway_v4
+                    .get_or_insert_with(|| ipam.gateway().to_string());
+                allocated = Some(address);
+            }
+            Err(e @ NetworkError::AddressPoolExhausted(_)) => {
+                return HttpResponse::Conflict().json(serde_json::json!({ "error": e.to_string() }))
+            }
+            Err(e) => return hotplug_error_response(e.into()),
+        }
+    }
+
+    match hotplug::attach(&name, &interface).await {
+        Ok(live) => {
+            audit_interface_change(
+                &audit_logger,
+                &user,
+                &http,
+                &name,
+                format!(
+                    "Interface {} attached to bridge {}",
+                    interface.name, interface.bridge
+                ),
+            );
+            HttpResponse::Created().json(serde_json::json!({
+                "container": name,
+                "interface": interface,
+                "live": live
+            }))
+        }
+        Err(e) => {
+            if let (Some(address), Some(ipam)) = (allocated, &ipam) {
+                if let Err(e) = ipam.free(&[address]) {
+                    warn!("Failed to free address {}: {}", address, e);
+                }
+            }
+            hotplug_error_response(e)
+        }
+    }
+}
+
+/// Remove a network interface from a container, live when it is running
+pub async fn detach_container_interface(
+    user: AuthenticatedUser,
+    http: HttpRequest,
+    path: web::Path<(String, String)>,
+    ipam: Option<web::Data<Arc<Ipam>>>,
+    audit_logger: web::Data<Arc<AuditLogger>>,
+) -> impl Responder {
+    for permission in [Permission::ContainerUpdate, Permission::NetworkWrite] {
+        if let Err(e) = user.require(permission) {
+            return e.error_response();
+        }
+    }
+    let (name, interface) = path.into_inner();
+    info!(
+        "{} detaching interface {} from container: {}",
+        user.username, interface, name
+    );
+
+    match hotplug::detach(&name, &interface).await {
+        Ok(removed) => {
+            // Only addresses the range handed to this container go back to it
+            let address = removed
+                .ipv4
+                .as_deref()
+                .and_then(|cidr| cidr.split('/').next())
+                .and_then(|address| address.parse().ok());
+            if let (Some(address), Some(ipam)) = (address, &ipam) {
+                if ipam.allocations().get(&address) == Some(&name) {
+                    if let Err(e) = ipam.free(&[address]) {
+                        warn!("Failed to free address {}: {}", address, e);
+                    }
+                }
+            }
+            audit_interface_change(
+                &audit_logger,
+                &user,
+                &http,
+                &name,
+                format!("Interface {} detached", interface),
+            );
+            HttpResponse::Ok().json(serde_json::json!({
+                "container": name,
+                "interface": removed
+            }))
+        }
+        Err(e) => hotplug_error_response(e),
+    }
 }
 
+// ============================================================================
+// Container Secret Handlers
+// ============================================================================
+
 #[derive(Debug, Deserialize)]
-pub struct CloneFromSnapshotRequest {
-    pub snapshot_name: String,
-    pub new_container_name: String,
+pub struct PutSecretRequest {
+    pub value: String,
 }
 
-/// List all snapshots for a container
-pub async fn list_snapshots(path: web::Path<String>) -> impl Responder {
-    let container_name = path.into_inner();
-    info!("Listing snapshots for container: {}", container_name);
+fn secret_error_response(e: SecretError) -> HttpResponse {
+    match e {
+        SecretError::Container(ContainerError::NotFound(name)) => {
+            HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("Container not found: {}", name)
+            }))
+        }
+        SecretError::NotFound(_) => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        SecretError::InvalidName(_) => {
+            HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        SecretError::MissingValue(_) => {
+            HttpResponse::Conflict().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        SecretError::NotConfigured => {
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        e => {
+            error!("Secret operation failed: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
+}
 
-    match SnapshotManager::list(&container_name).await {
-        Ok(snapshots) => HttpResponse::Ok().json(serde_json::json!({
-            "container": container_name,
-            "snapshots": snapshots
+fn audit_secret_change(
+    audit_logger: &AuditLogger,
+    actor: &AuthenticatedUser,
+    http: &HttpRequest,
+    container: &str,
+    details: String,
+) {
+    // Details name the secret only; values never reach the audit log
+    if let Ok(log) = AuditLogger::builder()
+        .actor(actor)
+        .request(http)
+        .action(AuditAction::ContainerUpdated)
+        .resource_type("container".to_string())
+        .resource_id(container.to_string())
+        .result(AuditResult::Success)
+        .details(details)
+        .build()
+    {
+        audit_logger.log_entry(log);
+    }
+}
+
+pub async fn list_container_secrets(
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    secret_store: Option<web::Data<Arc<SecretStore>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::ContainerRead) {
+        return e.error_response();
+    }
+    let name = path.into_inner();
+    let Some(store) = secret_store else {
+        return secret_error_response(SecretError::NotConfigured);
+    };
+
+    if !ContainerManager::exists(&name) {
+        return secret_error_response(ContainerError::NotFound(name).into());
+    }
+    match store.list(&name) {
+        Ok(secrets) => HttpResponse::Ok().json(serde_json::json!({
+            "container": name,
+            "secrets": secrets
         })),
-        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
+        Err(e) => secret_error_response(e),
+    }
+}
+
+pub async fn put_container_secret(
+    user: AuthenticatedUser,
+    http: HttpRequest,
+    path: web::Path<(String, String)>,
+    req: web::Json<PutSecretRequest>,
+    secret_store: Option<web::Data<Arc<SecretStore>>>,
+    audit_logger: web::Data<Arc<AuditLogger>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::ContainerUpdate) {
+        return e.error_response();
+    }
+    let (name, secret) = path.into_inner();
+    info!(
+        "{} storing secret '{}' for container: {}",
+        user.username, secret, name
+    );
+    let Some(store) = secret_store else {
+        return secret_error_response(SecretError::NotConfigured);
+    };
+
+    if !ContainerManager::exists(&name) {
+        return secret_error_response(ContainerError::NotFound(name).into());
+    }
+    match store.put(&name, &secret, req.value.as_bytes()) {
+        Ok(()) => {
+            audit_secret_change(
+                &audit_logger,
+                &user,
+                &http,
+                &name,
+                format!("Secret '{}' set", secret),
+            );
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": format!("Secret '{}' stored for container {}", secret, name)
+            }))
+        }
+        Err(e) => secret_error_response(e),
+    }
+}
+
+pub async fn delete_container_secret(
+    user: AuthenticatedUser,
+    http: HttpRequest,
+    path: web::Path<(String, String)>,
+    secret_store: Option<web::Data<Arc<SecretStore>>>,
+    audit_logger: web::Data<Arc<AuditLogger>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::ContainerUpdate) {
+        return e.error_response();
+    }
+    let (name, secret) = path.into_inner();
+    info!(
+        "{} deleting secret '{}' for container: {}",
+        user.username, secret, name
+    );
+    let Some(store) = secret_store else {
+        return secret_error_response(SecretError::NotConfigured);
+    };
+
+    match store.delete(&name, &secret) {
+        Ok(()) => {
+            audit_secret_change(
+                &audit_logger,
+                &user,
+                &http,
+                &name,
+                format!("Secret '{}' deleted", secret),
+            );
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": format!("Secret '{}' deleted from container {}", secret, name)
+            }))
+        }
+        Err(e) => secret_error_response(e),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateContainerQuery {
+    #[serde(default)]
+    pub apply: ApplyLimits,
+}
+
+/// Update a container's config, its running cgroup, or both (`?apply=`)
+///
+/// Live application only writes the limits the request changes, and happens
+/// before the config is saved so a container that cannot take them is left
+/// untouched.
+pub async fn update_container(
+    http: HttpRequest,
+    path: web::Path<String>,
+    req: web::Json<UpdateContainerRequest>,
+    query: web::Query<UpdateContainerQuery>,
+    user: Option<AuthenticatedUser>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    let name = path.into_inner();
+    info!("Updating container: {} ({:?})", name, query.apply);
+
+    let mut applied_live = None;
+    if query.apply != ApplyLimits::Persistent {
+        let result = match ContainerManager::preview_update(&name, &req).await {
+            Ok(config) => {
+                let changes_memory = req.memory_limit.is_some() || req.memory_swap_limit.is_some();
+                let live = ContainerConfig {
+                    cpu_limit: None,
+                    memory_limit: config.memory_limit.filter(|_| changes_memory),
+                    memory_swap_limit: config.memory_swap_limit.filter(|_| changes_memory),
+                    ..config
+                };
+                ContainerManager::apply_limits_live(&name, &live).await
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(writes) => applied_live = Some(writes),
+            Err(e) => return update_error_response(e),
+        }
+    }
+    if query.apply == ApplyLimits::Live {
+        return match ContainerManager::get(&name).await {
+            Ok(container) => HttpResponse::Ok().json(serde_json::json!({
+                "container": container,
+                "applied_live": applied_live,
+            })),
+            Err(e) => update_error_response(e),
+        };
+    }
+
+    let before = ContainerManager::get(&name).await.ok().map(|c| c.config);
+    match ContainerManager::update(&name, &req).await {
+        Ok(container) => {
+            if let (Some(audit_logger), Some(before)) = (audit_logger, before) {
+                let builder = match user {
+                    Some(ref user) => AuditLogger::builder().actor(user),
+                    None => AuditLogger::builder(),
+                };
+                audit_changes(
+                    &audit_logger,
+                    builder
+                        .request(&http)
+                        .action(AuditAction::ContainerUpdated)
+                        .resource_type("container".to_string())
+                        .resource_id(name.clone()),
+                    &Diff::between(&before, &container.config),
+                );
+            }
+            match applied_live {
+                Some(writes) => HttpResponse::Ok().json(serde_json::json!({
+                    "container": container,
+                    "applied_live": writes,
+                })),
+                None => HttpResponse::Ok().json(ContainerResponse { container }),
+            }
+        }
+        Err(e) => update_error_response(e),
+    }
+}
+
+fn update_error_response(e: ContainerError) -> HttpResponse {
+    match e {
+        ContainerError::NotFound(name) => HttpResponse::NotFound().json(serde_json::json!({
             "error": format!("Container not found: {}", name)
         })),
-        Err(e) => {
-            error!("Failed to list snapshots: {}", e);
+        ContainerError::InvalidConfig(e) => {
+            HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))
+        }
+        e @ ContainerError::NotRunning(_) => {
+            HttpResponse::Conflict().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        e @ ContainerError::Unsupported(_) => {
+            HttpResponse::NotImplemented().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        e => {
+            error!("Failed to update container: {}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": e.to_string()
             }))
@@ -312,24 +1321,110 @@ pub async fn list_snapshots(path: web::Path<String>) -> impl Responder {
     }
 }
 
-/// Create a snapshot of a container
-pub async fn create_snapshot(
+#[derive(Debug, Default, Deserialize)]
+pub struct DeleteContainerQuery {
+    /// Remove snapshots, volumes and port forwards before destroying
+    #[serde(default)]
+    pub cascade: bool,
+}
+
+/// Delete a container, refusing with 409 while it has dependents unless
+/// `?cascade=true`
+pub async fn delete_container(
+    http: HttpRequest,
     path: web::Path<String>,
-    req: web::Json<CreateSnapshotRequest>,
+    query: web::Query<DeleteContainerQuery>,
+    ipam: Option<web::Data<Arc<Ipam>>>,
+    secret_store: Option<web::Data<Arc<SecretStore>>>,
+    user: Option<AuthenticatedUser>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
 ) -> impl Responder {
-    let container_name = path.into_inner();
-    info!("Creating snapshot for container: {}", container_name);
+    let name = path.into_inner();
+    info!("Deleting container: {} (cascade: {})", name, query.cascade);
+    let forget_secrets = || {
+        if let Some(store) = &secret_store {
+            if let Err(e) = store.remove_container(&name) {
+                warn!("Failed to remove stored secrets of {}: {}", name, e);
+            }
+        }
+    };
+    let ipam = ipam.as_ref().map(|ipam| ipam.as_ref().as_ref());
 
-    match SnapshotManager::create(&container_name, req.name.clone(), req.comment.clone()).await {
-        Ok(snapshot) => HttpResponse::Created().json(serde_json::json!({
-            "message": "Snapshot created successfully",
-            "snapshot": snapshot
-        })),
-        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
+    if query.cascade {
+        let audit = || {
+            let builder = AuditLogger::builder().request(&http);
+            match user {
+                Some(ref user) => builder.actor(user),
+                None => builder,
+            }
+        };
+        let audit_logger = audit_logger.as_ref().map(|logger| logger.as_ref().as_ref());
+        return match container_delete::cascade(&name, ipam, audit_logger, audit).await {
+            Ok(report) if report.remaining.is_empty() => {
+                forget_secrets();
+                HttpResponse::Ok().json(serde_json::json!({
+                    "message": format!("Container {} deleted", name),
+                    "completed": report.completed,
+                }))
+            }
+            Ok(report) => {
+                error!(
+                    "Cascading delete of {} left {} cleanups",
+                    name,
+                    report.remaining.len()
+                );
+                HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": format!("Container {} was not deleted; cleanups remain", name),
+                    "completed": report.completed,
+                    "remaining": report.remaining,
+                }))
+            }
+            Err(e) => delete_error_response(e),
+        };
+    }
+
+    match container_delete::dependents(&name, ipam).await {
+        Ok(dependents) if !dependents.is_empty() => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!(
+                    "Container {} has dependents; delete them first or pass cascade=true",
+                    name
+                ),
+                "dependents": dependents,
+            }))
+        }
+        Ok(_) => {}
+        Err(e) => return delete_error_response(e),
+    }
+
+    egress::remove(&name).await;
+    let result = ContainerManager::delete(&name).await;
+    if let (Ok(_), Some(ipam)) = (&result, ipam) {
+        if let Err(e) = ipam.release(&name) {
+            warn!("Failed to release addresses of {}: {}", name, e);
+        }
+    }
+    match result {
+        Ok(_) => {
+            forget_secrets();
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": format!("Container {} deleted", name)
+            }))
+        }
+        Err(e) => delete_error_response(e),
+    }
+}
+
+fn delete_error_response(e: ContainerError) -> HttpResponse {
+    match e {
+        ContainerError::NotFound(name) => HttpResponse::NotFound().json(serde_json::json!({
             "error": format!("Container not found: {}", name)
         })),
-        Err(e) => {
-            error!("Failed to create snapshot: {}", e);
+        e @ ContainerError::QueueTimeout { .. } => {
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        e => {
+            error!("Failed to delete container: {}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": e.to_string()
             }))
@@ -337,239 +1432,2981 @@ pub async fn create_snapshot(
     }
 }
 
-/// Restore a container from a snapshot
-pub async fn restore_snapshot(
-    path: web::Path<String>,
-    req: web::Json<RestoreSnapshotRequest>,
+pub async fn list_nodes(
+    membership: Option<web::Data<Arc<RwLock<MembershipManager>>>>,
+    peer_health: Option<web::Data<Arc<RwLock<PeerHealth>>>>,
+    cluster_state: Option<web::Data<Arc<RwLock<ClusterState>>>>,
 ) -> impl Responder {
-    let container_name = path.into_inner();
+    info!("Listing cluster nodes");
+
+    // Without membership this node cannot tell an empty cluster from an
+    // unknown one
+    let Some(membership) = membership else {
+        return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "Cluster membership is not available"
+        }));
+    };
+    let mut nodes: Vec<Node> = match membership.read() {
+        Ok(membership) => membership.list_nodes().into_iter().cloned().collect(),
+        Err(e) => {
+            error!("Cluster membership lock poisoned: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to read cluster membership"
+            }));
+        }
+    };
+    if let Some(health) = peer_health {
+        let health = health.read().unwrap();
+        for node in &mut nodes {
+            node.latency = health.get(&node.id).cloned();
+        }
+    }
+    if let Some(ref state) = cluster_state {
+        with_storage_pools(&mut nodes, state);
+    }
+
+    HttpResponse::Ok().json(NodeListResponse { nodes })
+}
+
+/// Fill in the pools each node last reported
+fn with_storage_pools(nodes: &mut [Node], cluster_state: &RwLock<ClusterState>) {
+    let state = cluster_state.read().unwrap();
+    for node in nodes {
+        node.storage_pools = state.get_node_pools(&node.id);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct JoinTokenRequest {
+    pub ttl_seconds: u64,
+    pub single_use: bool,
+}
+
+impl Default for JoinTokenRequest {
+    fn default() -> Self {
+        Self {
+            ttl_seconds: 900,
+            single_use: true,
+        }
+    }
+}
+
+/// Issue a token allowing one node (or any node until it expires) to join
+pub async fn create_join_token(
+    user: AuthenticatedUser,
+    req: web::Json<JoinTokenRequest>,
+    join_tokens: web::Data<Arc<JoinTokenManager>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    if req.ttl_seconds == 0 || req.ttl_seconds > MAX_JOIN_TOKEN_TTL_SECS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("ttl_seconds must be between 1 and {}", MAX_JOIN_TOKEN_TTL_SECS)
+        }));
+    }
+
+    let (token, expires_at) = join_tokens.issue(req.ttl_seconds, req.single_use);
     info!(
-        "Restoring container '{}' from snapshot '{}'",
-        container_name, req.snapshot_name
+        "{} issued a {} join token expiring at {}",
+        user.username,
+        if req.single_use {
+            "single-use"
+        } else {
+            "reusable"
+        },
+        expires_at
     );
 
-    match SnapshotManager::restore(&container_name, &req.snapshot_name).await {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": format!(
-                "Container '{}' restored from snapshot '{}'",
-                container_name, req.snapshot_name
-            )
-        })),
-        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": format!("Container not found: {}", name)
+    HttpResponse::Created().json(serde_json::json!({
+        "token": token,
+        "expires_at": expires_at,
+        "single_use": req.single_use
+    }))
+}
+
+/// Replace the cluster join token; existing members are not affected
+pub async fn rotate_cluster_token(
+    http: HttpRequest,
+    user: AuthenticatedUser,
+    join_tokens: web::Data<Arc<JoinTokenManager>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+
+    let result = join_tokens.rotate_cluster_token();
+    if let Some(audit_logger) = audit_logger {
+        // Never record the token itself
+        if let Ok(log) = AuditLogger::builder()
+            .actor(&user)
+            .action(AuditAction::ConfigurationChanged)
+            .resource_type("cluster_join_token".to_string())
+            .result(match result {
+                Ok(_) => AuditResult::Success,
+                Err(ref e) => AuditResult::Failure(e.to_string()),
+            })
+            .request(&http)
+            .details("Cluster join token rotated".to_string())
+            .build()
+        {
+            audit_logger.log_entry(log);
+        }
+    }
+
+    match result {
+        Ok(token) => {
+            info!("{} rotated the cluster join token", user.username);
+            HttpResponse::Ok().json(serde_json::json!({ "token": token }))
+        }
+        Err(e) => {
+            error!("Failed to rotate cluster join token: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to store the new join token"
+            }))
+        }
+    }
+}
+
+pub async fn join_cluster(
+    req: web::Json<JoinClusterRequest>,
+    join_tokens: web::Data<Arc<JoinTokenManager>>,
+    membership: Option<web::Data<Arc<RwLock<MembershipManager>>>>,
+    cluster_state: Option<web::Data<Arc<RwLock<ClusterState>>>>,
+) -> impl Responder {
+    info!("Joining cluster: {}", req.cluster_name);
+
+    if let Err(errors) = req.validate() {
+        return validation_error_response(errors);
+    }
+
+    // Only the leader admits nodes, so only its token counts
+    let local_id = membership.map(|membership| membership.read().unwrap().local_node_id());
+    let mut cluster_id = None;
+    if let (Some(local_id), Some(state)) = (local_id, cluster_state) {
+        let state = state.read().unwrap();
+        cluster_id = Some(state.cluster_id);
+        match state.leader_id {
+            None => {
+                return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                    "error": "No cluster leader elected"
+                }))
+            }
+            Some(leader) if leader != local_id => {
+                return HttpResponse::MisdirectedRequest().json(serde_json::json!({
+                    "error": "Joins are admitted by the leader",
+                    "leader_id": leader
+                }))
+            }
+            Some(_) => {}
+        }
+    }
+
+    match join_tokens.redeem(req.join_token.as_deref()) {
+        Ok(credential) => info!(
+            "Admitting {}:{} with {}",
+            req.node_address,
+            req.node_port,
+            match credential {
+                JoinCredential::ClusterToken => "the cluster join token",
+                JoinCredential::Issued(_) => "an issued join token",
+            }
+        ),
+        Err(e) => {
+            warn!(
+                "Rejected join from {}:{}: {}",
+                req.node_address, req.node_port, e
+            );
+            return HttpResponse::Forbidden().json(serde_json::json!({
+                "error": e.to_string()
+            }));
+        }
+    }
+
+    // The joining node follows this one, which leads (or, standalone, is
+    // the only node there is)
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": "Cluster join initiated",
+        "cluster_id": cluster_id,
+        "leader_id": local_id
+    }))
+}
+
+/// Leave the cluster: hand off leadership, move this node's containers to
+/// the remaining members, then drop out of membership and tell the peers
+pub async fn leave_cluster(
+    user: AuthenticatedUser,
+    req: Option<web::Json<LeaveRequest>>,
+    cluster: Option<web::Data<Arc<ClusterMembership>>>,
+    jobs: web::Data<Arc<JobManager>>,
+    audit_logger: web::Data<Arc<AuditLogger>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    let Some(cluster) = cluster else {
+        return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "This node is not part of a cluster"
+        }));
+    };
+
+    let job = match jobs.create_exclusive(LEAVE_JOB, &[LEAVE_JOB]) {
+        Ok(job) => job,
+        Err(active) => return system_job_conflict(&active),
+    };
+    info!(
+        "Cluster leave requested by {} (job {})",
+        user.username, job.id
+    );
+
+    jobs::spawn(
+        &job,
+        cluster_leave::run(
+            job.id,
+            req.map(web::Json::into_inner).unwrap_or_default(),
+            cluster.get_ref().clone(),
+            user,
+            jobs.get_ref().clone(),
+            audit_logger.get_ref().clone(),
+        ),
+    );
+
+    HttpResponse::Accepted().json(serde_json::json!({ "job_id": job.id }))
+}
+
+pub async fn cluster_status(auto_join: Option<web::Data<Arc<AutoJoin>>>) -> impl Responder {
+    info!("Getting cluster status");
+
+    // Standalone nodes have no seeds to join through
+    let join = auto_join.map(|auto_join| auto_join.status());
+    // In production, get from cluster manager
+    HttpResponse::Ok().json(serde_json::json!({
+        "cluster": {
+            "id": "00000000-0000-0000-0000-000000000000",
+            "name": "default",
+            "node_count": 0
+        },
+        "status": match join {
+            None => "standalone",
+            Some(JoinStatus::Joining { .. }) => "joining",
+            Some(JoinStatus::Joined { .. }) => "joined",
+        },
+        "join": join
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClusterContainersQuery {
+    /// Node id or name
+    pub node: Option<String>,
+    pub status: Option<ContainerStatus>,
+    /// Ask every node over its API instead of using what its heartbeats
+    /// reported
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+/// A member as (id, name, API address); the address is None for this node,
+/// which is not asked over HTTP
+type ClusterTarget = (Uuid, Option<String>, Option<String>);
+
+/// This node's id and every member, this node first
+fn cluster_targets(membership: &MembershipManager) -> (Uuid, Vec<ClusterTarget>) {
+    let local_id = membership.local_node_id();
+    let mut targets: Vec<ClusterTarget> = membership
+        .list_nodes()
+        .into_iter()
+        .filter(|n| n.id != local_id)
+        .map(|n| {
+            (
+                n.id,
+                Some(n.name.clone()),
+                Some(format!("{}:{}", n.address, n.port)),
+            )
+        })
+        .collect();
+    let local_name = membership.get_local_node().map(|n| n.name.clone());
+    targets.insert(0, (local_id, local_name, None));
+    (local_id, targets)
+}
+
+/// List containers across all cluster members
+///
+/// Only the leader serves this, from the containers each node reported on
+/// its heartbeats unless `refresh` is set; in standalone mode the local node
+/// is the only member and is always asked directly.
+pub async fn list_cluster_containers(
+    http: HttpRequest,
+    query: web::Query<ClusterContainersQuery>,
+    config: Option<web::Data<AppConfig>>,
+    membership: web::Data<Arc<RwLock<MembershipManager>>>,
+    cluster_state: Option<web::Data<Arc<RwLock<ClusterState>>>>,
+) -> impl Responder {
+    let (local_id, mut targets) = cluster_targets(&membership.read().unwrap());
+
+    let clustered = cluster_state.is_some();
+    let (assignments, reported) = match cluster_state {
+        Some(state) => {
+            let state = state.read().unwrap();
+            match state.leader_id {
+                None => {
+                    return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                        "error": "No cluster leader elected"
+                    }))
+                }
+                Some(leader) if leader != local_id => {
+                    return HttpResponse::MisdirectedRequest().json(serde_json::json!({
+                        "error": "Cluster-wide listings are served by the leader",
+                        "leader_id": leader
+                    }))
+                }
+                Some(_) => (
+                    state.node_assignments.clone(),
+                    state.node_containers.clone(),
+                ),
+            }
+        }
+        None => Default::default(),
+    };
+
+    if let Some(ref node) = query.node {
+        targets.retain(|(id, name, _)| id.to_string() == *node || name.as_deref() == Some(node));
+        if targets.is_empty() {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("Node not found: {}", node)
+            }));
+        }
+    }
+    let live = query.refresh || !clustered;
+    info!(
+        "Listing containers across {} node(s) from {}",
+        targets.len(),
+        if live { "their APIs" } else { "heartbeats" }
+    );
+
+    let heartbeat_interval = config
+        .as_ref()
+        .and_then(|c| c.cluster.heartbeat_interval)
+        .unwrap_or(1000);
+    let stale_after =
+        std::time::Duration::from_millis(heartbeat_interval) * cluster_view::STALE_AFTER_HEARTBEATS;
+    let (source, results, received) = if live {
+        let scheme = match config.as_ref().and_then(|c| c.server.tls.as_ref()) {
+            Some(_) => "https",
+            None => "http",
+        };
+        let authorization = http
+            .headers()
+            .get(actix_web::http::header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok());
+        let results =
+            futures::future::join_all(targets.iter().map(|(id, _, address)| async move {
+                let result = match address {
+                    None => cluster_view::local_containers().await,
+                    Some(address) => {
+                        cluster_view::remote_containers(
+                            &format!("{}://{}", scheme, address),
+                            authorization,
+                        )
+                        .await
+                    }
+                };
+                (*id, result)
+            }))
+            .await;
+        let received = vec![Some(chrono::Utc::now()); results.len()];
+        (cluster_view::ListingSource::Live, results, received)
+    } else {
+        let mut results = Vec::with_capacity(targets.len());
+        let mut received = Vec::with_capacity(targets.len());
+        for (id, _, _) in &targets {
+            let cached = reported.get(id);
+            results.push((*id, cluster_view::heartbeat_containers(cached)));
+            received.push(cached.map(|c| c.received_at));
+        }
+        (cluster_view::ListingSource::Heartbeat, results, received)
+    };
+
+    let nodes: Vec<cluster_view::NodeListing> = targets
+        .iter()
+        .zip(&results)
+        .zip(&received)
+        .map(|(((id, name, _), (_, result)), received_at)| {
+            if let Err(e) = result {
+                warn!("Container listing from node {} is stale: {}", id, e);
+            }
+            cluster_view::node_listing(*id, name.clone(), source, result, *received_at, stale_after)
+        })
+        .collect();
+    let stale: std::collections::HashSet<Uuid> = nodes
+        .iter()
+        .filter(|node| node.stale)
+        .map(|node| node.node_id)
+        .collect();
+
+    // A node filter also restricts the assignment map to that node
+    let assignments = if query.node.is_some() {
+        assignments
+            .into_iter()
+            .filter(|(id, _)| targets.iter().any(|(t, _, _)| t == id))
+            .collect()
+    } else {
+        assignments
+    };
+    let mut containers = cluster_view::merge(results, &stale, &assignments);
+    if let Some(ref status) = query.status {
+        containers.retain(|c| c.status.as_ref() == Some(status));
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "containers": containers,
+        "nodes": nodes
+    }))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct InventoryQuery {
+    #[serde(default)]
+    pub format: ReportFormat,
+    /// `label:key=value[,...]`, matched against the labels of each node
+    pub filter: Option<String>,
+    /// Only this node's containers, as JSON; what the leader asks members for
+    #[serde(default)]
+    pub local: bool,
+}
+
+/// Report of every container in the cluster with its limits, and what each
+/// node has committed against its capacity
+///
+/// Like the cluster-wide listing, only the leader serves the full report;
+/// members are asked for their own containers over their API.
+pub async fn inventory_report(
+    user: AuthenticatedUser,
+    http: HttpRequest,
+    query: web::Query<InventoryQuery>,
+    config: Option<web::Data<AppConfig>>,
+    membership: web::Data<Arc<RwLock<MembershipManager>>>,
+    cluster_state: Option<web::Data<Arc<RwLock<ClusterState>>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemRead) {
+        return e.error_response();
+    }
+    let filter = match query.filter.as_deref().map(inventory::parse_filter) {
+        None => Vec::new(),
+        Some(Ok(filter)) => filter,
+        Some(Err(e)) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+        }
+    };
+    let (local_id, mut targets, nodes) = {
+        let membership = membership.read().unwrap();
+        let (local_id, targets) = cluster_targets(&membership);
+        let nodes: std::collections::HashMap<Uuid, Node> = membership
+            .list_nodes()
+            .into_iter()
+            .map(|n| (n.id, n.clone()))
+            .collect();
+        (local_id, targets, nodes)
+    };
+
+    if query.local {
+        let local_name = targets[0].1.clone();
+        return match inventory::local_rows(local_id, local_name).await {
+            Ok(containers) => HttpResponse::Ok().json(inventory::NodeInventory { containers }),
+            Err(e) => {
+                error!("Failed to list containers for the inventory: {}", e);
+                HttpResponse::InternalServerError().json(serde_json::json!({ "error": e }))
+            }
+        };
+    }
+    if let Some(state) = cluster_state {
+        match state.read().unwrap().leader_id {
+            None => {
+                return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                    "error": "No cluster leader elected"
+                }))
+            }
+            Some(leader) if leader != local_id => {
+                return HttpResponse::MisdirectedRequest().json(serde_json::json!({
+                    "error": "Cluster-wide reports are served by the leader",
+                    "leader_id": leader
+                }))
+            }
+            Some(_) => {}
+        }
+    }
+
+    // Members that do not match the filter are not asked at all
+    targets.retain(|(id, _, _)| inventory::matches(nodes.get(id), &filter));
+    let scheme = match config.as_ref().and_then(|c| c.server.tls.as_ref()) {
+        Some(_) => "https",
+        None => "http",
+    };
+    let authorization = http
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+    let results = futures::future::join_all(targets.iter().map(|(id, name, address)| async move {
+        let result = match address {
+            None => inventory::local_rows(*id, name.clone()).await,
+            Some(address) => {
+                inventory::remote_rows(&format!("{}://{}", scheme, address), authorization).await
+            }
+        };
+        if let Err(ref e) = result {
+            warn!("Inventory of node {} unavailable: {}", id, e);
+        }
+        (*id, result)
+    }))
+    .await;
+
+    let report = inventory::build(results, &nodes);
+    info!(
+        "Inventory report of {} container(s) on {} node(s) for {}",
+        report.containers.len(),
+        report.nodes.len(),
+        user.username
+    );
+    let chunks = inventory::render(&report, query.format);
+    HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .streaming(futures::stream::iter(
+            chunks
+                .into_iter()
+                .map(|chunk| Ok::<_, actix_web::Error>(web::Bytes::from(chunk))),
+        ))
+}
+
+/// Export the committed cluster state and membership for disaster recovery
+///
+/// Only the leader serves this, since followers may lag behind.
+pub async fn export_cluster_state(
+    user: AuthenticatedUser,
+    membership: web::Data<Arc<RwLock<MembershipManager>>>,
+    cluster_state: Option<web::Data<Arc<RwLock<ClusterState>>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::ClusterRead) {
+        return e.error_response();
+    }
+    let Some(state) = cluster_state else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "This node is not part of a cluster"
+        }));
+    };
+
+    let membership = membership.read().unwrap();
+    let state = state.read().unwrap();
+    match state.leader_id {
+        None => {
+            return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": "No cluster leader elected"
+            }))
+        }
+        Some(leader) if leader != membership.local_node_id() => {
+            return HttpResponse::MisdirectedRequest().json(serde_json::json!({
+                "error": "Cluster state is exported by the leader",
+                "leader_id": leader
+            }))
+        }
+        Some(_) => {}
+    }
+
+    info!("{} exported the cluster state", user.username);
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(ClusterSnapshot::capture(&state, &membership).to_bytes())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportClusterStateQuery {
+    /// Replace state the cluster already has
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Seed a replacement cluster from an exported snapshot
+///
+/// Meant for bootstrap: a cluster that already has assignments or storage
+/// allocations is only overwritten with `force`.
+pub async fn import_cluster_state(
+    http: HttpRequest,
+    user: AuthenticatedUser,
+    query: web::Query<ImportClusterStateQuery>,
+    body: web::Bytes,
+    membership: web::Data<Arc<RwLock<MembershipManager>>>,
+    cluster_state: Option<web::Data<Arc<RwLock<ClusterState>>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    let Some(state) = cluster_state else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "This node is not part of a cluster"
+        }));
+    };
+    let snapshot = match ClusterSnapshot::from_bytes(&body) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    };
+
+    let (nodes, containers) = {
+        let mut membership = membership.write().unwrap();
+        let mut state = state.write().unwrap();
+        // Before an election the bootstrapping node may import on its own
+        if let Some(leader) = state.leader_id {
+            if leader != membership.local_node_id() {
+                return HttpResponse::MisdirectedRequest().json(serde_json::json!({
+                    "error": "Cluster state is imported through the leader",
+                    "leader_id": leader
+                }));
+            }
+        }
+        if !state.is_empty() && !query.force {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "The cluster already has committed state; pass force=true to replace it"
+            }));
+        }
+
+        let nodes = snapshot.nodes.len();
+        let containers: usize = snapshot.state.node_assignments.values().map(Vec::len).sum();
+        snapshot.restore(&mut state, &mut membership);
+        (nodes, containers)
+    };
+
+    info!(
+        "{} imported cluster state with {} node(s) and {} assigned container(s)",
+        user.username, nodes, containers
+    );
+    if let Some(audit_logger) = audit_logger {
+        if let Ok(log) = AuditLogger::builder()
+            .actor(&user)
+            .action(AuditAction::ConfigurationChanged)
+            .resource_type("cluster_state".to_string())
+            .result(AuditResult::Success)
+            .request(&http)
+            .details(format!(
+                "Imported {} node(s) and {} assigned container(s){}",
+                nodes,
+                containers,
+                if query.force {
+                    ", replacing existing state"
+                } else {
+                    ""
+                }
+            ))
+            .build()
+        {
+            audit_logger.log_entry(log);
+        }
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "nodes": nodes,
+        "containers": containers
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduleQuery {
+    #[serde(default)]
+    pub explain: bool,
+}
+
+pub async fn schedule_container(
+    user: AuthenticatedUser,
+    query: web::Query<ScheduleQuery>,
+    req: web::Json<PlacementRequest>,
+    membership: web::Data<Arc<RwLock<MembershipManager>>>,
+    cluster_state: Option<web::Data<Arc<RwLock<ClusterState>>>>,
+    jobs: web::Data<Arc<JobManager>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::ContainerCreate) {
+        return e.error_response();
+    }
+    let request = req.into_inner();
+    let mut nodes: Vec<Node> = membership
+        .read()
+        .unwrap()
+        .list_nodes()
+        .into_iter()
+        .cloned()
+        .collect();
+    if let Some(ref state) = cluster_state {
+        with_storage_pools(&mut nodes, state);
+    }
+    let nodes: Vec<&Node> = nodes.iter().collect();
+
+    if query.explain {
+        info!("Explaining placement across {} node(s)", nodes.len());
+        return HttpResponse::Ok().json(Scheduler::explain(&nodes, &request));
+    }
+
+    info!("Scheduling placement across {} node(s)", nodes.len());
+    let job = jobs.create("placement");
+
+    match Scheduler::schedule(&nodes, &request) {
+        Ok((node_id, explanation)) => {
+            jobs.succeed(
+                job.id,
+                serde_json::json!({ "node_id": node_id, "explanation": explanation }),
+            );
+            HttpResponse::Ok().json(serde_json::json!({
+                "job_id": job.id,
+                "node_id": node_id
+            }))
+        }
+        Err((e, explanation)) => {
+            error!("Placement failed: {}", e);
+            jobs.fail(
+                job.id,
+                e.to_string(),
+                Some(serde_json::json!({ "explanation": explanation })),
+            );
+            HttpResponse::Conflict().json(serde_json::json!({
+                "error": e.to_string(),
+                "job_id": job.id
+            }))
+        }
+    }
+}
+
+pub async fn list_jobs(
+    user: AuthenticatedUser,
+    jobs: web::Data<Arc<JobManager>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemRead) {
+        return e.error_response();
+    }
+    HttpResponse::Ok().json(serde_json::json!({
+        "jobs": jobs.list(),
+        "operations": ContainerManager::operation_queue(),
+    }))
+}
+
+pub async fn get_job(
+    user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    jobs: web::Data<Arc<JobManager>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemRead) {
+        return e.error_response();
+    }
+    let id = path.into_inner();
+
+    match jobs.get(id) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Job not found: {}", id)
+        })),
+    }
+}
+
+// ============================================================================
+// Wait Handlers
+// ============================================================================
+
+fn wait_timeout(query: &WaitQuery, config: Option<web::Data<AppConfig>>) -> std::time::Duration {
+    let max_secs = config
+        .map(|config| config.server.max_wait_secs)
+        .unwrap_or_else(|| AppConfig::default().server.max_wait_secs);
+    query.timeout(max_secs)
+}
+
+fn wait_target<T: serde::de::DeserializeOwned>(query: &WaitQuery) -> Result<T, String> {
+    query
+        .target()?
+        .ok_or_else(|| "Query parameter 'for' is required".to_string())
+}
+
+/// Hold the request until the container reaches the state given by `for`
+pub async fn wait_container(
+    path: web::Path<String>,
+    query: web::Query<WaitQuery>,
+    config: Option<web::Data<AppConfig>>,
+    monitor: Option<web::Data<Arc<LxcMonitor>>>,
+) -> impl Responder {
+    let name = path.into_inner();
+    let target: ContainerStatus = match wait_target(&query) {
+        Ok(target) => target,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    let Some(monitor) = monitor else {
+        return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "Container state events are not available"
+        }));
+    };
+    if !ContainerManager::exists(&name) {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Container not found: {}", name)
+        }));
+    }
+
+    let timeout = wait_timeout(&query, config);
+    info!(
+        "Waiting up to {}s for container {} to be {:?}",
+        timeout.as_secs(),
+        name,
+        target
+    );
+    let events = monitor.subscribe();
+    // A container created moments ago may not have been seen by the monitor yet
+    let mut status = match monitor.state(&name) {
+        Some(status) => status,
+        None => match ContainerManager::status(&name).await {
+            Ok(status) => status,
+            Err(e) => {
+                error!("Failed to get container status: {}", e);
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": e.to_string()
+                }));
+            }
+        },
+    };
+    let reached = wait::until(events, timeout, || {
+        if let Some(current) = monitor.state(&name) {
+            status = current;
+        }
+        (status == target).then_some(())
+    })
+    .await;
+
+    match reached {
+        Some(()) => HttpResponse::Ok().json(serde_json::json!({
+            "name": name,
+            "status": status
+        })),
+        None => HttpResponse::RequestTimeout().json(serde_json::json!({
+            "error": format!("Timed out waiting for container {} to be {:?}", name, target),
+            "status": status
+        })),
+    }
+}
+
+/// Hold the request until the job finishes, or reaches the status given by
+/// `for`
+pub async fn wait_job(
+    path: web::Path<Uuid>,
+    query: web::Query<WaitQuery>,
+    config: Option<web::Data<AppConfig>>,
+    jobs: web::Data<Arc<JobManager>>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let target: Option<JobStatus> = match query.target() {
+        Ok(target) => target,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+
+    let events = jobs.subscribe();
+    let Some(mut job) = jobs.get(id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Job not found: {}", id)
+        }));
+    };
+    let timeout = wait_timeout(&query, config);
+    let reached = wait::until(events, timeout, || {
+        if let Some(current) = jobs.get(id) {
+            job = current;
+        }
+        let reached = match target {
+            Some(ref target) => job.status == *target,
+            None => !matches!(job.status, JobStatus::Pending | JobStatus::Running),
+        };
+        reached.then_some(())
+    })
+    .await;
+
+    match reached {
+        Some(()) => HttpResponse::Ok().json(job),
+        None => HttpResponse::RequestTimeout().json(serde_json::json!({
+            "error": format!("Timed out waiting for job {}", id),
+            "status": job.status
+        })),
+    }
+}
+
+/// Hold the request until the cluster node reaches the status given by `for`
+pub async fn wait_node(
+    path: web::Path<Uuid>,
+    query: web::Query<WaitQuery>,
+    config: Option<web::Data<AppConfig>>,
+    membership: Option<web::Data<Arc<RwLock<MembershipManager>>>>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let target: NodeStatus = match wait_target(&query) {
+        Ok(target) => target,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    let Some(membership) = membership else {
+        return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "Cluster membership is not available"
+        }));
+    };
+
+    let (events, node) = {
+        let membership = membership.read().unwrap();
+        (membership.subscribe(), membership.get_node(&id).cloned())
+    };
+    let Some(mut node) = node else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Node not found: {}", id)
+        }));
+    };
+    let timeout = wait_timeout(&query, config);
+    let reached = wait::until(events, timeout, || {
+        if let Some(current) = membership.read().unwrap().get_node(&id) {
+            node = current.clone();
+        }
+        (node.status == target).then_some(())
+    })
+    .await;
+
+    match reached {
+        Some(()) => HttpResponse::Ok().json(node),
+        None => HttpResponse::RequestTimeout().json(serde_json::json!({
+            "error": format!("Timed out waiting for node {} to be {:?}", id, target),
+            "status": node.status
+        })),
+    }
+}
+
+// ============================================================================
+// System Orchestration Handlers
+// ============================================================================
+
+/// Host facts, including how this node can drive LXC and the network and
+/// the download settings in effect
+pub async fn system_info() -> impl Responder {
+    let report = privileges::check();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "hostname": gethostname::gethostname().to_string_lossy(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "privileges": {
+            "mode": report.lxc.mode,
+            "error": report.lxc.error,
+            "net_admin": report.net_admin,
+            "remediation": report.remediation(),
+        },
+        "cgroup_version": container_manager::config::LxcConfig::cgroup_version(),
+        "downloads": container_manager::downloads::current().redacted(),
+        // Containers here are in-memory records, not LXC containers
+        "fake_backend": container_manager::lxc::LxcCommand::is_fake()
+    }))
+}
+
+fn system_job_conflict(active: &crate::jobs::Job) -> HttpResponse {
+    HttpResponse::Conflict().json(serde_json::json!({
+        "error": format!("A {} job is already running", active.kind),
+        "job_id": active.id
+    }))
+}
+
+/// Stop all containers (reverse start order), flush state and optionally power off
+pub async fn system_shutdown(
+    user: AuthenticatedUser,
+    req: web::Json<ShutdownRequest>,
+    config: web::Data<AppConfig>,
+    jobs: web::Data<Arc<JobManager>>,
+    audit_logger: web::Data<Arc<AuditLogger>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+
+    let job = match jobs.create_exclusive(SHUTDOWN_JOB, SYSTEM_JOBS) {
+        Ok(job) => job,
+        Err(active) => return system_job_conflict(&active),
+    };
+    info!(
+        "System shutdown requested by {} (job {})",
+        user.username, job.id
+    );
+
+    let firewall_rules_path = config
+        .network
+        .firewall_enabled
+        .then(|| config.paths().firewall_rules);
+    jobs::spawn(
+        &job,
+        system::run_shutdown(
+            job.id,
+            req.into_inner(),
+            user,
+            jobs.get_ref().clone(),
+            audit_logger.get_ref().clone(),
+            firewall_rules_path,
+        ),
+    );
+
+    HttpResponse::Accepted().json(serde_json::json!({ "job_id": job.id }))
+}
+
+/// Start all autostart containers in start order
+pub async fn system_start_all(
+    user: AuthenticatedUser,
+    jobs: web::Data<Arc<JobManager>>,
+    audit_logger: web::Data<Arc<AuditLogger>>,
+    secret_store: Option<web::Data<Arc<SecretStore>>>,
+    config: web::Data<AppConfig>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+
+    let job = match jobs.create_exclusive(START_ALL_JOB, SYSTEM_JOBS) {
+        Ok(job) => job,
+        Err(active) => return system_job_conflict(&active),
+    };
+    info!(
+        "System start-all requested by {} (job {})",
+        user.username, job.id
+    );
+
+    jobs::spawn(
+        &job,
+        system::run_start_all(
+            job.id,
+            user,
+            jobs.get_ref().clone(),
+            audit_logger.get_ref().clone(),
+            secret_store.map(|store| store.get_ref().clone()),
+            config.network.dns_servers.clone(),
+        ),
+    );
+
+    HttpResponse::Accepted().json(serde_json::json!({ "job_id": job.id }))
+}
+
+/// Stop every running container, leaving the host up, e.g. before maintenance
+///
+/// Containers keep their autostart setting, so a later start-all brings
+/// them back.
+pub async fn system_stop_all_containers(
+    user: AuthenticatedUser,
+    req: web::Json<StopAllRequest>,
+    audit_logger: web::Data<Arc<AuditLogger>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    info!(
+        "Stop of all containers requested by {} (timeout {}s)",
+        user.username, req.timeout_seconds
+    );
+
+    match ContainerManager::stop_all(std::time::Duration::from_secs(req.timeout_seconds)).await {
+        Ok(summary) => {
+            let failures: Vec<String> = summary
+                .failed
+                .iter()
+                .map(|(name, e)| format!("{}: {}", name, e))
+                .collect();
+            system::audit(
+                &audit_logger,
+                &user,
+                AuditAction::SystemStopped,
+                &failures,
+                format!(
+                    "stop-all-containers: {} stopped, {} killed",
+                    summary.stopped.len(),
+                    summary.killed.len()
+                ),
+            );
+            HttpResponse::Ok().json(summary)
+        }
+        Err(e) => {
+            error!("Failed to stop all containers: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
+}
+
+// ============================================================================
+// Background Task Handlers
+// ============================================================================
+
+/// Registered background tasks with their schedule, last run and last error
+pub async fn list_tasks(
+    user: AuthenticatedUser,
+    tasks: Option<web::Data<Arc<TaskManager>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemRead) {
+        return e.error_response();
+    }
+    let tasks = tasks.map_or_else(Vec::new, |tasks| tasks.list());
+    HttpResponse::Ok().json(serde_json::json!({ "tasks": tasks }))
+}
+
+/// Run an interval task now rather than at its next tick
+pub async fn run_task(
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    tasks: Option<web::Data<Arc<TaskManager>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    let name = path.into_inner();
+
+    let result = match tasks {
+        Some(tasks) => tasks.trigger(&name),
+        None => Err(TaskError::NotFound(name.clone())),
+    };
+    match result {
+        Ok(task) => {
+            info!("Background task {} triggered by {}", name, user.username);
+            HttpResponse::Accepted().json(task)
+        }
+        Err(e @ TaskError::NotFound(_)) => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        Err(e @ TaskError::NotTriggerable(_)) => {
+            HttpResponse::Conflict().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        Err(e @ TaskError::ShuttingDown) => {
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": e.to_string() }))
+        }
+    }
+}
+
+// ============================================================================
+// Image Cache Handlers
+// ============================================================================
+
+/// Templates with the options each accepts in `template_options`
+/// `?node=<id>` or `?arch=<arch>`: the node an image or template is meant for
+#[derive(Debug, Default, Deserialize)]
+pub struct TargetArchQuery {
+    pub node: Option<Uuid>,
+    pub arch: Option<String>,
+}
+
+/// The architecture the query targets, if any; a node that has not reported
+/// one yet cannot be matched
+fn target_arch(
+    query: &TargetArchQuery,
+    membership: &RwLock<MembershipManager>,
+) -> Result<Option<String>, Box<HttpResponse>> {
+    if let Some(ref arch) = query.arch {
+        return Ok(Some(normalize_arch(arch)));
+    }
+    let Some(node_id) = query.node else {
+        return Ok(None);
+    };
+    let membership = membership.read().unwrap();
+    if node_id == membership.local_node_id() {
+        return Ok(Some(LxcConfig::arch()));
+    }
+    match membership.get_node(&node_id) {
+        Some(node) => match node.resources.arch {
+            Some(ref arch) => Ok(Some(normalize_arch(arch))),
+            None => Err(Box::new(HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("Node {} has not reported its architecture yet", node_id)
+            })))),
+        },
+        None => Err(Box::new(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Node not found: {}", node_id)
+        })))),
+    }
+}
+
+/// Templates build for the host they run on unless given an `arch` option,
+/// so the target architecture is returned alongside them
+pub async fn list_templates(
+    query: web::Query<TargetArchQuery>,
+    membership: web::Data<Arc<RwLock<MembershipManager>>>,
+) -> impl Responder {
+    let arch = match target_arch(&query, &membership) {
+        Ok(arch) => arch.unwrap_or_else(LxcConfig::arch),
+        Err(response) => return *response,
+    };
+    let templates: Vec<_> = models::validate::TEMPLATE_OPTIONS
+        .iter()
+        .map(|(name, options)| serde_json::json!({ "name": name, "options": options }))
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({ "templates": templates, "arch": arch }))
+}
+
+pub async fn list_images(
+    query: web::Query<TargetArchQuery>,
+    image_cache: web::Data<Arc<ImageCache>>,
+    membership: web::Data<Arc<RwLock<MembershipManager>>>,
+) -> impl Responder {
+    let arch = match target_arch(&query, &membership) {
+        Ok(arch) => arch,
+        Err(response) => return *response,
+    };
+    match image_cache.list() {
+        Ok(mut images) => {
+            if let Some(ref arch) = arch {
+                images.retain(|image| normalize_arch(&image.arch) == *arch);
+            }
+            let total_bytes: u64 = images.iter().map(|image| image.size_bytes).sum();
+            HttpResponse::Ok().json(serde_json::json!({
+                "images": images,
+                "total_bytes": total_bytes
+            }))
+        }
+        Err(e) => {
+            error!("Failed to list cached images: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
+}
+
+pub async fn delete_image(
+    user: AuthenticatedUser,
+    http: HttpRequest,
+    path: web::Path<String>,
+    image_cache: web::Data<Arc<ImageCache>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemWrite) {
+        return e.error_response();
+    }
+    let id = path.into_inner();
+    info!("{} deleting cached image: {}", user.username, id);
+
+    match image_cache.remove(&id) {
+        Ok(_) => {
+            if let Some(audit_logger) = audit_logger {
+                if let Ok(log) = AuditLogger::builder()
+                    .actor(&user)
+                    .action(AuditAction::ImageDeleted)
+                    .resource_type("image".to_string())
+                    .resource_id(id.clone())
+                    .result(AuditResult::Success)
+                    .request(&http)
+                    .build()
+                {
+                    audit_logger.log_entry(log);
+                }
+            }
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": format!("Image {} deleted", id)
+            }))
+        }
+        Err(ContainerError::ImageNotFound(id)) => {
+            HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("Image not found: {}", id)
+            }))
+        }
+        Err(ContainerError::InvalidConfig(e)) => {
+            HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))
+        }
+        Err(e) => {
+            error!("Failed to delete cached image: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
+}
+
+/// Download an image into the cache ahead of container creation
+///
+/// A cached image is answered immediately; otherwise the image is checked
+/// against the server's index and pulled in a background job.
+pub async fn cache_template(
+    user: AuthenticatedUser,
+    http: HttpRequest,
+    req: web::Json<ImageSpec>,
+    image_cache: web::Data<Arc<ImageCache>>,
+    jobs: web::Data<Arc<JobManager>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemWrite) {
+        return e.error_response();
+    }
+    let image = req.into_inner();
+    if let Err(errors) = image.validate() {
+        return validation_error_response(errors);
+    }
+    let id = ImageCache::image_id(&image);
+    let cache = image_cache.get_ref().clone();
+
+    if cache.is_cached(&image) {
+        info!("Image {} is already cached", id);
+        if let Err(e) = cache.record_use(&image) {
+            warn!("Failed to record image cache use: {}", e);
+        }
+        return HttpResponse::Ok().json(serde_json::json!({
+            "image": id,
+            "cached": true
+        }));
+    }
+
+    match ContainerManager::ensure_image_available(&image, Some(&cache)).await {
+        Ok(()) => {}
+        Err(ContainerError::ImageNotFound(id)) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Image not available: {}", id)
+            }))
+        }
+        Err(e @ ContainerError::DownloadFailed { .. }) => {
+            error!("Failed to fetch image index: {}", e);
+            return HttpResponse::BadGateway().json(serde_json::json!({ "error": e.to_string() }));
+        }
+        Err(e) => {
+            error!("Failed to fetch image index: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }));
+        }
+    }
+
+    let job = jobs.create("image-pull");
+    info!("{} pulling image {} (job {})", user.username, id, job.id);
+    if let Some(audit_logger) = audit_logger {
+        if let Ok(log) = AuditLogger::builder()
+            .actor(&user)
+            .action(AuditAction::ImagePulled)
+            .resource_type("image".to_string())
+            .resource_id(id.clone())
+            .result(AuditResult::Success)
+            .request(&http)
+            .details(format!("Pull started as job {}", job.id))
+            .build()
+        {
+            audit_logger.log_entry(log);
+        }
+    }
+    let image_job = job.clone();
+    let jobs = jobs.get_ref().clone();
+    let image_id = id.clone();
+    jobs::spawn(&image_job, async move {
+        let result = match ContainerManager::pull_image(&image, &cache).await {
+            Ok(_) => cache.evict(),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(_) => jobs.succeed(job.id, serde_json::json!({ "image": image_id })),
+            Err(e) => {
+                error!("Image pull failed: {}", e);
+                jobs.fail(job.id, e.to_string(), None)
+            }
+        };
+    });
+
+    HttpResponse::Accepted().json(serde_json::json!({
+        "image": id,
+        "cached": false,
+        "job_id": job.id
+    }))
+}
+
+pub async fn list_storage_pools(
+    pool_usage: Option<web::Data<Arc<PoolUsageMonitor>>>,
+) -> impl Responder {
+    info!("Listing storage pools");
+
+    let pools = pool_usage.map_or_else(Vec::new, |monitor| monitor.pools());
+    HttpResponse::Ok().json(StoragePoolListResponse { pools })
+}
+
+/// A configured pool with its latest usage and alert level
+pub async fn get_storage_pool(
+    path: web::Path<String>,
+    pool_usage: Option<web::Data<Arc<PoolUsageMonitor>>>,
+) -> impl Responder {
+    let name = path.into_inner();
+    info!("Getting storage pool: {}", name);
+
+    let found = pool_usage.and_then(|monitor| Some((monitor.pool(&name)?, monitor.get(&name)?)));
+    match found {
+        Some((pool, usage)) => HttpResponse::Ok().json(serde_json::json!({
+            "pool": pool,
+            "usage": usage,
+        })),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Storage pool not found: {}", name)
+        })),
+    }
+}
+
+/// Mark a pool active, read-only or in maintenance
+///
+/// Read-only pools keep serving and deleting what they hold but take no new
+/// volumes or root filesystems; maintenance also takes the pool out of health
+/// alerts and scheduling. The state survives restarts.
+pub async fn set_storage_pool_state(
+    http: HttpRequest,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    req: web::Json<SetPoolStateRequest>,
+    pool_usage: Option<web::Data<Arc<PoolUsageMonitor>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::StorageWrite) {
+        return e.error_response();
+    }
+    let name = path.into_inner();
+
+    let Some(monitor) = pool_usage else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": PoolStateError::NotFound(name).to_string()
+        }));
+    };
+    let previous = match monitor.set_state(&name, req.state) {
+        Ok(previous) => previous,
+        Err(e @ PoolStateError::NotFound(_)) => {
+            return HttpResponse::NotFound().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        Err(e) => {
+            error!("Failed to set state of storage pool {}: {}", name, e);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({ "error": e.to_string() }));
+        }
+    };
+
+    info!(
+        "Storage pool {} state {:?} -> {:?} by {}",
+        name, previous, req.state, user.username
+    );
+    if previous != req.state {
+        if let Some(audit_logger) = audit_logger {
+            if let Ok(log) = AuditLogger::builder()
+                .actor(&user)
+                .action(AuditAction::StoragePoolStateChanged)
+                .resource_type("storage_pool".to_string())
+                .resource_id(name.clone())
+                .result(AuditResult::Success)
+                .request(&http)
+                .details(format!("State {:?} -> {:?}", previous, req.state))
+                .build()
+            {
+                audit_logger.log_entry(log);
+            }
+        }
+    }
+
+    match monitor.pool(&name) {
+        Some(pool) => HttpResponse::Ok().json(pool),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": PoolStateError::NotFound(name).to_string()
+        })),
+    }
+}
+
+pub async fn create_storage_pool(req: web::Json<CreateStoragePoolRequest>) -> impl Responder {
+    info!("Creating storage pool: {}", req.name);
+
+    if let Err(errors) = req.validate() {
+        return validation_error_response(errors);
+    }
+    match ::storage::create_pool(&req).await {
+        Ok(pool) => HttpResponse::Created().json(pool),
+        Err(StorageError::InvalidRequest(message)) => {
+            HttpResponse::BadRequest().json(serde_json::json!({ "error": message }))
+        }
+        Err(e) => {
+            error!("Failed to create storage pool: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
+}
+
+/// Check that an NFS export or CIFS share can be used, without creating a pool
+///
+/// An unreachable server is reported with `success: false` and a 200; only
+/// malformed requests are errors.
+pub async fn test_storage_connection(
+    user: AuthenticatedUser,
+    req: web::Json<StorageConnectionTestRequest>,
+) -> impl Responder {
+    // The probe reaches out to whatever host is named, so it is no more open
+    // than adding the pool would be
+    if let Err(e) = user.require(Permission::StorageWrite) {
+        return e.error_response();
+    }
+    if let Err(errors) = req.validate() {
+        return validation_error_response(errors);
+    }
+    match ::storage::test_connection(&req).await {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(StorageError::InvalidRequest(message)) => {
+            HttpResponse::BadRequest().json(serde_json::json!({ "error": message }))
+        }
+        Err(e) => {
+            error!("Failed to test storage connection: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NetworkInterfacesQuery {
+    /// List `lo` too
+    #[serde(default)]
+    pub include_loopback: bool,
+}
+
+pub async fn list_network_interfaces(query: web::Query<NetworkInterfacesQuery>) -> impl Responder {
+    info!("Listing network interfaces");
+
+    match InterfaceManager::list(query.include_loopback).await {
+        Ok(interfaces) => HttpResponse::Ok().json(NetworkListResponse { interfaces }),
+        Err(e) => {
+            error!("Failed to list network interfaces: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Failed to list network interfaces: {}", e)
+            }))
+        }
+    }
+}
+
+pub async fn list_bridges() -> impl Responder {
+    info!("Listing bridges");
+
+    match BridgeManager::list().await {
+        Ok(bridge_names) => {
+            // In production, get full bridge details
+            HttpResponse::Ok().json(serde_json::json!({
+                "bridges": bridge_names
+            }))
+        }
+        Err(e) => {
+            error!("Failed to list bridges: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("{}", e)
+            }))
+        }
+    }
+}
+
+/// Bridges with their ports, leases and nat rules, plus IPAM usage
+///
+/// Always 200; sections that could not be gathered are null and explained
+/// under `errors`.
+pub async fn get_network_overview(
+    config: Option<web::Data<AppConfig>>,
+    ipam: Option<web::Data<Arc<Ipam>>>,
+) -> impl Responder {
+    info!("Building network overview");
+
+    let bridge_state = config.map(|config| config.paths().bridge_state);
+    let ipam = ipam.map(|ipam| ipam.get_ref().clone());
+    HttpResponse::Ok().json(network_overview::gather(ipam, bridge_state).await)
+}
+
+pub async fn create_bridge(
+    req: web::Json<CreateBridgeRequest>,
+    config: Option<web::Data<AppConfig>>,
+) -> impl Responder {
+    info!("Creating bridge: {}", req.name);
+
+    if let Err(errors) = req.validate() {
+        return validation_error_response(errors);
+    }
+
+    let request = req.into_inner();
+    match BridgeManager::create(request.clone()).await {
+        Ok(bridge) => {
+            // Remembered so a start with `?fix=true` can recreate it
+            if let Some(config) = config {
+                let path = config.paths().bridge_state;
+                if let Err(e) = BridgeManager::record_desired(&path, &request) {
+                    warn!("Failed to record bridge {}: {}", request.name, e);
+                }
+            }
+            HttpResponse::Created().json(bridge)
+        }
+        Err(NetworkError::InvalidRequest(message)) => {
+            HttpResponse::BadRequest().json(serde_json::json!({ "error": message }))
+        }
+        Err(NetworkError::BridgeExists(name)) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Bridge already exists: {}", name)
+        })),
+        Err(NetworkError::PermissionDenied(reason)) => {
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": reason
+            }))
+        }
+        Err(e) => {
+            error!("Failed to create bridge: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("{}", e)
+            }))
+        }
+    }
+}
+
+// ============================================================================
+// Container Snapshot Handlers
+// ============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSnapshotRequest {
+    pub name: Option<String>,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestoreSnapshotRequest {
+    pub snapshot_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CloneFromSnapshotRequest {
+    pub snapshot_name: String,
+    pub new_container_name: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListSnapshotsQuery {
+    /// Recompute every snapshot size instead of returning cached values
+    #[serde(default)]
+    pub refresh_sizes: bool,
+}
+
+/// List all snapshots for a container
+pub async fn list_snapshots(
+    path: web::Path<String>,
+    query: web::Query<ListSnapshotsQuery>,
+) -> impl Responder {
+    let container_name = path.into_inner();
+    info!("Listing snapshots for container: {}", container_name);
+
+    match SnapshotManager::list(&container_name, query.refresh_sizes).await {
+        Ok(snapshots) => HttpResponse::Ok().json(serde_json::json!({
+            "container": container_name,
+            "snapshots": snapshots
+        })),
+        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Container not found: {}", name)
+        })),
+        Err(e) => {
+            error!("Failed to list snapshots: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
+}
+
+/// Create a snapshot of a container
+pub async fn create_snapshot(
+    path: web::Path<String>,
+    req: web::Json<CreateSnapshotRequest>,
+) -> impl Responder {
+    let container_name = path.into_inner();
+    info!("Creating snapshot for container: {}", container_name);
+
+    match SnapshotManager::create(&container_name, req.name.clone(), req.comment.clone()).await {
+        Ok(snapshot) => HttpResponse::Created().json(serde_json::json!({
+            "message": "Snapshot created successfully",
+            "snapshot": snapshot
+        })),
+        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Container not found: {}", name)
+        })),
+        Err(e @ ContainerError::QueueTimeout { .. }) => {
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        Err(e) => {
+            error!("Failed to create snapshot: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
+}
+
+/// Snapshot several containers at once; failures are reported per container
+pub async fn batch_snapshot(
+    user: AuthenticatedUser,
+    req: web::Json<BatchSnapshotRequest>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::ContainerSnapshot) {
+        return e.error_response();
+    }
+    let snapshot_name = match req.snapshot_name() {
+        Ok(name) => name,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    info!(
+        "User '{}' creating snapshot '{}' of {} container(s)",
+        user.username,
+        snapshot_name,
+        req.containers.len()
+    );
+
+    let results = snapshot_batch::run(&req.containers, &snapshot_name, req.quiesce).await;
+    let failed = results.iter().filter(|result| !result.ok).count();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "snapshot_name": snapshot_name,
+        "succeeded": results.len() - failed,
+        "failed": failed,
+        "results": results
+    }))
+}
+
+/// Restore a container from a snapshot
+pub async fn restore_snapshot(
+    path: web::Path<String>,
+    req: web::Json<RestoreSnapshotRequest>,
+) -> impl Responder {
+    let container_name = path.into_inner();
+    info!(
+        "Restoring container '{}' from snapshot '{}'",
+        container_name, req.snapshot_name
+    );
+
+    match SnapshotManager::restore(&container_name, &req.snapshot_name).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "message": format!(
+                "Container '{}' restored from snapshot '{}'",
+                container_name, req.snapshot_name
+            )
+        })),
+        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Container not found: {}", name)
+        })),
+        Err(e @ ContainerError::QueueTimeout { .. }) => {
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        Err(e) => {
+            error!("Failed to restore snapshot: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
+}
+
+/// Delete a snapshot
+pub async fn delete_snapshot(path: web::Path<(String, String)>) -> impl Responder {
+    let (container_name, snapshot_name) = path.into_inner();
+    info!(
+        "Deleting snapshot '{}' for container '{}'",
+        snapshot_name, container_name
+    );
+
+    match SnapshotManager::delete(&container_name, &snapshot_name).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "message": format!("Snapshot '{}' deleted", snapshot_name)
+        })),
+        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Container not found: {}", name)
+        })),
+        Err(e) => {
+            error!("Failed to delete snapshot: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
+}
+
+/// Check a snapshot against the checksum recorded when it was taken
+///
+/// A corrupted snapshot is reported with 409 so callers relying on the
+/// status code don't restore from it by accident.
+pub async fn verify_snapshot(path: web::Path<(String, String)>) -> impl Responder {
+    let (container_name, snapshot_name) = path.into_inner();
+
+    match SnapshotManager::verify(&container_name, &snapshot_name).await {
+        Ok(verification) if verification.status == IntegrityStatus::Corrupted => {
+            HttpResponse::Conflict().json(verification)
+        }
+        Ok(verification) => HttpResponse::Ok().json(verification),
+        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Snapshot or container not found: {}", name)
+        })),
+        Err(e) => {
+            error!("Failed to verify snapshot: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
+}
+
+/// Clone a container from a snapshot
+pub async fn clone_from_snapshot(
+    path: web::Path<String>,
+    req: web::Json<CloneFromSnapshotRequest>,
+) -> impl Responder {
+    let container_name = path.into_inner();
+    info!(
+        "Cloning container '{}' from snapshot '{}' to '{}'",
+        container_name, req.snapshot_name, req.new_container_name
+    );
+
+    match SnapshotManager::clone(&container_name, &req.snapshot_name, &req.new_container_name).await
+    {
+        Ok(_) => HttpResponse::Created().json(serde_json::json!({
+            "message": format!(
+                "Container '{}' cloned from snapshot '{}' to '{}'",
+                container_name, req.snapshot_name, req.new_container_name
+            )
+        })),
+        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Container not found: {}", name)
+        })),
+        Err(ContainerError::AlreadyExists(name)) => {
+            HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("Container already exists: {}", name)
+            }))
+        }
+        Err(e @ ContainerError::QueueTimeout { .. }) => {
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        Err(e @ ContainerError::Unsupported(_)) => {
+            HttpResponse::NotImplemented().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        Err(e) => {
+            error!("Failed to clone from snapshot: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }))
+        }
+    }
+}
+
+// ============================================================================
+// User Management Handlers (RBAC)
+// ============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUserRequest {
+    pub username: String,
+    pub email: Option<String>,
+    pub role: crate::rbac::Role,
+    pub password: Option<String>,
+    #[serde(default)]
+    pub kind: UserKind,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUserRequest {
+    pub email: Option<String>,
+    pub role: Option<crate::rbac::Role>,
+    pub enabled: Option<bool>,
+    pub password: Option<String>,
+    /// Required when users without `SystemAdmin` change their own password
+    pub current_password: Option<String>,
+}
+
+/// List all users
+pub async fn list_users(
+    user: AuthenticatedUser,
+    user_store: actix_web::web::Data<std::sync::Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemRead) {
+        return e.error_response();
+    }
+    info!("Listing users");
+
+    let store = user_store.lock().unwrap();
+    let users = store.list_users();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "users": users
+    }))
+}
+
+/// Get a specific user
+pub async fn get_user(
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    user_store: actix_web::web::Data<std::sync::Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemRead) {
+        return e.error_response();
+    }
+    let username = path.into_inner();
+    info!("Getting user: {}", username);
+
+    let store = user_store.lock().unwrap();
+    match store.get_user(&username) {
+        Some(user) => HttpResponse::Ok().json(user),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("User not found: {}", username)
+        })),
+    }
+}
+
+/// Create a new user (admin only)
+pub async fn create_user(
+    caller: AuthenticatedUser,
+    http: HttpRequest,
+    req: web::Json<CreateUserRequest>,
+    user_store: actix_web::web::Data<std::sync::Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    if let Err(e) = caller.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    info!("{} creating user: {}", caller.username, req.username);
+
+    if req.kind == UserKind::Service && req.password.is_some() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Service accounts cannot have a password; issue a service token instead"
+        }));
+    }
+
+    let mut user = crate::rbac::User {
+        id: Uuid::new_v4(),
+        username: req.username.clone(),
+        email: req.email.clone(),
+        role: req.role.clone(),
+        custom_permissions: vec![],
+        enabled: true,
+        kind: req.kind,
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+        password_hash: None,
+        login: crate::rbac::LoginState::default(),
+        totp: None,
+    };
+    if let Some(password) = &req.password {
+        user.set_password(password);
+    }
+
+    let mut store = user_store.lock().unwrap();
+    if store.get_user(&req.username).is_some() {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("User already exists: {}", req.username)
+        }));
+    }
+
+    if let Err(e) = store.add_user(user.clone()) {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e }));
+    }
+    audit_user_change(
+        audit_logger.as_ref(),
+        &caller,
+        &http,
+        AuditAction::UserCreated,
+        &user.username,
+        format!("Created {:?} user with role {:?}", user.kind, user.role),
+    );
+
+    HttpResponse::Created().json(serde_json::json!({
+        "message": "User created successfully",
+        "user": user
+    }))
+}
+
+/// Update a user
+///
+/// Admins may change anything. Everyone else may only change their own email
+/// and, given the current one, their own password.
+pub async fn update_user(
+    caller: AuthenticatedUser,
+    path: web::Path<String>,
+    req: web::Json<UpdateUserRequest>,
+    user_store: actix_web::web::Data<std::sync::Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    let username = path.into_inner();
+    info!("{} updating user: {}", caller.username, username);
+
+    let is_self = caller.username == username;
+    let is_admin = caller.require(Permission::SystemAdmin).is_ok();
+    if !is_admin && (!is_self || req.role.is_some() || req.enabled.is_some()) {
+        return AuthError::Forbidden(Permission::SystemAdmin).error_response();
+    }
+    // Hashing is slow on purpose; keep it outside the store lock
+    if req.password.is_some() && !is_admin {
+        let account = user_store.lock().unwrap().get_user(&username).cloned();
+        let confirmed = account
+            .zip(req.current_password.as_deref())
+            .is_some_and(|(account, current)| account.verify_password(current));
+        if !confirmed {
+            return HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "The current password is required to change it"
+            }));
+        }
+    }
+
+    let mut store = user_store.lock().unwrap();
+    let mut user = match store.get_user(&username) {
+        Some(u) => u.clone(),
+        None => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("User not found: {}", username)
+            }))
+        }
+    };
+    let before = user.clone();
+
+    // A service account must not be able to widen its own access
+    if req.role.is_some() && is_self && user.kind == UserKind::Service {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Service accounts cannot change their own role"
+        }));
+    }
+
+    if let Some(email) = &req.email {
+        user.email = Some(email.clone());
+    }
+    if let Some(role) = &req.role {
+        user.role = role.clone();
+    }
+    if let Some(enabled) = req.enabled {
+        user.enabled = enabled;
+    }
+    if let Some(password) = &req.password {
+        if user.kind == UserKind::Service {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Service accounts cannot have a password; issue a service token instead"
+            }));
+        }
+        user.set_password(password);
+    }
+    user.updated_at = chrono::Utc::now();
+
+    match store.update_user(&username, user.clone()) {
+        Ok(_) => {
+            if let Some(audit_logger) = audit_logger {
+                // Password hashes are never serialized; only the fact of the change is kept
+                let mut diff = Diff::between(&before, &user);
+                if req.password.is_some() {
+                    diff = diff.redacted_change("password");
+                }
+                audit_changes(
+                    &audit_logger,
+                    AuditLogger::builder()
+                        .actor(&caller)
+                        .action(AuditAction::UserUpdated)
+                        .resource_type("user".to_string())
+                        .resource_id(username.clone()),
+                    &diff,
+                );
+            }
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "User updated successfully",
+                "user": user
+            }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e
+        })),
+    }
+}
+
+/// Delete a user (admin only)
+pub async fn delete_user_handler(
+    caller: AuthenticatedUser,
+    http: HttpRequest,
+    path: web::Path<String>,
+    user_store: actix_web::web::Data<std::sync::Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    if let Err(e) = caller.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    let username = path.into_inner();
+    info!("{} deleting user: {}", caller.username, username);
+
+    let result = user_store.lock().unwrap().delete_user(&username);
+    match result {
+        Ok(_) => {
+            audit_user_change(
+                audit_logger.as_ref(),
+                &caller,
+                &http,
+                AuditAction::UserDeleted,
+                &username,
+                "User deleted".to_string(),
+            );
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": format!("User '{}' deleted successfully", username)
+            }))
+        }
+        Err(e @ "User not found") => HttpResponse::NotFound().json(serde_json::json!({"error": e})),
+        Err(e @ "Failed to save users") => {
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": e}))
+        }
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({"error": e})),
+    }
+}
+
+fn audit_user_change(
+    audit_logger: Option<&web::Data<Arc<AuditLogger>>>,
+    actor: &AuthenticatedUser,
+    http: &HttpRequest,
+    action: AuditAction,
+    username: &str,
+    details: String,
+) {
+    let Some(audit_logger) = audit_logger else {
+        return;
+    };
+    if let Ok(log) = AuditLogger::builder()
+        .actor(actor)
+        .action(action)
+        .resource_type("user".to_string())
+        .resource_id(username.to_string())
+        .result(AuditResult::Success)
+        .request(http)
+        .details(details)
+        .build()
+    {
+        audit_logger.log_entry(log);
+    }
+}
+
+/// Clear a user's failed login count and lock (admin only)
+pub async fn unlock_user(
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    user_store: web::Data<Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    let username = path.into_inner();
+
+    let unlocked = {
+        let mut store = user_store.lock().unwrap();
+        let Some(mut target) = store.get_user(&username).cloned() else {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("User not found: {}", username)
+            }));
+        };
+        target.unlock();
+        if let Err(e) = store.update_user(&username, target.clone()) {
+            return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e }));
+        }
+        target
+    };
+    info!("{} unlocked user {}", user.username, username);
+
+    if let Some(audit_logger) = audit_logger {
+        if let Ok(log) = AuditLogger::builder()
+            .actor(&user)
+            .action(AuditAction::UserUpdated)
+            .resource_type("user".to_string())
+            .resource_id(username)
+            .result(AuditResult::Success)
+            .details("Account unlocked".to_string())
+            .build()
+        {
+            audit_logger.log_entry(log);
+        }
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": "User unlocked",
+        "user": unlocked
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateServiceTokenRequest {
+    /// Label for the token, e.g. the pipeline that will use it
+    pub name: String,
+    pub ttl_days: Option<u32>,
+}
+
+/// Mint a service token for a service account (admin only)
+///
+/// The token is only returned here; listings show its id and metadata.
+pub async fn create_service_token(
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    req: web::Json<CreateServiceTokenRequest>,
+    config: web::Data<AppConfig>,
+    user_store: web::Data<Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+    tokens: Option<web::Data<Arc<ServiceTokenStore>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    let username = path.into_inner();
+    let (Some(tokens), Some(secret)) = (tokens, config.security.jwt_secret.as_deref()) else {
+        return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "Service tokens are not configured"
+        }));
+    };
+
+    let ttl_days = req.ttl_days.unwrap_or(DEFAULT_SERVICE_TOKEN_TTL_DAYS);
+    if !(1..=MAX_SERVICE_TOKEN_TTL_DAYS).contains(&ttl_days) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("ttl_days must be between 1 and {}", MAX_SERVICE_TOKEN_TTL_DAYS)
+        }));
+    }
+
+    let account = user_store.lock().unwrap().get_user(&username).cloned();
+    let account = match account {
+        Some(account) if account.kind == UserKind::Service => account,
+        Some(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("{} is not a service account", username)
+            }))
+        }
+        None => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("User not found: {}", username)
+            }))
+        }
+    };
+
+    let (token, record) = match tokens.issue(&username, account.id, &req.name, ttl_days, secret) {
+        Ok(issued) => issued,
+        Err(e) => {
+            error!("Failed to issue service token: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to issue token"
+            }));
+        }
+    };
+    info!(
+        "{} issued service token {} for {}",
+        user.username, record.id, username
+    );
+    audit_service_token(
+        audit_logger.as_ref(),
+        &user,
+        AuditAction::ServiceTokenCreated,
+        &record.id,
+        format!("Issued token '{}' for {}", record.name, username),
+    );
+
+    HttpResponse::Created().json(serde_json::json!({
+        "token": token,
+        "service_token": record
+    }))
+}
+
+/// List a service account's tokens without their secrets (admin only)
+pub async fn list_service_tokens(
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    tokens: Option<web::Data<Arc<ServiceTokenStore>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    let username = path.into_inner();
+    let tokens = tokens
+        .map(|store| store.list(&username))
+        .unwrap_or_default();
+
+    HttpResponse::Ok().json(serde_json::json!({ "tokens": tokens }))
+}
+
+/// Revoke a single service token (admin only)
+pub async fn revoke_service_token(
+    user: AuthenticatedUser,
+    path: web::Path<(String, Uuid)>,
+    tokens: Option<web::Data<Arc<ServiceTokenStore>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    let (username, token_id) = path.into_inner();
+
+    let revoked = match tokens.map(|store| store.revoke(&username, token_id)) {
+        Some(Ok(revoked)) => revoked,
+        Some(Err(e @ ServiceTokenError::Save(_))) => {
+            error!("Failed to revoke service token {}: {}", token_id, e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            }));
+        }
+        _ => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("Service token not found: {}", token_id)
+            }))
+        }
+    };
+    info!(
+        "{} revoked service token {} of {}",
+        user.username, token_id, username
+    );
+    audit_service_token(
+        audit_logger.as_ref(),
+        &user,
+        AuditAction::ServiceTokenRevoked,
+        &token_id,
+        format!("Revoked token '{}' of {}", revoked.name, username),
+    );
+
+    HttpResponse::Ok().json(serde_json::json!({ "service_token": revoked }))
+}
+
+fn audit_service_token(
+    audit_logger: Option<&web::Data<Arc<AuditLogger>>>,
+    actor: &AuthenticatedUser,
+    action: AuditAction,
+    token_id: &Uuid,
+    details: String,
+) {
+    let Some(audit_logger) = audit_logger else {
+        return;
+    };
+    if let Ok(log) = AuditLogger::builder()
+        .actor(actor)
+        .action(action)
+        .resource_type("service_token".to_string())
+        .resource_id(token_id.to_string())
+        .result(AuditResult::Success)
+        .details(details)
+        .build()
+    {
+        audit_logger.log_entry(log);
+    }
+}
+
+// ============================================================================
+// Authentication Handlers
+// ============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+    /// TOTP or recovery code, required once the user has enrolled
+    #[serde(default)]
+    pub totp_code: Option<String>,
+}
+
+/// Exchange a username and password (and second factor, if enrolled) for
+/// a JWT
+///
+/// Unknown users, disabled users and wrong passwords get the same 401 so the
+/// endpoint does not reveal which accounts exist. Repeated failures, including
+/// wrong second factors, lock the account per `security.login_lockout`;
+/// attempts from one client address are limited per `security.rate_limit`.
+#[allow(clippy::too_many_arguments)]
+pub async fn login(
+    http: HttpRequest,
+    req: web::Json<LoginRequest>,
+    config: web::Data<AppConfig>,
+    user_store: web::Data<Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+    secret_store: Option<web::Data<Arc<SecretStore>>>,
+    setup: Option<web::Data<Arc<AdminSetup>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+    rate_limiter: Option<web::Data<Arc<RateLimiter>>>,
+) -> impl Responder {
+    if setup.is_some_and(|setup| setup.is_pending()) {
+        return AuthError::SetupRequired.error_response();
+    }
+    let Some(secret) = config.security.jwt_secret.as_deref() else {
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Authentication is not configured"
+        }));
+    };
+    let invalid = || {
+        HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Invalid username or password"
+        }))
+    };
+
+    let audit = audit_logger.as_ref();
+    if let (Some(limiter), Some(client)) = (&rate_limiter, client_ip(&http)) {
+        if !limiter.check(client) {
+            audit_login(audit, &http, &req.username, false, "rate limited");
+            return HttpResponse::TooManyRequests().json(serde_json::json!({
+                "error": "Too many login attempts, try again later"
+            }));
+        }
+    }
+    let now = chrono::Utc::now();
+    let candidate = {
+        let store = user_store.lock().unwrap();
+        // Service accounts authenticate with service tokens only
+        store
+            .get_user(&req.username)
+            .filter(|user| user.enabled && user.kind == UserKind::Human)
+            .cloned()
+    };
+    let Some(candidate) = candidate else {
+        audit_login(audit, &http, &req.username, false, "unknown user");
+        return invalid();
+    };
+    if candidate.is_locked(now) {
+        audit_login(audit, &http, &req.username, false, "account locked");
+        return HttpResponse::build(actix_web::http::StatusCode::LOCKED).json(serde_json::json!({
+            "error": "Account is locked",
+            "locked_until": candidate.login.locked_until
+        }));
+    }
+
+    // Hashing is slow on purpose; keep it outside the store lock
+    let password_ok = candidate.verify_password(&req.password);
+
+    // The seed is only decrypted once the password is known to be right
+    let totp_secret = match candidate.totp {
+        Some(ref totp) if password_ok && totp.confirmed => {
+            if req.totp_code.is_none() {
+                audit_login(audit, &http, &req.username, false, "second factor missing");
+                return HttpResponse::Unauthorized().json(serde_json::json!({
+                    "error": "A TOTP or recovery code is required",
+                    "totp_required": true
+                }));
+            }
+            let store = secret_store.as_ref().map(|store| store.as_ref().as_ref());
+            match open_totp_secret(store, &candidate.id, &totp.sealed_secret) {
+                Ok(secret) => Some(secret),
+                Err(e) => return totp_secret_error_response(e),
+            }
+        }
+        _ => None,
+    };
+
+    let outcome = {
+        let mut store = user_store.lock().unwrap();
+        let Some(mut user) = store.get_user(&req.username).cloned() else {
+            return invalid();
+        };
+        let outcome = match (password_ok, totp_secret, user.totp.as_mut()) {
+            (false, _, _) => Err("wrong password"),
+            (true, Some(secret), Some(totp)) => {
+                let code = req.totp_code.as_deref().unwrap_or_default();
+                if totp.verify_code(&secret, code, now.timestamp()) {
+                    Ok("password and TOTP login")
+                } else if totp.use_recovery_code(code) {
+                    Ok("password and recovery code login")
+                } else {
+                    Err("wrong second factor")
+                }
+            }
+            (true, _, _) => Ok("password login"),
+        };
+        if outcome.is_err() {
+            user.record_login_failure(&config.security.login_lockout, now);
+            if user.login.locked {
+                warn!(
+                    "Locking user {} after {} failed logins",
+                    user.username, user.login.failed_login_attempts
+                );
+            }
+        } else {
+            user.record_login_success(now);
+        }
+        // Counted failures and used codes must outlive a restart
+        if let Err(e) = store.update_user(&req.username, user) {
+            return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e }));
+        }
+        outcome
+    };
+    let method = match outcome {
+        Ok(method) => method,
+        Err(reason) => {
+            audit_login(audit, &http, &req.username, false, reason);
+            return invalid();
+        }
+    };
+
+    let expires_at =
+        now + chrono::Duration::seconds(config.security.jwt_expiry.unwrap_or(86400) as i64);
+    let claims = Claims {
+        sub: req.username.clone(),
+        exp: expires_at.timestamp() as usize,
+        jti: None,
+    };
+    let token = match jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(secret.as_bytes()),
+    ) {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Failed to sign token: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to sign token"
+            }));
+        }
+    };
+    audit_login(audit, &http, &req.username, true, method);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "token": token,
+        "expires_at": expires_at
+    }))
+}
+
+fn open_totp_secret(
+    secret_store: Option<&SecretStore>,
+    user_id: &Uuid,
+    sealed: &[u8],
+) -> Result<Vec<u8>, SecretError> {
+    secret_store
+        .ok_or(SecretError::NotConfigured)?
+        .unseal(&totp::seal_context(user_id), sealed)
+}
+
+fn totp_secret_error_response(e: SecretError) -> HttpResponse {
+    match e {
+        SecretError::NotConfigured => HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "Two-factor authentication requires a secrets master key"
         })),
-        Err(e) => {
-            error!("Failed to restore snapshot: {}", e);
+        e => {
+            error!("Failed to decrypt TOTP secret: {}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": e.to_string()
+                "error": "Failed to decrypt TOTP secret"
             }))
         }
     }
 }
 
-/// Delete a snapshot
-pub async fn delete_snapshot(path: web::Path<(String, String)>) -> impl Responder {
-    let (container_name, snapshot_name) = path.into_inner();
-    info!(
-        "Deleting snapshot '{}' for container '{}'",
-        snapshot_name, container_name
-    );
+/// Start TOTP enrollment for the calling user
+///
+/// The seed and recovery codes are only ever returned here. Login does not
+/// ask for a code until the enrollment is confirmed via `/auth/totp/verify`;
+/// enrolling again before that replaces the pending seed.
+pub async fn enroll_totp(
+    user: AuthenticatedUser,
+    user_store: web::Data<Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+    secret_store: Option<web::Data<Arc<SecretStore>>>,
+) -> impl Responder {
+    let Some(secret_store) = secret_store else {
+        return totp_secret_error_response(SecretError::NotConfigured);
+    };
 
-    match SnapshotManager::delete(&container_name, &snapshot_name).await {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": format!("Snapshot '{}' deleted", snapshot_name)
-        })),
-        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": format!("Container not found: {}", name)
-        })),
+    let mut store = user_store.lock().unwrap();
+    let Some(mut account) = store.get_user(&user.username).cloned() else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("User not found: {}", user.username)
+        }));
+    };
+    if account.kind != UserKind::Human {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Only interactive users can enroll in two-factor authentication"
+        }));
+    }
+    if account.totp_required() {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Two-factor authentication is already enabled; an admin must reset it first"
+        }));
+    }
+
+    let secret = totp::generate_secret();
+    let sealed_secret = match secret_store.seal(&totp::seal_context(&account.id), &secret) {
+        Ok(sealed) => sealed,
         Err(e) => {
-            error!("Failed to delete snapshot: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": e.to_string()
+            error!("Failed to encrypt TOTP secret: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to encrypt TOTP secret"
+            }));
+        }
+    };
+    let recovery_codes = totp::generate_recovery_codes();
+    account.totp = Some(TotpEnrollment {
+        sealed_secret,
+        confirmed: false,
+        recovery_code_hashes: recovery_codes
+            .iter()
+            .map(|code| totp::hash_recovery_code(code))
+            .collect(),
+        last_used_step: None,
+    });
+    if let Err(e) = store.update_user(&user.username, account) {
+        return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e }));
+    }
+    info!("User {} started TOTP enrollment", user.username);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "secret": totp::encode_secret(&secret),
+        "otpauth_uri": totp::otpauth_uri(&user.username, &secret),
+        "recovery_codes": recovery_codes
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+/// Confirm a pending TOTP enrollment with a code from the authenticator
+pub async fn verify_totp(
+    user: AuthenticatedUser,
+    req: web::Json<TotpCodeRequest>,
+    user_store: web::Data<Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+    secret_store: Option<web::Data<Arc<SecretStore>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    let pending = {
+        let store = user_store.lock().unwrap();
+        store
+            .get_user(&user.username)
+            .and_then(|account| Some((account.id, account.totp.clone()?)))
+    };
+    let (user_id, enrollment) = match pending {
+        Some((_, enrollment)) if enrollment.confirmed => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Two-factor authentication is already enabled"
             }))
         }
+        Some(pending) => pending,
+        None => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "No TOTP enrollment is pending"
+            }))
+        }
+    };
+    let store = secret_store.as_ref().map(|store| store.as_ref().as_ref());
+    let secret = match open_totp_secret(store, &user_id, &enrollment.sealed_secret) {
+        Ok(secret) => secret,
+        Err(e) => return totp_secret_error_response(e),
+    };
+
+    {
+        let mut store = user_store.lock().unwrap();
+        // Re-check under the lock; the enrollment may have been replaced
+        let Some(mut account) = store.get_user(&user.username).cloned() else {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "The TOTP enrollment changed; enroll again"
+            }));
+        };
+        let Some(totp) = account
+            .totp
+            .as_mut()
+            .filter(|totp| totp.sealed_secret == enrollment.sealed_secret)
+        else {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "The TOTP enrollment changed; enroll again"
+            }));
+        };
+        if !totp.verify_code(&secret, &req.code, chrono::Utc::now().timestamp()) {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid TOTP code"
+            }));
+        }
+        totp.confirmed = true;
+        if let Err(e) = store.update_user(&user.username, account) {
+            return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e }));
+        }
     }
+    info!("User {} enabled two-factor authentication", user.username);
+    audit_totp(audit_logger.as_ref(), &user, &user.username, "TOTP enabled");
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": "Two-factor authentication enabled"
+    }))
 }
 
-/// Clone a container from a snapshot
-pub async fn clone_from_snapshot(
+/// Remove a user's second factor so they can enroll again (admin only)
+pub async fn reset_totp(
+    user: AuthenticatedUser,
     path: web::Path<String>,
-    req: web::Json<CloneFromSnapshotRequest>,
+    user_store: web::Data<Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
 ) -> impl Responder {
-    let container_name = path.into_inner();
-    info!(
-        "Cloning container '{}' from snapshot '{}' to '{}'",
-        container_name, req.snapshot_name, req.new_container_name
-    );
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    let username = path.into_inner();
 
-    match SnapshotManager::clone(&container_name, &req.snapshot_name, &req.new_container_name).await
     {
-        Ok(_) => HttpResponse::Created().json(serde_json::json!({
-            "message": format!(
-                "Container '{}' cloned from snapshot '{}' to '{}'",
-                container_name, req.snapshot_name, req.new_container_name
-            )
-        })),
-        Err(ContainerError::NotFound(name)) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": format!("Container not found: {}", name)
-        })),
-        Err(ContainerError::AlreadyExists(name)) => {
-            HttpResponse::Conflict().json(serde_json::json!({
-                "error": format!("Container already exists: {}", name)
-            }))
-        }
-        Err(e) => {
-            error!("Failed to clone from snapshot: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": e.to_string()
-            }))
+        let mut store = user_store.lock().unwrap();
+        let Some(mut account) = store.get_user(&username).cloned() else {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("User not found: {}", username)
+            }));
+        };
+        account.totp = None;
+        if let Err(e) = store.update_user(&username, account) {
+            return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e }));
         }
     }
+    info!(
+        "{} reset two-factor authentication of {}",
+        user.username, username
+    );
+    audit_totp(audit_logger.as_ref(), &user, &username, "TOTP reset");
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": format!("Two-factor authentication of {} reset", username)
+    }))
 }
 
-// ============================================================================
-// User Management Handlers (RBAC)
-// ============================================================================
+fn audit_totp(
+    audit_logger: Option<&web::Data<Arc<AuditLogger>>>,
+    actor: &AuthenticatedUser,
+    username: &str,
+    details: &str,
+) {
+    let Some(audit_logger) = audit_logger else {
+        return;
+    };
+    if let Ok(log) = AuditLogger::builder()
+        .actor(actor)
+        .action(AuditAction::UserUpdated)
+        .resource_type("user".to_string())
+        .resource_id(username.to_string())
+        .result(AuditResult::Success)
+        .details(details.to_string())
+        .build()
+    {
+        audit_logger.log_entry(log);
+    }
+}
 
 #[derive(Debug, Deserialize)]
-pub struct CreateUserRequest {
+pub struct SetupRequest {
+    /// One-time token logged at startup or set in `ADMIN_SETUP_TOKEN`
+    pub token: String,
+    #[serde(default = "default_admin_username")]
     pub username: String,
-    pub email: Option<String>,
-    pub role: crate::rbac::Role,
+    pub password: String,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct UpdateUserRequest {
-    pub email: Option<String>,
-    pub role: Option<crate::rbac::Role>,
-    pub enabled: Option<bool>,
+fn default_admin_username() -> String {
+    "admin".to_string()
 }
 
-/// List all users
-pub async fn list_users(
-    user_store: actix_web::web::Data<std::sync::Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+/// Create the initial admin on first boot; see [`crate::setup`]
+///
+/// Only possible while no admin exists. Afterwards the endpoint answers 409.
+pub async fn setup_admin(
+    http: HttpRequest,
+    req: web::Json<SetupRequest>,
+    setup: web::Data<Arc<AdminSetup>>,
+    user_store: web::Data<Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
 ) -> impl Responder {
-    info!("Listing users");
+    let admin = match setup.complete(&user_store, &req.token, &req.username, &req.password) {
+        Ok(admin) => admin,
+        Err(e) => {
+            warn!("Admin setup rejected: {}", e);
+            let body = serde_json::json!({ "error": e.to_string() });
+            return match e {
+                SetupError::AlreadyCompleted => HttpResponse::Conflict().json(body),
+                SetupError::InvalidToken => HttpResponse::Unauthorized().json(body),
+                SetupError::WeakPassword | SetupError::InvalidUsername => {
+                    HttpResponse::BadRequest().json(body)
+                }
+                SetupError::Save => HttpResponse::InternalServerError().json(body),
+            };
+        }
+    };
+    info!("Initial admin {} created, setup complete", admin.username);
 
-    let store = user_store.lock().unwrap();
-    let users = store.list_users();
+    if let Some(audit_logger) = audit_logger {
+        if let Ok(log) = AuditLogger::builder()
+            .user(admin.username.clone())
+            .request(&http)
+            .action(AuditAction::UserCreated)
+            .resource_type("user".to_string())
+            .resource_id(admin.username.clone())
+            .result(AuditResult::Success)
+            .details("Initial admin created by setup".to_string())
+            .build()
+        {
+            audit_logger.log_entry(log);
+        }
+    }
 
-    HttpResponse::Ok().json(serde_json::json!({
-        "users": users
+    HttpResponse::Created().json(serde_json::json!({
+        "message": "Setup complete",
+        "user": admin
     }))
 }
 
-/// Get a specific user
-pub async fn get_user(
-    path: web::Path<String>,
-    user_store: actix_web::web::Data<std::sync::Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
-) -> impl Responder {
-    let username = path.into_inner();
-    info!("Getting user: {}", username);
-
-    let store = user_store.lock().unwrap();
-    match store.get_user(&username) {
-        Some(user) => HttpResponse::Ok().json(user),
-        None => HttpResponse::NotFound().json(serde_json::json!({
-            "error": format!("User not found: {}", username)
-        })),
+fn audit_login(
+    audit_logger: Option<&web::Data<Arc<AuditLogger>>>,
+    http: &HttpRequest,
+    username: &str,
+    ok: bool,
+    details: &str,
+) {
+    let Some(audit_logger) = audit_logger else {
+        return;
+    };
+    if let Ok(log) = AuditLogger::builder()
+        .user(username.to_string())
+        .request(http)
+        .action(AuditAction::UserLogin)
+        .resource_type("user".to_string())
+        .resource_id(username.to_string())
+        .result(if ok {
+            AuditResult::Success
+        } else {
+            AuditResult::Failure(details.to_string())
+        })
+        .details(details.to_string())
+        .build()
+    {
+        audit_logger.log_entry(log);
     }
 }
 
-/// Create a new user
-pub async fn create_user(
-    req: web::Json<CreateUserRequest>,
-    user_store: actix_web::web::Data<std::sync::Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+/// Configured and created API keys by fingerprint with their last use
+/// (admin only)
+pub async fn list_api_keys(
+    user: AuthenticatedUser,
+    config: web::Data<AppConfig>,
+    api_keys: Option<web::Data<Arc<ApiKeyStore>>>,
 ) -> impl Responder {
-    info!("Creating user: {}", req.username);
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    let keys = api_keys
+        .map(|store| store.list(&config.security.api_keys))
+        .unwrap_or_default();
 
-    let user = crate::rbac::User {
-        id: Uuid::new_v4(),
-        username: req.username.clone(),
-        email: req.email.clone(),
-        role: req.role.clone(),
-        custom_permissions: vec![],
-        enabled: true,
-        created_at: chrono::Utc::now(),
-        updated_at: chrono::Utc::now(),
-    };
+    HttpResponse::Ok().json(serde_json::json!({ "api_keys": keys }))
+}
 
-    let mut store = user_store.lock().unwrap();
-    if store.get_user(&req.username).is_some() {
-        return HttpResponse::Conflict().json(serde_json::json!({
-            "error": format!("User already exists: {}", req.username)
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub permissions: Vec<Permission>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Create an API key granting `permissions` (admin only)
+///
+/// The key is only returned here; the server keeps its hash.
+pub async fn create_api_key(
+    user: AuthenticatedUser,
+    req: web::Json<CreateApiKeyRequest>,
+    config: web::Data<AppConfig>,
+    api_keys: Option<web::Data<Arc<ApiKeyStore>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    let Some(api_keys) = api_keys else {
+        return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "API keys are not configured"
+        }));
+    };
+    let req = req.into_inner();
+    if req.name.trim().is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "name is required"
+        }));
+    }
+    if req.permissions.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "An API key must grant at least one permission"
+        }));
+    }
+    if req.expires_at.is_some_and(|at| at <= chrono::Utc::now()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "expires_at must be in the future"
         }));
     }
 
-    store.add_user(user.clone());
+    let (key, record) = match api_keys.create(
+        &req.name,
+        req.permissions,
+        req.expires_at,
+        &config.security.api_keys,
+    ) {
+        Ok(created) => created,
+        Err(e @ ApiKeyError::NameTaken(_)) => {
+            return HttpResponse::Conflict().json(serde_json::json!({ "error": e.to_string() }))
+        }
+        Err(e) => {
+            error!("Failed to create API key: {}", e);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({ "error": e.to_string() }));
+        }
+    };
+    info!(
+        "{} created API key {} ({})",
+        user.username, record.name, record.id
+    );
+    audit_api_key(
+        audit_logger.as_ref(),
+        &user,
+        AuditAction::ApiKeyCreated,
+        &record.id,
+        format!(
+            "Created API key '{}' with {:?}",
+            record.name, record.permissions
+        ),
+    );
 
     HttpResponse::Created().json(serde_json::json!({
-        "message": "User created successfully",
-        "user": user
+        "key": key,
+        "api_key": ApiKeySummary::from(record)
     }))
 }
 
-/// Update a user
-pub async fn update_user(
-    path: web::Path<String>,
-    req: web::Json<UpdateUserRequest>,
-    user_store: actix_web::web::Data<std::sync::Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
+/// Revoke an API key created through the API (admin only); configured keys
+/// are disabled in the config file
+pub async fn revoke_api_key(
+    user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    api_keys: Option<web::Data<Arc<ApiKeyStore>>>,
+    audit_logger: Option<web::Data<Arc<AuditLogger>>>,
 ) -> impl Responder {
-    let username = path.into_inner();
-    info!("Updating user: {}", username);
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+    let id = path.into_inner();
 
-    let mut store = user_store.lock().unwrap();
-    let mut user = match store.get_user(&username) {
-        Some(u) => u.clone(),
-        None => {
+    let revoked = match api_keys.map(|store| store.revoke(id)) {
+        Some(Ok(revoked)) => revoked,
+        Some(Err(e @ ApiKeyError::Save(_))) => {
+            error!("Failed to revoke API key {}: {}", id, e);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({ "error": e.to_string() }));
+        }
+        _ => {
             return HttpResponse::NotFound().json(serde_json::json!({
-                "error": format!("User not found: {}", username)
+                "error": format!("API key not found: {}", id)
             }))
         }
     };
+    info!(
+        "{} revoked API key {} ({})",
+        user.username, revoked.name, id
+    );
+    audit_api_key(
+        audit_logger.as_ref(),
+        &user,
+        AuditAction::ApiKeyRevoked,
+        &id,
+        format!("Revoked API key '{}'", revoked.name),
+    );
 
-    if let Some(email) = &req.email {
-        user.email = Some(email.clone());
-    }
-    if let Some(role) = &req.role {
-        user.role = role.clone();
-    }
-    if let Some(enabled) = req.enabled {
-        user.enabled = enabled;
-    }
-    user.updated_at = chrono::Utc::now();
-
-    match store.update_user(&username, user.clone()) {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": "User updated successfully",
-            "user": user
-        })),
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": e
-        })),
-    }
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": "API key revoked",
+        "id": id
+    }))
 }
 
-/// Delete a user
-pub async fn delete_user_handler(
-    path: web::Path<String>,
-    user_store: actix_web::web::Data<std::sync::Arc<std::sync::Mutex<crate::rbac::UserStore>>>,
-) -> impl Responder {
-    let username = path.into_inner();
-    info!("Deleting user: {}", username);
-
-    let mut store = user_store.lock().unwrap();
-    match store.delete_user(&username) {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": format!("User '{}' deleted successfully", username)
-        })),
-        Err(e) => {
-            if e == "User not found" {
-                HttpResponse::NotFound().json(serde_json::json!({"error": e}))
-            } else {
-                HttpResponse::BadRequest().json(serde_json::json!({"error": e}))
-            }
-        }
+fn audit_api_key(
+    audit_logger: Option<&web::Data<Arc<AuditLogger>>>,
+    actor: &AuthenticatedUser,
+    action: AuditAction,
+    key_id: &Uuid,
+    details: String,
+) {
+    let Some(audit_logger) = audit_logger else {
+        return;
+    };
+    if let Ok(log) = AuditLogger::builder()
+        .actor(actor)
+        .action(action)
+        .resource_type("api_key".to_string())
+        .resource_id(key_id.to_string())
+        .result(AuditResult::Success)
+        .details(details)
+        .build()
+    {
+        audit_logger.log_entry(log);
     }
 }
 
@@ -581,25 +4418,129 @@ pub async fn delete_user_handler(
 pub struct AuditLogQuery {
     pub user: Option<String>,
     pub resource_type: Option<String>,
+    /// Only entries written while serving the request with this
+    /// `X-Correlation-ID`
+    pub correlation_id: Option<Uuid>,
     pub limit: Option<usize>,
+    /// Return `details` holding JSON, such as update diffs, as JSON
+    #[serde(default)]
+    pub parse_details: bool,
 }
 
 /// Get audit logs
+///
+/// Admins see every entry. Other users see only their own trail (see
+/// [`crate::audit::AuditLog::concerns`]) unless `audit.self_service` is off,
+/// and may not filter by another user. The full trail needs `SystemAdmin`
+/// rather than `SystemRead` because entries name other users and the
+/// addresses they connected from, which viewers and operators, who hold
+/// `SystemRead`, have no business seeing.
 pub async fn get_audit_logs(
+    user: AuthenticatedUser,
     query: web::Query<AuditLogQuery>,
+    config: web::Data<AppConfig>,
     audit_logger: actix_web::web::Data<std::sync::Arc<crate::audit::AuditLogger>>,
 ) -> impl Responder {
     info!("Getting audit logs");
 
-    let logs = audit_logger.get_logs(
-        query.user.clone(),
-        None,
-        query.resource_type.clone(),
-        query.limit,
-    );
+    let own_trail = user.require(Permission::SystemAdmin).is_err();
+    if own_trail
+        && (!config.audit.self_service || query.user.as_ref().is_some_and(|u| *u != user.username))
+    {
+        return AuthError::Forbidden(Permission::SystemAdmin).error_response();
+    }
+
+    let (total, logs) = if own_trail {
+        let mut logs: Vec<_> = audit_logger
+            .get_logs(
+                query.user.clone(),
+                None,
+                query.resource_type.clone(),
+                query.correlation_id,
+                None,
+            )
+            .into_iter()
+            .filter(|log| log.concerns(&user.username))
+            .collect();
+        if let Some(limit) = query.limit {
+            logs.truncate(limit);
+        }
+        (audit_logger.count_concerning(&user.username), logs)
+    } else {
+        let logs = audit_logger.get_logs(
+            query.user.clone(),
+            None,
+            query.resource_type.clone(),
+            query.correlation_id,
+            query.limit,
+        );
+        (audit_logger.count(), logs)
+    };
+
+    if query.parse_details {
+        let logs: Vec<serde_json::Value> = logs
+            .into_iter()
+            .map(|log| {
+                let details = log.details.as_deref().map(audit_diff::parse_details);
+                let mut log = serde_json::to_value(log).unwrap_or_default();
+                if let Some(details) = details {
+                    log["details"] = details;
+                }
+                log
+            })
+            .collect();
+        return HttpResponse::Ok().json(serde_json::json!({
+            "total": total,
+            "logs": logs
+        }));
+    }
 
     HttpResponse::Ok().json(serde_json::json!({
-        "total": audit_logger.count(),
+        "total": total,
         "logs": logs
     }))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct AuditPurgeQuery {
+    /// Entries older than this RFC 3339 timestamp are removed
+    pub before: chrono::DateTime<chrono::Utc>,
+}
+
+/// Manually purge old entries from the in-memory audit log (admin only);
+/// the purge itself is audited
+pub async fn purge_audit_logs(
+    user: AuthenticatedUser,
+    query: web::Query<AuditPurgeQuery>,
+    audit_logger: web::Data<Arc<AuditLogger>>,
+) -> impl Responder {
+    if let Err(e) = user.require(Permission::SystemAdmin) {
+        return e.error_response();
+    }
+
+    let removed = audit_logger.purge_before(query.before);
+    info!(
+        "{} purged {} audit entries before {}",
+        user.username, removed, query.before
+    );
+
+    if let Ok(log) = AuditLogger::builder()
+        .actor(&user)
+        .action(AuditAction::AuditLogsPurged)
+        .resource_type("audit".to_string())
+        .result(AuditResult::Success)
+        .details(format!(
+            "Removed {} entries before {}",
+            removed,
+            query.before.to_rfc3339()
+        ))
+        .build()
+    {
+        audit_logger.log_entry(log);
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "removed": removed,
+        "total": audit_logger.count()
+    }))
+}
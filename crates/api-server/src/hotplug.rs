@@ -0,0 +1,116 @@
+/// Attaching and detaching container network interfaces
+///
+/// The interface is always added to or removed from the container's config,
+/// so the change survives restarts. A running container is changed live as
+/// well: the live step runs first and is undone if the config cannot be
+/// written, so a failed request leaves neither an orphaned veth nor a config
+/// that disagrees with the container.
+use thiserror::Error;
+use tracing::{info, warn};
+
+use container_manager::{ContainerError, ContainerManager};
+use models::{ContainerNetworkInterface, ContainerStatus};
+use network::{FirewallManager, NetworkError, VethManager};
+
+use crate::egress;
+
+#[derive(Debug, Error)]
+pub enum HotplugError {
+    #[error("Container error: {0}")]
+    Container(#[from] ContainerError),
+
+    #[error("Network error: {0}")]
+    Network(#[from] NetworkError),
+}
+
+/// Add `interface` to the container; returns whether it was plugged in live
+pub async fn attach(
+    container: &str,
+    interface: &ContainerNetworkInterface,
+) -> Result<bool, HotplugError> {
+    let (_, config) = ContainerManager::effective_config(container).await?;
+    if config
+        .network_interfaces
+        .iter()
+        .any(|existing| existing.name == interface.name)
+    {
+        return Err(ContainerError::InterfaceExists(interface.name.clone()).into());
+    }
+
+    let live = match ContainerManager::status(container).await? {
+        ContainerStatus::Running => {
+            let pid = ContainerManager::init_pid(container).await?;
+            let host = VethManager::attach(pid, interface).await?;
+            // The new link must not bypass the container's egress policy
+            if let Some(ref policy) = config.egress_policy {
+                if let Err(e) = FirewallManager::apply_egress(&host, policy).await {
+                    undo_attach(container, &host, true).await;
+                    return Err(e.into());
+                }
+                egress::record(container, &host);
+            }
+            Some(host)
+        }
+        _ => None,
+    };
+
+    if let Err(e) = ContainerManager::add_interface(container, interface).await {
+        if let Some(ref host) = live {
+            undo_attach(container, host, config.egress_policy.is_some()).await;
+        }
+        return Err(e.into());
+    }
+    info!(
+        "Attached interface {} to container {}{}",
+        interface.name,
+        container,
+        if live.is_some() { " (live)" } else { "" }
+    );
+    Ok(live.is_some())
+}
+
+/// Remove the interface called `name` from the container, returning it
+pub async fn detach(
+    container: &str,
+    name: &str,
+) -> Result<ContainerNetworkInterface, HotplugError> {
+    let (_, config) = ContainerManager::effective_config(container).await?;
+    if !config
+        .network_interfaces
+        .iter()
+        .any(|existing| existing.name == name)
+    {
+        return Err(ContainerError::InterfaceNotFound(name.to_string()).into());
+    }
+
+    if ContainerManager::status(container).await? == ContainerStatus::Running {
+        let pid = ContainerManager::init_pid(container).await?;
+        let host = VethManager::detach(pid, name).await?;
+        if let (Some(host), Some(_)) = (host, &config.egress_policy) {
+            match FirewallManager::remove_egress(&host).await {
+                Ok(()) => egress::forget(container, &host),
+                Err(e) => warn!("Failed to remove egress policy from {}: {}", host, e),
+            }
+        }
+    }
+
+    let removed = ContainerManager::remove_interface(container, name).await?;
+    info!("Detached interface {} from container {}", name, container);
+    Ok(removed)
+}
+
+/// Remove a live interface again after a later step failed
+async fn undo_attach(container: &str, host: &str, egress: bool) {
+    if egress {
+        match FirewallManager::remove_egress(host).await {
+            Ok(()) => egress::forget(container, host),
+            Err(e) => warn!("Failed to remove egress policy from {}: {}", host, e),
+        }
+    }
+    if let Err(e) = VethManager::delete(host).await {
+        warn!(
+            "Failed to remove veth {} after a failed attach: {}",
+            host, e
+        );
+    }
+}
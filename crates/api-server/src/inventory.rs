@@ -0,0 +1,458 @@
+/// Container inventory report across the cluster
+///
+/// Every member lists its own containers with their template and limits;
+/// the leader asks the other members for their part over the HTTP API,
+/// forwarding the caller's credentials as the cluster-wide listing does, and
+/// adds a summary of what is committed on each node against the capacity it
+/// last reported on its heartbeats. A member that cannot be asked is named
+/// in the summary instead of failing the report. Containers carry no labels,
+/// so `label:` filters select containers by the labels of their node.
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use container_manager::ContainerManager;
+use models::{ContainerEventKind, ContainerStatus, Node};
+
+use crate::cluster_view::{CLIENT, NODE_REQUEST_TIMEOUT};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    Csv,
+    Markdown,
+}
+
+impl ReportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+        }
+    }
+}
+
+/// One container in the report
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InventoryRow {
+    pub name: String,
+    pub node_id: Uuid,
+    pub node: Option<String>,
+    pub status: ContainerStatus,
+    pub template: String,
+    /// CPU cores
+    pub cpu_limit: Option<u32>,
+    /// Bytes
+    pub memory_limit: Option<u64>,
+    /// Unknown for containers created before lifecycle events were kept
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A node's part of the report, as members return it to the leader
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NodeInventory {
+    pub containers: Vec<InventoryRow>,
+}
+
+/// What is committed on a node against its capacity
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct NodeTotals {
+    pub node_id: Uuid,
+    pub node: Option<String>,
+    pub containers: usize,
+    pub running: usize,
+    /// Sum of the containers' CPU limits, in cores
+    pub cpu_committed: u64,
+    pub cpu_cores: Option<u32>,
+    /// Sum of the containers' memory limits, in bytes
+    pub memory_committed: u64,
+    pub memory_total: Option<u64>,
+    /// Why the node's containers are missing from the report
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InventoryReport {
+    pub generated_at: DateTime<Utc>,
+    pub containers: Vec<InventoryRow>,
+    pub nodes: Vec<NodeTotals>,
+}
+
+/// Parse `label:key=value[,label:key=value...]`; every label must match
+pub fn parse_filter(filter: &str) -> Result<Vec<(String, String)>, String> {
+    filter
+        .split(',')
+        .map(|term| {
+            let (key, value) = term
+                .trim()
+                .strip_prefix("label:")
+                .and_then(|label| label.split_once('='))
+                .ok_or_else(|| format!("Invalid filter {:?}: expected label:key=value", term))?;
+            if key.is_empty() {
+                return Err(format!("Invalid filter {:?}: empty label key", term));
+            }
+            Ok((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Whether `node` carries every label in `filter`
+pub fn matches(node: Option<&Node>, filter: &[(String, String)]) -> bool {
+    filter
+        .iter()
+        .all(|(key, value)| node.and_then(|n| n.labels.get(key)) == Some(value))
+}
+
+/// This node's containers as report rows
+pub async fn local_rows(node_id: Uuid, node: Option<String>) -> Result<Vec<InventoryRow>, String> {
+    let names = ContainerManager::list().await.map_err(|e| e.to_string())?;
+    let mut rows = Vec::with_capacity(names.len());
+    for name in names {
+        let container = ContainerManager::get(&name)
+            .await
+            .map_err(|e| e.to_string())?;
+        let created_at = ContainerManager::events(&name)
+            .iter()
+            .find(|event| event.kind == ContainerEventKind::Created)
+            .map(|event| event.timestamp);
+        rows.push(InventoryRow {
+            name,
+            node_id,
+            node: node.clone(),
+            status: container.status,
+            template: container.template,
+            cpu_limit: container.config.cpu_limit,
+            memory_limit: container.config.memory_limit,
+            created_at,
+        });
+    }
+    Ok(rows)
+}
+
+/// Fetch a member's part of the report, forwarding the caller's credentials
+pub async fn remote_rows(
+    base_url: &str,
+    authorization: Option<&str>,
+) -> Result<Vec<InventoryRow>, String> {
+    let mut request = CLIENT.get(format!("{}/api/v1/reports/inventory?local=true", base_url));
+    if let Some(authorization) = authorization {
+        request = request.header("Authorization", authorization);
+    }
+
+    let response = request.send().await.map_err(|e| {
+        if e.is_timeout() {
+            format!("no response within {}s", NODE_REQUEST_TIMEOUT.as_secs())
+        } else {
+            e.to_string()
+        }
+    })?;
+    if !response.status().is_success() {
+        return Err(format!("node answered {}", response.status()));
+    }
+    response
+        .json::<NodeInventory>()
+        .await
+        .map(|inventory| inventory.containers)
+        .map_err(|e| e.to_string())
+}
+
+/// Put the per-node results together with the capacity of each node
+pub fn build(
+    results: Vec<(Uuid, Result<Vec<InventoryRow>, String>)>,
+    nodes: &HashMap<Uuid, Node>,
+) -> InventoryReport {
+    let mut containers = Vec::new();
+    let mut totals = Vec::new();
+    for (node_id, result) in results {
+        let node = nodes.get(&node_id);
+        let mut node_totals = NodeTotals {
+            node_id,
+            node: node.map(|n| n.name.clone()),
+            cpu_cores: node.map(|n| n.resources.cpu_cores),
+            memory_total: node.map(|n| n.resources.memory_total),
+            ..Default::default()
+        };
+        match result {
+            Ok(rows) => {
+                for row in &rows {
+                    node_totals.containers += 1;
+                    if row.status == ContainerStatus::Running {
+                        node_totals.running += 1;
+                    }
+                    node_totals.cpu_committed += u64::from(row.cpu_limit.unwrap_or(0));
+                    node_totals.memory_committed += row.memory_limit.unwrap_or(0);
+                }
+                containers.extend(rows);
+            }
+            Err(e) => node_totals.error = Some(e),
+        }
+        totals.push(node_totals);
+    }
+    containers.sort_by(|a, b| (&a.node, &a.name).cmp(&(&b.node, &b.name)));
+
+    InventoryReport {
+        generated_at: Utc::now(),
+        containers,
+        nodes: totals,
+    }
+}
+
+const CONTAINER_COLUMNS: [&str; 8] = [
+    "name",
+    "node",
+    "status",
+    "template",
+    "cpu_limit",
+    "memory_limit",
+    "created_at",
+    "node_id",
+];
+
+const NODE_COLUMNS: [&str; 8] = [
+    "node",
+    "containers",
+    "running",
+    "cpu_committed",
+    "cpu_cores",
+    "memory_committed",
+    "memory_total",
+    "error",
+];
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn container_cells(row: &InventoryRow) -> [String; 8] {
+    [
+        row.name.clone(),
+        optional(row.node.as_ref()),
+        serde_json::to_value(&row.status)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default(),
+        row.template.clone(),
+        optional(row.cpu_limit),
+        optional(row.memory_limit),
+        optional(row.created_at.map(|at| at.to_rfc3339())),
+        row.node_id.to_string(),
+    ]
+}
+
+fn node_cells(totals: &NodeTotals) -> [String; 8] {
+    [
+        totals
+            .node
+            .clone()
+            .unwrap_or_else(|| totals.node_id.to_string()),
+        totals.containers.to_string(),
+        totals.running.to_string(),
+        totals.cpu_committed.to_string(),
+        optional(totals.cpu_cores),
+        totals.memory_committed.to_string(),
+        optional(totals.memory_total),
+        optional(totals.error.as_ref()),
+    ]
+}
+
+fn csv_line<S: AsRef<str>>(cells: &[S]) -> String {
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|cell| {
+            let cell = cell.as_ref();
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.to_string()
+            }
+        })
+        .collect();
+    format!("{}\n", cells.join(","))
+}
+
+fn markdown_line<S: AsRef<str>>(cells: &[S]) -> String {
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|cell| cell.as_ref().replace('|', "\\|").replace(['\n', '\r'], " "))
+        .collect();
+    format!("| {} |\n", cells.join(" | "))
+}
+
+/// The report as chunks to stream, a line each for the text formats
+///
+/// CSV holds two tables separated by a blank line: the containers, then the
+/// node totals.
+pub fn render(report: &InventoryReport, format: ReportFormat) -> Vec<String> {
+    let containers = report.containers.iter().map(container_cells);
+    let nodes = report.nodes.iter().map(node_cells);
+    let mut chunks = Vec::new();
+    match format {
+        ReportFormat::Json => chunks.push(serde_json::to_string(report).unwrap_or_default()),
+        ReportFormat::Csv => {
+            chunks.push(csv_line(&CONTAINER_COLUMNS));
+            chunks.extend(containers.map(|cells| csv_line(&cells)));
+            chunks.push("\n".to_string());
+            chunks.push(csv_line(&NODE_COLUMNS));
+            chunks.extend(nodes.map(|cells| csv_line(&cells)));
+        }
+        ReportFormat::Markdown => {
+            let separator = format!("|{}\n", "---|".repeat(8));
+            chunks.push(format!(
+                "# Container inventory\n\nGenerated {}\n\n## Containers\n\n",
+                report.generated_at.to_rfc3339()
+            ));
+            chunks.push(markdown_line(&CONTAINER_COLUMNS));
+            chunks.push(separator.clone());
+            chunks.extend(containers.map(|cells| markdown_line(&cells)));
+            chunks.push("\n## Nodes\n\n".to_string());
+            chunks.push(markdown_line(&NODE_COLUMNS));
+            chunks.push(separator);
+            chunks.extend(nodes.map(|cells| markdown_line(&cells)));
+        }
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use models::{NodeResources, NodeStatus};
+
+    fn node(name: &str, labels: &[(&str, &str)]) -> Node {
+        Node {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            address: "10.0.0.1".to_string(),
+            port: 8080,
+            status: NodeStatus::Online,
+            cluster_id: None,
+            resources: NodeResources {
+                cpu_cores: 4,
+                memory_total: 8 << 30,
+                memory_used: 0,
+                disk_total: 0,
+                disk_used: 0,
+                exclusive_cpus_allocated: 0,
+                arch: None,
+            },
+            joined_at: Utc::now(),
+            last_seen: Utc::now(),
+            labels: labels
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            cordoned: false,
+            latency: None,
+            storage_pools: vec![],
+        }
+    }
+
+    fn row(name: &str, node: &Node, status: ContainerStatus) -> InventoryRow {
+        InventoryRow {
+            name: name.to_string(),
+            node_id: node.id,
+            node: Some(node.name.clone()),
+            status,
+            template: "debian".to_string(),
+            cpu_limit: Some(2),
+            memory_limit: Some(1 << 30),
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn test_filter_matches_node_labels() {
+        let filter = parse_filter("label:env=prod, label:zone=a").unwrap();
+        assert_eq!(
+            filter,
+            vec![
+                ("env".to_string(), "prod".to_string()),
+                ("zone".to_string(), "a".to_string())
+            ]
+        );
+        assert!(matches(
+            Some(&node("a", &[("env", "prod"), ("zone", "a")])),
+            &filter
+        ));
+        assert!(!matches(Some(&node("b", &[("env", "prod")])), &filter));
+        assert!(!matches(None, &filter));
+        assert!(matches(None, &[]));
+
+        assert!(parse_filter("env=prod").is_err());
+        assert!(parse_filter("label:env").is_err());
+        assert!(parse_filter("label:=prod").is_err());
+    }
+
+    #[test]
+    fn test_totals_per_node() {
+        let (a, b) = (node("a", &[]), node("b", &[]));
+        let nodes = HashMap::from([(a.id, a.clone()), (b.id, b.clone())]);
+        let report = build(
+            vec![
+                (
+                    a.id,
+                    Ok(vec![
+                        row("web", &a, ContainerStatus::Running),
+                        row("db", &a, ContainerStatus::Stopped),
+                    ]),
+                ),
+                (b.id, Err("node answered 503".to_string())),
+            ],
+            &nodes,
+        );
+
+        assert_eq!(report.containers.len(), 2);
+        assert_eq!(report.containers[0].name, "db");
+        let totals = &report.nodes[0];
+        assert_eq!((totals.containers, totals.running), (2, 1));
+        assert_eq!(totals.cpu_committed, 4);
+        assert_eq!(totals.cpu_cores, Some(4));
+        assert_eq!(totals.memory_committed, 2 << 30);
+        assert_eq!(report.nodes[1].error.as_deref(), Some("node answered 503"));
+        assert_eq!(report.nodes[1].containers, 0);
+    }
+
+    #[test]
+    fn test_csv_quotes_fields() {
+        let a = node("a", &[]);
+        let mut web = row("web", &a, ContainerStatus::Running);
+        web.template = "download, \"alpine\"".to_string();
+        let report = build(
+            vec![(a.id, Ok(vec![web]))],
+            &HashMap::from([(a.id, a.clone())]),
+        );
+
+        let csv = render(&report, ReportFormat::Csv).concat();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], CONTAINER_COLUMNS.join(","));
+        assert_eq!(
+            lines[1],
+            format!(
+                "web,a,running,\"download, \"\"alpine\"\"\",2,1073741824,,{}",
+                a.id
+            )
+        );
+        assert_eq!(lines[2], "");
+        assert_eq!(lines[3], NODE_COLUMNS.join(","));
+        assert_eq!(lines[4], "a,1,1,2,4,1073741824,8589934592,");
+    }
+
+    #[test]
+    fn test_markdown_escapes_pipes() {
+        let a = node("a|b", &[]);
+        let report = build(
+            vec![(a.id, Ok(vec![row("web", &a, ContainerStatus::Running)]))],
+            &HashMap::from([(a.id, a.clone())]),
+        );
+
+        let markdown = render(&report, ReportFormat::Markdown).concat();
+        assert!(markdown.contains("| web | a\\|b | running | debian |"));
+        assert!(markdown.contains("## Nodes"));
+    }
+}
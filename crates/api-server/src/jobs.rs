@@ -0,0 +1,285 @@
+/// In-memory record of long-running and background operations
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::request_tracing;
+
+/// Finished jobs buffered per subscriber before a slow one starts missing them
+const EVENT_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: Uuid,
+    pub kind: String,
+    pub status: JobStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Operation-specific output, kept on failure too for post-hoc debugging
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    /// Per-item progress for jobs that work through a list
+    #[serde(default)]
+    pub steps: Vec<JobStep>,
+    /// Correlation ID of the request that started the job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobStep {
+    pub name: String,
+    pub status: JobStatus,
+    pub message: Option<String>,
+}
+
+pub struct JobManager {
+    jobs: Mutex<HashMap<Uuid, Job>>,
+    max_jobs: usize,
+    finished: broadcast::Sender<Uuid>,
+}
+
+impl JobManager {
+    pub fn new(max_jobs: usize) -> Self {
+        Self {
+            jobs: Mutex::new(HashMap::new()),
+            max_jobs,
+            finished: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+
+    /// Register a new running job
+    pub fn create(&self, kind: &str) -> Job {
+        let mut jobs = self.jobs.lock().unwrap();
+        self.insert(&mut jobs, kind)
+    }
+
+    /// Register a new running job unless a job of one of the `exclusive_with`
+    /// kinds is still running, in which case that job is returned instead
+    pub fn create_exclusive(&self, kind: &str, exclusive_with: &[&str]) -> Result<Job, Box<Job>> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(active) = jobs
+            .values()
+            .find(|j| j.status == JobStatus::Running && exclusive_with.contains(&j.kind.as_str()))
+        {
+            return Err(Box::new(active.clone()));
+        }
+        Ok(self.insert(&mut jobs, kind))
+    }
+
+    fn insert(&self, jobs: &mut HashMap<Uuid, Job>, kind: &str) -> Job {
+        let now = Utc::now();
+        let job = Job {
+            id: Uuid::new_v4(),
+            kind: kind.to_string(),
+            status: JobStatus::Running,
+            created_at: now,
+            updated_at: now,
+            result: None,
+            error: None,
+            steps: Vec::new(),
+            correlation_id: request_tracing::current_correlation_id(),
+        };
+
+        // Drop the oldest finished job once over capacity
+        if jobs.len() >= self.max_jobs {
+            let oldest = jobs
+                .values()
+                .filter(|j| j.status != JobStatus::Running)
+                .min_by_key(|j| j.updated_at)
+                .map(|j| j.id);
+            if let Some(id) = oldest {
+                jobs.remove(&id);
+            }
+        }
+        jobs.insert(job.id, job.clone());
+        job
+    }
+
+    /// Declare the steps a job will work through, all pending
+    pub fn set_steps(&self, id: Uuid, names: Vec<String>) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.get_mut(&id) {
+            job.steps = names
+                .into_iter()
+                .map(|name| JobStep {
+                    name,
+                    status: JobStatus::Pending,
+                    message: None,
+                })
+                .collect();
+            job.updated_at = Utc::now();
+        }
+    }
+
+    /// Update the status of one step
+    pub fn update_step(&self, id: Uuid, name: &str, status: JobStatus, message: Option<String>) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.get_mut(&id) {
+            if let Some(step) = job.steps.iter_mut().find(|s| s.name == name) {
+                step.status = status;
+                step.message = message;
+            }
+            job.updated_at = Utc::now();
+        }
+    }
+
+    /// Mark a job as succeeded with its result
+    pub fn succeed(&self, id: Uuid, result: serde_json::Value) -> Option<Job> {
+        self.finish(id, JobStatus::Succeeded, Some(result), None)
+    }
+
+    /// Mark a job as failed, optionally keeping partial output
+    pub fn fail(&self, id: Uuid, error: String, result: Option<serde_json::Value>) -> Option<Job> {
+        self.finish(id, JobStatus::Failed, result, Some(error))
+    }
+
+    fn finish(
+        &self,
+        id: Uuid,
+        status: JobStatus,
+        result: Option<serde_json::Value>,
+        error: Option<String>,
+    ) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(&id)?;
+        job.status = status;
+        job.result = result;
+        job.error = error;
+        job.updated_at = Utc::now();
+        // Having no subscribers is not an error
+        let _ = self.finished.send(id);
+        Some(job.clone())
+    }
+
+    /// Receive the id of every job finishing from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Uuid> {
+        self.finished.subscribe()
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<Job> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+
+    /// All jobs, most recently created first
+    pub fn list(&self) -> Vec<Job> {
+        let mut jobs: Vec<Job> = self.jobs.lock().unwrap().values().cloned().collect();
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+        jobs
+    }
+}
+
+/// Run `task` for `job` in the background
+///
+/// The task keeps the correlation ID of the request that created the job,
+/// so audit entries it writes after the response was sent still belong to
+/// that request.
+pub fn spawn<F>(job: &Job, task: F)
+where
+    F: Future<Output = ()> + 'static,
+{
+    match job.correlation_id {
+        Some(id) => actix_rt::spawn(request_tracing::with_correlation_id(id, task)),
+        None => actix_rt::spawn(task),
+    };
+}
+
+impl Default for JobManager {
+    fn default() -> Self {
+        Self::new(1000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_lifecycle() {
+        let manager = JobManager::default();
+        let job = manager.create("placement");
+        assert_eq!(job.status, JobStatus::Running);
+
+        let job = manager
+            .fail(
+                job.id,
+                "no node".to_string(),
+                Some(serde_json::json!({"nodes": []})),
+            )
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Failed);
+        assert_eq!(
+            manager.get(job.id).unwrap().error.as_deref(),
+            Some("no node")
+        );
+        assert!(manager.get(job.id).unwrap().result.is_some());
+    }
+
+    #[test]
+    fn test_exclusive_jobs_refuse_while_active() {
+        let manager = JobManager::default();
+        let shutdown = manager
+            .create_exclusive("shutdown", &["shutdown", "start"])
+            .unwrap();
+
+        let active = manager
+            .create_exclusive("start", &["shutdown", "start"])
+            .unwrap_err();
+        assert_eq!(active.id, shutdown.id);
+
+        manager.succeed(shutdown.id, serde_json::Value::Null);
+        assert!(manager
+            .create_exclusive("start", &["shutdown", "start"])
+            .is_ok());
+    }
+
+    #[test]
+    fn test_step_progress() {
+        let manager = JobManager::default();
+        let job = manager.create("stop");
+        manager.set_steps(job.id, vec!["web".to_string(), "db".to_string()]);
+        manager.update_step(job.id, "db", JobStatus::Succeeded, None);
+
+        let steps = manager.get(job.id).unwrap().steps;
+        assert_eq!(steps[0].status, JobStatus::Pending);
+        assert_eq!(steps[1].status, JobStatus::Succeeded);
+    }
+
+    #[test]
+    fn test_oldest_finished_job_is_evicted() {
+        let manager = JobManager::new(2);
+        let first = manager.create("a");
+        manager.succeed(first.id, serde_json::Value::Null);
+        let running = manager.create("b");
+        let third = manager.create("c");
+
+        assert!(manager.get(first.id).is_none());
+        assert!(manager.get(running.id).is_some());
+        assert!(manager.get(third.id).is_some());
+        assert_eq!(manager.list().len(), 2);
+    }
+
+    #[test]
+    fn test_finished_jobs_are_published() {
+        let manager = JobManager::default();
+        let mut finished = manager.subscribe();
+        let job = manager.create("stop");
+        assert!(finished.try_recv().is_err());
+
+        manager.succeed(job.id, serde_json::Value::Null);
+        assert_eq!(finished.try_recv().unwrap(), job.id);
+    }
+}
@@ -0,0 +1,292 @@
+/// Tokens authorizing a node to join the cluster
+///
+/// Issued tokens are HS256 JWTs signed with a key derived from the configured
+/// JWT secret, so they cannot be confused with session tokens. Single-use
+/// tokens are remembered once redeemed until they would have expired anyway.
+///
+/// Besides those, the cluster has one long-lived join token, set in the
+/// config or generated at first start and kept in a file. Rotating it only
+/// affects future joins. Only its SHA-256 is held in memory, and candidates
+/// are compared in constant time.
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{decode, encode, errors::ErrorKind, Algorithm, DecodingKey, EncodingKey};
+use jsonwebtoken::{Header, Validation};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use thiserror::Error;
+use uuid::Uuid;
+
+const JOIN_TOKEN_AUDIENCE: &str = "cluster-join";
+
+/// Longest lifetime an admin may request for a join token
+pub const MAX_JOIN_TOKEN_TTL_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JoinTokenClaims {
+    pub jti: Uuid,
+    pub aud: String,
+    pub exp: i64,
+    pub single_use: bool,
+}
+
+/// What a joining node authenticated with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinCredential {
+    ClusterToken,
+    Issued(JoinTokenClaims),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum JoinTokenError {
+    #[error("A join token is required")]
+    Missing,
+
+    #[error("Join token is invalid")]
+    Invalid,
+
+    #[error("Join token has expired")]
+    Expired,
+
+    #[error("Join token has already been used")]
+    AlreadyUsed,
+}
+
+pub struct JoinTokenManager {
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    /// Redeemed single-use tokens and their expiry (unix seconds)
+    used: Mutex<HashMap<Uuid, i64>>,
+    /// SHA-256 of the cluster join token
+    cluster_token: RwLock<Option<[u8; 32]>>,
+    /// Where the cluster join token is kept across restarts
+    cluster_token_path: RwLock<Option<PathBuf>>,
+}
+
+impl JoinTokenManager {
+    pub fn new(key_material: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(b"arm-hypervisor/join-tokens/v1");
+        hasher.update(key_material);
+        let key = hasher.finalize();
+
+        Self {
+            encoding_key: EncodingKey::from_secret(&key),
+            decoding_key: DecodingKey::from_secret(&key),
+            used: Mutex::new(HashMap::new()),
+            cluster_token: RwLock::new(None),
+            cluster_token_path: RwLock::new(None),
+        }
+    }
+
+    /// Load the cluster join token from `path`, falling back to `configured`
+    /// and then to a new random token, and keep it there from now on
+    ///
+    /// A token in the file wins over the configured one, so a rotation
+    /// survives restarts.
+    pub fn load_cluster_token(&self, path: &Path, configured: Option<&str>) -> io::Result<()> {
+        let stored = match std::fs::read_to_string(path) {
+            Ok(token) => Some(token.trim().to_string()).filter(|token| !token.is_empty()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        *self.cluster_token_path.write().unwrap() = Some(path.to_path_buf());
+
+        match stored {
+            Some(token) => self.set_cluster_token(&token),
+            None => {
+                let token = configured.map_or_else(generate_cluster_token, str::to_string);
+                self.store_cluster_token(&token)?;
+                self.set_cluster_token(&token);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the cluster join token with a new random one and return it
+    ///
+    /// Nodes that already joined are unaffected; only joins presenting the
+    /// old token are refused from now on.
+    pub fn rotate_cluster_token(&self) -> io::Result<String> {
+        let token = generate_cluster_token();
+        self.store_cluster_token(&token)?;
+        self.set_cluster_token(&token);
+        Ok(token)
+    }
+
+    pub fn set_cluster_token(&self, token: &str) {
+        *self.cluster_token.write().unwrap() = Some(Sha256::digest(token.as_bytes()).into());
+    }
+
+    fn store_cluster_token(&self, token: &str) -> io::Result<()> {
+        let Some(path) = self.cluster_token_path.read().unwrap().clone() else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, token)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))?;
+        }
+        std::fs::rename(&tmp, &path)
+    }
+
+    fn is_cluster_token(&self, token: &str) -> bool {
+        let Some(expected) = *self.cluster_token.read().unwrap() else {
+            return false;
+        };
+        let candidate: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+        constant_time_eq(&candidate, &expected)
+    }
+
+    /// Issue a token valid for `ttl_secs`
+    pub fn issue(&self, ttl_secs: u64, single_use: bool) -> (String, DateTime<Utc>) {
+        let expires_at = Utc::now() + Duration::seconds(ttl_secs as i64);
+        (self.issue_until(expires_at, single_use), expires_at)
+    }
+
+    fn issue_until(&self, expires_at: DateTime<Utc>, single_use: bool) -> String {
+        let claims = JoinTokenClaims {
+            jti: Uuid::new_v4(),
+            aud: JOIN_TOKEN_AUDIENCE.to_string(),
+            exp: expires_at.timestamp(),
+            single_use,
+        };
+        encode(&Header::new(Algorithm::HS256), &claims, &self.encoding_key)
+            .expect("HS256 encoding cannot fail")
+    }
+
+    /// Verify a token and, if it is single-use, consume it
+    pub fn redeem(&self, token: Option<&str>) -> Result<JoinCredential, JoinTokenError> {
+        let token = token.ok_or(JoinTokenError::Missing)?;
+        if self.is_cluster_token(token) {
+            return Ok(JoinCredential::ClusterToken);
+        }
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_audience(&[JOIN_TOKEN_AUDIENCE]);
+        validation.leeway = 0;
+        let claims = decode::<JoinTokenClaims>(token, &self.decoding_key, &validation)
+            .map_err(|e| match e.kind() {
+                ErrorKind::ExpiredSignature => JoinTokenError::Expired,
+                _ => JoinTokenError::Invalid,
+            })?
+            .claims;
+
+        if claims.single_use {
+            let now = Utc::now().timestamp();
+            let mut used = self.used.lock().unwrap();
+            used.retain(|_, exp| *exp >= now);
+            if used.insert(claims.jti, claims.exp).is_some() {
+                return Err(JoinTokenError::AlreadyUsed);
+            }
+        }
+
+        Ok(JoinCredential::Issued(claims))
+    }
+}
+
+fn generate_cluster_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Compare without returning early, so timing reveals nothing about where
+/// the inputs differ
+fn constant_time_eq(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_token_is_accepted() {
+        let manager = JoinTokenManager::new(b"secret");
+        let (token, _) = manager.issue(600, false);
+
+        assert!(manager.redeem(Some(&token)).is_ok());
+        // TTL-limited tokens can be reused until they expire
+        assert!(manager.redeem(Some(&token)).is_ok());
+    }
+
+    #[test]
+    fn test_expired_token_is_rejected() {
+        let manager = JoinTokenManager::new(b"secret");
+        let token = manager.issue_until(Utc::now() - Duration::seconds(5), false);
+
+        assert_eq!(manager.redeem(Some(&token)), Err(JoinTokenError::Expired));
+    }
+
+    #[test]
+    fn test_single_use_token_cannot_be_reused() {
+        let manager = JoinTokenManager::new(b"secret");
+        let (token, _) = manager.issue(600, true);
+
+        assert!(manager.redeem(Some(&token)).is_ok());
+        assert_eq!(
+            manager.redeem(Some(&token)),
+            Err(JoinTokenError::AlreadyUsed)
+        );
+    }
+
+    #[test]
+    fn test_foreign_and_missing_tokens_are_rejected() {
+        let manager = JoinTokenManager::new(b"secret");
+        let (foreign, _) = JoinTokenManager::new(b"other").issue(600, false);
+
+        assert_eq!(manager.redeem(Some(&foreign)), Err(JoinTokenError::Invalid));
+        assert_eq!(manager.redeem(None), Err(JoinTokenError::Missing));
+    }
+
+    #[test]
+    fn test_cluster_token_rotation() {
+        let dir = std::env::temp_dir().join(format!("join-token-{}", Uuid::new_v4()));
+        let path = dir.join("cluster-join-token");
+        let manager = JoinTokenManager::new(b"secret");
+        manager
+            .load_cluster_token(&path, Some("configured-token"))
+            .unwrap();
+        assert_eq!(
+            manager.redeem(Some("configured-token")),
+            Ok(JoinCredential::ClusterToken)
+        );
+        assert_eq!(
+            manager.redeem(Some("configured-tokem")),
+            Err(JoinTokenError::Invalid)
+        );
+
+        let rotated = manager.rotate_cluster_token().unwrap();
+        assert_eq!(
+            manager.redeem(Some("configured-token")),
+            Err(JoinTokenError::Invalid)
+        );
+        assert_eq!(
+            manager.redeem(Some(&rotated)),
+            Ok(JoinCredential::ClusterToken)
+        );
+
+        // The rotated token wins over the configured one after a restart
+        let restarted = JoinTokenManager::new(b"secret");
+        restarted
+            .load_cluster_token(&path, Some("configured-token"))
+            .unwrap();
+        assert_eq!(
+            restarted.redeem(Some(&rotated)),
+            Ok(JoinCredential::ClusterToken)
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+/// JSON files the credential stores keep under the data dir
+///
+/// The files hold key and token hashes, so they are written owner-only, to a
+/// temp file that is then renamed so an interrupted save keeps the previous
+/// file.
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use tracing::warn;
+
+/// Read `path`; a missing file gives the default, and an unreadable or
+/// invalid one is logged as `what` and gives the default too
+pub fn load<T: DeserializeOwned + Default>(path: &Path, what: &str) -> T {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return T::default(),
+        Err(e) => {
+            warn!("Could not read {} {}: {}", what, path.display(), e);
+            return T::default();
+        }
+    };
+    serde_json::from_slice(&content).unwrap_or_else(|e| {
+        warn!("Invalid {} {}: {}", what, path.display(), e);
+        T::default()
+    })
+}
+
+/// Write `value` to `path` with mode 0600
+pub fn save<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let content = serde_json::to_vec_pretty(value)?;
+    let tmp = path.with_extension("tmp");
+    // A leftover temp file may have looser permissions than a new one gets
+    match fs::remove_file(&tmp) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp)?;
+    file.write_all(&content)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_saved_file_is_owner_only_and_loads_back() {
+        let dir = std::env::temp_dir().join(format!("json_file_{}", uuid::Uuid::new_v4()));
+        let path = dir.join("values.json");
+
+        let missing: BTreeMap<String, u32> = load(&path, "values");
+        assert!(missing.is_empty());
+
+        let values = BTreeMap::from([("a".to_string(), 1u32)]);
+        save(&path, &values).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(load::<BTreeMap<String, u32>>(&path, "values"), values);
+
+        fs::write(&path, "not json").unwrap();
+        assert!(load::<BTreeMap<String, u32>>(&path, "values").is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,10 +1,49 @@
+pub mod address_conflicts;
+pub mod api_keys;
 pub mod audit;
+pub mod audit_diff;
+pub mod auth;
+pub mod auto_join;
+pub mod client_ip;
+pub mod cluster_leave;
+pub mod cluster_view;
+pub mod config;
+pub mod container_delete;
+pub mod container_health;
+pub mod egress;
 pub mod handlers;
+pub mod hotplug;
+pub mod inventory;
+pub mod jobs;
+pub mod join_tokens;
+pub mod json_file;
+pub mod memory_watchdog;
 pub mod middleware;
+pub mod network_overview;
 pub mod observability;
+pub mod outbound;
+pub mod paths;
+pub mod peer_probe;
+pub mod pool_usage;
+pub mod privileges;
+pub mod rate_limit;
 pub mod rbac;
+pub mod readiness;
 pub mod request_tracing;
 pub mod routes;
+pub mod runtime;
+pub mod secrets;
+pub mod service_tokens;
+pub mod setup;
+pub mod snapshot_batch;
+pub mod start_checks;
+pub mod system;
+pub mod systemd;
+pub mod tasks;
+pub mod tls;
+pub mod totp;
+pub mod usage_history;
+pub mod wait;
 
 pub use audit::*;
 pub use handlers::*;
@@ -1,26 +1,92 @@
 use actix_web::{middleware::Logger, web, App, HttpServer};
+use cluster::{ClusterNetwork, HeartbeatSource, MembershipManager, PeerHealth};
+use container_manager::{ContainerManager, ImageCache, LxcMonitor};
+use network::{FirewallManager, Ipam};
 use std::path::Path;
 use std::sync::Arc;
+use uuid::Uuid;
 
+mod address_conflicts;
+mod api_keys;
 mod audit;
+mod audit_diff;
+mod auth;
+mod auto_join;
+mod client_ip;
+mod cluster_leave;
+mod cluster_view;
 mod config;
+mod container_delete;
+mod container_health;
+mod egress;
 mod handlers;
+mod hotplug;
+mod inventory;
+mod jobs;
+mod join_tokens;
+mod json_file;
+mod memory_watchdog;
 mod middleware;
+mod network_overview;
 mod observability;
+mod outbound;
+mod paths;
+mod peer_probe;
+mod pool_usage;
+mod privileges;
+mod rate_limit;
 mod rbac;
+mod readiness;
 mod request_tracing;
 mod routes;
+mod runtime;
+mod secrets;
+mod service_tokens;
+mod setup;
+mod snapshot_batch;
+mod start_checks;
+mod system;
+mod systemd;
+mod tasks;
+mod tls;
+mod totp;
+mod usage_history;
+mod wait;
 
 use audit::AuditLogger;
+use auto_join::{AutoJoin, MembershipRecord};
+use client_ip::TrustedProxies;
+use cluster_leave::ClusterMembership;
 use config::AppConfig;
+use container_health::ContainerHealth;
+use jobs::JobManager;
+use join_tokens::JoinTokenManager;
 use middleware::{RequestLogging, SecurityHeaders, SimpleCors};
 use observability::MetricsCollector;
+use pool_usage::PoolUsageMonitor;
+use rate_limit::RateLimiter;
 use rbac::UserStore;
 use routes::configure_routes;
+use secrets::SecretStore;
+use setup::AdminSetup;
+use systemd::SystemdNotifier;
+use tasks::TaskManager;
+use usage_history::UsageHistory;
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    // Load configuration
+/// How long background tasks get to stop on shutdown
+const TASK_SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+fn main() -> std::io::Result<()> {
+    let app_config = load_config();
+    // Built here rather than by #[actix_web::main] so the blocking pool
+    // follows the config
+    let runtime = runtime::builder(&app_config.server).build()?;
+    actix_rt::System::with_tokio_rt(|| runtime).block_on(run(app_config))
+}
+
+/// Config from the environment and the first config file found, validated;
+/// exits on invalid config
+fn load_config() -> AppConfig {
     let mut app_config = AppConfig::from_env();
 
     // Try to load from config file if it exists
@@ -34,6 +100,13 @@ async fn main() -> std::io::Result<()> {
     for config_path in &config_paths {
         if Path::new(config_path).exists() {
             if let Err(e) = app_config.merge_with_file(config_path) {
+                if let config::ConfigMergeError::Invalid(errors) = e {
+                    eprintln!("Configuration in {} is invalid:", config_path);
+                    for error in errors {
+                        eprintln!("  - {}", error);
+                    }
+                    std::process::exit(1);
+                }
                 eprintln!("Warning: Failed to load config from {}: {}", config_path, e);
             } else {
                 println!("Loaded configuration from: {}", config_path);
@@ -58,7 +131,10 @@ async fn main() -> std::io::Result<()> {
         }
         std::process::exit(1);
     }
+    app_config
+}
 
+async fn run(mut app_config: AppConfig) -> std::io::Result<()> {
     // Initialize logging based on config
     let log_level = &app_config.logging.level;
     let env_filter = format!("api_server={},actix_web=info,tower_http=info", log_level);
@@ -76,6 +152,52 @@ async fn main() -> std::io::Result<()> {
         app_config.security.auth_enabled
     );
 
+    let paths = app_config.paths();
+    tracing::info!("Data directory: {}", paths.data_dir.display());
+    if app_config.paths.lxc_root.is_some() {
+        container_manager::config::LxcConfig::set_lxc_root(paths.lxc_root.clone());
+    }
+    if app_config.dev.fake_backend {
+        // Keep fake containers out of a real LXC root unless one was chosen
+        if app_config.paths.lxc_root.is_none() && std::env::var_os("LXC_ROOT").is_none() {
+            container_manager::config::LxcConfig::set_lxc_root(paths.data_dir.join("fake-lxc"));
+        }
+        container_manager::lxc::LxcCommand::use_fake_backend();
+        network::BridgeManager::use_fake_backend(&[app_config.network.default_bridge.as_str()]);
+        tracing::warn!(
+            "FAKE BACKEND ENABLED: containers and bridges are in-memory records under {}; \
+             nothing here runs LXC. Never use this on a real host.",
+            container_manager::config::LxcConfig::lxc_root().display()
+        );
+    }
+    ContainerManager::set_max_concurrent_ops(app_config.container.max_concurrent_heavy_ops);
+    ContainerManager::set_operation_queue_timeout(Some(std::time::Duration::from_secs(
+        app_config.container.heavy_op_queue_timeout_secs,
+    )));
+    ContainerManager::set_default_limits(app_config.container.default_limits());
+    let events_path = app_config
+        .container
+        .persist_events
+        .then_some(paths.container_events.as_path());
+    if let Err(e) =
+        ContainerManager::configure_events(app_config.container.event_history_size, events_path)
+    {
+        tracing::error!("Failed to load container event history: {}", e);
+    }
+    if let Some(ref arch) = app_config.container.arch {
+        container_manager::config::LxcConfig::set_arch(arch);
+    }
+    tracing::info!(
+        "Container architecture: {}",
+        container_manager::config::LxcConfig::arch()
+    );
+    if let Err(e) =
+        container_manager::downloads::configure(app_config.downloads.clone(), &paths.data_dir)
+    {
+        tracing::error!("Failed to apply download settings: {}", e);
+        std::process::exit(1);
+    }
+
     // Override JWT secret from environment if provided
     if let Ok(jwt_secret) = std::env::var("JWT_SECRET") {
         tracing::info!("JWT secret loaded from environment variable");
@@ -88,6 +210,29 @@ async fn main() -> std::io::Result<()> {
         std::process::exit(1);
     }
 
+    let privilege_report = privileges::check();
+    privileges::log_report(&privilege_report);
+    if app_config.server.require_privileges
+        && privilege_report.lxc.mode == container_manager::PrivilegeMode::None
+    {
+        eprintln!("CRITICAL ERROR: LXC cannot be run as root or through passwordless sudo");
+        for step in privilege_report.remediation() {
+            eprintln!("  - {}", step);
+        }
+        std::process::exit(1);
+    }
+
+    match container_manager::lxc::LxcCommand::detect_version() {
+        Ok(version) => match container_manager::CloneStrategy::for_version(version) {
+            Ok(strategy) => tracing::info!("LXC {}, snapshot clone via {:?}", version, strategy),
+            Err(e) => tracing::warn!("LXC {}: {}", version, e),
+        },
+        Err(e) => tracing::warn!("Could not detect the LXC version: {:#}", e),
+    }
+
+    let notifier = SystemdNotifier::from_env();
+    notifier.log_unit_expectations();
+
     if app_config.server.tls.is_some() {
         tracing::info!("TLS is enabled");
     } else {
@@ -97,20 +242,370 @@ async fn main() -> std::io::Result<()> {
     // Create shared app data
     let server_config = app_config.server.clone();
     let _security_config = app_config.security.clone();
+    let trusted_proxies = Arc::new(
+        TrustedProxies::parse(&app_config.security.trusted_proxies)
+            .expect("trusted proxies are validated with the config"),
+    );
+    let rate_limiter = app_config
+        .security
+        .rate_limit
+        .clone()
+        .map(|config| Arc::new(RateLimiter::new(config)));
 
     // Create metrics collector
     let metrics_collector = Arc::new(MetricsCollector::new());
+    // Lets the library crates time the LXC and network commands they run
+    models::metrics::install(metrics_collector.clone());
 
     // Create user store and audit logger
-    let user_store = Arc::new(std::sync::Mutex::new(UserStore::new()));
+    let mut users = UserStore::load(&paths.users);
+    // Unattended installs may create the admin up front instead of via setup
+    if let Ok(password) = std::env::var("ADMIN_PASSWORD") {
+        if let Err(e) = users.create_admin("admin", &password) {
+            tracing::warn!("Ignoring ADMIN_PASSWORD: {}", e);
+        }
+    }
+    let setup_token = std::env::var(setup::SETUP_TOKEN_ENV).ok();
+    let admin_setup = Arc::new(AdminSetup::new(&users, setup_token.clone()));
+    if admin_setup.is_pending() && app_config.security.auth_enabled {
+        let token = match setup_token {
+            Some(_) => format!("the token from {}", setup::SETUP_TOKEN_ENV),
+            None => format!(
+                "the one-time setup token {}",
+                admin_setup.token().unwrap_or_default()
+            ),
+        };
+        tracing::warn!(
+            "No admin user exists. Create one with POST /api/v1/auth/setup \
+             {{\"token\", \"username\", \"password\"}} using {}; other authenticated \
+             requests return 503 until then",
+            token
+        );
+    }
+    let user_store = Arc::new(std::sync::Mutex::new(users));
+    let api_keys = Arc::new(api_keys::ApiKeyStore::load(&paths.api_keys));
+    let service_tokens = Arc::new(service_tokens::ServiceTokenStore::load(
+        &paths.service_tokens,
+    ));
     let audit_logger = Arc::new(AuditLogger::new(10000));
+    let job_manager = Arc::new(JobManager::default());
+    // Without a JWT secret tokens are signed with a per-process key and only
+    // valid until restart
+    let join_tokens = Arc::new(match app_config.security.jwt_secret {
+        Some(ref secret) => JoinTokenManager::new(secret.as_bytes()),
+        None => JoinTokenManager::new(Uuid::new_v4().as_bytes()),
+    });
+    let join_token_path = paths.join_token.clone();
+    if let Err(e) =
+        join_tokens.load_cluster_token(&join_token_path, app_config.cluster.join_token.as_deref())
+    {
+        tracing::error!(
+            "Failed to load cluster join token {}: {}",
+            join_token_path.display(),
+            e
+        );
+        std::process::exit(1);
+    }
+    let image_cache = Arc::new(
+        ImageCache::new(
+            paths.image_cache.clone(),
+            app_config.storage.image_cache_max_bytes(),
+        )
+        .with_server(app_config.storage.image_server.clone()),
+    );
+
+    let secret_store = match app_config.security.secrets_master_key_file {
+        Some(ref key_file) => match SecretStore::open(Path::new(key_file), paths.secrets.clone()) {
+            Ok(store) => Some(Arc::new(store)),
+            Err(e) => {
+                tracing::error!("Failed to open secret store ({}): {}", key_file, e);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            tracing::info!("No secrets master key configured; container secrets are disabled");
+            None
+        }
+    };
+    // A node that joined before keeps the identity it joined with
+    let membership_record = match MembershipRecord::load(&paths.cluster_membership) {
+        Ok(record) => record,
+        Err(e) => {
+            tracing::error!(
+                "Failed to read cluster membership {}: {}",
+                paths.cluster_membership.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    };
+    let node_id = match membership_record {
+        Some(ref record) => record.node_id,
+        None => match auto_join::load_node_id(&paths.node_id) {
+            Ok(node_id) => node_id,
+            Err(e) => {
+                tracing::error!("Failed to read node id {}: {}", paths.node_id.display(), e);
+                std::process::exit(1);
+            }
+        },
+    };
+    let membership = Arc::new(std::sync::RwLock::new(MembershipManager::new(node_id)));
+
+    // Only clustered nodes track cluster state; readiness then waits for a leader
+    let cluster_state = auto_join::cluster_state(&app_config, node_id, membership_record.as_ref());
+    let peer_health = Arc::new(std::sync::RwLock::new(PeerHealth::new()));
+    // Shared pools with a source are mounted before anything measures them;
+    // one that fails to mount shows up in the storage readiness check
+    if !app_config.dev.fake_backend {
+        for pool in &app_config.storage.pool_configs {
+            let (Some(storage_type), Some(ref source)) = (pool.storage_type(), &pool.source) else {
+                continue;
+            };
+            let target = paths.pool_path(&pool.path);
+            if storage::is_mount_point(&target) {
+                continue;
+            }
+            if let Err(e) =
+                storage::mount_pool(&storage_type, source, &target, &pool.mount_options()).await
+            {
+                tracing::error!("Failed to mount storage pool {}: {}", pool.name, e);
+            }
+        }
+    }
+    let pool_usage = Arc::new(PoolUsageMonitor::new(&app_config.storage, &paths));
+    let task_manager = Arc::new(TaskManager::new());
+
+    // Container state changes for everything that would otherwise poll LXC
+    let lxc_monitor = Arc::new(LxcMonitor::new());
+    let container_health = Arc::new(ContainerHealth::new(&app_config.health_checks));
+
+    let mut cluster_membership = None;
+    // Stops the cluster listener at shutdown; the task finishes once peer
+    // connections have drained
+    let cluster_stop = Arc::new(tokio::sync::Notify::new());
+    let mut cluster_listener = None;
+    let mut listener_stats = None;
+    if let Some(ref cluster_state) = cluster_state {
+        let cluster = &app_config.cluster;
+        let heartbeat: HeartbeatSource = {
+            let pool_usage = pool_usage.clone();
+            let containers =
+                peer_probe::LocalContainers::new(lxc_monitor.clone(), container_health.clone());
+            Arc::new(move |request| peer_probe::local_heartbeat(&pool_usage, &containers, request))
+        };
+        match tokio::net::TcpListener::bind((cluster.bind_address.as_str(), cluster.bind_port))
+            .await
+        {
+            Ok(listener) => {
+                let network = ClusterNetwork::new(listener.local_addr()?)
+                    .with_limits(cluster.listener_limits())
+                    .with_heartbeat(heartbeat.clone())
+                    .with_leave_listener(Arc::new({
+                        let (membership, state) = (membership.clone(), cluster_state.clone());
+                        move |notice| cluster_leave::apply_notice(&membership, &state, &notice)
+                    }));
+                cluster_membership = Some(Arc::new(ClusterMembership {
+                    membership: membership.clone(),
+                    state: cluster_state.clone(),
+                    network: network.clone(),
+                    cluster_port: cluster.bind_port,
+                    record_path: paths.cluster_membership.clone(),
+                }));
+                listener_stats = Some(network.listener_stats());
+                cluster_listener = Some(actix_rt::spawn({
+                    let (network, stop) = (network.clone(), cluster_stop.clone());
+                    async move {
+                        if let Err(e) = network.serve_until(listener, stop.notified()).await {
+                            tracing::error!("Cluster listener stopped: {}", e);
+                        }
+                    }
+                }));
+                let (cluster_port, interval) = (
+                    cluster.bind_port,
+                    std::time::Duration::from_millis(cluster.heartbeat_interval.unwrap_or(1000)),
+                );
+                let (membership, peer_health, cluster_state) = (
+                    membership.clone(),
+                    peer_health.clone(),
+                    cluster_state.clone(),
+                );
+                task_manager.register_continuous("peer-probe", "heartbeat interval", move || {
+                    let probe = peer_probe::run(
+                        network.clone(),
+                        cluster_port,
+                        membership.clone(),
+                        peer_health.clone(),
+                        cluster_state.clone(),
+                        heartbeat.clone(),
+                        interval,
+                    );
+                    async move {
+                        probe.await;
+                        Ok(())
+                    }
+                });
+            }
+            Err(e) => tracing::error!(
+                "Failed to bind cluster port {}:{}: {}",
+                cluster.bind_address,
+                cluster.bind_port,
+                e
+            ),
+        }
+    }
+
+    task_manager.register_continuous("lxc-monitor", "lxc-monitor events", {
+        let lxc_monitor = lxc_monitor.clone();
+        move || {
+            let monitor = lxc_monitor
+                .clone()
+                .run(container_manager::monitor::DEFAULT_POLL_INTERVAL);
+            async move {
+                monitor.await;
+                Ok(())
+            }
+        }
+    });
+    task_manager.register_continuous("state-change-metrics", "container state changes", {
+        let (lxc_monitor, metrics_collector) = (lxc_monitor.clone(), metrics_collector.clone());
+        move || {
+            let counter = observability::count_state_changes(
+                lxc_monitor.subscribe(),
+                metrics_collector.clone(),
+            );
+            async move {
+                counter.await;
+                Ok(())
+            }
+        }
+    });
 
-    let server = HttpServer::new(move || {
+    let ipam = if app_config.network.ip_range.is_empty() {
+        None
+    } else {
+        let path = &paths.ipam_state;
+        match Ipam::open(&app_config.network.ip_range, path) {
+            Ok(ipam) => Some(Arc::new(ipam)),
+            Err(e) => {
+                tracing::error!("Failed to open IPAM state {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let usage_history = app_config
+        .usage_history
+        .enabled
+        .then(|| Arc::new(UsageHistory::new(&app_config.usage_history)));
+    if let Some(ref history) = usage_history {
+        usage_history::register(&task_manager, history.clone(), lxc_monitor.clone());
+    }
+
+    if app_config.storage.usage_alerts.enabled {
+        pool_usage::register(
+            &task_manager,
+            pool_usage.clone(),
+            app_config.storage.usage_alerts.clone(),
+            audit_logger.clone(),
+        );
+    }
+
+    if app_config.health_checks.enabled {
+        container_health::register(
+            &task_manager,
+            container_health.clone(),
+            lxc_monitor.clone(),
+            audit_logger.clone(),
+        );
+    }
+
+    if app_config.memory_watchdog.enabled {
+        memory_watchdog::register(
+            &task_manager,
+            app_config.memory_watchdog.clone(),
+            audit_logger.clone(),
+            metrics_collector.clone(),
+            lxc_monitor.clone(),
+        );
+    }
+
+    if app_config.audit.max_age_days.is_some() || app_config.audit.max_total_size_mb.is_some() {
+        audit::register_retention(
+            &task_manager,
+            audit_logger.clone(),
+            app_config.audit.clone(),
+        );
+    }
+
+    if let Some(sink) = audit::sink_from_config(&app_config.audit_forwarder) {
+        let (forwarder, queue) = audit::AuditForwarder::channel(
+            app_config.audit_forwarder.queue_size,
+            Some(metrics_collector.clone()),
+        );
+        audit_logger.set_forwarder(forwarder);
+        actix_rt::spawn(audit::run_forwarder(
+            queue,
+            sink,
+            app_config.audit_forwarder.clone(),
+            Some(metrics_collector.clone()),
+        ));
+    }
+
+    // Bring back orchestrator-managed firewall rules lost on reboot
+    let firewall_rules_path = app_config
+        .network
+        .firewall_enabled
+        .then(|| paths.firewall_rules.clone());
+    if let Some(ref path) = firewall_rules_path {
+        if path.exists() {
+            if let Err(e) = FirewallManager::restore(path).await {
+                tracing::warn!("Failed to restore firewall rules: {}", e);
+            }
+        }
+    }
+    FirewallManager::persist_to(firewall_rules_path.clone());
+    if let Err(e) = egress::configure(&paths.egress_chains) {
+        tracing::error!("Failed to load egress chain record: {}", e);
+    }
+
+    // A bootstrapping node founds the cluster and has no seeds to join through
+    let auto_join = cluster_state
+        .as_ref()
+        .filter(|_| !app_config.cluster.bootstrap)
+        .map(|state| {
+            Arc::new(
+                AutoJoin::new(&app_config, &paths, node_id, membership_record.as_ref())
+                    .with_cluster_state(state.clone()),
+            )
+        });
+    if let Some(ref auto_join) = auto_join {
+        if let Err(e) = auto_join::start(auto_join.clone(), &app_config).await {
+            tracing::error!("CRITICAL ERROR: cluster.require_join is set and {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // The app factory takes the manager along; this handle stops the tasks
+    let running_tasks = task_manager.clone();
+    let app_factory = move || {
         App::new()
             .app_data(web::Data::new(app_config.clone()))
             .app_data(web::Data::new(metrics_collector.clone()))
             .app_data(web::Data::new(user_store.clone()))
+            .app_data(web::Data::new(admin_setup.clone()))
             .app_data(web::Data::new(audit_logger.clone()))
+            .app_data(web::Data::new(api_keys.clone()))
+            .app_data(web::Data::new(service_tokens.clone()))
+            .app_data(web::Data::new(job_manager.clone()))
+            .app_data(web::Data::new(join_tokens.clone()))
+            .app_data(web::Data::new(image_cache.clone()))
+            .app_data(web::Data::new(membership.clone()))
+            .app_data(web::Data::new(peer_health.clone()))
+            .app_data(web::Data::new(lxc_monitor.clone()))
+            .app_data(web::Data::new(pool_usage.clone()))
+            .app_data(web::Data::new(container_health.clone()))
+            .app_data(web::Data::new(trusted_proxies.clone()))
+            .app_data(web::Data::new(task_manager.clone()))
             .wrap(Logger::default())
             .wrap(SecurityHeaders)
             .wrap(request_tracing::RequestTracing::new(
@@ -118,69 +613,129 @@ async fn main() -> std::io::Result<()> {
             ))
             .wrap(RequestLogging)
             .wrap(SimpleCors)
+            .configure(|cfg| {
+                // Only registered when configured; handlers treat it as optional
+                if let Some(ref store) = secret_store {
+                    cfg.app_data(web::Data::new(store.clone()));
+                }
+                if let Some(ref history) = usage_history {
+                    cfg.app_data(web::Data::new(history.clone()));
+                }
+                if let Some(ref ipam) = ipam {
+                    cfg.app_data(web::Data::new(ipam.clone()));
+                }
+                if let Some(ref state) = cluster_state {
+                    cfg.app_data(web::Data::new(state.clone()));
+                }
+                if let Some(ref auto_join) = auto_join {
+                    cfg.app_data(web::Data::new(auto_join.clone()));
+                }
+                if let Some(ref cluster_membership) = cluster_membership {
+                    cfg.app_data(web::Data::new(cluster_membership.clone()));
+                }
+                if let Some(ref stats) = listener_stats {
+                    cfg.app_data(web::Data::new(stats.clone()));
+                }
+                if let Some(ref rate_limiter) = rate_limiter {
+                    cfg.app_data(web::Data::new(rate_limiter.clone()));
+                }
+            })
             .configure(configure_routes)
-    });
+    };
 
     // Configure server based on config
     let bind_address = (server_config.host.as_str(), server_config.port);
+    let tls_config = match server_config.tls {
+        Some(ref tls) => {
+            tracing::info!("Binding with TLS using cert: {:?}", tls.cert_file);
+            Some(tls::load_server_config(tls)?)
+        }
+        None => None,
+    };
 
-    let mut server = if let Some(ref tls_config) = server_config.tls {
-        // TLS is configured - bind with TLS
-        tracing::info!("Binding with TLS using cert: {:?}", tls_config.cert_file);
+    // Signals are handled below so systemd can be told we are stopping
+    let server = match tls_config {
+        Some(tls_config) if !server_config.http2_enabled => {
+            tracing::info!("HTTP/2 disabled; serving HTTP/1.1 only over TLS");
+            tls::http1_server(app_factory, tls_config, &server_config)?
+        }
+        tls_config => {
+            let server = HttpServer::new(app_factory);
+            let mut server = match tls_config {
+                // HttpServer offers h2 and http/1.1 over ALPN
+                Some(tls_config) => server.bind_rustls_0_23(bind_address, tls_config)?,
+                // No TLS - plain HTTP/1.1
+                None => server.bind(bind_address)?,
+            };
 
-        use rustls::ServerConfig;
-        use rustls_pemfile::{certs, pkcs8_private_keys};
-        use std::fs::File;
-        use std::io::BufReader;
+            if let Some(workers) = server_config.workers {
+                server = server.workers(workers);
+            }
 
-        // Load TLS certificate and key
-        let cert_file = &mut BufReader::new(
-            File::open(&tls_config.cert_file)
-                .map_err(|e| std::io::Error::other(format!("Failed to open cert file: {}", e)))?,
-        );
-        let key_file = &mut BufReader::new(
-            File::open(&tls_config.key_file)
-                .map_err(|e| std::io::Error::other(format!("Failed to open key file: {}", e)))?,
-        );
+            if let Some(max_conn) = server_config.max_connections {
+                server = server.max_connections(max_conn);
+            }
 
-        let cert_chain = certs(cert_file)
-            .collect::<Result<Vec<_>, _>>()
-            .map_err(|e| std::io::Error::other(format!("Failed to parse cert: {}", e)))?;
-        let mut keys = pkcs8_private_keys(key_file)
-            .collect::<Result<Vec<_>, _>>()
-            .map_err(|e| std::io::Error::other(format!("Failed to parse key: {}", e)))?;
+            if let Some(threads) = server_config.blocking_threads {
+                server = server.worker_max_blocking_threads(threads);
+            }
 
-        if keys.is_empty() {
-            return Err(std::io::Error::other("No private key found in key file"));
-        }
+            if let Some(keepalive) = server_config.keepalive {
+                server = server.keep_alive(std::time::Duration::from_secs(keepalive));
+            }
 
-        let tls_config = ServerConfig::builder()
-            .with_no_client_auth()
-            .with_single_cert(cert_chain, keys.remove(0).into())
-            .map_err(|e| std::io::Error::other(format!("Failed to build TLS config: {}", e)))?;
+            if let Some(timeout) = server_config.client_timeout {
+                server = server.client_request_timeout(std::time::Duration::from_secs(timeout));
+            }
 
-        server.bind_rustls_0_23(bind_address, tls_config)?
-    } else {
-        // No TLS - plain HTTP
-        server.bind(bind_address)?
+            server.disable_signals().run()
+        }
     };
+    let handle = server.handle();
 
-    if let Some(workers) = server_config.workers {
-        server = server.workers(workers);
-    }
+    actix_rt::spawn({
+        let notifier = notifier.clone();
+        async move {
+            shutdown_signal().await;
+            tracing::info!("Shutdown signal received, stopping gracefully");
+            notifier.stopping();
+            if let Some(ref path) = firewall_rules_path {
+                if let Err(e) = FirewallManager::save(path).await {
+                    tracing::warn!("Failed to save firewall rules: {}", e);
+                }
+            }
+            running_tasks.shutdown(TASK_SHUTDOWN_TIMEOUT).await;
+            // Peers fail over once the listener closes; wait for the drain
+            if let Some(listener) = cluster_listener {
+                cluster_stop.notify_one();
+                let _ = listener.await;
+            }
+            handle.stop(true).await;
+        }
+    });
 
-    if let Some(max_conn) = server_config.max_connections {
-        server = server.max_connections(max_conn);
-    }
+    notifier.ready();
+    notifier.spawn_watchdog();
 
-    if let Some(keepalive) = server_config.keepalive {
-        server = server.keep_alive(std::time::Duration::from_secs(keepalive));
-    }
+    tracing::info!("ARM Hypervisor API server started successfully");
+    server.await
+}
 
-    if let Some(timeout) = server_config.client_timeout {
-        server = server.client_request_timeout(std::time::Duration::from_secs(timeout));
-    }
+/// Wait for SIGTERM or SIGINT
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
 
-    tracing::info!("ARM Hypervisor API server started successfully");
-    server.run().await
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::error!("Failed to install SIGTERM handler: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = sigterm.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
 }
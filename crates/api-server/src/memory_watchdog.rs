@@ -0,0 +1,341 @@
+/// Host memory watchdog that freezes containers under memory pressure
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use container_manager::{ContainerManager, LxcMonitor};
+use models::ContainerStatus;
+
+use crate::audit::{AuditAction, AuditLogger, AuditResult};
+use crate::config::MemoryWatchdogConfig;
+use crate::observability::MetricsCollector;
+use crate::tasks::TaskManager;
+
+const MIB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchdogAction {
+    Freeze(String),
+    Unfreeze(String),
+}
+
+/// Decision state for the memory watchdog
+///
+/// Only containers frozen by the watchdog itself are ever unfrozen by it, in
+/// reverse order of freezing.
+pub struct MemoryWatchdog {
+    config: MemoryWatchdogConfig,
+    frozen: Vec<String>,
+}
+
+impl MemoryWatchdog {
+    pub fn new(config: MemoryWatchdogConfig) -> Self {
+        Self {
+            config,
+            frozen: Vec::new(),
+        }
+    }
+
+    /// Containers currently frozen by the watchdog, oldest first
+    pub fn frozen(&self) -> &[String] {
+        &self.frozen
+    }
+
+    /// Decide what to do given the host's available memory and the memory
+    /// usage (in bytes) of each running container
+    ///
+    /// Under pressure, the largest non-critical containers are frozen until
+    /// their combined usage covers the deficit. Once memory has recovered past
+    /// the recovery threshold, one container is unfrozen per check so the host
+    /// is not immediately pushed back under pressure.
+    pub fn plan(&self, available_bytes: u64, usage: &HashMap<String, u64>) -> Vec<WatchdogAction> {
+        let min_available = self.config.min_available_mb * MIB;
+        let recovery_available = self.config.recovery_available_mb * MIB;
+
+        if available_bytes < min_available {
+            let deficit = min_available - available_bytes;
+
+            let mut candidates: Vec<(&String, u64)> = usage
+                .iter()
+                .filter(|(name, _)| !self.is_critical(name) && !self.frozen.contains(name))
+                .map(|(name, bytes)| (name, *bytes))
+                .collect();
+            // Largest first; ties broken by name so decisions are deterministic
+            candidates.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+            let mut actions = Vec::new();
+            let mut covered = 0u64;
+            for (name, bytes) in candidates {
+                if covered >= deficit {
+                    break;
+                }
+                actions.push(WatchdogAction::Freeze(name.clone()));
+                covered = covered.saturating_add(bytes);
+            }
+            return actions;
+        }
+
+        if available_bytes >= recovery_available {
+            if let Some(name) = self.frozen.last() {
+                return vec![WatchdogAction::Unfreeze(name.clone())];
+            }
+        }
+
+        Vec::new()
+    }
+
+    /// Record an action that was successfully applied
+    pub fn applied(&mut self, action: &WatchdogAction) {
+        match action {
+            WatchdogAction::Freeze(name) => self.frozen.push(name.clone()),
+            WatchdogAction::Unfreeze(name) => self.frozen.retain(|n| n != name),
+        }
+    }
+
+    /// Forget frozen containers that no longer exist
+    pub fn retain_known(&mut self, known: &HashSet<String>) {
+        self.frozen.retain(|name| known.contains(name));
+    }
+
+    fn is_critical(&self, name: &str) -> bool {
+        self.config.critical_containers.iter().any(|c| c == name)
+    }
+}
+
+/// Check host memory every `check_interval_secs`
+pub fn register(
+    tasks: &TaskManager,
+    config: MemoryWatchdogConfig,
+    audit_logger: Arc<AuditLogger>,
+    metrics: Arc<MetricsCollector>,
+    monitor: Arc<LxcMonitor>,
+) {
+    info!(
+        "Memory watchdog enabled: freeze below {} MiB, recover above {} MiB, critical: {:?}",
+        config.min_available_mb, config.recovery_available_mb, config.critical_containers
+    );
+
+    let interval = Duration::from_secs(config.check_interval_secs);
+    // Runs never overlap, so the lock is only ever taken by the current one
+    let watchdog = Arc::new(tokio::sync::Mutex::new(MemoryWatchdog::new(config)));
+    tasks.register_interval("memory-watchdog", interval, move || {
+        check(
+            watchdog.clone(),
+            audit_logger.clone(),
+            metrics.clone(),
+            monitor.clone(),
+        )
+    });
+}
+
+/// Freeze or unfreeze containers once for the memory now available
+async fn check(
+    watchdog: Arc<tokio::sync::Mutex<MemoryWatchdog>>,
+    audit_logger: Arc<AuditLogger>,
+    metrics: Arc<MetricsCollector>,
+    monitor: Arc<LxcMonitor>,
+) -> Result<(), String> {
+    let mut watchdog = watchdog.lock().await;
+
+    let available_bytes = sys_info::mem_info()
+        .map(|mem| mem.avail * 1024)
+        .map_err(|e| format!("could not read host memory: {}", e))?;
+
+    let names = ContainerManager::list()
+        .await
+        .map_err(|e| format!("could not list containers: {}", e))?;
+    watchdog.retain_known(&names.iter().cloned().collect());
+
+    // The monitor already knows which containers run, unless it is down
+    let health = monitor.health();
+    let states = health.is_current().then(|| monitor.states());
+    let mut usage = HashMap::new();
+    for name in names {
+        let running = match states {
+            Some(ref states) => states.get(&name) == Some(&ContainerStatus::Running),
+            None => matches!(
+                ContainerManager::status(&name).await,
+                Ok(ContainerStatus::Running)
+            ),
+        };
+        if !running {
+            continue;
+        }
+        if let Ok(container_usage) = ContainerManager::usage(&name).await {
+            usage.insert(name, container_usage.memory_bytes.unwrap_or(0));
+        }
+    }
+
+    let actions = watchdog.plan(available_bytes, &usage);
+    if actions.is_empty() {
+        return Ok(());
+    }
+
+    for action in actions {
+        if apply(&action, available_bytes, &audit_logger, &metrics).await {
+            watchdog.applied(&action);
+        }
+    }
+    info!(
+        "Containers frozen by memory watchdog: {:?}",
+        watchdog.frozen()
+    );
+    Ok(())
+}
+
+/// Apply an action, auditing the outcome; returns whether it succeeded
+async fn apply(
+    action: &WatchdogAction,
+    available_bytes: u64,
+    audit_logger: &AuditLogger,
+    metrics: &MetricsCollector,
+) -> bool {
+    let (name, audit_action, result) = match action {
+        WatchdogAction::Freeze(name) => {
+            warn!(
+                "Host available memory {} MiB below threshold, freezing container {}",
+                available_bytes / MIB,
+                name
+            );
+            let result = ContainerManager::freeze(name).await;
+            if result.is_ok() {
+                metrics.record_watchdog_freeze();
+            }
+            (name, AuditAction::ContainerFrozen, result)
+        }
+        WatchdogAction::Unfreeze(name) => {
+            info!(
+                "Host available memory recovered to {} MiB, unfreezing container {}",
+                available_bytes / MIB,
+                name
+            );
+            let result = ContainerManager::unfreeze(name).await;
+            if result.is_ok() {
+                metrics.record_watchdog_unfreeze();
+            }
+            (name, AuditAction::ContainerUnfrozen, result)
+        }
+    };
+
+    let succeeded = result.is_ok();
+    let audit_result = match result {
+        Ok(()) => AuditResult::Success,
+        Err(e) => {
+            error!("Memory watchdog action on {} failed: {}", name, e);
+            AuditResult::Failure(e.to_string())
+        }
+    };
+
+    if let Ok(log) = AuditLogger::builder()
+        .user("memory-watchdog".to_string())
+        .action(audit_action)
+        .resource_type("container".to_string())
+        .resource_id(name.clone())
+        .result(audit_result)
+        .details(format!(
+            "Host available memory: {} MiB",
+            available_bytes / MIB
+        ))
+        .build()
+    {
+        audit_logger.log_entry(log);
+    }
+
+    succeeded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> MemoryWatchdogConfig {
+        MemoryWatchdogConfig {
+            enabled: true,
+            min_available_mb: 256,
+            recovery_available_mb: 512,
+            check_interval_secs: 10,
+            critical_containers: vec!["db".to_string()],
+        }
+    }
+
+    fn usage(entries: &[(&str, u64)]) -> HashMap<String, u64> {
+        entries
+            .iter()
+            .map(|(name, mib)| (name.to_string(), mib * MIB))
+            .collect()
+    }
+
+    #[test]
+    fn test_no_action_when_memory_is_plentiful() {
+        let watchdog = MemoryWatchdog::new(config());
+        let usage = usage(&[("web", 300), ("cache", 100)]);
+        assert!(watchdog.plan(1024 * MIB, &usage).is_empty());
+    }
+
+    #[test]
+    fn test_freezes_largest_non_critical_container() {
+        let watchdog = MemoryWatchdog::new(config());
+        let usage = usage(&[("db", 900), ("web", 300), ("cache", 100)]);
+
+        // 200 MiB available -> 56 MiB deficit, covered by the largest candidate
+        let actions = watchdog.plan(200 * MIB, &usage);
+        assert_eq!(actions, vec![WatchdogAction::Freeze("web".to_string())]);
+    }
+
+    #[test]
+    fn test_freezes_until_deficit_is_covered() {
+        let watchdog = MemoryWatchdog::new(config());
+        let usage = usage(&[("web", 100), ("cache", 80), ("batch", 50)]);
+
+        // 56 MiB available -> 200 MiB deficit needs web + cache + batch
+        let actions = watchdog.plan(56 * MIB, &usage);
+        assert_eq!(
+            actions,
+            vec![
+                WatchdogAction::Freeze("web".to_string()),
+                WatchdogAction::Freeze("cache".to_string()),
+                WatchdogAction::Freeze("batch".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_critical_containers_are_never_frozen() {
+        let watchdog = MemoryWatchdog::new(config());
+        let usage = usage(&[("db", 900)]);
+        assert!(watchdog.plan(10 * MIB, &usage).is_empty());
+    }
+
+    #[test]
+    fn test_hysteresis_and_unfreeze_order() {
+        let mut watchdog = MemoryWatchdog::new(config());
+        let usage = usage(&[("web", 100), ("cache", 80)]);
+
+        for action in watchdog.plan(100 * MIB, &usage) {
+            watchdog.applied(&action);
+        }
+        assert_eq!(watchdog.frozen(), ["web", "cache"]);
+
+        // Between thresholds: nothing changes
+        assert!(watchdog.plan(300 * MIB, &usage).is_empty());
+
+        // Recovered: most recently frozen container goes first, one per check
+        let actions = watchdog.plan(600 * MIB, &usage);
+        assert_eq!(actions, vec![WatchdogAction::Unfreeze("cache".to_string())]);
+        watchdog.applied(&actions[0]);
+
+        let actions = watchdog.plan(600 * MIB, &usage);
+        assert_eq!(actions, vec![WatchdogAction::Unfreeze("web".to_string())]);
+        watchdog.applied(&actions[0]);
+        assert!(watchdog.frozen().is_empty());
+    }
+
+    #[test]
+    fn test_forgets_deleted_containers() {
+        let mut watchdog = MemoryWatchdog::new(config());
+        watchdog.applied(&WatchdogAction::Freeze("gone".to_string()));
+        watchdog.retain_known(&HashSet::new());
+        assert!(watchdog.frozen().is_empty());
+    }
+}
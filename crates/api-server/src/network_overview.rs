@@ -0,0 +1,229 @@
+/// One document describing host networking, for debugging connectivity
+///
+/// Bridges, the container interfaces attached to them, DHCP leases, nat
+/// rules and IPAM usage come from different sources. The sources are queried
+/// concurrently, and one that fails is reported under
+/// `errors` with its section left null while the rest is still returned.
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use container_manager::ContainerManager;
+use models::{InterfaceStatus, InterfaceType, NetworkInterface};
+use network::{
+    BridgeManager, DhcpLease, DhcpLeases, FirewallManager, InterfaceManager, Ipam, IpamUtilization,
+    NatRule,
+};
+
+#[derive(Debug, Serialize)]
+pub struct NetworkOverview {
+    pub bridges: Option<Vec<BridgeOverview>>,
+    /// Null when automatic addressing is not configured
+    pub ipam: Option<IpamUtilization>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BridgeOverview {
+    pub name: String,
+    pub status: InterfaceStatus,
+    /// Created through the API, so `?fix=true` on start recreates it
+    pub managed: Option<bool>,
+    pub ip_addresses: Vec<String>,
+    pub mac_address: Option<String>,
+    pub ports: Vec<BridgePort>,
+    pub dhcp_leases: Option<Vec<DhcpLease>>,
+    pub nat_rules: Option<Vec<NatRule>>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct BridgePort {
+    pub interface: String,
+    /// Running container owning the interface, if it is a container's veth
+    pub container: Option<String>,
+}
+
+/// Everything gathered about the host, before it is put together per bridge
+#[derive(Default)]
+struct Sources {
+    owners: Option<HashMap<String, String>>,
+    nat_rules: Option<Vec<NatRule>>,
+    managed: Option<Vec<String>>,
+    leases: HashMap<String, Vec<DhcpLease>>,
+}
+
+pub async fn gather(ipam: Option<Arc<Ipam>>, bridge_state: Option<PathBuf>) -> NetworkOverview {
+    let mut errors = BTreeMap::new();
+    let mut record = |section: &str, error: String| {
+        tracing::warn!("Network overview: {} unavailable: {}", section, error);
+        errors.insert(section.to_string(), error);
+    };
+
+    let (interfaces, owners, nat_rules, managed) = tokio::join!(
+        InterfaceManager::list(false),
+        ContainerManager::interface_owners(),
+        FirewallManager::nat_rules(),
+        async move {
+            bridge_state
+                .map(|path| BridgeManager::desired(&path))
+                .transpose()
+        },
+    );
+
+    let mut sources = Sources::default();
+    match owners {
+        Ok(owners) => sources.owners = Some(owners),
+        Err(e) => record("containers", e.to_string()),
+    }
+    match nat_rules {
+        Ok(rules) => sources.nat_rules = Some(rules),
+        Err(e) => record("nat_rules", e.to_string()),
+    }
+    match managed {
+        Ok(desired) => {
+            sources.managed =
+                desired.map(|desired| desired.into_iter().map(|bridge| bridge.name).collect())
+        }
+        Err(e) => record("managed_bridges", e.to_string()),
+    }
+
+    let bridges = match interfaces {
+        Ok(interfaces) => {
+            let names: Vec<String> = interfaces
+                .iter()
+                .filter(|i| i.interface_type == InterfaceType::Bridge)
+                .map(|i| i.name.clone())
+                .collect();
+            let leases = tokio::task::spawn_blocking(move || {
+                names
+                    .into_iter()
+                    .map(|name| {
+                        let leases = DhcpLeases::for_bridge(&name);
+                        (name, leases)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await
+            .unwrap_or_default();
+            for (bridge, result) in leases {
+                match result {
+                    Ok(leases) => {
+                        sources.leases.insert(bridge, leases);
+                    }
+                    Err(e) => record(&format!("dhcp_leases.{}", bridge), e.to_string()),
+                }
+            }
+            Some(compose(&interfaces, &sources))
+        }
+        Err(e) => {
+            record("interfaces", e.to_string());
+            None
+        }
+    };
+
+    NetworkOverview {
+        bridges,
+        ipam: ipam.map(|ipam| ipam.utilization()),
+        errors,
+    }
+}
+
+/// Put the gathered sources together per bridge
+fn compose(interfaces: &[NetworkInterface], sources: &Sources) -> Vec<BridgeOverview> {
+    interfaces
+        .iter()
+        .filter(|i| i.interface_type == InterfaceType::Bridge)
+        .map(|bridge| BridgeOverview {
+            name: bridge.name.clone(),
+            status: bridge.status.clone(),
+            managed: sources
+                .managed
+                .as_ref()
+                .map(|managed| managed.contains(&bridge.name)),
+            ip_addresses: bridge.ip_addresses.clone(),
+            mac_address: bridge.mac_address.clone(),
+            ports: interfaces
+                .iter()
+                .filter(|port| port.master.as_deref() == Some(bridge.name.as_str()))
+                .map(|port| BridgePort {
+                    interface: port.name.clone(),
+                    container: sources
+                        .owners
+                        .as_ref()
+                        .and_then(|owners| owners.get(&port.name).cloned()),
+                })
+                .collect(),
+            dhcp_leases: sources.leases.get(&bridge.name).cloned(),
+            nat_rules: sources.nat_rules.as_ref().map(|rules| {
+                rules
+                    .iter()
+                    .filter(|rule| rule.applies_to(&bridge.name, &bridge.ip_addresses))
+                    .cloned()
+                    .collect()
+            }),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interface(name: &str, kind: InterfaceType, master: Option<&str>) -> NetworkInterface {
+        NetworkInterface {
+            name: name.to_string(),
+            ip_addresses: if kind == InterfaceType::Bridge {
+                vec!["10.0.3.1/24".to_string()]
+            } else {
+                vec![]
+            },
+            interface_type: kind,
+            status: InterfaceStatus::Up,
+            mac_address: None,
+            master: master.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_compose_resolves_ports_and_rules() {
+        let interfaces = vec![
+            interface("eth0", InterfaceType::Physical, None),
+            interface("lxcbr0", InterfaceType::Bridge, None),
+            interface("vethA", InterfaceType::Veth, Some("lxcbr0")),
+            interface("vethB", InterfaceType::Veth, Some("lxcbr0")),
+        ];
+        let sources = Sources {
+            owners: Some([("vethA".to_string(), "web".to_string())].into()),
+            nat_rules: Some(FirewallManager::parse_nat_rules(
+                "-A POSTROUTING -s 10.0.3.0/24 ! -d 10.0.3.0/24 -j MASQUERADE\n\
+                 -A POSTROUTING -s 192.168.1.0/24 -j MASQUERADE\n",
+            )),
+            managed: None,
+            leases: HashMap::new(),
+        };
+
+        let bridges = compose(&interfaces, &sources);
+        assert_eq!(bridges.len(), 1);
+        let bridge = &bridges[0];
+        assert_eq!(
+            bridge.ports,
+            [
+                BridgePort {
+                    interface: "vethA".to_string(),
+                    container: Some("web".to_string()),
+                },
+                BridgePort {
+                    interface: "vethB".to_string(),
+                    container: None,
+                },
+            ]
+        );
+        assert_eq!(bridge.nat_rules.as_ref().unwrap().len(), 1);
+        // Unknown sources stay unknown rather than empty
+        assert_eq!(bridge.managed, None);
+        assert_eq!(bridge.dhcp_leases, None);
+    }
+}
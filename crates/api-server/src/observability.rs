@@ -1,46 +1,499 @@
 /// Observability module providing enhanced monitoring and metrics
-use actix_web::{HttpResponse, Responder};
+use actix_web::{web, HttpResponse, Responder};
+use prometheus::proto::{MetricFamily, MetricType};
+use prometheus::{
+    core::Collector, Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
 use serde_json::json;
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::time::SystemTime;
-use tracing::info;
-
-use container_manager::ContainerManager;
-use models::ContainerStatus;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::config::AppConfig;
+use crate::container_health::ContainerHealth;
+use crate::pool_usage::{PoolUsageMonitor, UsageLevel};
+use crate::readiness::{self, Gate};
+use cluster::{ClusterState, ListenerSnapshot, ListenerStats, MembershipManager, PeerHealth};
+use container_manager::{ContainerManager, LxcMonitor, MonitorMode, PrivilegeMode};
+use models::metrics::MetricsSink;
+use models::{ContainerStateChange, ContainerStatus, PoolState};
 use network::BridgeManager;
 
+/// Prefix of every exported metric name; left out of the JSON keys
+const NAMESPACE: &str = "arm_hypervisor";
+
 /// Global metrics collector
+///
+/// Every metric lives in one registry, which both the Prometheus and the
+/// JSON endpoint render. Gauges sampled at scrape time are label vectors,
+/// possibly without labels, so a value that could not be read is left out
+/// instead of being reported as zero.
 pub struct MetricsCollector {
+    registry: Registry,
+    /// Serializes scrapes, which reset and refill the sampled gauges
+    scrape: tokio::sync::Mutex<()>,
     /// Total HTTP requests received
-    pub http_requests_total: AtomicU64,
+    pub http_requests_total: IntCounter,
     /// Total HTTP errors
-    pub http_errors_total: AtomicU64,
+    pub http_errors_total: IntCounter,
+    /// Containers frozen by the memory watchdog
+    pub watchdog_freezes_total: IntCounter,
+    /// Containers unfrozen by the memory watchdog
+    pub watchdog_unfreezes_total: IntCounter,
+    /// Container creations served from the image cache
+    pub image_cache_hits_total: IntCounter,
+    /// Container creations that had to download their image
+    pub image_cache_misses_total: IntCounter,
+    /// Audit entries delivered to the external forwarder target
+    pub audit_forwarded_total: IntCounter,
+    /// Audit entries dropped or given up on by the forwarder
+    pub audit_forward_failures_total: IntCounter,
+    /// LXC commands run directly as root or through sudo, by path
+    privileged_commands: IntCounterVec,
+    /// Execution time of external commands by command name and outcome
+    command_durations: HistogramVec,
+    /// Container state changes seen by the LXC monitor, by new state
+    container_state_changes: IntCounterVec,
+    /// Failed or panicked system metric collections, by collector
+    collector_errors: IntCounterVec,
+    uptime_seconds: IntGauge,
+    cpu_count: IntGauge,
+    /// Creates, clones and snapshots holding an operation slot
+    container_operations_in_flight: IntGauge,
+    container_operations_queued: IntGauge,
+    /// Whether each system collector succeeded on the last scrape
+    collector_up: IntGaugeVec,
+    /// Seconds each running container has been up
+    container_uptime_seconds: IntGaugeVec,
+    /// Host and inventory gauges by name, without labels
+    sampled: BTreeMap<&'static str, GaugeVec>,
+    cluster_peer_rtt: GaugeVec,
+    cluster_peer_reachable: IntGaugeVec,
+    /// Peer connections to the cluster listener, by outcome
+    cluster_connections: IntCounterVec,
+    cluster_connections_active: IntGauge,
+    /// Share of each storage pool's usable space in use
+    pub storage_pool_used_percent: GaugeVec,
+    /// Storage pool usage alert level, by pool
+    pub storage_pool_alert_level: IntGaugeVec,
+    /// Last health-check result of each probed container
+    pub container_healthy: IntGaugeVec,
     /// Server start time
     pub start_time: SystemTime,
 }
 
+/// Unlabelled gauges sampled on every scrape
+const SAMPLED_GAUGES: [(&str, &str); 15] = [
+    ("system_load_1min", "System load average (1 minute)"),
+    ("system_load_5min", "System load average (5 minutes)"),
+    ("system_load_15min", "System load average (15 minutes)"),
+    ("memory_total_kb", "Total system memory in KB"),
+    ("memory_free_kb", "Free system memory in KB"),
+    ("memory_available_kb", "Available system memory in KB"),
+    ("memory_usage_percent", "Share of system memory in use"),
+    ("disk_total_kb", "Total disk space in KB"),
+    ("disk_free_kb", "Free disk space in KB"),
+    ("disk_usage_percent", "Share of disk space in use"),
+    ("containers_total", "Total number of containers"),
+    ("containers_running", "Number of running containers"),
+    ("containers_stopped", "Number of stopped containers"),
+    ("containers_error", "Number of containers in error state"),
+    ("bridges_total", "Total number of network bridges"),
+];
+
+/// Add `metric` to `registry`, returning it for recording
+fn register<T: Collector + Clone + 'static>(registry: &Registry, metric: T) -> T {
+    registry
+        .register(Box::new(metric.clone()))
+        .expect("metric names are unique");
+    metric
+}
+
 impl MetricsCollector {
     pub fn new() -> Self {
+        let registry = Registry::new_custom(Some(NAMESPACE.to_string()), None)
+            .expect("metric namespace is valid");
+        let counter =
+            |name: &str, help: &str| register(&registry, IntCounter::new(name, help).unwrap());
+        let counter_vec = |name: &str, help: &str, labels: &[&str]| {
+            register(
+                &registry,
+                IntCounterVec::new(Opts::new(name, help), labels).unwrap(),
+            )
+        };
+        let gauge_vec = |name: &str, help: &str, labels: &[&str]| {
+            register(
+                &registry,
+                GaugeVec::new(Opts::new(name, help), labels).unwrap(),
+            )
+        };
+        let int_gauge_vec = |name: &str, help: &str, labels: &[&str]| {
+            register(
+                &registry,
+                IntGaugeVec::new(Opts::new(name, help), labels).unwrap(),
+            )
+        };
+
+        let privileged_commands = counter_vec(
+            "privileged_commands_total",
+            "LXC commands run directly as root or through sudo",
+            &["path"],
+        );
+        for path in ["direct", "sudo"] {
+            privileged_commands.with_label_values(&[path]);
+        }
+        let collector_errors = counter_vec(
+            "collector_errors_total",
+            "System metric collections that failed or panicked",
+            &["collector"],
+        );
+        for collector in SYSTEM_COLLECTORS {
+            collector_errors.with_label_values(&[collector]);
+        }
+        let cluster_connections = counter_vec(
+            "cluster_connections_total",
+            "Peer connections to the cluster listener, by outcome",
+            &["result"],
+        );
+
         Self {
-            http_requests_total: AtomicU64::new(0),
-            http_errors_total: AtomicU64::new(0),
+            http_requests_total: counter("http_requests_total", "Total HTTP requests received"),
+            http_errors_total: counter("http_errors_total", "Total HTTP errors"),
+            watchdog_freezes_total: counter(
+                "watchdog_freezes_total",
+                "Containers frozen by the memory watchdog",
+            ),
+            watchdog_unfreezes_total: counter(
+                "watchdog_unfreezes_total",
+                "Containers unfrozen by the memory watchdog",
+            ),
+            image_cache_hits_total: counter(
+                "image_cache_hits_total",
+                "Container creations served from the image cache",
+            ),
+            image_cache_misses_total: counter(
+                "image_cache_misses_total",
+                "Container creations that downloaded their image",
+            ),
+            audit_forwarded_total: counter(
+                "audit_forwarded_total",
+                "Audit entries delivered to the external forwarder target",
+            ),
+            audit_forward_failures_total: counter(
+                "audit_forward_failures_total",
+                "Audit entries dropped or given up on by the forwarder",
+            ),
+            privileged_commands,
+            command_durations: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new(
+                        "command_duration_seconds",
+                        "Execution time of LXC and system commands",
+                    )
+                    .buckets(COMMAND_DURATION_BUCKETS.to_vec()),
+                    &["command", "result"],
+                )
+                .unwrap(),
+            ),
+            container_state_changes: counter_vec(
+                "container_state_changes_total",
+                "Container state changes seen by the LXC monitor",
+                &["state"],
+            ),
+            collector_errors,
+            uptime_seconds: register(
+                &registry,
+                IntGauge::new("uptime_seconds", "Server uptime in seconds").unwrap(),
+            ),
+            cpu_count: register(
+                &registry,
+                IntGauge::new("cpu_count", "Number of CPU cores").unwrap(),
+            ),
+            container_operations_in_flight: register(
+                &registry,
+                IntGauge::new(
+                    "container_operations_in_flight",
+                    "Heavy container operations currently running",
+                )
+                .unwrap(),
+            ),
+            container_operations_queued: register(
+                &registry,
+                IntGauge::new(
+                    "container_operations_queued",
+                    "Heavy container operations waiting for a slot",
+                )
+                .unwrap(),
+            ),
+            collector_up: int_gauge_vec(
+                "collector_up",
+                "Whether a system metrics collector succeeded",
+                &["collector"],
+            ),
+            container_uptime_seconds: int_gauge_vec(
+                "container_uptime_seconds",
+                "Seconds since a running container's init process started",
+                &["container"],
+            ),
+            sampled: SAMPLED_GAUGES
+                .iter()
+                .map(|(name, help)| (*name, gauge_vec(name, help, &[])))
+                .collect(),
+            cluster_peer_rtt: gauge_vec(
+                "cluster_peer_rtt_seconds",
+                "Round-trip time of the last ping to a cluster peer",
+                &["peer"],
+            ),
+            cluster_peer_reachable: int_gauge_vec(
+                "cluster_peer_reachable",
+                "Whether a cluster peer answered its last ping",
+                &["peer"],
+            ),
+            cluster_connections,
+            cluster_connections_active: register(
+                &registry,
+                IntGauge::new(
+                    "cluster_connections_active",
+                    "Peer connections the cluster listener is serving",
+                )
+                .unwrap(),
+            ),
+            storage_pool_used_percent: gauge_vec(
+                "storage_pool_used_percent",
+                "Share of a storage pool's usable space in use",
+                &["pool"],
+            ),
+            storage_pool_alert_level: int_gauge_vec(
+                "storage_pool_alert_level",
+                "Storage pool usage alert level (0 ok, 1 warning, 2 critical)",
+                &["pool"],
+            ),
+            container_healthy: int_gauge_vec(
+                "container_healthy",
+                "Whether a container passes its health check (1 healthy, 0 unhealthy)",
+                &["container"],
+            ),
+            registry,
+            scrape: tokio::sync::Mutex::new(()),
             start_time: SystemTime::now(),
         }
     }
 
     pub fn record_request(&self) {
-        self.http_requests_total.fetch_add(1, Ordering::Relaxed);
+        self.http_requests_total.inc();
     }
 
     pub fn record_error(&self) {
-        self.http_errors_total.fetch_add(1, Ordering::Relaxed);
+        self.http_errors_total.inc();
+    }
+
+    pub fn record_watchdog_freeze(&self) {
+        self.watchdog_freezes_total.inc();
+    }
+
+    pub fn record_watchdog_unfreeze(&self) {
+        self.watchdog_unfreezes_total.inc();
+    }
+
+    pub fn record_image_cache_lookup(&self, hit: bool) {
+        if hit {
+            self.image_cache_hits_total.inc();
+        } else {
+            self.image_cache_misses_total.inc();
+        }
+    }
+
+    pub fn record_audit_forward(&self, delivered: bool) {
+        if delivered {
+            self.audit_forwarded_total.inc();
+        } else {
+            self.audit_forward_failures_total.inc();
+        }
+    }
+
+    pub fn record_state_change(&self, change: &ContainerStateChange) {
+        let state = format!("{:?}", change.new_state).to_lowercase();
+        self.container_state_changes
+            .with_label_values(&[&state])
+            .inc();
+    }
+
+    pub fn record_collector_error(&self, collector: &str) {
+        self.collector_errors.with_label_values(&[collector]).inc();
+    }
+
+    /// Catch the cluster connection metrics up with the listener's counters
+    pub fn record_cluster_listener(&self, stats: ListenerSnapshot) {
+        for (result, total) in [
+            ("accepted", stats.accepted),
+            ("rejected", stats.rejected),
+            ("timed_out", stats.timed_out),
+        ] {
+            let counter = self.cluster_connections.with_label_values(&[result]);
+            counter.inc_by(total.saturating_sub(counter.get()));
+        }
+        self.cluster_connections_active.set(stats.active as i64);
     }
 
     pub fn get_uptime_seconds(&self) -> u64 {
         self.start_time.elapsed().unwrap_or_default().as_secs()
     }
+
+    /// Set the unlabelled gauge `name`, or leave it out when `value` is unknown
+    fn sample(&self, name: &str, value: Option<f64>) {
+        let gauge = &self.sampled[name];
+        gauge.reset();
+        if let Some(value) = value {
+            gauge.with_label_values(&[]).set(value);
+        }
+    }
+
+    /// Every metric in Prometheus text format
+    pub fn encode(&self) -> String {
+        let mut output = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut output)
+            .expect("metric families are well-formed");
+        String::from_utf8(output).expect("Prometheus text is UTF-8")
+    }
+
+    /// Every metric as JSON, keyed by name without the namespace
+    ///
+    /// A labelled metric becomes an object nested by label value, in label
+    /// name order; a histogram sample becomes its count and sum.
+    pub fn to_json(&self) -> serde_json::Map<String, serde_json::Value> {
+        let prefix = format!("{}_", NAMESPACE);
+        self.registry
+            .gather()
+            .iter()
+            .map(|family| {
+                let name = family.get_name();
+                (
+                    name.strip_prefix(&prefix).unwrap_or(name).to_string(),
+                    family_json(family),
+                )
+            })
+            .collect()
+    }
+}
+
+fn family_json(family: &MetricFamily) -> serde_json::Value {
+    let mut value = serde_json::Value::Null;
+    for metric in family.get_metric() {
+        let sample = match family.get_field_type() {
+            MetricType::COUNTER => json_number(metric.get_counter().get_value()),
+            MetricType::GAUGE => json_number(metric.get_gauge().get_value()),
+            MetricType::HISTOGRAM => {
+                let histogram = metric.get_histogram();
+                json!({
+                    "count": histogram.get_sample_count(),
+                    "sum": histogram.get_sample_sum()
+                })
+            }
+            _ => continue,
+        };
+        let labels: Vec<&str> = metric.get_label().iter().map(|l| l.get_value()).collect();
+        insert_json(&mut value, &labels, sample);
+    }
+    value
+}
+
+fn insert_json(slot: &mut serde_json::Value, labels: &[&str], sample: serde_json::Value) {
+    match labels.split_first() {
+        None => *slot = sample,
+        Some((label, rest)) => {
+            if !slot.is_object() {
+                *slot = serde_json::Value::Object(serde_json::Map::new());
+            }
+            let entry = slot
+                .as_object_mut()
+                .unwrap()
+                .entry(label.to_string())
+                .or_insert(serde_json::Value::Null);
+            insert_json(entry, rest, sample);
+        }
+    }
+}
+
+/// Whole values as integers, as they were counted
+fn json_number(value: f64) -> serde_json::Value {
+    if value.fract() == 0.0 && value.abs() < (1u64 << 53) as f64 {
+        json!(value as i64)
+    } else {
+        json!(value)
+    }
+}
+
+/// Count state changes from an LXC monitor subscription until the monitor
+/// goes away
+pub async fn count_state_changes(
+    mut changes: broadcast::Receiver<ContainerStateChange>,
+    metrics: Arc<MetricsCollector>,
+) {
+    loop {
+        match changes.recv().await {
+            Ok(change) => metrics.record_state_change(&change),
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Metrics missed {} container state changes", missed)
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Running names, and stopped and failed counts among `containers`
+///
+/// Taken from the LXC monitor while its view is current; otherwise LXC is
+/// asked about each container. A container the monitor has not seen was
+/// created after it started and has not been started since.
+async fn container_counts<'a>(
+    containers: &'a [String],
+    monitor: Option<&LxcMonitor>,
+) -> (Vec<&'a str>, usize, usize) {
+    let known = monitor
+        .filter(|monitor| monitor.health().is_current())
+        .map(|monitor| monitor.states());
+
+    let (mut running, mut stopped, mut failed) = (Vec::new(), 0, 0);
+    for name in containers {
+        let status = match known {
+            Some(ref states) => Ok(states
+                .get(name)
+                .cloned()
+                .unwrap_or(ContainerStatus::Stopped)),
+            None => ContainerManager::status(name).await,
+        };
+        match status {
+            Ok(ContainerStatus::Running) => running.push(name.as_str()),
+            Ok(ContainerStatus::Stopped) => stopped += 1,
+            Ok(ContainerStatus::Error) | Err(_) => failed += 1,
+            Ok(_) => {}
+        }
+    }
+    (running, stopped, failed)
+}
+
+/// Upper bounds in seconds of the command duration histogram buckets
+const COMMAND_DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+impl MetricsSink for MetricsCollector {
+    fn record_command(&self, command: &str, duration: Duration, success: bool) {
+        let result = if success { "success" } else { "failure" };
+        self.command_durations
+            .with_label_values(&[command, result])
+            .observe(duration.as_secs_f64());
+    }
+
+    fn record_privilege_path(&self, sudo: bool) {
+        let path = if sudo { "sudo" } else { "direct" };
+        self.privileged_commands.with_label_values(&[path]).inc();
+    }
 }
 
 impl Default for MetricsCollector {
@@ -49,8 +502,103 @@ impl Default for MetricsCollector {
     }
 }
 
+/// Host statistics used by the metrics endpoints; replaceable so failures
+/// can be simulated
+pub trait SystemStats: Send + Sync {
+    fn load(&self) -> Result<sys_info::LoadAvg, String>;
+    fn memory(&self) -> Result<sys_info::MemInfo, String>;
+    fn disk(&self) -> Result<sys_info::DiskInfo, String>;
+}
+
+/// [`SystemStats`] read from the host through sys-info
+pub struct SysInfoStats;
+
+impl SystemStats for SysInfoStats {
+    fn load(&self) -> Result<sys_info::LoadAvg, String> {
+        sys_info::loadavg().map_err(|e| e.to_string())
+    }
+
+    fn memory(&self) -> Result<sys_info::MemInfo, String> {
+        sys_info::mem_info().map_err(|e| e.to_string())
+    }
+
+    fn disk(&self) -> Result<sys_info::DiskInfo, String> {
+        sys_info::disk_info().map_err(|e| e.to_string())
+    }
+}
+
+/// Names of the system collectors, as used in labels and statuses
+const SYSTEM_COLLECTORS: [&str; 3] = ["load", "memory", "disk"];
+
+/// System metrics of one request; a collector that failed leaves its
+/// metrics out and says why in `collectors`
+struct SystemMetrics {
+    load: Option<sys_info::LoadAvg>,
+    memory: Option<sys_info::MemInfo>,
+    disk: Option<sys_info::DiskInfo>,
+    collectors: BTreeMap<&'static str, serde_json::Value>,
+}
+
+impl SystemMetrics {
+    fn collect(stats: &dyn SystemStats, metrics: &MetricsCollector) -> Self {
+        let mut collectors = BTreeMap::new();
+        let load = run_collector("load", || stats.load(), metrics, &mut collectors);
+        let memory = run_collector("memory", || stats.memory(), metrics, &mut collectors);
+        let disk = run_collector("disk", || stats.disk(), metrics, &mut collectors);
+        Self {
+            load,
+            memory,
+            disk,
+            collectors,
+        }
+    }
+}
+
+/// Run a collector, counting errors and panics instead of failing the request
+fn run_collector<T>(
+    name: &'static str,
+    collect: impl FnOnce() -> Result<T, String>,
+    metrics: &MetricsCollector,
+    statuses: &mut BTreeMap<&'static str, serde_json::Value>,
+) -> Option<T> {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(collect))
+        .unwrap_or_else(|_| Err("collector panicked".to_string()));
+    match result {
+        Ok(value) => {
+            statuses.insert(name, json!({"status": "ok"}));
+            Some(value)
+        }
+        Err(e) => {
+            warn!("System metrics collector {} failed: {}", name, e);
+            metrics.record_collector_error(name);
+            statuses.insert(name, json!({"status": "error", "error": e}));
+            None
+        }
+    }
+}
+
+fn system_stats(stats: &Option<web::Data<Arc<dyn SystemStats>>>) -> &dyn SystemStats {
+    match stats {
+        Some(stats) => stats.as_ref().as_ref(),
+        None => &SysInfoStats,
+    }
+}
+
+/// Liveness endpoint for load balancers and process supervisors
+///
+/// Answers as long as the process can serve requests. It deliberately calls
+/// no subsystem (LXC, sys-info, storage, cluster), so a slow or broken
+/// dependency cannot get a healthy process restarted or pulled from a
+/// target group; `/health` and `/ready` report on those.
+pub async fn liveness_check() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
 /// Enhanced health check endpoint
-pub async fn health_check() -> impl Responder {
+pub async fn health_check(
+    monitor: Option<web::Data<Arc<LxcMonitor>>>,
+    pool_usage: Option<web::Data<Arc<PoolUsageMonitor>>>,
+) -> impl Responder {
     info!("Health check requested");
 
     let mut status = HashMap::new();
@@ -67,8 +615,12 @@ pub async fn health_check() -> impl Responder {
         );
     } else {
         match ContainerManager::list().await {
-            Ok(_) => {
-                status.insert("container_manager", json!({"status": "healthy"}));
+            // No containers yet is a fresh host, not a fault
+            Ok(containers) => {
+                status.insert(
+                    "container_manager",
+                    json!({"status": "healthy", "containers": containers.len()}),
+                );
             }
             Err(e) => {
                 status.insert(
@@ -107,6 +659,58 @@ pub async fn health_check() -> impl Responder {
         }
     }
 
+    // Without LXC access every container operation fails; say why
+    if !skip_system_checks {
+        let report = crate::privileges::check();
+        let healthy = report.lxc.mode != PrivilegeMode::None;
+        overall_healthy &= healthy;
+        status.insert(
+            "privileges",
+            json!({
+                "status": if healthy { "healthy" } else { "unhealthy" },
+                "lxc": report.lxc,
+                "net_admin": report.net_admin,
+                "remediation": report.remediation(),
+            }),
+        );
+    }
+
+    // Consumers fall back to polling while the monitor restarts, so a
+    // restarting monitor degrades the node rather than failing it
+    if let Some(monitor) = monitor {
+        let health = monitor.health();
+        status.insert(
+            "lxc_monitor",
+            json!({
+                "status": if health.mode == MonitorMode::Restarting { "degraded" } else { "healthy" },
+                "monitor": health,
+            }),
+        );
+    }
+
+    // A filling pool still serves requests, so it degrades rather than fails;
+    // pools in maintenance are listed but never degrade the check
+    if let Some(pool_usage) = pool_usage {
+        let (maintenance, active): (Vec<_>, Vec<_>) = pool_usage
+            .states()
+            .into_iter()
+            .partition(|state| state.state == PoolState::Maintenance);
+        let alerts: Vec<_> = active
+            .into_iter()
+            .filter(|state| state.level != UsageLevel::Ok)
+            .map(|state| json!({"pool": state.pool, "level": state.level, "used_percent": state.used_percent}))
+            .collect();
+        let maintenance: Vec<_> = maintenance.into_iter().map(|state| state.pool).collect();
+        status.insert(
+            "storage_pools",
+            json!({
+                "status": if alerts.is_empty() { "healthy" } else { "degraded" },
+                "alerts": alerts,
+                "maintenance": maintenance,
+            }),
+        );
+    }
+
     let response = json!({
         "status": if overall_healthy { "healthy" } else { "unhealthy" },
         "timestamp": chrono::Utc::now().to_rfc3339(),
@@ -123,329 +727,391 @@ pub async fn health_check() -> impl Responder {
 
 /// Readiness check endpoint (for k8s-style readiness probes)
 /// Returns 200 if the service is ready to accept traffic
-pub async fn readiness_check() -> impl Responder {
+///
+/// Every gate that ran is listed with its result. The database, storage and
+/// cluster leader gates are toggled in `[readiness]`; without a registered
+/// config only LXC, bridges and the leader are checked. Cluster state is only
+/// registered in clustered mode, so standalone nodes skip the leader gate.
+pub async fn readiness_check(
+    app_config: Option<web::Data<AppConfig>>,
+    cluster_state: Option<web::Data<Arc<RwLock<ClusterState>>>>,
+    membership: Option<web::Data<Arc<RwLock<MembershipManager>>>>,
+    peer_health: Option<web::Data<Arc<RwLock<PeerHealth>>>>,
+) -> impl Responder {
     info!("Readiness check requested");
 
     let skip_system_checks = std::env::var("SKIP_SYSTEM_CHECKS")
         .map(|v| matches!(v.as_str(), "1" | "true" | "True" | "TRUE"))
         .unwrap_or(false);
+    let settings = app_config
+        .as_ref()
+        .map(|config| config.readiness.clone())
+        .unwrap_or_default();
+    let timeout = Duration::from_millis(settings.gate_timeout_ms);
 
-    // Check if critical services are operational
-    let container_ready = if skip_system_checks {
-        true
-    } else {
-        ContainerManager::list().await.is_ok()
-    };
-    let network_ready = if skip_system_checks {
-        true
-    } else {
-        BridgeManager::list().await.is_ok()
-    };
+    let mut gates = Vec::new();
+    if !skip_system_checks {
+        gates.push(
+            Gate::run("container_manager", "lxc_unavailable", timeout, async {
+                ContainerManager::list()
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            })
+            .await,
+        );
+        gates.push(
+            Gate::run("network_manager", "network_unavailable", timeout, async {
+                BridgeManager::list()
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            })
+            .await,
+        );
+    }
+    if let Some(ref config) = app_config {
+        if settings.check_database {
+            gates.push(
+                Gate::run(
+                    "database",
+                    "database_unreachable",
+                    timeout,
+                    readiness::check_database(&config.database),
+                )
+                .await,
+            );
+        }
+        if settings.check_storage {
+            gates.push(
+                Gate::run(
+                    "storage",
+                    "storage_unavailable",
+                    timeout,
+                    readiness::check_storage(&config.storage, &config.paths()),
+                )
+                .await,
+            );
+        }
+    }
+    if let (Some(state), true) = (cluster_state, settings.check_cluster_leader) {
+        let leader_id = state.read().unwrap().leader_id;
+        let local_node_id = membership.map(|m| m.read().unwrap().local_node_id());
+        let leader_reachable = leader_id.and_then(|leader| {
+            peer_health.and_then(|health| health.read().unwrap().get(&leader).map(|l| l.reachable))
+        });
+        gates.push(readiness::leader_gate(
+            leader_id,
+            local_node_id,
+            leader_reachable,
+        ));
+    }
 
-    if container_ready && network_ready {
-        HttpResponse::Ok().json(json!({
+    let failed = gates.iter().find(|gate| !gate.ok);
+    match failed {
+        None => HttpResponse::Ok().json(json!({
             "status": "ready",
-            "timestamp": chrono::Utc::now().to_rfc3339()
-        }))
-    } else {
-        HttpResponse::ServiceUnavailable().json(json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "gates": gates
+        })),
+        Some(gate) => HttpResponse::ServiceUnavailable().json(json!({
             "status": "not_ready",
+            "reason": gate.reason,
             "timestamp": chrono::Utc::now().to_rfc3339(),
-            "container_manager": container_ready,
-            "network_manager": network_ready
-        }))
+            "gates": gates
+        })),
     }
 }
 
-/// Enhanced metrics endpoint with JSON format
-pub async fn metrics_json(
-    metrics_collector: actix_web::web::Data<Arc<MetricsCollector>>,
-) -> impl Responder {
-    info!("Metrics (JSON) requested");
-
-    let mut metrics = HashMap::new();
-
-    // Application metrics
-    metrics.insert(
-        "http_requests_total",
-        json!(metrics_collector
-            .http_requests_total
-            .load(Ordering::Relaxed)),
-    );
-    metrics.insert(
-        "http_errors_total",
-        json!(metrics_collector.http_errors_total.load(Ordering::Relaxed)),
-    );
-    metrics.insert(
-        "uptime_seconds",
-        json!(metrics_collector.get_uptime_seconds()),
-    );
+/// Bring the gauges sampled at scrape time up to date; returns the status
+/// of each system collector
+async fn refresh(
+    metrics: &MetricsCollector,
+    stats: &dyn SystemStats,
+    monitor: Option<&LxcMonitor>,
+    peer_health: Option<&RwLock<PeerHealth>>,
+    listener: Option<&ListenerStats>,
+    pool_usage: Option<&PoolUsageMonitor>,
+    health: Option<&ContainerHealth>,
+) -> BTreeMap<&'static str, serde_json::Value> {
+    metrics
+        .uptime_seconds
+        .set(metrics.get_uptime_seconds() as i64);
+    metrics.cpu_count.set(num_cpus::get() as i64);
+    metrics
+        .container_operations_in_flight
+        .set(ContainerManager::operations_in_flight() as i64);
+    metrics
+        .container_operations_queued
+        .set(ContainerManager::operations_queued() as i64);
 
     // System metrics
-    if let Ok(load_avg) = sys_info::loadavg() {
-        metrics.insert("system_load_1min", json!(load_avg.one));
-        metrics.insert("system_load_5min", json!(load_avg.five));
-        metrics.insert("system_load_15min", json!(load_avg.fifteen));
-    }
-
-    if let Ok(mem_info) = sys_info::mem_info() {
-        metrics.insert("memory_total_kb", json!(mem_info.total));
-        metrics.insert("memory_free_kb", json!(mem_info.free));
-        metrics.insert("memory_available_kb", json!(mem_info.avail));
-        let used = mem_info.total.saturating_sub(mem_info.avail);
-        let usage_percent = if mem_info.total > 0 {
-            (used as f64 / mem_info.total as f64) * 100.0
-        } else {
-            0.0
-        };
-        metrics.insert("memory_usage_percent", json!(usage_percent));
+    let system = SystemMetrics::collect(stats, metrics);
+    metrics.collector_up.reset();
+    for (collector, status) in &system.collectors {
+        metrics
+            .collector_up
+            .with_label_values(&[collector])
+            .set(i64::from(status["status"] == "ok"));
     }
 
-    if let Ok(disk_info) = sys_info::disk_info() {
-        metrics.insert("disk_total_kb", json!(disk_info.total));
-        metrics.insert("disk_free_kb", json!(disk_info.free));
-        let used = disk_info.total.saturating_sub(disk_info.free);
-        let usage_percent = if disk_info.total > 0 {
-            (used as f64 / disk_info.total as f64) * 100.0
-        } else {
-            0.0
-        };
-        metrics.insert("disk_usage_percent", json!(usage_percent));
-    }
+    let load = system.load.as_ref();
+    metrics.sample("system_load_1min", load.map(|load| load.one));
+    metrics.sample("system_load_5min", load.map(|load| load.five));
+    metrics.sample("system_load_15min", load.map(|load| load.fifteen));
 
-    // CPU count
-    metrics.insert("cpu_count", json!(num_cpus::get()));
+    let memory = system.memory.as_ref();
+    metrics.sample("memory_total_kb", memory.map(|mem| mem.total as f64));
+    metrics.sample("memory_free_kb", memory.map(|mem| mem.free as f64));
+    metrics.sample("memory_available_kb", memory.map(|mem| mem.avail as f64));
+    metrics.sample(
+        "memory_usage_percent",
+        memory.map(|mem| percent(mem.total.saturating_sub(mem.avail), mem.total)),
+    );
+
+    let disk = system.disk.as_ref();
+    metrics.sample("disk_total_kb", disk.map(|disk| disk.total as f64));
+    metrics.sample("disk_free_kb", disk.map(|disk| disk.free as f64));
+    metrics.sample(
+        "disk_usage_percent",
+        disk.map(|disk| percent(disk.total.saturating_sub(disk.free), disk.total)),
+    );
 
     // Container metrics
-    match ContainerManager::list().await {
+    metrics.container_uptime_seconds.reset();
+    let counts = match ContainerManager::list().await {
         Ok(containers) => {
-            metrics.insert("containers_total", json!(containers.len()));
-
-            let mut running_count = 0;
-            let mut stopped_count = 0;
-            let mut error_count = 0;
-
-            for container_name in containers {
-                match ContainerManager::status(&container_name).await {
-                    Ok(status) => match status {
-                        ContainerStatus::Running => running_count += 1,
-                        ContainerStatus::Stopped => stopped_count += 1,
-                        ContainerStatus::Error => error_count += 1,
-                        _ => {}
-                    },
-                    Err(_) => error_count += 1,
+            let (running, stopped, failed) = container_counts(&containers, monitor).await;
+            for name in &running {
+                if let Ok(Some(uptime)) = ContainerManager::uptime(name).await {
+                    metrics
+                        .container_uptime_seconds
+                        .with_label_values(&[name])
+                        .set(uptime as i64);
                 }
             }
-
-            metrics.insert("containers_running", json!(running_count));
-            metrics.insert("containers_stopped", json!(stopped_count));
-            metrics.insert("containers_error", json!(error_count));
+            Some((containers.len(), running.len(), stopped, failed))
         }
-        Err(_) => {
-            metrics.insert("containers_total", json!(-1));
-        }
-    }
+        Err(_) => None,
+    };
+    metrics.sample("containers_total", counts.map(|c| c.0 as f64));
+    metrics.sample("containers_running", counts.map(|c| c.1 as f64));
+    metrics.sample("containers_stopped", counts.map(|c| c.2 as f64));
+    metrics.sample("containers_error", counts.map(|c| c.3 as f64));
 
     // Network metrics
-    match BridgeManager::list().await {
-        Ok(bridges) => {
-            metrics.insert("bridges_total", json!(bridges.len()));
-        }
-        Err(_) => {
-            metrics.insert("bridges_total", json!(-1));
+    let bridges = BridgeManager::list().await.ok();
+    metrics.sample("bridges_total", bridges.map(|bridges| bridges.len() as f64));
+
+    // Cluster peer latency, one series per peer
+    metrics.cluster_peer_rtt.reset();
+    metrics.cluster_peer_reachable.reset();
+    if let Some(health) = peer_health {
+        for (_, latency) in health.read().unwrap().peers() {
+            if let Some(rtt) = latency.rtt_seconds {
+                metrics
+                    .cluster_peer_rtt
+                    .with_label_values(&[&latency.address])
+                    .set(rtt);
+            }
+            metrics
+                .cluster_peer_reachable
+                .with_label_values(&[&latency.address])
+                .set(i64::from(latency.reachable));
         }
     }
+    if let Some(listener) = listener {
+        metrics.record_cluster_listener(listener.snapshot());
+    }
 
-    let response = json!({
-        "timestamp": chrono::Utc::now().to_rfc3339(),
-        "metrics": metrics
-    });
+    if let Some(pool_usage) = pool_usage {
+        pool_usage.export(metrics);
+    }
+    if let Some(health) = health {
+        health.export(metrics);
+    }
+
+    system.collectors
+}
+
+fn percent(used: u64, total: u64) -> f64 {
+    if total > 0 {
+        (used as f64 / total as f64) * 100.0
+    } else {
+        0.0
+    }
+}
+
+/// Enhanced metrics endpoint with JSON format
+///
+/// Rendered from the same registry as the Prometheus endpoint, with the
+/// metric names less their `arm_hypervisor_` prefix as keys.
+pub async fn metrics_json(
+    metrics_collector: actix_web::web::Data<Arc<MetricsCollector>>,
+    peer_health: Option<web::Data<Arc<RwLock<PeerHealth>>>>,
+    listener: Option<web::Data<Arc<ListenerStats>>>,
+    monitor: Option<web::Data<Arc<LxcMonitor>>>,
+    pool_usage: Option<web::Data<Arc<PoolUsageMonitor>>>,
+    health: Option<web::Data<Arc<ContainerHealth>>>,
+    stats: Option<web::Data<Arc<dyn SystemStats>>>,
+) -> impl Responder {
+    info!("Metrics (JSON) requested");
 
-    HttpResponse::Ok().json(response)
+    let _scrape = metrics_collector.scrape.lock().await;
+    let collectors = refresh(
+        &metrics_collector,
+        system_stats(&stats),
+        monitor.as_ref().map(|monitor| monitor.as_ref().as_ref()),
+        peer_health.as_ref().map(|health| health.as_ref().as_ref()),
+        listener.as_ref().map(|listener| listener.as_ref().as_ref()),
+        pool_usage
+            .as_ref()
+            .map(|pool_usage| pool_usage.as_ref().as_ref()),
+        health.as_ref().map(|health| health.as_ref().as_ref()),
+    )
+    .await;
+
+    HttpResponse::Ok().json(json!({
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "metrics": metrics_collector.to_json(),
+        "collectors": collectors,
+    }))
 }
 
 /// Prometheus-compatible metrics endpoint
 /// Exports metrics in Prometheus text format
 pub async fn metrics_prometheus(
     metrics_collector: actix_web::web::Data<Arc<MetricsCollector>>,
+    peer_health: Option<web::Data<Arc<RwLock<PeerHealth>>>>,
+    listener: Option<web::Data<Arc<ListenerStats>>>,
+    monitor: Option<web::Data<Arc<LxcMonitor>>>,
+    pool_usage: Option<web::Data<Arc<PoolUsageMonitor>>>,
+    health: Option<web::Data<Arc<ContainerHealth>>>,
+    stats: Option<web::Data<Arc<dyn SystemStats>>>,
 ) -> impl Responder {
     info!("Metrics (Prometheus) requested");
 
-    let mut output = String::new();
+    let _scrape = metrics_collector.scrape.lock().await;
+    refresh(
+        &metrics_collector,
+        system_stats(&stats),
+        monitor.as_ref().map(|monitor| monitor.as_ref().as_ref()),
+        peer_health.as_ref().map(|health| health.as_ref().as_ref()),
+        listener.as_ref().map(|listener| listener.as_ref().as_ref()),
+        pool_usage
+            .as_ref()
+            .map(|pool_usage| pool_usage.as_ref().as_ref()),
+        health.as_ref().map(|health| health.as_ref().as_ref()),
+    )
+    .await;
 
-    // Helper to add a metric
-    let add_metric =
-        |output: &mut String, name: &str, help: &str, metric_type: &str, value: String| {
-            output.push_str(&format!("# HELP {} {}\n", name, help));
-            output.push_str(&format!("# TYPE {} {}\n", name, metric_type));
-            output.push_str(&format!("{} {}\n", name, value));
-        };
-
-    // Application metrics
-    add_metric(
-        &mut output,
-        "arm_hypervisor_http_requests_total",
-        "Total HTTP requests received",
-        "counter",
-        metrics_collector
-            .http_requests_total
-            .load(Ordering::Relaxed)
-            .to_string(),
-    );
+    HttpResponse::Ok()
+        .content_type(TextEncoder::new().format_type())
+        .body(metrics_collector.encode())
+}
 
-    add_metric(
-        &mut output,
-        "arm_hypervisor_http_errors_total",
-        "Total HTTP errors",
-        "counter",
-        metrics_collector
-            .http_errors_total
-            .load(Ordering::Relaxed)
-            .to_string(),
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    add_metric(
-        &mut output,
-        "arm_hypervisor_uptime_seconds",
-        "Server uptime in seconds",
-        "gauge",
-        metrics_collector.get_uptime_seconds().to_string(),
-    );
+    #[test]
+    fn test_command_histogram_export() {
+        let collector = MetricsCollector::new();
+        collector.record_command("lxc-info", Duration::from_millis(30), true);
+        collector.record_command("lxc-info", Duration::from_secs(3), true);
+        collector.record_command("lxc-info", Duration::from_secs(30), false);
 
-    // System metrics
-    if let Ok(load_avg) = sys_info::loadavg() {
-        add_metric(
-            &mut output,
-            "arm_hypervisor_system_load_1min",
-            "System load average (1 minute)",
-            "gauge",
-            load_avg.one.to_string(),
-        );
-        add_metric(
-            &mut output,
-            "arm_hypervisor_system_load_5min",
-            "System load average (5 minutes)",
-            "gauge",
-            load_avg.five.to_string(),
-        );
-        add_metric(
-            &mut output,
-            "arm_hypervisor_system_load_15min",
-            "System load average (15 minutes)",
-            "gauge",
-            load_avg.fifteen.to_string(),
+        let output = collector.encode();
+        let success = "command=\"lxc-info\",result=\"success\"";
+        for line in [
+            format!("arm_hypervisor_command_duration_seconds_bucket{{{},le=\"0.025\"}} 0", success),
+            format!("arm_hypervisor_command_duration_seconds_bucket{{{},le=\"0.05\"}} 1", success),
+            format!("arm_hypervisor_command_duration_seconds_bucket{{{},le=\"5\"}} 2", success),
+            format!("arm_hypervisor_command_duration_seconds_bucket{{{},le=\"+Inf\"}} 2", success),
+            format!("arm_hypervisor_command_duration_seconds_count{{{}}} 2", success),
+            "arm_hypervisor_command_duration_seconds_bucket{command=\"lxc-info\",result=\"failure\",le=\"10\"} 0".to_string(),
+            "arm_hypervisor_command_duration_seconds_count{command=\"lxc-info\",result=\"failure\"} 1".to_string(),
+        ] {
+            assert!(output.lines().any(|l| l == line), "missing {:?} in\n{}", line, output);
+        }
+        assert_eq!(
+            output
+                .matches("# TYPE arm_hypervisor_command_duration_seconds histogram")
+                .count(),
+            1
         );
-    }
 
-    if let Ok(mem_info) = sys_info::mem_info() {
-        add_metric(
-            &mut output,
-            "arm_hypervisor_memory_total_kb",
-            "Total system memory in KB",
-            "gauge",
-            mem_info.total.to_string(),
-        );
-        add_metric(
-            &mut output,
-            "arm_hypervisor_memory_free_kb",
-            "Free system memory in KB",
-            "gauge",
-            mem_info.free.to_string(),
-        );
-        add_metric(
-            &mut output,
-            "arm_hypervisor_memory_available_kb",
-            "Available system memory in KB",
-            "gauge",
-            mem_info.avail.to_string(),
-        );
+        let metrics = collector.to_json();
+        let lxc_info = &metrics["command_duration_seconds"]["lxc-info"];
+        assert_eq!(lxc_info["success"]["count"], 2);
+        assert_eq!(lxc_info["failure"]["count"], 1);
+        assert_eq!(metrics["privileged_commands_total"]["sudo"], 0);
     }
 
-    if let Ok(disk_info) = sys_info::disk_info() {
-        add_metric(
-            &mut output,
-            "arm_hypervisor_disk_total_kb",
-            "Total disk space in KB",
-            "gauge",
-            disk_info.total.to_string(),
-        );
-        add_metric(
-            &mut output,
-            "arm_hypervisor_disk_free_kb",
-            "Free disk space in KB",
-            "gauge",
-            disk_info.free.to_string(),
-        );
-    }
+    #[actix_web::test]
+    async fn test_state_changes_are_counted() {
+        let monitor = LxcMonitor::new();
+        let collector = Arc::new(MetricsCollector::new());
+        let counter = actix_rt::spawn(count_state_changes(monitor.subscribe(), collector.clone()));
 
-    add_metric(
-        &mut output,
-        "arm_hypervisor_cpu_count",
-        "Number of CPU cores",
-        "gauge",
-        num_cpus::get().to_string(),
-    );
+        monitor.observe("web", ContainerStatus::Running);
+        monitor.observe("db", ContainerStatus::Running);
+        monitor.observe("web", ContainerStatus::Stopped);
+        drop(monitor);
+        counter.await.unwrap();
 
-    // Container metrics
-    if let Ok(containers) = ContainerManager::list().await {
-        add_metric(
-            &mut output,
-            "arm_hypervisor_containers_total",
-            "Total number of containers",
-            "gauge",
-            containers.len().to_string(),
-        );
+        let output = collector.encode();
+        assert!(output
+            .lines()
+            .any(|l| l == "arm_hypervisor_container_state_changes_total{state=\"running\"} 2"));
+        assert!(output
+            .lines()
+            .any(|l| l == "arm_hypervisor_container_state_changes_total{state=\"stopped\"} 1"));
+    }
 
-        let mut running_count = 0;
-        let mut stopped_count = 0;
-        let mut error_count = 0;
-
-        for container_name in containers {
-            if let Ok(status) = ContainerManager::status(&container_name).await {
-                match status {
-                    ContainerStatus::Running => running_count += 1,
-                    ContainerStatus::Stopped => stopped_count += 1,
-                    ContainerStatus::Error => error_count += 1,
-                    _ => {}
-                }
-            }
-        }
+    #[test]
+    fn test_cluster_listener_counters_follow_snapshots() {
+        let collector = MetricsCollector::new();
+        let mut stats = ListenerSnapshot {
+            accepted: 3,
+            rejected: 1,
+            timed_out: 0,
+            active: 2,
+        };
+        collector.record_cluster_listener(stats);
+        stats.accepted = 5;
+        stats.active = 0;
+        collector.record_cluster_listener(stats);
 
-        add_metric(
-            &mut output,
-            "arm_hypervisor_containers_running",
-            "Number of running containers",
-            "gauge",
-            running_count.to_string(),
-        );
-        add_metric(
-            &mut output,
-            "arm_hypervisor_containers_stopped",
-            "Number of stopped containers",
-            "gauge",
-            stopped_count.to_string(),
-        );
-        add_metric(
-            &mut output,
-            "arm_hypervisor_containers_error",
-            "Number of containers in error state",
-            "gauge",
-            error_count.to_string(),
-        );
+        let output = collector.encode();
+        for line in [
+            "arm_hypervisor_cluster_connections_total{result=\"accepted\"} 5",
+            "arm_hypervisor_cluster_connections_total{result=\"rejected\"} 1",
+            "arm_hypervisor_cluster_connections_total{result=\"timed_out\"} 0",
+            "arm_hypervisor_cluster_connections_active 0",
+        ] {
+            assert!(
+                output.lines().any(|l| l == line),
+                "missing {:?} in\n{}",
+                line,
+                output
+            );
+        }
     }
 
-    // Network metrics
-    if let Ok(bridges) = BridgeManager::list().await {
-        add_metric(
-            &mut output,
-            "arm_hypervisor_bridges_total",
-            "Total number of network bridges",
-            "gauge",
-            bridges.len().to_string(),
-        );
-    }
+    #[test]
+    fn test_unknown_samples_are_left_out() {
+        let collector = MetricsCollector::new();
+        collector.sample("containers_total", Some(3.0));
+        assert!(collector
+            .encode()
+            .lines()
+            .any(|l| l == "arm_hypervisor_containers_total 3"));
 
-    HttpResponse::Ok()
-        .content_type("text/plain; version=0.0.4")
-        .body(output)
+        collector.sample("containers_total", None);
+        assert!(!collector
+            .encode()
+            .contains("arm_hypervisor_containers_total"));
+        assert!(!collector.to_json().contains_key("containers_total"));
+    }
 }
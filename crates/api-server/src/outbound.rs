@@ -0,0 +1,56 @@
+/// HTTP clients for requests leaving the cluster, such as webhooks
+///
+/// They go through the proxy and trust the CA bundle of the `[downloads]`
+/// section, the same as template downloads.
+use container_manager::downloads::{self, redact_url};
+use reqwest::{Certificate, NoProxy, Proxy};
+use tracing::warn;
+
+/// Client builder with the download proxy and CA bundle applied
+///
+/// Settings that cannot be applied are logged and skipped; the config has
+/// been validated at startup.
+pub fn client_builder() -> reqwest::ClientBuilder {
+    let settings = downloads::current();
+    let mut builder = reqwest::Client::builder();
+
+    let no_proxy = || NoProxy::from_string(&settings.no_proxy.join(","));
+    let proxies = [
+        settings
+            .http_proxy
+            .as_deref()
+            .map(|url| (url, Proxy::http(url))),
+        settings
+            .https_proxy_or_http()
+            .map(|url| (url, Proxy::https(url))),
+    ];
+    for (url, proxy) in proxies.into_iter().flatten() {
+        match proxy {
+            Ok(proxy) => builder = builder.proxy(proxy.no_proxy(no_proxy())),
+            Err(e) => warn!("Ignoring proxy {}: {}", redact_url(url), e),
+        }
+    }
+
+    if let Some(path) = &settings.ca_bundle {
+        match std::fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|pem| Certificate::from_pem_bundle(&pem).map_err(|e| e.to_string()))
+        {
+            Ok(certificates) => {
+                for certificate in certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+            }
+            Err(e) => warn!("Ignoring CA bundle {}: {}", path.display(), e),
+        }
+    }
+    builder
+}
+
+/// Describe a failed request to `url`, naming the proxy it went through
+pub fn describe_error(url: &str, error: &reqwest::Error) -> String {
+    match downloads::current().proxy_for(url) {
+        Some(proxy) => format!("request failed via proxy {}: {}", redact_url(proxy), error),
+        None => error.to_string(),
+    }
+}
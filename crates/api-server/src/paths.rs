@@ -0,0 +1,193 @@
+//! Filesystem locations used by the hypervisor.
+//!
+//! Everything the server writes lives under `paths.data_dir` (logs under
+//! `paths.log_dir`) unless a section overrides an individual path, so a
+//! whole installation can be moved by changing one setting.
+
+use crate::config::AppConfig;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_DATA_DIR: &str = "/var/lib/arm-hypervisor";
+pub const DEFAULT_LOG_DIR: &str = "/var/log/arm-hypervisor";
+pub const DEFAULT_LXC_ROOT: &str = "/var/lib/lxc";
+
+/// Resolved locations, with per-path overrides applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    pub data_dir: PathBuf,
+    pub log_dir: PathBuf,
+    /// Where LXC keeps container configs and root filesystems
+    pub lxc_root: PathBuf,
+    pub storage: PathBuf,
+    pub image_cache: PathBuf,
+    pub secrets: PathBuf,
+    pub join_token: PathBuf,
+    /// This node's cluster identity, generated at first start
+    pub node_id: PathBuf,
+    /// Membership recorded once a seed admitted this node
+    pub cluster_membership: PathBuf,
+    pub firewall_rules: PathBuf,
+    pub bridge_state: PathBuf,
+    pub ipam_state: PathBuf,
+    /// States set through `PUT /storage/{name}/state`
+    pub pool_state: PathBuf,
+    /// Container event histories, when `container.persist_events` is set
+    pub container_events: PathBuf,
+    /// Users with their password hashes and sealed TOTP seeds
+    pub users: PathBuf,
+    /// API keys created through `POST /auth/api-keys`, stored hashed
+    pub api_keys: PathBuf,
+    /// Service account tokens, stored hashed
+    pub service_tokens: PathBuf,
+    /// Host interfaces each container's egress chains were applied to
+    pub egress_chains: PathBuf,
+    pub log_file: PathBuf,
+}
+
+impl Paths {
+    pub fn resolve(config: &AppConfig) -> Self {
+        let data_dir = config.paths.data_dir.clone();
+        let log_dir = config.paths.log_dir.clone();
+        let storage = config
+            .storage
+            .base_path
+            .clone()
+            .unwrap_or_else(|| data_dir.join("storage"));
+
+        Self {
+            lxc_root: config
+                .paths
+                .lxc_root
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_LXC_ROOT)),
+            image_cache: config
+                .storage
+                .image_cache_dir
+                .clone()
+                .unwrap_or_else(|| storage.join("images")),
+            secrets: config
+                .security
+                .secrets_dir
+                .as_ref()
+                .map_or_else(|| data_dir.join("secrets"), PathBuf::from),
+            join_token: config
+                .cluster
+                .join_token_path
+                .clone()
+                .unwrap_or_else(|| data_dir.join("cluster-join-token")),
+            node_id: data_dir.join("node-id"),
+            cluster_membership: data_dir.join("cluster-membership.json"),
+            firewall_rules: config
+                .network
+                .firewall_rules_path
+                .clone()
+                .unwrap_or_else(|| data_dir.join("firewall.rules")),
+            bridge_state: config
+                .network
+                .bridge_state_path
+                .clone()
+                .unwrap_or_else(|| data_dir.join("bridges.json")),
+            ipam_state: config
+                .network
+                .ipam_state_path
+                .clone()
+                .unwrap_or_else(|| data_dir.join("ipam.json")),
+            pool_state: data_dir.join("pool-states.json"),
+            container_events: data_dir.join("container-events.json"),
+            users: data_dir.join("users.json"),
+            api_keys: data_dir.join("api-keys.json"),
+            service_tokens: data_dir.join("service-tokens.json"),
+            egress_chains: data_dir.join("egress-chains.json"),
+            log_file: config
+                .logging
+                .file
+                .clone()
+                .unwrap_or_else(|| log_dir.join("hypervisor.log")),
+            storage,
+            data_dir,
+            log_dir,
+        }
+    }
+
+    /// Location of a storage pool; relative pool paths live under the storage directory
+    pub fn pool_path(&self, path: &str) -> PathBuf {
+        self.storage.join(Path::new(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_match_previous_locations() {
+        let paths = AppConfig::default().paths();
+
+        assert_eq!(paths.data_dir, Path::new(DEFAULT_DATA_DIR));
+        assert_eq!(paths.lxc_root, Path::new(DEFAULT_LXC_ROOT));
+        assert_eq!(paths.storage, Path::new("/var/lib/arm-hypervisor/storage"));
+        assert_eq!(
+            paths.ipam_state,
+            Path::new("/var/lib/arm-hypervisor/ipam.json")
+        );
+        assert_eq!(
+            paths.log_file,
+            Path::new("/var/log/arm-hypervisor/hypervisor.log")
+        );
+    }
+
+    #[test]
+    fn test_data_dir_moves_every_derived_path() {
+        let mut config = AppConfig::default();
+        config.paths.data_dir = PathBuf::from("/srv/hv");
+        config.paths.log_dir = PathBuf::from("/srv/hv/log");
+        let paths = config.paths();
+
+        for path in [
+            &paths.storage,
+            &paths.image_cache,
+            &paths.secrets,
+            &paths.join_token,
+            &paths.node_id,
+            &paths.cluster_membership,
+            &paths.firewall_rules,
+            &paths.bridge_state,
+            &paths.ipam_state,
+            &paths.pool_state,
+            &paths.container_events,
+            &paths.users,
+            &paths.api_keys,
+            &paths.service_tokens,
+            &paths.egress_chains,
+            &paths.log_file,
+        ] {
+            assert!(
+                path.starts_with("/srv/hv"),
+                "{} not relocated",
+                path.display()
+            );
+        }
+        assert_eq!(paths.image_cache, Path::new("/srv/hv/storage/images"));
+        assert_eq!(paths.log_file, Path::new("/srv/hv/log/hypervisor.log"));
+        assert_eq!(
+            paths.pool_path("default"),
+            Path::new("/srv/hv/storage/default")
+        );
+    }
+
+    #[test]
+    fn test_overrides_win_over_data_dir() {
+        let mut config = AppConfig::default();
+        config.paths.data_dir = PathBuf::from("/srv/hv");
+        config.storage.base_path = Some(PathBuf::from("/mnt/pools"));
+        config.network.ipam_state_path = Some(PathBuf::from("/etc/hv/ipam.json"));
+        let paths = config.paths();
+
+        assert_eq!(paths.storage, Path::new("/mnt/pools"));
+        assert_eq!(paths.image_cache, Path::new("/mnt/pools/images"));
+        assert_eq!(paths.ipam_state, Path::new("/etc/hv/ipam.json"));
+        assert_eq!(paths.bridge_state, Path::new("/srv/hv/bridges.json"));
+        // Absolute pool paths are used as they are
+        assert_eq!(paths.pool_path("/data/pool"), Path::new("/data/pool"));
+    }
+}
@@ -0,0 +1,187 @@
+/// Periodic round-trip measurements to the other cluster nodes, which also
+/// collect the heartbeat each node sends back
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono::Utc;
+use cluster::{
+    ClusterError, ClusterNetwork, ClusterState, ContainerSummaryLog, HeartbeatRequest,
+    HeartbeatSource, MembershipManager, PeerHealth,
+};
+use container_manager::config::LxcConfig;
+use container_manager::LxcMonitor;
+use futures::future::join_all;
+use models::{ContainerSummary, ContainerSummaryDelta, NodeHeartbeat, NodeResources};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::container_health::ContainerHealth;
+use crate::pool_usage::PoolUsageMonitor;
+
+/// Ping every known peer each `interval` and record the results
+///
+/// `Node::port` is the peer's API port; all nodes are expected to listen for
+/// cluster traffic on the same `cluster_port`. The local node's own
+/// heartbeat is recorded on every round too, so it can be scheduled on.
+pub async fn run(
+    network: ClusterNetwork,
+    cluster_port: u16,
+    membership: Arc<RwLock<MembershipManager>>,
+    health: Arc<RwLock<PeerHealth>>,
+    cluster_state: Arc<RwLock<ClusterState>>,
+    local_heartbeat: HeartbeatSource,
+    interval: Duration,
+) {
+    info!("Probing cluster peers every {}ms", interval.as_millis());
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+
+        let (local, peers): (Uuid, Vec<_>) = {
+            let membership = membership.read().unwrap();
+            let local = membership.local_node_id();
+            let peers = membership
+                .list_nodes()
+                .into_iter()
+                .filter(|n| n.id != local)
+                .map(|n| (n.id, n.address.clone()))
+                .collect();
+            (local, peers)
+        };
+
+        // Each node is asked only for the container changes not held yet
+        let request = |id: &Uuid| HeartbeatRequest {
+            containers_since: cluster_state.read().unwrap().containers_version(id),
+        };
+        let results = join_all(peers.iter().map(|(id, address)| {
+            let network = &network;
+            let request = request(id);
+            async move {
+                let result = match resolve(address, cluster_port).await {
+                    Ok(addr) => network.heartbeat(addr, &request).await,
+                    Err(e) => Err(e),
+                };
+                (*id, format!("{}:{}", address, cluster_port), result)
+            }
+        }))
+        .await;
+
+        let mut members: Vec<_> = peers.iter().map(|(id, _)| *id).collect();
+        let mut heartbeats = vec![(local, local_heartbeat(&request(&local)))];
+        {
+            let mut health = health.write().unwrap();
+            health.retain_members(&members);
+            for (id, address, result) in results {
+                let result = result.map(|(rtt, heartbeat)| {
+                    heartbeats.extend(heartbeat.map(|heartbeat| (id, heartbeat)));
+                    rtt
+                });
+                health.record(id, address, &result);
+            }
+        }
+
+        members.push(local);
+        let mut membership = membership.write().unwrap();
+        let mut state = cluster_state.write().unwrap();
+        state.retain_node_reports(&members);
+        for (id, heartbeat) in heartbeats {
+            record(&mut membership, &mut state, id, heartbeat);
+        }
+    }
+}
+
+/// Store the resources, pools and containers a node reported
+fn record(
+    membership: &mut MembershipManager,
+    state: &mut ClusterState,
+    node_id: Uuid,
+    heartbeat: NodeHeartbeat,
+) {
+    let mut resources = heartbeat.resources;
+    // Exclusive CPU pinning is accounted by the scheduler, not the node
+    if let Some(node) = membership.get_node(&node_id) {
+        resources.exclusive_cpus_allocated = node.resources.exclusive_cpus_allocated;
+    }
+    membership.update_node_resources(&node_id, resources);
+    state.set_node_pools(node_id, heartbeat.storage_pools);
+    if let Some(containers) = heartbeat.containers {
+        state.apply_container_delta(node_id, containers, Utc::now());
+    }
+}
+
+/// The containers this node reports on its heartbeats, as the LXC monitor
+/// and the health checks last saw them
+pub struct LocalContainers {
+    monitor: Arc<LxcMonitor>,
+    health: Arc<ContainerHealth>,
+    log: ContainerSummaryLog,
+}
+
+impl LocalContainers {
+    pub fn new(monitor: Arc<LxcMonitor>, health: Arc<ContainerHealth>) -> Self {
+        Self {
+            monitor,
+            health,
+            log: ContainerSummaryLog::new(),
+        }
+    }
+
+    /// Changes since version `since`, taking in the current states first
+    pub fn delta(&self, since: Option<u64>) -> ContainerSummaryDelta {
+        let summaries = self
+            .monitor
+            .states()
+            .into_iter()
+            .map(|(name, status)| ContainerSummary {
+                health: self.health.status(&name),
+                name,
+                status,
+            })
+            .collect();
+        self.log.update(summaries);
+        self.log.delta(since)
+    }
+}
+
+/// The heartbeat this node sends: host resources, the pools it can use and
+/// its containers
+pub fn local_heartbeat(
+    pool_usage: &PoolUsageMonitor,
+    containers: &LocalContainers,
+    request: &HeartbeatRequest,
+) -> NodeHeartbeat {
+    let memory = sys_info::mem_info()
+        .map_err(|e| warn!("Heartbeat could not read host memory: {}", e))
+        .ok();
+    let disk = sys_info::disk_info()
+        .map_err(|e| warn!("Heartbeat could not read host disk: {}", e))
+        .ok();
+
+    NodeHeartbeat {
+        resources: NodeResources {
+            cpu_cores: num_cpus::get() as u32,
+            memory_total: memory.as_ref().map_or(0, |m| m.total * 1024),
+            memory_used: memory
+                .as_ref()
+                .map_or(0, |m| m.total.saturating_sub(m.avail) * 1024),
+            disk_total: disk.as_ref().map_or(0, |d| d.total * 1024),
+            disk_used: disk
+                .as_ref()
+                .map_or(0, |d| d.total.saturating_sub(d.free) * 1024),
+            exclusive_cpus_allocated: 0,
+            arch: Some(LxcConfig::arch()),
+        },
+        storage_pools: pool_usage.node_pools(),
+        containers: Some(containers.delta(request.containers_since)),
+    }
+}
+
+pub(crate) async fn resolve(address: &str, port: u16) -> Result<SocketAddr, ClusterError> {
+    tokio::net::lookup_host((address, port))
+        .await?
+        .next()
+        .ok_or_else(|| ClusterError::Network(format!("No address found for {}", address)))
+}
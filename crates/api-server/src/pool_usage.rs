@@ -0,0 +1,584 @@
+/// Usage thresholds for the configured storage pools
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use models::{
+    NodeStoragePool, PoolHealth, PoolState, StoragePool, StorageType, VolumeProvisioning,
+};
+use storage::{FilesystemUsage, LocalStorageManager};
+
+use crate::audit::{AuditAction, AuditLogger, AuditResult, AuditSink, WebhookSink};
+use crate::config::{PoolConfig, PoolUsageAlertConfig, StorageConfig};
+use crate::observability::MetricsCollector;
+use crate::paths::Paths;
+use crate::tasks::TaskManager;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageLevel {
+    Ok,
+    Warning,
+    Critical,
+}
+
+impl UsageLevel {
+    /// Value of the Prometheus gauge
+    fn gauge(self) -> i64 {
+        match self {
+            UsageLevel::Ok => 0,
+            UsageLevel::Warning => 1,
+            UsageLevel::Critical => 2,
+        }
+    }
+}
+
+/// Thresholds of one pool, in percent of usable space
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Thresholds {
+    pub warning_percent: f64,
+    pub critical_percent: f64,
+    pub hysteresis_percent: f64,
+}
+
+impl Thresholds {
+    /// Global thresholds with the pool's own overrides applied
+    pub fn for_pool(alerts: &PoolUsageAlertConfig, pool: &PoolConfig) -> Self {
+        Self {
+            warning_percent: pool.warning_percent.unwrap_or(alerts.warning_percent),
+            critical_percent: pool.critical_percent.unwrap_or(alerts.critical_percent),
+            hysteresis_percent: alerts.hysteresis_percent,
+        }
+    }
+
+    fn level(&self, used_percent: f64) -> UsageLevel {
+        if used_percent >= self.critical_percent {
+            UsageLevel::Critical
+        } else if used_percent >= self.warning_percent {
+            UsageLevel::Warning
+        } else {
+            UsageLevel::Ok
+        }
+    }
+
+    /// Level after a measurement, given the level the pool was at
+    ///
+    /// Rising usage raises the level immediately; a level is only left once
+    /// usage is `hysteresis_percent` below its threshold.
+    pub fn evaluate(&self, current: UsageLevel, used_percent: f64) -> UsageLevel {
+        let raised = self.level(used_percent);
+        let lowered = self.level(used_percent + self.hysteresis_percent);
+        raised.max(current.min(lowered))
+    }
+}
+
+/// Latest measurement and alert level of a pool
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolUsageState {
+    pub pool: String,
+    pub path: String,
+    pub storage_type: String,
+    pub provisioning: VolumeProvisioning,
+    pub state: PoolState,
+    pub level: UsageLevel,
+    pub thresholds: Thresholds,
+    pub used_percent: Option<f64>,
+    pub total_bytes: u64,
+    pub used_bytes: u64,
+    pub available_bytes: u64,
+    /// When the pool entered its current level
+    pub level_since: DateTime<Utc>,
+    pub checked_at: Option<DateTime<Utc>>,
+    /// Why the last measurement failed; the level is kept until one succeeds
+    pub error: Option<String>,
+}
+
+impl PoolUsageState {
+    pub fn health(&self) -> PoolHealth {
+        if self.level == UsageLevel::Ok {
+            PoolHealth::Healthy
+        } else {
+            PoolHealth::Degraded
+        }
+    }
+}
+
+/// A level change the caller should alert on
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelChange {
+    pub pool: String,
+    pub from: UsageLevel,
+    pub to: UsageLevel,
+    pub used_percent: f64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PoolStateError {
+    #[error("Storage pool not found: {0}")]
+    NotFound(String),
+    #[error("Failed to save pool states: {0}")]
+    Save(#[from] std::io::Error),
+}
+
+/// Usage state of every configured pool, updated by [`run`]
+pub struct PoolUsageMonitor {
+    ids: BTreeMap<String, Uuid>,
+    pools: RwLock<BTreeMap<String, PoolUsageState>>,
+    created_at: DateTime<Utc>,
+    state_path: PathBuf,
+}
+
+impl PoolUsageMonitor {
+    pub fn new(config: &StorageConfig, paths: &Paths) -> Self {
+        let now = Utc::now();
+        let saved = load_states(&paths.pool_state);
+        let pools = config
+            .pool_configs
+            .iter()
+            .map(|pool| {
+                let state = PoolUsageState {
+                    pool: pool.name.clone(),
+                    path: paths.pool_path(&pool.path).display().to_string(),
+                    storage_type: pool.storage_type.clone(),
+                    provisioning: pool.provisioning,
+                    state: saved.get(&pool.name).copied().unwrap_or_default(),
+                    level: UsageLevel::Ok,
+                    thresholds: Thresholds::for_pool(&config.usage_alerts, pool),
+                    used_percent: None,
+                    total_bytes: 0,
+                    used_bytes: 0,
+                    available_bytes: 0,
+                    level_since: now,
+                    checked_at: None,
+                    error: None,
+                };
+                (pool.name.clone(), state)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        Self {
+            ids: pools
+                .keys()
+                .map(|name| (name.clone(), Uuid::new_v4()))
+                .collect(),
+            pools: RwLock::new(pools),
+            created_at: now,
+            state_path: paths.pool_state.clone(),
+        }
+    }
+
+    /// Change a pool's state and save every state; returns the previous one
+    pub fn set_state(&self, pool: &str, state: PoolState) -> Result<PoolState, PoolStateError> {
+        let mut pools = self.pools.write().unwrap();
+        let previous = pools
+            .get(pool)
+            .ok_or_else(|| PoolStateError::NotFound(pool.to_string()))?
+            .state;
+
+        let mut states: BTreeMap<_, _> = pools
+            .values()
+            .map(|existing| (existing.pool.clone(), existing.state))
+            .collect();
+        states.insert(pool.to_string(), state);
+        save_states(&self.state_path, &states)?;
+
+        pools.get_mut(pool).unwrap().state = state;
+        Ok(previous)
+    }
+
+    pub fn get(&self, pool: &str) -> Option<PoolUsageState> {
+        self.pools.read().unwrap().get(pool).cloned()
+    }
+
+    /// Every configured pool, by name
+    pub fn states(&self) -> Vec<PoolUsageState> {
+        self.pools.read().unwrap().values().cloned().collect()
+    }
+
+    /// Record a measurement; returns the level change, if any
+    pub fn record(&self, pool: &str, usage: &FilesystemUsage) -> Option<LevelChange> {
+        let mut pools = self.pools.write().unwrap();
+        let state = pools.get_mut(pool)?;
+        let used_percent = usage.used_percent();
+        let now = Utc::now();
+
+        state.used_percent = Some(used_percent);
+        state.total_bytes = usage.total_bytes;
+        state.used_bytes = usage.used_bytes;
+        state.available_bytes = usage.available_bytes;
+        state.checked_at = Some(now);
+        state.error = None;
+
+        let level = state.thresholds.evaluate(state.level, used_percent);
+        if level == state.level {
+            return None;
+        }
+        let change = LevelChange {
+            pool: pool.to_string(),
+            from: state.level,
+            to: level,
+            used_percent,
+        };
+        state.level = level;
+        state.level_since = now;
+        Some(change)
+    }
+
+    /// Record a failed measurement, keeping the last known level
+    pub fn record_error(&self, pool: &str, error: String) {
+        if let Some(state) = self.pools.write().unwrap().get_mut(pool) {
+            state.checked_at = Some(Utc::now());
+            state.error = Some(error);
+        }
+    }
+
+    /// The pool as listed by the storage API
+    pub fn pool(&self, name: &str) -> Option<StoragePool> {
+        let state = self.get(name)?;
+        Some(StoragePool {
+            id: self.ids[name],
+            name: state.pool.clone(),
+            storage_type: match state.storage_type.as_str() {
+                "nfs" => StorageType::Nfs,
+                "cifs" => StorageType::Cifs,
+                _ => StorageType::Local,
+            },
+            path: state.path.clone(),
+            total_size: state.total_bytes,
+            used_size: state.used_bytes,
+            available_size: state.available_bytes,
+            created_at: self.created_at,
+            health: state.health(),
+            provisioning: state.provisioning,
+            state: state.state,
+        })
+    }
+
+    pub fn pools(&self) -> Vec<StoragePool> {
+        self.ids.keys().filter_map(|name| self.pool(name)).collect()
+    }
+
+    /// Pools this node can place data on, as reported in its heartbeat;
+    /// a pool whose last measurement failed is most likely not mounted, and
+    /// one in maintenance is not offered at all
+    pub fn node_pools(&self) -> Vec<NodeStoragePool> {
+        self.states()
+            .into_iter()
+            .filter(|state| state.error.is_none() && state.state != PoolState::Maintenance)
+            .filter_map(|state| self.pool(&state.pool))
+            .map(|pool| NodeStoragePool {
+                name: pool.name,
+                storage_type: pool.storage_type,
+                health: pool.health,
+                state: pool.state,
+            })
+            .collect()
+    }
+
+    /// Set the per-pool gauges of `metrics`
+    pub fn export(&self, metrics: &MetricsCollector) {
+        metrics.storage_pool_used_percent.reset();
+        metrics.storage_pool_alert_level.reset();
+        for state in self.pools.read().unwrap().values() {
+            if let Some(percent) = state.used_percent {
+                metrics
+                    .storage_pool_used_percent
+                    .with_label_values(&[&state.pool])
+                    .set(percent);
+            }
+            metrics
+                .storage_pool_alert_level
+                .with_label_values(&[&state.pool])
+                .set(state.level.gauge());
+        }
+    }
+}
+
+/// States saved by [`PoolUsageMonitor::set_state`]; a missing or unreadable
+/// file leaves every pool active
+fn load_states(path: &Path) -> BTreeMap<String, PoolState> {
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return BTreeMap::new(),
+        Err(e) => {
+            warn!("Could not read pool states {}: {}", path.display(), e);
+            return BTreeMap::new();
+        }
+    };
+    serde_json::from_slice(&content).unwrap_or_else(|e| {
+        warn!("Invalid pool states {}: {}", path.display(), e);
+        BTreeMap::new()
+    })
+}
+
+fn save_states(path: &Path, states: &BTreeMap<String, PoolState>) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let content = serde_json::to_vec_pretty(states)?;
+    // Write then rename so an interrupted save keeps the previous file
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Measure every configured pool every `check_interval_secs`
+pub fn register(
+    tasks: &TaskManager,
+    monitor: Arc<PoolUsageMonitor>,
+    alerts: PoolUsageAlertConfig,
+    audit_logger: Arc<AuditLogger>,
+) {
+    info!(
+        "Storage pool usage alerts enabled: warning at {}%, critical at {}%",
+        alerts.warning_percent, alerts.critical_percent
+    );
+
+    let webhook = Arc::new(alerts.webhook_url.clone().map(WebhookSink::new));
+    tasks.register_interval(
+        "pool-usage",
+        Duration::from_secs(alerts.check_interval_secs),
+        move || check_pools(monitor.clone(), audit_logger.clone(), webhook.clone()),
+    );
+}
+
+/// Measure every pool once, alerting on level changes; fails naming the
+/// pools that could not be measured. Pools in maintenance are measured but
+/// neither alert nor fail the check.
+async fn check_pools(
+    monitor: Arc<PoolUsageMonitor>,
+    audit_logger: Arc<AuditLogger>,
+    webhook: Arc<Option<WebhookSink>>,
+) -> Result<(), String> {
+    let mut failed = Vec::new();
+    for state in monitor.states() {
+        let path = std::path::PathBuf::from(&state.path);
+        // A hung NFS mount must not stall the runtime
+        let usage = tokio::task::spawn_blocking(move || {
+            LocalStorageManager::filesystem_usage(&path).map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+
+        let maintenance = state.state == PoolState::Maintenance;
+        match usage {
+            Ok(usage) => {
+                let change = monitor.record(&state.pool, &usage);
+                if let Some(change) = change.filter(|_| !maintenance) {
+                    alert(&change, &audit_logger, webhook.as_ref().as_ref()).await;
+                }
+            }
+            Err(e) if maintenance => {
+                info!("Storage pool {} in maintenance: {}", state.pool, e);
+                monitor.record_error(&state.pool, e);
+                continue;
+            }
+            Err(e) => {
+                warn!("Could not measure storage pool {}: {}", state.pool, e);
+                monitor.record_error(&state.pool, e);
+                failed.push(state.pool);
+                continue;
+            }
+        }
+
+        // Volume usage is served from this cache between refreshes
+        let path = std::path::PathBuf::from(&state.path);
+        if let Ok(Err(e)) =
+            tokio::task::spawn_blocking(move || storage::refresh_volume_usage(&path)).await
+        {
+            warn!("Could not measure volumes in pool {}: {}", state.pool, e);
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("could not measure {}", failed.join(", ")))
+    }
+}
+
+/// Log, audit and forward a level change
+async fn alert(change: &LevelChange, audit_logger: &AuditLogger, webhook: Option<&WebhookSink>) {
+    let message = format!(
+        "Storage pool {} usage {:.1}%: {:?} -> {:?}",
+        change.pool, change.used_percent, change.from, change.to
+    );
+    if change.to > change.from {
+        warn!("{}", message);
+    } else {
+        info!("{}", message);
+    }
+
+    let log = match AuditLogger::builder()
+        .user("pool-usage-monitor".to_string())
+        .action(AuditAction::StoragePoolUsageChanged)
+        .resource_type("storage_pool".to_string())
+        .resource_id(change.pool.clone())
+        .result(AuditResult::Success)
+        .details(message)
+        .build()
+    {
+        Ok(log) => log,
+        Err(e) => {
+            warn!("Failed to audit storage pool alert: {}", e);
+            return;
+        }
+    };
+    audit_logger.log_entry(log.clone());
+
+    if let Some(webhook) = webhook {
+        if let Err(e) = webhook.send(&log).await {
+            warn!(
+                "Failed to send storage pool alert for {}: {}",
+                change.pool, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+
+    fn thresholds() -> Thresholds {
+        Thresholds {
+            warning_percent: 80.0,
+            critical_percent: 90.0,
+            hysteresis_percent: 5.0,
+        }
+    }
+
+    fn usage(used: u64) -> FilesystemUsage {
+        FilesystemUsage {
+            total_bytes: 100,
+            used_bytes: used,
+            available_bytes: 100 - used,
+        }
+    }
+
+    #[test]
+    fn test_levels_rise_immediately() {
+        let t = thresholds();
+        assert_eq!(t.evaluate(UsageLevel::Ok, 79.9), UsageLevel::Ok);
+        assert_eq!(t.evaluate(UsageLevel::Ok, 80.0), UsageLevel::Warning);
+        assert_eq!(t.evaluate(UsageLevel::Ok, 95.0), UsageLevel::Critical);
+        assert_eq!(t.evaluate(UsageLevel::Warning, 90.0), UsageLevel::Critical);
+    }
+
+    #[test]
+    fn test_levels_clear_only_below_hysteresis() {
+        let t = thresholds();
+        assert_eq!(t.evaluate(UsageLevel::Warning, 77.0), UsageLevel::Warning);
+        assert_eq!(t.evaluate(UsageLevel::Warning, 74.9), UsageLevel::Ok);
+        assert_eq!(t.evaluate(UsageLevel::Critical, 86.0), UsageLevel::Critical);
+        assert_eq!(t.evaluate(UsageLevel::Critical, 84.0), UsageLevel::Warning);
+        assert_eq!(t.evaluate(UsageLevel::Critical, 50.0), UsageLevel::Ok);
+    }
+
+    #[test]
+    fn test_hovering_pool_alerts_once() {
+        let config = AppConfig::default();
+        let monitor = PoolUsageMonitor::new(&config.storage, &config.paths());
+
+        let changes: Vec<_> = [84, 86, 79, 85, 81, 78]
+            .into_iter()
+            .filter_map(|used| monitor.record("default", &usage(used)))
+            .collect();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].from, UsageLevel::Ok);
+        assert_eq!(changes[0].to, UsageLevel::Warning);
+
+        let state = monitor.get("default").unwrap();
+        assert_eq!(state.level, UsageLevel::Warning);
+        assert_eq!(monitor.pools()[0].health, PoolHealth::Degraded);
+
+        let cleared = monitor.record("default", &usage(60)).unwrap();
+        assert_eq!(cleared.to, UsageLevel::Ok);
+        assert_eq!(monitor.pools()[0].health, PoolHealth::Healthy);
+        assert!(monitor.record("unknown", &usage(99)).is_none());
+    }
+
+    #[test]
+    fn test_per_pool_thresholds_override_global() {
+        let mut config = AppConfig::default();
+        config.storage.pool_configs[0].warning_percent = Some(50.0);
+        let monitor = PoolUsageMonitor::new(&config.storage, &config.paths());
+
+        let thresholds = monitor.get("default").unwrap().thresholds;
+        assert_eq!(thresholds.warning_percent, 50.0);
+        assert_eq!(thresholds.critical_percent, 90.0);
+        assert!(monitor.record("default", &usage(55)).is_some());
+    }
+
+    #[test]
+    fn test_errors_keep_the_last_level() {
+        let config = AppConfig::default();
+        let monitor = PoolUsageMonitor::new(&config.storage, &config.paths());
+        monitor.record("default", &usage(95));
+        monitor.record_error("default", "stale file handle".to_string());
+
+        let state = monitor.get("default").unwrap();
+        assert_eq!(state.level, UsageLevel::Critical);
+        assert_eq!(state.error.as_deref(), Some("stale file handle"));
+
+        let metrics = MetricsCollector::new();
+        monitor.export(&metrics);
+        assert!(metrics
+            .encode()
+            .contains("arm_hypervisor_storage_pool_alert_level{pool=\"default\"} 2"));
+    }
+
+    #[test]
+    fn test_unmeasurable_pools_are_not_reported_to_the_cluster() {
+        let config = AppConfig::default();
+        let monitor = PoolUsageMonitor::new(&config.storage, &config.paths());
+        monitor.record("default", &usage(95));
+        assert_eq!(
+            monitor.node_pools(),
+            vec![NodeStoragePool {
+                name: "default".to_string(),
+                storage_type: StorageType::Local,
+                health: PoolHealth::Degraded,
+                state: PoolState::Active,
+            }]
+        );
+
+        monitor.record_error("default", "stale file handle".to_string());
+        assert!(monitor.node_pools().is_empty());
+        monitor.record("default", &usage(10));
+        assert_eq!(monitor.node_pools().len(), 1);
+    }
+
+    #[test]
+    fn test_states_are_saved_and_maintenance_pools_are_not_offered() {
+        let mut config = AppConfig::default();
+        config.paths.data_dir =
+            std::env::temp_dir().join(format!("pool_states_{}", Uuid::new_v4()));
+        let paths = config.paths();
+        let monitor = PoolUsageMonitor::new(&config.storage, &paths);
+        monitor.record("default", &usage(10));
+
+        let previous = monitor.set_state("default", PoolState::ReadOnly).unwrap();
+        assert_eq!(previous, PoolState::Active);
+        assert_eq!(monitor.node_pools()[0].state, PoolState::ReadOnly);
+        assert!(matches!(
+            monitor.set_state("unknown", PoolState::ReadOnly),
+            Err(PoolStateError::NotFound(_))
+        ));
+
+        monitor
+            .set_state("default", PoolState::Maintenance)
+            .unwrap();
+        assert!(monitor.node_pools().is_empty());
+
+        let reloaded = PoolUsageMonitor::new(&config.storage, &paths);
+        assert_eq!(reloaded.pools()[0].state, PoolState::Maintenance);
+        std::fs::remove_dir_all(&config.paths.data_dir).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+/// Startup self-check of the privileges needed to drive LXC and networking
+use serde::Serialize;
+use tracing::{info, warn};
+
+use container_manager::lxc::LxcCommand;
+use container_manager::{PrivilegeMode, PrivilegeProbe};
+use network::capabilities;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrivilegeReport {
+    pub lxc: PrivilegeProbe,
+    /// Whether bridge, VLAN and firewall changes are possible
+    pub net_admin: bool,
+}
+
+impl PrivilegeReport {
+    /// Steps that would fix what the check found, empty when nothing is missing
+    pub fn remediation(&self) -> Vec<&'static str> {
+        let mut steps = Vec::new();
+        if self.lxc.mode == PrivilegeMode::None {
+            steps.push("Install LXC (lxc-ls, lxc-create, ...) if it is missing");
+            steps.push("Run the service as root, or");
+            steps.push(
+                "allow passwordless sudo for LXC, e.g. in /etc/sudoers.d/arm-hypervisor: \
+                 <user> ALL=(root) NOPASSWD: /usr/bin/lxc-*",
+            );
+        }
+        if !self.net_admin {
+            steps.push(
+                "Grant CAP_NET_ADMIN for network operations, e.g. \
+                 AmbientCapabilities=CAP_NET_ADMIN in the systemd unit",
+            );
+        }
+        steps
+    }
+}
+
+/// Probe LXC access and CAP_NET_ADMIN; blocks while `lxc-ls` runs
+pub fn check() -> PrivilegeReport {
+    PrivilegeReport {
+        lxc: LxcCommand::probe_privileges(),
+        net_admin: capabilities::has_net_admin(),
+    }
+}
+
+/// Log the result, loudly if anything is missing
+pub fn log_report(report: &PrivilegeReport) {
+    match report.lxc.mode {
+        PrivilegeMode::Root => info!("LXC privileges: running as root"),
+        PrivilegeMode::Sudo => info!("LXC privileges: using passwordless sudo"),
+        PrivilegeMode::Fake => warn!("LXC privileges: not needed - using the FAKE backend"),
+        PrivilegeMode::None => warn!(
+            "LXC privileges: NONE - container operations will fail ({})",
+            report.lxc.error.as_deref().unwrap_or("unknown error")
+        ),
+    }
+    if !report.net_admin {
+        warn!("CAP_NET_ADMIN is missing - bridge, VLAN and firewall operations will fail");
+    }
+    for step in report.remediation() {
+        warn!("  remediation: {}", step);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(mode: PrivilegeMode, net_admin: bool) -> PrivilegeReport {
+        PrivilegeReport {
+            lxc: PrivilegeProbe { mode, error: None },
+            net_admin,
+        }
+    }
+
+    #[test]
+    fn test_remediation_only_for_missing_privileges() {
+        assert!(report(PrivilegeMode::Root, true).remediation().is_empty());
+        assert_eq!(report(PrivilegeMode::Sudo, false).remediation().len(), 1);
+        assert_eq!(report(PrivilegeMode::None, true).remediation().len(), 3);
+    }
+}
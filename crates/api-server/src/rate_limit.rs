@@ -0,0 +1,88 @@
+/// Per-client request limits from `security.rate_limit`, keyed on the
+/// address [`crate::client_ip`] resolves
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::config::RateLimitConfig;
+
+/// Clients tracked before idle ones are forgotten
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Token bucket per client: `burst_size` requests at once, refilled at
+/// `requests_per_minute`
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a request from `client`'s allowance; false if it has none left
+    pub fn check(&self, client: IpAddr) -> bool {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, now: Instant) -> bool {
+        let burst = self.config.burst_size.max(1) as f64;
+        let per_sec = self.config.requests_per_minute as f64 / 60.0;
+        let refill = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            (bucket.tokens + elapsed * per_sec).min(burst)
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, bucket| refill(bucket) < burst);
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        bucket.tokens = refill(bucket);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_clients_are_limited_separately() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            requests_per_minute: 60,
+            burst_size: 2,
+        });
+        let (a, b): (IpAddr, IpAddr) =
+            ("203.0.113.7".parse().unwrap(), "10.0.0.5".parse().unwrap());
+        let now = Instant::now();
+
+        assert!(limiter.check_at(a, now));
+        assert!(limiter.check_at(a, now));
+        assert!(!limiter.check_at(a, now));
+        assert!(limiter.check_at(b, now));
+
+        // One request per second comes back
+        assert!(limiter.check_at(a, now + Duration::from_secs(1)));
+        assert!(!limiter.check_at(a, now + Duration::from_secs(1)));
+    }
+}
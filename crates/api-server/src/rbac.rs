@@ -1,8 +1,17 @@
 /// Role-Based Access Control (RBAC) module
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tracing::warn;
 use uuid::Uuid;
 
+use crate::config::LoginLockoutConfig;
+use crate::json_file;
+use crate::totp::TotpEnrollment;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum Permission {
     // Container permissions
@@ -107,11 +116,108 @@ pub struct User {
     pub role: Role,
     pub custom_permissions: Vec<Permission>,
     pub enabled: bool,
+    #[serde(default)]
+    pub kind: UserKind,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
+    /// Argon2 PHC string; users without one cannot log in with a password
+    #[serde(default, skip_serializing)]
+    pub password_hash: Option<String>,
+    #[serde(flatten, default)]
+    pub login: LoginState,
+    /// Second factor; never serialized so the sealed seed and recovery
+    /// hashes stay on the server
+    #[serde(default, skip_serializing)]
+    pub totp: Option<TotpEnrollment>,
+}
+
+/// Whether an account belongs to a person or to automation
+///
+/// Service accounts authenticate only with service tokens; they cannot log
+/// in with a password.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum UserKind {
+    #[default]
+    Human,
+    Service,
+}
+
+/// Login bookkeeping for brute-force protection
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoginState {
+    pub last_login_at: Option<DateTime<Utc>>,
+    /// Consecutive failures since the last successful login or unlock
+    pub failed_login_attempts: u32,
+    pub locked: bool,
+    /// End of a timed lock; `None` while `locked` means until an admin unlocks
+    pub locked_until: Option<DateTime<Utc>>,
 }
 
 impl User {
+    pub fn set_password(&mut self, password: &str) {
+        // UUIDv4 bytes come from the OS random source
+        let salt = SaltString::encode_b64(Uuid::new_v4().as_bytes())
+            .expect("16 bytes is a valid salt length");
+        let hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .expect("argon2 hashing with default parameters");
+        self.password_hash = Some(hash.to_string());
+    }
+
+    pub fn verify_password(&self, password: &str) -> bool {
+        self.password_hash
+            .as_deref()
+            .and_then(|hash| PasswordHash::new(hash).ok())
+            .is_some_and(|hash| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &hash)
+                    .is_ok()
+            })
+    }
+
+    /// Whether login requires a TOTP or recovery code
+    pub fn totp_required(&self) -> bool {
+        self.totp.as_ref().is_some_and(|totp| totp.confirmed)
+    }
+
+    pub fn is_locked(&self, now: DateTime<Utc>) -> bool {
+        self.login.locked && self.login.locked_until.is_none_or(|until| now < until)
+    }
+
+    /// Count a failed login, locking the account once the limit is reached
+    ///
+    /// Further failures after a timed lock expires lock it again right away.
+    pub fn record_login_failure(&mut self, policy: &LoginLockoutConfig, now: DateTime<Utc>) {
+        self.login.failed_login_attempts = self.login.failed_login_attempts.saturating_add(1);
+        if self.login.failed_login_attempts < policy.max_failed_attempts {
+            return;
+        }
+
+        let lockout_secs = if self.role == Role::Admin {
+            Some(policy.admin_lockout_secs)
+        } else {
+            (policy.lockout_secs > 0).then_some(policy.lockout_secs)
+        };
+        self.login.locked = true;
+        self.login.locked_until =
+            lockout_secs.map(|secs| now + chrono::Duration::seconds(secs as i64));
+    }
+
+    pub fn record_login_success(&mut self, now: DateTime<Utc>) {
+        self.login = LoginState {
+            last_login_at: Some(now),
+            ..LoginState::default()
+        };
+    }
+
+    pub fn unlock(&mut self) {
+        self.login = LoginState {
+            last_login_at: self.login.last_login_at,
+            ..LoginState::default()
+        };
+    }
+
     /// Check if the user has a specific permission
     #[allow(dead_code)]
     pub fn has_permission(&self, permission: &Permission) -> bool {
@@ -141,65 +247,165 @@ impl User {
     }
 }
 
-/// In-memory user store (in production, use a database)
+/// A user as saved: what is listed, plus the secrets that are never served
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredUser {
+    #[serde(flatten)]
+    user: User,
+    password_hash: Option<String>,
+    totp: Option<TotpEnrollment>,
+}
+
+/// Users by name; the default store keeps them in memory only
 pub struct UserStore {
     users: HashMap<String, User>,
+    /// Where users are saved; None keeps them in memory
+    path: Option<PathBuf>,
 }
 
 impl UserStore {
+    /// An empty store; the first admin is created by setup, see
+    /// [`crate::setup`]
     pub fn new() -> Self {
-        let mut store = Self {
+        Self {
             users: HashMap::new(),
-        };
+            path: None,
+        }
+    }
 
-        // Create default admin user
-        let admin = User {
+    /// Load the users saved at `path`; a missing or unreadable file starts
+    /// with none
+    ///
+    /// Every change is saved before it is kept, so ids, lockouts and TOTP
+    /// enrollments survive a restart and setup stays closed once an admin
+    /// exists.
+    pub fn load(path: &Path) -> Self {
+        let stored: BTreeMap<String, StoredUser> = json_file::load(path, "users");
+        let users = stored
+            .into_iter()
+            .map(|(username, stored)| {
+                let user = User {
+                    password_hash: stored.password_hash,
+                    totp: stored.totp,
+                    ..stored.user
+                };
+                (username, user)
+            })
+            .collect();
+        Self {
+            users,
+            path: Some(path.to_path_buf()),
+        }
+    }
+
+    /// Whether any enabled user holds the admin role
+    pub fn has_admin(&self) -> bool {
+        self.users
+            .values()
+            .any(|user| user.enabled && user.role == Role::Admin)
+    }
+
+    /// Create the initial admin; fails once an admin exists
+    pub fn create_admin(&mut self, username: &str, password: &str) -> Result<User, &'static str> {
+        if self.has_admin() {
+            return Err("An admin user already exists");
+        }
+        if self.users.contains_key(username) {
+            return Err("User already exists");
+        }
+        let mut admin = User {
             id: Uuid::new_v4(),
-            username: "admin".to_string(),
-            email: Some("admin@example.com".to_string()),
+            username: username.to_string(),
+            email: None,
             role: Role::Admin,
             custom_permissions: vec![],
             enabled: true,
+            kind: UserKind::Human,
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
+            password_hash: None,
+            login: LoginState::default(),
+            totp: None,
         };
-        store.users.insert("admin".to_string(), admin);
-
-        store
+        admin.set_password(password);
+        self.add_user(admin.clone())?;
+        Ok(admin)
     }
 
     pub fn get_user(&self, username: &str) -> Option<&User> {
         self.users.get(username)
     }
 
-    pub fn add_user(&mut self, user: User) {
-        self.users.insert(user.username.clone(), user);
+    pub fn add_user(&mut self, user: User) -> Result<(), &'static str> {
+        let username = user.username.clone();
+        let previous = self.users.insert(username.clone(), user);
+        self.save()
+            .inspect_err(|_| self.restore(&username, previous))
     }
 
     pub fn update_user(&mut self, username: &str, user: User) -> Result<(), &'static str> {
-        if self.users.contains_key(username) {
-            self.users.insert(username.to_string(), user);
-            Ok(())
-        } else {
-            Err("User not found")
+        if !self.users.contains_key(username) {
+            return Err("User not found");
         }
+        let previous = self.users.insert(username.to_string(), user);
+        self.save()
+            .inspect_err(|_| self.restore(username, previous))
     }
 
     pub fn delete_user(&mut self, username: &str) -> Result<(), &'static str> {
-        if username == "admin" {
-            return Err("Cannot delete admin user");
+        let admins = self
+            .users
+            .values()
+            .filter(|user| user.enabled && user.role == Role::Admin)
+            .count();
+        let is_admin = self
+            .users
+            .get(username)
+            .is_some_and(|user| user.enabled && user.role == Role::Admin);
+        if is_admin && admins == 1 {
+            return Err("Cannot delete the last admin user");
         }
 
-        if self.users.remove(username).is_some() {
-            Ok(())
-        } else {
-            Err("User not found")
-        }
+        let Some(previous) = self.users.remove(username) else {
+            return Err("User not found");
+        };
+        self.save()
+            .inspect_err(|_| self.restore(username, Some(previous)))
     }
 
     pub fn list_users(&self) -> Vec<&User> {
         self.users.values().collect()
     }
+
+    /// Undo a change that could not be saved
+    fn restore(&mut self, username: &str, previous: Option<User>) {
+        match previous {
+            Some(user) => self.users.insert(username.to_string(), user),
+            None => self.users.remove(username),
+        };
+    }
+
+    fn save(&self) -> Result<(), &'static str> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let stored: BTreeMap<&String, StoredUser> = self
+            .users
+            .iter()
+            .map(|(username, user)| {
+                let stored = StoredUser {
+                    user: user.clone(),
+                    password_hash: user.password_hash.clone(),
+                    totp: user.totp.clone(),
+                };
+                (username, stored)
+            })
+            .collect();
+        json_file::save(path, &stored).map_err(|e| {
+            warn!("Could not save users {}: {}", path.display(), e);
+            "Failed to save users"
+        })
+    }
 }
 
 impl Default for UserStore {
@@ -246,8 +452,12 @@ mod tests {
             role: Role::Viewer,
             custom_permissions: vec![Permission::ContainerStart],
             enabled: true,
+            kind: UserKind::Human,
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
+            password_hash: None,
+            login: LoginState::default(),
+            totp: None,
         };
 
         assert!(user.has_permission(&Permission::ContainerRead));
@@ -264,8 +474,12 @@ mod tests {
             role: Role::Admin,
             custom_permissions: vec![],
             enabled: false,
+            kind: UserKind::Human,
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
+            password_hash: None,
+            login: LoginState::default(),
+            totp: None,
         };
 
         assert!(!user.has_permission(&Permission::ContainerRead));
@@ -275,9 +489,16 @@ mod tests {
     #[test]
     fn test_user_store() {
         let mut store = UserStore::new();
+        assert!(!store.has_admin());
 
-        // Default admin should exist
-        assert!(store.get_user("admin").is_some());
+        store.create_admin("admin", "admin-password").unwrap();
+        assert!(store.has_admin());
+        assert!(store
+            .get_user("admin")
+            .unwrap()
+            .verify_password("admin-password"));
+        // Only one admin is bootstrapped
+        assert!(store.create_admin("root", "root-password").is_err());
 
         // Add a new user
         let user = User {
@@ -287,10 +508,14 @@ mod tests {
             role: Role::Viewer,
             custom_permissions: vec![],
             enabled: true,
+            kind: UserKind::Human,
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
+            password_hash: None,
+            login: LoginState::default(),
+            totp: None,
         };
-        store.add_user(user);
+        store.add_user(user).unwrap();
 
         assert!(store.get_user("testuser").is_some());
         assert_eq!(store.list_users().len(), 2);
@@ -302,4 +527,102 @@ mod tests {
         assert!(store.delete_user("testuser").is_ok());
         assert_eq!(store.list_users().len(), 1);
     }
+
+    fn user(role: Role) -> User {
+        User {
+            id: Uuid::new_v4(),
+            username: "testuser".to_string(),
+            email: None,
+            role,
+            custom_permissions: vec![],
+            enabled: true,
+            kind: UserKind::Human,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            password_hash: None,
+            login: LoginState::default(),
+            totp: None,
+        }
+    }
+
+    #[test]
+    fn test_password_verification() {
+        let mut user = user(Role::Viewer);
+        assert!(!user.verify_password(""));
+
+        user.set_password("correct horse");
+        assert!(user.verify_password("correct horse"));
+        assert!(!user.verify_password("battery staple"));
+        // The hash never leaves the server
+        assert!(serde_json::to_value(&user).unwrap()["password_hash"].is_null());
+    }
+
+    #[test]
+    fn test_lockout_after_consecutive_failures() {
+        let policy = LoginLockoutConfig {
+            max_failed_attempts: 3,
+            lockout_secs: 0,
+            admin_lockout_secs: 60,
+        };
+        let now = chrono::Utc::now();
+
+        let mut viewer = user(Role::Viewer);
+        viewer.record_login_failure(&policy, now);
+        viewer.record_login_failure(&policy, now);
+        assert!(!viewer.is_locked(now));
+        viewer.record_login_failure(&policy, now);
+        // lockout_secs = 0 locks until an admin unlocks
+        assert!(viewer.is_locked(now + chrono::Duration::days(365)));
+
+        viewer.unlock();
+        assert!(!viewer.is_locked(now));
+        assert_eq!(viewer.login.failed_login_attempts, 0);
+
+        // Admins are only delayed
+        let mut admin = user(Role::Admin);
+        for _ in 0..3 {
+            admin.record_login_failure(&policy, now);
+        }
+        assert!(admin.is_locked(now + chrono::Duration::seconds(59)));
+        assert!(!admin.is_locked(now + chrono::Duration::seconds(60)));
+
+        admin.record_login_success(now);
+        assert_eq!(admin.login.failed_login_attempts, 0);
+        assert_eq!(admin.login.last_login_at, Some(now));
+        assert!(!admin.login.locked);
+    }
+
+    #[test]
+    fn test_users_survive_a_restart() {
+        let dir = std::env::temp_dir().join(format!("rbac_{}", Uuid::new_v4()));
+        let path = dir.join("users.json");
+
+        let mut store = UserStore::load(&path);
+        let admin = store.create_admin("admin", "admin-password").unwrap();
+        let mut viewer = user(Role::Viewer);
+        viewer.totp = Some(TotpEnrollment {
+            sealed_secret: vec![1, 2, 3],
+            confirmed: true,
+            ..TotpEnrollment::default()
+        });
+        viewer.record_login_failure(&LoginLockoutConfig::default(), chrono::Utc::now());
+        store.add_user(viewer.clone()).unwrap();
+
+        let store = UserStore::load(&path);
+        assert!(store.has_admin());
+        let loaded = store.get_user("admin").unwrap();
+        assert_eq!(loaded.id, admin.id);
+        assert!(loaded.verify_password("admin-password"));
+        let loaded = store.get_user("testuser").unwrap();
+        assert_eq!(loaded.id, viewer.id);
+        assert!(loaded.totp_required());
+        assert_eq!(loaded.totp.as_ref().unwrap().sealed_secret, vec![1, 2, 3]);
+        assert_eq!(loaded.login.failed_login_attempts, 1);
+
+        let mut store = store;
+        store.delete_user("testuser").unwrap();
+        assert!(UserStore::load(&path).get_user("testuser").is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
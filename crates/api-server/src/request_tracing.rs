@@ -1,16 +1,40 @@
+use actix_web::http::header::{HeaderName, HeaderValue};
 /// Request tracing middleware with correlation ID support
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    Error, HttpMessage,
+    Error, HttpMessage, HttpRequest,
 };
 use futures::future::LocalBoxFuture;
-use std::future::{ready, Ready};
+use std::future::{ready, Future, Ready};
 use std::sync::Arc;
 use tracing::{info, warn};
 use uuid::Uuid;
 
 use crate::observability::MetricsCollector;
 
+const CORRELATION_HEADER: &str = "X-Correlation-ID";
+
+tokio::task_local! {
+    /// Correlation ID of the request the current task works for, also set
+    /// for background jobs the request started
+    static CURRENT: Uuid;
+}
+
+/// Correlation ID `RequestTracing` assigned to `req`, if the middleware ran
+pub fn correlation_id(req: &HttpRequest) -> Option<Uuid> {
+    req.extensions().get::<Uuid>().copied()
+}
+
+/// Correlation ID of the request being served by the current task
+pub fn current_correlation_id() -> Option<Uuid> {
+    CURRENT.try_with(|id| *id).ok()
+}
+
+/// Run `future` as part of the request with `correlation_id`
+pub async fn with_correlation_id<F: Future>(correlation_id: Uuid, future: F) -> F::Output {
+    CURRENT.scope(correlation_id, future).await
+}
+
 /// Middleware for adding correlation IDs and request tracing
 pub struct RequestTracing {
     metrics: Arc<MetricsCollector>,
@@ -66,7 +90,7 @@ where
         // Generate or extract correlation ID
         let correlation_id = req
             .headers()
-            .get("X-Correlation-ID")
+            .get(CORRELATION_HEADER)
             .and_then(|h| h.to_str().ok())
             .and_then(|s| Uuid::parse_str(s).ok())
             .unwrap_or_else(Uuid::new_v4);
@@ -86,12 +110,21 @@ where
         );
 
         let metrics = self.metrics.clone();
-        let fut = self.service.call(req);
+        let fut = with_correlation_id(correlation_id, self.service.call(req));
 
         Box::pin(async move {
-            let res = fut.await;
+            let mut res = fut.await;
             let duration = start.elapsed();
 
+            // Lets the caller look up what the request did, e.g. in the audit log
+            if let Ok(response) = &mut res {
+                response.headers_mut().insert(
+                    HeaderName::from_static("x-correlation-id"),
+                    HeaderValue::from_str(&correlation_id.to_string())
+                        .expect("UUIDs are valid header values"),
+                );
+            }
+
             match &res {
                 Ok(response) => {
                     let status = response.status();
@@ -2,73 +2,261 @@ use actix_web::web;
 
 use crate::{handlers, observability};
 
+/// Every method of a path is registered on one resource, so a known path
+/// requested with another method answers 405 with an `Allow` header instead
+/// of falling through to 404. Literal segments are registered before the
+/// `{param}` they would otherwise match, e.g. `snapshots/clone` before
+/// `snapshots/{snapshot_name}`.
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api/v1")
             // Container routes
-            .route("/containers", web::get().to(handlers::list_containers))
-            .route("/containers", web::post().to(handlers::create_container))
-            .route("/containers/{id}", web::get().to(handlers::get_container))
-            .route(
-                "/containers/{id}/start",
-                web::post().to(handlers::start_container),
+            .service(
+                web::resource("/containers")
+                    .route(web::get().to(handlers::list_containers))
+                    .route(web::post().to(handlers::create_container)),
             )
-            .route(
-                "/containers/{id}/stop",
-                web::post().to(handlers::stop_container),
+            .service(
+                web::resource("/containers/{id}")
+                    .route(web::get().to(handlers::get_container))
+                    .route(web::patch().to(handlers::update_container))
+                    .route(web::delete().to(handlers::delete_container)),
             )
-            .route(
-                "/containers/{id}",
-                web::delete().to(handlers::delete_container),
+            .service(
+                web::resource("/containers/{id}/start")
+                    .route(web::post().to(handlers::start_container)),
+            )
+            .service(
+                web::resource("/containers/{id}/stop")
+                    .route(web::post().to(handlers::stop_container)),
+            )
+            .service(
+                web::resource("/containers/{id}/freeze")
+                    .route(web::post().to(handlers::freeze_container)),
+            )
+            .service(
+                web::resource("/containers/{id}/unfreeze")
+                    .route(web::post().to(handlers::unfreeze_container)),
+            )
+            .service(
+                web::resource("/containers/{id}/wait")
+                    .route(web::get().to(handlers::wait_container)),
+            )
+            .service(
+                web::resource("/containers/{id}/usage")
+                    .route(web::get().to(handlers::get_container_usage)),
+            )
+            .service(
+                web::resource("/containers/{id}/stats/history")
+                    .route(web::get().to(handlers::get_container_usage_history)),
+            )
+            .service(
+                web::resource("/containers/{id}/events")
+                    .route(web::get().to(handlers::get_container_events)),
+            )
+            .service(
+                web::resource("/containers/{id}/config")
+                    .route(web::get().to(handlers::get_container_config)),
+            )
+            .service(
+                web::resource("/containers/{id}/interfaces")
+                    .route(web::post().to(handlers::attach_container_interface)),
+            )
+            .service(
+                web::resource("/containers/{id}/interfaces/{name}")
+                    .route(web::delete().to(handlers::detach_container_interface)),
+            )
+            .service(
+                web::resource("/containers/{id}/secrets")
+                    .route(web::get().to(handlers::list_container_secrets)),
+            )
+            .service(
+                web::resource("/containers/{id}/secrets/{name}")
+                    .route(web::put().to(handlers::put_container_secret))
+                    .route(web::delete().to(handlers::delete_container_secret)),
             )
             // Snapshot routes
-            .route(
-                "/containers/{id}/snapshots",
-                web::get().to(handlers::list_snapshots),
+            .service(
+                web::resource("/containers/{id}/snapshots")
+                    .route(web::get().to(handlers::list_snapshots))
+                    .route(web::post().to(handlers::create_snapshot)),
+            )
+            .service(
+                web::resource("/containers/{id}/snapshots/restore")
+                    .route(web::post().to(handlers::restore_snapshot)),
             )
-            .route(
-                "/containers/{id}/snapshots",
-                web::post().to(handlers::create_snapshot),
+            .service(
+                web::resource("/containers/{id}/snapshots/clone")
+                    .route(web::post().to(handlers::clone_from_snapshot)),
             )
-            .route(
-                "/containers/{id}/snapshots/restore",
-                web::post().to(handlers::restore_snapshot),
+            .service(
+                web::resource("/containers/{id}/snapshots/{snapshot_name}")
+                    .route(web::delete().to(handlers::delete_snapshot)),
             )
-            .route(
-                "/containers/{id}/snapshots/{snapshot_name}",
-                web::delete().to(handlers::delete_snapshot),
+            .service(
+                web::resource("/containers/{id}/snapshots/{snapshot_name}/verify")
+                    .route(web::post().to(handlers::verify_snapshot)),
             )
-            .route(
-                "/containers/{id}/snapshots/clone",
-                web::post().to(handlers::clone_from_snapshot),
+            .service(
+                web::resource("/snapshots/batch").route(web::post().to(handlers::batch_snapshot)),
             )
             // User management routes (RBAC)
-            .route("/users", web::get().to(handlers::list_users))
-            .route("/users", web::post().to(handlers::create_user))
-            .route("/users/{username}", web::get().to(handlers::get_user))
-            .route("/users/{username}", web::put().to(handlers::update_user))
-            .route(
-                "/users/{username}",
-                web::delete().to(handlers::delete_user_handler),
+            .service(
+                web::resource("/users")
+                    .route(web::get().to(handlers::list_users))
+                    .route(web::post().to(handlers::create_user)),
+            )
+            .service(
+                web::resource("/users/{username}")
+                    .route(web::get().to(handlers::get_user))
+                    .route(web::put().to(handlers::update_user))
+                    .route(web::delete().to(handlers::delete_user_handler)),
+            )
+            .service(
+                web::resource("/users/{username}/unlock")
+                    .route(web::post().to(handlers::unlock_user)),
+            )
+            .service(
+                web::resource("/users/{username}/totp/reset")
+                    .route(web::post().to(handlers::reset_totp)),
+            )
+            .service(
+                web::resource("/users/{username}/tokens")
+                    .route(web::get().to(handlers::list_service_tokens))
+                    .route(web::post().to(handlers::create_service_token)),
+            )
+            .service(
+                web::resource("/users/{username}/tokens/{token_id}")
+                    .route(web::delete().to(handlers::revoke_service_token)),
+            )
+            // Authentication routes
+            .service(web::resource("/auth/login").route(web::post().to(handlers::login)))
+            .service(web::resource("/auth/setup").route(web::post().to(handlers::setup_admin)))
+            .service(
+                web::resource("/auth/totp/enroll").route(web::post().to(handlers::enroll_totp)),
+            )
+            .service(
+                web::resource("/auth/totp/verify").route(web::post().to(handlers::verify_totp)),
+            )
+            .service(
+                web::resource("/auth/api-keys")
+                    .route(web::get().to(handlers::list_api_keys))
+                    .route(web::post().to(handlers::create_api_key)),
+            )
+            .service(
+                web::resource("/auth/api-keys/{id}")
+                    .route(web::delete().to(handlers::revoke_api_key)),
             )
             // Audit log routes
-            .route("/audit/logs", web::get().to(handlers::get_audit_logs))
+            .service(
+                web::resource("/audit/logs")
+                    .route(web::get().to(handlers::get_audit_logs))
+                    .route(web::delete().to(handlers::purge_audit_logs)),
+            )
             // Cluster routes
-            .route("/cluster/nodes", web::get().to(handlers::list_nodes))
-            .route("/cluster/join", web::post().to(handlers::join_cluster))
-            .route("/cluster/status", web::get().to(handlers::cluster_status))
+            .service(web::resource("/cluster/nodes").route(web::get().to(handlers::list_nodes)))
+            .service(
+                web::resource("/cluster/nodes/{id}/wait").route(web::get().to(handlers::wait_node)),
+            )
+            .service(web::resource("/cluster/join").route(web::post().to(handlers::join_cluster)))
+            .service(web::resource("/cluster/leave").route(web::post().to(handlers::leave_cluster)))
+            .service(
+                web::resource("/cluster/join-tokens")
+                    .route(web::post().to(handlers::create_join_token)),
+            )
+            .service(
+                web::resource("/cluster/token/rotate")
+                    .route(web::post().to(handlers::rotate_cluster_token)),
+            )
+            .service(
+                web::resource("/cluster/status").route(web::get().to(handlers::cluster_status)),
+            )
+            .service(
+                web::resource("/cluster/containers")
+                    .route(web::get().to(handlers::list_cluster_containers)),
+            )
+            .service(
+                web::resource("/cluster/state/export")
+                    .route(web::get().to(handlers::export_cluster_state)),
+            )
+            .service(
+                web::resource("/cluster/state/import")
+                    .route(web::post().to(handlers::import_cluster_state)),
+            )
+            .service(
+                web::resource("/cluster/schedule")
+                    .route(web::post().to(handlers::schedule_container)),
+            )
+            // System routes
+            .service(web::resource("/system/info").route(web::get().to(handlers::system_info)))
+            .service(
+                web::resource("/system/shutdown").route(web::post().to(handlers::system_shutdown)),
+            )
+            .service(
+                web::resource("/system/start-all")
+                    .route(web::post().to(handlers::system_start_all)),
+            )
+            .service(
+                web::resource("/system/stop-all-containers")
+                    .route(web::post().to(handlers::system_stop_all_containers)),
+            )
+            // Job routes
+            .service(web::resource("/jobs").route(web::get().to(handlers::list_jobs)))
+            .service(web::resource("/jobs/{id}").route(web::get().to(handlers::get_job)))
+            .service(web::resource("/jobs/{id}/wait").route(web::get().to(handlers::wait_job)))
+            // Report routes
+            .service(
+                web::resource("/reports/inventory")
+                    .route(web::get().to(handlers::inventory_report)),
+            )
+            // Background task routes
+            .service(web::resource("/admin/tasks").route(web::get().to(handlers::list_tasks)))
+            .service(
+                web::resource("/admin/tasks/{name}/run").route(web::post().to(handlers::run_task)),
+            )
+            // Image cache routes
+            .service(web::resource("/images").route(web::get().to(handlers::list_images)))
+            .service(web::resource("/images/{id}").route(web::delete().to(handlers::delete_image)))
+            .service(web::resource("/templates").route(web::get().to(handlers::list_templates)))
+            .service(
+                web::resource("/templates/cache").route(web::post().to(handlers::cache_template)),
+            )
             // Storage routes
-            .route("/storage", web::get().to(handlers::list_storage_pools))
-            .route("/storage", web::post().to(handlers::create_storage_pool))
+            .service(
+                web::resource("/storage")
+                    .route(web::get().to(handlers::list_storage_pools))
+                    .route(web::post().to(handlers::create_storage_pool)),
+            )
+            .service(
+                web::resource("/storage/test-connection")
+                    .route(web::post().to(handlers::test_storage_connection)),
+            )
+            .service(
+                web::resource("/storage/{name}").route(web::get().to(handlers::get_storage_pool)),
+            )
+            .service(
+                web::resource("/storage/{name}/state")
+                    .route(web::put().to(handlers::set_storage_pool_state)),
+            )
             // Network routes
-            .route("/network", web::get().to(handlers::list_network_interfaces))
-            .route("/network/bridges", web::get().to(handlers::list_bridges))
-            .route("/network/bridges", web::post().to(handlers::create_bridge)),
+            .service(
+                web::resource("/network").route(web::get().to(handlers::list_network_interfaces)),
+            )
+            .service(
+                web::resource("/network/overview")
+                    .route(web::get().to(handlers::get_network_overview)),
+            )
+            .service(
+                web::resource("/network/bridges")
+                    .route(web::get().to(handlers::list_bridges))
+                    .route(web::post().to(handlers::create_bridge)),
+            ),
     );
 
     // Add health and metrics endpoints (outside API versioning)
-    cfg.route("/health", web::get().to(observability::health_check))
-        .route("/ready", web::get().to(observability::readiness_check))
-        .route("/metrics", web::get().to(observability::metrics_prometheus))
-        .route("/metrics/json", web::get().to(observability::metrics_json));
+    cfg.service(web::resource("/healthz").route(web::get().to(observability::liveness_check)))
+        .service(web::resource("/health").route(web::get().to(observability::health_check)))
+        .service(web::resource("/ready").route(web::get().to(observability::readiness_check)))
+        .service(web::resource("/metrics").route(web::get().to(observability::metrics_prometheus)))
+        .service(web::resource("/metrics/json").route(web::get().to(observability::metrics_json)));
 }
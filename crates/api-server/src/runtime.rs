@@ -0,0 +1,59 @@
+/// The tokio runtime the server starts on
+///
+/// Startup and the background tasks run on this runtime; each actix worker
+/// then runs its own single-threaded runtime for requests. Every one of them
+/// has a separate blocking pool for `spawn_blocking` work such as directory
+/// walks and `lxc-*` commands, capped by `server.blocking_threads`, so with
+/// `workers` workers the server may start up to (workers + 1) times that
+/// many blocking threads. Workers are CPU-bound request handling and should
+/// match the cores; blocking threads mostly wait on disks and child
+/// processes, so there can be many more of them.
+use crate::config::ServerConfig;
+use tokio::runtime::Builder;
+
+/// Builder for the main runtime under `config`
+pub fn builder(config: &ServerConfig) -> Builder {
+    let mut builder = Builder::new_current_thread();
+    builder.enable_all();
+    if let Some(threads) = config.blocking_threads {
+        builder.max_blocking_threads(threads);
+    }
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_blocking_threads_cap_the_pool() {
+        let config: ServerConfig =
+            toml::from_str("host = \"127.0.0.1\"\nport = 8080\nblocking_threads = 2\n").unwrap();
+        assert_eq!(config.blocking_threads, Some(2));
+
+        let runtime = builder(&config).build().unwrap();
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        runtime.block_on(async {
+            let tasks: Vec<_> = (0..6)
+                .map(|_| {
+                    let running = running.clone();
+                    let most = most.clone();
+                    tokio::task::spawn_blocking(move || {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        most.fetch_max(now, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(50));
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+        });
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+}
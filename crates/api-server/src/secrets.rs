@@ -0,0 +1,353 @@
+/// Encrypted-at-rest storage for container secrets
+///
+/// Each value is sealed with AES-256-GCM under a key derived from the master
+/// key file (`security.secrets_master_key_file`) and stored as
+/// `<secrets_dir>/<container>/<name>.enc` (nonce followed by ciphertext).
+/// Values are only ever decrypted to be written into the container at start.
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use container_manager::{ContainerError, ContainerManager};
+use models::SecretRef;
+
+const NONCE_LEN: usize = 12;
+const SECRET_FILE_EXT: &str = "enc";
+
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("Invalid secret or container name: {0}")]
+    InvalidName(String),
+
+    #[error("Secret not found: {0}")]
+    NotFound(String),
+
+    #[error("Secret '{0}' is referenced by the container but has no value")]
+    MissingValue(String),
+
+    #[error("Container references secrets but no secrets master key is configured")]
+    NotConfigured,
+
+    #[error("Secret could not be decrypted: {0}")]
+    Crypto(String),
+
+    #[error("Container error: {0}")]
+    Container(#[from] ContainerError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub struct SecretStore {
+    dir: PathBuf,
+    cipher: Aes256Gcm,
+}
+
+impl SecretStore {
+    /// Open a store whose key is derived from the contents of `master_key_file`
+    pub fn open(master_key_file: &Path, dir: PathBuf) -> Result<Self, SecretError> {
+        let material = fs::read(master_key_file)?;
+        if material.iter().all(|b| b.is_ascii_whitespace()) {
+            return Err(SecretError::Crypto(format!(
+                "master key file {} is empty",
+                master_key_file.display()
+            )));
+        }
+        Ok(Self::from_key_material(&material, dir))
+    }
+
+    pub fn from_key_material(material: &[u8], dir: PathBuf) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(b"arm-hypervisor/secrets/v1");
+        hasher.update(material);
+        let key = hasher.finalize();
+
+        Self {
+            dir,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        }
+    }
+
+    /// Encrypt and store a secret value, replacing any previous value
+    pub fn put(&self, container: &str, name: &str, value: &[u8]) -> Result<(), SecretError> {
+        let path = self.secret_path(container, name)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = Self::aad(container, name);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: value,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|e| SecretError::Crypto(e.to_string()))?;
+
+        let container_dir = self.dir.join(container);
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&container_dir)?;
+
+        // Write to a temp file and rename so a crash never leaves a torn value
+        let tmp = container_dir.join(format!(".{}.tmp", name));
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)?;
+        std::io::Write::write_all(&mut file, &nonce)?;
+        std::io::Write::write_all(&mut file, &ciphertext)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+
+    /// Decrypt a stored secret value
+    pub fn get(&self, container: &str, name: &str) -> Result<Vec<u8>, SecretError> {
+        let path = self.secret_path(container, name)?;
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(SecretError::NotFound(name.to_string()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if data.len() < NONCE_LEN {
+            return Err(SecretError::Crypto(format!("{} is truncated", name)));
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let aad = Self::aad(container, name);
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| SecretError::Crypto(format!("{} (wrong master key?)", name)))
+    }
+
+    pub fn delete(&self, container: &str, name: &str) -> Result<(), SecretError> {
+        let path = self.secret_path(container, name)?;
+        match fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(SecretError::NotFound(name.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remove every secret stored for a container, once it is deleted
+    pub fn remove_container(&self, container: &str) -> Result<(), SecretError> {
+        Self::validate_name(container)?;
+        match fs::remove_dir_all(self.dir.join(container)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Names of the secrets stored for a container, sorted
+    pub fn list(&self, container: &str) -> Result<Vec<SecretRef>, SecretError> {
+        Self::validate_name(container)?;
+        let entries = match fs::read_dir(self.dir.join(container)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut secrets = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SECRET_FILE_EXT) {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                secrets.push(SecretRef {
+                    name: name.to_string(),
+                });
+            }
+        }
+        secrets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(secrets)
+    }
+
+    fn secret_path(&self, container: &str, name: &str) -> Result<PathBuf, SecretError> {
+        Self::validate_name(container)?;
+        Self::validate_name(name)?;
+        Ok(self
+            .dir
+            .join(container)
+            .join(format!("{}.{}", name, SECRET_FILE_EXT)))
+    }
+
+    /// Names become path components, so only allow a conservative charset
+    fn validate_name(name: &str) -> Result<(), SecretError> {
+        let valid = !name.is_empty()
+            && name.len() <= 128
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if valid {
+            Ok(())
+        } else {
+            Err(SecretError::InvalidName(name.to_string()))
+        }
+    }
+
+    /// Encrypt a value kept outside the store, such as a TOTP seed;
+    /// `context` binds the ciphertext to its owner and purpose
+    pub fn seal(&self, context: &str, value: &[u8]) -> Result<Vec<u8>, SecretError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: value,
+                    aad: context.as_bytes(),
+                },
+            )
+            .map_err(|e| SecretError::Crypto(e.to_string()))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    /// Decrypt a value sealed with the same `context`
+    pub fn unseal(&self, context: &str, data: &[u8]) -> Result<Vec<u8>, SecretError> {
+        if data.len() < NONCE_LEN {
+            return Err(SecretError::Crypto(format!("{} is truncated", context)));
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: context.as_bytes(),
+                },
+            )
+            .map_err(|_| SecretError::Crypto(format!("{} (wrong master key?)", context)))
+    }
+
+    /// Binds each ciphertext to its container and name so files can't be swapped
+    fn aad(container: &str, name: &str) -> String {
+        format!("{}/{}", container, name)
+    }
+}
+
+/// Write a container's secrets into its rootfs ahead of start
+///
+/// Fails if the container references a secret with no stored value, or
+/// references any secret while no store is configured.
+pub async fn inject(store: Option<&SecretStore>, container: &str) -> Result<(), SecretError> {
+    let (_, config) = ContainerManager::effective_config(container).await?;
+    let refs = config.secrets;
+
+    let Some(store) = store else {
+        return if refs.is_empty() {
+            Ok(())
+        } else {
+            Err(SecretError::NotConfigured)
+        };
+    };
+
+    let stored = store.list(container)?;
+    if let Some(missing) = refs.iter().find(|r| !stored.contains(r)) {
+        return Err(SecretError::MissingValue(missing.name.clone()));
+    }
+    if stored.is_empty() {
+        return Ok(());
+    }
+
+    let mut values = Vec::with_capacity(stored.len());
+    for secret in stored {
+        let value = store.get(container, &secret.name)?;
+        values.push((secret.name, value));
+    }
+    ContainerManager::write_secrets(container, &values).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(key: &[u8]) -> (SecretStore, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("secrets_{}", uuid::Uuid::new_v4()));
+        (SecretStore::from_key_material(key, dir.clone()), dir)
+    }
+
+    #[test]
+    fn test_round_trip_is_encrypted_at_rest() {
+        let (store, dir) = store(b"master-key");
+        store.put("db", "password", b"hunter2").unwrap();
+
+        let on_disk = fs::read(dir.join("db").join("password.enc")).unwrap();
+        assert!(!on_disk.windows(7).any(|w| w == b"hunter2"));
+        assert_eq!(store.get("db", "password").unwrap(), b"hunter2");
+
+        assert_eq!(
+            store.list("db").unwrap(),
+            vec![SecretRef {
+                name: "password".to_string()
+            }]
+        );
+
+        store.delete("db", "password").unwrap();
+        assert!(matches!(
+            store.get("db", "password"),
+            Err(SecretError::NotFound(_))
+        ));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_wrong_key_and_swapped_files_fail() {
+        let (store, dir) = store(b"master-key");
+        store.put("db", "password", b"hunter2").unwrap();
+        store.put("db", "token", b"abc").unwrap();
+
+        let other = SecretStore::from_key_material(b"other-key", dir.clone());
+        assert!(matches!(
+            other.get("db", "password"),
+            Err(SecretError::Crypto(_))
+        ));
+
+        // A ciphertext moved to another name must not decrypt
+        fs::copy(
+            dir.join("db").join("password.enc"),
+            dir.join("db").join("token.enc"),
+        )
+        .unwrap();
+        assert!(matches!(
+            store.get("db", "token"),
+            Err(SecretError::Crypto(_))
+        ));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rejects_path_traversal_names() {
+        let (store, dir) = store(b"master-key");
+        for name in ["../etc", "a/b", "", ".hidden"] {
+            assert!(matches!(
+                store.put("db", name, b"x"),
+                Err(SecretError::InvalidName(_))
+            ));
+        }
+        assert!(store.list("..").is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
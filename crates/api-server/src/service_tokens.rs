@@ -0,0 +1,293 @@
+/// Long-lived tokens for service accounts
+///
+/// Tokens are session JWTs (see `auth::Claims`) that carry a `jti`. Every
+/// issued id is recorded here, and a token is only accepted while its record
+/// exists and is not revoked, so one leaked token can be killed without
+/// rotating the JWT secret. Records are saved to `paths.service_tokens` with
+/// the SHA-256 of the token, never the token itself; a presented token must
+/// match that hash as well as its id, and be presented for the account id it
+/// was issued to, which is saved with the users in `paths.users`.
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::api_keys::hash_key;
+use crate::auth::Claims;
+use crate::json_file;
+
+/// Lifetime of a token when the request does not ask for one
+pub const DEFAULT_SERVICE_TOKEN_TTL_DAYS: u32 = 365;
+/// Longest lifetime an admin may request for a service token
+pub const MAX_SERVICE_TOKEN_TTL_DAYS: u32 = 5 * 365;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceToken {
+    pub id: Uuid,
+    pub username: String,
+    /// Free-form label, e.g. the pipeline using the token
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// A token as saved: what is listed, plus what authenticates it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredToken {
+    #[serde(flatten)]
+    token: ServiceToken,
+    /// Id of the account the token was issued to; a later account reusing
+    /// the name does not inherit its tokens
+    user_id: Uuid,
+    /// Hex SHA-256 of the signed token
+    token_hash: String,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ServiceTokenError {
+    #[error("Service token not found")]
+    NotFound,
+
+    #[error("Service token has been revoked")]
+    Revoked,
+
+    #[error("Failed to sign service token: {0}")]
+    Sign(String),
+
+    #[error("Failed to save service tokens: {0}")]
+    Save(String),
+}
+
+/// Issued tokens; the default store keeps them in memory only
+#[derive(Default)]
+pub struct ServiceTokenStore {
+    tokens: Mutex<BTreeMap<Uuid, StoredToken>>,
+    /// Where tokens are saved; None keeps them in memory
+    path: Option<PathBuf>,
+}
+
+impl ServiceTokenStore {
+    /// Load the tokens saved at `path`; a missing or unreadable file starts
+    /// with none
+    ///
+    /// When a token was last used is saved along with the next issue or
+    /// revocation only.
+    pub fn load(path: &Path) -> Self {
+        Self {
+            tokens: Mutex::new(json_file::load(path, "service tokens")),
+            path: Some(path.to_path_buf()),
+        }
+    }
+
+    /// Mint a token for `username` valid for `ttl_days`, signed with the
+    /// session JWT secret
+    pub fn issue(
+        &self,
+        username: &str,
+        user_id: Uuid,
+        name: &str,
+        ttl_days: u32,
+        jwt_secret: &str,
+    ) -> Result<(String, ServiceToken), ServiceTokenError> {
+        let now = Utc::now();
+        let record = ServiceToken {
+            id: Uuid::new_v4(),
+            username: username.to_string(),
+            name: name.to_string(),
+            created_at: now,
+            expires_at: now + Duration::days(ttl_days as i64),
+            last_used_at: None,
+            revoked_at: None,
+        };
+        let claims = Claims {
+            sub: username.to_string(),
+            exp: record.expires_at.timestamp() as usize,
+            jti: Some(record.id),
+        };
+        let token = encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(jwt_secret.as_bytes()),
+        )
+        .map_err(|e| ServiceTokenError::Sign(e.to_string()))?;
+
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.insert(
+            record.id,
+            StoredToken {
+                token: record.clone(),
+                user_id,
+                token_hash: hash_key(&token),
+            },
+        );
+        if let Err(e) = self.save(&tokens) {
+            tokens.remove(&record.id);
+            return Err(e);
+        }
+        Ok((token, record))
+    }
+
+    /// Tokens issued to `username`, oldest first
+    pub fn list(&self, username: &str) -> Vec<ServiceToken> {
+        let mut tokens: Vec<_> = self
+            .tokens
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|stored| stored.token.username == username)
+            .map(|stored| stored.token.clone())
+            .collect();
+        tokens.sort_by_key(|token| token.created_at);
+        tokens
+    }
+
+    /// Revoke a token; revoking it again is a no-op
+    pub fn revoke(&self, username: &str, id: Uuid) -> Result<ServiceToken, ServiceTokenError> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let token = &mut tokens
+            .get_mut(&id)
+            .filter(|stored| stored.token.username == username)
+            .ok_or(ServiceTokenError::NotFound)?
+            .token;
+        if token.revoked_at.is_some() {
+            return Ok(token.clone());
+        }
+        token.revoked_at = Some(Utc::now());
+        let revoked = token.clone();
+        if let Err(e) = self.save(&tokens) {
+            if let Some(stored) = tokens.get_mut(&id) {
+                stored.token.revoked_at = None;
+            }
+            return Err(e);
+        }
+        Ok(revoked)
+    }
+
+    /// Check that `token`, carrying id `id`, is live, was issued by this
+    /// store and belongs to account `user_id`, recording its use
+    ///
+    /// Expiry is enforced by the JWT itself.
+    pub fn authenticate(
+        &self,
+        id: Uuid,
+        token: &str,
+        user_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<(), ServiceTokenError> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let stored = tokens
+            .get_mut(&id)
+            .filter(|stored| stored.user_id == user_id && stored.token_hash == hash_key(token))
+            .ok_or(ServiceTokenError::NotFound)?;
+        if stored.token.revoked_at.is_some() {
+            return Err(ServiceTokenError::Revoked);
+        }
+        stored.token.last_used_at = Some(now);
+        Ok(())
+    }
+
+    fn save(&self, tokens: &BTreeMap<Uuid, StoredToken>) -> Result<(), ServiceTokenError> {
+        match &self.path {
+            Some(path) => {
+                json_file::save(path, tokens).map_err(|e| ServiceTokenError::Save(e.to_string()))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_revoked_token_is_rejected() {
+        let store = ServiceTokenStore::default();
+        let user_id = Uuid::new_v4();
+        let (first_token, first) = store.issue("ci", user_id, "deploy", 30, "secret").unwrap();
+        let (second_token, second) = store.issue("ci", user_id, "backup", 30, "secret").unwrap();
+
+        assert!(store
+            .authenticate(first.id, &first_token, user_id, Utc::now())
+            .is_ok());
+        assert!(store.list("ci")[0].last_used_at.is_some());
+        // Tokens are bound to the account they were issued to
+        assert_eq!(
+            store.authenticate(first.id, &first_token, Uuid::new_v4(), Utc::now()),
+            Err(ServiceTokenError::NotFound)
+        );
+        // and to the token minted for the id
+        assert_eq!(
+            store.authenticate(first.id, &second_token, user_id, Utc::now()),
+            Err(ServiceTokenError::NotFound)
+        );
+
+        assert_eq!(
+            store.revoke("other", first.id).unwrap_err(),
+            ServiceTokenError::NotFound
+        );
+        store.revoke("ci", first.id).unwrap();
+        assert_eq!(
+            store.authenticate(first.id, &first_token, user_id, Utc::now()),
+            Err(ServiceTokenError::Revoked)
+        );
+        // Revoking one token leaves the others working
+        assert!(store
+            .authenticate(second.id, &second_token, user_id, Utc::now())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_tokens_are_stored_hashed_and_survive_a_restart() {
+        use crate::rbac::{LoginState, Role, User, UserKind, UserStore};
+
+        let dir = std::env::temp_dir().join(format!("service_tokens_{}", Uuid::new_v4()));
+        let (users_path, tokens_path) = (dir.join("users.json"), dir.join("service-tokens.json"));
+        let mut users = UserStore::load(&users_path);
+        users
+            .add_user(User {
+                id: Uuid::new_v4(),
+                username: "ci".to_string(),
+                email: None,
+                role: Role::Operator,
+                custom_permissions: vec![],
+                enabled: true,
+                kind: UserKind::Service,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+                password_hash: None,
+                login: LoginState::default(),
+                totp: None,
+            })
+            .unwrap();
+        let user_id = users.get_user("ci").unwrap().id;
+        let store = ServiceTokenStore::load(&tokens_path);
+        let (token, record) = store.issue("ci", user_id, "deploy", 30, "secret").unwrap();
+        let (revoked_token, revoked) = store.issue("ci", user_id, "old", 30, "secret").unwrap();
+        store.revoke("ci", revoked.id).unwrap();
+
+        let saved = std::fs::read_to_string(&tokens_path).unwrap();
+        assert!(!saved.contains(&token));
+        assert!(saved.contains(&hash_key(&token)));
+
+        // After a restart the account keeps its id, so its tokens still work
+        let users = UserStore::load(&users_path);
+        let user_id = users.get_user("ci").unwrap().id;
+        let store = ServiceTokenStore::load(&tokens_path);
+        assert_eq!(store.list("ci").len(), 2);
+        assert!(store
+            .authenticate(record.id, &token, user_id, Utc::now())
+            .is_ok());
+        assert_eq!(
+            store.authenticate(revoked.id, &revoked_token, user_id, Utc::now()),
+            Err(ServiceTokenError::Revoked)
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,147 @@
+/// First-boot creation of the initial admin
+///
+/// The server ships without users or default credentials. While no admin
+/// exists it is in setup mode: a one-time token, read from
+/// `ADMIN_SETUP_TOKEN` or generated and logged at startup, must be presented
+/// to `POST /api/v1/auth/setup` together with the admin's password, and every
+/// other authenticated request fails with 503. Creating the admin discards
+/// the token, and the admin is saved with the users, so setup cannot run a
+/// second time, not even after a restart.
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::rbac::{User, UserStore};
+
+/// Environment variable with a setup token chosen by the operator
+pub const SETUP_TOKEN_ENV: &str = "ADMIN_SETUP_TOKEN";
+
+pub const MIN_PASSWORD_LENGTH: usize = 12;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SetupError {
+    #[error("Setup has already been completed")]
+    AlreadyCompleted,
+
+    #[error("Invalid setup token")]
+    InvalidToken,
+
+    #[error("Password must be at least {MIN_PASSWORD_LENGTH} characters")]
+    WeakPassword,
+
+    #[error("Invalid username")]
+    InvalidUsername,
+
+    #[error("Failed to save the admin user")]
+    Save,
+}
+
+/// Whether setup is pending, and the token that completes it
+pub struct AdminSetup {
+    token: Mutex<Option<String>>,
+}
+
+impl AdminSetup {
+    /// Pending unless `users` already has an admin; without `token` a
+    /// random one is generated
+    pub fn new(users: &UserStore, token: Option<String>) -> Self {
+        let token = (!users.has_admin())
+            .then(|| token.unwrap_or_else(|| Uuid::new_v4().simple().to_string()));
+        Self {
+            token: Mutex::new(token),
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.token.lock().unwrap().is_some()
+    }
+
+    /// The token to log at startup, while setup is pending
+    pub fn token(&self) -> Option<String> {
+        self.token.lock().unwrap().clone()
+    }
+
+    /// Create the initial admin if `token` is the setup token
+    ///
+    /// The token stays locked until the admin exists, so concurrent
+    /// attempts cannot both succeed.
+    pub fn complete(
+        &self,
+        users: &Mutex<UserStore>,
+        token: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<User, SetupError> {
+        let mut pending = self.token.lock().unwrap();
+        let expected = pending.as_deref().ok_or(SetupError::AlreadyCompleted)?;
+        // Compare digests so the check does not leak a matching prefix
+        if Sha256::digest(expected.as_bytes()) != Sha256::digest(token.as_bytes()) {
+            return Err(SetupError::InvalidToken);
+        }
+        if username.trim().is_empty() || username.contains('/') {
+            return Err(SetupError::InvalidUsername);
+        }
+        if password.chars().count() < MIN_PASSWORD_LENGTH {
+            return Err(SetupError::WeakPassword);
+        }
+
+        let admin = users
+            .lock()
+            .unwrap()
+            .create_admin(username, password)
+            .map_err(|e| match e {
+                "Failed to save users" => SetupError::Save,
+                _ => SetupError::AlreadyCompleted,
+            })?;
+        *pending = None;
+        Ok(admin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setup_creates_one_admin() {
+        let users = Mutex::new(UserStore::new());
+        let setup = AdminSetup::new(&users.lock().unwrap(), Some("setup-token".to_string()));
+        assert!(setup.is_pending());
+
+        assert_eq!(
+            setup
+                .complete(&users, "guess", "admin", "long-enough-password")
+                .unwrap_err(),
+            SetupError::InvalidToken
+        );
+        assert_eq!(
+            setup
+                .complete(&users, "setup-token", "admin", "short")
+                .unwrap_err(),
+            SetupError::WeakPassword
+        );
+        assert!(setup.is_pending());
+
+        let admin = setup
+            .complete(&users, "setup-token", "admin", "long-enough-password")
+            .unwrap();
+        assert!(admin.verify_password("long-enough-password"));
+        assert!(!setup.is_pending());
+        assert_eq!(
+            setup
+                .complete(&users, "setup-token", "root", "long-enough-password")
+                .unwrap_err(),
+            SetupError::AlreadyCompleted
+        );
+    }
+
+    #[test]
+    fn test_existing_admin_skips_setup() {
+        let mut users = UserStore::new();
+        users.create_admin("admin", "from-environment").unwrap();
+        let setup = AdminSetup::new(&users, None);
+        assert!(!setup.is_pending());
+        assert_eq!(setup.token(), None);
+    }
+}
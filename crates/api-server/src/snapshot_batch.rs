@@ -0,0 +1,150 @@
+/// Snapshots of many containers in one request
+///
+/// Every container gets a snapshot with the same name so a backup set can be
+/// found and restored together. Failures are reported per container and never
+/// abort the rest of the batch.
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use container_manager::{ContainerError, ContainerManager, Snapshot, SnapshotManager};
+use models::ContainerStatus;
+
+/// Snapshots taken at the same time; each one copies a rootfs
+pub const MAX_CONCURRENT_SNAPSHOTS: usize = 4;
+
+#[derive(Debug, Deserialize)]
+pub struct BatchSnapshotRequest {
+    pub containers: Vec<String>,
+    /// Snapshots are named `<name_prefix>_<timestamp>` (default `batch`)
+    pub name_prefix: Option<String>,
+    /// Freeze running containers while their snapshot is taken
+    #[serde(default)]
+    pub quiesce: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchSnapshotResult {
+    pub container: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<Snapshot>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BatchSnapshotRequest {
+    /// Check the request and derive the snapshot name shared by the batch
+    pub fn snapshot_name(&self) -> Result<String, String> {
+        if self.containers.is_empty() {
+            return Err("containers must not be empty".to_string());
+        }
+        let prefix = self.name_prefix.as_deref().unwrap_or("batch");
+        let valid = !prefix.is_empty()
+            && prefix
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(format!("invalid name_prefix: {:?}", prefix));
+        }
+        Ok(format!(
+            "{}_{}",
+            prefix,
+            chrono::Utc::now().format("%Y%m%d_%H%M%S")
+        ))
+    }
+}
+
+/// Snapshot each listed container, at most `MAX_CONCURRENT_SNAPSHOTS` at a
+/// time; results keep the request order and duplicates are taken once
+pub async fn run(
+    containers: &[String],
+    snapshot_name: &str,
+    quiesce: bool,
+) -> Vec<BatchSnapshotResult> {
+    let mut unique = containers.to_vec();
+    let mut seen = std::collections::HashSet::new();
+    unique.retain(|name| seen.insert(name.clone()));
+
+    stream::iter(unique)
+        .map(|container| async move {
+            let result = snapshot_one(&container, snapshot_name.to_string(), quiesce).await;
+            match result {
+                Ok(snapshot) => BatchSnapshotResult {
+                    container,
+                    ok: true,
+                    snapshot: Some(snapshot),
+                    error: None,
+                },
+                Err(e) => {
+                    warn!("Batch snapshot of {} failed: {}", container, e);
+                    BatchSnapshotResult {
+                        container,
+                        ok: false,
+                        snapshot: None,
+                        error: Some(e.to_string()),
+                    }
+                }
+            }
+        })
+        .buffered(MAX_CONCURRENT_SNAPSHOTS)
+        .collect()
+        .await
+}
+
+async fn snapshot_one(
+    container: &str,
+    snapshot_name: String,
+    quiesce: bool,
+) -> Result<Snapshot, ContainerError> {
+    // Only running containers have anything to quiesce
+    let freeze = quiesce
+        && matches!(
+            ContainerManager::status(container).await?,
+            ContainerStatus::Running
+        );
+    if freeze {
+        ContainerManager::freeze(container).await?;
+    }
+
+    let result = SnapshotManager::create(container, Some(snapshot_name), None).await;
+
+    if freeze {
+        if let Err(e) = ContainerManager::unfreeze(container).await {
+            warn!("Failed to unfreeze {} after snapshot: {}", container, e);
+            // A snapshot is no consolation for a container left frozen
+            return Err(e);
+        }
+    }
+    if let Ok(ref snapshot) = result {
+        info!(
+            "Batch snapshot '{}' of {} created",
+            snapshot.name, container
+        );
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_name_validation() {
+        let request = |containers: &[&str], prefix: Option<&str>| BatchSnapshotRequest {
+            containers: containers.iter().map(|c| c.to_string()).collect(),
+            name_prefix: prefix.map(str::to_string),
+            quiesce: false,
+        };
+
+        let name = request(&["web"], Some("nightly")).snapshot_name().unwrap();
+        assert!(name.starts_with("nightly_"));
+        assert!(request(&["web"], None)
+            .snapshot_name()
+            .unwrap()
+            .starts_with("batch_"));
+        assert!(request(&[], None).snapshot_name().is_err());
+        assert!(request(&["web"], Some("../x")).snapshot_name().is_err());
+        assert!(request(&["web"], Some("")).snapshot_name().is_err());
+    }
+}
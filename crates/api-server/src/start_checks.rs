@@ -0,0 +1,225 @@
+/// Prerequisite checks run before a container is started
+///
+/// lxc-start reports a missing bridge or mount source with little more than
+/// an exit code, and happily starts a second container on an address that is
+/// already in use. These checks name the prerequisite that is missing
+/// instead. Hosts whose networking is managed externally can turn them off
+/// with `network.skip_start_checks`.
+use std::path::Path;
+
+use thiserror::Error;
+use tracing::{info, warn};
+
+use container_manager::{ContainerError, ContainerManager};
+use models::{ContainerConfig, ContainerMount, ContainerStatus};
+use network::{BridgeManager, NetworkError};
+
+use crate::address_conflicts::static_addresses;
+
+#[derive(Debug, Error)]
+pub enum StartCheckError {
+    #[error("Container error: {0}")]
+    Container(#[from] ContainerError),
+
+    #[error("Network error: {0}")]
+    Network(#[from] NetworkError),
+
+    #[error("Bridge {bridge} used by interface {interface} does not exist")]
+    MissingBridge { bridge: String, interface: String },
+
+    #[error("Mount source {path} for {target} does not exist")]
+    MissingMountSource { path: String, target: String },
+
+    #[error("Writable path {path} does not exist in the read-only rootfs")]
+    MissingWritablePath { path: String },
+
+    #[error("Address {address} is already used by running container {container}")]
+    AddressInUse { address: String, container: String },
+}
+
+/// Check that `container` can be started
+///
+/// With `bridge_state` set, bridges recorded there that have gone missing
+/// are recreated rather than reported. Returns the bridges created.
+pub async fn check(
+    container: &str,
+    bridge_state: Option<&Path>,
+) -> Result<Vec<String>, StartCheckError> {
+    let (_, config) = ContainerManager::effective_config(container).await?;
+
+    let mut created = Vec::new();
+    for interface in &config.network_interfaces {
+        if BridgeManager::exists(&interface.bridge)? {
+            continue;
+        }
+        let desired = match bridge_state {
+            Some(path) => BridgeManager::desired(path)?
+                .into_iter()
+                .find(|bridge| bridge.name == interface.bridge),
+            None => None,
+        };
+        let Some(request) = desired else {
+            return Err(StartCheckError::MissingBridge {
+                bridge: interface.bridge.clone(),
+                interface: interface.name.clone(),
+            });
+        };
+        info!(
+            "Recreating bridge {} for container {}",
+            request.name, container
+        );
+        created.push(BridgeManager::create(request).await?.name);
+    }
+
+    let mounts = ContainerManager::mounts(container).await?;
+    if let Some(mount) = mounts.iter().find(|mount| !mount_source_present(mount)) {
+        return Err(StartCheckError::MissingMountSource {
+            path: mount.source.clone(),
+            target: mount.target.clone(),
+        });
+    }
+
+    if let Some(path) = missing_writable_path(&config) {
+        return Err(StartCheckError::MissingWritablePath {
+            path: path.to_string(),
+        });
+    }
+
+    let running = running_configs(container).await?;
+    if let Some((address, other)) = address_conflict(&config, &running) {
+        return Err(StartCheckError::AddressInUse {
+            address,
+            container: other,
+        });
+    }
+
+    Ok(created)
+}
+
+/// Configurations of the running containers other than `container`
+async fn running_configs(
+    container: &str,
+) -> Result<Vec<(String, ContainerConfig)>, ContainerError> {
+    let mut running = Vec::new();
+    for name in ContainerManager::list().await? {
+        if name == container {
+            continue;
+        }
+        // A container removed since it was listed is simply not running
+        match ContainerManager::status(&name).await {
+            Ok(ContainerStatus::Running) => {}
+            Ok(_) | Err(ContainerError::NotFound(_)) => continue,
+            Err(e) => return Err(e),
+        }
+        match ContainerManager::effective_config(&name).await {
+            Ok((_, config)) => running.push((name, config)),
+            Err(e) => warn!("Could not read config of container {}: {}", name, e),
+        }
+    }
+    Ok(running)
+}
+
+/// Bind mounts need their host path; other sources (`proc`, `tmpfs`, ...)
+/// are not paths
+pub fn mount_source_present(mount: &ContainerMount) -> bool {
+    !mount.source.starts_with('/') || Path::new(&mount.source).exists()
+}
+
+/// A writable path of a read-only rootfs with no directory to mount its
+/// tmpfs on, which cannot be created once the rootfs is read-only. Root
+/// filesystems that are not plain directories are not inspected
+pub fn missing_writable_path(config: &ContainerConfig) -> Option<&str> {
+    let rootfs = Path::new(&config.rootfs_path);
+    if !config.readonly_rootfs || !rootfs.is_dir() {
+        return None;
+    }
+    config
+        .writable_paths
+        .iter()
+        .find(|path| !rootfs.join(path.trim_start_matches('/')).is_dir())
+        .map(String::as_str)
+}
+
+/// The first static address of `config` that one of `others` also uses,
+/// with the name of that container
+pub fn address_conflict(
+    config: &ContainerConfig,
+    others: &[(String, ContainerConfig)],
+) -> Option<(String, String)> {
+    let addresses = static_addresses(&config.network_interfaces);
+    others.iter().find_map(|(name, other)| {
+        static_addresses(&other.network_interfaces)
+            .into_iter()
+            .find(|address| addresses.contains(address))
+            .map(|address| (address.to_string(), name.clone()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use models::ContainerNetworkInterface;
+
+    fn config_with(ipv4: Option<&str>) -> ContainerConfig {
+        ContainerConfig {
+            network_interfaces: vec![ContainerNetworkInterface {
+                name: "eth0".to_string(),
+                bridge: "lxcbr0".to_string(),
+                ipv4: ipv4.map(str::to_string),
+                ipv6: None,
+                mac: None,
+                gateway_v4: None,
+                gateway_v6: None,
+                routes: vec![],
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_address_conflict() {
+        let config = config_with(Some("10.0.3.10/24"));
+        let others = vec![
+            ("web".to_string(), config_with(Some("10.0.3.11/24"))),
+            ("db".to_string(), config_with(Some("10.0.3.10/16"))),
+        ];
+        assert_eq!(
+            address_conflict(&config, &others),
+            Some(("10.0.3.10".to_string(), "db".to_string()))
+        );
+        assert_eq!(address_conflict(&config, &others[..1]), None);
+
+        let dhcp = config_with(Some("dhcp"));
+        let others = vec![("other".to_string(), config_with(Some("dhcp")))];
+        assert_eq!(address_conflict(&dhcp, &others), None);
+    }
+
+    #[test]
+    fn test_mount_source_present() {
+        let mount = |source: &str| ContainerMount {
+            source: source.to_string(),
+            target: "mnt".to_string(),
+            fs_type: "none".to_string(),
+            options: "bind".to_string(),
+        };
+        assert!(mount_source_present(&mount("/")));
+        assert!(mount_source_present(&mount("proc")));
+        assert!(!mount_source_present(&mount("/nonexistent/volume")));
+    }
+
+    #[test]
+    fn test_missing_writable_path() {
+        let rootfs = std::env::temp_dir().join(format!("rootfs_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(rootfs.join("var/log")).unwrap();
+        let mut config = config_with(None);
+        config.rootfs_path = rootfs.display().to_string();
+        config.writable_paths = vec!["/var/log".to_string(), "/run/app".to_string()];
+        assert_eq!(missing_writable_path(&config), None);
+
+        config.readonly_rootfs = true;
+        assert_eq!(missing_writable_path(&config), Some("/run/app"));
+        std::fs::create_dir_all(rootfs.join("run/app")).unwrap();
+        assert_eq!(missing_writable_path(&config), None);
+        let _ = std::fs::remove_dir_all(&rootfs);
+    }
+}
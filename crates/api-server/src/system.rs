@@ -0,0 +1,519 @@
+/// Host-wide shutdown and start-all orchestration
+///
+/// Both operations run as background jobs so a client that loses its
+/// connection can pick the job up again via `GET /jobs/{id}`. Containers are
+/// handled in groups of equal `start_order`: groups start in ascending order
+/// and stop in descending order, with the members of a group handled in
+/// parallel. A container is moved to a later group than everything in its
+/// `depends_on`, and start-all also starts the dependencies of autostart
+/// containers. Containers already in the target state are skipped, so
+/// re-running an interrupted operation is safe.
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use container_manager::dependencies::{self, DependencyError, DependencyGraph};
+use container_manager::ContainerManager;
+use models::ContainerStatus;
+use network::FirewallManager;
+
+use crate::audit::{AuditAction, AuditLogger, AuditResult};
+use crate::auth::AuthenticatedUser;
+use crate::egress;
+use crate::jobs::{JobManager, JobStatus};
+use crate::secrets::{self, SecretStore};
+
+pub const SHUTDOWN_JOB: &str = "system-shutdown";
+pub const START_ALL_JOB: &str = "system-start-all";
+
+/// Shutdown and start-all must never overlap
+pub const SYSTEM_JOBS: &[&str] = &[SHUTDOWN_JOB, START_ALL_JOB];
+
+const FLUSH_STEP: &str = "flush";
+const POWEROFF_STEP: &str = "poweroff";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShutdownRequest {
+    pub stop_containers: bool,
+    /// Graceful stop timeout per container before it is killed
+    pub timeout_seconds: u64,
+    pub poweroff: bool,
+}
+
+impl Default for ShutdownRequest {
+    fn default() -> Self {
+        Self {
+            stop_containers: true,
+            timeout_seconds: 120,
+            poweroff: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StopAllRequest {
+    /// Graceful stop timeout per container before it is killed
+    pub timeout_seconds: u64,
+}
+
+impl Default for StopAllRequest {
+    fn default() -> Self {
+        Self {
+            timeout_seconds: 120,
+        }
+    }
+}
+
+/// A container's name, `start_order` and `depends_on`
+pub type BootEntry = (String, i32, Vec<String>);
+
+/// Group container names by start order, lowest order first
+///
+/// Within and across orders, a container lands in a later group than the
+/// containers it depends on; dependencies outside `containers` are ignored.
+pub fn order_groups(containers: Vec<BootEntry>) -> Result<Vec<Vec<String>>, DependencyError> {
+    let orders: BTreeSet<i32> = containers.iter().map(|(_, order, _)| *order).collect();
+    let order_index: HashMap<i32, usize> = orders.into_iter().zip(0..).collect();
+    let graph: DependencyGraph = containers
+        .iter()
+        .map(|(name, _, depends_on)| (name.clone(), depends_on.clone()))
+        .collect();
+    let orders: HashMap<&str, i32> = containers
+        .iter()
+        .map(|(name, order, _)| (name.as_str(), *order))
+        .collect();
+
+    // Ranked by (start order, dependency depth within that order)
+    let mut ranks: HashMap<String, (usize, usize)> = HashMap::new();
+    let mut groups: BTreeMap<(usize, usize), Vec<String>> = BTreeMap::new();
+    for name in dependencies::sort(&graph)? {
+        let own = (order_index[&orders[name.as_str()]], 0);
+        let rank = graph[&name]
+            .iter()
+            .filter_map(|dependency| ranks.get(dependency))
+            .map(|&(order, depth)| (order, depth + 1))
+            .fold(own, std::cmp::max);
+        ranks.insert(name.clone(), rank);
+        groups.entry(rank).or_default().push(name);
+    }
+    Ok(groups
+        .into_values()
+        .map(|mut group| {
+            group.sort();
+            group
+        })
+        .collect())
+}
+
+/// Boot entries of containers, optionally only autostart ones and what they
+/// depend on
+async fn collect_containers(autostart_only: bool) -> Result<Vec<BootEntry>, String> {
+    let names = ContainerManager::list().await.map_err(|e| e.to_string())?;
+
+    let mut configs = BTreeMap::new();
+    for name in names {
+        match ContainerManager::effective_config(&name).await {
+            Ok((_, config)) => {
+                configs.insert(name, config);
+            }
+            Err(e) => warn!("Skipping container {}: {}", name, e),
+        }
+    }
+
+    let mut selected: HashSet<String> = configs
+        .iter()
+        .filter(|(_, config)| !autostart_only || config.autostart)
+        .map(|(name, _)| name.clone())
+        .collect();
+    // A dependency starts with its dependents even without autostart
+    let mut pending: Vec<String> = selected.iter().cloned().collect();
+    while let Some(name) = pending.pop() {
+        for dependency in &configs[&name].depends_on {
+            if !configs.contains_key(dependency) {
+                warn!(
+                    "Container {} depends on unknown container {}",
+                    name, dependency
+                );
+            } else if selected.insert(dependency.clone()) {
+                pending.push(dependency.clone());
+            }
+        }
+    }
+
+    Ok(configs
+        .into_iter()
+        .filter(|(name, _)| selected.contains(name))
+        .map(|(name, config)| (name, config.start_order, config.depends_on))
+        .collect())
+}
+
+pub async fn run_shutdown(
+    job_id: Uuid,
+    request: ShutdownRequest,
+    user: AuthenticatedUser,
+    jobs: Arc<JobManager>,
+    audit_logger: Arc<AuditLogger>,
+    firewall_rules_path: Option<PathBuf>,
+) {
+    info!("System shutdown job {} started: {:?}", job_id, request);
+
+    let groups = if request.stop_containers {
+        match collect_containers(false).await {
+            Ok(containers) => order_groups(containers.clone()).unwrap_or_else(|e| {
+                // Stopping must not be blocked by a broken dependency graph
+                warn!("Ignoring container dependencies for shutdown: {}", e);
+                let containers = containers
+                    .into_iter()
+                    .map(|(name, order, _)| (name, order, Vec::new()))
+                    .collect();
+                order_groups(containers).unwrap_or_default()
+            }),
+            Err(e) => {
+                error!("System shutdown could not list containers: {}", e);
+                jobs.fail(job_id, e, None);
+                return;
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
+    let mut steps: Vec<String> = groups.iter().flatten().cloned().collect();
+    steps.push(FLUSH_STEP.to_string());
+    if request.poweroff {
+        steps.push(POWEROFF_STEP.to_string());
+    }
+    jobs.set_steps(job_id, steps);
+
+    let mut failures = Vec::new();
+    for group in groups.iter().rev() {
+        let results = join_all(group.iter().map(|name| {
+            stop_container(job_id, name.clone(), request.timeout_seconds, jobs.clone())
+        }))
+        .await;
+        failures.extend(results.into_iter().flatten());
+    }
+
+    jobs.update_step(job_id, FLUSH_STEP, JobStatus::Running, None);
+    match flush_state(firewall_rules_path).await {
+        Ok(()) => jobs.update_step(job_id, FLUSH_STEP, JobStatus::Succeeded, None),
+        Err(e) => {
+            jobs.update_step(job_id, FLUSH_STEP, JobStatus::Failed, Some(e.clone()));
+            failures.push(format!("{}: {}", FLUSH_STEP, e));
+        }
+    }
+
+    let summary = serde_json::json!({
+        "stopped_groups": groups.len(),
+        "failures": failures,
+    });
+    audit(
+        &audit_logger,
+        &user,
+        AuditAction::SystemStopped,
+        &failures,
+        format!("{:?}", request),
+    );
+
+    if request.poweroff {
+        // Power is going away regardless; stop failures must not block poweroff
+        jobs.update_step(job_id, POWEROFF_STEP, JobStatus::Running, None);
+        finish(&jobs, job_id, &failures, summary);
+        warn!("Powering off host");
+        if let Err(e) = poweroff().await {
+            error!("Poweroff failed: {}", e);
+            jobs.update_step(job_id, POWEROFF_STEP, JobStatus::Failed, Some(e.clone()));
+            jobs.fail(job_id, format!("poweroff failed: {}", e), None);
+        } else {
+            jobs.update_step(job_id, POWEROFF_STEP, JobStatus::Succeeded, None);
+        }
+        return;
+    }
+
+    finish(&jobs, job_id, &failures, summary);
+}
+
+pub async fn run_start_all(
+    job_id: Uuid,
+    user: AuthenticatedUser,
+    jobs: Arc<JobManager>,
+    audit_logger: Arc<AuditLogger>,
+    secret_store: Option<Arc<SecretStore>>,
+    default_dns: Vec<String>,
+) {
+    info!("System start-all job {} started", job_id);
+
+    let groups = match collect_containers(true)
+        .await
+        .and_then(|containers| order_groups(containers).map_err(|e| e.to_string()))
+    {
+        Ok(groups) => groups,
+        Err(e) => {
+            error!("Start-all could not list containers: {}", e);
+            jobs.fail(job_id, e, None);
+            return;
+        }
+    };
+    jobs.set_steps(job_id, groups.iter().flatten().cloned().collect());
+
+    // A failing group does not stop later groups; each failure is recorded
+    let mut failures = Vec::new();
+    for group in &groups {
+        let results = join_all(group.iter().map(|name| {
+            start_container(
+                job_id,
+                name.clone(),
+                jobs.clone(),
+                secret_store.clone(),
+                &default_dns,
+            )
+        }))
+        .await;
+        failures.extend(results.into_iter().flatten());
+    }
+
+    audit(
+        &audit_logger,
+        &user,
+        AuditAction::SystemStarted,
+        &failures,
+        format!("{} autostart container(s)", groups.iter().flatten().count()),
+    );
+    let summary = serde_json::json!({
+        "started_groups": groups.len(),
+        "failures": failures,
+    });
+    finish(&jobs, job_id, &failures, summary);
+}
+
+/// Stop one container as a job step; returns a failure description on error
+async fn stop_container(
+    job_id: Uuid,
+    name: String,
+    timeout_secs: u64,
+    jobs: Arc<JobManager>,
+) -> Option<String> {
+    jobs.update_step(job_id, &name, JobStatus::Running, None);
+
+    let status = ContainerManager::status(&name).await;
+    if matches!(status, Ok(ContainerStatus::Stopped)) {
+        jobs.update_step(
+            job_id,
+            &name,
+            JobStatus::Succeeded,
+            Some("already stopped".to_string()),
+        );
+        return None;
+    }
+
+    egress::remove(&name).await;
+    match ContainerManager::stop_with_timeout(&name, timeout_secs).await {
+        Ok(()) => {
+            jobs.update_step(job_id, &name, JobStatus::Succeeded, None);
+            None
+        }
+        Err(e) => {
+            error!("Failed to stop container {}: {}", name, e);
+            jobs.update_step(job_id, &name, JobStatus::Failed, Some(e.to_string()));
+            Some(format!("{}: {}", name, e))
+        }
+    }
+}
+
+/// Start one container as a job step; returns a failure description on error
+async fn start_container(
+    job_id: Uuid,
+    name: String,
+    jobs: Arc<JobManager>,
+    secret_store: Option<Arc<SecretStore>>,
+    default_dns: &[String],
+) -> Option<String> {
+    jobs.update_step(job_id, &name, JobStatus::Running, None);
+
+    let status = ContainerManager::status(&name).await;
+    if matches!(status, Ok(ContainerStatus::Running)) {
+        jobs.update_step(
+            job_id,
+            &name,
+            JobStatus::Succeeded,
+            Some("already running".to_string()),
+        );
+        return None;
+    }
+
+    if let Err(e) = secrets::inject(secret_store.as_deref(), &name).await {
+        error!("Failed to prepare secrets for container {}: {}", name, e);
+        jobs.update_step(job_id, &name, JobStatus::Failed, Some(e.to_string()));
+        return Some(format!("{}: {}", name, e));
+    }
+
+    let started = async {
+        ContainerManager::write_resolv_conf(&name, default_dns)
+            .await
+            .map_err(|e| e.to_string())?;
+        ContainerManager::start(&name)
+            .await
+            .map_err(|e| e.to_string())?;
+        if let Err(e) = egress::apply(&name).await {
+            let _ = ContainerManager::stop(&name).await;
+            return Err(format!("{}; container was stopped", e));
+        }
+        Ok(())
+    };
+    match started.await {
+        Ok(()) => {
+            jobs.update_step(job_id, &name, JobStatus::Succeeded, None);
+            None
+        }
+        Err(e) => {
+            error!("Failed to start container {}: {}", name, e);
+            jobs.update_step(job_id, &name, JobStatus::Failed, Some(e.to_string()));
+            Some(format!("{}: {}", name, e))
+        }
+    }
+}
+
+/// Persist firewall rules and flush filesystem buffers
+async fn flush_state(firewall_rules_path: Option<PathBuf>) -> Result<(), String> {
+    if let Some(path) = firewall_rules_path {
+        FirewallManager::save(&path)
+            .await
+            .map_err(|e| format!("saving firewall rules: {}", e))?;
+    }
+
+    let status = tokio::process::Command::new("sync")
+        .status()
+        .await
+        .map_err(|e| format!("sync: {}", e))?;
+    if !status.success() {
+        return Err(format!("sync exited with {}", status));
+    }
+    Ok(())
+}
+
+async fn poweroff() -> Result<(), String> {
+    // The fake backend runs on a developer's machine, which stays up
+    if container_manager::lxc::LxcCommand::is_fake() {
+        warn!("Fake backend: not powering off the host");
+        return Ok(());
+    }
+    let output = tokio::process::Command::new("systemctl")
+        .arg("poweroff")
+        .output()
+        .await
+        .map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).to_string())
+    }
+}
+
+fn finish(jobs: &JobManager, job_id: Uuid, failures: &[String], summary: serde_json::Value) {
+    if failures.is_empty() {
+        jobs.succeed(job_id, summary);
+    } else {
+        jobs.fail(
+            job_id,
+            format!("{} step(s) failed", failures.len()),
+            Some(summary),
+        );
+    }
+}
+
+pub(crate) fn audit(
+    audit_logger: &AuditLogger,
+    user: &AuthenticatedUser,
+    action: AuditAction,
+    failures: &[String],
+    details: String,
+) {
+    let result = if failures.is_empty() {
+        AuditResult::Success
+    } else {
+        AuditResult::Failure(failures.join("; "))
+    };
+    if let Ok(log) = AuditLogger::builder()
+        .actor(user)
+        .action(action)
+        .resource_type("system".to_string())
+        .result(result)
+        .details(details)
+        .build()
+    {
+        audit_logger.log_entry(log);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_groups() {
+        let groups = order_groups(vec![
+            ("web".to_string(), 20, vec![]),
+            ("db".to_string(), 10, vec![]),
+            ("cache".to_string(), 10, vec![]),
+            ("misc".to_string(), 0, vec![]),
+        ])
+        .unwrap();
+        assert_eq!(
+            groups,
+            vec![
+                vec!["misc".to_string()],
+                vec!["cache".to_string(), "db".to_string()],
+                vec!["web".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_order_groups_follow_dependencies() {
+        let entry = |name: &str, order, depends_on: &[&str]| {
+            (
+                name.to_string(),
+                order,
+                depends_on.iter().map(|d| d.to_string()).collect(),
+            )
+        };
+        let groups = order_groups(vec![
+            entry("app", 0, &["db", "cache"]),
+            entry("db", 0, &["storage"]),
+            entry("cache", 0, &[]),
+            entry("storage", 0, &[]),
+            // Earlier order, but waits for a later one
+            entry("metrics", 0, &["proxy"]),
+            entry("proxy", 10, &[]),
+            entry("batch", 10, &["gone"]),
+        ])
+        .unwrap();
+        assert_eq!(
+            groups,
+            vec![
+                vec!["cache", "storage"],
+                vec!["db"],
+                vec!["app"],
+                vec!["batch", "proxy"],
+                vec!["metrics"],
+            ]
+        );
+
+        let cycle = order_groups(vec![entry("a", 0, &["b"]), entry("b", 10, &["a"])]);
+        assert!(matches!(cycle, Err(DependencyError::Cycle(_))));
+    }
+
+    #[test]
+    fn test_shutdown_request_defaults() {
+        let request: ShutdownRequest = serde_json::from_str("{}").unwrap();
+        assert!(request.stop_containers);
+        assert_eq!(request.timeout_seconds, 120);
+        assert!(!request.poweroff);
+    }
+}
@@ -0,0 +1,232 @@
+/// systemd service manager integration (sd_notify readiness and watchdog)
+///
+/// Speaks the `sd_notify(3)` datagram protocol directly over `NOTIFY_SOCKET`.
+/// When the process is not started by systemd (no `NOTIFY_SOCKET` in the
+/// environment) every call is a no-op.
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// How long the runtime liveness probe may take before a watchdog ping is skipped
+const LIVENESS_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+pub struct SystemdNotifier {
+    socket: Option<String>,
+    watchdog_interval: Option<Duration>,
+}
+
+impl SystemdNotifier {
+    /// Build a notifier from the environment systemd passes to the service
+    pub fn from_env() -> Self {
+        Self::from_vars(
+            std::env::var("NOTIFY_SOCKET").ok(),
+            std::env::var("WATCHDOG_USEC").ok(),
+            std::env::var("WATCHDOG_PID").ok(),
+            std::process::id(),
+        )
+    }
+
+    fn from_vars(
+        notify_socket: Option<String>,
+        watchdog_usec: Option<String>,
+        watchdog_pid: Option<String>,
+        pid: u32,
+    ) -> Self {
+        let socket = notify_socket.filter(|s| !s.is_empty());
+
+        // WATCHDOG_PID, when set, must name this process; otherwise the
+        // watchdog belongs to someone else (e.g. a wrapper script).
+        let pid_matches = watchdog_pid
+            .map(|p| p.parse::<u32>().map(|p| p == pid).unwrap_or(false))
+            .unwrap_or(true);
+
+        let watchdog_interval = watchdog_usec
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|usec| *usec > 0 && pid_matches && socket.is_some())
+            // Ping at half the configured timeout, as recommended by sd_watchdog_enabled(3)
+            .map(|usec| Duration::from_micros(usec / 2));
+
+        Self {
+            socket,
+            watchdog_interval,
+        }
+    }
+
+    /// Whether the service was started with a notify socket
+    pub fn is_enabled(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    /// Interval at which WATCHDOG=1 should be sent, if the watchdog is enabled
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog_interval
+    }
+
+    /// Log what the process expects from its systemd unit
+    pub fn log_unit_expectations(&self) {
+        match (self.is_enabled(), self.watchdog_interval()) {
+            (true, Some(interval)) => info!(
+                "systemd notify socket detected; READY=1 is sent once the listener is bound, \
+                 WATCHDOG=1 every {}ms (unit should use Type=notify with WatchdogSec set)",
+                interval.as_millis()
+            ),
+            (true, None) => info!(
+                "systemd notify socket detected without WATCHDOG_USEC; set WatchdogSec= in the \
+                 unit to enable hang detection"
+            ),
+            (false, _) => debug!(
+                "Not running under systemd notify; to enable readiness and watchdog support use \
+                 Type=notify, NotifyAccess=main and WatchdogSec=30s in the unit"
+            ),
+        }
+    }
+
+    /// Send READY=1
+    pub fn ready(&self) {
+        self.notify("READY=1\nSTATUS=Serving requests");
+    }
+
+    /// Send STOPPING=1
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1\nSTATUS=Shutting down");
+    }
+
+    /// Send WATCHDOG=1
+    pub fn watchdog(&self) {
+        self.notify("WATCHDOG=1");
+    }
+
+    /// Send a raw notification message; errors are logged, never returned
+    pub fn notify(&self, state: &str) {
+        let Some(ref socket) = self.socket else {
+            return;
+        };
+
+        if let Err(e) = Self::send(socket, state) {
+            warn!(
+                "Failed to notify systemd ({}): {}",
+                state.replace('\n', " "),
+                e
+            );
+        }
+    }
+
+    fn send(socket: &str, state: &str) -> std::io::Result<()> {
+        let sock = UnixDatagram::unbound()?;
+
+        // A leading '@' denotes a socket in the Linux abstract namespace
+        if let Some(name) = socket.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())?;
+                sock.send_to_addr(state.as_bytes(), &addr)?;
+                return Ok(());
+            }
+            #[cfg(not(target_os = "linux"))]
+            {
+                let _ = name;
+                return Err(std::io::Error::other(
+                    "abstract notify sockets are only supported on Linux",
+                ));
+            }
+        }
+
+        sock.send_to(state.as_bytes(), PathBuf::from(socket))?;
+        Ok(())
+    }
+
+    /// Spawn the watchdog loop on the current runtime
+    ///
+    /// Each tick runs a cheap liveness check: the runtime must be able to
+    /// schedule and complete a trivial task within `LIVENESS_PROBE_TIMEOUT`.
+    /// If the check fails the ping is skipped so systemd restarts the service.
+    pub fn spawn_watchdog(&self) {
+        let Some(interval) = self.watchdog_interval() else {
+            return;
+        };
+
+        let notifier = self.clone();
+        actix_rt::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+
+                if Self::runtime_responsive().await {
+                    notifier.watchdog();
+                } else {
+                    warn!("Liveness check failed; skipping systemd watchdog ping");
+                }
+            }
+        });
+    }
+
+    async fn runtime_responsive() -> bool {
+        // There is no database pool yet; once one exists its acquire check belongs here.
+        let probe = tokio::spawn(async {});
+        matches!(
+            tokio::time::timeout(LIVENESS_PROBE_TIMEOUT, probe).await,
+            Ok(Ok(()))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_without_notify_socket() {
+        let notifier = SystemdNotifier::from_vars(None, Some("30000000".to_string()), None, 1);
+        assert!(!notifier.is_enabled());
+        assert!(notifier.watchdog_interval().is_none());
+
+        // Must be a no-op rather than an error
+        notifier.ready();
+        notifier.stopping();
+    }
+
+    #[test]
+    fn test_watchdog_interval_is_half_timeout() {
+        let notifier = SystemdNotifier::from_vars(
+            Some("/run/systemd/notify".to_string()),
+            Some("30000000".to_string()),
+            Some("42".to_string()),
+            42,
+        );
+        assert!(notifier.is_enabled());
+        assert_eq!(notifier.watchdog_interval(), Some(Duration::from_secs(15)));
+    }
+
+    #[test]
+    fn test_watchdog_pid_mismatch_disables_watchdog() {
+        let notifier = SystemdNotifier::from_vars(
+            Some("/run/systemd/notify".to_string()),
+            Some("30000000".to_string()),
+            Some("7".to_string()),
+            42,
+        );
+        assert!(notifier.is_enabled());
+        assert!(notifier.watchdog_interval().is_none());
+    }
+
+    #[test]
+    fn test_notify_writes_datagram() {
+        let dir = std::env::temp_dir().join(format!("sd_notify_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify.sock");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        let notifier = SystemdNotifier::from_vars(Some(path.display().to_string()), None, None, 1);
+        notifier.ready();
+
+        let mut buf = [0u8; 128];
+        let n = receiver.recv(&mut buf).unwrap();
+        let msg = String::from_utf8_lossy(&buf[..n]);
+        assert!(msg.starts_with("READY=1"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,458 @@
+/// Named background tasks with their schedule, last run and errors
+///
+/// Background loops register here instead of spawning themselves, so they
+/// can be listed under `GET /api/v1/admin/tasks`, interval tasks can be run
+/// early with `POST /api/v1/admin/tasks/{name}/run`, and shutdown stops them
+/// all. Each run is spawned on its own; one that panics is logged and the
+/// task starts again after a backoff that doubles up to [`MAX_BACKOFF`].
+/// Loops that own a one-off resource, such as a bound listener or a channel
+/// receiver, cannot be restarted and are still spawned directly.
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// Wait after the first failure before a task runs again
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest wait between restarts of a failing task
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// One run of a task; an error is recorded and the task carries on
+pub type TaskFuture = BoxFuture<'static, Result<(), String>>;
+
+type TaskFn = Arc<dyn Fn() -> TaskFuture + Send + Sync>;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TaskError {
+    #[error("Task not found: {0}")]
+    NotFound(String),
+
+    #[error("Task {0} runs continuously and cannot be triggered")]
+    NotTriggerable(String),
+
+    #[error("Task manager is shutting down")]
+    ShuttingDown,
+}
+
+/// When a task runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Schedule {
+    /// Every `interval_secs`, and whenever it is triggered
+    Interval { interval_secs: u64 },
+    /// Runs until it returns and is started again when it does; `trigger`
+    /// says what it reacts to
+    Continuous { trigger: String },
+}
+
+/// What the tasks listing reports about a task
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskInfo {
+    pub name: String,
+    pub schedule: Schedule,
+    pub running: bool,
+    pub run_count: u64,
+    /// When the last run started
+    pub last_run: Option<DateTime<Utc>>,
+    /// Error or panic message of the last finished run; cleared by a run
+    /// that succeeds
+    pub last_error: Option<String>,
+    pub panics: u64,
+}
+
+struct Task {
+    info: Mutex<TaskInfo>,
+    trigger: Notify,
+}
+
+impl Task {
+    fn started(&self) {
+        let mut info = self.info.lock().unwrap();
+        info.running = true;
+        info.run_count += 1;
+        info.last_run = Some(Utc::now());
+    }
+
+    fn finished(&self, error: Option<String>, panicked: bool) {
+        let mut info = self.info.lock().unwrap();
+        info.running = false;
+        info.last_error = error;
+        if panicked {
+            info.panics += 1;
+        }
+    }
+}
+
+/// How a run ended
+enum Outcome {
+    Finished,
+    Failed,
+    Panicked,
+    Cancelled,
+}
+
+pub struct TaskManager {
+    tasks: Mutex<BTreeMap<String, Arc<Task>>>,
+    handles: Mutex<Vec<JoinHandle<()>>>,
+    cancel: watch::Sender<bool>,
+}
+
+impl Default for TaskManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TaskManager {
+    pub fn new() -> Self {
+        Self {
+            tasks: Mutex::new(BTreeMap::new()),
+            handles: Mutex::new(Vec::new()),
+            cancel: watch::channel(false).0,
+        }
+    }
+
+    /// Run `task` every `interval`, starting now
+    ///
+    /// A name that is already taken replaces nothing; the second task is
+    /// not started.
+    pub fn register_interval<F, Fut>(&self, name: &str, interval: Duration, task: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let schedule = Schedule::Interval {
+            interval_secs: interval.as_secs(),
+        };
+        if let Some(entry) = self.insert(name, schedule) {
+            let run: TaskFn = Arc::new(move || Box::pin(task()));
+            let cancel = self.cancel.subscribe();
+            self.spawn(run_interval(entry, run, interval, cancel));
+        }
+    }
+
+    /// Run `task` until it returns and start it again when it does, after
+    /// a backoff; `trigger` describes what it waits for
+    pub fn register_continuous<F, Fut>(&self, name: &str, trigger: &str, task: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let schedule = Schedule::Continuous {
+            trigger: trigger.to_string(),
+        };
+        if let Some(entry) = self.insert(name, schedule) {
+            let run: TaskFn = Arc::new(move || Box::pin(task()));
+            let cancel = self.cancel.subscribe();
+            self.spawn(run_continuous(entry, run, cancel));
+        }
+    }
+
+    fn insert(&self, name: &str, schedule: Schedule) -> Option<Arc<Task>> {
+        let mut tasks = self.tasks.lock().unwrap();
+        if tasks.contains_key(name) {
+            error!("Background task {} is already registered", name);
+            return None;
+        }
+        let task = Arc::new(Task {
+            info: Mutex::new(TaskInfo {
+                name: name.to_string(),
+                schedule,
+                running: false,
+                run_count: 0,
+                last_run: None,
+                last_error: None,
+                panics: 0,
+            }),
+            trigger: Notify::new(),
+        });
+        tasks.insert(name.to_string(), task.clone());
+        Some(task)
+    }
+
+    fn spawn(&self, supervisor: impl Future<Output = ()> + Send + 'static) {
+        let mut handles = self.handles.lock().unwrap();
+        handles.retain(|handle| !handle.is_finished());
+        handles.push(tokio::spawn(supervisor));
+    }
+
+    /// Every registered task, by name
+    pub fn list(&self) -> Vec<TaskInfo> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .values()
+            .map(|task| task.info.lock().unwrap().clone())
+            .collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn get(&self, name: &str) -> Option<TaskInfo> {
+        let task = self.tasks.lock().unwrap().get(name)?.clone();
+        let info = task.info.lock().unwrap().clone();
+        Some(info)
+    }
+
+    /// Run an interval task now instead of at its next tick; a task that is
+    /// running goes again once it finishes
+    pub fn trigger(&self, name: &str) -> Result<TaskInfo, TaskError> {
+        if *self.cancel.borrow() {
+            return Err(TaskError::ShuttingDown);
+        }
+        let tasks = self.tasks.lock().unwrap();
+        let task = tasks
+            .get(name)
+            .ok_or_else(|| TaskError::NotFound(name.to_string()))?;
+        let info = task.info.lock().unwrap().clone();
+        if !matches!(info.schedule, Schedule::Interval { .. }) {
+            return Err(TaskError::NotTriggerable(name.to_string()));
+        }
+        task.trigger.notify_one();
+        Ok(info)
+    }
+
+    /// Cancel every task and wait up to `timeout` for them to stop; runs
+    /// still going are aborted at their next await point
+    pub async fn shutdown(&self, timeout: Duration) {
+        self.cancel.send_replace(true);
+        let handles = std::mem::take(&mut *self.handles.lock().unwrap());
+        info!("Stopping {} background tasks", handles.len());
+        let all = futures::future::join_all(handles);
+        if tokio::time::timeout(timeout, all).await.is_err() {
+            warn!("Background tasks did not stop within {:?}", timeout);
+        }
+    }
+}
+
+async fn run_interval(
+    task: Arc<Task>,
+    run: TaskFn,
+    interval: Duration,
+    mut cancel: watch::Receiver<bool>,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = task.trigger.notified() => {}
+            _ = cancelled(&mut cancel) => return,
+        }
+
+        match run_once(&task, &run, &mut cancel).await {
+            Outcome::Cancelled => return,
+            Outcome::Panicked => {
+                if !sleep_unless_cancelled(backoff, &mut cancel).await {
+                    return;
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            Outcome::Finished | Outcome::Failed => backoff = INITIAL_BACKOFF,
+        }
+    }
+}
+
+async fn run_continuous(task: Arc<Task>, run: TaskFn, mut cancel: watch::Receiver<bool>) {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let started = tokio::time::Instant::now();
+        if let Outcome::Cancelled = run_once(&task, &run, &mut cancel).await {
+            return;
+        }
+        // A task that ran for a while before stopping is not failing fast
+        if started.elapsed() >= MAX_BACKOFF {
+            backoff = INITIAL_BACKOFF;
+        }
+        let name = task.info.lock().unwrap().name.clone();
+        warn!(
+            "Background task {} stopped, restarting in {:?}",
+            name, backoff
+        );
+        if !sleep_unless_cancelled(backoff, &mut cancel).await {
+            return;
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Run the task once on its own tokio task, so a panic ends only that run
+async fn run_once(task: &Task, run: &TaskFn, cancel: &mut watch::Receiver<bool>) -> Outcome {
+    let name = task.info.lock().unwrap().name.clone();
+    task.started();
+    let mut handle = tokio::spawn(run());
+    let joined = tokio::select! {
+        joined = &mut handle => joined,
+        _ = cancelled(cancel) => {
+            handle.abort();
+            task.finished(None, false);
+            return Outcome::Cancelled;
+        }
+    };
+
+    match joined {
+        Ok(Ok(())) => {
+            task.finished(None, false);
+            Outcome::Finished
+        }
+        Ok(Err(e)) => {
+            warn!("Background task {} failed: {}", name, e);
+            task.finished(Some(e), false);
+            Outcome::Failed
+        }
+        Err(e) if e.is_panic() => {
+            let message = panic_message(e.into_panic());
+            error!("Background task {} panicked: {}", name, message);
+            task.finished(Some(format!("panicked: {}", message)), true);
+            Outcome::Panicked
+        }
+        Err(e) => {
+            task.finished(Some(e.to_string()), false);
+            Outcome::Failed
+        }
+    }
+}
+
+/// Resolves once shutdown has begun, or the manager is gone
+async fn cancelled(cancel: &mut watch::Receiver<bool>) {
+    while !*cancel.borrow_and_update() {
+        if cancel.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Sleep for `duration`; false if shutdown began first
+async fn sleep_unless_cancelled(duration: Duration, cancel: &mut watch::Receiver<bool>) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => true,
+        _ = cancelled(cancel) => false,
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    async fn wait_for(manager: &TaskManager, name: &str, check: impl Fn(&TaskInfo) -> bool) {
+        for _ in 0..1000 {
+            if manager.get(name).is_some_and(|info| check(&info)) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("task {} never got there: {:?}", name, manager.get(name));
+    }
+
+    #[tokio::test]
+    async fn test_interval_task_runs_on_trigger() {
+        let manager = TaskManager::new();
+        let runs = Arc::new(AtomicU64::new(0));
+        manager.register_interval("sweep", Duration::from_secs(3600), {
+            let runs = runs.clone();
+            move || {
+                let runs = runs.clone();
+                async move {
+                    match runs.fetch_add(1, Ordering::SeqCst) {
+                        0 => Ok(()),
+                        _ => Err("disk gone".to_string()),
+                    }
+                }
+            }
+        });
+
+        // The first tick is immediate
+        wait_for(&manager, "sweep", |info| {
+            info.run_count == 1 && !info.running
+        })
+        .await;
+        assert_eq!(manager.get("sweep").unwrap().last_error, None);
+
+        manager.trigger("sweep").unwrap();
+        wait_for(&manager, "sweep", |info| {
+            info.run_count == 2 && !info.running
+        })
+        .await;
+        let info = manager.get("sweep").unwrap();
+        assert_eq!(info.last_error.as_deref(), Some("disk gone"));
+        assert_eq!(
+            info.schedule,
+            Schedule::Interval {
+                interval_secs: 3600
+            }
+        );
+
+        assert_eq!(
+            manager.trigger("missing").unwrap_err(),
+            TaskError::NotFound("missing".to_string())
+        );
+        manager.shutdown(Duration::from_secs(1)).await;
+        assert_eq!(
+            manager.trigger("sweep").unwrap_err(),
+            TaskError::ShuttingDown
+        );
+    }
+
+    #[tokio::test]
+    async fn test_panicking_task_is_restarted_with_backoff() {
+        let manager = TaskManager::new();
+        manager.register_continuous("listener", "peer messages", || async {
+            panic!("socket closed")
+        });
+
+        wait_for(&manager, "listener", |info| info.panics == 1).await;
+        let info = manager.get("listener").unwrap();
+        assert_eq!(info.last_error.as_deref(), Some("panicked: socket closed"));
+        assert_eq!(
+            manager.trigger("listener").unwrap_err(),
+            TaskError::NotTriggerable("listener".to_string())
+        );
+
+        // Started again once the first backoff has passed
+        wait_for(&manager, "listener", |info| info.panics == 2).await;
+        assert_eq!(manager.get("listener").unwrap().run_count, 2);
+
+        // The next restart is 2s out; shutdown cuts that wait short
+        manager.shutdown(Duration::from_secs(1)).await;
+        tokio::time::sleep(INITIAL_BACKOFF * 3).await;
+        assert_eq!(manager.get("listener").unwrap().run_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_running_tasks() {
+        let manager = TaskManager::new();
+        manager.register_continuous("forever", "nothing", || async {
+            std::future::pending::<()>().await;
+            Ok(())
+        });
+        manager.register_continuous("forever", "duplicate", || async { Ok(()) });
+        wait_for(&manager, "forever", |info| info.running).await;
+        assert_eq!(manager.list().len(), 1);
+
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            manager.shutdown(Duration::from_secs(5)),
+        )
+        .await
+        .unwrap();
+        assert!(!manager.get("forever").unwrap().running);
+    }
+}
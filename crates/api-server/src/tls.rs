@@ -0,0 +1,97 @@
+/// TLS listener setup
+///
+/// With HTTP/2 enabled the listener is bound through `HttpServer`, which
+/// offers `h2` ahead of `http/1.1` over ALPN. actix-web has no switch to
+/// drop `h2` from a TLS listener, so with HTTP/2 disabled the listener is
+/// built from actix-http's HTTP/1.1 service instead.
+use crate::config::{ServerConfig, TlsConfig};
+use actix_http::{HttpService, Request, Response};
+use actix_service::{map_config, IntoServiceFactory, ServiceFactory, ServiceFactoryExt};
+use actix_web::body::MessageBody;
+use actix_web::dev::{AppConfig, Server};
+use rustls_pemfile::{certs, pkcs8_private_keys};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::time::Duration;
+
+/// Load the certificate chain and PKCS#8 key named in `tls`
+pub fn load_server_config(tls: &TlsConfig) -> io::Result<rustls::ServerConfig> {
+    let cert_file = &mut BufReader::new(
+        File::open(&tls.cert_file)
+            .map_err(|e| io::Error::other(format!("Failed to open cert file: {}", e)))?,
+    );
+    let key_file = &mut BufReader::new(
+        File::open(&tls.key_file)
+            .map_err(|e| io::Error::other(format!("Failed to open key file: {}", e)))?,
+    );
+
+    let cert_chain = certs(cert_file)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| io::Error::other(format!("Failed to parse cert: {}", e)))?;
+    let mut keys = pkcs8_private_keys(key_file)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| io::Error::other(format!("Failed to parse key: {}", e)))?;
+
+    if keys.is_empty() {
+        return Err(io::Error::other("No private key found in key file"));
+    }
+
+    rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, keys.remove(0).into())
+        .map_err(|e| io::Error::other(format!("Failed to build TLS config: {}", e)))
+}
+
+/// Serve `factory` over TLS with HTTP/1.1 only
+///
+/// Applies the same worker, connection and timeout settings the
+/// `HttpServer` path does. Signals are left to the caller.
+pub fn http1_server<F, I, S, B>(
+    factory: F,
+    mut tls_config: rustls::ServerConfig,
+    server_config: &ServerConfig,
+) -> io::Result<Server>
+where
+    F: Fn() -> I + Send + Clone + 'static,
+    I: IntoServiceFactory<S, Request>,
+    S: ServiceFactory<Request, Config = AppConfig> + 'static,
+    S::Error: Into<actix_web::Error> + 'static,
+    S::InitError: std::fmt::Debug,
+    S::Response: Into<Response<B>> + 'static,
+    B: MessageBody + 'static,
+{
+    tls_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    let keep_alive = server_config.keepalive.map(Duration::from_secs);
+    let client_timeout = server_config.client_timeout.map(Duration::from_secs);
+
+    let mut builder = Server::build().disable_signals();
+    if let Some(workers) = server_config.workers {
+        builder = builder.workers(workers);
+    }
+    if let Some(max_conn) = server_config.max_connections {
+        builder = builder.max_concurrent_connections(max_conn);
+    }
+    if let Some(threads) = server_config.blocking_threads {
+        builder = builder.worker_max_blocking_threads(threads);
+    }
+
+    let bind_address = (server_config.host.as_str(), server_config.port);
+    let builder = builder.bind("api-server-http1-tls", bind_address, move || {
+        let mut service = HttpService::build();
+        if let Some(keep_alive) = keep_alive {
+            service = service.keep_alive(keep_alive);
+        }
+        if let Some(timeout) = client_timeout {
+            service = service.client_request_timeout(timeout);
+        }
+
+        let app = factory()
+            .into_factory()
+            .map_err(|err| err.into().error_response());
+        service
+            .h1(map_config(app, |_| AppConfig::default()))
+            .rustls_0_23(tls_config.clone())
+    })?;
+
+    Ok(builder.run())
+}
@@ -0,0 +1,340 @@
+/// In-memory history of container resource usage
+///
+/// A sampler records the usage of every running container at a fixed
+/// interval into a ring per container, which history queries downsample to
+/// the requested step. Memory stays bounded whatever the container count:
+/// rings hold `retention / interval` samples, at most `max_containers` rings
+/// exist, and the rings of deleted containers are dropped.
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use container_manager::{ContainerManager, LxcMonitor};
+use models::{ContainerStatus, ContainerUsage};
+
+use crate::config::UsageHistoryConfig;
+use crate::tasks::TaskManager;
+
+/// Most points a single history query may return
+pub const MAX_POINTS: u64 = 1440;
+
+#[derive(Debug, Clone, PartialEq)]
+struct UsageSample {
+    timestamp: DateTime<Utc>,
+    cpu_time_ns: Option<u64>,
+    /// CPU use since the previous sample, in percent of one core
+    cpu_percent: Option<f64>,
+    memory_bytes: Option<u64>,
+    tx_bytes: Option<u64>,
+    rx_bytes: Option<u64>,
+}
+
+/// One step of a history query
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsagePoint {
+    /// End of the step
+    pub timestamp: DateTime<Utc>,
+    /// Mean CPU use over the step, in percent of one core
+    pub cpu_percent: Option<f64>,
+    /// Peak memory use in the step
+    pub memory_bytes: Option<u64>,
+    /// Interface counters at the end of the step
+    pub tx_bytes: Option<u64>,
+    pub rx_bytes: Option<u64>,
+}
+
+pub struct UsageHistory {
+    interval: Duration,
+    capacity: usize,
+    max_containers: usize,
+    series: Mutex<HashMap<String, VecDeque<UsageSample>>>,
+}
+
+impl UsageHistory {
+    pub fn new(config: &UsageHistoryConfig) -> Self {
+        let interval = config.sample_interval_secs.max(1);
+        Self {
+            interval: Duration::from_secs(interval),
+            capacity: (config.retention_secs / interval).max(1) as usize,
+            max_containers: config.max_containers,
+            series: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Add a sample of `name` taken at `at`
+    pub fn record(&self, name: &str, usage: &ContainerUsage, at: DateTime<Utc>) {
+        if self.max_containers == 0 {
+            return;
+        }
+        let mut series = self.series.lock().unwrap();
+        if !series.contains_key(name) && series.len() >= self.max_containers {
+            let least_recent = series
+                .iter()
+                .min_by_key(|(_, ring)| ring.back().map(|sample| sample.timestamp))
+                .map(|(name, _)| name.clone());
+            if let Some(least_recent) = least_recent {
+                series.remove(&least_recent);
+            }
+        }
+
+        let ring = series.entry(name.to_string()).or_default();
+        let cpu_percent = ring.back().and_then(|previous| {
+            let used = usage.cpu_time_ns?.checked_sub(previous.cpu_time_ns?)?;
+            let elapsed = (at - previous.timestamp).num_nanoseconds()?;
+            (elapsed > 0).then(|| used as f64 / elapsed as f64 * 100.0)
+        });
+        if ring.len() >= self.capacity {
+            ring.pop_front();
+        }
+        ring.push_back(UsageSample {
+            timestamp: at,
+            cpu_time_ns: usage.cpu_time_ns,
+            cpu_percent,
+            memory_bytes: usage.memory_bytes,
+            tx_bytes: usage.tx_bytes,
+            rx_bytes: usage.rx_bytes,
+        });
+    }
+
+    /// Drop the history of containers not in `names`
+    pub fn retain_known(&self, names: &HashSet<String>) {
+        self.series
+            .lock()
+            .unwrap()
+            .retain(|name, _| names.contains(name));
+    }
+
+    /// Usage of `name` over the `window` before `now`, one point per `step`
+    /// that has samples
+    pub fn query(
+        &self,
+        name: &str,
+        window: Duration,
+        step: Duration,
+        now: DateTime<Utc>,
+    ) -> Vec<UsagePoint> {
+        let series = self.series.lock().unwrap();
+        let Some(ring) = series.get(name) else {
+            return vec![];
+        };
+        let (Ok(window), Ok(step)) = (
+            chrono::Duration::from_std(window),
+            chrono::Duration::from_std(step),
+        ) else {
+            return vec![];
+        };
+        let step_ns = step.num_nanoseconds().unwrap_or(i64::MAX).max(1);
+        let start = now - window;
+
+        let mut points: Vec<UsagePoint> = Vec::new();
+        let mut cpu = (0.0, 0);
+        for sample in ring
+            .iter()
+            .filter(|sample| sample.timestamp > start && sample.timestamp <= now)
+        {
+            // Steps end at start + step, start + 2 * step, ..., now
+            let offset = (sample.timestamp - start).num_nanoseconds().unwrap_or(0);
+            let end =
+                start + chrono::Duration::nanoseconds((offset - 1) / step_ns * step_ns) + step;
+
+            match points.last_mut() {
+                Some(point) if point.timestamp == end => {
+                    point.memory_bytes = point.memory_bytes.max(sample.memory_bytes);
+                    point.tx_bytes = sample.tx_bytes;
+                    point.rx_bytes = sample.rx_bytes;
+                }
+                last => {
+                    if let Some(point) = last {
+                        point.cpu_percent = mean(cpu);
+                    }
+                    cpu = (0.0, 0);
+                    points.push(UsagePoint {
+                        timestamp: end,
+                        cpu_percent: None,
+                        memory_bytes: sample.memory_bytes,
+                        tx_bytes: sample.tx_bytes,
+                        rx_bytes: sample.rx_bytes,
+                    });
+                }
+            }
+            if let Some(percent) = sample.cpu_percent {
+                cpu = (cpu.0 + percent, cpu.1 + 1);
+            }
+        }
+        if let Some(point) = points.last_mut() {
+            point.cpu_percent = mean(cpu);
+        }
+        points
+    }
+}
+
+fn mean((sum, count): (f64, u32)) -> Option<f64> {
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// Parse a duration such as `90s`, `15m`, `6h` or `7d`
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let unit_at = value.find(|c: char| !c.is_ascii_digit())?;
+    let amount: u64 = value[..unit_at].parse().ok()?;
+    let unit_secs = match &value[unit_at..] {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return None,
+    };
+    if amount == 0 {
+        return None;
+    }
+    Some(Duration::from_secs(amount.checked_mul(unit_secs)?))
+}
+
+/// Sample the usage of every running container every sample interval
+pub fn register(tasks: &TaskManager, history: Arc<UsageHistory>, monitor: Arc<LxcMonitor>) {
+    info!(
+        "Recording container usage history every {}s",
+        history.interval().as_secs()
+    );
+
+    tasks.register_interval("usage-history", history.interval(), move || {
+        sample(history.clone(), monitor.clone())
+    });
+}
+
+/// Record one sample of every running container
+async fn sample(history: Arc<UsageHistory>, monitor: Arc<LxcMonitor>) -> Result<(), String> {
+    let names = ContainerManager::list()
+        .await
+        .map_err(|e| format!("could not list containers: {}", e))?;
+    history.retain_known(&names.iter().cloned().collect());
+
+    // The monitor already knows which containers run, unless it is down
+    let health = monitor.health();
+    let states = health.is_current().then(|| monitor.states());
+    let mut samples = Vec::new();
+    for name in names {
+        let running = match states {
+            Some(ref states) => states.get(&name) == Some(&ContainerStatus::Running),
+            None => matches!(
+                ContainerManager::status(&name).await,
+                Ok(ContainerStatus::Running)
+            ),
+        };
+        if !running {
+            continue;
+        }
+        ContainerManager::check_oom_kills(&name);
+        match ContainerManager::usage(&name).await {
+            Ok(usage) => samples.push((name, usage)),
+            Err(e) => warn!("Could not sample usage of container {}: {}", name, e),
+        }
+    }
+
+    let now = Utc::now();
+    for (name, usage) in &samples {
+        history.record(name, usage, now);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(retention_secs: u64, max_containers: usize) -> UsageHistory {
+        UsageHistory::new(&UsageHistoryConfig {
+            enabled: true,
+            sample_interval_secs: 10,
+            retention_secs,
+            max_containers,
+        })
+    }
+
+    fn usage(cpu_secs: u64, memory_bytes: u64) -> ContainerUsage {
+        ContainerUsage {
+            cpu_time_ns: Some(cpu_secs * 1_000_000_000),
+            memory_bytes: Some(memory_bytes),
+            ..ContainerUsage::default()
+        }
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn test_rings_are_bounded() {
+        let history = history(30, 2);
+        for i in 0..10 {
+            history.record("web", &usage(i, 100), at(i as i64 * 10));
+        }
+        let points = history.query(
+            "web",
+            Duration::from_secs(3600),
+            Duration::from_secs(10),
+            at(100),
+        );
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].timestamp, at(70));
+
+        // A third container displaces the least recently sampled one
+        history.record("db", &usage(0, 100), at(95));
+        history.record("cache", &usage(0, 100), at(96));
+        let names: HashSet<_> = history.series.lock().unwrap().keys().cloned().collect();
+        assert_eq!(names, ["db", "cache"].map(String::from).into());
+
+        history.retain_known(&["cache".to_string()].into());
+        assert!(history
+            .query(
+                "db",
+                Duration::from_secs(60),
+                Duration::from_secs(10),
+                at(100)
+            )
+            .is_empty());
+    }
+
+    #[test]
+    fn test_query_downsamples_to_step() {
+        let history = history(3600, 10);
+        // One core busy half the time, memory peaking at 300
+        history.record("web", &usage(0, 100), at(0));
+        history.record("web", &usage(5, 300), at(10));
+        history.record("web", &usage(10, 200), at(20));
+        history.record("web", &usage(20, 100), at(30));
+
+        let points = history.query(
+            "web",
+            Duration::from_secs(40),
+            Duration::from_secs(20),
+            at(40),
+        );
+        // Steps end at 20 and 40; the sample at 0 is outside the window
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].timestamp, at(20));
+        assert_eq!(points[0].cpu_percent, Some(50.0));
+        assert_eq!(points[0].memory_bytes, Some(300));
+        assert_eq!(points[1].timestamp, at(40));
+        assert_eq!(points[1].cpu_percent, Some(100.0));
+        assert_eq!(points[1].memory_bytes, Some(100));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1m"), Some(Duration::from_secs(60)));
+        assert_eq!(parse_duration("6h"), Some(Duration::from_secs(21600)));
+        assert_eq!(parse_duration("7d"), Some(Duration::from_secs(604800)));
+        for invalid in ["", "6", "h", "0m", "1w", "-1h", "1.5h"] {
+            assert_eq!(parse_duration(invalid), None, "{}", invalid);
+        }
+    }
+}
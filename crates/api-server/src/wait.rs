@@ -0,0 +1,129 @@
+/// Long-polling until a resource reaches a desired state
+///
+/// Waiters subscribe to the event channel of the resource before checking
+/// its current state, then re-check on every event, so a change between the
+/// two is never missed and LXC is not polled while waiting.
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::Instant;
+
+/// Timeout used when a request does not ask for one, in seconds
+pub const DEFAULT_WAIT_SECS: u64 = 30;
+
+#[derive(Debug, Deserialize)]
+pub struct WaitQuery {
+    /// Desired state, e.g. `running`
+    #[serde(rename = "for")]
+    pub target: Option<String>,
+    /// Seconds to wait, capped at the configured maximum
+    pub timeout: Option<u64>,
+}
+
+impl WaitQuery {
+    /// How long to wait, never more than `max_secs`
+    pub fn timeout(&self, max_secs: u64) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(DEFAULT_WAIT_SECS).min(max_secs))
+    }
+
+    /// The desired state as one of the resource's status values
+    pub fn target<T: DeserializeOwned>(&self) -> Result<Option<T>, String> {
+        let Some(ref target) = self.target else {
+            return Ok(None);
+        };
+        serde_json::from_value(serde_json::Value::String(target.clone()))
+            .map(Some)
+            .map_err(|_| format!("Unknown state: {}", target))
+    }
+}
+
+/// Run `check` now and after every event until it returns a value or
+/// `timeout` passes
+///
+/// `events` must be subscribed before the resource is first looked at. A
+/// subscriber that lagged behind re-checks like on any other event; once the
+/// channel is closed no more changes can be seen, so the state is only
+/// checked again at the deadline.
+pub async fn until<E: Clone, T>(
+    mut events: broadcast::Receiver<E>,
+    timeout: Duration,
+    mut check: impl FnMut() -> Option<T>,
+) -> Option<T> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(reached) = check() {
+            return Some(reached);
+        }
+        match tokio::time::timeout_at(deadline, events.recv()).await {
+            Err(_) => return None,
+            Ok(Ok(_)) | Ok(Err(RecvError::Lagged(_))) => {}
+            Ok(Err(RecvError::Closed)) => {
+                tokio::time::sleep_until(deadline).await;
+                return check();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use models::ContainerStatus;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_timeout_is_capped() {
+        let query = |timeout| WaitQuery {
+            target: None,
+            timeout,
+        };
+        assert_eq!(query(None).timeout(300), Duration::from_secs(30));
+        assert_eq!(query(Some(5)).timeout(300), Duration::from_secs(5));
+        assert_eq!(query(Some(3600)).timeout(300), Duration::from_secs(300));
+        assert_eq!(query(None).timeout(10), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_target_parsing() {
+        let query = |target: &str| WaitQuery {
+            target: Some(target.to_string()),
+            timeout: None,
+        };
+        assert_eq!(
+            query("running").target::<ContainerStatus>(),
+            Ok(Some(ContainerStatus::Running))
+        );
+        assert!(query("up").target::<ContainerStatus>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_until_rechecks_on_events() {
+        let (sender, events) = broadcast::channel(4);
+        let value = Arc::new(AtomicU32::new(0));
+
+        let writer = value.clone();
+        tokio::spawn(async move {
+            for _ in 0..3 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                writer.fetch_add(1, Ordering::SeqCst);
+                sender.send(()).unwrap();
+            }
+        });
+
+        let reached = until(events, Duration::from_secs(5), || {
+            let current = value.load(Ordering::SeqCst);
+            (current == 3).then_some(current)
+        })
+        .await;
+        assert_eq!(reached, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_until_times_out() {
+        let (_sender, events) = broadcast::channel::<()>(4);
+        let reached = until(events, Duration::from_millis(20), || None::<()>).await;
+        assert!(reached.is_none());
+    }
+}
@@ -0,0 +1,96 @@
+//! Tests for the dev-mode fake backend, driving a container through its
+//! lifecycle without LXC. Switching to the fake backend cannot be undone, so
+//! this test has a binary of its own.
+
+use actix_web::{test, web, App};
+use api_server::config::AppConfig;
+use container_manager::lxc::LxcCommand;
+use serde_json::json;
+use test_support::FakeHost;
+
+#[actix_web::test]
+async fn test_container_lifecycle_against_fake_backend() {
+    let host = FakeHost::new("fake");
+    let base = &host.base;
+    host.env("SKIP_SYSTEM_CHECKS", "1");
+    LxcCommand::use_fake_backend();
+    network::BridgeManager::use_fake_backend(&["lxcbr0"]);
+
+    let mut config = AppConfig::default();
+    config.security.auth_enabled = false;
+    config.network.skip_start_checks = true;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/system/info")
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["fake_backend"], true);
+    assert_eq!(body["privileges"]["mode"], "fake");
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers")
+        .set_json(json!({
+            "name": "web",
+            "template": "alpine",
+            "config": {
+                "cpu_limit": 1,
+                "memory_limit": 268435456i64,
+                "disk_limit": 1073741824i64,
+                "network_interfaces": [],
+                "rootfs_path": base.join("web").join("rootfs").display().to_string(),
+                "environment": []
+            }
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success(), "create: {}", resp.status());
+    assert!(base.join("web").join("config").is_file());
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers/web/start")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success(), "start: {}", resp.status());
+
+    // Starting twice is refused, as LXC would
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers/web/start")
+        .to_request();
+    assert!(!test::call_service(&app, req).await.status().is_success());
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/web")
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["container"]["status"], "running");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/web/usage")
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert!(body["usage"]["memory_bytes"].as_u64().unwrap() > 0);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers/web/stop")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success(), "stop: {}", resp.status());
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/containers/web")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success(), "delete: {}", resp.status());
+    assert!(!base.join("web").exists());
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/web")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
@@ -0,0 +1,1134 @@
+//! Endpoints against fake `lxc-*`, `ip` and `iptables` tools on PATH. Each
+//! test holds a [`FakeHost`] for its whole run, so they take turns.
+
+use actix_web::{test, web, App};
+use serde_json::json;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use api_server::audit::{AuditAction, AuditLogger, AuditResult};
+use api_server::config::AppConfig;
+use api_server::egress;
+use api_server::jobs::JobManager;
+use api_server::observability::MetricsCollector;
+use api_server::rbac::{Role, UserStore};
+use api_server::request_tracing::RequestTracing;
+use api_server::secrets::SecretStore;
+use container_manager::{ContainerManager, ImageCache, LxcMonitor};
+use models::ContainerStatus;
+use network::Ipam;
+use test_support::FakeHost;
+
+/// Config with authentication off, so requests act as the local admin
+fn open_config() -> AppConfig {
+    let mut config = AppConfig::default();
+    config.security.auth_enabled = false;
+    config
+}
+
+/// Config with authentication on and nobody logged in
+fn closed_config() -> AppConfig {
+    let mut config = AppConfig::default();
+    config.security.jwt_secret = Some("test-secret-at-least-32-characters-long".to_string());
+    config
+}
+
+/// A stopped `container` with the given LXC `config`
+fn fake_container(prefix: &str, container: &str, config: &str) -> FakeHost {
+    let host = FakeHost::new(prefix);
+    fs::create_dir_all(host.base.join(container)).unwrap();
+    fs::write(host.base.join(container).join("config"), config).unwrap();
+    host.sh("lxc-ls", &format!("echo {}", container));
+    host.sh("lxc-info", "echo \"State: STOPPED\"");
+    host
+}
+
+#[actix_web::test]
+async fn test_liveness_does_not_depend_on_lxc() {
+    // No tools at all
+    let host = FakeHost::new("liveness");
+    host.env("PATH", &host.bin);
+    host.remove_env("SKIP_SYSTEM_CHECKS");
+
+    let app = test::init_service(App::new().configure(api_server::routes::configure_routes)).await;
+
+    // The deep check notices that LXC is gone...
+    let req = test::TestRequest::get().uri("/health").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 503);
+
+    // ...while liveness still answers, and quickly
+    let started = Instant::now();
+    let req = test::TestRequest::get().uri("/healthz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "ok");
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+// A host with LXC installed but no containers
+#[actix_web::test]
+async fn test_no_containers_is_healthy_and_failure_is_not() {
+    let host = FakeHost::new("empty");
+    host.sh("lxc-ls", "exit 0");
+
+    assert_eq!(
+        ContainerManager::list().await.unwrap(),
+        Vec::<String>::new()
+    );
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Arc::new(MetricsCollector::new())))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/health").to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let container_manager = &body["services"]["container_manager"];
+    assert_eq!(container_manager["status"], "healthy");
+    assert_eq!(container_manager["containers"], 0);
+
+    let req = test::TestRequest::get().uri("/metrics/json").to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["metrics"]["containers_total"], 0);
+    assert_eq!(body["metrics"]["containers_running"], 0);
+
+    // A failing lxc-ls is an error, not an empty host
+    host.sh("lxc-ls", "echo 'lxcpath not accessible' >&2\nexit 1");
+    assert!(ContainerManager::list().await.is_err());
+
+    let req = test::TestRequest::get().uri("/health").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 503);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["services"]["container_manager"]["status"], "unhealthy");
+
+    let req = test::TestRequest::get().uri("/metrics/json").to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert!(body["metrics"]["containers_total"].is_null());
+}
+
+#[actix_web::test]
+async fn test_container_config_redacts_sensitive_environment() {
+    let _host = fake_container(
+        "config",
+        "db",
+        "lxc.uts.name = db\n\
+         lxc.cgroup2.memory.max = 1073741824\n\
+         lxc.environment = POSTGRES_PASSWORD=hunter2\n\
+         lxc.environment = POSTGRES_DB=app\n",
+    );
+
+    let app = test::init_service(App::new().configure(api_server::routes::configure_routes)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/db/config")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let raw = body["raw"].as_str().unwrap();
+    assert!(!raw.contains("hunter2"));
+    assert!(raw.contains("POSTGRES_PASSWORD=***REDACTED***"));
+    assert!(raw.contains("POSTGRES_DB=app"));
+
+    let environment = body["config"]["environment"].as_array().unwrap();
+    assert_eq!(
+        environment[0],
+        json!(["POSTGRES_PASSWORD", "***REDACTED***"])
+    );
+    assert_eq!(environment[1], json!(["POSTGRES_DB", "app"]));
+    assert_eq!(body["config"]["memory_limit"], 1073741824);
+
+    // Unknown containers are a 404
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/missing/config")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_secret_values_are_never_returned() {
+    let host = FakeHost::new("secrets");
+    let base = &host.base;
+    fs::create_dir_all(base.join("db")).unwrap();
+    fs::write(
+        base.join("db").join("config"),
+        "lxc.uts.name = db\n# orchestrator.secret = db_password\n",
+    )
+    .unwrap();
+
+    let containers = base.join("containers");
+    fs::write(&containers, "db\n").unwrap();
+    host.sh("lxc-ls", &format!("cat {}", containers.display()));
+    host.sh("lxc-info", "echo 'State: STOPPED'");
+    host.sh("lxc-snapshot", "true");
+    host.sh("lxc-destroy", &format!(": > {}", containers.display()));
+
+    let store = Arc::new(SecretStore::from_key_material(
+        b"test-master-key",
+        base.join("secrets"),
+    ));
+    let audit_logger = Arc::new(AuditLogger::new(100));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(open_config()))
+            .app_data(web::Data::new(store.clone()))
+            .app_data(web::Data::new(audit_logger.clone()))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/api/v1/containers/db/secrets/db_password")
+        .set_json(json!({ "value": "hunter2" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body = test::read_body(resp).await;
+    assert!(!String::from_utf8_lossy(&body).contains("hunter2"));
+
+    // Listing returns names only
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/db/secrets")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["secrets"], json!([{ "name": "db_password" }]));
+
+    // The container config shows the reference, not the value
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/db/config")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(
+        body["config"]["secrets"],
+        json!([{ "name": "db_password" }])
+    );
+    assert!(!body.to_string().contains("hunter2"));
+
+    // Nor does the audit trail
+    let logs = audit_logger.get_resource_logs("container", "db", 10);
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].user.as_deref(), Some("anonymous"));
+    assert!(!serde_json::to_string(&logs).unwrap().contains("hunter2"));
+
+    // Nothing on disk holds the plaintext
+    let on_disk = fs::read(base.join("secrets").join("db").join("db_password.enc")).unwrap();
+    assert!(!on_disk.windows(7).any(|w| w == b"hunter2"));
+
+    // Secrets on unknown containers are a 404
+    let req = test::TestRequest::put()
+        .uri("/api/v1/containers/missing/secrets/token")
+        .set_json(json!({ "value": "x" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    // Deleting the container removes its stored secrets with it
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/containers/db")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert!(!base.join("secrets").join("db").exists());
+
+    // With auth on, anonymous callers cannot touch secrets at all
+    let config = closed_config();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(store))
+            .app_data(web::Data::new(audit_logger))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+    let req = test::TestRequest::put()
+        .uri("/api/v1/containers/db/secrets/db_password")
+        .set_json(json!({ "value": "x" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+    for req in [
+        test::TestRequest::get().uri("/api/v1/containers/db/secrets"),
+        test::TestRequest::delete().uri("/api/v1/containers/db/secrets/db_password"),
+    ] {
+        assert_eq!(
+            test::call_service(&app, req.to_request()).await.status(),
+            401
+        );
+    }
+}
+
+#[actix_web::test]
+async fn test_interfaces_of_stopped_container_change_its_config() {
+    let host = fake_container(
+        "interfaces",
+        "web",
+        "lxc.uts.name = web\n\
+         lxc.net.0.type = veth\n\
+         lxc.net.0.link = lxcbr0\n\
+         lxc.net.0.name = eth0\n",
+    );
+    let base = &host.base;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(open_config()))
+            .app_data(web::Data::new(Arc::new(AuditLogger::new(100))))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers/web/interfaces")
+        .set_json(json!({
+            "name": "eth1",
+            "bridge": "hvbr1",
+            "ipv4": "10.1.0.5/24"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["live"], false);
+
+    let config = fs::read_to_string(base.join("web/config")).unwrap();
+    assert!(config.contains("lxc.net.1.link = hvbr1\n"));
+    assert!(config.contains("lxc.net.1.ipv4.address = 10.1.0.5/24\n"));
+
+    // Same name again
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers/web/interfaces")
+        .set_json(json!({ "name": "eth1", "bridge": "hvbr2" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 409);
+
+    // An address that is already configured
+    let duplicate = |uri: &str| {
+        test::TestRequest::post()
+            .uri(uri)
+            .set_json(json!({
+                "name": "eth2",
+                "bridge": "hvbr1",
+                "ipv4": "10.1.0.5/24"
+            }))
+            .to_request()
+    };
+    let resp = test::call_service(&app, duplicate("/api/v1/containers/web/interfaces")).await;
+    assert_eq!(resp.status(), 409);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["conflict"]["address"], "10.1.0.5");
+    assert_eq!(body["conflict"]["holder"], "container web");
+
+    // Shared on purpose
+    let uri = "/api/v1/containers/web/interfaces?skip_conflict_check=true";
+    assert_eq!(test::call_service(&app, duplicate(uri)).await.status(), 201);
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/containers/web/interfaces/eth2")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers/web/interfaces")
+        .set_json(json!({ "name": "eth 2", "bridge": "hvbr2" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/containers/web/interfaces/eth0")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["interface"]["bridge"], "lxcbr0");
+
+    // eth1 took eth0's place
+    let config = fs::read_to_string(base.join("web/config")).unwrap();
+    assert!(config.contains("lxc.net.0.name = eth1\n"));
+    assert!(!config.contains("lxc.net.1"));
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/containers/web/interfaces/eth0")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/containers/missing/interfaces/eth0")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_interface_changes_require_authentication() {
+    let config = closed_config();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(Arc::new(AuditLogger::new(100))))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers/web/interfaces")
+        .set_json(json!({ "name": "eth1", "bridge": "hvbr1" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/containers/web/interfaces/eth0")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+}
+
+// Disk usage in the expanded container detail
+#[actix_web::test]
+async fn test_expanded_detail_reports_rootfs_and_volume_usage() {
+    let host = FakeHost::new("disk");
+    let base = &host.base;
+    let volume = base.join("pool/data");
+    fs::create_dir_all(base.join("web/rootfs/etc")).unwrap();
+    fs::create_dir_all(&volume).unwrap();
+    fs::write(base.join("web/rootfs/etc/hostname"), "web\n").unwrap();
+    fs::write(volume.join("db.sqlite"), vec![0u8; 4096]).unwrap();
+    fs::write(
+        base.join("web/config"),
+        format!(
+            "lxc.uts.name = web\n\
+             lxc.mount.entry = {} srv/data none bind,create=dir 0 0\n\
+             lxc.mount.entry = proc proc proc nodev,noexec,nosuid 0 0\n",
+            volume.display()
+        ),
+    )
+    .unwrap();
+
+    host.sh("lxc-ls", "echo web");
+    host.sh("lxc-info", "echo \"State: STOPPED\"");
+
+    let app = test::init_service(App::new().configure(api_server::routes::configure_routes)).await;
+
+    // The first request starts the walks; later ones are served from them
+    let mut disk = serde_json::Value::Null;
+    for _ in 0..100 {
+        let req = test::TestRequest::get()
+            .uri("/api/v1/containers/web?expand=disk")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        disk = body["disk"].clone();
+        if disk["stale"] == false {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(disk["stale"], false);
+    assert_eq!(disk["rootfs"]["used_bytes"], 4);
+    let volumes = disk["volumes"].as_array().unwrap();
+    assert_eq!(volumes.len(), 1);
+    assert_eq!(volumes[0]["target"], "srv/data");
+    assert_eq!(volumes[0]["used_bytes"], 4096);
+    assert!(volumes[0]["measured_at"].is_string());
+    assert_eq!(disk["total_bytes"], 4100);
+}
+
+// Event histories are process-wide, so this container's name is used by no
+// other test
+#[actix_web::test]
+async fn test_start_then_stop_yields_two_events_in_order() {
+    let host = FakeHost::new("events");
+    let base = &host.base;
+    fs::create_dir_all(base.join("history").join("rootfs").join("etc")).unwrap();
+    fs::write(
+        base.join("history").join("config"),
+        "lxc.uts.name = history\n",
+    )
+    .unwrap();
+    host.sh("lxc-ls", "echo history");
+    host.sh("lxc-start", "true");
+    host.sh("lxc-stop", "true");
+    host.sh("lxc-info", "echo 'State: STOPPED'");
+    host.env("SKIP_SYSTEM_CHECKS", "1");
+
+    let mut config = open_config();
+    config.network.skip_start_checks = true;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/history/events")
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["events"], json!([]));
+
+    for action in ["start", "stop"] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/v1/containers/history/{}", action))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success(), "{} failed", action);
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/history/events")
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let events = body["events"].as_array().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["kind"], "started");
+    assert_eq!(events[1]["kind"], "stopped");
+    assert!(events[0]["timestamp"].as_str().unwrap() <= events[1]["timestamp"].as_str().unwrap());
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/missing/events")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    // Audit entries under ?expand=events are scoped like /audit/logs
+    let config = closed_config();
+    let mut users = UserStore::new();
+    let admin = users.create_admin("admin", "admin-password").unwrap();
+    let mut dev = admin.clone();
+    dev.id = uuid::Uuid::new_v4();
+    dev.username = "dev".to_string();
+    dev.role = Role::Viewer;
+    dev.set_password("dev-password");
+    users.add_user(dev).unwrap();
+    let audit_logger = Arc::new(AuditLogger::new(100));
+    for user in ["dev", "ops"] {
+        audit_logger.log_entry(
+            AuditLogger::builder()
+                .user(user.to_string())
+                .action(AuditAction::ContainerStarted)
+                .resource_type("container".to_string())
+                .resource_id("history".to_string())
+                .result(AuditResult::Success)
+                .build()
+                .unwrap(),
+        );
+    }
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(Arc::new(Mutex::new(users))))
+            .app_data(web::Data::new(audit_logger))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+    let events = |bearer: Option<String>| {
+        let mut req = test::TestRequest::get().uri("/api/v1/containers/history?expand=events");
+        if let Some(bearer) = bearer {
+            req = req.insert_header(("Authorization", bearer));
+        }
+        req.to_request()
+    };
+    let mut bearer = std::collections::HashMap::new();
+    for name in ["dev", "admin"] {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/auth/login")
+            .set_json(json!({"username": name, "password": format!("{}-password", name)}))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        bearer.insert(name, format!("Bearer {}", body["token"].as_str().unwrap()));
+    }
+
+    let body: serde_json::Value = test::call_and_read_body_json(&app, events(None)).await;
+    assert!(body["events"].is_null());
+    assert!(body["errors"]["events"].is_string());
+
+    let body: serde_json::Value =
+        test::call_and_read_body_json(&app, events(Some(bearer["dev"].clone()))).await;
+    let logs = body["events"].as_array().unwrap();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0]["user"], "dev");
+
+    let body: serde_json::Value =
+        test::call_and_read_body_json(&app, events(Some(bearer["admin"].clone()))).await;
+    assert_eq!(body["events"].as_array().unwrap().len(), 2);
+}
+
+#[actix_web::test]
+async fn test_delete_refuses_dependents_unless_cascading() {
+    let host = FakeHost::new("delete");
+    let base = &host.base;
+    fs::create_dir_all(base.join("web")).unwrap();
+    fs::write(
+        base.join("web").join("config"),
+        "lxc.uts.name = web\n\
+         lxc.net.0.type = veth\n\
+         lxc.net.0.link = lxcbr0\n\
+         lxc.net.0.ipv4.address = 10.0.3.5/24\n\
+         lxc.mount.entry = /srv/volumes/data srv/data none bind 0 0\n",
+    )
+    .unwrap();
+    let containers = base.join("containers");
+    let snapshots = base.join("snapshots");
+    let iptables_log = base.join("iptables.log");
+    fs::write(&containers, "web\n").unwrap();
+    fs::write(&snapshots, "snap0\npinned\n").unwrap();
+
+    host.sh("lxc-ls", &format!("cat {}", containers.display()));
+    host.sh("lxc-info", "echo 'State: STOPPED'");
+    host.sh("lxc-stop", "true");
+    // `pinned` cannot be deleted until the marker file is removed
+    host.sh("lxc-snapshot", &format!(
+            "snaps={snaps}\n\
+             if [ \"$1\" = -L ]; then\n\
+               while read s; do echo \"$s (/var/lib/lxc/web/snaps/$s) 2024:01:01 00:00:00\"; done < $snaps\n\
+             elif [ \"$1\" = -d ]; then\n\
+               if [ \"$2\" = pinned ] && [ -e {marker} ]; then echo busy >&2; exit 1; fi\n\
+               grep -vx \"$2\" $snaps > $snaps.new; mv $snaps.new $snaps\n\
+             fi",
+            snaps = snapshots.display(),
+            marker = base.join("pinned").display(),
+        ),
+    );
+    fs::write(base.join("pinned"), "").unwrap();
+    host.sh("lxc-destroy", &format!(": > {}", containers.display()));
+    host.sh("iptables-save", &format!(
+            "if ! grep -q deleted {log} 2>/dev/null; then \
+             echo '-A PREROUTING -p tcp -m tcp --dport 8080 -j DNAT --to-destination 10.0.3.5:80'; fi",
+            log = iptables_log.display()
+        ),
+    );
+    host.sh(
+        "iptables",
+        &format!(
+            "printf '%s\\n' \"$*\" >> {log}; echo deleted >> {log}",
+            log = iptables_log.display()
+        ),
+    );
+    host.env("SKIP_SYSTEM_CHECKS", "1");
+
+    let ipam = Arc::new(Ipam::open("10.0.3.0/24", &base.join("ipam.json")).unwrap());
+    ipam.allocate("web").unwrap();
+    let audit_logger = Arc::new(AuditLogger::new(100));
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(open_config()))
+            .app_data(web::Data::new(ipam.clone()))
+            .app_data(web::Data::new(audit_logger.clone()))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/containers/web")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["dependents"]["snapshots"][0], "snap0");
+    assert_eq!(body["dependents"]["snapshots"][1], "pinned");
+    assert_eq!(body["dependents"]["volumes"][0], "/srv/volumes/data");
+    assert_eq!(
+        body["dependents"]["port_forwards"][0]["to_destination"],
+        "10.0.3.5:80"
+    );
+    assert_eq!(fs::read_to_string(&containers).unwrap(), "web\n");
+
+    // A snapshot that cannot be deleted keeps the container; the rest of
+    // the cleanups still happen and the report says what is left
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/containers/web?cascade=true")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 500);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let completed: Vec<&str> = body["completed"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|step| step.as_str().unwrap())
+        .collect();
+    assert!(completed.contains(&"snapshot:snap0"));
+    assert!(completed.contains(&"volume:/srv/volumes/data"));
+    assert!(completed.contains(&"addresses"));
+    assert!(completed
+        .iter()
+        .any(|step| step.starts_with("port-forward:PREROUTING")));
+    assert_eq!(body["remaining"][0]["step"], "snapshot:pinned");
+    assert!(body["remaining"][0]["error"]
+        .as_str()
+        .unwrap()
+        .contains("busy"));
+    assert_eq!(body["remaining"][1]["step"], "destroy");
+    assert_eq!(fs::read_to_string(&containers).unwrap(), "web\n");
+    assert!(ipam.allocations().is_empty());
+    assert!(!fs::read_to_string(base.join("web").join("config"))
+        .unwrap()
+        .contains("lxc.mount.entry"));
+    assert!(fs::read_to_string(&iptables_log)
+        .unwrap()
+        .starts_with("-t nat -D PREROUTING -p tcp -m tcp --dport 8080 -j DNAT"));
+
+    // Retrying picks up only what is left
+    fs::remove_file(base.join("pinned")).unwrap();
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/containers/web?cascade=true")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["completed"][0], "snapshot:pinned");
+    assert_eq!(
+        body["completed"].as_array().unwrap().last().unwrap(),
+        "destroy"
+    );
+    assert_eq!(fs::read_to_string(&containers).unwrap(), "");
+
+    let deleted =
+        audit_logger.get_logs(None, Some(AuditAction::ContainerDeleted), None, None, None);
+    assert_eq!(deleted.len(), 1);
+    let snapshot_deletes = audit_logger.get_logs(
+        None,
+        Some(AuditAction::ContainerSnapshotDeleted),
+        None,
+        None,
+        None,
+    );
+    assert_eq!(snapshot_deletes.len(), 3);
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/containers/web")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
+
+// The long-polling `/wait` endpoints
+#[actix_web::test]
+async fn test_wait_for_container_and_job() {
+    let _host = fake_container("wait", "web", "lxc.uts.name = web\n");
+    let monitor = Arc::new(LxcMonitor::new());
+    let jobs = Arc::new(JobManager::default());
+    let mut config = AppConfig::default();
+    config.server.max_wait_secs = 1;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(monitor.clone()))
+            .app_data(web::Data::new(jobs.clone()))
+            .app_data(web::Data::new(config))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    // Started while the request is waiting
+    let starter = monitor.clone();
+    actix_rt::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        starter.observe("web", ContainerStatus::Running);
+    });
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/web/wait?for=running&timeout=5")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "running");
+
+    // Capped at max_wait_secs instead of the requested hour
+    let started = Instant::now();
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/web/wait?for=stopped&timeout=3600")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 408);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "running");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/web/wait?for=up")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/missing/wait?for=running")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    // Jobs are waited for until they finish
+    let job = jobs.create("start-all");
+    let finisher = jobs.clone();
+    actix_rt::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        finisher.succeed(job.id, json!({ "started": 1 }));
+    });
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/jobs/{}/wait?timeout=5", job.id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "succeeded");
+    assert_eq!(body["result"]["started"], 1);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/jobs/{}/wait", Uuid::new_v4()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
+
+// Following one request through to the audit entries of the background job
+// it started
+#[actix_web::test]
+async fn test_audit_trail_of_a_request_by_correlation_id() {
+    let host = FakeHost::new("correlation");
+    host.sh("lxc-ls", "exit 0");
+
+    let metrics = Arc::new(MetricsCollector::new());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(metrics.clone()))
+            .app_data(web::Data::new(Arc::new(AuditLogger::new(100))))
+            .app_data(web::Data::new(Arc::new(JobManager::default())))
+            .app_data(web::Data::new(open_config()))
+            .configure(api_server::routes::configure_routes)
+            .wrap(RequestTracing::new(metrics)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/system/start-all")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 202);
+    let correlation_id: Uuid = resp
+        .headers()
+        .get("X-Correlation-ID")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let job_id = body["job_id"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/jobs/{}/wait?timeout=5", job_id))
+        .to_request();
+    let job: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(job["status"], "succeeded");
+    assert_eq!(job["correlation_id"], correlation_id.to_string());
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/api/v1/audit/logs?correlation_id={}",
+            correlation_id
+        ))
+        .to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let logs = body["logs"].as_array().unwrap();
+    assert!(!logs.is_empty());
+    assert!(logs
+        .iter()
+        .all(|log| log["correlation_id"] == correlation_id.to_string()));
+}
+
+#[actix_web::test]
+async fn test_batch_snapshot_reports_missing_containers() {
+    let host = FakeHost::new("batch");
+    let base = &host.base;
+    let log = base.join("snapshots.log");
+    host.sh("lxc-ls", "echo web\necho db");
+    host.sh(
+        "lxc-snapshot",
+        &format!("printf '%s\\n' \"$*\" >> {}", log.display()),
+    );
+
+    // Snapshotting needs a caller
+    let config = closed_config();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/snapshots/batch")
+        .set_json(json!({ "containers": ["web"] }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+    assert!(!log.exists());
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(open_config()))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/snapshots/batch")
+        .set_json(json!({
+            "containers": ["web", "missing", "db", "web"],
+            "name_prefix": "nightly"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["succeeded"], 2);
+    assert_eq!(body["failed"], 1);
+    let snapshot_name = body["snapshot_name"].as_str().unwrap();
+    assert!(snapshot_name.starts_with("nightly_"));
+
+    // Request order is kept and duplicates are snapshotted once
+    let results = body["results"].as_array().unwrap();
+    let containers: Vec<_> = results.iter().map(|r| &r["container"]).collect();
+    assert_eq!(containers, ["web", "missing", "db"]);
+    assert_eq!(results[0]["snapshot"]["name"], snapshot_name);
+    assert_eq!(results[1]["ok"], false);
+    assert!(results[1]["error"].as_str().unwrap().contains("missing"));
+
+    let calls = fs::read_to_string(&log).unwrap();
+    assert_eq!(calls.lines().count(), 2);
+    assert!(calls
+        .lines()
+        .all(|line| line.starts_with(&format!("-n {}", snapshot_name))));
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/snapshots/batch")
+        .set_json(json!({ "containers": [] }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+}
+
+#[actix_web::test]
+async fn test_uncached_image_is_pulled_by_a_job() {
+    let host = FakeHost::new("image_pull");
+    let base = &host.base;
+    host.sh("lxc-ls", "true");
+    // Lists a one-image index; otherwise leaves the rootfs where the
+    // download template caches it
+    host.sh(
+        "lxc-create",
+        "case \" $* \" in *\" --list \"*)\n\
+           printf 'DIST\\tRELEASE\\tARCH\\tVARIANT\\tBUILD\\n---\\n'\n\
+           printf 'alpine\\t3.19\\tarm64\\tdefault\\t20240101_13:00\\n'\n\
+           exit 0;;\n\
+         esac\n\
+         while [ $# -gt 0 ]; do\n\
+           case \"$1\" in -d) d=$2;; -r) r=$2;; -a) a=$2;; esac\n\
+           shift\n\
+         done\n\
+         dir=\"$LXC_CACHE_PATH/download/$d/$r/$a/default\"\n\
+         mkdir -p \"$dir\" && echo rootfs > \"$dir/rootfs.tar.xz\"",
+    );
+
+    let cache = Arc::new(ImageCache::new(base.join("images"), u64::MAX));
+    let jobs = Arc::new(JobManager::default());
+    let audit_logger = Arc::new(AuditLogger::new(100));
+    let app = |config: AppConfig| {
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(cache.clone()))
+            .app_data(web::Data::new(jobs.clone()))
+            .app_data(web::Data::new(audit_logger.clone()))
+            .configure(api_server::routes::configure_routes)
+    };
+    let pull = |release: &str| {
+        test::TestRequest::post()
+            .uri("/api/v1/templates/cache")
+            .set_json(json!({"distro": "alpine", "release": release, "arch": "arm64"}))
+            .to_request()
+    };
+
+    // Anonymous callers cannot start downloads
+    let config = closed_config();
+    let closed = test::init_service(app(config)).await;
+    assert_eq!(
+        test::call_service(&closed, pull("3.19")).await.status(),
+        401
+    );
+
+    let app = test::init_service(app(open_config())).await;
+
+    // Not in the index: refused before any job starts
+    assert_eq!(test::call_service(&app, pull("edge")).await.status(), 400);
+
+    let resp = test::call_service(&app, pull("3.19")).await;
+    assert_eq!(resp.status(), 202);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["cached"], false);
+    let job_id = body["job_id"].as_str().unwrap().to_string();
+    let logs = audit_logger.get_resource_logs("image", "alpine:3.19:arm64", 10);
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].user.as_deref(), Some("anonymous"));
+
+    let mut job = serde_json::Value::Null;
+    for _ in 0..100 {
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/jobs/{}", job_id))
+            .to_request();
+        job = test::read_body_json(test::call_service(&app, req).await).await;
+        if job["status"] != "pending" && job["status"] != "running" {
+            break;
+        }
+        actix_rt::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(job["status"], "succeeded", "{}", job);
+    assert_eq!(job["result"]["image"], "alpine:3.19:arm64");
+    assert_eq!(cache.list().unwrap()[0].id, "alpine:3.19:arm64");
+
+    // Now cached: answered without a job
+    let resp = test::call_service(&app, pull("3.19")).await;
+    assert_eq!(resp.status(), 200);
+}
+
+#[tokio::test]
+async fn test_chains_are_removed_from_the_record() {
+    if network::capabilities::ensure_net_admin().is_err() {
+        eprintln!("skipping: CAP_NET_ADMIN is required");
+        return;
+    }
+
+    let host = FakeHost::new("egress");
+    let base = &host.base;
+    fs::create_dir_all(base.join("web")).unwrap();
+    fs::write(
+        base.join("web").join("config"),
+        "lxc.uts.name = web\n\
+         # orchestrator.egress.default_drop = true\n\
+         # orchestrator.egress.allow = 10.0.0.0/8 port=443\n",
+    )
+    .unwrap();
+    let links = base.join("links");
+    fs::write(&links, "Link: vethweb0\n").unwrap();
+    let iptables_log = base.join("iptables.log");
+
+    host.sh("lxc-ls", "echo web");
+    host.sh(
+        "lxc-info",
+        &format!("echo 'State: RUNNING'; cat {}", links.display()),
+    );
+    host.sh(
+        "iptables",
+        &format!(
+            "printf '%s\\n' \"$*\" >> {}; case \"$1\" in -C) exit 1;; esac",
+            iptables_log.display()
+        ),
+    );
+
+    let record = base.join("egress-chains.json");
+    egress::configure(&record).unwrap();
+    egress::apply("web").await.unwrap();
+    assert_eq!(egress::recorded("web"), vec!["vethweb0".to_string()]);
+    assert!(fs::read_to_string(&record).unwrap().contains("vethweb0"));
+
+    // A stopped container has no links left for lxc-info to report
+    fs::write(&links, "").unwrap();
+    fs::write(&iptables_log, "").unwrap();
+    egress::remove("web").await;
+
+    let log = fs::read_to_string(&iptables_log).unwrap();
+    assert!(log.contains("-X ARM-EGRESS-vethweb0"), "{}", log);
+    assert!(egress::recorded("web").is_empty());
+
+    // A restarted server picks the record up again
+    egress::apply("web").await.unwrap();
+    egress::record("web", "vethweb1");
+    egress::configure(&record).unwrap();
+    assert_eq!(egress::recorded("web"), vec!["vethweb1".to_string()]);
+
+    // The record is process-wide; later tests must not find web's chain
+    egress::remove("web").await;
+    assert!(egress::recorded("web").is_empty());
+}
+
+#[actix_web::test]
+async fn test_interface_query_failure_is_not_an_empty_list() {
+    let host = FakeHost::new("ip");
+    // Fails unless IP_FAKE_OUTPUT names a file to print
+    host.script("ip", "#!/bin/sh\n\
+         [ -n \"$IP_FAKE_OUTPUT\" ] || { echo 'RTNETLINK answers: Operation not permitted' >&2; exit 2; }\n\
+         cat \"$IP_FAKE_OUTPUT\"\n",
+    );
+
+    let app = test::init_service(App::new().configure(api_server::routes::configure_routes)).await;
+
+    let req = test::TestRequest::get().uri("/api/v1/network").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 500);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("Operation not permitted"));
+
+    // A host with only loopback genuinely has nothing to list
+    let output = host.base.join("output.json");
+    fs::write(
+        &output,
+        r#"[{"ifname":"lo","operstate":"UNKNOWN","link_type":"loopback","addr_info":[]}]"#,
+    )
+    .unwrap();
+    host.env("IP_FAKE_OUTPUT", &output);
+
+    let req = test::TestRequest::get().uri("/api/v1/network").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["interfaces"], json!([]));
+}
+
+#[actix_web::test]
+async fn test_overview_degrades_per_section() {
+    let host = FakeHost::new("overview");
+    host.script(
+        "ip",
+        r#"#!/bin/sh
+cat <<'JSON'
+[{"ifname":"lxcbr0","operstate":"UP","link_type":"ether","linkinfo":{"info_kind":"bridge"},
+  "addr_info":[{"family":"inet","local":"10.0.3.1","prefixlen":24}]},
+ {"ifname":"vethA","operstate":"UP","link_type":"ether","master":"lxcbr0",
+  "linkinfo":{"info_kind":"veth"},"addr_info":[]}]
+JSON
+"#,
+    );
+    host.script(
+        "iptables-save",
+        "#!/bin/sh\necho 'iptables-save: permission denied' >&2\nexit 1\n",
+    );
+    host.script("lxc-ls", "#!/bin/sh\necho 'lxc-ls: broken' >&2\nexit 1\n");
+
+    let app = test::init_service(App::new().configure(api_server::routes::configure_routes)).await;
+    let req = test::TestRequest::get()
+        .uri("/api/v1/network/overview")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+
+    let bridges = body["bridges"].as_array().unwrap();
+    assert_eq!(bridges.len(), 1);
+    assert_eq!(bridges[0]["name"], "lxcbr0");
+    assert_eq!(bridges[0]["ip_addresses"][0], "10.0.3.1/24");
+    // The port is known even though its container could not be resolved
+    assert_eq!(bridges[0]["ports"][0]["interface"], "vethA");
+    assert!(bridges[0]["ports"][0]["container"].is_null());
+    assert!(bridges[0]["nat_rules"].is_null());
+    assert!(body["ipam"].is_null());
+
+    let errors = body["errors"].as_object().unwrap();
+    assert!(errors["nat_rules"]
+        .as_str()
+        .unwrap()
+        .contains("permission denied"));
+    assert!(errors.contains_key("containers"));
+    assert!(!errors.contains_key("interfaces"));
+}
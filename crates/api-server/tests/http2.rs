@@ -0,0 +1,113 @@
+//! Tests that the TLS listener negotiates `h2` over ALPN only when HTTP/2 is
+//! enabled. Uses the self-signed `localhost` certificate in `tests/fixtures`.
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use api_server::config::{ServerConfig, TlsConfig};
+use api_server::tls;
+use std::io::BufReader;
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn server_config(port: u16) -> ServerConfig {
+    ServerConfig {
+        host: "127.0.0.1".to_string(),
+        port,
+        workers: Some(1),
+        max_connections: None,
+        keepalive: None,
+        client_timeout: None,
+        tls: Some(TlsConfig {
+            cert_file: fixture("tls-cert.pem"),
+            key_file: fixture("tls-key.pem"),
+            ca_file: None,
+        }),
+        require_privileges: false,
+        http2_enabled: true,
+        max_wait_secs: 300,
+        blocking_threads: None,
+    }
+}
+
+fn app() -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    App::new().route("/", web::get().to(HttpResponse::Ok))
+}
+
+/// Complete a handshake offering `h2` and `http/1.1`; returns the protocol
+/// the server picked
+async fn negotiated_protocol(addr: SocketAddr) -> Option<Vec<u8>> {
+    actix_web::web::block(move || {
+        let mut roots = rustls::RootCertStore::empty();
+        let cert_file = &mut BufReader::new(std::fs::File::open(fixture("tls-cert.pem")).unwrap());
+        for cert in rustls_pemfile::certs(cert_file) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let mut config = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        let mut conn =
+            rustls::ClientConnection::new(Arc::new(config), "localhost".try_into().unwrap())
+                .unwrap();
+        let mut sock = TcpStream::connect(addr).unwrap();
+        while conn.is_handshaking() {
+            conn.complete_io(&mut sock).unwrap();
+        }
+        conn.alpn_protocol().map(|p| p.to_vec())
+    })
+    .await
+    .unwrap()
+}
+
+#[actix_web::test]
+async fn test_alpn_offers_h2_when_enabled() {
+    let config = server_config(0);
+    let tls_config = tls::load_server_config(config.tls.as_ref().unwrap()).unwrap();
+    let server = HttpServer::new(app)
+        .workers(1)
+        .bind_rustls_0_23((config.host.as_str(), config.port), tls_config)
+        .unwrap();
+    let addr = server.addrs()[0];
+    let server = server.disable_signals().run();
+    let handle = server.handle();
+    actix_rt::spawn(server);
+
+    assert_eq!(negotiated_protocol(addr).await, Some(b"h2".to_vec()));
+    handle.stop(false).await;
+}
+
+#[actix_web::test]
+async fn test_alpn_falls_back_to_http1_when_disabled() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = ServerConfig {
+        http2_enabled: false,
+        ..server_config(port)
+    };
+    let tls_config = tls::load_server_config(config.tls.as_ref().unwrap()).unwrap();
+    let server = tls::http1_server(app, tls_config, &config).unwrap();
+    let handle = server.handle();
+    actix_rt::spawn(server);
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    assert_eq!(negotiated_protocol(addr).await, Some(b"http/1.1".to_vec()));
+    handle.stop(false).await;
+}
@@ -1,4 +1,5 @@
 use actix_web::{test, web, App};
+use api_server::observability::{MetricsCollector, SystemStats};
 use serde_json::json;
 use std::sync::Arc;
 
@@ -14,13 +15,23 @@ fn create_test_app() -> App<
 > {
     // Create required shared state
     let metrics_collector = Arc::new(api_server::observability::MetricsCollector::new());
-    let user_store = Arc::new(std::sync::Mutex::new(api_server::rbac::UserStore::new()));
+    let mut users = api_server::rbac::UserStore::new();
+    users.create_admin("admin", "admin-password").unwrap();
+    let user_store = Arc::new(std::sync::Mutex::new(users));
     let audit_logger = Arc::new(api_server::audit::AuditLogger::new(10000));
+    let job_manager = Arc::new(api_server::jobs::JobManager::default());
+    let join_tokens = Arc::new(api_server::join_tokens::JoinTokenManager::new(b"test"));
+    let membership = Arc::new(std::sync::RwLock::new(cluster::MembershipManager::new(
+        uuid::Uuid::new_v4(),
+    )));
 
     App::new()
         .app_data(web::Data::new(metrics_collector))
         .app_data(web::Data::new(user_store))
         .app_data(web::Data::new(audit_logger))
+        .app_data(web::Data::new(job_manager))
+        .app_data(web::Data::new(join_tokens))
+        .app_data(web::Data::new(membership))
         .configure(api_server::routes::configure_routes)
 }
 
@@ -101,6 +112,159 @@ async fn test_create_container() {
     }
 }
 
+#[actix_web::test]
+async fn test_create_container_rejects_invalid_egress_policy() {
+    let app = test::init_service(App::new().configure(api_server::routes::configure_routes)).await;
+
+    let container_request = json!({
+        "name": "egress-container",
+        "template": "alpine",
+        "config": {
+            "network_interfaces": [],
+            "rootfs_path": "/var/lib/lxc/egress-container/rootfs",
+            "environment": [],
+            "egress_policy": {
+                "default_drop": true,
+                "allow": [{"cidr": "10.0.0.0/40", "port": 443}]
+            }
+        }
+    });
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers")
+        .set_json(&container_request)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+}
+
+#[actix_web::test]
+async fn test_create_container_rejects_gateway_outside_subnet() {
+    let app = test::init_service(App::new().configure(api_server::routes::configure_routes)).await;
+
+    let container_request = json!({
+        "name": "gateway-container",
+        "template": "alpine",
+        "config": {
+            "network_interfaces": [{
+                "name": "eth0",
+                "bridge": "lxcbr0",
+                "ipv4": "10.0.3.10/24",
+                "ipv6": null,
+                "mac": null,
+                "gateway_v4": "10.0.4.1",
+                "routes": ["10.2.0.0/16 via 10.0.3.254"]
+            }],
+            "rootfs_path": "/var/lib/lxc/gateway-container/rootfs",
+            "environment": []
+        }
+    });
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers")
+        .set_json(&container_request)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(
+        body["fields"][0]["field"],
+        "config.network_interfaces[0].gateway_v4"
+    );
+    assert_eq!(body["fields"].as_array().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_create_container_auto_address() {
+    let container_request = json!({
+        "name": "auto-container",
+        "template": "alpine",
+        "config": {
+            "network_interfaces": [
+                {"name": "eth0", "bridge": "lxcbr0", "ipv4": "auto", "ipv6": null, "mac": null}
+            ],
+            "rootfs_path": "/var/lib/lxc/auto-container/rootfs",
+            "environment": []
+        }
+    });
+
+    // Without a range there is nothing to allocate from
+    let app = test::init_service(App::new().configure(api_server::routes::configure_routes)).await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers")
+        .set_json(&container_request)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    // An exhausted range is reported before anything is created
+    let dir = std::env::temp_dir().join(format!("ipam-api-{}", std::process::id()));
+    let ipam = network::Ipam::open("10.0.3.0/29", &dir.join("ipam.json")).unwrap();
+    while ipam.allocate("other").is_ok() {}
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Arc::new(ipam)))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers")
+        .set_json(&container_request)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[actix_web::test]
+async fn test_oom_score_adj_out_of_range_is_rejected() {
+    let app = test::init_service(App::new().configure(api_server::routes::configure_routes)).await;
+
+    let container_request = json!({
+        "name": "oom-container",
+        "template": "alpine",
+        "config": {
+            "network_interfaces": [],
+            "rootfs_path": "/var/lib/lxc/oom-container/rootfs",
+            "environment": [],
+            "oom_score_adj": 1001
+        }
+    });
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers")
+        .set_json(&container_request)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/containers/oom-container")
+        .set_json(json!({ "oom_score_adj": -1001 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+}
+
+#[actix_web::test]
+async fn test_list_nodes_requires_membership() {
+    let app = test::init_service(create_test_app()).await;
+    let req = test::TestRequest::get()
+        .uri("/api/v1/cluster/nodes")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert!(body["nodes"].is_array());
+
+    // Unknown membership is an error, not an empty cluster
+    let app = test::init_service(App::new().configure(api_server::routes::configure_routes)).await;
+    let req = test::TestRequest::get()
+        .uri("/api/v1/cluster/nodes")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 503);
+}
+
 #[actix_web::test]
 async fn test_cluster_status() {
     let app = test::init_service(App::new().configure(api_server::routes::configure_routes)).await;
@@ -237,6 +401,14 @@ async fn test_nonexistent_container_operations() {
     let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_client_error());
 
+    // Prerequisite checks report a missing container before anything else
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers/nonexistent/start?fix=true")
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
     // Test stopping non-existent container
     let req = test::TestRequest::post()
         .uri("/api/v1/containers/nonexistent/stop")
@@ -246,6 +418,128 @@ async fn test_nonexistent_container_operations() {
     assert!(resp.status().is_client_error());
 }
 
+#[actix_web::test]
+async fn test_container_usage_history() {
+    use api_server::config::UsageHistoryConfig;
+    use api_server::usage_history::UsageHistory;
+
+    let app = test::init_service(create_test_app()).await;
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/nonexistent/stats/history")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 503);
+
+    let history = Arc::new(UsageHistory::new(&UsageHistoryConfig::default()));
+    let app = test::init_service(create_test_app().app_data(web::Data::new(history.clone()))).await;
+    for (query, status) in [
+        ("window=6h&step=1m", 404),
+        ("window=6x", 400),
+        ("step=0m", 400),
+        // 7 days at 1 second steps is far too many points
+        ("window=7d&step=1s", 400),
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/api/v1/containers/nonexistent/stats/history?{}",
+                query
+            ))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            status,
+            "{}",
+            query
+        );
+    }
+}
+
+#[actix_web::test]
+async fn test_get_container_expand() {
+    let app = test::init_service(create_test_app()).await;
+
+    // Unknown sections are rejected before the container is looked up
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/nonexistent?expand=snapshots,bogus")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    // A missing container is still a 404 when expanding
+    let req = test::TestRequest::get()
+        .uri("/api/v1/containers/nonexistent?expand=snapshots,volumes,stats,events")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_cluster_schedule_explain_and_job_record() {
+    let placement = serde_json::json!({ "memory_bytes": 1024 });
+
+    // Scheduling and reading jobs need a caller
+    let mut config = api_server::config::AppConfig::default();
+    config.security.jwt_secret = Some("test-secret-at-least-32-characters-long".to_string());
+    let app = test::init_service(create_test_app().app_data(web::Data::new(config))).await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/cluster/schedule?explain=true")
+        .set_json(&placement)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+    let req = test::TestRequest::get().uri("/api/v1/jobs").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    let app = test::init_service(create_test_app().app_data(web::Data::new(config))).await;
+
+    // Dry run: explanation only, no job
+    let req = test::TestRequest::post()
+        .uri("/api/v1/cluster/schedule?explain=true")
+        .set_json(&placement)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert!(body["nodes"].as_array().unwrap().is_empty());
+    assert!(body["chosen_node"].is_null());
+
+    // Real placement with no nodes fails, but the job keeps the explanation
+    let req = test::TestRequest::post()
+        .uri("/api/v1/cluster/schedule")
+        .set_json(&placement)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let job_id = body["job_id"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/jobs/{}", job_id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let job: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(job["kind"], "placement");
+    assert_eq!(job["status"], "failed");
+    assert!(job["result"]["explanation"]["nodes"].is_array());
+}
+
+#[actix_web::test]
+async fn test_jobs_list_includes_operation_queue() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    let app = test::init_service(create_test_app().app_data(web::Data::new(config))).await;
+    let req = test::TestRequest::get().uri("/api/v1/jobs").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert!(body["jobs"].is_array());
+    let operations = &body["operations"];
+    assert!(operations["max_concurrent"].as_u64().unwrap() > 0);
+    assert!(operations["running"].is_array());
+    assert!(operations["queued"].is_array());
+}
+
 // Tests for new features added on 2026-01-28
 
 #[actix_web::test]
@@ -305,6 +599,103 @@ async fn test_metrics_json_endpoint() {
     );
 }
 
+/// Load fails, memory panics and disk works
+struct BrokenStats;
+
+impl SystemStats for BrokenStats {
+    fn load(&self) -> Result<sys_info::LoadAvg, String> {
+        Err("/proc/loadavg unreadable".to_string())
+    }
+
+    fn memory(&self) -> Result<sys_info::MemInfo, String> {
+        panic!("meminfo parser blew up")
+    }
+
+    fn disk(&self) -> Result<sys_info::DiskInfo, String> {
+        Ok(sys_info::DiskInfo {
+            total: 1000,
+            free: 250,
+        })
+    }
+}
+
+#[actix_web::test]
+async fn test_failing_collectors_are_flagged_not_fatal() {
+    let stats: Arc<dyn SystemStats> = Arc::new(BrokenStats);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Arc::new(MetricsCollector::new())))
+            .app_data(web::Data::new(stats))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/metrics/json").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["collectors"]["load"]["status"], "error");
+    assert_eq!(
+        body["collectors"]["load"]["error"],
+        "/proc/loadavg unreadable"
+    );
+    assert_eq!(body["collectors"]["memory"]["status"], "error");
+    assert_eq!(body["collectors"]["disk"]["status"], "ok");
+    // Missing data is absent rather than reported as zero
+    assert!(body["metrics"]["system_load_1min"].is_null());
+    assert!(body["metrics"]["memory_total_kb"].is_null());
+    assert_eq!(body["metrics"]["disk_total_kb"], 1000);
+
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body = test::read_body(resp).await;
+    let metrics = String::from_utf8(body.to_vec()).unwrap();
+    for line in [
+        "arm_hypervisor_collector_up{collector=\"load\"} 0",
+        "arm_hypervisor_collector_up{collector=\"memory\"} 0",
+        "arm_hypervisor_collector_up{collector=\"disk\"} 1",
+        // Counted once by each endpoint
+        "arm_hypervisor_collector_errors_total{collector=\"load\"} 2",
+        "arm_hypervisor_collector_errors_total{collector=\"memory\"} 2",
+        "arm_hypervisor_collector_errors_total{collector=\"disk\"} 0",
+        "arm_hypervisor_disk_total_kb 1000",
+    ] {
+        assert!(
+            metrics.lines().any(|l| l == line),
+            "missing {:?} in\n{}",
+            line,
+            metrics
+        );
+    }
+    assert!(!metrics.contains("arm_hypervisor_system_load_1min"));
+
+    // Each metric is described once, however many series it has
+    let scrape =
+        prometheus_parse::Scrape::parse(metrics.lines().map(|l| Ok(l.to_string()))).unwrap();
+    for name in [
+        "arm_hypervisor_collector_up",
+        "arm_hypervisor_collector_errors_total",
+        "arm_hypervisor_privileged_commands_total",
+    ] {
+        assert!(scrape.docs.contains_key(name), "no HELP for {}", name);
+        assert_eq!(
+            metrics.matches(&format!("# TYPE {} ", name)).count(),
+            1,
+            "TYPE of {} repeated",
+            name
+        );
+    }
+    let load_up = scrape
+        .samples
+        .iter()
+        .find(|s| {
+            s.metric == "arm_hypervisor_collector_up" && s.labels.get("collector") == Some("load")
+        })
+        .unwrap();
+    assert!(matches!(load_up.value, prometheus_parse::Value::Gauge(v) if v == 0.0));
+}
+
 #[actix_web::test]
 async fn test_list_snapshots() {
     let app = test::init_service(create_test_app()).await;
@@ -323,8 +714,20 @@ async fn test_list_snapshots() {
 }
 
 #[actix_web::test]
-async fn test_list_users() {
+async fn test_verify_snapshot_missing_container() {
     let app = test::init_service(create_test_app()).await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/containers/nonexistent/snapshots/snap0/verify")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_list_users() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    let app = test::init_service(create_test_app().app_data(web::Data::new(config))).await;
     let req = test::TestRequest::get().uri("/api/v1/users").to_request();
     let resp = test::call_service(&app, req).await;
     let status = resp.status();
@@ -337,17 +740,1637 @@ async fn test_list_users() {
 }
 
 #[actix_web::test]
-async fn test_get_audit_logs() {
-    let app = test::init_service(create_test_app()).await;
+async fn test_user_management_requires_an_admin() {
+    use api_server::rbac::{Role, UserStore};
+
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = true;
+    config.security.jwt_secret = Some("test-secret-at-least-32-characters-long".to_string());
+    let mut users = UserStore::new();
+    let admin = users.create_admin("admin", "admin-password").unwrap();
+    let mut viewer = admin.clone();
+    viewer.id = uuid::Uuid::new_v4();
+    viewer.username = "viewer".to_string();
+    viewer.role = Role::Viewer;
+    viewer.set_password("viewer-password");
+    users.add_user(viewer).unwrap();
+    let audit_logger = Arc::new(api_server::audit::AuditLogger::new(100));
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(Arc::new(std::sync::Mutex::new(users))))
+            .app_data(web::Data::new(audit_logger.clone())),
+    )
+    .await;
+    let mut bearer = std::collections::HashMap::new();
+    for name in ["viewer", "admin"] {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/auth/login")
+            .set_json(json!({"username": name, "password": format!("{}-password", name)}))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        bearer.insert(name, format!("Bearer {}", body["token"].as_str().unwrap()));
+    }
+    let create = || {
+        test::TestRequest::post().uri("/api/v1/users").set_json(
+            json!({"username": "mallory", "role": "Admin", "password": "mallory-password"}),
+        )
+    };
+
+    // Anonymous callers cannot mint an admin, nor remove the real one
+    let resp = test::call_service(&app, create().to_request()).await;
+    assert_eq!(resp.status(), 401);
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/users/admin")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+    let req = test::TestRequest::get().uri("/api/v1/users").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    // Viewers may look but not change
     let req = test::TestRequest::get()
-        .uri("/api/v1/audit/logs")
+        .uri("/api/v1/users/admin")
+        .insert_header(("Authorization", bearer["viewer"].clone()))
         .to_request();
-    let resp = test::call_service(&app, req).await;
-    let status = resp.status();
-    // With proper dependencies, audit logs should return 200
-    assert!(
-        status.is_success(),
-        "Audit logs endpoint should return 2xx with dependencies, got: {}",
-        status
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let req = create()
+        .insert_header(("Authorization", bearer["viewer"].clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/users/admin")
+        .insert_header(("Authorization", bearer["viewer"].clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = create()
+        .insert_header(("Authorization", bearer["admin"].clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/users/mallory")
+        .insert_header(("Authorization", bearer["admin"].clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let logs = audit_logger.get_resource_logs("user", "mallory", 10);
+    assert_eq!(logs.len(), 2);
+    assert!(logs.iter().all(|log| log.user.as_deref() == Some("admin")));
+}
+
+#[actix_web::test]
+async fn test_non_admins_may_only_change_their_own_password() {
+    use api_server::rbac::{Role, UserStore};
+
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = true;
+    config.security.jwt_secret = Some("test-secret-at-least-32-characters-long".to_string());
+    let mut users = UserStore::new();
+    let admin = users.create_admin("admin", "admin-password").unwrap();
+    let mut viewer = admin.clone();
+    viewer.id = uuid::Uuid::new_v4();
+    viewer.username = "viewer".to_string();
+    viewer.role = Role::Viewer;
+    viewer.set_password("viewer-password");
+    users.add_user(viewer).unwrap();
+    let users = Arc::new(std::sync::Mutex::new(users));
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(users.clone())),
+    )
+    .await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({"username": "viewer", "password": "viewer-password"}))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let bearer = format!("Bearer {}", body["token"].as_str().unwrap());
+    let update = |username: &str, body: serde_json::Value| {
+        test::TestRequest::put()
+            .uri(&format!("/api/v1/users/{}", username))
+            .insert_header(("Authorization", bearer.clone()))
+            .set_json(body)
+            .to_request()
+    };
+
+    for (username, body) in [
+        ("viewer", json!({"role": "Admin"})),
+        ("viewer", json!({"enabled": false})),
+        ("admin", json!({"enabled": false})),
+        ("admin", json!({"password": "taken-over-admin"})),
+    ] {
+        let resp = test::call_service(&app, update(username, body.clone())).await;
+        assert_eq!(resp.status(), 403, "{} {}", username, body);
+    }
+    let resp = test::call_service(
+        &app,
+        update("viewer", json!({"password": "new-viewer-password"})),
+    )
+    .await;
+    assert_eq!(resp.status(), 403);
+    let resp = test::call_service(
+        &app,
+        update(
+            "viewer",
+            json!({"password": "new-viewer-password", "current_password": "viewer-password"}),
+        ),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+
+    let users = users.lock().unwrap();
+    assert_eq!(users.get_user("viewer").unwrap().role, Role::Viewer);
+    assert!(users
+        .get_user("viewer")
+        .unwrap()
+        .verify_password("new-viewer-password"));
+    assert!(users
+        .get_user("admin")
+        .unwrap()
+        .verify_password("admin-password"));
+}
+
+#[actix_web::test]
+async fn test_user_update_audits_a_redacted_diff() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    let app = test::init_service(create_test_app().app_data(web::Data::new(config))).await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(json!({"username": "dev", "role": "Viewer", "password": "hunter22"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+
+    let req = test::TestRequest::put()
+        .uri("/api/v1/users/dev")
+        .set_json(json!({"role": "Operator", "password": "correct horse battery"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/audit/logs?resource_type=user&parse_details=true")
+        .to_request();
+    let body = test::read_body(test::call_service(&app, req).await).await;
+    let text = String::from_utf8_lossy(&body);
+    assert!(!text.contains("correct horse battery"), "{}", text);
+    assert!(!text.contains("hunter22"), "{}", text);
+    assert!(!text.contains("$argon2"), "{}", text);
+
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let entry = body["logs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|log| log["action"] == "UserUpdated" && log["resource_id"] == "dev")
+        .unwrap();
+    let changes = entry["details"]["changes"].as_array().unwrap();
+    let change = |field: &str| changes.iter().find(|c| c["field"] == field).unwrap();
+    assert_eq!(change("role")["before"], "Viewer");
+    assert_eq!(change("role")["after"], "Operator");
+    assert_eq!(change("password")["after"], "***REDACTED***");
+
+    // Stored as a JSON string unless asked otherwise
+    let req = test::TestRequest::get()
+        .uri("/api/v1/audit/logs?resource_type=user")
+        .to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert!(body["logs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|log| log["details"].is_string() || log["details"].is_null()));
+}
+
+#[actix_web::test]
+async fn test_login_lockout_and_unlock() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    config.security.jwt_secret = Some("test-secret-at-least-32-characters-long".to_string());
+    config.security.login_lockout.max_failed_attempts = 2;
+    config.security.login_lockout.lockout_secs = 0;
+
+    let app = test::init_service(create_test_app().app_data(web::Data::new(config))).await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(json!({"username": "ops", "role": "Operator", "password": "hunter22"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+
+    let login = |password: &str| {
+        test::TestRequest::post()
+            .uri("/api/v1/auth/login")
+            .set_json(json!({"username": "ops", "password": password}))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, login("wrong")).await.status(), 401);
+    assert_eq!(test::call_service(&app, login("wrong")).await.status(), 401);
+    // Locked now, even with the right password
+    assert_eq!(
+        test::call_service(&app, login("hunter22")).await.status(),
+        423
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/users/ops/unlock")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let resp = test::call_service(&app, login("hunter22")).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert!(body["token"].is_string());
+
+    let req = test::TestRequest::get().uri("/api/v1/users").to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let ops = body["users"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|u| u["username"] == "ops")
+        .unwrap();
+    assert_eq!(ops["failed_login_attempts"], 0);
+    assert!(ops["last_login_at"].is_string());
+    assert!(ops.get("password_hash").is_none());
+}
+
+#[actix_web::test]
+async fn test_login_client_ip_through_trusted_proxy() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    config.security.jwt_secret = Some("test-secret-at-least-32-characters-long".to_string());
+    let proxies = api_server::client_ip::TrustedProxies::parse(&["10.0.0.0/8".to_string()]);
+    let limiter = api_server::rate_limit::RateLimiter::new(api_server::config::RateLimitConfig {
+        requests_per_minute: 1,
+        burst_size: 1,
+    });
+
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(Arc::new(proxies.unwrap())))
+            .app_data(web::Data::new(Arc::new(limiter))),
+    )
+    .await;
+    let login = |peer: &str, forwarded_for: &str| {
+        test::TestRequest::post()
+            .uri("/api/v1/auth/login")
+            .peer_addr(peer.parse().unwrap())
+            .insert_header(("X-Forwarded-For", forwarded_for))
+            .set_json(json!({"username": "admin", "password": "wrong"}))
+            .to_request()
+    };
+
+    // Through the proxy the forwarded client is recorded and limited
+    let via_proxy = login("10.0.0.5:40000", "203.0.113.7");
+    assert_eq!(test::call_service(&app, via_proxy).await.status(), 401);
+    let via_proxy = login("10.0.0.5:40000", "203.0.113.7");
+    assert_eq!(test::call_service(&app, via_proxy).await.status(), 429);
+    // Another client behind the same proxy has its own allowance
+    let other = login("10.0.0.5:40000", "203.0.113.8");
+    assert_eq!(test::call_service(&app, other).await.status(), 401);
+    // A direct client cannot pose as someone else
+    let spoofed = login("198.51.100.1:40000", "203.0.113.9");
+    assert_eq!(test::call_service(&app, spoofed).await.status(), 401);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/audit/logs?resource_type=user")
+        .to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let mut addresses: Vec<&str> = body["logs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|log| log["action"] == "UserLogin")
+        .map(|log| log["ip_address"].as_str().unwrap())
+        .collect();
+    addresses.sort();
+    assert_eq!(
+        addresses,
+        ["198.51.100.1", "203.0.113.7", "203.0.113.7", "203.0.113.8"]
     );
 }
+
+#[actix_web::test]
+async fn test_service_account_tokens() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    config.security.jwt_secret = Some("test-secret-at-least-32-characters-long".to_string());
+    let tokens = Arc::new(api_server::service_tokens::ServiceTokenStore::default());
+
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(tokens.clone())),
+    )
+    .await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(json!({"username": "ci", "role": "Operator", "kind": "Service"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+
+    // Tokens are only for service accounts
+    let req = test::TestRequest::post()
+        .uri("/api/v1/users/admin/tokens")
+        .set_json(json!({"name": "deploy"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/users/ci/tokens")
+        .set_json(json!({"name": "deploy", "ttl_days": 30}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert!(body["token"].is_string());
+    let token_id = body["service_token"]["id"].as_str().unwrap().to_string();
+
+    // Service accounts cannot use the password login
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/login")
+        .set_json(json!({"username": "ci", "password": ""}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/users/ci/tokens/{}", token_id))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/users/ci/tokens")
+        .to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["tokens"][0]["id"], token_id);
+    assert!(body["tokens"][0]["revoked_at"].is_string());
+
+    // Both token operations are audited with the token id
+    let req = test::TestRequest::get()
+        .uri("/api/v1/audit/logs?resource_type=service_token")
+        .to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["logs"].as_array().unwrap().len(), 2);
+    assert_eq!(body["logs"][0]["resource_id"], token_id);
+}
+
+#[actix_web::test]
+async fn test_admin_setup_flow() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = true;
+    config.security.jwt_secret = Some("test-secret-at-least-32-characters-long".to_string());
+    let users = Arc::new(std::sync::Mutex::new(api_server::rbac::UserStore::new()));
+    let setup = Arc::new(api_server::setup::AdminSetup::new(
+        &users.lock().unwrap(),
+        Some("one-time-token".to_string()),
+    ));
+
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(users))
+            .app_data(web::Data::new(setup)),
+    )
+    .await;
+    let setup = |token: &str| {
+        test::TestRequest::post()
+            .uri("/api/v1/auth/setup")
+            .set_json(json!({"token": token, "password": "correct-horse-battery"}))
+            .to_request()
+    };
+    let login = || {
+        test::TestRequest::post()
+            .uri("/api/v1/auth/login")
+            .set_json(json!({"username": "admin", "password": "correct-horse-battery"}))
+            .to_request()
+    };
+
+    // Nothing but setup works before the admin exists
+    assert_eq!(test::call_service(&app, login()).await.status(), 503);
+    let req = test::TestRequest::get()
+        .uri("/api/v1/auth/api-keys")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 503);
+
+    assert_eq!(
+        test::call_service(&app, setup("wrong-token"))
+            .await
+            .status(),
+        401
+    );
+    assert_eq!(
+        test::call_service(&app, setup("one-time-token"))
+            .await
+            .status(),
+        201
+    );
+    assert_eq!(
+        test::call_service(&app, setup("one-time-token"))
+            .await
+            .status(),
+        409
+    );
+
+    let body: serde_json::Value =
+        test::read_body_json(test::call_service(&app, login()).await).await;
+    let req = test::TestRequest::get()
+        .uri("/api/v1/auth/api-keys")
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", body["token"].as_str().unwrap()),
+        ))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+}
+
+#[actix_web::test]
+async fn test_totp_enrollment_and_login() {
+    use api_server::totp;
+
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = true;
+    config.security.jwt_secret = Some("test-secret-at-least-32-characters-long".to_string());
+    let mut users = api_server::rbac::UserStore::new();
+    users.create_admin("admin", "admin-pass").unwrap();
+    let secrets = Arc::new(api_server::secrets::SecretStore::from_key_material(
+        b"master-key",
+        std::env::temp_dir().join(format!("totp_{}", uuid::Uuid::new_v4())),
+    ));
+
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(Arc::new(std::sync::Mutex::new(users))))
+            .app_data(web::Data::new(secrets)),
+    )
+    .await;
+    let login = |code: Option<&str>| {
+        test::TestRequest::post()
+            .uri("/api/v1/auth/login")
+            .set_json(json!({"username": "admin", "password": "admin-pass", "totp_code": code}))
+            .to_request()
+    };
+    let body: serde_json::Value =
+        test::read_body_json(test::call_service(&app, login(None)).await).await;
+    let bearer = format!("Bearer {}", body["token"].as_str().unwrap());
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/totp/enroll")
+        .insert_header(("Authorization", bearer.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let enrollment: serde_json::Value = test::read_body_json(resp).await;
+    let secret = data_encoding::BASE32_NOPAD
+        .decode(enrollment["secret"].as_str().unwrap().as_bytes())
+        .unwrap();
+    assert!(enrollment["otpauth_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/"));
+    let recovery_code = enrollment["recovery_codes"][0]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let now = chrono::Utc::now().timestamp();
+    let previous_code = totp::code_at(&secret, totp::step_at(now) - 1);
+    let req = test::TestRequest::post()
+        .uri("/api/v1/auth/totp/verify")
+        .insert_header(("Authorization", bearer.clone()))
+        .set_json(json!({"code": previous_code}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    // The second factor is now required
+    let resp = test::call_service(&app, login(None)).await;
+    assert_eq!(resp.status(), 401);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["totp_required"], true);
+    // The code used to confirm enrollment cannot be replayed
+    assert_eq!(
+        test::call_service(&app, login(Some(&previous_code)))
+            .await
+            .status(),
+        401
+    );
+    let code = totp::code_at(&secret, totp::step_at(now) + 1);
+    assert_eq!(
+        test::call_service(&app, login(Some(&code))).await.status(),
+        200
+    );
+
+    // Recovery codes work once
+    let resp = test::call_service(&app, login(Some(&recovery_code))).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        test::call_service(&app, login(Some(&recovery_code)))
+            .await
+            .status(),
+        401
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/users/admin/totp/reset")
+        .insert_header(("Authorization", bearer))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    assert_eq!(test::call_service(&app, login(None)).await.status(), 200);
+}
+
+#[actix_web::test]
+async fn test_get_audit_logs() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    let app = test::init_service(create_test_app().app_data(web::Data::new(config))).await;
+    let req = test::TestRequest::get()
+        .uri("/api/v1/audit/logs")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let status = resp.status();
+    // With proper dependencies, audit logs should return 200
+    assert!(
+        status.is_success(),
+        "Audit logs endpoint should return 2xx with dependencies, got: {}",
+        status
+    );
+}
+
+#[actix_web::test]
+async fn test_audit_logs_are_scoped_to_own_trail() {
+    use api_server::audit::{AuditAction, AuditLogger, AuditResult};
+    use api_server::rbac::{Role, UserStore};
+
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = true;
+    config.security.jwt_secret = Some("test-secret-at-least-32-characters-long".to_string());
+    let mut users = UserStore::new();
+    let admin = users.create_admin("admin", "admin-password").unwrap();
+    for name in ["ops", "dev"] {
+        let mut operator = admin.clone();
+        operator.id = uuid::Uuid::new_v4();
+        operator.username = name.to_string();
+        operator.role = Role::Operator;
+        operator.set_password(&format!("{}-password", name));
+        users.add_user(operator).unwrap();
+    }
+    let users = Arc::new(std::sync::Mutex::new(users));
+    let audit_logger = Arc::new(AuditLogger::new(100));
+    for (user, resource_type, resource_id) in [
+        ("dev", "container", "web"),
+        ("ops", "container", "db"),
+        ("admin", "user", "ops"),
+    ] {
+        audit_logger.log_entry(
+            AuditLogger::builder()
+                .user(user.to_string())
+                .action(AuditAction::ContainerStarted)
+                .resource_type(resource_type.to_string())
+                .resource_id(resource_id.to_string())
+                .result(AuditResult::Success)
+                .build()
+                .unwrap(),
+        );
+    }
+
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(users.clone()))
+            .app_data(web::Data::new(audit_logger.clone())),
+    )
+    .await;
+    let mut bearer = std::collections::HashMap::new();
+    for name in ["ops", "admin"] {
+        let req = test::TestRequest::post()
+            .uri("/api/v1/auth/login")
+            .set_json(json!({"username": name, "password": format!("{}-password", name)}))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        bearer.insert(name, format!("Bearer {}", body["token"].as_str().unwrap()));
+    }
+    let get = |uri: &str, as_user: &str| {
+        test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("Authorization", bearer[as_user].clone()))
+            .to_request()
+    };
+
+    // An operator sees their actions and those on their account
+    let body: serde_json::Value =
+        test::call_and_read_body_json(&app, get("/api/v1/audit/logs", "ops")).await;
+    let logs = body["logs"].as_array().unwrap();
+    assert_eq!(body["total"], logs.len());
+    assert!(logs.iter().all(|log| log["user"] == "ops"
+        || (log["resource_type"] == "user" && log["resource_id"] == "ops")));
+    assert!(logs.iter().any(|log| log["resource_id"] == "db"));
+    assert!(logs.iter().any(|log| log["user"] == "admin"));
+    assert!(!logs.iter().any(|log| log["user"] == "dev"));
+    let resp = test::call_service(&app, get("/api/v1/audit/logs?user=ops", "ops")).await;
+    assert_eq!(resp.status(), 200);
+
+    // ...but not another user's
+    let resp = test::call_service(&app, get("/api/v1/audit/logs?user=dev", "ops")).await;
+    assert_eq!(resp.status(), 403);
+    let body: serde_json::Value =
+        test::call_and_read_body_json(&app, get("/api/v1/audit/logs?user=dev", "admin")).await;
+    assert_eq!(body["logs"].as_array().unwrap().len(), 1);
+
+    config.audit.self_service = false;
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(users.clone()))
+            .app_data(web::Data::new(audit_logger.clone())),
+    )
+    .await;
+    let resp = test::call_service(&app, get("/api/v1/audit/logs", "ops")).await;
+    assert_eq!(resp.status(), 403);
+    let resp = test::call_service(&app, get("/api/v1/audit/logs", "admin")).await;
+    assert_eq!(resp.status(), 200);
+}
+
+#[actix_web::test]
+async fn test_system_orchestration_requires_auth() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = true;
+    config.security.jwt_secret = Some("test-secret-at-least-32-characters-long".to_string());
+
+    let app = test::init_service(create_test_app().app_data(web::Data::new(config))).await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/system/shutdown")
+        .set_json(json!({"stop_containers": true}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/system/stop-all-containers")
+        .set_json(json!({"timeout_seconds": 30}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+}
+
+#[actix_web::test]
+async fn test_scoped_api_keys() {
+    use api_server::api_keys::{hash_key, ApiKeyStore};
+
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = true;
+    config.security.api_keys = vec![api_server::config::ApiKeyConfig {
+        name: "provisioning".to_string(),
+        key_hash: hash_key("admin-key"),
+        permissions: vec![api_server::rbac::Permission::SystemAdmin],
+        enabled: true,
+        expires_at: None,
+    }];
+    let store = Arc::new(ApiKeyStore::default());
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(store.clone())),
+    )
+    .await;
+    let create = |body: serde_json::Value| {
+        test::TestRequest::post()
+            .uri("/api/v1/auth/api-keys")
+            .insert_header(("X-API-Key", "admin-key"))
+            .set_json(body)
+            .to_request()
+    };
+
+    let body: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        create(json!({"name": "monitoring", "permissions": ["SystemRead"]})),
+    )
+    .await;
+    let key = body["key"].as_str().unwrap().to_string();
+    let id = body["api_key"]["id"].as_str().unwrap().to_string();
+    assert!(body["api_key"].get("key_hash").is_none());
+
+    // A read-only key may read but not act
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/tasks")
+        .insert_header(("X-API-Key", key.as_str()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let req = test::TestRequest::post()
+        .uri("/api/v1/admin/tasks/pool-usage/run")
+        .insert_header(("X-API-Key", key.as_str()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+    let req = test::TestRequest::get()
+        .uri("/api/v1/auth/api-keys")
+        .insert_header(("X-API-Key", key.as_str()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    // Expired keys are rejected
+    let (expired, _) = store
+        .create(
+            "expired",
+            vec![api_server::rbac::Permission::SystemRead],
+            Some(chrono::Utc::now() - chrono::Duration::seconds(1)),
+            &[],
+        )
+        .unwrap();
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/tasks")
+        .insert_header(("X-API-Key", expired.as_str()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+    let resp = test::call_service(
+        &app,
+        create(json!({
+            "name": "past",
+            "permissions": ["SystemRead"],
+            "expires_at": "2000-01-01T00:00:00Z"
+        })),
+    )
+    .await;
+    assert_eq!(resp.status(), 400);
+
+    // Revoked keys stop working at once
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/auth/api-keys/{}", id))
+        .insert_header(("X-API-Key", "admin-key"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/tasks")
+        .insert_header(("X-API-Key", key.as_str()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/auth/api-keys")
+        .insert_header(("X-API-Key", "admin-key"))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let listed: Vec<_> = body["api_keys"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|key| {
+            (
+                key["name"].as_str().unwrap(),
+                key["enabled"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        listed,
+        [
+            ("provisioning", true),
+            ("monitoring", false),
+            ("expired", true)
+        ]
+    );
+}
+
+#[actix_web::test]
+async fn test_system_start_all_refused_while_shutdown_active() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    let jobs = Arc::new(api_server::jobs::JobManager::default());
+    let shutdown = jobs
+        .create_exclusive(
+            api_server::system::SHUTDOWN_JOB,
+            api_server::system::SYSTEM_JOBS,
+        )
+        .unwrap();
+
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(jobs.clone())),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/system/start-all")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["job_id"], shutdown.id.to_string());
+
+    // Once the shutdown has finished a start-all is accepted and recorded
+    jobs.succeed(shutdown.id, json!({}));
+    let req = test::TestRequest::post()
+        .uri("/api/v1/system/start-all")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 202);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let job_id = body["job_id"].as_str().unwrap().parse().unwrap();
+    assert_eq!(
+        jobs.get(job_id).unwrap().kind,
+        api_server::system::START_ALL_JOB
+    );
+}
+
+#[actix_web::test]
+async fn test_purge_audit_logs_is_audited() {
+    use api_server::audit::{AuditAction, AuditLogger, AuditResult};
+
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    let audit_logger = Arc::new(AuditLogger::new(100));
+    for _ in 0..3 {
+        let log = AuditLogger::builder()
+            .user("admin".to_string())
+            .action(AuditAction::ContainerCreated)
+            .resource_type("container".to_string())
+            .result(AuditResult::Success)
+            .build()
+            .unwrap();
+        audit_logger.log_entry(log);
+    }
+
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(audit_logger.clone())),
+    )
+    .await;
+
+    let cutoff = (chrono::Utc::now() + chrono::Duration::seconds(1))
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string();
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/v1/audit/logs?before={}", cutoff))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["removed"], 3);
+
+    // Only the record of the purge itself remains
+    let remaining = audit_logger.get_logs(None, None, None, None, None);
+    assert_eq!(remaining.len(), 1);
+    assert!(matches!(remaining[0].action, AuditAction::AuditLogsPurged));
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/audit/logs?before=yesterday")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+}
+
+#[actix_web::test]
+async fn test_image_cache_endpoints() {
+    let root = std::env::temp_dir().join(format!("images_{}", uuid::Uuid::new_v4()));
+    let cache = Arc::new(container_manager::ImageCache::new(root.clone(), u64::MAX));
+
+    // What the download template leaves behind after fetching an image
+    let image = models::ImageSpec {
+        distro: "alpine".to_string(),
+        release: "3.19".to_string(),
+        arch: "arm64".to_string(),
+    };
+    let dir = root.join("download/alpine/3.19/arm64/default");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("rootfs.tar.xz"), b"rootfs").unwrap();
+    cache.record_use(&image).unwrap();
+
+    // Changing the cache needs a caller with system write access
+    let mut config = api_server::config::AppConfig::default();
+    config.security.jwt_secret = Some("test-secret-at-least-32-characters-long".to_string());
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(cache.clone())),
+    )
+    .await;
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/images/alpine:3.19:arm64")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+    assert!(dir.exists());
+
+    config.security.auth_enabled = false;
+    let audit_logger = Arc::new(api_server::audit::AuditLogger::new(100));
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(cache))
+            .app_data(web::Data::new(audit_logger.clone())),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/api/v1/images").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["images"][0]["id"], "alpine:3.19:arm64");
+    assert_eq!(body["total_bytes"], 6);
+
+    // Filtered by the target's architecture, whatever it is called
+    for (query, count) in [("arch=aarch64", 1), ("arch=amd64", 0)] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/images?{}", query))
+            .to_request();
+        let body: serde_json::Value =
+            test::read_body_json(test::call_service(&app, req).await).await;
+        assert_eq!(body["images"].as_array().unwrap().len(), count, "{}", query);
+    }
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/images?node={}", uuid::Uuid::new_v4()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    // Pre-pulling a cached image needs no download
+    let req = test::TestRequest::post()
+        .uri("/api/v1/templates/cache")
+        .set_json(&image)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["cached"], true);
+
+    // Each component names a directory in the cache
+    let req = test::TestRequest::post()
+        .uri("/api/v1/templates/cache")
+        .set_json(json!({"distro": "..", "release": "3.19", "arch": "arm64"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/images/alpine:3.19:arm64")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    assert!(!dir.exists());
+    let logs = audit_logger.get_resource_logs("image", "alpine:3.19:arm64", 10);
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].user.as_deref(), Some("anonymous"));
+
+    let req = test::TestRequest::delete()
+        .uri("/api/v1/images/alpine:3.19:arm64")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[actix_web::test]
+async fn test_unsupported_method_on_known_path_is_405() {
+    let app = test::init_service(create_test_app()).await;
+
+    for (method, uri, allow) in [
+        (
+            actix_web::http::Method::PUT,
+            "/api/v1/containers",
+            "GET, POST",
+        ),
+        (
+            actix_web::http::Method::DELETE,
+            "/api/v1/cluster/status",
+            "GET",
+        ),
+        (
+            actix_web::http::Method::GET,
+            "/api/v1/containers/web/snapshots/clone",
+            "POST",
+        ),
+    ] {
+        let req = test::TestRequest::default()
+            .method(method)
+            .uri(uri)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 405, "{}", uri);
+        assert_eq!(resp.headers().get("allow").unwrap(), allow, "{}", uri);
+    }
+
+    // Unknown paths are still 404
+    let req = test::TestRequest::get()
+        .uri("/api/v1/no-such-thing")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_list_templates_advertises_options() {
+    let app = test::init_service(create_test_app()).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/templates")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let debian = body["templates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|template| template["name"] == "debian")
+        .unwrap();
+    assert!(debian["options"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("release")));
+}
+
+#[actix_web::test]
+async fn test_invalid_requests_report_fields() {
+    let app = test::init_service(App::new().configure(api_server::routes::configure_routes)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/network/bridges")
+        .set_json(json!({
+            "name": "Bridge_0",
+            "ip_address": "10.0.0.1/33",
+            "stp_enabled": false
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let fields: Vec<_> = body["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["name", "ip_address"]);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/storage")
+        .set_json(json!({
+            "name": "pool",
+            "storage_type": "local",
+            "path": "/proc/pool"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["fields"][0]["field"], "path");
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/storage")
+        .set_json(json!({
+            "name": "pool",
+            "storage_type": "nfs",
+            "path": "nas:exports/pool"
+        }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+}
+
+#[actix_web::test]
+async fn test_audit_logs_filter_by_correlation_id() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    let metrics = Arc::new(api_server::observability::MetricsCollector::new());
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .wrap(api_server::request_tracing::RequestTracing::new(metrics)),
+    )
+    .await;
+
+    let correlation_id = uuid::Uuid::new_v4();
+    let purge = |correlation_id: Option<uuid::Uuid>| {
+        let mut req =
+            test::TestRequest::delete().uri("/api/v1/audit/logs?before=2000-01-01T00:00:00Z");
+        if let Some(id) = correlation_id {
+            req = req.insert_header(("X-Correlation-ID", id.to_string()));
+        }
+        req.to_request()
+    };
+    for id in [Some(correlation_id), Some(correlation_id), None] {
+        assert_eq!(test::call_service(&app, purge(id)).await.status(), 200);
+    }
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/api/v1/audit/logs?correlation_id={}",
+            correlation_id
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let logs = body["logs"].as_array().unwrap();
+    assert_eq!(logs.len(), 2);
+    assert!(logs
+        .iter()
+        .all(|log| log["correlation_id"] == correlation_id.to_string()));
+}
+
+#[actix_web::test]
+async fn test_cluster_join_requires_token() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    let app = test::init_service(create_test_app().app_data(web::Data::new(config))).await;
+
+    let join = |token: Option<&str>| {
+        test::TestRequest::post()
+            .uri("/api/v1/cluster/join")
+            .set_json(json!({
+                "cluster_name": "default",
+                "node_address": "192.168.1.20",
+                "node_port": 7946,
+                "join_token": token
+            }))
+            .to_request()
+    };
+
+    // No token
+    assert_eq!(test::call_service(&app, join(None)).await.status(), 403);
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/cluster/join-tokens")
+        .set_json(json!({"ttl_seconds": 300}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["single_use"], true);
+    let token = body["token"].as_str().unwrap().to_string();
+
+    // Valid join, then the single-use token is spent
+    assert_eq!(
+        test::call_service(&app, join(Some(&token))).await.status(),
+        200
+    );
+    assert_eq!(
+        test::call_service(&app, join(Some(&token))).await.status(),
+        403
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/cluster/join-tokens")
+        .set_json(json!({"ttl_seconds": 999999}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+}
+
+#[actix_web::test]
+async fn test_cluster_token_rotation() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    let app = test::init_service(create_test_app().app_data(web::Data::new(config))).await;
+
+    let join = |token: &str| {
+        test::TestRequest::post()
+            .uri("/api/v1/cluster/join")
+            .set_json(json!({
+                "cluster_name": "default",
+                "node_address": "192.168.1.20",
+                "node_port": 7946,
+                "join_token": token
+            }))
+            .to_request()
+    };
+    let rotate = || {
+        test::TestRequest::post()
+            .uri("/api/v1/cluster/token/rotate")
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, rotate()).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let first = body["token"].as_str().unwrap().to_string();
+    // The cluster token may be presented by any number of nodes
+    for _ in 0..2 {
+        assert_eq!(test::call_service(&app, join(&first)).await.status(), 200);
+    }
+
+    let resp = test::call_service(&app, rotate()).await;
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let second = body["token"].as_str().unwrap().to_string();
+    assert_ne!(first, second);
+    assert_eq!(test::call_service(&app, join(&first)).await.status(), 403);
+    assert_eq!(test::call_service(&app, join(&second)).await.status(), 200);
+
+    // The audit trail records the rotation but not the token
+    let req = test::TestRequest::get()
+        .uri("/api/v1/audit/logs")
+        .to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let logs = body["logs"].to_string();
+    assert!(logs.contains("cluster_join_token"));
+    assert!(!logs.contains(&first) && !logs.contains(&second));
+}
+
+#[actix_web::test]
+async fn test_cluster_containers_marks_unreachable_node_stale() {
+    let local_id = uuid::Uuid::new_v4();
+    let remote_id = uuid::Uuid::new_v4();
+    let assigned_id = uuid::Uuid::new_v4();
+
+    let mut membership = cluster::MembershipManager::new(local_id);
+    membership.add_node(models::Node {
+        id: remote_id,
+        name: "node-b".to_string(),
+        // Nothing listens on the discard port, so the fetch fails fast
+        address: "127.0.0.1".to_string(),
+        port: 9,
+        status: models::NodeStatus::Online,
+        cluster_id: None,
+        resources: models::NodeResources {
+            cpu_cores: 4,
+            memory_total: 0,
+            memory_used: 0,
+            disk_total: 0,
+            disk_used: 0,
+            exclusive_cpus_allocated: 0,
+            arch: None,
+        },
+        joined_at: chrono::Utc::now(),
+        last_seen: chrono::Utc::now(),
+        labels: Default::default(),
+        cordoned: false,
+        latency: None,
+        storage_pools: vec![],
+    });
+    let mut state = cluster::ClusterState::new(uuid::Uuid::new_v4());
+    state.set_leader(local_id);
+    state.assign_container(remote_id, assigned_id);
+    let state = Arc::new(std::sync::RwLock::new(state));
+
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(Arc::new(std::sync::RwLock::new(membership))))
+            .app_data(web::Data::new(state.clone())),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/cluster/containers?node=node-b")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["nodes"].as_array().unwrap().len(), 1);
+    assert_eq!(body["nodes"][0]["stale"], true);
+    assert_eq!(body["nodes"][0]["source"], "heartbeat");
+    assert_eq!(body["containers"][0]["id"], assigned_id.to_string());
+    assert_eq!(body["containers"][0]["node_reachable"], false);
+    assert!(body["containers"][0]["status"].is_null());
+
+    // Refreshing asks the node itself, which does not answer
+    let req = test::TestRequest::get()
+        .uri("/api/v1/cluster/containers?node=node-b&refresh=true")
+        .to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["nodes"][0]["source"], "live");
+    assert_eq!(body["nodes"][0]["stale"], true);
+    assert!(body["nodes"][0]["error"].is_string());
+
+    // Containers reported on heartbeats are listed without asking the node
+    state.write().unwrap().apply_container_delta(
+        remote_id,
+        models::ContainerSummaryDelta {
+            version: 3,
+            base: None,
+            updated: vec![models::ContainerSummary {
+                name: "web".to_string(),
+                status: models::ContainerStatus::Running,
+                health: models::HealthStatus::Healthy,
+            }],
+            removed: vec![],
+        },
+        chrono::Utc::now(),
+    );
+    let req = test::TestRequest::get()
+        .uri("/api/v1/cluster/containers?node=node-b")
+        .to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["nodes"][0]["stale"], false);
+    assert_eq!(body["nodes"][0]["age_secs"], 0);
+    assert_eq!(body["containers"][0]["name"], "web");
+    assert_eq!(body["containers"][0]["status"], "running");
+    assert_eq!(body["containers"][0]["health"], "healthy");
+    assert_eq!(body["containers"][0]["node_reachable"], true);
+
+    // A delta on top of a version not held drops the report
+    let applied = state.write().unwrap().apply_container_delta(
+        remote_id,
+        models::ContainerSummaryDelta {
+            version: 9,
+            base: Some(8),
+            ..Default::default()
+        },
+        chrono::Utc::now(),
+    );
+    assert!(!applied);
+    assert_eq!(state.read().unwrap().containers_version(&remote_id), None);
+
+    // Unknown status values and nodes are rejected
+    let req = test::TestRequest::get()
+        .uri("/api/v1/cluster/containers?status=bogus")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+    let req = test::TestRequest::get()
+        .uri("/api/v1/cluster/containers?node=node-z")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    // Followers point callers at the leader
+    state.write().unwrap().set_leader(remote_id);
+    let req = test::TestRequest::get()
+        .uri("/api/v1/cluster/containers")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 421);
+}
+
+#[actix_web::test]
+async fn test_placement_requires_reported_storage_pools() {
+    let mounted_id = uuid::Uuid::new_v4();
+    let bare_id = uuid::Uuid::new_v4();
+    let mut membership = cluster::MembershipManager::new(mounted_id);
+    membership.add_node(cluster_node(mounted_id, "node-a"));
+    membership.add_node(cluster_node(bare_id, "node-b"));
+    let mut state = cluster::ClusterState::new(uuid::Uuid::new_v4());
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    state.set_node_pools(
+        mounted_id,
+        vec![models::NodeStoragePool {
+            name: "nfs-shared".to_string(),
+            storage_type: models::StorageType::Nfs,
+            health: models::PoolHealth::Healthy,
+            state: models::PoolState::Active,
+        }],
+    );
+
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(Arc::new(std::sync::RwLock::new(membership))))
+            .app_data(web::Data::new(Arc::new(std::sync::RwLock::new(state))))
+            .app_data(web::Data::new(config)),
+    )
+    .await;
+
+    // Operators see which node lacks the pool
+    let req = test::TestRequest::get()
+        .uri("/api/v1/cluster/nodes")
+        .to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    for node in body["nodes"].as_array().unwrap() {
+        let pools = node["storage_pools"].as_array().unwrap();
+        if node["name"] == "node-a" {
+            assert_eq!(pools[0]["name"], "nfs-shared");
+            assert_eq!(pools[0]["health"], "healthy");
+        } else {
+            assert!(pools.is_empty());
+        }
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/cluster/schedule?explain=true")
+        .set_json(serde_json::json!({ "storage_pools": ["nfs-shared"] }))
+        .to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["chosen_node"], mounted_id.to_string());
+    let bare = &body["nodes"][1];
+    assert_eq!(bare["node_name"], "node-b");
+    assert_eq!(bare["eligible"], false);
+    let pool_filter = bare["filters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|f| f["filter"] == "storage_pools")
+        .unwrap();
+    assert_eq!(pool_filter["reason"], "missing storage pools: nfs-shared");
+}
+
+fn cluster_node(id: uuid::Uuid, name: &str) -> models::Node {
+    models::Node {
+        id,
+        name: name.to_string(),
+        address: "10.0.0.2".to_string(),
+        port: 8080,
+        status: models::NodeStatus::Online,
+        cluster_id: None,
+        resources: models::NodeResources {
+            cpu_cores: 4,
+            memory_total: 0,
+            memory_used: 0,
+            disk_total: 0,
+            disk_used: 0,
+            exclusive_cpus_allocated: 0,
+            arch: None,
+        },
+        joined_at: chrono::Utc::now(),
+        last_seen: chrono::Utc::now(),
+        labels: Default::default(),
+        cordoned: false,
+        latency: None,
+        storage_pools: vec![],
+    }
+}
+
+#[actix_web::test]
+async fn test_cluster_state_export_and_import() {
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+
+    // The old leader with one container assigned to a follower
+    let leader_id = uuid::Uuid::new_v4();
+    let follower_id = uuid::Uuid::new_v4();
+    let assigned_id = uuid::Uuid::new_v4();
+    let mut membership = cluster::MembershipManager::new(leader_id);
+    membership.add_node(cluster_node(leader_id, "node-a"));
+    membership.add_node(cluster_node(follower_id, "node-b"));
+    let mut state = cluster::ClusterState::new(uuid::Uuid::new_v4());
+    state.set_leader(leader_id);
+    state.assign_container(follower_id, assigned_id);
+    let cluster_id = state.cluster_id;
+    let state = Arc::new(std::sync::RwLock::new(state));
+
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(Arc::new(std::sync::RwLock::new(membership))))
+            .app_data(web::Data::new(state.clone())),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .uri("/api/v1/cluster/state/export")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let exported = test::read_body(resp).await;
+    let body: serde_json::Value = serde_json::from_slice(&exported).unwrap();
+    assert_eq!(body["version"], cluster::SNAPSHOT_VERSION);
+    assert_eq!(body["nodes"].as_array().unwrap().len(), 2);
+
+    // Followers point callers at the leader
+    state.write().unwrap().set_leader(follower_id);
+    let req = test::TestRequest::get()
+        .uri("/api/v1/cluster/state/export")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 421);
+
+    // A replacement cluster bootstrapping from the export
+    let replacement_id = uuid::Uuid::new_v4();
+    let mut membership = cluster::MembershipManager::new(replacement_id);
+    membership.add_node(cluster_node(replacement_id, "node-c"));
+    let membership = Arc::new(std::sync::RwLock::new(membership));
+    let state = Arc::new(std::sync::RwLock::new(cluster::ClusterState::new(
+        uuid::Uuid::new_v4(),
+    )));
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(membership.clone()))
+            .app_data(web::Data::new(state.clone())),
+    )
+    .await;
+    let import = |uri: &str, body: Vec<u8>| {
+        test::TestRequest::post()
+            .uri(uri)
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(body)
+            .to_request()
+    };
+
+    let req = import("/api/v1/cluster/state/import", exported.to_vec());
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["containers"], 1);
+    {
+        let state = state.read().unwrap();
+        assert_eq!(state.cluster_id, cluster_id);
+        assert_eq!(state.get_node_containers(&follower_id), [assigned_id]);
+        let membership = membership.read().unwrap();
+        assert_eq!(membership.node_count(), 3);
+        assert_eq!(
+            membership.get_node(&follower_id).unwrap().status,
+            models::NodeStatus::Offline
+        );
+    }
+
+    // Committed state is only replaced on request
+    let req = import("/api/v1/cluster/state/import", exported.to_vec());
+    assert_eq!(test::call_service(&app, req).await.status(), 409);
+    let req = import("/api/v1/cluster/state/import?force=true", exported.to_vec());
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    // Assignments to nodes missing from the document are refused
+    let mut dangling: serde_json::Value = serde_json::from_slice(&exported).unwrap();
+    dangling["nodes"] = json!([]);
+    let req = import(
+        "/api/v1/cluster/state/import?force=true",
+        serde_json::to_vec(&dangling).unwrap(),
+    );
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert!(body["error"].as_str().unwrap().contains("unknown node"));
+}
+
+#[actix_web::test]
+async fn test_system_info_reports_privilege_mode() {
+    let app = test::init_service(create_test_app()).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/system/info")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let mode = body["privileges"]["mode"].as_str().unwrap();
+    assert!(["root", "sudo", "none"].contains(&mode), "mode: {}", mode);
+    assert!(body["privileges"]["net_admin"].is_boolean());
+    // Remediation is only suggested when something is missing
+    assert_eq!(
+        body["privileges"]["remediation"]
+            .as_array()
+            .unwrap()
+            .is_empty(),
+        mode != "none" && body["privileges"]["net_admin"] == true
+    );
+    // Download settings are shown as in effect, defaults here
+    assert!(body["downloads"]["max_concurrent"].as_u64().unwrap() > 0);
+    assert!(body["downloads"]["http_proxy"].is_null());
+    let cgroup = body["cgroup_version"].as_str().unwrap();
+    assert!(["v1", "v2"].contains(&cgroup), "cgroup: {}", cgroup);
+}
+
+#[actix_web::test]
+async fn test_admin_tasks_list_and_trigger() {
+    use api_server::tasks::TaskManager;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::Duration;
+
+    let tasks = Arc::new(TaskManager::new());
+    let runs = Arc::new(AtomicU64::new(0));
+    tasks.register_interval("sweep", Duration::from_secs(3600), {
+        let runs = runs.clone();
+        move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+            async { Ok(()) }
+        }
+    });
+    tasks.register_continuous("watcher", "events", || async {
+        std::future::pending::<()>().await;
+        Ok(())
+    });
+    let mut config = api_server::config::AppConfig::default();
+    config.security.auth_enabled = false;
+    let app = test::init_service(
+        create_test_app()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(tasks.clone())),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/admin/tasks")
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let listed = body["tasks"].as_array().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0]["name"], "sweep");
+    assert_eq!(listed[0]["schedule"]["kind"], "interval");
+    assert_eq!(listed[0]["schedule"]["interval_secs"], 3600);
+    assert_eq!(listed[1]["schedule"]["trigger"], "events");
+
+    // The first tick is immediate; a trigger runs it again without waiting
+    for _ in 0..100 {
+        if tasks.get("sweep").unwrap().run_count == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let req = test::TestRequest::post()
+        .uri("/api/v1/admin/tasks/sweep/run")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 202);
+    for _ in 0..100 {
+        if runs.load(Ordering::SeqCst) == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+    for (uri, status) in [
+        ("/api/v1/admin/tasks/watcher/run", 409),
+        ("/api/v1/admin/tasks/missing/run", 404),
+    ] {
+        let req = test::TestRequest::post().uri(uri).to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            status,
+            "{}",
+            uri
+        );
+    }
+
+    tasks.shutdown(Duration::from_secs(1)).await;
+    let req = test::TestRequest::post()
+        .uri("/api/v1/admin/tasks/sweep/run")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 503);
+}
@@ -0,0 +1,278 @@
+//! Readiness gates (cluster leader, database, storage) and joining a cluster,
+//! with the system checks skipped through SKIP_SYSTEM_CHECKS.
+
+use actix_web::{test, web, App, HttpServer};
+use std::sync::{Arc, RwLock};
+
+use api_server::auto_join::{self, AutoJoin, JoinStatus, MembershipRecord};
+use api_server::config::AppConfig;
+use api_server::join_tokens::JoinTokenManager;
+use cluster::{ClusterState, MembershipManager, PeerHealth};
+
+#[actix_web::test]
+async fn test_readiness_waits_for_leader_in_clustered_mode() {
+    std::env::set_var("SKIP_SYSTEM_CHECKS", "1");
+
+    let state = Arc::new(RwLock::new(ClusterState::new(uuid::Uuid::new_v4())));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 503);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["reason"], "no_leader");
+
+    state.write().unwrap().set_leader(uuid::Uuid::new_v4());
+
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+}
+
+#[actix_web::test]
+async fn test_bootstrapped_cluster_is_ready() {
+    std::env::set_var("SKIP_SYSTEM_CHECKS", "1");
+
+    let data_dir = std::env::temp_dir().join(format!("ready_bootstrap_{}", uuid::Uuid::new_v4()));
+    let mut config = api_server::config::AppConfig::default();
+    config.paths.data_dir = data_dir.clone();
+    config.readiness.check_storage = false;
+    config.cluster.bootstrap = true;
+    let paths = config.paths();
+
+    // Started the way the server starts a clustered node
+    let node_id = auto_join::load_node_id(&paths.node_id).unwrap();
+    let state = auto_join::cluster_state(&config, node_id, None).unwrap();
+    let membership = Arc::new(RwLock::new(MembershipManager::new(node_id)));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(state.clone()))
+            .app_data(web::Data::new(membership))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["gates"][0]["name"], "cluster_leader");
+    assert_eq!(state.read().unwrap().leader_id, Some(node_id));
+
+    // A restart keeps the node's id, and with it the leadership
+    assert_eq!(auto_join::load_node_id(&paths.node_id).unwrap(), node_id);
+    std::fs::remove_dir_all(&data_dir).unwrap();
+}
+
+#[actix_web::test]
+async fn test_follower_readiness_tracks_the_recorded_leader() {
+    std::env::set_var("SKIP_SYSTEM_CHECKS", "1");
+
+    let mut config = api_server::config::AppConfig::default();
+    config.readiness.check_storage = false;
+    config.cluster.join_addresses = vec!["10.0.0.1:8080".to_string()];
+    let node_id = uuid::Uuid::new_v4();
+    let ready = |state: Arc<RwLock<ClusterState>>, health: Arc<RwLock<PeerHealth>>| {
+        let config = config.clone();
+        async move {
+            let app = test::init_service(
+                App::new()
+                    .app_data(web::Data::new(config))
+                    .app_data(web::Data::new(state))
+                    .app_data(web::Data::new(Arc::new(RwLock::new(
+                        MembershipManager::new(node_id),
+                    ))))
+                    .app_data(web::Data::new(health))
+                    .configure(api_server::routes::configure_routes),
+            )
+            .await;
+            let req = test::TestRequest::get().uri("/ready").to_request();
+            let resp = test::call_service(&app, req).await;
+            let status = resp.status().as_u16();
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            (status, body["reason"].clone())
+        }
+    };
+
+    // Still joining: no leader yet
+    let state = auto_join::cluster_state(&config, node_id, None).unwrap();
+    let health = Arc::new(RwLock::new(PeerHealth::new()));
+    assert_eq!(
+        ready(state, health.clone()).await,
+        (503, serde_json::json!("no_leader"))
+    );
+
+    // Restarted after joining: the recorded leader is followed
+    let leader = uuid::Uuid::new_v4();
+    let record = auto_join::MembershipRecord {
+        node_id,
+        seed: "10.0.0.1:8080".to_string(),
+        joined_at: chrono::Utc::now(),
+        cluster_id: Some(uuid::Uuid::new_v4()),
+        leader_id: Some(leader),
+    };
+    let state = auto_join::cluster_state(&config, node_id, Some(&record)).unwrap();
+    assert_eq!(
+        ready(state.clone(), health.clone()).await,
+        (200, serde_json::Value::Null)
+    );
+
+    health.write().unwrap().record(
+        leader,
+        "10.0.0.1:7946",
+        &Err(cluster::ClusterError::Unreachable("timed out".to_string())),
+    );
+    assert_eq!(
+        ready(state, health).await,
+        (503, serde_json::json!("leader_unreachable"))
+    );
+}
+
+#[actix_web::test]
+async fn test_readiness_skips_leader_check_when_standalone() {
+    std::env::set_var("SKIP_SYSTEM_CHECKS", "1");
+
+    let app = test::init_service(App::new().configure(api_server::routes::configure_routes)).await;
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+}
+
+#[actix_web::test]
+async fn test_readiness_lists_configured_gates() {
+    std::env::set_var("SKIP_SYSTEM_CHECKS", "1");
+
+    let pool_dir = std::env::temp_dir().join(format!("ready_pool_{}", uuid::Uuid::new_v4()));
+    let mut config = api_server::config::AppConfig::default();
+    config.storage.pool_configs[0].path = pool_dir.to_string_lossy().to_string();
+    config.readiness.check_database = true;
+    config.database.url = format!("sqlite://{}/hypervisor.db", pool_dir.display());
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    // Neither the pool nor the database directory exists yet
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 503);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["reason"], "database_unreachable");
+    let gates = body["gates"].as_array().unwrap();
+    assert_eq!(gates.len(), 2);
+    assert!(gates.iter().all(|gate| gate["ok"] == false));
+    assert!(gates.iter().all(|gate| gate["error"].is_string()));
+
+    std::fs::create_dir_all(&pool_dir).unwrap();
+
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let names: Vec<&str> = body["gates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|gate| gate["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["database", "storage"]);
+
+    std::fs::remove_dir_all(&pool_dir).unwrap();
+}
+
+const JOIN_TOKEN: &str = "cluster-join-token-for-tests";
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("cluster_join_{}_{}", name, uuid::Uuid::new_v4()))
+}
+
+// End-to-end join: a node started with `join_addresses` joins a seed started
+// with `cluster.bootstrap`, both served over HTTP on loopback, and follows
+// the leader the seed reports.
+#[actix_web::test]
+async fn test_node_joins_a_bootstrapped_seed_and_becomes_ready() {
+    std::env::set_var("SKIP_SYSTEM_CHECKS", "1");
+
+    // The seed founds the cluster and leads it
+    let seed_dir = temp_dir("seed");
+    let mut seed_config = AppConfig::default();
+    seed_config.paths.data_dir = seed_dir.clone();
+    seed_config.cluster.bootstrap = true;
+    let seed_id = auto_join::load_node_id(&seed_config.paths().node_id).unwrap();
+    let seed_state = auto_join::cluster_state(&seed_config, seed_id, None).unwrap();
+    let seed_membership = Arc::new(RwLock::new(MembershipManager::new(seed_id)));
+    let join_tokens = Arc::new(JoinTokenManager::new(b"seed-secret"));
+    join_tokens.set_cluster_token(JOIN_TOKEN);
+
+    let server = HttpServer::new({
+        let (state, membership) = (seed_state.clone(), seed_membership.clone());
+        move || {
+            App::new()
+                .app_data(web::Data::new(join_tokens.clone()))
+                .app_data(web::Data::new(state.clone()))
+                .app_data(web::Data::new(membership.clone()))
+                .configure(api_server::routes::configure_routes)
+        }
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let seed_address = server.addrs()[0].to_string();
+    let server = server.run();
+    let handle = server.handle();
+    actix_rt::spawn(server);
+
+    // The joining node lists the seed and knows no leader until admitted
+    let node_dir = temp_dir("node");
+    let mut node_config = AppConfig::default();
+    node_config.paths.data_dir = node_dir.clone();
+    node_config.readiness.check_storage = false;
+    node_config.cluster.join_addresses = vec![seed_address.clone()];
+    node_config.cluster.join_token = Some(JOIN_TOKEN.to_string());
+    let paths = node_config.paths();
+    let node_id = auto_join::load_node_id(&paths.node_id).unwrap();
+    let node_state = auto_join::cluster_state(&node_config, node_id, None).unwrap();
+    assert_eq!(node_state.read().unwrap().leader_id, None);
+
+    let join =
+        AutoJoin::new(&node_config, &paths, node_id, None).with_cluster_state(node_state.clone());
+    assert_eq!(join.join(Some(1)).await.unwrap(), seed_address);
+    assert!(matches!(join.status(), JoinStatus::Joined { .. }));
+
+    {
+        let state = node_state.read().unwrap();
+        assert_eq!(state.leader_id, Some(seed_id));
+        assert_eq!(state.cluster_id, seed_state.read().unwrap().cluster_id);
+    }
+    let record = MembershipRecord::load(&paths.cluster_membership)
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.node_id, node_id);
+    assert_eq!(record.leader_id, Some(seed_id));
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(node_config))
+            .app_data(web::Data::new(node_state))
+            .app_data(web::Data::new(Arc::new(RwLock::new(
+                MembershipManager::new(node_id),
+            ))))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    handle.stop(true).await;
+    let _ = std::fs::remove_dir_all(&seed_dir);
+    let _ = std::fs::remove_dir_all(&node_dir);
+}
@@ -0,0 +1,161 @@
+use actix_web::{test, web, App};
+use api_server::config::AppConfig;
+use api_server::observability::MetricsCollector;
+use api_server::pool_usage::PoolUsageMonitor;
+use std::sync::Arc;
+use storage::FilesystemUsage;
+
+#[actix_web::test]
+async fn test_full_pool_is_reported_as_degraded() {
+    std::env::set_var("SKIP_SYSTEM_CHECKS", "1");
+
+    let config = AppConfig::default();
+    let monitor = Arc::new(PoolUsageMonitor::new(&config.storage, &config.paths()));
+    monitor.record(
+        "default",
+        &FilesystemUsage {
+            total_bytes: 1000,
+            used_bytes: 950,
+            available_bytes: 50,
+        },
+    );
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(monitor.clone()))
+            .app_data(web::Data::new(Arc::new(MetricsCollector::new())))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/storage/default")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["pool"]["health"], "degraded");
+    assert_eq!(body["usage"]["level"], "critical");
+    assert_eq!(body["usage"]["used_percent"], 95.0);
+    assert_eq!(body["usage"]["thresholds"]["warning_percent"], 80.0);
+
+    let req = test::TestRequest::get().uri("/api/v1/storage").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["pools"][0]["name"], "default");
+    assert_eq!(body["pools"][0]["health"], "degraded");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/storage/missing")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+    let req = test::TestRequest::get().uri("/health").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["services"]["storage_pools"]["status"], "degraded");
+    assert_eq!(
+        body["services"]["storage_pools"]["alerts"][0]["pool"],
+        "default"
+    );
+
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    let metrics = String::from_utf8(body.to_vec()).unwrap();
+    assert!(metrics.contains("arm_hypervisor_storage_pool_alert_level{pool=\"default\"} 2"));
+    assert!(metrics.contains("arm_hypervisor_storage_pool_used_percent{pool=\"default\"} 95"));
+}
+
+#[actix_web::test]
+async fn test_pool_state_changes_are_audited_and_survive_restarts() {
+    use api_server::audit::{AuditAction, AuditLogger};
+
+    std::env::set_var("SKIP_SYSTEM_CHECKS", "1");
+    let mut config = AppConfig::default();
+    config.security.auth_enabled = false;
+    config.paths.data_dir =
+        std::env::temp_dir().join(format!("pool_state_{}", uuid::Uuid::new_v4()));
+    let monitor = Arc::new(PoolUsageMonitor::new(&config.storage, &config.paths()));
+    monitor.record(
+        "default",
+        &FilesystemUsage {
+            total_bytes: 1000,
+            used_bytes: 950,
+            available_bytes: 50,
+        },
+    );
+    let audit_logger = Arc::new(AuditLogger::new(100));
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(monitor.clone()))
+            .app_data(web::Data::new(audit_logger.clone()))
+            .app_data(web::Data::new(Arc::new(MetricsCollector::new())))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/api/v1/storage/default/state")
+        .set_json(serde_json::json!({ "state": "maintenance" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["state"], "maintenance");
+
+    let req = test::TestRequest::get().uri("/api/v1/storage").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["pools"][0]["state"], "maintenance");
+
+    // A full pool in maintenance no longer degrades the health check
+    let req = test::TestRequest::get().uri("/health").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["services"]["storage_pools"]["status"], "healthy");
+    assert_eq!(
+        body["services"]["storage_pools"]["maintenance"][0],
+        "default"
+    );
+
+    let logs = audit_logger.get_logs(
+        None,
+        Some(AuditAction::StoragePoolStateChanged),
+        None,
+        None,
+        None,
+    );
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].resource_id.as_deref(), Some("default"));
+
+    let req = test::TestRequest::put()
+        .uri("/api/v1/storage/missing/state")
+        .set_json(serde_json::json!({ "state": "read_only" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 404);
+    let req = test::TestRequest::put()
+        .uri("/api/v1/storage/default/state")
+        .set_json(serde_json::json!({ "state": "frozen" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    let restarted = PoolUsageMonitor::new(&config.storage, &config.paths());
+    assert_eq!(restarted.pools()[0].state, models::PoolState::Maintenance);
+    std::fs::remove_dir_all(&config.paths.data_dir).unwrap();
+}
+
+#[actix_web::test]
+async fn test_connection_probe_requires_authentication() {
+    let mut config = AppConfig::default();
+    config.security.jwt_secret = Some("test-secret-at-least-32-characters-long".to_string());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .configure(api_server::routes::configure_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/storage/test-connection")
+        .set_json(serde_json::json!({ "storage_type": "nfs", "path": "10.0.0.9:/export" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+}
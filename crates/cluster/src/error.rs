@@ -11,6 +11,15 @@ pub enum ClusterError {
     #[error("Network error: {0}")]
     Network(String),
 
+    #[error("Peer unreachable: {0}")]
+    Unreachable(String),
+
+    #[error("No eligible node for placement: {0}")]
+    NoEligibleNode(String),
+
+    #[error("Invalid cluster snapshot: {0}")]
+    InvalidSnapshot(String),
+
     #[error("Consensus error: {0}")]
     Consensus(String),
 
@@ -0,0 +1,96 @@
+use crate::error::ClusterError;
+use chrono::Utc;
+use models::PeerLatency;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Latest ping results for each cluster peer
+#[derive(Debug, Default)]
+pub struct PeerHealth {
+    peers: HashMap<Uuid, PeerLatency>,
+}
+
+impl PeerHealth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store the outcome of a ping; any error marks the peer unreachable
+    pub fn record(
+        &mut self,
+        node_id: Uuid,
+        address: impl Into<String>,
+        result: &Result<Duration, ClusterError>,
+    ) {
+        let address = address.into();
+        let latency = match result {
+            Ok(rtt) => PeerLatency {
+                address,
+                reachable: true,
+                rtt_seconds: Some(rtt.as_secs_f64()),
+                last_checked: Utc::now(),
+                error: None,
+            },
+            Err(e) => {
+                if self.peers.get(&node_id).is_none_or(|p| p.reachable) {
+                    warn!(
+                        "Cluster peer {} ({}) is unreachable: {}",
+                        node_id, address, e
+                    );
+                }
+                PeerLatency {
+                    address,
+                    reachable: false,
+                    rtt_seconds: None,
+                    last_checked: Utc::now(),
+                    error: Some(e.to_string()),
+                }
+            }
+        };
+        self.peers.insert(node_id, latency);
+    }
+
+    pub fn get(&self, node_id: &Uuid) -> Option<&PeerLatency> {
+        self.peers.get(node_id)
+    }
+
+    pub fn peers(&self) -> impl Iterator<Item = (&Uuid, &PeerLatency)> {
+        self.peers.iter()
+    }
+
+    /// Drop results for nodes that are no longer members
+    pub fn retain_members(&mut self, members: &[Uuid]) {
+        self.peers.retain(|id, _| members.contains(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_marks_failures_unreachable() {
+        let mut health = PeerHealth::new();
+        let node = Uuid::new_v4();
+
+        health.record(node, "10.0.0.2:7946", &Ok(Duration::from_millis(3)));
+        let latency = health.get(&node).unwrap();
+        assert!(latency.reachable);
+        assert_eq!(latency.rtt_seconds, Some(0.003));
+
+        health.record(
+            node,
+            "10.0.0.2:7946",
+            &Err(ClusterError::Unreachable("timed out".to_string())),
+        );
+        let latency = health.get(&node).unwrap();
+        assert!(!latency.reachable);
+        assert_eq!(latency.rtt_seconds, None);
+        assert!(latency.error.is_some());
+
+        health.retain_members(&[]);
+        assert!(health.get(&node).is_none());
+    }
+}
@@ -1,11 +1,19 @@
 pub mod consensus;
 pub mod error;
+pub mod health;
 pub mod membership;
 pub mod network;
+pub mod scheduler;
+pub mod snapshot;
 pub mod state;
+pub mod summaries;
 
 pub use consensus::*;
 pub use error::*;
+pub use health::*;
 pub use membership::*;
 pub use network::*;
+pub use scheduler::*;
+pub use snapshot::*;
 pub use state::*;
+pub use summaries::*;
@@ -1,12 +1,17 @@
 use chrono::Utc;
 use models::{Node, NodeResources, NodeStatus};
 use std::collections::HashMap;
+use tokio::sync::broadcast;
 use tracing::info;
 use uuid::Uuid;
 
+/// Changes buffered per subscriber before a slow one starts missing them
+const EVENT_CAPACITY: usize = 64;
+
 pub struct MembershipManager {
     nodes: HashMap<Uuid, Node>,
     local_node_id: Uuid,
+    changes: broadcast::Sender<Uuid>,
 }
 
 impl MembershipManager {
@@ -14,24 +19,43 @@ impl MembershipManager {
         Self {
             nodes: HashMap::new(),
             local_node_id,
+            changes: broadcast::channel(EVENT_CAPACITY).0,
         }
     }
 
+    /// Receive the id of every node that joins, leaves or changes status
+    /// from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Uuid> {
+        self.changes.subscribe()
+    }
+
+    fn publish(&self, node_id: Uuid) {
+        // Having no subscribers is not an error
+        let _ = self.changes.send(node_id);
+    }
+
     pub fn add_node(&mut self, node: Node) {
         info!("Adding node to cluster: {} ({})", node.name, node.id);
-        self.nodes.insert(node.id, node);
+        let id = node.id;
+        self.nodes.insert(id, node);
+        self.publish(id);
     }
 
     pub fn remove_node(&mut self, node_id: &Uuid) {
         if let Some(node) = self.nodes.remove(node_id) {
             info!("Removing node from cluster: {} ({})", node.name, node.id);
+            self.publish(node.id);
         }
     }
 
     pub fn update_node_status(&mut self, node_id: &Uuid, status: NodeStatus) {
         if let Some(node) = self.nodes.get_mut(node_id) {
+            let changed = node.status != status;
             node.status = status;
             node.last_seen = Utc::now();
+            if changed {
+                self.publish(*node_id);
+            }
         }
     }
 
@@ -49,6 +73,10 @@ impl MembershipManager {
         self.nodes.values().collect()
     }
 
+    pub fn local_node_id(&self) -> Uuid {
+        self.local_node_id
+    }
+
     pub fn get_local_node(&self) -> Option<&Node> {
         self.nodes.get(&self.local_node_id)
     }
@@ -60,4 +88,14 @@ impl MembershipManager {
     pub fn node_count(&self) -> usize {
         self.nodes.len()
     }
+
+    /// The online member to take over from `leaving`; the lowest id wins so
+    /// the choice does not depend on map order
+    pub fn successor(&self, leaving: &Uuid) -> Option<Uuid> {
+        self.nodes
+            .values()
+            .filter(|node| node.id != *leaving && node.status == NodeStatus::Online)
+            .map(|node| node.id)
+            .min()
+    }
 }
@@ -1,17 +1,168 @@
 use crate::error::ClusterError;
 use anyhow::Result;
+use models::NodeHeartbeat;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tracing::info;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{watch, Semaphore};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
 
+/// Handshake sent to a peer to measure round-trip time, optionally followed
+/// by a [`HeartbeatRequest`] as JSON
+pub const PING_MESSAGE: &[u8] = b"arm-ping";
+/// Reply expected for [`PING_MESSAGE`], followed by the responder's
+/// [`NodeHeartbeat`] as JSON when it has one to report
+pub const PONG_MESSAGE: &[u8] = b"arm-pong";
+/// Sent by a node leaving the cluster, followed by a [`LeaveNotice`] as JSON
+pub const LEAVE_MESSAGE: &[u8] = b"arm-leave";
+/// Reply to [`LEAVE_MESSAGE`] once the notice has been applied
+pub const ACK_MESSAGE: &[u8] = b"arm-ack";
+/// How long a peer may take to answer a ping before it counts as unreachable
+pub const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(2);
+/// Largest message a peer may send; longer length prefixes close the
+/// connection before anything is allocated
+pub const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// Produces the heartbeat sent back with every pong, for what the asking
+/// node already holds
+pub type HeartbeatSource = Arc<dyn Fn(&HeartbeatRequest) -> NodeHeartbeat + Send + Sync>;
+/// Applies the leave notices peers send
+pub type LeaveListener = Arc<dyn Fn(LeaveNotice) + Send + Sync>;
+
+/// What the node sending a ping already knows about the peer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeartbeatRequest {
+    /// Version of the peer's containers held; see [`crate::summaries`]
+    #[serde(default)]
+    pub containers_since: Option<u64>,
+}
+
+/// A node telling the others it has left the cluster
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeaveNotice {
+    pub node_id: Uuid,
+    /// Leader once the node is gone; set when the leaving node was the leader
+    pub leader_id: Option<Uuid>,
+}
+
+/// Bounds on the peer connections [`ClusterNetwork::serve`] accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenerLimits {
+    /// Connections handled at once; further ones are closed on accept
+    pub max_peers: usize,
+    /// How long a peer may go without sending a message before its
+    /// connection is closed
+    pub read_timeout: Duration,
+    /// How long open connections get to finish on shutdown before they are
+    /// dropped
+    pub drain_timeout: Duration,
+}
+
+impl Default for ListenerLimits {
+    fn default() -> Self {
+        Self {
+            max_peers: 64,
+            read_timeout: Duration::from_secs(30),
+            drain_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Connection counters of the cluster listener
+#[derive(Debug, Default)]
+pub struct ListenerStats {
+    accepted: AtomicU64,
+    rejected: AtomicU64,
+    timed_out: AtomicU64,
+    active: AtomicUsize,
+}
+
+/// Point-in-time copy of [`ListenerStats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ListenerSnapshot {
+    pub accepted: u64,
+    /// Closed on accept because `max_peers` were already connected
+    pub rejected: u64,
+    /// Closed after sending nothing for `read_timeout`
+    pub timed_out: u64,
+    pub active: usize,
+}
+
+impl ListenerStats {
+    pub fn snapshot(&self) -> ListenerSnapshot {
+        ListenerSnapshot {
+            accepted: self.accepted.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            timed_out: self.timed_out.load(Ordering::Relaxed),
+            active: self.active.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counts a connection as active for as long as it is held
+struct ActiveConnection(Arc<ListenerStats>);
+
+impl ActiveConnection {
+    fn new(stats: Arc<ListenerStats>) -> Self {
+        stats.active.fetch_add(1, Ordering::Relaxed);
+        Self(stats)
+    }
+}
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone)]
 pub struct ClusterNetwork {
     local_address: SocketAddr,
+    heartbeat: Option<HeartbeatSource>,
+    leave_listener: Option<LeaveListener>,
+    limits: ListenerLimits,
+    stats: Arc<ListenerStats>,
 }
 
 impl ClusterNetwork {
     pub fn new(local_address: SocketAddr) -> Self {
-        Self { local_address }
+        Self {
+            local_address,
+            heartbeat: None,
+            leave_listener: None,
+            limits: ListenerLimits::default(),
+            stats: Arc::new(ListenerStats::default()),
+        }
+    }
+
+    /// Bound the connections [`serve`](Self::serve) accepts
+    pub fn with_limits(mut self, limits: ListenerLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Counters of the connections this network has served
+    pub fn listener_stats(&self) -> Arc<ListenerStats> {
+        self.stats.clone()
+    }
+
+    /// Answer pings with the heartbeat `source` returns at that moment
+    pub fn with_heartbeat(mut self, source: HeartbeatSource) -> Self {
+        self.heartbeat = Some(source);
+        self
+    }
+
+    /// Hand the leave notices of peers to `listener`
+    pub fn with_leave_listener(mut self, listener: LeaveListener) -> Self {
+        self.leave_listener = Some(listener);
+        self
     }
 
     pub async fn connect_to_node(&self, address: SocketAddr) -> Result<TcpStream, ClusterError> {
@@ -45,6 +196,12 @@ impl ClusterNetwork {
             .await
             .map_err(|e| ClusterError::Network(format!("Failed to read length: {}", e)))?
             as usize;
+        if len > MAX_MESSAGE_LEN {
+            return Err(ClusterError::Network(format!(
+                "Message of {} bytes exceeds the {} byte limit",
+                len, MAX_MESSAGE_LEN
+            )));
+        }
 
         let mut buffer = vec![0u8; len];
         stream
@@ -58,4 +215,435 @@ impl ClusterNetwork {
     pub fn local_address(&self) -> SocketAddr {
         self.local_address
     }
+
+    /// Measure the round-trip time of a ping handshake with a peer
+    pub async fn ping(&self, peer: SocketAddr) -> Result<Duration, ClusterError> {
+        self.ping_with_timeout(peer, DEFAULT_PING_TIMEOUT).await
+    }
+
+    /// Ping a peer and return its heartbeat along with the round-trip time;
+    /// peers that send a bare pong have no heartbeat
+    pub async fn heartbeat(
+        &self,
+        peer: SocketAddr,
+        request: &HeartbeatRequest,
+    ) -> Result<(Duration, Option<NodeHeartbeat>), ClusterError> {
+        let mut message = PING_MESSAGE.to_vec();
+        serde_json::to_writer(&mut message, request).expect("heartbeat requests always serialize");
+        self.exchange(peer, &message, DEFAULT_PING_TIMEOUT).await
+    }
+
+    /// Like [`ping`](Self::ping), giving up once `timeout` has elapsed
+    ///
+    /// The returned duration covers only the message exchange, not the TCP
+    /// connect.
+    pub async fn ping_with_timeout(
+        &self,
+        peer: SocketAddr,
+        timeout: Duration,
+    ) -> Result<Duration, ClusterError> {
+        self.exchange(peer, PING_MESSAGE, timeout)
+            .await
+            .map(|(rtt, _)| rtt)
+    }
+
+    async fn exchange(
+        &self,
+        peer: SocketAddr,
+        ping: &[u8],
+        timeout: Duration,
+    ) -> Result<(Duration, Option<NodeHeartbeat>), ClusterError> {
+        let exchange = async {
+            let mut stream = self.connect_to_node(peer).await?;
+            let started = Instant::now();
+            self.send_message(&mut stream, ping).await?;
+            let reply = self.receive_message(&mut stream).await?;
+            let rtt = started.elapsed();
+            let heartbeat = match reply.strip_prefix(PONG_MESSAGE) {
+                Some([]) => None,
+                Some(body) => Some(serde_json::from_slice(body).map_err(|e| {
+                    ClusterError::Network(format!("Malformed heartbeat from {}: {}", peer, e))
+                })?),
+                None => {
+                    return Err(ClusterError::Network(format!(
+                        "Unexpected ping reply from {}",
+                        peer
+                    )))
+                }
+            };
+            Ok((rtt, heartbeat))
+        };
+
+        tokio::time::timeout(timeout, exchange).await.map_err(|_| {
+            ClusterError::Unreachable(format!(
+                "{} did not respond within {}ms",
+                peer,
+                timeout.as_millis()
+            ))
+        })?
+    }
+
+    /// Tell `peer` that a node has left, waiting for it to acknowledge
+    pub async fn announce_leave(
+        &self,
+        peer: SocketAddr,
+        notice: &LeaveNotice,
+    ) -> Result<(), ClusterError> {
+        let mut message = LEAVE_MESSAGE.to_vec();
+        serde_json::to_writer(&mut message, notice).expect("leave notices always serialize");
+        let exchange = async {
+            let mut stream = self.connect_to_node(peer).await?;
+            self.send_message(&mut stream, &message).await?;
+            match self.receive_message(&mut stream).await? {
+                reply if reply == ACK_MESSAGE => Ok(()),
+                _ => Err(ClusterError::Network(format!(
+                    "Unexpected leave reply from {}",
+                    peer
+                ))),
+            }
+        };
+
+        tokio::time::timeout(DEFAULT_PING_TIMEOUT, exchange)
+            .await
+            .map_err(|_| {
+                ClusterError::Unreachable(format!(
+                    "{} did not acknowledge the leave within {}ms",
+                    peer,
+                    DEFAULT_PING_TIMEOUT.as_millis()
+                ))
+            })?
+    }
+
+    /// Answer pings from other nodes on `listener` until it fails
+    pub async fn serve(&self, listener: TcpListener) -> Result<(), ClusterError> {
+        self.serve_until(listener, std::future::pending()).await
+    }
+
+    /// Answer pings from other nodes on `listener` until it fails or
+    /// `shutdown` completes
+    ///
+    /// On shutdown the listener is closed at once, so peers see this node as
+    /// unreachable and fail over, while open connections finish the message
+    /// they are handling and are closed; connections still busy after
+    /// `drain_timeout` are dropped.
+    pub async fn serve_until(
+        &self,
+        listener: TcpListener,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), ClusterError> {
+        info!("Cluster network listening on {}", listener.local_addr()?);
+
+        let permits = Arc::new(Semaphore::new(self.limits.max_peers));
+        let (draining, drain) = watch::channel(false);
+        let mut connections = JoinSet::new();
+        tokio::pin!(shutdown);
+
+        loop {
+            let (stream, peer) = tokio::select! {
+                _ = &mut shutdown => break,
+                // Reap finished connections so the set stays small
+                Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+                accepted = listener.accept() => accepted?,
+            };
+            let Ok(permit) = permits.clone().try_acquire_owned() else {
+                self.stats.rejected.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Rejecting cluster connection from {}: {} peers already connected",
+                    peer, self.limits.max_peers
+                );
+                continue;
+            };
+            self.stats.accepted.fetch_add(1, Ordering::Relaxed);
+
+            let network = self.clone();
+            let mut drain = drain.clone();
+            connections.spawn(async move {
+                let _permit = permit;
+                let _active = ActiveConnection::new(network.stats.clone());
+                if let Err(e) = network.handle_peer(stream, &mut drain).await {
+                    debug!("Connection from {} closed: {}", peer, e);
+                }
+            });
+        }
+
+        drop(listener);
+        info!(
+            "Cluster listener stopped; draining {} peer connections",
+            connections.len()
+        );
+        let _ = draining.send(true);
+        let drained = tokio::time::timeout(self.limits.drain_timeout, async {
+            while connections.join_next().await.is_some() {}
+        })
+        .await;
+        if drained.is_err() {
+            warn!(
+                "Dropping {} cluster connections that did not drain within {}s",
+                connections.len(),
+                self.limits.drain_timeout.as_secs()
+            );
+            connections.abort_all();
+        }
+        Ok(())
+    }
+
+    async fn handle_peer(
+        &self,
+        mut stream: TcpStream,
+        drain: &mut watch::Receiver<bool>,
+    ) -> Result<(), ClusterError> {
+        loop {
+            // Messages are only interrupted while waiting for them, so one
+            // being handled when the listener drains still gets its reply
+            let message = tokio::select! {
+                _ = drain.wait_for(|draining| *draining) => return Ok(()),
+                message = tokio::time::timeout(
+                    self.limits.read_timeout,
+                    self.receive_message(&mut stream),
+                ) => match message {
+                    Ok(message) => message?,
+                    Err(_) => {
+                        self.stats.timed_out.fetch_add(1, Ordering::Relaxed);
+                        return Err(ClusterError::Network(format!(
+                            "No message within {}s",
+                            self.limits.read_timeout.as_secs()
+                        )));
+                    }
+                },
+            };
+            if let Some(body) = message.strip_prefix(PING_MESSAGE) {
+                // A bare ping asks for everything
+                let request: HeartbeatRequest = match body {
+                    [] => HeartbeatRequest::default(),
+                    body => serde_json::from_slice(body).map_err(|e| {
+                        ClusterError::Network(format!("Malformed heartbeat request: {}", e))
+                    })?,
+                };
+                let mut reply = PONG_MESSAGE.to_vec();
+                if let Some(ref heartbeat) = self.heartbeat {
+                    serde_json::to_writer(&mut reply, &heartbeat(&request))
+                        .expect("heartbeats always serialize");
+                }
+                self.send_message(&mut stream, &reply).await?;
+            } else if let Some(body) = message.strip_prefix(LEAVE_MESSAGE) {
+                let notice: LeaveNotice = serde_json::from_slice(body)
+                    .map_err(|e| ClusterError::Network(format!("Malformed leave notice: {}", e)))?;
+                info!("Node {} announced it left the cluster", notice.node_id);
+                if let Some(ref listener) = self.leave_listener {
+                    listener(notice);
+                }
+                self.send_message(&mut stream, ACK_MESSAGE).await?;
+            } else {
+                warn!("Ignoring unknown cluster message ({} bytes)", message.len());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn spawn_node() -> ClusterNetwork {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let network = ClusterNetwork::new(listener.local_addr().unwrap());
+        let server = network.clone();
+        tokio::spawn(async move { server.serve(listener).await });
+        network
+    }
+
+    #[tokio::test]
+    async fn test_ping_between_nodes() {
+        let a = spawn_node().await;
+        let b = spawn_node().await;
+
+        let rtt = a.ping(b.local_address()).await.unwrap();
+        assert!(rtt > Duration::ZERO);
+        let rtt = b.ping(a.local_address()).await.unwrap();
+        assert!(rtt > Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_pong_carries_heartbeat() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let reporting = ClusterNetwork::new(listener.local_addr().unwrap()).with_heartbeat(
+            Arc::new(|request: &HeartbeatRequest| NodeHeartbeat {
+                resources: models::NodeResources {
+                    cpu_cores: 8,
+                    memory_total: 0,
+                    memory_used: 0,
+                    disk_total: 0,
+                    disk_used: 0,
+                    exclusive_cpus_allocated: 0,
+                    arch: None,
+                },
+                storage_pools: vec![models::NodeStoragePool {
+                    name: "nfs-shared".to_string(),
+                    storage_type: models::StorageType::Nfs,
+                    health: models::PoolHealth::Healthy,
+                    state: models::PoolState::Active,
+                }],
+                // Echo what the asker holds back
+                containers: Some(models::ContainerSummaryDelta {
+                    version: 7,
+                    base: request.containers_since,
+                    ..Default::default()
+                }),
+            }),
+        );
+        let server = reporting.clone();
+        tokio::spawn(async move { server.serve(listener).await });
+        let silent = spawn_node().await;
+
+        let request = HeartbeatRequest {
+            containers_since: Some(5),
+        };
+        let (_, heartbeat) = silent
+            .heartbeat(reporting.local_address(), &request)
+            .await
+            .unwrap();
+        let heartbeat = heartbeat.unwrap();
+        assert_eq!(heartbeat.resources.cpu_cores, 8);
+        assert_eq!(heartbeat.storage_pools[0].name, "nfs-shared");
+        assert_eq!(heartbeat.containers.unwrap().base, Some(5));
+
+        // Nodes without a heartbeat still answer, and plain pings ignore it
+        let (_, heartbeat) = reporting
+            .heartbeat(silent.local_address(), &HeartbeatRequest::default())
+            .await
+            .unwrap();
+        assert!(heartbeat.is_none());
+        assert!(silent.ping(reporting.local_address()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_leave_notice_reaches_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let peer = ClusterNetwork::new(listener.local_addr().unwrap())
+            .with_leave_listener(Arc::new(move |notice| tx.send(notice).unwrap()));
+        let server = peer.clone();
+        tokio::spawn(async move { server.serve(listener).await });
+        let leaving = spawn_node().await;
+
+        let notice = LeaveNotice {
+            node_id: Uuid::new_v4(),
+            leader_id: Some(Uuid::new_v4()),
+        };
+        leaving
+            .announce_leave(peer.local_address(), &notice)
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap(), notice);
+    }
+
+    #[tokio::test]
+    async fn test_ping_silent_peer_is_unreachable() {
+        // Accepts connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let silent = listener.local_addr().unwrap();
+        let network = spawn_node().await;
+
+        let result = network
+            .ping_with_timeout(silent, Duration::from_millis(100))
+            .await;
+        assert!(matches!(result, Err(ClusterError::Unreachable(_))));
+        drop(listener);
+    }
+
+    async fn spawn_limited(
+        limits: ListenerLimits,
+    ) -> (
+        ClusterNetwork,
+        tokio::sync::oneshot::Sender<()>,
+        tokio::task::JoinHandle<()>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let network = ClusterNetwork::new(listener.local_addr().unwrap()).with_limits(limits);
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = network.clone();
+        let handle = tokio::spawn(async move {
+            server
+                .serve_until(listener, async {
+                    let _ = stopped.await;
+                })
+                .await
+                .unwrap();
+        });
+        (network, stop, handle)
+    }
+
+    #[tokio::test]
+    async fn test_listener_rejects_peers_over_limit() {
+        let (node, _stop, _) = spawn_limited(ListenerLimits {
+            max_peers: 1,
+            ..Default::default()
+        })
+        .await;
+        let client = spawn_node().await;
+
+        // Hold the only slot open
+        let mut held = client.connect_to_node(node.local_address()).await.unwrap();
+        client.send_message(&mut held, PING_MESSAGE).await.unwrap();
+        client.receive_message(&mut held).await.unwrap();
+
+        let result = client
+            .ping_with_timeout(node.local_address(), Duration::from_millis(500))
+            .await;
+        assert!(result.is_err());
+        let stats = node.listener_stats().snapshot();
+        assert_eq!(stats.accepted, 1);
+        assert_eq!(stats.rejected, 1);
+        assert_eq!(stats.active, 1);
+
+        // The slot frees up once the held connection goes away
+        drop(held);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(client.ping(node.local_address()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_listener_closes_idle_peers() {
+        let (node, _stop, _) = spawn_limited(ListenerLimits {
+            read_timeout: Duration::from_millis(100),
+            ..Default::default()
+        })
+        .await;
+        let client = spawn_node().await;
+
+        let mut idle = client.connect_to_node(node.local_address()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(client.receive_message(&mut idle).await.is_err());
+        let stats = node.listener_stats().snapshot();
+        assert_eq!(stats.timed_out, 1);
+        assert_eq!(stats.active, 0);
+    }
+
+    #[tokio::test]
+    async fn test_listener_drains_on_shutdown() {
+        let (node, stop, handle) = spawn_limited(ListenerLimits::default()).await;
+        let client = spawn_node().await;
+
+        let mut open = client.connect_to_node(node.local_address()).await.unwrap();
+        client.send_message(&mut open, PING_MESSAGE).await.unwrap();
+        client.receive_message(&mut open).await.unwrap();
+
+        stop.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .unwrap()
+            .unwrap();
+        // The open connection was closed and new ones are refused
+        assert!(client.receive_message(&mut open).await.is_err());
+        assert!(client.ping(node.local_address()).await.is_err());
+        assert_eq!(node.listener_stats().snapshot().active, 0);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_message_is_refused() {
+        let node = spawn_node().await;
+        let mut stream = node.connect_to_node(node.local_address()).await.unwrap();
+        stream.write_u32(MAX_MESSAGE_LEN as u32 + 1).await.unwrap();
+        assert!(node.receive_message(&mut stream).await.is_err());
+    }
 }
@@ -0,0 +1,487 @@
+use models::{normalize_arch, Node, NodeStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::error::ClusterError;
+
+/// Resources and constraints a container needs from the node it lands on
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlacementRequest {
+    #[serde(default)]
+    pub cpu_cores: u32,
+    #[serde(default)]
+    pub memory_bytes: u64,
+    #[serde(default)]
+    pub disk_bytes: u64,
+    /// Labels the node must carry with exactly these values
+    #[serde(default)]
+    pub node_selector: HashMap<String, String>,
+    /// Cores to pin exclusively to the container
+    #[serde(default)]
+    pub exclusive_cpus: u32,
+    /// Pools holding the container's rootfs and volumes; the node must have
+    /// mounted every one
+    #[serde(default)]
+    pub storage_pools: Vec<String>,
+    /// Architecture of the container's image or template, e.g. `arm64`; the
+    /// node must run the same one
+    #[serde(default)]
+    pub arch: Option<String>,
+}
+
+/// Outcome of a single filter for a single node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterResult {
+    pub filter: String,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Every filter result and the score for one node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeEvaluation {
+    pub node_id: Uuid,
+    pub node_name: String,
+    pub filters: Vec<FilterResult>,
+    pub eligible: bool,
+    /// Only eligible nodes are scored
+    pub score: Option<f64>,
+}
+
+/// Full record of a scheduling decision
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlacementExplanation {
+    pub nodes: Vec<NodeEvaluation>,
+    pub chosen_node: Option<Uuid>,
+}
+
+pub struct Scheduler;
+
+impl Scheduler {
+    /// Evaluate every node against every filter without short-circuiting,
+    /// score the eligible ones, and pick the highest score
+    pub fn explain(nodes: &[&Node], request: &PlacementRequest) -> PlacementExplanation {
+        let mut evaluations: Vec<NodeEvaluation> = nodes
+            .iter()
+            .map(|node| {
+                let filters = vec![
+                    Self::filter_status(node),
+                    Self::filter_cordon(node),
+                    Self::filter_resources(node, request),
+                    Self::filter_labels(node, request),
+                    Self::filter_exclusive_cpu(node, request),
+                    Self::filter_storage_pools(node, request),
+                    Self::filter_arch(node, request),
+                ];
+                let eligible = filters.iter().all(|f| f.passed);
+                let score = eligible.then(|| Self::score(node, request));
+                debug!("Node {} eligible={} score={:?}", node.name, eligible, score);
+
+                NodeEvaluation {
+                    node_id: node.id,
+                    node_name: node.name.clone(),
+                    filters,
+                    eligible,
+                    score,
+                }
+            })
+            .collect();
+        // Stable output order regardless of membership map ordering
+        evaluations.sort_by(|a, b| a.node_name.cmp(&b.node_name));
+
+        // Highest score wins; ties go to the first node by name
+        let chosen_node = evaluations
+            .iter()
+            .filter_map(|e| e.score.map(|score| (e, score)))
+            .fold(
+                None::<(&NodeEvaluation, f64)>,
+                |best, (e, score)| match best {
+                    Some((_, best_score)) if best_score >= score => best,
+                    _ => Some((e, score)),
+                },
+            )
+            .map(|(e, _)| e.node_id);
+
+        PlacementExplanation {
+            nodes: evaluations,
+            chosen_node,
+        }
+    }
+
+    /// Choose a node for the request, returning the explanation either way
+    pub fn schedule(
+        nodes: &[&Node],
+        request: &PlacementRequest,
+    ) -> Result<(Uuid, PlacementExplanation), (ClusterError, PlacementExplanation)> {
+        let explanation = Self::explain(nodes, request);
+        match explanation.chosen_node {
+            Some(node_id) => {
+                info!("Scheduled placement on node {}", node_id);
+                Ok((node_id, explanation))
+            }
+            None => Err((
+                ClusterError::NoEligibleNode(format!(
+                    "none of {} node(s) passed all filters",
+                    explanation.nodes.len()
+                )),
+                explanation,
+            )),
+        }
+    }
+
+    fn filter_status(node: &Node) -> FilterResult {
+        let passed = node.status == NodeStatus::Online;
+        FilterResult {
+            filter: "status".to_string(),
+            passed,
+            reason: (!passed).then(|| format!("node is {:?}", node.status).to_lowercase()),
+        }
+    }
+
+    fn filter_cordon(node: &Node) -> FilterResult {
+        FilterResult {
+            filter: "cordon".to_string(),
+            passed: !node.cordoned,
+            reason: node.cordoned.then(|| "node is cordoned".to_string()),
+        }
+    }
+
+    fn filter_resources(node: &Node, request: &PlacementRequest) -> FilterResult {
+        let resources = &node.resources;
+        let memory_free = resources.memory_total.saturating_sub(resources.memory_used);
+        let disk_free = resources.disk_total.saturating_sub(resources.disk_used);
+
+        let mut shortfalls = Vec::new();
+        if request.cpu_cores > resources.cpu_cores {
+            shortfalls.push(format!(
+                "needs {} cpu cores, node has {}",
+                request.cpu_cores, resources.cpu_cores
+            ));
+        }
+        if request.memory_bytes > memory_free {
+            shortfalls.push(format!(
+                "needs {} bytes memory, {} free",
+                request.memory_bytes, memory_free
+            ));
+        }
+        if request.disk_bytes > disk_free {
+            shortfalls.push(format!(
+                "needs {} bytes disk, {} free",
+                request.disk_bytes, disk_free
+            ));
+        }
+
+        FilterResult {
+            filter: "resources".to_string(),
+            passed: shortfalls.is_empty(),
+            reason: (!shortfalls.is_empty()).then(|| shortfalls.join("; ")),
+        }
+    }
+
+    fn filter_labels(node: &Node, request: &PlacementRequest) -> FilterResult {
+        let mut mismatched: Vec<String> = request
+            .node_selector
+            .iter()
+            .filter(|(key, value)| node.labels.get(*key) != Some(*value))
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        mismatched.sort();
+
+        FilterResult {
+            filter: "labels".to_string(),
+            passed: mismatched.is_empty(),
+            reason: (!mismatched.is_empty())
+                .then(|| format!("missing labels: {}", mismatched.join(", "))),
+        }
+    }
+
+    fn filter_exclusive_cpu(node: &Node, request: &PlacementRequest) -> FilterResult {
+        let available = node
+            .resources
+            .cpu_cores
+            .saturating_sub(node.resources.exclusive_cpus_allocated);
+        let passed = request.exclusive_cpus <= available;
+
+        FilterResult {
+            filter: "exclusive_cpu".to_string(),
+            passed,
+            reason: (!passed).then(|| {
+                format!(
+                    "needs {} exclusive cpus, {} unpinned",
+                    request.exclusive_cpus, available
+                )
+            }),
+        }
+    }
+
+    fn filter_storage_pools(node: &Node, request: &PlacementRequest) -> FilterResult {
+        let mut missing = Vec::new();
+        let mut read_only = Vec::new();
+        for wanted in &request.storage_pools {
+            match node.storage_pools.iter().find(|p| &p.name == wanted) {
+                None => missing.push(wanted.as_str()),
+                // A new container's rootfs has to be created on the pool
+                Some(pool) if !pool.state.accepts_new_data() => read_only.push(wanted.as_str()),
+                Some(_) => {}
+            }
+        }
+        let mut reasons = Vec::new();
+        for (label, pools) in [("missing", &mut missing), ("read-only", &mut read_only)] {
+            pools.sort();
+            pools.dedup();
+            if !pools.is_empty() {
+                reasons.push(format!("{} storage pools: {}", label, pools.join(", ")));
+            }
+        }
+
+        FilterResult {
+            filter: "storage_pools".to_string(),
+            passed: reasons.is_empty(),
+            reason: (!reasons.is_empty()).then(|| reasons.join("; ")),
+        }
+    }
+
+    fn filter_arch(node: &Node, request: &PlacementRequest) -> FilterResult {
+        let wanted = request.arch.as_deref().map(normalize_arch);
+        let reason = match (wanted, node.resources.arch.as_deref()) {
+            (None, _) => None,
+            (Some(wanted), None) => Some(format!(
+                "needs {}, node has not reported its architecture",
+                wanted
+            )),
+            (Some(wanted), Some(arch)) if normalize_arch(arch) != wanted => {
+                Some(format!("needs {}, node is {}", wanted, arch))
+            }
+            (Some(_), Some(_)) => None,
+        };
+
+        FilterResult {
+            filter: "arch".to_string(),
+            passed: reason.is_none(),
+            reason,
+        }
+    }
+
+    /// Score 0-100: the mean fraction of memory and disk left free after placement
+    fn score(node: &Node, request: &PlacementRequest) -> f64 {
+        let free_after = |total: u64, used: u64, requested: u64| {
+            if total == 0 {
+                return 0.0;
+            }
+            total.saturating_sub(used).saturating_sub(requested) as f64 / total as f64
+        };
+
+        let resources = &node.resources;
+        let memory = free_after(
+            resources.memory_total,
+            resources.memory_used,
+            request.memory_bytes,
+        );
+        let disk = free_after(
+            resources.disk_total,
+            resources.disk_used,
+            request.disk_bytes,
+        );
+        ((memory + disk) / 2.0 * 100.0 * 100.0).round() / 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use models::{NodeResources, NodeStoragePool, PoolHealth, PoolState, StorageType};
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn node(name: &str, memory_used_gib: u64) -> Node {
+        Node {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            address: "10.0.0.1".to_string(),
+            port: 8080,
+            status: NodeStatus::Online,
+            cluster_id: None,
+            resources: NodeResources {
+                cpu_cores: 4,
+                memory_total: 8 * GIB,
+                memory_used: memory_used_gib * GIB,
+                disk_total: 100 * GIB,
+                disk_used: 10 * GIB,
+                exclusive_cpus_allocated: 0,
+                arch: None,
+            },
+            joined_at: Utc::now(),
+            last_seen: Utc::now(),
+            labels: HashMap::new(),
+            cordoned: false,
+            latency: None,
+            storage_pools: vec![],
+        }
+    }
+
+    fn filter<'a>(evaluation: &'a NodeEvaluation, name: &str) -> &'a FilterResult {
+        evaluation
+            .filters
+            .iter()
+            .find(|f| f.filter == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_least_loaded_node_is_chosen() {
+        let busy = node("busy", 6);
+        let idle = node("idle", 1);
+        let request = PlacementRequest {
+            memory_bytes: GIB,
+            ..Default::default()
+        };
+
+        let explanation = Scheduler::explain(&[&busy, &idle], &request);
+        assert_eq!(explanation.chosen_node, Some(idle.id));
+        assert!(explanation.nodes.iter().all(|e| e.eligible));
+        assert!(explanation.nodes[1].score > explanation.nodes[0].score);
+    }
+
+    #[test]
+    fn test_all_filters_are_reported_without_short_circuit() {
+        let mut node = node("edge", 7);
+        node.cordoned = true;
+        node.resources.exclusive_cpus_allocated = 4;
+        let request = PlacementRequest {
+            memory_bytes: 2 * GIB,
+            node_selector: HashMap::from([("arch".to_string(), "arm64".to_string())]),
+            exclusive_cpus: 1,
+            ..Default::default()
+        };
+
+        let explanation = Scheduler::explain(&[&node], &request);
+        let evaluation = &explanation.nodes[0];
+        assert!(!evaluation.eligible);
+        assert!(evaluation.score.is_none());
+        assert_eq!(evaluation.filters.len(), 7);
+        assert!(filter(evaluation, "status").passed);
+        assert!(!filter(evaluation, "cordon").passed);
+        assert!(!filter(evaluation, "resources").passed);
+        assert_eq!(
+            filter(evaluation, "labels").reason.as_deref(),
+            Some("missing labels: arch=arm64")
+        );
+        assert!(!filter(evaluation, "exclusive_cpu").passed);
+        assert!(filter(evaluation, "storage_pools").passed);
+        assert!(filter(evaluation, "arch").passed);
+        assert!(explanation.chosen_node.is_none());
+    }
+
+    #[test]
+    fn test_nodes_without_the_pools_are_filtered_out() {
+        let pool = |name: &str| NodeStoragePool {
+            name: name.to_string(),
+            storage_type: StorageType::Nfs,
+            health: PoolHealth::Healthy,
+            state: PoolState::Active,
+        };
+        let mut mounted = node("mounted", 6);
+        mounted.storage_pools = vec![pool("nfs-shared"), pool("backups")];
+        let mut partial = node("partial", 1);
+        partial.storage_pools = vec![pool("backups")];
+        let unreported = node("unreported", 0);
+        let request = PlacementRequest {
+            storage_pools: vec!["nfs-shared".to_string(), "backups".to_string()],
+            ..Default::default()
+        };
+
+        let explanation = Scheduler::explain(&[&mounted, &partial, &unreported], &request);
+        assert_eq!(explanation.chosen_node, Some(mounted.id));
+        assert_eq!(
+            filter(&explanation.nodes[1], "storage_pools")
+                .reason
+                .as_deref(),
+            Some("missing storage pools: nfs-shared")
+        );
+        assert_eq!(
+            filter(&explanation.nodes[2], "storage_pools")
+                .reason
+                .as_deref(),
+            Some("missing storage pools: backups, nfs-shared")
+        );
+        // Only the pool filter keeps the emptier nodes out
+        for evaluation in &explanation.nodes[1..] {
+            let failed: Vec<_> = evaluation.filters.iter().filter(|f| !f.passed).collect();
+            assert_eq!(failed.len(), 1);
+            assert_eq!(failed[0].filter, "storage_pools");
+        }
+    }
+
+    #[test]
+    fn test_read_only_pools_do_not_take_placements() {
+        let pool = |name: &str, state: PoolState| NodeStoragePool {
+            name: name.to_string(),
+            storage_type: StorageType::Local,
+            health: PoolHealth::Healthy,
+            state,
+        };
+        let mut active = node("active", 1);
+        active.storage_pools = vec![pool("data", PoolState::Active)];
+        let mut read_only = node("read-only", 6);
+        read_only.storage_pools = vec![pool("data", PoolState::ReadOnly)];
+        let request = PlacementRequest {
+            storage_pools: vec!["data".to_string()],
+            ..Default::default()
+        };
+
+        let explanation = Scheduler::explain(&[&read_only, &active], &request);
+        assert_eq!(explanation.chosen_node, Some(active.id));
+        assert_eq!(
+            filter(&explanation.nodes[1], "storage_pools")
+                .reason
+                .as_deref(),
+            Some("read-only storage pools: data")
+        );
+    }
+
+    #[test]
+    fn test_nodes_of_another_arch_are_filtered_out() {
+        let mut arm = node("arm", 6);
+        arm.resources.arch = Some("arm64".to_string());
+        let mut x86 = node("x86", 0);
+        x86.resources.arch = Some("amd64".to_string());
+        let unreported = node("unreported", 0);
+        let request = PlacementRequest {
+            arch: Some("aarch64".to_string()),
+            ..Default::default()
+        };
+
+        let explanation = Scheduler::explain(&[&arm, &x86, &unreported], &request);
+        assert_eq!(explanation.chosen_node, Some(arm.id));
+        assert_eq!(
+            filter(&explanation.nodes[2], "arch").reason.as_deref(),
+            Some("needs arm64, node is amd64")
+        );
+        assert_eq!(
+            filter(&explanation.nodes[1], "arch").reason.as_deref(),
+            Some("needs arm64, node has not reported its architecture")
+        );
+
+        // Requests without an arch place anywhere
+        let explanation = Scheduler::explain(&[&arm, &x86], &PlacementRequest::default());
+        assert_eq!(explanation.chosen_node, Some(x86.id));
+    }
+
+    #[test]
+    fn test_schedule_fails_with_explanation() {
+        let mut offline = node("offline", 0);
+        offline.status = NodeStatus::Offline;
+
+        let (err, explanation) =
+            Scheduler::schedule(&[&offline], &PlacementRequest::default()).unwrap_err();
+        assert!(matches!(err, ClusterError::NoEligibleNode(_)));
+        assert_eq!(
+            filter(&explanation.nodes[0], "status").reason.as_deref(),
+            Some("node is offline")
+        );
+    }
+}
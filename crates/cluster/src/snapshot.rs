@@ -0,0 +1,215 @@
+/// Versioned snapshot of the committed cluster state and its membership
+///
+/// The leader exports it for disaster recovery, and a replacement cluster
+/// imports it during bootstrap to get its assignments back. Raft log
+/// compaction is meant to write the same document once it exists, so there
+/// is only one format to keep readable.
+use chrono::{DateTime, Utc};
+use models::{Node, NodeStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::error::ClusterError;
+use crate::membership::MembershipManager;
+use crate::state::ClusterState;
+
+/// Format version written by this build; newer documents are refused
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterSnapshot {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub state: ClusterState,
+    pub nodes: Vec<Node>,
+}
+
+impl ClusterSnapshot {
+    /// Take a snapshot of `state` and the members in `membership`
+    pub fn capture(state: &ClusterState, membership: &MembershipManager) -> Self {
+        let mut nodes: Vec<Node> = membership.list_nodes().into_iter().cloned().collect();
+        nodes.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+
+        Self {
+            version: SNAPSHOT_VERSION,
+            created_at: Utc::now(),
+            state: state.clone(),
+            nodes,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).expect("cluster snapshots always serialize")
+    }
+
+    /// Parse and validate a snapshot written by [`ClusterSnapshot::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ClusterError> {
+        let snapshot: Self = serde_json::from_slice(bytes)
+            .map_err(|e| ClusterError::InvalidSnapshot(e.to_string()))?;
+        snapshot.validate()?;
+        Ok(snapshot)
+    }
+
+    /// Check that every assignment refers to a member and that no container
+    /// is assigned to more than one node
+    pub fn validate(&self) -> Result<(), ClusterError> {
+        if self.version == 0 || self.version > SNAPSHOT_VERSION {
+            return Err(ClusterError::InvalidSnapshot(format!(
+                "unsupported version {} (this build reads up to {})",
+                self.version, SNAPSHOT_VERSION
+            )));
+        }
+
+        let members: HashSet<Uuid> = self.nodes.iter().map(|node| node.id).collect();
+        let mut hosts: HashMap<Uuid, Uuid> = HashMap::new();
+        for (node_id, containers) in &self.state.node_assignments {
+            if !members.contains(node_id) {
+                return Err(ClusterError::InvalidSnapshot(format!(
+                    "containers are assigned to unknown node {}",
+                    node_id
+                )));
+            }
+            for container_id in containers {
+                if let Some(other) = hosts.insert(*container_id, *node_id) {
+                    if other != *node_id {
+                        return Err(ClusterError::InvalidSnapshot(format!(
+                            "container {} is assigned to both {} and {}",
+                            container_id, other, node_id
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the assignments and storage allocations in `state` and add
+    /// the recorded members to `membership`
+    ///
+    /// The local node and the current leader are kept. Other members are
+    /// added as offline until they report in again.
+    pub fn restore(self, state: &mut ClusterState, membership: &mut MembershipManager) {
+        info!(
+            "Restoring cluster {} from snapshot taken at {}",
+            self.state.cluster_id, self.created_at
+        );
+        state.cluster_id = self.state.cluster_id;
+        state.node_assignments = self.state.node_assignments;
+        state.storage_allocations = self.state.storage_allocations;
+
+        let local_id = membership.local_node_id();
+        for mut node in self.nodes {
+            if node.id == local_id {
+                continue;
+            }
+            node.status = NodeStatus::Offline;
+            membership.add_node(node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use models::NodeResources;
+
+    fn node(name: &str) -> Node {
+        Node {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            address: "10.0.0.2".to_string(),
+            port: 8080,
+            status: NodeStatus::Online,
+            cluster_id: None,
+            resources: NodeResources {
+                cpu_cores: 4,
+                memory_total: 0,
+                memory_used: 0,
+                disk_total: 0,
+                disk_used: 0,
+                exclusive_cpus_allocated: 0,
+                arch: None,
+            },
+            joined_at: Utc::now(),
+            last_seen: Utc::now(),
+            labels: Default::default(),
+            cordoned: false,
+            latency: None,
+            storage_pools: vec![],
+        }
+    }
+
+    #[test]
+    fn test_round_trip_and_restore() {
+        let local = node("node-a");
+        let remote = node("node-b");
+        let container = Uuid::new_v4();
+        let mut membership = MembershipManager::new(local.id);
+        membership.add_node(local.clone());
+        membership.add_node(remote.clone());
+        let mut state = ClusterState::new(Uuid::new_v4());
+        state.assign_container(remote.id, container);
+        state.allocate_storage(Uuid::new_v4(), Uuid::new_v4());
+
+        let snapshot =
+            ClusterSnapshot::from_bytes(&ClusterSnapshot::capture(&state, &membership).to_bytes())
+                .unwrap();
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+        assert_eq!(snapshot.nodes.len(), 2);
+
+        let replacement = node("node-c");
+        let mut new_membership = MembershipManager::new(replacement.id);
+        new_membership.add_node(replacement.clone());
+        let mut new_state = ClusterState::new(Uuid::new_v4());
+        new_state.set_leader(replacement.id);
+        assert!(new_state.is_empty());
+
+        snapshot.restore(&mut new_state, &mut new_membership);
+        assert_eq!(new_state.cluster_id, state.cluster_id);
+        assert_eq!(new_state.leader_id, Some(replacement.id));
+        assert_eq!(new_state.get_node_containers(&remote.id), [container]);
+        assert_eq!(new_state.storage_allocations, state.storage_allocations);
+        assert_eq!(new_membership.node_count(), 3);
+        assert_eq!(
+            new_membership.get_node(&remote.id).unwrap().status,
+            NodeStatus::Offline
+        );
+        assert_eq!(
+            new_membership.get_node(&replacement.id).unwrap().status,
+            NodeStatus::Online
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_dangling_and_duplicate_assignments() {
+        let a = node("node-a");
+        let b = node("node-b");
+        let container = Uuid::new_v4();
+        let mut snapshot = ClusterSnapshot {
+            version: SNAPSHOT_VERSION,
+            created_at: Utc::now(),
+            state: ClusterState::new(Uuid::new_v4()),
+            nodes: vec![a.clone()],
+        };
+        snapshot.state.assign_container(a.id, container);
+        assert!(snapshot.validate().is_ok());
+
+        snapshot.state.assign_container(b.id, container);
+        assert!(matches!(
+            snapshot.validate(),
+            Err(ClusterError::InvalidSnapshot(message)) if message.contains("unknown node")
+        ));
+
+        snapshot.nodes.push(b);
+        assert!(matches!(
+            snapshot.validate(),
+            Err(ClusterError::InvalidSnapshot(message)) if message.contains("both")
+        ));
+
+        snapshot.state.node_assignments.clear();
+        snapshot.version = SNAPSHOT_VERSION + 1;
+        assert!(snapshot.validate().is_err());
+    }
+}
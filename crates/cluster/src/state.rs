@@ -1,3 +1,5 @@
+use chrono::{DateTime, Utc};
+use models::{ContainerSummary, ContainerSummaryDelta, NodeStoragePool};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tracing::{debug, info};
@@ -9,6 +11,23 @@ pub struct ClusterState {
     pub leader_id: Option<Uuid>,
     pub node_assignments: HashMap<Uuid, Vec<Uuid>>, // node_id -> container_ids
     pub storage_allocations: HashMap<Uuid, Vec<Uuid>>, // pool_id -> volume_ids
+    /// Pools each node reported in its last heartbeat
+    #[serde(default)]
+    pub node_pools: HashMap<Uuid, Vec<NodeStoragePool>>,
+    /// Containers each node reported on its heartbeats
+    #[serde(default)]
+    pub node_containers: HashMap<Uuid, NodeContainers>,
+}
+
+/// A node's containers as last reported on its heartbeats
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeContainers {
+    /// Version of the node's containers these are at
+    pub version: u64,
+    /// When the last heartbeat carrying them arrived
+    pub received_at: DateTime<Utc>,
+    /// Sorted by name
+    pub containers: Vec<ContainerSummary>,
 }
 
 impl ClusterState {
@@ -18,6 +37,8 @@ impl ClusterState {
             leader_id: None,
             node_assignments: HashMap::new(),
             storage_allocations: HashMap::new(),
+            node_pools: HashMap::new(),
+            node_containers: HashMap::new(),
         }
     }
 
@@ -47,6 +68,12 @@ impl ClusterState {
             .unwrap_or_default()
     }
 
+    /// True until anything has been assigned or allocated
+    pub fn is_empty(&self) -> bool {
+        self.node_assignments.values().all(Vec::is_empty)
+            && self.storage_allocations.values().all(Vec::is_empty)
+    }
+
     pub fn allocate_storage(&mut self, pool_id: Uuid, volume_id: Uuid) {
         debug!("Allocating volume {} to pool {}", volume_id, pool_id);
         self.storage_allocations
@@ -54,4 +81,96 @@ impl ClusterState {
             .or_default()
             .push(volume_id);
     }
+
+    pub fn set_node_pools(&mut self, node_id: Uuid, pools: Vec<NodeStoragePool>) {
+        if self.node_pools.get(&node_id) != Some(&pools) {
+            debug!("Node {} reports {} storage pool(s)", node_id, pools.len());
+            self.node_pools.insert(node_id, pools);
+        }
+    }
+
+    /// Pools `node_id` last reported; empty until it has sent a heartbeat
+    pub fn get_node_pools(&self, node_id: &Uuid) -> Vec<NodeStoragePool> {
+        self.node_pools.get(node_id).cloned().unwrap_or_default()
+    }
+
+    /// Version of `node_id`'s containers held here, to ask for changes since
+    pub fn containers_version(&self, node_id: &Uuid) -> Option<u64> {
+        self.node_containers.get(node_id).map(|c| c.version)
+    }
+
+    /// Apply the container changes `node_id` sent at `received_at`
+    ///
+    /// A delta on top of a version other than the one held means a
+    /// heartbeat was missed; the containers held are dropped so the next
+    /// ping asks for all of them. Returns whether the delta applied.
+    pub fn apply_container_delta(
+        &mut self,
+        node_id: Uuid,
+        delta: ContainerSummaryDelta,
+        received_at: DateTime<Utc>,
+    ) -> bool {
+        let mut containers = match delta.base {
+            None => Vec::new(),
+            Some(base) if self.containers_version(&node_id) == Some(base) => {
+                self.node_containers.remove(&node_id).unwrap().containers
+            }
+            Some(base) => {
+                debug!(
+                    "Container delta from node {} is on top of version {}, which is not held",
+                    node_id, base
+                );
+                self.node_containers.remove(&node_id);
+                return false;
+            }
+        };
+        containers.retain(|c| {
+            !delta.removed.contains(&c.name) && !delta.updated.iter().any(|u| u.name == c.name)
+        });
+        containers.extend(delta.updated);
+        containers.sort_by(|a, b| a.name.cmp(&b.name));
+        self.node_containers.insert(
+            node_id,
+            NodeContainers {
+                version: delta.version,
+                received_at,
+                containers,
+            },
+        );
+        true
+    }
+
+    /// Move leadership off `leaving` to `successor`, if `leaving` holds it;
+    /// returns whether it did
+    pub fn hand_off_leadership(&mut self, leaving: &Uuid, successor: Option<Uuid>) -> bool {
+        if self.leader_id != Some(*leaving) {
+            return false;
+        }
+        match successor {
+            Some(successor) => info!("Handing leadership from {} to {}", leaving, successor),
+            None => info!("Leader {} is leaving with no successor", leaving),
+        }
+        self.leader_id = successor;
+        true
+    }
+
+    /// Spread the containers assigned to `from` over `to`, round-robin;
+    /// with nowhere to go they are unassigned. Returns how many moved.
+    pub fn reassign_containers(&mut self, from: &Uuid, to: &[Uuid]) -> usize {
+        let containers = self.node_assignments.remove(from).unwrap_or_default();
+        if to.is_empty() {
+            return 0;
+        }
+        for (container, node) in containers.iter().zip(to.iter().cycle()) {
+            self.assign_container(*node, *container);
+        }
+        containers.len()
+    }
+
+    /// Drop the pool and container reports of nodes that are no longer
+    /// members
+    pub fn retain_node_reports(&mut self, members: &[Uuid]) {
+        self.node_pools.retain(|id, _| members.contains(id));
+        self.node_containers.retain(|id, _| members.contains(id));
+    }
 }
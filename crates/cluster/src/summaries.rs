@@ -0,0 +1,194 @@
+/// Container summaries carried on heartbeats
+///
+/// A node numbers the state of its containers with a version that goes up
+/// whenever one of them changes, and remembers the changes of the last few
+/// versions. A ping names the version the asking node already holds and the
+/// pong carries only what changed since, so a node with hundreds of idle
+/// containers sends little more than a version number. An asking node that
+/// holds nothing, or is too far behind, gets every container.
+use models::{ContainerSummary, ContainerSummaryDelta};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::Mutex;
+
+/// Versions whose changes are kept for deltas
+pub const MAX_TRACKED_VERSIONS: usize = 64;
+
+pub struct ContainerSummaryLog {
+    inner: Mutex<LogInner>,
+}
+
+struct LogInner {
+    version: u64,
+    current: BTreeMap<String, ContainerSummary>,
+    /// Changes that led to each of the latest versions, oldest first
+    changes: VecDeque<Change>,
+}
+
+struct Change {
+    version: u64,
+    updated: Vec<ContainerSummary>,
+    removed: Vec<String>,
+}
+
+impl Default for ContainerSummaryLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContainerSummaryLog {
+    /// An empty log; versions start at the current time in milliseconds so
+    /// a restarted node never reuses one a peer still holds
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(LogInner {
+                version: chrono::Utc::now().timestamp_millis().max(0) as u64,
+                current: BTreeMap::new(),
+                changes: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Replace the summaries with `containers`, moving to a new version when
+    /// anything changed
+    pub fn update(&self, containers: Vec<ContainerSummary>) {
+        let mut inner = self.inner.lock().unwrap();
+        let next: BTreeMap<String, ContainerSummary> = containers
+            .into_iter()
+            .map(|summary| (summary.name.clone(), summary))
+            .collect();
+        let updated: Vec<ContainerSummary> = next
+            .values()
+            .filter(|summary| inner.current.get(&summary.name) != Some(summary))
+            .cloned()
+            .collect();
+        let removed: Vec<String> = inner
+            .current
+            .keys()
+            .filter(|name| !next.contains_key(*name))
+            .cloned()
+            .collect();
+        if updated.is_empty() && removed.is_empty() {
+            return;
+        }
+
+        inner.version += 1;
+        let version = inner.version;
+        inner.changes.push_back(Change {
+            version,
+            updated,
+            removed,
+        });
+        if inner.changes.len() > MAX_TRACKED_VERSIONS {
+            inner.changes.pop_front();
+        }
+        inner.current = next;
+    }
+
+    /// What changed since version `since`; every container when `since` is
+    /// None, unknown or older than the changes kept
+    pub fn delta(&self, since: Option<u64>) -> ContainerSummaryDelta {
+        let inner = self.inner.lock().unwrap();
+        let oldest_base = inner.changes.front().map(|change| change.version - 1);
+        match since {
+            Some(since) if since == inner.version => ContainerSummaryDelta {
+                version: inner.version,
+                base: Some(since),
+                ..Default::default()
+            },
+            Some(since)
+                if oldest_base.is_some_and(|oldest| oldest <= since) && since < inner.version =>
+            {
+                let mut updated = BTreeMap::new();
+                let mut removed = BTreeSet::new();
+                for change in inner.changes.iter().filter(|c| c.version > since) {
+                    for summary in &change.updated {
+                        removed.remove(&summary.name);
+                        updated.insert(summary.name.clone(), summary.clone());
+                    }
+                    for name in &change.removed {
+                        updated.remove(name);
+                        removed.insert(name.clone());
+                    }
+                }
+                ContainerSummaryDelta {
+                    version: inner.version,
+                    base: Some(since),
+                    updated: updated.into_values().collect(),
+                    removed: removed.into_iter().collect(),
+                }
+            }
+            _ => ContainerSummaryDelta {
+                version: inner.version,
+                base: None,
+                updated: inner.current.values().cloned().collect(),
+                removed: vec![],
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use models::{ContainerStatus, HealthStatus};
+
+    fn summary(name: &str, status: ContainerStatus) -> ContainerSummary {
+        ContainerSummary {
+            name: name.to_string(),
+            status,
+            health: HealthStatus::Unknown,
+        }
+    }
+
+    #[test]
+    fn test_deltas_carry_only_changes() {
+        let log = ContainerSummaryLog::new();
+        log.update(vec![
+            summary("db", ContainerStatus::Running),
+            summary("web", ContainerStatus::Stopped),
+        ]);
+        let full = log.delta(None);
+        assert_eq!(full.base, None);
+        assert_eq!(full.updated.len(), 2);
+
+        // Nothing changed: only the version goes over the wire
+        log.update(vec![
+            summary("db", ContainerStatus::Running),
+            summary("web", ContainerStatus::Stopped),
+        ]);
+        let idle = log.delta(Some(full.version));
+        assert_eq!(idle.version, full.version);
+        assert!(idle.updated.is_empty() && idle.removed.is_empty());
+
+        log.update(vec![summary("web", ContainerStatus::Running)]);
+        log.update(vec![
+            summary("web", ContainerStatus::Running),
+            summary("cache", ContainerStatus::Running),
+        ]);
+        let delta = log.delta(Some(full.version));
+        assert_eq!(delta.base, Some(full.version));
+        assert_eq!(delta.version, full.version + 2);
+        assert_eq!(
+            delta.updated,
+            [
+                summary("cache", ContainerStatus::Running),
+                summary("web", ContainerStatus::Running)
+            ]
+        );
+        assert_eq!(delta.removed, ["db"]);
+
+        // Unknown versions, and versions older than the log, get everything
+        assert_eq!(log.delta(Some(full.version + 100)).base, None);
+        for i in 0..MAX_TRACKED_VERSIONS {
+            let status = match i % 2 {
+                0 => ContainerStatus::Stopped,
+                _ => ContainerStatus::Running,
+            };
+            log.update(vec![summary("web", status)]);
+        }
+        let resync = log.delta(Some(full.version));
+        assert_eq!(resync.base, None);
+        assert_eq!(resync.updated.len(), 1);
+    }
+}
@@ -0,0 +1,160 @@
+/// Writing limits straight into a running container's cgroup v2 files
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use models::ContainerConfig;
+
+use crate::config::LxcConfig;
+use crate::error::ContainerError;
+
+/// Period written to `cpu.max`; a CPU limit of N allows N periods per period
+pub const CPU_PERIOD_US: u64 = 100_000;
+
+/// Where the unified hierarchy is mounted: `CGROUP_ROOT`, else /sys/fs/cgroup
+pub fn cgroup_root() -> PathBuf {
+    std::env::var("CGROUP_ROOT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("/sys/fs/cgroup"))
+}
+
+/// The cgroup of a running container, as named by LXC 4+ and by older releases
+pub fn container_cgroup(root: &Path, name: &str) -> Option<PathBuf> {
+    [
+        root.join(format!("lxc.payload.{}", name)),
+        root.join("lxc.payload").join(name),
+        root.join("lxc").join(name),
+    ]
+    .into_iter()
+    .find(|path| path.is_dir())
+}
+
+/// Processes the kernel OOM-killed in the cgroup `dir`, from the
+/// `oom_kill` line of its `memory.events`
+pub fn oom_kills(dir: &Path) -> Option<u64> {
+    fs::read_to_string(dir.join("memory.events"))
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// A cgroup file and the value written to it
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct LimitWrite {
+    pub file: String,
+    pub value: String,
+}
+
+/// The files the limits in `config` map to; unset limits are left alone
+pub fn limit_writes(config: &ContainerConfig) -> Result<Vec<LimitWrite>, ContainerError> {
+    let mut writes = Vec::new();
+    let mut write = |file: &str, value: String| {
+        writes.push(LimitWrite {
+            file: file.to_string(),
+            value,
+        })
+    };
+
+    if let Some(cpus) = config.cpu_limit {
+        if cpus == 0 {
+            return Err(ContainerError::InvalidConfig(
+                "cpu_limit must be greater than 0".to_string(),
+            ));
+        }
+        write("cpuset.cpus", format!("0-{}", cpus - 1));
+        write(
+            "cpu.max",
+            format!("{} {}", u64::from(cpus) * CPU_PERIOD_US, CPU_PERIOD_US),
+        );
+    }
+    if let Some(memory) = config.memory_limit {
+        if memory == 0 {
+            return Err(ContainerError::InvalidConfig(
+                "memory_limit must be greater than 0".to_string(),
+            ));
+        }
+        write("memory.max", memory.to_string());
+    }
+    if let Some(total) = config.memory_swap_limit {
+        models::validate::memory_swap_limit(config.memory_limit, total)
+            .map_err(|e| ContainerError::InvalidConfig(format!("memory_swap_limit {}", e)))?;
+        if let Some(swap) = LxcConfig::swap_max(config.memory_limit, Some(total)) {
+            write("memory.swap.max", swap.to_string());
+        }
+    }
+    Ok(writes)
+}
+
+/// Write `writes` into the cgroup directory `dir` of container `name`
+///
+/// Every controller is checked against `cgroup.controllers` before anything
+/// is written, so an undelegated controller leaves all limits unchanged.
+pub fn apply(dir: &Path, name: &str, writes: &[LimitWrite]) -> Result<(), ContainerError> {
+    let available = fs::read_to_string(dir.join("cgroup.controllers")).unwrap_or_default();
+    let available: Vec<&str> = available.split_whitespace().collect();
+    for write in writes {
+        let controller = write.file.split('.').next().unwrap_or_default();
+        if !available.contains(&controller) {
+            return Err(ContainerError::Unsupported(format!(
+                "The {} controller is not delegated to the cgroup of {}; enable it in {}",
+                controller,
+                name,
+                dir.parent()
+                    .unwrap_or(dir)
+                    .join("cgroup.subtree_control")
+                    .display()
+            )));
+        }
+    }
+
+    for write in writes {
+        let path = dir.join(&write.file);
+        fs::write(&path, &write.value).map_err(|e| {
+            ContainerError::InvalidConfig(format!(
+                "Could not write {} to {}: {}",
+                write.value,
+                path.display(),
+                e
+            ))
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_writes() {
+        let mut config = LxcConfig::parse("web", "");
+        assert!(limit_writes(&config).unwrap().is_empty());
+
+        config.cpu_limit = Some(2);
+        config.memory_limit = Some(512);
+        config.memory_swap_limit = Some(1024);
+        let writes: Vec<_> = limit_writes(&config)
+            .unwrap()
+            .into_iter()
+            .map(|w| (w.file, w.value))
+            .collect();
+        assert_eq!(
+            writes,
+            [
+                ("cpuset.cpus", "0-1"),
+                ("cpu.max", "200000 100000"),
+                ("memory.max", "512"),
+                ("memory.swap.max", "512"),
+            ]
+            .map(|(file, value)| (file.to_string(), value.to_string()))
+        );
+
+        config.memory_swap_limit = Some(100);
+        assert!(matches!(
+            limit_writes(&config),
+            Err(ContainerError::InvalidConfig(_))
+        ));
+    }
+}
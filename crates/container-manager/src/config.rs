@@ -1,60 +1,562 @@
 use anyhow::{Context, Result};
-use models::ContainerConfig;
+use models::{
+    validate, CidrPort, ContainerConfig, ContainerMount, ContainerNetworkInterface, EgressPolicy,
+    HealthCheck, HealthProbe, SecretRef,
+};
+use serde::Serialize;
+use std::borrow::Cow;
 use std::fs;
-use std::path::PathBuf;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Substrings that mark an environment variable as sensitive (matched case-insensitively)
+const SENSITIVE_KEY_MARKERS: &[&str] = &[
+    "PASSWORD",
+    "PASSWD",
+    "SECRET",
+    "TOKEN",
+    "API_KEY",
+    "PRIVATE_KEY",
+    "CREDENTIAL",
+];
+
+/// Marker for secret references, stored as comments so LXC ignores them
+const SECRET_REF_PREFIX: &str = "# orchestrator.secret =";
+
+/// Markers for the egress policy; the firewall, not LXC, enforces it
+const EGRESS_DEFAULT_DROP_PREFIX: &str = "# orchestrator.egress.default_drop =";
+const EGRESS_ALLOW_PREFIX: &str = "# orchestrator.egress.allow =";
+
+/// Markers for DNS settings, applied to the rootfs resolv.conf on start
+const DNS_SERVER_PREFIX: &str = "# orchestrator.dns.server =";
+const DNS_SEARCH_PREFIX: &str = "# orchestrator.dns.search =";
+
+/// Marker for boot dependencies, resolved by the orchestrator before start
+const DEPENDS_ON_PREFIX: &str = "# orchestrator.depends_on =";
+
+/// Marker for the health check, as JSON; the orchestrator runs the probes
+const HEALTH_CHECK_PREFIX: &str = "# orchestrator.health_check =";
+
+/// Marker for static routes as `<interface> <route>`; LXC has no key for
+/// them, so they are added inside the container after it starts
+const ROUTE_PREFIX: &str = "# orchestrator.route =";
+
+/// Options of the tmpfs mounted on each writable path of a read-only rootfs
+const WRITABLE_MOUNT_OPTIONS: &str = "rw,nosuid,nodev";
+
+/// Signals a container may be stopped with (`lxc.signal.stop`)
+pub const STOP_SIGNALS: &[&str] = &[
+    "SIGHUP",
+    "SIGINT",
+    "SIGQUIT",
+    "SIGKILL",
+    "SIGUSR1",
+    "SIGUSR2",
+    "SIGTERM",
+    "SIGPWR",
+    "SIGRTMIN+3",
+];
+
+/// Values the kernel accepts for `/proc/<pid>/oom_score_adj`
+pub const OOM_SCORE_ADJ_RANGE: RangeInclusive<i32> = -1000..=1000;
+
+/// Placeholder substituted for sensitive values
+pub const REDACTED: &str = "***REDACTED***";
+
+/// Container directory configured by the server, used when `LXC_ROOT` is unset
+static CONFIGURED_LXC_ROOT: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Architecture set by the server, used instead of the host's
+static CONFIGURED_ARCH: RwLock<Option<String>> = RwLock::new(None);
+
+/// Cgroup hierarchy set by the server, used instead of the detected one
+static CONFIGURED_CGROUP_VERSION: RwLock<Option<CgroupVersion>> = RwLock::new(None);
+
+/// Cgroup hierarchy the host mounts, which decides the limit keys LXC honours
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CgroupVersion {
+    /// Legacy per-controller hierarchies, configured with `lxc.cgroup.*`
+    V1,
+    /// The unified hierarchy, configured with `lxc.cgroup2.*`
+    V2,
+}
+
+impl CgroupVersion {
+    /// The unified hierarchy exposes `cgroup.controllers` at its root
+    pub fn detect() -> Self {
+        Self::detect_at(Path::new("/sys/fs/cgroup"))
+    }
+
+    fn detect_at(cgroup_root: &Path) -> Self {
+        if cgroup_root.join("cgroup.controllers").exists() {
+            CgroupVersion::V2
+        } else {
+            CgroupVersion::V1
+        }
+    }
+
+    pub fn cpuset_key(self) -> &'static str {
+        match self {
+            CgroupVersion::V1 => "lxc.cgroup.cpuset.cpus",
+            CgroupVersion::V2 => "lxc.cgroup2.cpuset.cpus",
+        }
+    }
+
+    pub fn memory_key(self) -> &'static str {
+        match self {
+            CgroupVersion::V1 => "lxc.cgroup.memory.limit_in_bytes",
+            CgroupVersion::V2 => "lxc.cgroup2.memory.max",
+        }
+    }
+
+    /// Key and value limiting swap: v2 limits the swap alone, v1 memory and
+    /// swap together, which needs a memory limit
+    pub fn swap_entry(
+        self,
+        memory_limit: Option<u64>,
+        memory_swap_limit: Option<u64>,
+    ) -> Option<(&'static str, u64)> {
+        let swap_max = LxcConfig::swap_max(memory_limit, memory_swap_limit)?;
+        match self {
+            CgroupVersion::V1 => Some((
+                "lxc.cgroup.memory.memsw.limit_in_bytes",
+                memory_limit? + swap_max,
+            )),
+            CgroupVersion::V2 => Some(("lxc.cgroup2.memory.swap.max", swap_max)),
+        }
+    }
+}
 
 pub struct LxcConfig;
 
 impl LxcConfig {
+    /// Directory holding container configs and root filesystems: `LXC_ROOT`,
+    /// then the configured root, then /var/lib/lxc
     pub fn lxc_root() -> PathBuf {
         std::env::var("LXC_ROOT")
             .map(PathBuf::from)
-            .unwrap_or_else(|_| PathBuf::from("/var/lib/lxc"))
+            .ok()
+            .or_else(|| CONFIGURED_LXC_ROOT.read().unwrap().clone())
+            .unwrap_or_else(|| PathBuf::from("/var/lib/lxc"))
+    }
+
+    /// Use `root` instead of /var/lib/lxc; it must match LXC's `lxc.lxcpath`
+    pub fn set_lxc_root(root: PathBuf) {
+        *CONFIGURED_LXC_ROOT.write().unwrap() = Some(root);
+    }
+
+    /// Architecture written as `lxc.arch`: the configured one, else the host's
+    pub fn arch() -> String {
+        CONFIGURED_ARCH
+            .read()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(models::host_arch)
+    }
+
+    /// Use `arch` instead of the detected host architecture
+    pub fn set_arch(arch: &str) {
+        *CONFIGURED_ARCH.write().unwrap() = Some(models::normalize_arch(arch));
+    }
+
+    /// Cgroup version limits are written for: the configured one, else the
+    /// host's
+    pub fn cgroup_version() -> CgroupVersion {
+        let configured = *CONFIGURED_CGROUP_VERSION.read().unwrap();
+        configured.unwrap_or_else(CgroupVersion::detect)
+    }
+
+    /// Use `version` instead of the detected cgroup hierarchy
+    pub fn set_cgroup_version(version: CgroupVersion) {
+        *CONFIGURED_CGROUP_VERSION.write().unwrap() = Some(version);
+    }
+
+    /// `memory.swap.max` for a memory+swap limit: the swap on top of the
+    /// memory limit, 0 when swap is disabled
+    pub fn swap_max(memory_limit: Option<u64>, memory_swap_limit: Option<u64>) -> Option<u64> {
+        match memory_swap_limit? {
+            0 => Some(0),
+            limit => Some(limit.saturating_sub(memory_limit?)),
+        }
     }
 
-    /// Generate LXC configuration file content
+    /// Generate LXC configuration file content for the host's cgroup version
     pub fn generate(name: &str, config: &ContainerConfig) -> String {
+        Self::generate_for(name, config, Self::cgroup_version())
+    }
+
+    /// Generate LXC configuration file content with limits for `cgroup`
+    pub fn generate_for(name: &str, config: &ContainerConfig, cgroup: CgroupVersion) -> String {
         let lxc_root = Self::lxc_root();
         let mut lxc_config = String::new();
 
         // Basic container configuration
-        lxc_config.push_str(&format!("lxc.uts.name = {}\n", name));
-        lxc_config.push_str("lxc.arch = arm64\n");
+        lxc_config.push_str(&format!("lxc.uts.name = {}\n", single_line(name)));
+        lxc_config.push_str(&format!("lxc.arch = {}\n", single_line(&Self::arch())));
         lxc_config.push_str("lxc.rootfs.path = dir:\n");
-        lxc_config.push_str(&format!(
-            "lxc.rootfs.path = {}/{}/rootfs\n",
-            lxc_root.display(),
-            name
-        ));
+        let rootfs = format!("{}/{}/rootfs", lxc_root.display(), name);
+        lxc_config.push_str(&format!("lxc.rootfs.path = {}\n", single_line(&rootfs)));
+
+        // Read-only root, with empty tmpfs mounts where the workload writes
+        if config.readonly_rootfs {
+            lxc_config.push_str("lxc.rootfs.options = ro\n");
+            for path in &config.writable_paths {
+                lxc_config.push_str(&format!(
+                    "lxc.mount.entry = tmpfs {} tmpfs {} 0 0\n",
+                    single_line(path.trim_start_matches('/')),
+                    WRITABLE_MOUNT_OPTIONS
+                ));
+            }
+        }
 
         // CPU limits
         if let Some(cpu_limit) = config.cpu_limit {
-            lxc_config.push_str(&format!("lxc.cgroup2.cpuset.cpus = 0-{}\n", cpu_limit - 1));
+            lxc_config.push_str(&format!("{} = 0-{}\n", cgroup.cpuset_key(), cpu_limit - 1));
         }
 
         // Memory limits
         if let Some(memory_limit) = config.memory_limit {
-            lxc_config.push_str(&format!("lxc.cgroup2.memory.max = {}\n", memory_limit));
+            lxc_config.push_str(&format!("{} = {}\n", cgroup.memory_key(), memory_limit));
+        }
+        if let Some((key, value)) = cgroup.swap_entry(config.memory_limit, config.memory_swap_limit)
+        {
+            lxc_config.push_str(&format!("{} = {}\n", key, value));
+        }
+
+        // OOM killer preference, applied to the container's init process
+        if let Some(oom_score_adj) = config.oom_score_adj {
+            lxc_config.push_str(&format!("lxc.proc.oom_score_adj = {}\n", oom_score_adj));
+        }
+
+        if let Some(ref signal) = config.stop_signal {
+            lxc_config.push_str(&format!("lxc.signal.stop = {}\n", single_line(signal)));
         }
 
         // Network interfaces
         for (idx, net_if) in config.network_interfaces.iter().enumerate() {
-            lxc_config.push_str(&format!("lxc.net.{}.type = veth\n", idx));
-            lxc_config.push_str(&format!("lxc.net.{}.link = {}\n", idx, net_if.bridge));
-            lxc_config.push_str(&format!("lxc.net.{}.name = {}\n", idx, net_if.name));
-            if let Some(ref mac) = net_if.mac {
-                lxc_config.push_str(&format!("lxc.net.{}.hwaddr = {}\n", idx, mac));
-            }
+            lxc_config.push_str(&Self::interface_lines(idx, net_if));
         }
 
-        // Environment variables
+        // Environment variables; LXC splits on the first `=`, so only the key
+        // must be free of it
         for (key, value) in &config.environment {
-            lxc_config.push_str(&format!("lxc.environment = {}={}\n", key, value));
+            lxc_config.push_str(&format!(
+                "lxc.environment = {}={}\n",
+                single_line(key),
+                single_line(value)
+            ));
+        }
+
+        // Host boot ordering, also honoured by lxc-autostart
+        if config.autostart {
+            lxc_config.push_str("lxc.start.auto = 1\n");
+        }
+        if config.start_order != 0 {
+            lxc_config.push_str(&format!("lxc.start.order = {}\n", config.start_order));
+        }
+
+        // Secret references (values live in the encrypted secret store)
+        for secret in &config.secrets {
+            lxc_config.push_str(&format!(
+                "{} {}\n",
+                SECRET_REF_PREFIX,
+                single_line(&secret.name)
+            ));
+        }
+
+        // Egress policy, written as `<cidr> [port=N] [protocol=P]` per allow entry
+        if let Some(ref policy) = config.egress_policy {
+            lxc_config.push_str(&format!(
+                "{} {}\n",
+                EGRESS_DEFAULT_DROP_PREFIX, policy.default_drop
+            ));
+            for allow in &policy.allow {
+                let mut entry = single_line(&allow.cidr).into_owned();
+                if let Some(port) = allow.port {
+                    entry.push_str(&format!(" port={}", port));
+                }
+                if let Some(ref protocol) = allow.protocol {
+                    entry.push_str(&format!(" protocol={}", single_line(protocol)));
+                }
+                lxc_config.push_str(&format!("{} {}\n", EGRESS_ALLOW_PREFIX, entry));
+            }
+        }
+
+        for server in &config.dns_servers {
+            lxc_config.push_str(&format!("{} {}\n", DNS_SERVER_PREFIX, single_line(server)));
+        }
+        for domain in &config.search_domains {
+            lxc_config.push_str(&format!("{} {}\n", DNS_SEARCH_PREFIX, single_line(domain)));
+        }
+
+        for dependency in &config.depends_on {
+            lxc_config.push_str(&format!(
+                "{} {}\n",
+                DEPENDS_ON_PREFIX,
+                single_line(dependency)
+            ));
+        }
+
+        if let Some(ref check) = config.health_check {
+            let json = serde_json::to_string(check).expect("health check serializes");
+            lxc_config.push_str(&format!("{} {}\n", HEALTH_CHECK_PREFIX, json));
         }
 
         lxc_config
     }
 
+    /// The `lxc.net.<idx>.*` keys describing one interface
+    fn interface_lines(idx: usize, net_if: &ContainerNetworkInterface) -> String {
+        let mut lines = format!("lxc.net.{}.type = veth\n", idx);
+        lines.push_str(&format!(
+            "lxc.net.{}.link = {}\n",
+            idx,
+            single_line(&net_if.bridge)
+        ));
+        lines.push_str(&format!(
+            "lxc.net.{}.name = {}\n",
+            idx,
+            single_line(&net_if.name)
+        ));
+        if let Some(ref mac) = net_if.mac {
+            lines.push_str(&format!("lxc.net.{}.hwaddr = {}\n", idx, single_line(mac)));
+        }
+        if let Some(ref ipv4) = net_if.ipv4 {
+            lines.push_str(&format!(
+                "lxc.net.{}.ipv4.address = {}\n",
+                idx,
+                single_line(ipv4)
+            ));
+        }
+        if let Some(ref ipv6) = net_if.ipv6 {
+            lines.push_str(&format!(
+                "lxc.net.{}.ipv6.address = {}\n",
+                idx,
+                single_line(ipv6)
+            ));
+        }
+        if let Some(ref gateway) = net_if.gateway_v4 {
+            lines.push_str(&format!(
+                "lxc.net.{}.ipv4.gateway = {}\n",
+                idx,
+                single_line(gateway)
+            ));
+        }
+        if let Some(ref gateway) = net_if.gateway_v6 {
+            lines.push_str(&format!(
+                "lxc.net.{}.ipv6.gateway = {}\n",
+                idx,
+                single_line(gateway)
+            ));
+        }
+        for route in &net_if.routes {
+            lines.push_str(&format!(
+                "{} {} {}\n",
+                ROUTE_PREFIX,
+                single_line(&net_if.name),
+                single_line(route)
+            ));
+        }
+        lines
+    }
+
+    /// Interface name and route of a route marker line
+    fn route_entry(line: &str) -> Option<(&str, &str)> {
+        let (name, route) = line.strip_prefix(ROUTE_PREFIX)?.trim().split_once(' ')?;
+        Some((name, route.trim()))
+    }
+
+    /// `ip route` commands adding the static routes of `interfaces`, run
+    /// inside the container once it is up
+    pub fn route_commands(interfaces: &[ContainerNetworkInterface]) -> Vec<Vec<String>> {
+        let mut commands = Vec::new();
+        for interface in interfaces {
+            for route in &interface.routes {
+                let mut command: Vec<String> = ["ip", "route", "replace"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect();
+                command.extend(route.split_whitespace().map(String::from));
+                command.extend(["dev".to_string(), interface.name.clone()]);
+                commands.push(command);
+            }
+        }
+        commands
+    }
+
+    /// Index and field of an `lxc.net.<idx>.<field>` line
+    fn net_key(line: &str) -> Option<(usize, &str)> {
+        let (key, _) = line.split_once('=')?;
+        let (idx, field) = key.trim().strip_prefix("lxc.net.")?.split_once('.')?;
+        Some((idx.parse().ok()?, field))
+    }
+
+    /// Append `interface` to configuration file content after the existing
+    /// interfaces
+    pub fn add_interface(content: &str, interface: &ContainerNetworkInterface) -> String {
+        let idx = content
+            .lines()
+            .filter_map(|line| Self::net_key(line.trim()))
+            .map(|(idx, _)| idx + 1)
+            .max()
+            .unwrap_or(0);
+        let mut updated = content.to_string();
+        if !updated.is_empty() && !updated.ends_with('\n') {
+            updated.push('\n');
+        }
+        updated.push_str(&Self::interface_lines(idx, interface));
+        updated
+    }
+
+    /// Remove the interface called `name` from configuration file content,
+    /// renumbering the interfaces after it; `None` if there is no such
+    /// interface
+    pub fn remove_interface(content: &str, name: &str) -> Option<String> {
+        let idx = Self::parse_interfaces(content)
+            .into_iter()
+            .find(|(_, interface)| interface.name == name)
+            .map(|(idx, _)| idx)?;
+
+        let mut updated = String::new();
+        for line in content.lines() {
+            if Self::route_entry(line.trim()).is_some_and(|(interface, _)| interface == name) {
+                continue;
+            }
+            match Self::net_key(line.trim()) {
+                Some((i, _)) if i == idx => continue,
+                Some((i, field)) if i > idx => {
+                    let (_, value) = line.split_once('=').unwrap_or_default();
+                    updated.push_str(&format!("lxc.net.{}.{} ={}\n", i - 1, field, value));
+                }
+                _ => updated.push_str(&format!("{}\n", line)),
+            }
+        }
+        Some(updated)
+    }
+
+    /// Content of `/etc/resolv.conf` for the given nameservers and search
+    /// domains
+    pub fn resolv_conf(servers: &[String], search_domains: &[String]) -> String {
+        let mut content =
+            String::from("# Generated by arm-hypervisor; changes are overwritten on start\n");
+        if !search_domains.is_empty() {
+            content.push_str(&format!("search {}\n", search_domains.join(" ")));
+        }
+        for server in servers {
+            content.push_str(&format!("nameserver {}\n", server));
+        }
+        content
+    }
+
+    /// Check values the kernel or LXC would reject at start time
+    pub fn validate(config: &ContainerConfig) -> Result<(), String> {
+        if let Some(value) = config.oom_score_adj {
+            Self::validate_oom_score_adj(value)?;
+        }
+        if let Some(ref signal) = config.stop_signal {
+            Self::validate_stop_signal(signal)?;
+        }
+        if let Some(ref check) = config.health_check {
+            Self::validate_health_check(check)?;
+        }
+        Self::validate_environment(&config.environment)?;
+        Self::validate_dns(&config.dns_servers, &config.search_domains)
+    }
+
+    /// Probes need a command or port, and must time out within their interval
+    pub fn validate_health_check(check: &HealthCheck) -> Result<(), String> {
+        match &check.probe {
+            HealthProbe::Exec { command } if command.is_empty() => {
+                return Err("health_check command must not be empty".to_string());
+            }
+            HealthProbe::Tcp { port: 0 } => {
+                return Err("health_check port must not be 0".to_string());
+            }
+            _ => {}
+        }
+        if check.interval_secs == 0 || check.timeout_secs == 0 {
+            return Err("health_check interval and timeout must be greater than 0".to_string());
+        }
+        if check.timeout_secs > check.interval_secs {
+            return Err(format!(
+                "health_check timeout ({}s) must not exceed its interval ({}s)",
+                check.timeout_secs, check.interval_secs
+            ));
+        }
+        if check.failure_threshold == 0 {
+            return Err("health_check failure_threshold must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+
+    /// Keys and values must fit on their `lxc.environment` line
+    pub fn validate_environment(environment: &[(String, String)]) -> Result<(), String> {
+        for (key, value) in environment {
+            validate::env_key(key)
+                .and_then(|()| validate::env_value(value))
+                .map_err(|e| format!("environment variable {:?}: {}", key, e))?;
+        }
+        Ok(())
+    }
+
+    /// Nameservers must be IP addresses; search domains must be single
+    /// words, as resolv.conf separates them with whitespace
+    pub fn validate_dns(servers: &[String], search_domains: &[String]) -> Result<(), String> {
+        if let Some(server) = servers
+            .iter()
+            .find(|s| s.parse::<std::net::IpAddr>().is_err())
+        {
+            return Err(format!(
+                "DNS server must be an IP address, got {:?}",
+                server
+            ));
+        }
+        if let Some(domain) = search_domains
+            .iter()
+            .find(|d| d.is_empty() || d.contains(|c: char| c.is_whitespace() || c == '#'))
+        {
+            return Err(format!("invalid DNS search domain: {:?}", domain));
+        }
+        Ok(())
+    }
+
+    pub fn validate_stop_signal(signal: &str) -> Result<(), String> {
+        if !STOP_SIGNALS.contains(&signal) {
+            return Err(format!(
+                "stop_signal must be one of {}, got {:?}",
+                STOP_SIGNALS.join(", "),
+                signal
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn validate_oom_score_adj(value: i32) -> Result<(), String> {
+        if !OOM_SCORE_ADJ_RANGE.contains(&value) {
+            return Err(format!(
+                "oom_score_adj must be between {} and {}, got {}",
+                OOM_SCORE_ADJ_RANGE.start(),
+                OOM_SCORE_ADJ_RANGE.end(),
+                value
+            ));
+        }
+        Ok(())
+    }
+
+    /// Set `key` to `value` in configuration file content, replacing any
+    /// existing assignments
+    pub fn set_key(content: &str, key: &str, value: &str) -> String {
+        let mut updated: String = content
+            .lines()
+            .filter(|line| line.split_once('=').is_none_or(|(k, _)| k.trim() != key))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        updated.push_str(&format!("{} = {}\n", key, single_line(value)));
+        updated
+    }
+
     /// Write configuration to file
     pub fn write(name: &str, config: &ContainerConfig) -> Result<()> {
         let config_dir = Self::lxc_root().join(name);
@@ -67,9 +569,839 @@ impl LxcConfig {
         Ok(())
     }
 
+    /// Replace the configuration file with `content` as-is
+    pub fn write_raw(name: &str, content: &str) -> Result<()> {
+        let config_path = Self::lxc_root().join(name).join("config");
+        fs::write(&config_path, content).context("Failed to write LXC config file")
+    }
+
     /// Read configuration from file
     pub fn read(name: &str) -> Result<String> {
         let config_path = Self::lxc_root().join(name).join("config");
         fs::read_to_string(&config_path).context("Failed to read LXC config file")
     }
+
+    /// Parse configuration file content back into a `ContainerConfig`
+    ///
+    /// Only the keys written by `generate` are understood; anything else is
+    /// ignored. `disk_limit` is not stored in the LXC config and is always `None`.
+    pub fn parse(name: &str, content: &str) -> ContainerConfig {
+        let mut config = ContainerConfig {
+            rootfs_path: format!("{}/rootfs", Self::lxc_root().join(name).display()),
+            ..Default::default()
+        };
+        let mut swap_max: Option<u64> = None;
+        let mut memsw_limit: Option<u64> = None;
+        for line in content.lines() {
+            let line = line.trim();
+            if let Some(name) = line.strip_prefix(SECRET_REF_PREFIX) {
+                config.secrets.push(SecretRef {
+                    name: name.trim().to_string(),
+                });
+                continue;
+            }
+            if let Some(value) = line.strip_prefix(EGRESS_DEFAULT_DROP_PREFIX) {
+                config
+                    .egress_policy
+                    .get_or_insert_with(EgressPolicy::default)
+                    .default_drop = value.trim() == "true";
+                continue;
+            }
+            if let Some(server) = line.strip_prefix(DNS_SERVER_PREFIX) {
+                config.dns_servers.push(server.trim().to_string());
+                continue;
+            }
+            if let Some(domain) = line.strip_prefix(DNS_SEARCH_PREFIX) {
+                config.search_domains.push(domain.trim().to_string());
+                continue;
+            }
+            if let Some(dependency) = line.strip_prefix(DEPENDS_ON_PREFIX) {
+                config.depends_on.push(dependency.trim().to_string());
+                continue;
+            }
+            if let Some(check) = line.strip_prefix(HEALTH_CHECK_PREFIX) {
+                config.health_check = serde_json::from_str(check.trim()).ok();
+                continue;
+            }
+            if let Some(value) = line.strip_prefix(EGRESS_ALLOW_PREFIX) {
+                if let Some(allow) = Self::parse_egress_allow(value) {
+                    config
+                        .egress_policy
+                        .get_or_insert_with(EgressPolicy::default)
+                        .allow
+                        .push(allow);
+                }
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            match key {
+                "lxc.rootfs.path" => {
+                    let path = value.strip_prefix("dir:").unwrap_or(value);
+                    if !path.is_empty() {
+                        config.rootfs_path = path.to_string();
+                    }
+                }
+                "lxc.cgroup2.cpuset.cpus" | "lxc.cgroup.cpuset.cpus" => {
+                    config.cpu_limit = Self::count_cpus(value)
+                }
+                "lxc.cgroup2.memory.max" | "lxc.cgroup.memory.limit_in_bytes" => {
+                    config.memory_limit = value.parse().ok()
+                }
+                "lxc.cgroup2.memory.swap.max" => swap_max = value.parse().ok(),
+                "lxc.cgroup.memory.memsw.limit_in_bytes" => memsw_limit = value.parse().ok(),
+                "lxc.start.auto" => config.autostart = value == "1",
+                "lxc.start.order" => config.start_order = value.parse().unwrap_or(0),
+                "lxc.proc.oom_score_adj" => config.oom_score_adj = value.parse().ok(),
+                "lxc.signal.stop" => config.stop_signal = Some(value.to_string()),
+                "lxc.rootfs.options" => {
+                    config.readonly_rootfs = value.split(',').any(|option| option.trim() == "ro")
+                }
+                "lxc.environment" => {
+                    if let Some((k, v)) = value.split_once('=') {
+                        config.environment.push((k.to_string(), v.to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        config.memory_swap_limit = match (swap_max, memsw_limit) {
+            (Some(0), _) => Some(0),
+            (Some(swap), _) => config.memory_limit.map(|memory| memory + swap),
+            // v1 counts memory and swap together; equal to memory means no swap
+            (None, Some(total)) if Some(total) == config.memory_limit => Some(0),
+            (None, total) => total,
+        };
+        config.network_interfaces = Self::parse_interfaces(content)
+            .into_iter()
+            .map(|(_, interface)| interface)
+            .collect();
+        if config.readonly_rootfs {
+            config.writable_paths = Self::parse_mount_entries(content)
+                .into_iter()
+                .filter(|mount| {
+                    mount.source == "tmpfs"
+                        && mount.fs_type == "tmpfs"
+                        && mount.options == WRITABLE_MOUNT_OPTIONS
+                })
+                .map(|mount| format!("/{}", mount.target))
+                .collect();
+        }
+        config
+    }
+
+    /// The `lxc.net.<idx>.*` interfaces of configuration file content, by index
+    fn parse_interfaces(content: &str) -> Vec<(usize, ContainerNetworkInterface)> {
+        // Interfaces are keyed by index and their keys may appear in any order
+        let mut interfaces: Vec<(usize, ContainerNetworkInterface)> = Vec::new();
+        let mut routes = Vec::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if let Some(route) = Self::route_entry(line) {
+                routes.push(route);
+                continue;
+            }
+            let Some((idx, field)) = Self::net_key(line) else {
+                continue;
+            };
+            let value = line.split_once('=').map_or("", |(_, value)| value.trim());
+
+            let pos = match interfaces.iter().position(|(i, _)| *i == idx) {
+                Some(pos) => pos,
+                None => {
+                    interfaces.push((
+                        idx,
+                        ContainerNetworkInterface {
+                            name: format!("eth{}", idx),
+                            bridge: String::new(),
+                            ipv4: None,
+                            ipv6: None,
+                            mac: None,
+                            gateway_v4: None,
+                            gateway_v6: None,
+                            routes: vec![],
+                        },
+                    ));
+                    interfaces.len() - 1
+                }
+            };
+            let net_if = &mut interfaces[pos].1;
+            match field {
+                "link" => net_if.bridge = value.to_string(),
+                "name" => net_if.name = value.to_string(),
+                "hwaddr" => net_if.mac = Some(value.to_string()),
+                "ipv4.address" => net_if.ipv4 = Some(value.to_string()),
+                "ipv6.address" => net_if.ipv6 = Some(value.to_string()),
+                "ipv4.gateway" => net_if.gateway_v4 = Some(value.to_string()),
+                "ipv6.gateway" => net_if.gateway_v6 = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        for (name, route) in routes {
+            if let Some((_, net_if)) = interfaces.iter_mut().find(|(_, i)| i.name == name) {
+                net_if.routes.push(route.to_string());
+            }
+        }
+
+        interfaces.sort_by_key(|(idx, _)| *idx);
+        interfaces
+    }
+
+    /// Parse an egress allow entry of the form `<cidr> [port=N] [protocol=P]`
+    fn parse_egress_allow(value: &str) -> Option<CidrPort> {
+        let mut fields = value.split_whitespace();
+        let mut allow = CidrPort {
+            cidr: fields.next()?.to_string(),
+            port: None,
+            protocol: None,
+        };
+        for field in fields {
+            match field.split_once('=') {
+                Some(("port", port)) => allow.port = Some(port.parse().ok()?),
+                Some(("protocol", protocol)) => allow.protocol = Some(protocol.to_string()),
+                _ => {}
+            }
+        }
+        Some(allow)
+    }
+
+    /// Count the CPUs in a cpuset list such as `0-3` or `0,2,4-5`
+    fn count_cpus(cpuset: &str) -> Option<u32> {
+        let mut count = 0;
+        for part in cpuset.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            count += match part.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (start.parse::<u32>().ok()?, end.parse::<u32>().ok()?);
+                    end.checked_sub(start)? + 1
+                }
+                None => {
+                    part.parse::<u32>().ok()?;
+                    1
+                }
+            };
+        }
+        (count > 0).then_some(count)
+    }
+
+    /// Whether an environment variable name looks like it holds a secret
+    pub fn is_sensitive_key(key: &str) -> bool {
+        let key = key.to_uppercase();
+        SENSITIVE_KEY_MARKERS
+            .iter()
+            .any(|marker| key.contains(marker))
+    }
+
+    /// Replace sensitive `lxc.environment` values in configuration file content
+    pub fn redact(content: &str) -> String {
+        content
+            .lines()
+            .map(|line| {
+                let redacted = line.split_once('=').and_then(|(key, value)| {
+                    if key.trim() != "lxc.environment" {
+                        return None;
+                    }
+                    let (env_key, _) = value.split_once('=')?;
+                    Self::is_sensitive_key(env_key.trim())
+                        .then(|| format!("{}={}={}", key, env_key, REDACTED))
+                });
+                redacted.unwrap_or_else(|| line.to_string()) + "\n"
+            })
+            .collect()
+    }
+
+    /// Parse `lxc.mount.entry` lines from configuration file content
+    ///
+    /// Entries follow fstab syntax: `source target fstype options [dump [pass]]`.
+    pub fn parse_mount_entries(content: &str) -> Vec<ContainerMount> {
+        content
+            .lines()
+            .filter_map(|line| {
+                let (key, value) = line.split_once('=')?;
+                if key.trim() != "lxc.mount.entry" {
+                    return None;
+                }
+
+                let mut fields = value.split_whitespace();
+                Some(ContainerMount {
+                    source: fields.next()?.to_string(),
+                    target: fields.next()?.to_string(),
+                    fs_type: fields.next().unwrap_or("none").to_string(),
+                    options: fields.next().unwrap_or("defaults").to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// Content without the `lxc.mount.entry` lines mounting `source`
+    pub fn remove_mount_entries(content: &str, source: &str) -> String {
+        content
+            .lines()
+            .filter(|line| {
+                Self::parse_mount_entries(line)
+                    .first()
+                    .is_none_or(|mount| mount.source != source)
+            })
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+}
+
+/// `value` with control characters escaped, so it stays on its own config
+/// line whether or not it was validated: LXC has no quoting, and a line break
+/// would start a new key such as `lxc.hook.pre-start`
+fn single_line(value: &str) -> Cow<'_, str> {
+    if !value.contains(char::is_control) {
+        return Cow::Borrowed(value);
+    }
+    Cow::Owned(
+        value
+            .chars()
+            .map(|c| {
+                if c.is_control() {
+                    c.escape_default().to_string()
+                } else {
+                    c.to_string()
+                }
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_round_trips_generated_config() {
+        let config = ContainerConfig {
+            cpu_limit: Some(2),
+            memory_limit: Some(512 * 1024 * 1024),
+            network_interfaces: vec![ContainerNetworkInterface {
+                name: "eth0".to_string(),
+                bridge: "lxcbr0".to_string(),
+                ipv4: Some("10.0.3.10/24".to_string()),
+                ipv6: None,
+                mac: Some("00:16:3e:00:00:01".to_string()),
+                gateway_v4: None,
+                gateway_v6: None,
+                routes: vec![],
+            }],
+            environment: vec![("APP_ENV".to_string(), "prod".to_string())],
+            secrets: vec![SecretRef {
+                name: "db_password".to_string(),
+            }],
+            autostart: true,
+            start_order: 10,
+            egress_policy: Some(EgressPolicy {
+                default_drop: true,
+                allow: vec![
+                    CidrPort {
+                        cidr: "10.0.0.0/8".to_string(),
+                        port: None,
+                        protocol: None,
+                    },
+                    CidrPort {
+                        cidr: "1.1.1.1".to_string(),
+                        port: Some(53),
+                        protocol: Some("udp".to_string()),
+                    },
+                ],
+            }),
+            oom_score_adj: Some(500),
+            dns_servers: vec!["10.0.0.53".to_string(), "2001:db8::53".to_string()],
+            search_domains: vec!["corp.example".to_string()],
+            depends_on: vec!["db".to_string()],
+            ..Default::default()
+        };
+
+        let generated = LxcConfig::generate("web", &config);
+        assert!(generated.contains("lxc.proc.oom_score_adj = 500\n"));
+
+        let parsed = LxcConfig::parse("web", &generated);
+        assert_eq!(parsed.cpu_limit, Some(2));
+        assert_eq!(parsed.memory_limit, Some(512 * 1024 * 1024));
+        assert_eq!(parsed.network_interfaces.len(), 1);
+        assert_eq!(parsed.network_interfaces[0].bridge, "lxcbr0");
+        assert_eq!(
+            parsed.network_interfaces[0].mac.as_deref(),
+            Some("00:16:3e:00:00:01")
+        );
+        assert_eq!(
+            parsed.network_interfaces[0].ipv4.as_deref(),
+            Some("10.0.3.10/24")
+        );
+        assert!(parsed.rootfs_path.ends_with("web/rootfs"));
+        assert_eq!(parsed.environment, config.environment);
+        assert_eq!(parsed.secrets, config.secrets);
+        assert!(parsed.autostart);
+        assert_eq!(parsed.start_order, 10);
+        assert_eq!(parsed.egress_policy, config.egress_policy);
+        assert_eq!(parsed.oom_score_adj, Some(500));
+        assert_eq!(parsed.dns_servers, config.dns_servers);
+        assert_eq!(parsed.search_domains, config.search_domains);
+        assert_eq!(parsed.depends_on, config.depends_on);
+    }
+
+    #[test]
+    fn test_generate_keeps_hostile_values_on_one_line() {
+        let mut config = LxcConfig::parse("web", "");
+        config.environment = vec![
+            (
+                "A".to_string(),
+                "x\nlxc.hook.pre-start = /bin/sh -c id".to_string(),
+            ),
+            ("B".to_string(), "# not a comment=still B".to_string()),
+            ("C".to_string(), "c".repeat(64 * 1024)),
+            (
+                "D\r\nlxc.apparmor.profile".to_string(),
+                "unconfined".to_string(),
+            ),
+        ];
+        config.network_interfaces = vec![ContainerNetworkInterface {
+            name: "eth0\nlxc.net.0.script.up = /tmp/up".to_string(),
+            bridge: "lxcbr0\n".to_string(),
+            ipv4: None,
+            ipv6: None,
+            mac: Some("00:16:3e:00:00:01\nlxc.mount.auto = sys:rw".to_string()),
+            gateway_v4: None,
+            gateway_v6: None,
+            routes: vec![],
+        }];
+        config.stop_signal = Some("SIGTERM\nlxc.init.cmd = /bin/sh".to_string());
+        config.readonly_rootfs = true;
+        config.writable_paths = vec!["/data 0 0\nlxc.mount.entry = / host".to_string()];
+
+        let generated = LxcConfig::generate("web", &config);
+        // uts.name, arch, two rootfs.path, rootfs.options, one mount entry,
+        // stop signal, four interface keys and four environment variables
+        assert_eq!(generated.lines().count(), 15, "{}", generated);
+        for key in [
+            "lxc.hook.pre-start",
+            "lxc.apparmor.profile",
+            "lxc.net.0.script.up",
+            "lxc.mount.auto",
+            "lxc.init.cmd",
+        ] {
+            assert!(
+                !generated.lines().any(|line| line.starts_with(key)),
+                "{} injected",
+                key
+            );
+        }
+        assert!(generated.contains("lxc.environment = A=x\\nlxc.hook.pre-start"));
+        assert!(generated.contains("lxc.environment = B=# not a comment=still B\n"));
+
+        assert!(LxcConfig::validate(&config).is_err());
+        for environment in [
+            vec![("B".to_string(), "# not a comment=still B".to_string())],
+            vec![("PATH".to_string(), "/usr/bin:/bin".to_string())],
+        ] {
+            assert!(LxcConfig::validate_environment(&environment).is_ok());
+        }
+        for (key, value) in [
+            ("A", "x\ny"),
+            ("A", "tab\there"),
+            ("C", &*"c".repeat(64 * 1024)),
+            ("D=E", "f"),
+            ("", "empty"),
+        ] {
+            assert!(
+                LxcConfig::validate_environment(&[(key.to_string(), value.to_string())]).is_err(),
+                "{:?}={:?} accepted",
+                key,
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_gateways_and_routes_round_trip() {
+        let mut config = LxcConfig::parse("web", "");
+        config.network_interfaces = vec![
+            ContainerNetworkInterface {
+                name: "eth0".to_string(),
+                bridge: "lxcbr0".to_string(),
+                ipv4: Some("10.0.3.10/24".to_string()),
+                ipv6: Some("fd00::10/64".to_string()),
+                mac: None,
+                gateway_v4: Some("10.0.3.1".to_string()),
+                gateway_v6: Some("auto".to_string()),
+                routes: vec![],
+            },
+            ContainerNetworkInterface {
+                name: "eth1".to_string(),
+                bridge: "hvbr1".to_string(),
+                ipv4: Some("10.1.0.5/24".to_string()),
+                ipv6: None,
+                mac: None,
+                gateway_v4: None,
+                gateway_v6: None,
+                routes: vec![
+                    "10.2.0.0/16 via 10.1.0.254".to_string(),
+                    "10.3.0.0/16".to_string(),
+                ],
+            },
+        ];
+
+        let generated = LxcConfig::generate("web", &config);
+        assert!(generated.contains("lxc.net.0.ipv4.gateway = 10.0.3.1\n"));
+        assert!(generated.contains("lxc.net.0.ipv6.gateway = auto\n"));
+        assert!(!generated.contains("lxc.net.1.ipv4.gateway"));
+        assert!(generated.contains("# orchestrator.route = eth1 10.2.0.0/16 via 10.1.0.254\n"));
+
+        let parsed = LxcConfig::parse("web", &generated).network_interfaces;
+        assert_eq!(parsed[0].gateway_v4.as_deref(), Some("10.0.3.1"));
+        assert_eq!(parsed[0].gateway_v6.as_deref(), Some("auto"));
+        assert!(parsed[0].routes.is_empty());
+        assert_eq!(parsed[1].gateway_v4, None);
+        assert_eq!(parsed[1].routes, config.network_interfaces[1].routes);
+
+        let commands: Vec<String> = LxcConfig::route_commands(&parsed)
+            .into_iter()
+            .map(|command| command.join(" "))
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "ip route replace 10.2.0.0/16 via 10.1.0.254 dev eth1",
+                "ip route replace 10.3.0.0/16 dev eth1",
+            ]
+        );
+
+        let removed = LxcConfig::remove_interface(&generated, "eth1").unwrap();
+        assert!(!removed.contains("orchestrator.route"));
+        assert_eq!(
+            LxcConfig::parse("web", &removed).network_interfaces.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_add_and_remove_interfaces() {
+        let interface = |name: &str, bridge: &str| ContainerNetworkInterface {
+            name: name.to_string(),
+            bridge: bridge.to_string(),
+            ipv4: None,
+            ipv6: None,
+            mac: None,
+            gateway_v4: None,
+            gateway_v6: None,
+            routes: vec![],
+        };
+        let content = "lxc.uts.name = web\n\
+                       lxc.net.0.type = veth\n\
+                       lxc.net.0.link = lxcbr0\n\
+                       lxc.net.0.name = eth0\n\
+                       lxc.mount.entry = /srv srv none bind 0 0\n";
+
+        let mut eth1 = interface("eth1", "hvbr1");
+        eth1.ipv4 = Some("10.1.0.5/24".to_string());
+        let content = LxcConfig::add_interface(content, &eth1);
+        assert!(content.contains("lxc.net.1.link = hvbr1\n"));
+        let content = LxcConfig::add_interface(&content, &interface("eth2", "hvbr2"));
+        let names: Vec<_> = LxcConfig::parse("web", &content)
+            .network_interfaces
+            .into_iter()
+            .map(|i| i.name)
+            .collect();
+        assert_eq!(names, ["eth0", "eth1", "eth2"]);
+
+        let content = LxcConfig::remove_interface(&content, "eth1").unwrap();
+        let parsed = LxcConfig::parse("web", &content);
+        assert_eq!(parsed.network_interfaces.len(), 2);
+        // Later interfaces move down so indices stay contiguous
+        assert!(content.contains("lxc.net.1.name = eth2\n"));
+        assert!(!content.contains("lxc.net.2"));
+        assert!(!content.contains("10.1.0.5"));
+        // Keys the parser does not know survive
+        assert!(content.contains("lxc.mount.entry = /srv srv none bind 0 0\n"));
+
+        assert!(LxcConfig::remove_interface(&content, "eth1").is_none());
+    }
+
+    #[test]
+    fn test_resolv_conf() {
+        let servers = vec!["10.0.0.53".to_string(), "1.1.1.1".to_string()];
+        let search = vec!["corp.example".to_string(), "example".to_string()];
+        assert!(LxcConfig::validate_dns(&servers, &search).is_ok());
+
+        let content = LxcConfig::resolv_conf(&servers, &search);
+        let lines: Vec<&str> = content.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(
+            lines,
+            [
+                "search corp.example example",
+                "nameserver 10.0.0.53",
+                "nameserver 1.1.1.1"
+            ]
+        );
+
+        assert!(LxcConfig::validate_dns(&["dns.google".to_string()], &[]).is_err());
+        assert!(LxcConfig::validate_dns(&[], &["two words".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_oom_score_adj_range() {
+        for value in [-1000, 0, 1000] {
+            assert!(LxcConfig::validate_oom_score_adj(value).is_ok());
+        }
+        for value in [-1001, 1001, i32::MIN, i32::MAX] {
+            assert!(LxcConfig::validate_oom_score_adj(value).is_err());
+        }
+    }
+
+    #[test]
+    fn test_stop_signal_names() {
+        for signal in ["SIGINT", "SIGTERM", "SIGPWR", "SIGRTMIN+3"] {
+            assert!(
+                LxcConfig::validate_stop_signal(signal).is_ok(),
+                "{}",
+                signal
+            );
+        }
+        for signal in [
+            "",
+            "INT",
+            "sigint",
+            "SIGSEGV",
+            "2",
+            "SIGINT\nlxc.init.cmd = /bin/sh",
+        ] {
+            assert!(
+                LxcConfig::validate_stop_signal(signal).is_err(),
+                "{:?}",
+                signal
+            );
+        }
+
+        let mut config = LxcConfig::parse("web", "");
+        config.stop_signal = Some("SIGKILL".to_string());
+        config.oom_score_adj = Some(0);
+        assert!(LxcConfig::validate(&config).is_ok());
+        config.stop_signal = Some("SIGSTOP".to_string());
+        assert!(LxcConfig::validate(&config).is_err());
+    }
+
+    #[test]
+    fn test_generate_stop_signal() {
+        let mut config = LxcConfig::parse("web", "");
+        assert!(!LxcConfig::generate("web", &config).contains("lxc.signal.stop"));
+
+        config.stop_signal = Some("SIGINT".to_string());
+        let generated = LxcConfig::generate("web", &config);
+        assert!(generated.contains("lxc.signal.stop = SIGINT\n"));
+        assert_eq!(
+            LxcConfig::parse("web", &generated).stop_signal.as_deref(),
+            Some("SIGINT")
+        );
+    }
+
+    #[test]
+    fn test_generate_readonly_rootfs() {
+        let mut config = LxcConfig::parse("web", "");
+        assert!(!LxcConfig::generate("web", &config).contains("lxc.rootfs.options"));
+
+        config.readonly_rootfs = true;
+        config.writable_paths = vec!["/tmp".to_string(), "/var/log".to_string()];
+        let generated = LxcConfig::generate("web", &config);
+        assert!(generated.contains("lxc.rootfs.options = ro\n"));
+        assert!(generated.contains("lxc.mount.entry = tmpfs tmp tmpfs rw,nosuid,nodev 0 0\n"));
+        assert!(generated.contains("lxc.mount.entry = tmpfs var/log tmpfs rw,nosuid,nodev 0 0\n"));
+
+        // Other tmpfs mounts are not mistaken for writable paths
+        let generated =
+            generated + "lxc.mount.entry = tmpfs dev/shm tmpfs rw,size=64m,create=dir 0 0\n";
+        let parsed = LxcConfig::parse("web", &generated);
+        assert!(parsed.readonly_rootfs);
+        assert_eq!(parsed.writable_paths, config.writable_paths);
+    }
+
+    #[test]
+    fn test_generate_swap_limit() {
+        const MIB: u64 = 1024 * 1024;
+        let generate =
+            |config: &ContainerConfig| LxcConfig::generate_for("web", config, CgroupVersion::V2);
+        let mut config = LxcConfig::parse("web", "");
+        config.memory_limit = Some(512 * MIB);
+        assert!(!generate(&config).contains("memory.swap.max"));
+
+        config.memory_swap_limit = Some(0);
+        let generated = generate(&config);
+        assert!(generated.contains("lxc.cgroup2.memory.swap.max = 0\n"));
+        assert_eq!(
+            LxcConfig::parse("web", &generated).memory_swap_limit,
+            Some(0)
+        );
+
+        config.memory_swap_limit = Some(1024 * MIB);
+        let generated = generate(&config);
+        assert!(generated.contains("lxc.cgroup2.memory.swap.max = 536870912\n"));
+        assert_eq!(
+            LxcConfig::parse("web", &generated).memory_swap_limit,
+            Some(1024 * MIB)
+        );
+    }
+
+    #[test]
+    fn test_generate_cgroup_v1_limits() {
+        const MIB: u64 = 1024 * 1024;
+        let mut config = LxcConfig::parse("web", "");
+        config.cpu_limit = Some(2);
+        config.memory_limit = Some(512 * MIB);
+        config.memory_swap_limit = Some(1024 * MIB);
+
+        let generated = LxcConfig::generate_for("web", &config, CgroupVersion::V1);
+        assert!(!generated.contains("lxc.cgroup2."));
+        assert!(generated.contains("lxc.cgroup.cpuset.cpus = 0-1\n"));
+        assert!(generated.contains("lxc.cgroup.memory.limit_in_bytes = 536870912\n"));
+        assert!(generated.contains("lxc.cgroup.memory.memsw.limit_in_bytes = 1073741824\n"));
+        let parsed = LxcConfig::parse("web", &generated);
+        assert_eq!(parsed.cpu_limit, Some(2));
+        assert_eq!(parsed.memory_limit, Some(512 * MIB));
+        assert_eq!(parsed.memory_swap_limit, Some(1024 * MIB));
+
+        // Without swap the combined limit equals the memory limit
+        config.memory_swap_limit = Some(0);
+        let generated = LxcConfig::generate_for("web", &config, CgroupVersion::V1);
+        assert!(generated.contains("lxc.cgroup.memory.memsw.limit_in_bytes = 536870912\n"));
+        assert_eq!(
+            LxcConfig::parse("web", &generated).memory_swap_limit,
+            Some(0)
+        );
+
+        let generated = LxcConfig::generate_for("web", &config, CgroupVersion::V2);
+        assert!(!generated.contains("lxc.cgroup."));
+        assert!(generated.contains("lxc.cgroup2.memory.max = 536870912\n"));
+    }
+
+    #[test]
+    fn test_cgroup_version_detection() {
+        let root = std::env::temp_dir().join(format!("cgroup_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        assert_eq!(CgroupVersion::detect_at(&root), CgroupVersion::V1);
+        fs::write(root.join("cgroup.controllers"), "cpuset cpu memory\n").unwrap();
+        assert_eq!(CgroupVersion::detect_at(&root), CgroupVersion::V2);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_health_check_round_trip_and_validation() {
+        let mut config = LxcConfig::parse("web", "");
+        let check = HealthCheck {
+            probe: HealthProbe::Exec {
+                command: vec!["pg_isready".to_string(), "-q".to_string()],
+            },
+            interval_secs: 10,
+            timeout_secs: 3,
+            failure_threshold: 2,
+            restart_on_unhealthy: true,
+        };
+        config.health_check = Some(check.clone());
+        assert!(LxcConfig::validate(&config).is_ok());
+        let generated = LxcConfig::generate("web", &config);
+        assert_eq!(
+            LxcConfig::parse("web", &generated).health_check,
+            Some(check.clone())
+        );
+
+        let invalid = [
+            HealthCheck {
+                probe: HealthProbe::Exec { command: vec![] },
+                ..check.clone()
+            },
+            HealthCheck {
+                probe: HealthProbe::Tcp { port: 0 },
+                ..check.clone()
+            },
+            HealthCheck {
+                timeout_secs: 30,
+                ..check.clone()
+            },
+            HealthCheck {
+                failure_threshold: 0,
+                ..check
+            },
+        ];
+        for check in invalid {
+            assert!(
+                LxcConfig::validate_health_check(&check).is_err(),
+                "{:?}",
+                check
+            );
+        }
+    }
+
+    #[test]
+    fn test_set_key_replaces_existing_value() {
+        let content = "lxc.uts.name = web\n\
+                       lxc.proc.oom_score_adj = 100\n\
+                       # lxc.proc.oom_score_adj = 7\n";
+
+        let updated = LxcConfig::set_key(content, "lxc.proc.oom_score_adj", "-500");
+        assert_eq!(
+            updated,
+            "lxc.uts.name = web\n# lxc.proc.oom_score_adj = 7\nlxc.proc.oom_score_adj = -500\n"
+        );
+    }
+
+    #[test]
+    fn test_count_cpus() {
+        assert_eq!(LxcConfig::count_cpus("0-3"), Some(4));
+        assert_eq!(LxcConfig::count_cpus("0,2,4-5"), Some(4));
+        assert_eq!(LxcConfig::count_cpus("1"), Some(1));
+        assert_eq!(LxcConfig::count_cpus("3-1"), None);
+        assert_eq!(LxcConfig::count_cpus(""), None);
+    }
+
+    #[test]
+    fn test_redact_sensitive_environment() {
+        let content = "lxc.uts.name = db\n\
+                       lxc.environment = DB_PASSWORD=hunter2\n\
+                       lxc.environment = app_secret=abc\n\
+                       lxc.environment = APP_ENV=prod\n";
+
+        let redacted = LxcConfig::redact(content);
+        assert!(!redacted.contains("hunter2"));
+        assert!(!redacted.contains("abc"));
+        assert!(redacted.contains("DB_PASSWORD=***REDACTED***"));
+        assert!(redacted.contains("APP_ENV=prod"));
+        assert!(redacted.contains("lxc.uts.name = db"));
+    }
+
+    #[test]
+    fn test_parse_mount_entries() {
+        let content = "lxc.uts.name = web\n\
+                       lxc.mount.entry = /srv/volumes/data srv/data none bind,create=dir 0 0\n\
+                       # lxc.mount.entry = /commented out none bind 0 0\n\
+                       lxc.mount.entry=/srv/volumes/logs var/log none bind\n\
+                       lxc.mount.entry = incomplete\n";
+
+        let mounts = LxcConfig::parse_mount_entries(content);
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[0].source, "/srv/volumes/data");
+        assert_eq!(mounts[0].target, "srv/data");
+        assert_eq!(mounts[0].options, "bind,create=dir");
+        assert_eq!(mounts[1].source, "/srv/volumes/logs");
+        assert_eq!(mounts[1].target, "var/log");
+    }
+
+    #[test]
+    fn test_remove_mount_entries() {
+        let content = "lxc.uts.name = web\n\
+                       lxc.mount.entry = /srv/volumes/data srv/data none bind 0 0\n\
+                       lxc.mount.entry = /srv/volumes/logs var/log none bind 0 0\n";
+
+        let content = LxcConfig::remove_mount_entries(content, "/srv/volumes/data");
+        assert_eq!(
+            content,
+            "lxc.uts.name = web\nlxc.mount.entry = /srv/volumes/logs var/log none bind 0 0\n"
+        );
+    }
 }
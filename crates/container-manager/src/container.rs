@@ -1,26 +1,302 @@
 use anyhow::Result;
 use chrono::Utc;
-use tracing::{error, info};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-use crate::config::LxcConfig;
+use crate::cgroup;
+use crate::config::{CgroupVersion, LxcConfig};
+use crate::dependencies::{self, DependencyGraph};
+use crate::downloads;
 use crate::error::ContainerError;
+use crate::events::EVENT_HISTORY;
+use crate::image_cache::{download_template_args, parse_image_list, ImageCache};
+use crate::locks::{OperationQueue, CONTAINER_LOCKS, OPERATION_LIMIT};
 use crate::lxc::LxcCommand;
-use models::{Container, ContainerConfig, ContainerStatus, CreateContainerRequest};
+use crate::rootfs::RootfsDir;
+use crate::snapshot::SnapshotManager;
+use models::{
+    Container, ContainerConfig, ContainerEvent, ContainerEventKind, ContainerMount,
+    ContainerNetworkInterface, ContainerStatus, ContainerUsage, CreateContainerRequest,
+    HealthStatus, ImageSpec, StopAllSummary, UpdateContainerRequest, Validate,
+};
+
+/// How long `start_with_dependencies` waits for a dependency to be running
+const DEPENDENCY_START_TIMEOUT: Duration = Duration::from_secs(60);
+const DEPENDENCY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Namespace container ids are derived in; changing it changes every id
+pub const CONTAINER_ID_NAMESPACE: Uuid = Uuid::from_u128(0x5b1e_9a3c_7d24_4f0e_8c61_2a9f_d03b_e847);
 
 pub struct ContainerManager;
 
+/// Limits given to new containers whose request leaves them out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct DefaultLimits {
+    /// CPU cores
+    pub cpu_limit: Option<u32>,
+    /// Bytes
+    pub memory_limit: Option<u64>,
+    /// Bytes
+    pub disk_limit: Option<u64>,
+}
+
+impl DefaultLimits {
+    /// Fill in the limits `config` leaves out
+    pub fn apply(&self, config: &mut ContainerConfig) {
+        config.cpu_limit = config.cpu_limit.or(self.cpu_limit);
+        config.memory_limit = config.memory_limit.or(self.memory_limit);
+        config.disk_limit = config.disk_limit.or(self.disk_limit);
+    }
+
+    /// Limits in `config` set higher than their default
+    pub fn exceeded_by(&self, config: &ContainerConfig) -> Vec<&'static str> {
+        let above = |requested: Option<u64>, default: Option<u64>| matches!((requested, default), (Some(r), Some(d)) if r > d);
+        [
+            (
+                "cpu_limit",
+                above(
+                    config.cpu_limit.map(u64::from),
+                    self.cpu_limit.map(u64::from),
+                ),
+            ),
+            (
+                "memory_limit",
+                above(config.memory_limit, self.memory_limit),
+            ),
+            ("disk_limit", above(config.disk_limit, self.disk_limit)),
+        ]
+        .into_iter()
+        .filter_map(|(name, above)| above.then_some(name))
+        .collect()
+    }
+}
+
+static DEFAULT_LIMITS: RwLock<DefaultLimits> = RwLock::new(DefaultLimits {
+    cpu_limit: None,
+    memory_limit: None,
+    disk_limit: None,
+});
+
+/// True when `path` and `other` live on the same filesystem, so a rename
+/// moves data between them without copying
+fn same_filesystem(path: &Path, other: &Path) -> Result<bool, ContainerError> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(std::fs::metadata(path)?.dev() == std::fs::metadata(other)?.dev())
+}
+
+/// Rename `source` to `target`, or copy it with rsync across filesystems
+async fn move_rootfs(source: &Path, target: &Path, rename: bool) -> Result<(), ContainerError> {
+    let parent = target.parent().expect("target is inside a pool");
+    std::fs::create_dir_all(parent)?;
+    if rename {
+        std::fs::rename(source, target)?;
+        return Ok(());
+    }
+
+    let source = format!("{}/", source.display());
+    let target_arg = format!("{}/", target.display());
+    let output = tokio::task::spawn_blocking(move || {
+        models::metrics::output(std::process::Command::new("rsync").args([
+            "-aHAX",
+            "--numeric-ids",
+            &source,
+            &target_arg,
+        ]))
+    })
+    .await
+    .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))??;
+    if !output.status.success() {
+        return Err(ContainerError::LxcCommandFailed(format!(
+            "rsync failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// `lxc-create` arguments for `template`, with its options after `--` in key
+/// order so the command line does not depend on map ordering
+fn create_args(name: &str, template: &str, options: &HashMap<String, String>) -> Vec<String> {
+    let mut args: Vec<String> = ["create", name, "-t", template]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    if !options.is_empty() {
+        args.push("--".to_string());
+        let mut keys: Vec<&String> = options.keys().collect();
+        keys.sort();
+        for key in keys {
+            args.push(format!("--{}", key));
+            args.push(options[key].clone());
+        }
+    }
+    args
+}
+
+/// Sidecar file in each container directory recording how it was created
+const PROVENANCE_FILE: &str = "orchestrator-provenance.json";
+
+/// Template and options a container was created with, kept next to its
+/// config so clones carry them along
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct Provenance {
+    pub template: String,
+    #[serde(default)]
+    pub template_options: HashMap<String, String>,
+    /// Architecture the rootfs was built for
+    #[serde(default)]
+    pub arch: Option<String>,
+}
+
+impl Default for Provenance {
+    fn default() -> Self {
+        Self {
+            template: "unknown".to_string(),
+            template_options: HashMap::new(),
+            arch: None,
+        }
+    }
+}
+
+impl Provenance {
+    /// An image request is recorded as the download options it resolves to;
+    /// templates without an `arch` option build for this node
+    fn of(request: &CreateContainerRequest) -> Self {
+        match &request.image {
+            Some(image) => Self {
+                template: "download".to_string(),
+                template_options: HashMap::from([
+                    ("dist".to_string(), image.distro.clone()),
+                    ("release".to_string(), image.release.clone()),
+                    ("arch".to_string(), image.arch.clone()),
+                ]),
+                arch: Some(models::normalize_arch(&image.arch)),
+            },
+            None => Self {
+                template: request.template.clone(),
+                template_options: request.template_options.clone(),
+                arch: Some(
+                    request
+                        .template_options
+                        .get("arch")
+                        .map_or_else(LxcConfig::arch, |arch| models::normalize_arch(arch)),
+                ),
+            },
+        }
+    }
+
+    /// Provenance recorded for container `name`, if any
+    pub(crate) fn read(name: &str) -> Option<Self> {
+        let path = LxcConfig::lxc_root().join(name).join(PROVENANCE_FILE);
+        let content = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub(crate) fn write(&self, name: &str) -> Result<()> {
+        let path = LxcConfig::lxc_root().join(name).join(PROVENANCE_FILE);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+/// Run `lxc-create` with the configured proxy and CA settings in its
+/// environment, plus `env`
+fn run_create(args: &[&str], env: &[(&str, &str)]) -> Result<String> {
+    let download_env = downloads::command_env();
+    let mut env = env.to_vec();
+    env.extend(
+        download_env
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str())),
+    );
+    LxcCommand::execute_with_env(args, &env)
+}
+
+/// Run `f` on the blocking pool and wait for it
+///
+/// `lxc-*` commands block the calling thread until they exit, which would
+/// stall every other task on the runtime; callers also rely on several
+/// operations running at once.
+pub(crate) async fn blocking<T, F>(f: F) -> Result<T, ContainerError>
+where
+    F: FnOnce() -> Result<T, ContainerError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| ContainerError::LxcCommandFailed(format!("LXC task failed: {}", e)))?
+}
+
+/// Error for a failed `lxc-create`, naming the proxy when it is to blame
+fn download_error(e: anyhow::Error) -> ContainerError {
+    let message = e.to_string();
+    downloads::explain_failure(&message).unwrap_or(ContainerError::LxcCommandFailed(message))
+}
+
 impl ContainerManager {
+    /// The id of the container called `name`
+    ///
+    /// Derived from the name alone (UUIDv5), so it is the same on every call
+    /// and every node without being stored anywhere.
+    pub fn container_id(name: &str) -> Uuid {
+        Uuid::new_v5(&CONTAINER_ID_NAMESPACE, name.as_bytes())
+    }
+
     /// Create a new container
     pub async fn create(request: CreateContainerRequest) -> Result<Container, ContainerError> {
-        let container_id = Uuid::new_v4();
+        Self::create_with_image_cache(request, None).await
+    }
+
+    /// Create a new container, letting the `download` template keep its
+    /// images in `image_cache`
+    ///
+    /// A cached image is used without contacting the image server. Otherwise
+    /// the image is first checked against the server's index, then downloaded
+    /// into the cache.
+    pub async fn create_with_image_cache(
+        mut request: CreateContainerRequest,
+        image_cache: Option<&ImageCache>,
+    ) -> Result<Container, ContainerError> {
+        request
+            .validate()
+            .map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
+        Self::default_limits().apply(&mut request.config);
         let name = &request.name;
+        LxcConfig::validate(&request.config).map_err(ContainerError::InvalidConfig)?;
+
+        // Held until lxc-create finishes so a concurrent create of the same
+        // name deterministically sees the container and fails
+        let _lock = CONTAINER_LOCKS.lock(name).await;
 
         // Check if container already exists
         if LxcCommand::exists(name) {
             return Err(ContainerError::AlreadyExists(name.to_string()));
         }
 
+        // Existing containers may already depend on this name
+        let mut graph = Self::dependency_graph()?;
+        graph.insert(name.clone(), request.config.depends_on.clone());
+        dependencies::check_acyclic(&graph)?;
+
+        let _permit = OPERATION_LIMIT
+            .acquire(&format!("create of {}", name))
+            .await?;
+
+        let cached = match (&request.image, image_cache) {
+            (Some(image), Some(cache)) => cache.is_cached(image),
+            _ => false,
+        };
+        if let (Some(ref image), false) = (&request.image, cached) {
+            Self::ensure_image_available(image, image_cache).await?;
+        }
+
         info!("Creating container: {}", name);
 
         // Create container directory structure
@@ -35,98 +311,1013 @@ impl ContainerManager {
         // Note: This is a simplified version - in production, you'd need to handle templates
         // For now, we'll create a basic container structure
         // The actual lxc-create command format may vary by LXC version
-        let create_result = LxcCommand::execute(&["create", name, "-t", &request.template]);
+        let _download = match cached {
+            true => None,
+            false => Some(downloads::acquire(&format!("download for {}", name)).await?),
+        };
+        let create_result = match request.image {
+            Some(ref image) => {
+                if cached {
+                    info!("Using cached image {}", ImageCache::image_id(image));
+                }
+                Self::run_download_template(
+                    name,
+                    image,
+                    image_cache.and_then(ImageCache::server),
+                    image_cache.map(ImageCache::path),
+                    cached,
+                )
+            }
+            None => {
+                let args = create_args(name, &request.template, &request.template_options);
+                run_create(&args.iter().map(String::as_str).collect::<Vec<_>>(), &[])
+            }
+        };
 
         match create_result {
             Ok(_) => {
                 info!("Container created successfully: {}", name);
+                let provenance = Provenance::of(&request);
+                if let Err(e) = provenance.write(name) {
+                    warn!("Failed to record provenance of {}: {}", name, e);
+                }
+                EVENT_HISTORY.record(
+                    name,
+                    ContainerEventKind::Created,
+                    Some(format!("template {}", provenance.template)),
+                );
                 Ok(Container {
-                    id: container_id,
+                    id: Self::container_id(name),
                     name: name.clone(),
                     status: ContainerStatus::Stopped,
-                    template: request.template,
+                    template: provenance.template,
                     node_id: None,
                     created_at: Utc::now(),
                     updated_at: Utc::now(),
+                    uptime_seconds: None,
+                    template_options: provenance.template_options,
+                    arch: provenance.arch,
+                    health: HealthStatus::Unknown,
                     config: request.config,
                 })
             }
             Err(e) => {
                 error!("Failed to create container: {}", e);
-                Err(ContainerError::LxcCommandFailed(e.to_string()))
+                Err(download_error(e))
             }
         }
     }
 
+    /// Allow at most `max` heavy operations (creates, clones, snapshots,
+    /// restores and destroys) to run at once
+    ///
+    /// Further requests queue until one finishes.
+    pub fn set_max_concurrent_ops(max: usize) {
+        OPERATION_LIMIT.set_max(max);
+    }
+
+    /// Fail heavy operations that waited `timeout` for a slot; `None` lets
+    /// them wait indefinitely
+    pub fn set_operation_queue_timeout(timeout: Option<Duration>) {
+        OPERATION_LIMIT.set_queue_timeout(timeout);
+    }
+
+    /// Heavy operations currently running
+    pub fn operations_in_flight() -> usize {
+        OPERATION_LIMIT.in_flight()
+    }
+
+    /// Heavy operations waiting for a slot
+    pub fn operations_queued() -> usize {
+        OPERATION_LIMIT.queued()
+    }
+
+    /// Heavy operations running and waiting, for display
+    pub fn operation_queue() -> OperationQueue {
+        OPERATION_LIMIT.queue()
+    }
+
+    /// Give containers created from now on `limits` where their request
+    /// sets none
+    pub fn set_default_limits(limits: DefaultLimits) {
+        *DEFAULT_LIMITS.write().unwrap() = limits;
+    }
+
+    /// Limits in effect for requests that leave them out
+    pub fn default_limits() -> DefaultLimits {
+        *DEFAULT_LIMITS.read().unwrap()
+    }
+
+    /// Apply the fields set in `request` to an existing container's config
+    ///
+    /// Takes effect on the next start; see [`Self::apply_limits_live`] for a
+    /// running container.
+    pub async fn update(
+        name: &str,
+        request: &UpdateContainerRequest,
+    ) -> Result<Container, ContainerError> {
+        {
+            let _lock = CONTAINER_LOCKS.lock(name).await;
+            let content = Self::updated_config(name, request)?;
+            LxcConfig::write_raw(name, &content)
+                .map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
+            info!("Updated container config: {}", name);
+        }
+
+        Self::get(name).await
+    }
+
+    /// The config `request` would give a container, without saving it
+    pub async fn preview_update(
+        name: &str,
+        request: &UpdateContainerRequest,
+    ) -> Result<ContainerConfig, ContainerError> {
+        let _lock = CONTAINER_LOCKS.lock(name).await;
+        let content = Self::updated_config(name, request)?;
+        Ok(LxcConfig::parse(name, &content))
+    }
+
+    /// Config file content with the fields set in `request` applied
+    fn updated_config(
+        name: &str,
+        request: &UpdateContainerRequest,
+    ) -> Result<String, ContainerError> {
+        if let Some(value) = request.oom_score_adj {
+            LxcConfig::validate_oom_score_adj(value).map_err(ContainerError::InvalidConfig)?;
+        }
+        if request.memory_limit == Some(0) {
+            return Err(ContainerError::InvalidConfig(
+                "memory_limit must be greater than 0".to_string(),
+            ));
+        }
+        if !LxcCommand::exists(name) {
+            return Err(ContainerError::NotFound(name.to_string()));
+        }
+
+        let mut content =
+            LxcConfig::read(name).map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
+        if let Some(value) = request.oom_score_adj {
+            content = LxcConfig::set_key(&content, "lxc.proc.oom_score_adj", &value.to_string());
+        }
+        let cgroup = LxcConfig::cgroup_version();
+        if let Some(value) = request.memory_limit {
+            content = LxcConfig::set_key(&content, cgroup.memory_key(), &value.to_string());
+        }
+        if request.memory_limit.is_some() || request.memory_swap_limit.is_some() {
+            // Swap is kept relative to the memory limit, so changing
+            // either one rewrites it
+            let current = LxcConfig::parse(name, &content);
+            if let Some(total) = request.memory_swap_limit.or(current.memory_swap_limit) {
+                models::validate::memory_swap_limit(current.memory_limit, total).map_err(|e| {
+                    ContainerError::InvalidConfig(format!("memory_swap_limit {}", e))
+                })?;
+                if let Some((key, value)) = cgroup.swap_entry(current.memory_limit, Some(total)) {
+                    content = LxcConfig::set_key(&content, key, &value.to_string());
+                }
+            }
+        }
+        Ok(content)
+    }
+
+    /// Write the CPU and memory limits of `config` into a running
+    /// container's cgroup, leaving its config file alone
+    ///
+    /// Needs cgroup v2 with the cpu, cpuset and memory controllers delegated
+    /// to the container's cgroup. Returns the files written.
+    pub async fn apply_limits_live(
+        name: &str,
+        config: &ContainerConfig,
+    ) -> Result<Vec<cgroup::LimitWrite>, ContainerError> {
+        let writes = cgroup::limit_writes(config)?;
+        if LxcConfig::cgroup_version() != CgroupVersion::V2 {
+            return Err(ContainerError::Unsupported(
+                "Applying limits to a running container needs cgroup v2".to_string(),
+            ));
+        }
+
+        let _lock = CONTAINER_LOCKS.lock(name).await;
+        if Self::status(name).await? != ContainerStatus::Running {
+            return Err(ContainerError::NotRunning(name.to_string()));
+        }
+        let root = cgroup::cgroup_root();
+        let dir = cgroup::container_cgroup(&root, name).ok_or_else(|| {
+            ContainerError::Unsupported(format!(
+                "No cgroup found for {} under {}",
+                name,
+                root.display()
+            ))
+        })?;
+        cgroup::apply(&dir, name, &writes)?;
+        info!("Applied {} live limits to container {}", writes.len(), name);
+        Ok(writes)
+    }
+
+    /// Images the download template can fetch from the configured server
+    pub async fn available_images(
+        image_cache: Option<&ImageCache>,
+    ) -> Result<Vec<ImageSpec>, ContainerError> {
+        let server = image_cache.and_then(ImageCache::server).map(str::to_string);
+        blocking(move || {
+            // The template needs a container name even when only listing
+            let scratch = format!("image-index-{}", Uuid::new_v4().simple());
+            let mut args = vec!["create", &scratch, "-t", "download", "--", "--list"];
+            if let Some(ref server) = server {
+                args.extend(["--server", server]);
+            }
+
+            let output = run_create(&args, &[]);
+            if LxcCommand::exists(&scratch) {
+                let _ = LxcCommand::execute(&["destroy", &scratch]);
+            }
+            let output = output.map_err(download_error)?;
+            Ok(parse_image_list(&output))
+        })
+        .await
+    }
+
+    /// Fail with `ImageNotFound` unless the image server offers `image`
+    pub async fn ensure_image_available(
+        image: &ImageSpec,
+        image_cache: Option<&ImageCache>,
+    ) -> Result<(), ContainerError> {
+        if Self::available_images(image_cache).await?.contains(image) {
+            Ok(())
+        } else {
+            Err(ContainerError::ImageNotFound(ImageCache::image_id(image)))
+        }
+    }
+
+    /// Download an image into the cache without keeping a container
+    ///
+    /// Returns true if the image was already cached. Callers are expected to
+    /// have checked the image with [`Self::ensure_image_available`].
+    pub async fn pull_image(image: &ImageSpec, cache: &ImageCache) -> Result<bool, ContainerError> {
+        image
+            .validate()
+            .map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
+        if cache.is_cached(image) {
+            cache.record_use(image)?;
+            return Ok(true);
+        }
+
+        let scratch = format!("image-pull-{}", Uuid::new_v4().simple());
+        let _download =
+            downloads::acquire(&format!("pull of {}", ImageCache::image_id(image))).await?;
+        info!(
+            "Pulling image {} into {}",
+            ImageCache::image_id(image),
+            cache.path().display()
+        );
+        let (spec, server, cache_path) = (
+            image.clone(),
+            cache.server().map(str::to_string),
+            cache.path().to_path_buf(),
+        );
+        blocking(move || {
+            let result = Self::run_download_template(
+                &scratch,
+                &spec,
+                server.as_deref(),
+                Some(&cache_path),
+                false,
+            );
+            if LxcCommand::exists(&scratch) {
+                if let Err(e) = LxcCommand::execute(&["destroy", &scratch]) {
+                    warn!("Failed to remove scratch container {}: {}", scratch, e);
+                }
+            }
+            result.map(|_| ()).map_err(download_error)
+        })
+        .await?;
+
+        cache.record_use(image)?;
+        Ok(false)
+    }
+
+    /// Run the download template for `image`, with `server` and the cache
+    /// directory taken from the image cache
+    fn run_download_template(
+        name: &str,
+        image: &ImageSpec,
+        server: Option<&str>,
+        cache_path: Option<&Path>,
+        force_cache: bool,
+    ) -> Result<String> {
+        let template_args = download_template_args(image, server, force_cache);
+        let mut args = vec!["create", name, "-t", "download", "--"];
+        args.extend(template_args.iter().map(String::as_str));
+
+        let cache_path = cache_path.map(|path| path.display().to_string());
+        let env: Vec<(&str, &str)> = cache_path
+            .iter()
+            .map(|path| ("LXC_CACHE_PATH", path.as_str()))
+            .collect();
+        run_create(&args, &env)
+    }
+
     /// Start a container
     pub async fn start(name: &str) -> Result<(), ContainerError> {
         info!("Starting container: {}", name);
 
+        let name = name.to_string();
+        blocking(move || {
+            if !LxcCommand::exists(&name) {
+                return Err(ContainerError::NotFound(name));
+            }
+
+            LxcCommand::execute(&["start", &name])
+                .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+            EVENT_HISTORY.record_state(&name, &ContainerStatus::Running, true, None);
+            Self::add_routes(&name);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Stop and start a container, recorded as one restart in its history
+    pub async fn restart(name: &str, reason: Option<String>) -> Result<(), ContainerError> {
+        info!("Restarting container: {}", name);
+
+        let name = name.to_string();
+        blocking(move || {
+            if !LxcCommand::exists(&name) {
+                return Err(ContainerError::NotFound(name));
+            }
+
+            LxcCommand::execute(&["stop", &name])
+                .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+            LxcCommand::execute(&["start", &name])
+                .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+            EVENT_HISTORY.record(&name, ContainerEventKind::Restarted, reason);
+            Self::add_routes(&name);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Add the static routes of a started container's interfaces; a route
+    /// that cannot be added is logged and the rest are still tried
+    fn add_routes(name: &str) {
+        let Ok(content) = LxcConfig::read(name) else {
+            return;
+        };
+        let config = LxcConfig::parse(name, &content);
+        for command in LxcConfig::route_commands(&config.network_interfaces) {
+            let command: Vec<&str> = command.iter().map(String::as_str).collect();
+            if let Err(e) = LxcCommand::attach(name, &command) {
+                warn!(
+                    "Failed to add route \"{}\" in container {}: {}",
+                    command.join(" "),
+                    name,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Start a container after everything it depends on, in dependency
+    /// order; returns the containers that were started
+    ///
+    /// Dependencies that are already running are left alone. Each one that
+    /// is started must reach `Running` before the next is started.
+    pub async fn start_with_dependencies(name: &str) -> Result<Vec<String>, ContainerError> {
         if !LxcCommand::exists(name) {
             return Err(ContainerError::NotFound(name.to_string()));
         }
 
-        LxcCommand::execute(&["start", name])
-            .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+        let graph = Self::dependency_graph()?;
+        let sequence = dependencies::start_sequence(&graph, name)?;
+        let mut started = Vec::new();
+        for container in sequence {
+            if Self::status(&container).await? == ContainerStatus::Running {
+                continue;
+            }
+            Self::start(&container).await?;
+            if container != name {
+                Self::wait_for_running(&container, DEPENDENCY_START_TIMEOUT).await?;
+            }
+            started.push(container);
+        }
+        Ok(started)
+    }
 
-        Ok(())
+    async fn wait_for_running(name: &str, timeout: Duration) -> Result<(), ContainerError> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let status = Self::status(name).await?;
+            if status == ContainerStatus::Running {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(ContainerError::LxcCommandFailed(format!(
+                    "{} did not reach running within {}s (state: {:?})",
+                    name,
+                    timeout.as_secs(),
+                    status
+                )));
+            }
+            tokio::time::sleep(DEPENDENCY_POLL_INTERVAL).await;
+        }
+    }
+
+    /// `depends_on` of every container, read from their configs
+    pub fn dependency_graph() -> Result<DependencyGraph, ContainerError> {
+        let names =
+            LxcCommand::list().map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+        Ok(names
+            .into_iter()
+            .map(|name| {
+                // A container without a readable config has no known dependencies
+                let depends_on = LxcConfig::read(&name)
+                    .map(|raw| LxcConfig::parse(&name, &raw).depends_on)
+                    .unwrap_or_default();
+                (name, depends_on)
+            })
+            .collect())
     }
 
     /// Stop a container
     pub async fn stop(name: &str) -> Result<(), ContainerError> {
         info!("Stopping container: {}", name);
 
+        let name = name.to_string();
+        blocking(move || {
+            if !LxcCommand::exists(&name) {
+                return Err(ContainerError::NotFound(name));
+            }
+
+            LxcCommand::execute(&["stop", &name])
+                .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+            EVENT_HISTORY.record_state(&name, &ContainerStatus::Stopped, true, None);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Stop a container, allowing `timeout_secs` for a clean shutdown before
+    /// it is killed
+    pub async fn stop_with_timeout(name: &str, timeout_secs: u64) -> Result<(), ContainerError> {
+        info!("Stopping container: {} (timeout {}s)", name, timeout_secs);
+
+        let name = name.to_string();
+        blocking(move || {
+            if !LxcCommand::exists(&name) {
+                return Err(ContainerError::NotFound(name));
+            }
+
+            LxcCommand::execute(&["stop", &name, "-t", &timeout_secs.to_string()])
+                .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+            EVENT_HISTORY.record_state(&name, &ContainerStatus::Stopped, true, None);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Stop every running or frozen container concurrently, giving each
+    /// `timeout` to shut down cleanly before it is killed
+    ///
+    /// Only the runtime state changes; `lxc.start.auto` is left as it is, so
+    /// the containers come back on the next start-all or host boot.
+    pub async fn stop_all(timeout: Duration) -> Result<StopAllSummary, ContainerError> {
+        let mut running = Vec::new();
+        for name in Self::list().await? {
+            // A container removed since it was listed needs no stopping
+            if let Ok(ContainerStatus::Running | ContainerStatus::Frozen) =
+                Self::status(&name).await
+            {
+                running.push(name);
+            }
+        }
+        info!(
+            "Stopping {} container(s) (timeout {}s)",
+            running.len(),
+            timeout.as_secs()
+        );
+
+        // LXC commands block, so each stop gets a thread of its own
+        let timeout_secs = timeout.as_secs().to_string();
+        let stops: Vec<_> = running
+            .into_iter()
+            .map(|name| {
+                let timeout_secs = timeout_secs.clone();
+                tokio::task::spawn_blocking(move || {
+                    let outcome = Self::stop_or_kill(&name, &timeout_secs);
+                    (name, outcome)
+                })
+            })
+            .collect();
+
+        let mut summary = StopAllSummary::default();
+        for stop in stops {
+            let (name, outcome) = stop
+                .await
+                .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+            match outcome {
+                Ok(false) => summary.stopped.push(name),
+                Ok(true) => summary.killed.push(name),
+                Err(e) => {
+                    error!("Failed to stop container {}: {}", name, e);
+                    summary.failed.insert(name, e.to_string());
+                }
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Ask the container to shut down, killing it if it is still running
+    /// after `timeout_secs`; returns whether it had to be killed
+    fn stop_or_kill(name: &str, timeout_secs: &str) -> Result<bool> {
+        if LxcCommand::execute(&["stop", name, "-t", timeout_secs, "--nokill"]).is_ok() {
+            EVENT_HISTORY.record_state(name, &ContainerStatus::Stopped, true, None);
+            return Ok(false);
+        }
+        warn!(
+            "Container {} did not stop within {}s, killing it",
+            name, timeout_secs
+        );
+        LxcCommand::execute(&["stop", name, "-k"])?;
+        EVENT_HISTORY.record_state(
+            name,
+            &ContainerStatus::Stopped,
+            true,
+            Some("killed after timeout".to_string()),
+        );
+        Ok(true)
+    }
+
+    /// Freeze (pause) all processes in a running container
+    pub async fn freeze(name: &str) -> Result<(), ContainerError> {
+        info!("Freezing container: {}", name);
+
+        let name = name.to_string();
+        blocking(move || {
+            if !LxcCommand::exists(&name) {
+                return Err(ContainerError::NotFound(name));
+            }
+
+            LxcCommand::execute(&["freeze", &name])
+                .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Resume a frozen container
+    pub async fn unfreeze(name: &str) -> Result<(), ContainerError> {
+        info!("Unfreezing container: {}", name);
+
+        let name = name.to_string();
+        blocking(move || {
+            if !LxcCommand::exists(&name) {
+                return Err(ContainerError::NotFound(name));
+            }
+
+            LxcCommand::execute(&["unfreeze", &name])
+                .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Get current resource usage of a container
+    pub async fn usage(name: &str) -> Result<ContainerUsage, ContainerError> {
+        let name = name.to_string();
+        blocking(move || {
+            if !LxcCommand::exists(&name) {
+                return Err(ContainerError::NotFound(name));
+            }
+
+            LxcCommand::usage(&name).map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))
+        })
+        .await
+    }
+
+    /// Host-side interfaces (veth peers) of a running container
+    pub async fn host_interfaces(name: &str) -> Result<Vec<String>, ContainerError> {
         if !LxcCommand::exists(name) {
             return Err(ContainerError::NotFound(name.to_string()));
         }
 
-        LxcCommand::execute(&["stop", name])
-            .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+        LxcCommand::links(name).map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))
+    }
 
+    /// Seconds a container has been running, `None` when it is not
+    pub async fn uptime(name: &str) -> Result<Option<u64>, ContainerError> {
+        if !LxcCommand::exists(name) {
+            return Err(ContainerError::NotFound(name.to_string()));
+        }
+
+        LxcCommand::uptime(name).map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))
+    }
+
+    /// Run `command` inside a running container; fails unless it exits 0
+    ///
+    /// Does not look the container up first, so frequent callers such as
+    /// health probes cost a single `lxc-attach`.
+    pub async fn run_command(name: &str, command: &[String]) -> Result<String, ContainerError> {
+        let (name, command) = (name.to_string(), command.to_vec());
+        blocking(move || {
+            let command: Vec<&str> = command.iter().map(String::as_str).collect();
+            LxcCommand::attach(&name, &command)
+                .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))
+        })
+        .await
+    }
+
+    /// Addresses of a running container
+    pub async fn addresses(name: &str) -> Result<Vec<IpAddr>, ContainerError> {
+        let name = name.to_string();
+        blocking(move || {
+            LxcCommand::ips(&name).map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))
+        })
+        .await
+    }
+
+    /// PID of a running container's init process, for entering its namespaces
+    pub async fn init_pid(name: &str) -> Result<u32, ContainerError> {
+        if !LxcCommand::exists(name) {
+            return Err(ContainerError::NotFound(name.to_string()));
+        }
+
+        LxcCommand::pid(name).map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))
+    }
+
+    /// Add a network interface to the container's config
+    ///
+    /// A running container only gets the interface on its next start unless
+    /// it is also plugged in live.
+    pub async fn add_interface(
+        name: &str,
+        interface: &ContainerNetworkInterface,
+    ) -> Result<(), ContainerError> {
+        let _lock = CONTAINER_LOCKS.lock(name).await;
+        if !LxcCommand::exists(name) {
+            return Err(ContainerError::NotFound(name.to_string()));
+        }
+
+        let content =
+            LxcConfig::read(name).map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
+        if LxcConfig::parse(name, &content)
+            .network_interfaces
+            .iter()
+            .any(|existing| existing.name == interface.name)
+        {
+            return Err(ContainerError::InterfaceExists(interface.name.clone()));
+        }
+        LxcConfig::write_raw(name, &LxcConfig::add_interface(&content, interface))
+            .map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
+        info!("Added interface {} to container {}", interface.name, name);
         Ok(())
     }
 
+    /// Remove a network interface from the container's config, returning it
+    pub async fn remove_interface(
+        name: &str,
+        interface: &str,
+    ) -> Result<ContainerNetworkInterface, ContainerError> {
+        let _lock = CONTAINER_LOCKS.lock(name).await;
+        if !LxcCommand::exists(name) {
+            return Err(ContainerError::NotFound(name.to_string()));
+        }
+
+        let content =
+            LxcConfig::read(name).map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
+        let removed = LxcConfig::parse(name, &content)
+            .network_interfaces
+            .into_iter()
+            .find(|existing| existing.name == interface)
+            .ok_or_else(|| ContainerError::InterfaceNotFound(interface.to_string()))?;
+        let updated = LxcConfig::remove_interface(&content, interface)
+            .ok_or_else(|| ContainerError::InterfaceNotFound(interface.to_string()))?;
+        LxcConfig::write_raw(name, &updated)
+            .map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
+        info!("Removed interface {} from container {}", interface, name);
+        Ok(removed)
+    }
+
+    /// Map host-side veth names to the running container owning them
+    ///
+    /// Containers that stop or vanish while being inspected are skipped.
+    pub async fn interface_owners() -> Result<HashMap<String, String>, ContainerError> {
+        let mut owners = HashMap::new();
+        for name in Self::list().await? {
+            if !matches!(Self::status(&name).await, Ok(ContainerStatus::Running)) {
+                continue;
+            }
+            let links = {
+                let name = name.clone();
+                blocking(move || {
+                    LxcCommand::links(&name)
+                        .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))
+                })
+                .await
+            };
+            match links {
+                Ok(links) => {
+                    for link in links {
+                        owners.insert(link, name.clone());
+                    }
+                }
+                Err(e) => debug!("Could not read links of container {}: {}", name, e),
+            }
+        }
+        Ok(owners)
+    }
+
+    /// Read the raw LXC configuration file along with its parsed form
+    pub async fn effective_config(name: &str) -> Result<(String, ContainerConfig), ContainerError> {
+        let name = name.to_string();
+        blocking(move || {
+            if !LxcCommand::exists(&name) {
+                return Err(ContainerError::NotFound(name));
+            }
+
+            let raw =
+                LxcConfig::read(&name).map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
+            let config = LxcConfig::parse(&name, &raw);
+            Ok((raw, config))
+        })
+        .await
+    }
+
+    /// Write secret values into the container's rootfs as root-only files
+    /// under `/etc/orchestrator/secrets`, replacing any previous contents
+    pub async fn write_secrets(
+        name: &str,
+        secrets: &[(String, Vec<u8>)],
+    ) -> Result<(), ContainerError> {
+        if !LxcCommand::exists(name) {
+            return Err(ContainerError::NotFound(name.to_string()));
+        }
+
+        let content =
+            LxcConfig::read(name).map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
+        let rootfs = PathBuf::from(LxcConfig::parse(name, &content).rootfs_path);
+
+        // The rootfs belongs to the container, so never follow its links
+        let dir =
+            RootfsDir::open(&rootfs, &["etc", "orchestrator"], 0o755)?.subdir("secrets", 0o700)?;
+        dir.clear()?;
+        dir.set_mode(0o700)?;
+        for (secret_name, value) in secrets {
+            dir.create_file(secret_name, value, 0o400)?;
+        }
+
+        info!("Wrote {} secret(s) into container {}", secrets.len(), name);
+        Ok(())
+    }
+
+    /// Write the container's `/etc/resolv.conf` from its DNS settings,
+    /// using `default_servers` when it has none of its own
+    ///
+    /// Nothing is written when neither provides a nameserver, leaving the
+    /// image's own resolver configuration in place.
+    pub async fn write_resolv_conf(
+        name: &str,
+        default_servers: &[String],
+    ) -> Result<(), ContainerError> {
+        let (_, config) = Self::effective_config(name).await?;
+        let servers = if config.dns_servers.is_empty() {
+            default_servers
+        } else {
+            &config.dns_servers
+        };
+        if servers.is_empty() {
+            return Ok(());
+        }
+
+        // Images often link resolv.conf to a resolver's runtime file; replace
+        // the link rather than write through it, possibly onto the host
+        RootfsDir::open(Path::new(&config.rootfs_path), &["etc"], 0o755)?.replace_file(
+            "resolv.conf",
+            LxcConfig::resolv_conf(servers, &config.search_domains).as_bytes(),
+            0o644,
+        )?;
+
+        info!("Wrote resolv.conf for container {}", name);
+        Ok(())
+    }
+
+    /// List bind mounts (attached volumes) from the container's configuration
+    pub async fn mounts(name: &str) -> Result<Vec<ContainerMount>, ContainerError> {
+        if !LxcCommand::exists(name) {
+            return Err(ContainerError::NotFound(name.to_string()));
+        }
+
+        let content =
+            LxcConfig::read(name).map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
+        Ok(LxcConfig::parse_mount_entries(&content))
+    }
+
+    /// Move the container's rootfs into the storage pool at `target_pool`,
+    /// returning its new path
+    ///
+    /// A running container is stopped for the move and started again
+    /// afterwards. Within one filesystem the rootfs is renamed; otherwise it
+    /// is copied with rsync, after checking the pool has room for it, and
+    /// the old copy is removed once the config points at the new one.
+    pub async fn move_storage(name: &str, target_pool: &Path) -> Result<PathBuf, ContainerError> {
+        let _lock = CONTAINER_LOCKS.lock(name).await;
+        if !LxcCommand::exists(name) {
+            return Err(ContainerError::NotFound(name.to_string()));
+        }
+
+        let content =
+            LxcConfig::read(name).map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
+        let source = PathBuf::from(LxcConfig::parse(name, &content).rootfs_path);
+        let target = target_pool.join(name).join("rootfs");
+        if source == target {
+            return Err(ContainerError::InvalidConfig(format!(
+                "{} is already in {}",
+                name,
+                target_pool.display()
+            )));
+        }
+        if target.exists() {
+            return Err(ContainerError::AlreadyExists(target.display().to_string()));
+        }
+        if !target_pool.is_dir() {
+            return Err(ContainerError::InvalidConfig(format!(
+                "storage pool {} does not exist",
+                target_pool.display()
+            )));
+        }
+
+        let same_filesystem = same_filesystem(&source, target_pool)?;
+        if !same_filesystem {
+            let needed = SnapshotManager::get_directory_size(&source)
+                .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+            let stats = nix::sys::statvfs::statvfs(target_pool)
+                .map_err(|e| ContainerError::Io(e.into()))?;
+            let available = stats.blocks_available() as u64 * stats.fragment_size() as u64;
+            if needed > available {
+                return Err(ContainerError::InsufficientSpace {
+                    path: target_pool.display().to_string(),
+                    needed,
+                    available,
+                });
+            }
+        }
+
+        let was_running = LxcCommand::state(name)
+            .map(|state| LxcCommand::parse_state(&state) == ContainerStatus::Running)
+            .unwrap_or(false);
+        if was_running {
+            info!("Stopping {} to move its rootfs", name);
+            LxcCommand::execute(&["stop", name])
+                .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+        }
+
+        info!(
+            "Moving rootfs of {} from {} to {}",
+            name,
+            source.display(),
+            target.display()
+        );
+        let mut moved = move_rootfs(&source, &target, same_filesystem)
+            .await
+            .and_then(|_| {
+                let updated = LxcConfig::set_key(
+                    &content,
+                    "lxc.rootfs.path",
+                    &format!("dir:{}", target.display()),
+                );
+                LxcConfig::write_raw(name, &updated)
+                    .map_err(|e| ContainerError::InvalidConfig(e.to_string()))
+            });
+        match &moved {
+            Ok(()) if !same_filesystem => {
+                if let Err(e) = std::fs::remove_dir_all(&source) {
+                    warn!(
+                        "Moved {} but could not remove its old rootfs {}: {}",
+                        name,
+                        source.display(),
+                        e
+                    );
+                }
+            }
+            Ok(()) => {}
+            // The config still points at the old rootfs, so put it back
+            Err(e) => {
+                error!("Failed to move rootfs of {}: {}", name, e);
+                if !same_filesystem {
+                    let _ = std::fs::remove_dir_all(&target);
+                } else if target.exists() {
+                    let _ = std::fs::rename(&target, &source);
+                }
+            }
+        }
+
+        // Started again either way; after a failed move it runs as before
+        if was_running {
+            if let Err(e) = LxcCommand::execute(&["start", name]) {
+                error!("Failed to restart {} after moving its rootfs: {}", name, e);
+                moved = moved.and(Err(ContainerError::LxcCommandFailed(e.to_string())));
+            }
+        }
+        moved.map(|_| target)
+    }
+
     /// Delete a container
     pub async fn delete(name: &str) -> Result<(), ContainerError> {
+        Self::delete_after(name, || async { true })
+            .await
+            .map(|_| ())
+    }
+
+    /// Run `cleanup` and then delete the container, all under its lock
+    ///
+    /// The container is only destroyed when `cleanup` returns true; the
+    /// result says whether it was.
+    pub async fn delete_after<F, Fut>(name: &str, cleanup: F) -> Result<bool, ContainerError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = bool>,
+    {
         info!("Deleting container: {}", name);
 
+        let _lock = CONTAINER_LOCKS.lock(name).await;
+
         if !LxcCommand::exists(name) {
             return Err(ContainerError::NotFound(name.to_string()));
         }
+        if !cleanup().await {
+            return Ok(false);
+        }
 
         // Stop container first if running
         let _ = Self::stop(name).await;
 
+        let _permit = OPERATION_LIMIT
+            .acquire(&format!("destroy of {}", name))
+            .await?;
         LxcCommand::execute(&["destroy", "-f", name])
             .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+        EVENT_HISTORY.record(name, ContainerEventKind::Deleted, None);
 
-        Ok(())
+        Ok(true)
     }
 
-    /// Get container status
-    pub async fn status(name: &str) -> Result<ContainerStatus, ContainerError> {
-        if !LxcCommand::exists(name) {
-            return Err(ContainerError::NotFound(name.to_string()));
-        }
+    /// Lifecycle events of a container, oldest first
+    pub fn events(name: &str) -> Vec<ContainerEvent> {
+        EVENT_HISTORY.events(name)
+    }
 
-        let state =
-            LxcCommand::state(name).map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+    /// Keep `capacity` events per container, persisted to `path` if given
+    pub fn configure_events(capacity: usize, path: Option<&Path>) -> std::io::Result<()> {
+        EVENT_HISTORY.configure(capacity, path)
+    }
 
-        let status = match state.as_str() {
-            "running" => ContainerStatus::Running,
-            "stopped" => ContainerStatus::Stopped,
-            "starting" => ContainerStatus::Starting,
-            "stopping" => ContainerStatus::Stopping,
-            "frozen" => ContainerStatus::Frozen,
-            _ => ContainerStatus::Error,
+    /// Record an OOM event if the running container's cgroup counted OOM
+    /// kills since the last check; cgroup v1 hosts are not checked
+    pub fn check_oom_kills(name: &str) {
+        if LxcConfig::cgroup_version() != CgroupVersion::V2 {
+            return;
+        }
+        let Some(dir) = cgroup::container_cgroup(&cgroup::cgroup_root(), name) else {
+            return;
         };
+        if let Some(count) = cgroup::oom_kills(&dir) {
+            EVENT_HISTORY.record_oom_kills(name, count);
+        }
+    }
 
-        Ok(status)
+    /// Remove the bind mount of `source` from the container's config; the
+    /// mounted directory is left alone
+    ///
+    /// Does not take the container's lock, so it can run inside
+    /// [`Self::delete_after`].
+    pub fn detach_mount(name: &str, source: &str) -> Result<(), ContainerError> {
+        let content =
+            LxcConfig::read(name).map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
+        LxcConfig::write_raw(name, &LxcConfig::remove_mount_entries(&content, source))
+            .map_err(|e| ContainerError::InvalidConfig(e.to_string()))
+    }
+
+    /// Get container status
+    pub async fn status(name: &str) -> Result<ContainerStatus, ContainerError> {
+        let name = name.to_string();
+        blocking(move || {
+            if !LxcCommand::exists(&name) {
+                return Err(ContainerError::NotFound(name));
+            }
+
+            let state = LxcCommand::state(&name)
+                .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+            Ok(LxcCommand::parse_state(&state))
+        })
+        .await
     }
 
     /// List all containers
     pub async fn list() -> Result<Vec<String>, ContainerError> {
-        LxcCommand::list().map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))
+        blocking(|| LxcCommand::list().map_err(|e| ContainerError::LxcCommandFailed(e.to_string())))
+            .await
+    }
+
+    /// Whether a container with this name exists
+    pub fn exists(name: &str) -> bool {
+        LxcCommand::exists(name)
     }
 
     /// Get container information
@@ -136,31 +1327,27 @@ impl ContainerManager {
         }
 
         let status = Self::status(name).await?;
-        let _config_str =
+        let config_str =
             LxcConfig::read(name).map_err(|e| ContainerError::InvalidConfig(e.to_string()))?;
-
-        // Parse config to get ContainerConfig
-        // This is simplified - in production, you'd properly parse the LXC config
-        let config = ContainerConfig {
-            cpu_limit: None,
-            memory_limit: None,
-            disk_limit: None,
-            network_interfaces: vec![],
-            rootfs_path: format!(
-                "{}/rootfs",
-                crate::config::LxcConfig::lxc_root().join(name).display()
-            ),
-            environment: vec![],
+        let config = LxcConfig::parse(name, &config_str);
+        let uptime_seconds = match status {
+            ContainerStatus::Running => LxcCommand::uptime(name).ok().flatten(),
+            _ => None,
         };
+        let provenance = Provenance::read(name).unwrap_or_default();
 
         Ok(Container {
-            id: Uuid::new_v4(), // In production, store this in a database
+            id: Self::container_id(name),
             name: name.to_string(),
             status,
-            template: "unknown".to_string(), // Parse from config
+            template: provenance.template,
             node_id: None,
             created_at: Utc::now(), // Parse from filesystem
             updated_at: Utc::now(),
+            uptime_seconds,
+            template_options: provenance.template_options,
+            arch: provenance.arch,
+            health: HealthStatus::Unknown,
             config,
         })
     }
@@ -0,0 +1,170 @@
+//! Boot ordering between containers declared with `depends_on`
+use std::collections::{BTreeMap, HashSet};
+use thiserror::Error;
+
+/// Container names mapped to the containers they depend on
+pub type DependencyGraph = BTreeMap<String, Vec<String>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DependencyError {
+    /// Names along the cycle, starting and ending with the same container
+    #[error("dependency cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+
+    #[error("{container} depends on unknown container {dependency}")]
+    Unknown {
+        container: String,
+        dependency: String,
+    },
+}
+
+/// `name` preceded by everything it depends on, each dependency after its
+/// own dependencies
+pub fn start_sequence(graph: &DependencyGraph, name: &str) -> Result<Vec<String>, DependencyError> {
+    let mut walk = Walk::new(graph, true);
+    walk.visit(name)?;
+    Ok(walk.order)
+}
+
+/// Every container in the graph, each after its dependencies; dependencies
+/// on containers outside the graph are ignored
+pub fn sort(graph: &DependencyGraph) -> Result<Vec<String>, DependencyError> {
+    let mut walk = Walk::new(graph, false);
+    for name in graph.keys() {
+        walk.visit(name)?;
+    }
+    Ok(walk.order)
+}
+
+/// Reject cycles anywhere in the graph; dependencies on containers that do
+/// not exist yet are allowed
+pub fn check_acyclic(graph: &DependencyGraph) -> Result<(), DependencyError> {
+    sort(graph).map(drop)
+}
+
+/// Depth-first walk emitting containers after their dependencies
+struct Walk<'a> {
+    graph: &'a DependencyGraph,
+    /// Whether a dependency missing from the graph is an error
+    strict: bool,
+    done: HashSet<&'a str>,
+    path: Vec<&'a str>,
+    order: Vec<String>,
+}
+
+impl<'a> Walk<'a> {
+    fn new(graph: &'a DependencyGraph, strict: bool) -> Self {
+        Self {
+            graph,
+            strict,
+            done: HashSet::new(),
+            path: Vec::new(),
+            order: Vec::new(),
+        }
+    }
+
+    fn visit(&mut self, name: &str) -> Result<(), DependencyError> {
+        let Some((name, dependencies)) = self.graph.get_key_value(name) else {
+            return Ok(());
+        };
+        if self.done.contains(name.as_str()) {
+            return Ok(());
+        }
+        if let Some(start) = self.path.iter().position(|n| *n == name) {
+            let mut cycle: Vec<String> = self.path[start..].iter().map(|n| n.to_string()).collect();
+            cycle.push(name.clone());
+            return Err(DependencyError::Cycle(cycle));
+        }
+
+        self.path.push(name);
+        for dependency in dependencies {
+            if self.strict && !self.graph.contains_key(dependency) {
+                return Err(DependencyError::Unknown {
+                    container: name.clone(),
+                    dependency: dependency.clone(),
+                });
+            }
+            self.visit(dependency)?;
+        }
+        self.path.pop();
+
+        self.done.insert(name);
+        self.order.push(name.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &[&str])]) -> DependencyGraph {
+        edges
+            .iter()
+            .map(|(name, deps)| {
+                (
+                    name.to_string(),
+                    deps.iter().map(|d| d.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dependencies_start_first() {
+        // app needs db and cache, both of which need the volume helper
+        let graph = graph(&[
+            ("app", &["db", "cache"]),
+            ("db", &["volumes"]),
+            ("cache", &["volumes"]),
+            ("volumes", &[]),
+            ("unrelated", &[]),
+        ]);
+
+        assert_eq!(
+            start_sequence(&graph, "app").unwrap(),
+            ["volumes", "db", "cache", "app"]
+        );
+        assert_eq!(start_sequence(&graph, "db").unwrap(), ["volumes", "db"]);
+        assert_eq!(
+            sort(&graph).unwrap(),
+            ["volumes", "db", "cache", "app", "unrelated"]
+        );
+    }
+
+    #[test]
+    fn test_cycles_are_detected() {
+        let graph = graph(&[
+            ("app", &["db"]),
+            ("db", &["proxy"]),
+            ("proxy", &["app"]),
+            ("other", &[]),
+        ]);
+
+        assert_eq!(
+            check_acyclic(&graph),
+            Err(DependencyError::Cycle(
+                ["app", "db", "proxy", "app"].map(String::from).to_vec()
+            ))
+        );
+        assert!(matches!(
+            start_sequence(&graph, "proxy"),
+            Err(DependencyError::Cycle(_))
+        ));
+    }
+
+    #[test]
+    fn test_unknown_dependencies() {
+        let graph = graph(&[("app", &["db"])]);
+
+        // Fine while validating; db may be created later
+        assert!(check_acyclic(&graph).is_ok());
+        assert_eq!(
+            start_sequence(&graph, "app"),
+            Err(DependencyError::Unknown {
+                container: "app".to_string(),
+                dependency: "db".to_string(),
+            })
+        );
+    }
+}
@@ -1,22 +1,56 @@
 use thiserror::Error;
 
+use crate::dependencies::DependencyError;
+
 #[derive(Debug, Error)]
 pub enum ContainerError {
     #[error("Container not found: {0}")]
     NotFound(String),
 
+    #[error("Image not found: {0}")]
+    ImageNotFound(String),
+
     #[error("Container already exists: {0}")]
     AlreadyExists(String),
 
+    #[error("Container is not running: {0}")]
+    NotRunning(String),
+
     #[error("LXC command failed: {0}")]
     LxcCommandFailed(String),
 
+    #[error("Interface not found: {0}")]
+    InterfaceNotFound(String),
+
+    #[error("Interface already exists: {0}")]
+    InterfaceExists(String),
+
     #[error("Invalid configuration: {0}")]
     InvalidConfig(String),
 
+    /// The installed LXC cannot perform the operation
+    #[error("{0}")]
+    Unsupported(String),
+
+    #[error("Not enough space in {path}: {needed} bytes needed, {available} available")]
+    InsufficientSpace {
+        path: String,
+        needed: u64,
+        available: u64,
+    },
+
+    #[error("Download failed via proxy {proxy}: {message}")]
+    DownloadFailed { proxy: String, message: String },
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
     #[error("Parse error: {0}")]
     Parse(String),
+
+    #[error("Timed out after {waited_secs}s waiting for a slot to run {operation}")]
+    QueueTimeout { operation: String, waited_secs: u64 },
+
+    #[error("Dependency error: {0}")]
+    Dependency(#[from] DependencyError),
 }
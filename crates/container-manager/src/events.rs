@@ -0,0 +1,253 @@
+/// Per-container lifecycle history
+///
+/// The managers record what they do to a container, and the LXC monitor
+/// records state changes nobody here asked for, such as a container that
+/// stopped on its own. Each container keeps its latest events in a bounded
+/// ring; the history can be persisted so it survives restarts.
+use chrono::Utc;
+use models::{ContainerEvent, ContainerEventKind, ContainerStatus};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use tracing::warn;
+
+/// Events kept per container unless configured
+pub const DEFAULT_EVENTS_PER_CONTAINER: usize = 100;
+
+/// The history the managers record into
+pub(crate) static EVENT_HISTORY: LazyLock<EventHistory> =
+    LazyLock::new(|| EventHistory::new(DEFAULT_EVENTS_PER_CONTAINER));
+
+pub struct EventHistory {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    capacity: usize,
+    events: HashMap<String, VecDeque<ContainerEvent>>,
+    /// OOM kills last read from each container's cgroup
+    oom_kills: HashMap<String, u64>,
+    path: Option<PathBuf>,
+}
+
+impl EventHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                capacity: capacity.max(1),
+                events: HashMap::new(),
+                oom_kills: HashMap::new(),
+                path: None,
+            }),
+        }
+    }
+
+    /// Keep `capacity` events per container, and persist them to `path` if
+    /// given, loading whatever it already holds
+    pub fn configure(&self, capacity: usize, path: Option<&Path>) -> std::io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.capacity = capacity.max(1);
+        inner.path = path.map(Path::to_path_buf);
+        if let Some(path) = path {
+            match std::fs::read(path) {
+                Ok(content) => {
+                    inner.events = serde_json::from_slice(&content).map_err(|e| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!("Invalid event history {}: {}", path.display(), e),
+                        )
+                    })?
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let capacity = inner.capacity;
+        for events in inner.events.values_mut() {
+            while events.len() > capacity {
+                events.pop_front();
+            }
+        }
+        Ok(())
+    }
+
+    /// Events of container `name`, oldest first
+    pub fn events(&self, name: &str) -> Vec<ContainerEvent> {
+        self.inner
+            .lock()
+            .unwrap()
+            .events
+            .get(name)
+            .map(|events| events.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn record(&self, name: &str, kind: ContainerEventKind, detail: Option<String>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.push(name, kind, detail);
+    }
+
+    /// Record that container `name` is now in `state`, unless its latest
+    /// start or stop already says so
+    ///
+    /// Only running and stopped are lifecycle events. A container seen for
+    /// the first time (`seen_before` false) with no history is not recorded,
+    /// so a restarted server does not log every container it finds.
+    pub fn record_state(
+        &self,
+        name: &str,
+        state: &ContainerStatus,
+        seen_before: bool,
+        detail: Option<String>,
+    ) {
+        let kind = match state {
+            ContainerStatus::Running => ContainerEventKind::Started,
+            ContainerStatus::Stopped => ContainerEventKind::Stopped,
+            _ => return,
+        };
+
+        let mut inner = self.inner.lock().unwrap();
+        let last = inner.events.get(name).and_then(|events| {
+            events.iter().rev().find_map(|event| match event.kind {
+                ContainerEventKind::Started | ContainerEventKind::Restarted => {
+                    Some(ContainerEventKind::Started)
+                }
+                ContainerEventKind::Stopped
+                | ContainerEventKind::Created
+                | ContainerEventKind::Deleted => Some(ContainerEventKind::Stopped),
+                _ => None,
+            })
+        });
+        match last {
+            None if !seen_before => {}
+            Some(last) if last == kind => {}
+            _ => inner.push(name, kind, detail),
+        }
+    }
+
+    /// Record an OOM event when the cgroup's OOM kill counter went up since
+    /// it was last read
+    ///
+    /// The first reading only sets the baseline; a counter that went down
+    /// belongs to a new cgroup, after the container was restarted.
+    pub fn record_oom_kills(&self, name: &str, count: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let previous = inner.oom_kills.insert(name.to_string(), count);
+        let Some(previous) = previous else {
+            return;
+        };
+        let new_kills = if count < previous {
+            count
+        } else {
+            count - previous
+        };
+        if new_kills > 0 {
+            inner.push(
+                name,
+                ContainerEventKind::Oom,
+                Some(format!("{} process(es) killed", new_kills)),
+            );
+        }
+    }
+}
+
+impl Inner {
+    fn push(&mut self, name: &str, kind: ContainerEventKind, detail: Option<String>) {
+        let capacity = self.capacity;
+        let events = self.events.entry(name.to_string()).or_default();
+        if events.len() >= capacity {
+            events.pop_front();
+        }
+        events.push_back(ContainerEvent {
+            timestamp: Utc::now(),
+            kind,
+            detail,
+        });
+        if kind == ContainerEventKind::Deleted {
+            self.oom_kills.remove(name);
+        }
+        self.save();
+    }
+
+    fn save(&self) {
+        let Some(ref path) = self.path else {
+            return;
+        };
+        let result = serde_json::to_vec(&self.events)
+            .map_err(std::io::Error::other)
+            .and_then(|content| {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                let tmp = path.with_extension("tmp");
+                std::fs::write(&tmp, content)?;
+                std::fs::rename(&tmp, path)
+            });
+        if let Err(e) = result {
+            warn!("Failed to save event history to {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(history: &EventHistory, name: &str) -> Vec<ContainerEventKind> {
+        history.events(name).into_iter().map(|e| e.kind).collect()
+    }
+
+    #[test]
+    fn test_history_is_bounded_and_deduplicates_states() {
+        let history = EventHistory::new(3);
+        history.record_state("web", &ContainerStatus::Stopped, false, None);
+        assert!(history.events("web").is_empty());
+
+        history.record("web", ContainerEventKind::Created, None);
+        history.record_state("web", &ContainerStatus::Stopped, true, None);
+        history.record_state("web", &ContainerStatus::Running, true, None);
+        history.record_state("web", &ContainerStatus::Running, true, None);
+        history.record_state("web", &ContainerStatus::Frozen, true, None);
+        assert_eq!(
+            kinds(&history, "web"),
+            [ContainerEventKind::Created, ContainerEventKind::Started]
+        );
+
+        history.record_oom_kills("web", 2);
+        history.record_oom_kills("web", 3);
+        history.record_oom_kills("web", 3);
+        history.record("web", ContainerEventKind::Snapshotted, None);
+        assert_eq!(
+            kinds(&history, "web"),
+            [
+                ContainerEventKind::Started,
+                ContainerEventKind::Oom,
+                ContainerEventKind::Snapshotted
+            ]
+        );
+        assert_eq!(
+            history.events("web")[1].detail.as_deref(),
+            Some("1 process(es) killed")
+        );
+    }
+
+    #[test]
+    fn test_history_persists() {
+        let dir = std::env::temp_dir().join(format!("container-events-{}", std::process::id()));
+        let path = dir.join("events.json");
+
+        let history = EventHistory::new(10);
+        history.configure(10, Some(&path)).unwrap();
+        history.record(
+            "web",
+            ContainerEventKind::Created,
+            Some("alpine".to_string()),
+        );
+
+        let reloaded = EventHistory::new(10);
+        reloaded.configure(10, Some(&path)).unwrap();
+        assert_eq!(reloaded.events("web"), history.events("web"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
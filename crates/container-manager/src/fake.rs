@@ -0,0 +1,354 @@
+/// In-process stand-in for the `lxc-*` tools, for development without LXC
+///
+/// Containers are records in memory; state changes follow LXC's rules
+/// (starting a running container fails, and so on) and usage counters grow
+/// with the time a container has been running, so the whole API can be
+/// exercised without root. Configs, snapshots and rootfs directories are
+/// still written under the LXC root, and containers found there are picked
+/// up again as stopped.
+use anyhow::{anyhow, bail, Result};
+use models::ContainerStatus;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::config::LxcConfig;
+
+/// Version reported by `lxc-ls --version`
+pub const FAKE_LXC_VERSION: &str = "5.0.0";
+
+/// Memory every running fake container reports, plus 1 MiB per index
+const BASE_MEMORY_BYTES: u64 = 64 * 1024 * 1024;
+
+struct FakeContainer {
+    state: ContainerStatus,
+    started: Option<Instant>,
+    snapshots: Vec<String>,
+    /// Stable per container; picks its address and link name
+    index: u32,
+}
+
+#[derive(Default)]
+pub struct FakeLxc {
+    containers: Mutex<BTreeMap<String, FakeContainer>>,
+}
+
+impl FakeLxc {
+    /// A fake holding the containers that already have a directory with a
+    /// config under the LXC root
+    pub fn new() -> Self {
+        let fake = Self::default();
+        let root = LxcConfig::lxc_root();
+        let Ok(entries) = std::fs::read_dir(&root) else {
+            return fake;
+        };
+        let mut names: Vec<String> = entries
+            .flatten()
+            .filter(|entry| entry.path().join("config").is_file())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        names.sort();
+        for name in names {
+            let snapshots = snapshot_names(&root.join(&name).join("snaps"));
+            fake.insert(&name, snapshots);
+        }
+        fake
+    }
+
+    fn insert(&self, name: &str, snapshots: Vec<String>) {
+        let mut containers = self.containers.lock().unwrap();
+        let index = containers.values().map(|c| c.index).max().unwrap_or(0) + 1;
+        containers.insert(
+            name.to_string(),
+            FakeContainer {
+                state: ContainerStatus::Stopped,
+                started: None,
+                snapshots,
+                index,
+            },
+        );
+    }
+
+    /// Answer `lxc-<args[0]> <args[1..]>` as the real tool would
+    pub fn execute(&self, args: &[&str]) -> Result<String> {
+        let (command, args) = args
+            .split_first()
+            .ok_or_else(|| anyhow!("No command specified"))?;
+        match *command {
+            "ls" if args.contains(&"--version") => Ok(format!("{}\n", FAKE_LXC_VERSION)),
+            "ls" => Ok(self
+                .containers
+                .lock()
+                .unwrap()
+                .keys()
+                .map(|name| format!("{}\n", name))
+                .collect()),
+            "info" => self.info(last_name(args)?),
+            "create" => {
+                let name = option(args, "-n")
+                    .or_else(|| args.iter().copied().find(|arg| !arg.starts_with('-')))
+                    .ok_or_else(|| anyhow!("lxc-create: no container name"))?;
+                if self.containers.lock().unwrap().contains_key(name) {
+                    bail!("Container {} already exists", name);
+                }
+                self.insert(name, Vec::new());
+                Ok(String::new())
+            }
+            "start" => self.transition(first_name(args)?, |state| match state {
+                ContainerStatus::Stopped => Ok(ContainerStatus::Running),
+                _ => Err("is already running"),
+            }),
+            "stop" => self.transition(first_name(args)?, |state| match state {
+                ContainerStatus::Stopped => Err("is not running"),
+                _ => Ok(ContainerStatus::Stopped),
+            }),
+            "freeze" => self.transition(first_name(args)?, |state| match state {
+                ContainerStatus::Running => Ok(ContainerStatus::Frozen),
+                _ => Err("is not running"),
+            }),
+            "unfreeze" => self.transition(first_name(args)?, |state| match state {
+                ContainerStatus::Frozen => Ok(ContainerStatus::Running),
+                _ => Err("is not frozen"),
+            }),
+            "destroy" => {
+                let name = last_name(args)?;
+                if self.containers.lock().unwrap().remove(name).is_none() {
+                    bail!("Container {} does not exist", name);
+                }
+                let _ = std::fs::remove_dir_all(LxcConfig::lxc_root().join(name));
+                Ok(String::new())
+            }
+            "snapshot" => self.snapshot(args),
+            "copy" | "clone" => self.copy(command, args),
+            "attach" => {
+                let name = option(args, "-n").ok_or_else(|| anyhow!("lxc-attach: no name"))?;
+                match self.containers.lock().unwrap().get(name) {
+                    Some(c) if c.state == ContainerStatus::Running => Ok(String::new()),
+                    Some(_) => bail!("Container {} is not running", name),
+                    None => bail!("Container {} does not exist", name),
+                }
+            }
+            other => bail!("lxc-{} is not supported by the fake backend", other),
+        }
+    }
+
+    fn transition(
+        &self,
+        name: &str,
+        next: impl FnOnce(&ContainerStatus) -> std::result::Result<ContainerStatus, &str>,
+    ) -> Result<String> {
+        let mut containers = self.containers.lock().unwrap();
+        let container = containers
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("Container {} does not exist", name))?;
+        let state = next(&container.state).map_err(|e| anyhow!("{} {}", name, e))?;
+        match (&container.state, &state) {
+            (ContainerStatus::Stopped, ContainerStatus::Running) => {
+                container.started = Some(Instant::now())
+            }
+            (_, ContainerStatus::Stopped) => container.started = None,
+            _ => {}
+        }
+        container.state = state;
+        Ok(String::new())
+    }
+
+    fn info(&self, name: &str) -> Result<String> {
+        let containers = self.containers.lock().unwrap();
+        let container = containers
+            .get(name)
+            .ok_or_else(|| anyhow!("{} doesn't exist", name))?;
+        let state = match container.state {
+            ContainerStatus::Running => "RUNNING",
+            ContainerStatus::Frozen => "FROZEN",
+            _ => "STOPPED",
+        };
+        let mut output = format!("Name:           {}\nState:          {}\n", name, state);
+        let Some(started) = container.started else {
+            return Ok(output);
+        };
+
+        let secs = started.elapsed().as_secs();
+        output.push_str(&format!(
+            "PID:            {}\n\
+             IP:             10.0.3.{}\n\
+             CPU use:        {}\n\
+             Memory use:     {}\n\
+             KMem use:       0\n\
+             Link:           vethfake{}\n \
+             TX bytes:      {}\n \
+             RX bytes:      {}\n",
+            std::process::id(),
+            (container.index % 253) + 2,
+            // A twentieth of a core
+            secs * 50_000_000,
+            BASE_MEMORY_BYTES + u64::from(container.index) * 1024 * 1024,
+            container.index,
+            secs * 1024,
+            secs * 4096,
+        ));
+        Ok(output)
+    }
+
+    fn snapshot(&self, args: &[&str]) -> Result<String> {
+        let name = last_name(args)?;
+        let snaps = LxcConfig::lxc_root().join(name).join("snaps");
+        let mut containers = self.containers.lock().unwrap();
+        let container = containers
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("Container {} does not exist", name))?;
+
+        if args.contains(&"-L") {
+            return Ok(container
+                .snapshots
+                .iter()
+                .map(|snap| format!("{} ({})\n", snap, snaps.join(snap).display()))
+                .collect());
+        }
+        if let Some(snap) = option(args, "-d") {
+            let position = container
+                .snapshots
+                .iter()
+                .position(|s| s == snap)
+                .ok_or_else(|| anyhow!("Snapshot {} of {} does not exist", snap, name))?;
+            container.snapshots.remove(position);
+            let _ = std::fs::remove_dir_all(snaps.join(snap));
+            return Ok(String::new());
+        }
+        if let Some(snap) = option(args, "-r") {
+            if !container.snapshots.iter().any(|s| s == snap) {
+                bail!("Snapshot {} of {} does not exist", snap, name);
+            }
+            return Ok(String::new());
+        }
+        let snap = match option(args, "-n") {
+            Some(snap) => snap.to_string(),
+            None => format!("snap{}", container.snapshots.len()),
+        };
+        if container.snapshots.contains(&snap) {
+            bail!("Snapshot {} of {} already exists", snap, name);
+        }
+        std::fs::create_dir_all(snaps.join(&snap))?;
+        container.snapshots.push(snap);
+        Ok(String::new())
+    }
+
+    /// `lxc-copy -P <root>/<src>/snaps -n <snap> -p <root> -N <new>`, or the
+    /// `lxc-clone -o <snap> -n <new>` equivalent
+    fn copy(&self, command: &str, args: &[&str]) -> Result<String> {
+        let (snap, new) = match command {
+            "copy" => (option(args, "-n"), option(args, "-N")),
+            _ => (option(args, "-o"), option(args, "-n")),
+        };
+        let (snap, new) = snap
+            .zip(new)
+            .ok_or_else(|| anyhow!("lxc-{}: missing source or new name", command))?;
+        let source = option(args, "-P")
+            .map(Path::new)
+            .and_then(Path::parent)
+            .and_then(Path::file_name)
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("lxc-{}: missing snapshot path", command))?;
+
+        {
+            let containers = self.containers.lock().unwrap();
+            let container = containers
+                .get(source)
+                .ok_or_else(|| anyhow!("Container {} does not exist", source))?;
+            if !container.snapshots.iter().any(|s| s == snap) {
+                bail!("Snapshot {} of {} does not exist", snap, source);
+            }
+            if containers.contains_key(new) {
+                bail!("Container {} already exists", new);
+            }
+        }
+
+        let root = LxcConfig::lxc_root();
+        std::fs::create_dir_all(root.join(new).join("rootfs"))?;
+        if let Ok(config) = std::fs::read_to_string(root.join(source).join("config")) {
+            let config = config
+                .lines()
+                .map(|line| match line.split_once('=') {
+                    Some((key, _)) if key.trim() == "lxc.uts.name" => {
+                        format!("lxc.uts.name = {}\n", new)
+                    }
+                    _ => format!(
+                        "{}\n",
+                        line.replace(&format!("/{}/", source), &format!("/{}/", new))
+                    ),
+                })
+                .collect::<String>();
+            std::fs::write(root.join(new).join("config"), config)?;
+        }
+        self.insert(new, Vec::new());
+        Ok(String::new())
+    }
+}
+
+/// Value following `flag` in `args`
+fn option<'a>(args: &[&'a str], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| *arg == flag)
+        .and_then(|i| args.get(i + 1).copied())
+}
+
+/// The container named by commands taking it first (`lxc-stop <name> -t 10`)
+fn first_name<'a>(args: &[&'a str]) -> Result<&'a str> {
+    args.first()
+        .copied()
+        .filter(|arg| !arg.starts_with('-'))
+        .ok_or_else(|| anyhow!("No container name given"))
+}
+
+/// The container named by commands taking it last (`lxc-info -H <name>`)
+fn last_name<'a>(args: &[&'a str]) -> Result<&'a str> {
+    args.last()
+        .copied()
+        .filter(|arg| !arg.starts_with('-'))
+        .ok_or_else(|| anyhow!("No container name given"))
+}
+
+fn snapshot_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_transitions_follow_lxc() {
+        let fake = FakeLxc::default();
+        fake.execute(&["create", "-n", "web", "-t", "alpine"])
+            .unwrap();
+        assert!(fake.execute(&["create", "-n", "web"]).is_err());
+        assert!(fake.execute(&["stop", "web"]).is_err());
+        assert!(fake
+            .execute(&["attach", "-n", "web", "--", "true"])
+            .is_err());
+
+        fake.execute(&["start", "web"]).unwrap();
+        assert!(fake.execute(&["start", "web"]).is_err());
+        let info = fake.execute(&["info", "web"]).unwrap();
+        assert!(info.contains("State:          RUNNING"));
+        assert!(info.contains("Memory use:"));
+
+        fake.execute(&["freeze", "web"]).unwrap();
+        assert!(fake.execute(&["freeze", "web"]).is_err());
+        fake.execute(&["unfreeze", "web"]).unwrap();
+        fake.execute(&["stop", "web", "-t", "10"]).unwrap();
+        let info = fake.execute(&["info", "web"]).unwrap();
+        assert!(info.contains("STOPPED") && !info.contains("PID"));
+
+        assert_eq!(fake.execute(&["ls"]).unwrap(), "web\n");
+    }
+}
@@ -0,0 +1,452 @@
+/// Local cache of distribution images used by the `download` template
+///
+/// The cache directory is handed to `lxc-create` as `LXC_CACHE_PATH`, so the
+/// download template stores and reuses rootfs tarballs under
+/// `<root>/download/<distro>/<release>/<arch>/default`. A manifest next to
+/// them tracks size, checksum and last use for listing and LRU eviction.
+/// Images are fetched from the default image server or a configured mirror.
+///
+/// Distro, release and arch each name a directory, so they are validated
+/// before any path is built from them, including for manifest entries.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::error::ContainerError;
+use crate::snapshot::SnapshotManager;
+use models::validate::image_component;
+use models::ImageSpec;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const ROOTFS_TARBALL: &str = "rootfs.tar.xz";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CachedImage {
+    /// `<distro>:<release>:<arch>`; none of them may contain a colon, so
+    /// the id is unambiguous
+    pub id: String,
+    pub distro: String,
+    pub release: String,
+    pub arch: String,
+    pub size_bytes: u64,
+    pub last_used: DateTime<Utc>,
+    /// SHA-256 of the rootfs tarball
+    pub checksum: Option<String>,
+}
+
+pub struct ImageCache {
+    root: PathBuf,
+    max_bytes: u64,
+    /// Image server (or local mirror) passed to the download template
+    server: Option<String>,
+    /// Serializes manifest read-modify-write cycles
+    manifest_lock: Mutex<()>,
+}
+
+impl ImageCache {
+    pub fn new(root: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            root,
+            max_bytes,
+            server: None,
+            manifest_lock: Mutex::new(()),
+        }
+    }
+
+    /// Download images from `server` instead of the template's default
+    pub fn with_server(mut self, server: Option<String>) -> Self {
+        self.server = server;
+        self
+    }
+
+    /// Directory to pass to the download template as `LXC_CACHE_PATH`
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn server(&self) -> Option<&str> {
+        self.server.as_deref()
+    }
+
+    pub fn image_id(spec: &ImageSpec) -> String {
+        format!("{}:{}:{}", spec.distro, spec.release, spec.arch)
+    }
+
+    fn image_dir(
+        &self,
+        distro: &str,
+        release: &str,
+        arch: &str,
+    ) -> Result<PathBuf, ContainerError> {
+        for (name, value) in [("distro", distro), ("release", release), ("arch", arch)] {
+            image_component(value).map_err(|e| {
+                ContainerError::InvalidConfig(format!("Invalid image {}: {}", name, e))
+            })?;
+        }
+        Ok(self
+            .root
+            .join("download")
+            .join(distro)
+            .join(release)
+            .join(arch)
+            .join("default"))
+    }
+
+    /// Whether the download template will find this image locally
+    pub fn is_cached(&self, spec: &ImageSpec) -> bool {
+        self.image_dir(&spec.distro, &spec.release, &spec.arch)
+            .is_ok_and(|dir| dir.join(ROOTFS_TARBALL).is_file())
+    }
+
+    /// Record that an image was just used, adding it to the manifest if new
+    pub fn record_use(&self, spec: &ImageSpec) -> Result<CachedImage, ContainerError> {
+        let _guard = self.manifest_lock.lock().unwrap();
+        let mut manifest = self.read_manifest()?;
+
+        let id = Self::image_id(spec);
+        let dir = self.image_dir(&spec.distro, &spec.release, &spec.arch)?;
+        let size_bytes = SnapshotManager::get_directory_size(&dir)
+            .map_err(|e| ContainerError::Parse(e.to_string()))?;
+
+        let image = match manifest.iter_mut().find(|image| image.id == id) {
+            Some(image) => {
+                image.size_bytes = size_bytes;
+                image.last_used = Utc::now();
+                image.clone()
+            }
+            None => {
+                let image = CachedImage {
+                    id,
+                    distro: spec.distro.clone(),
+                    release: spec.release.clone(),
+                    arch: spec.arch.clone(),
+                    size_bytes,
+                    last_used: Utc::now(),
+                    checksum: Self::checksum(&dir.join(ROOTFS_TARBALL)).ok(),
+                };
+                manifest.push(image.clone());
+                image
+            }
+        };
+
+        self.write_manifest(&manifest)?;
+        Ok(image)
+    }
+
+    /// Cached images, most recently used first
+    pub fn list(&self) -> Result<Vec<CachedImage>, ContainerError> {
+        let _guard = self.manifest_lock.lock().unwrap();
+        let mut images = self.read_manifest()?;
+        images.sort_by_key(|image| std::cmp::Reverse(image.last_used));
+        Ok(images)
+    }
+
+    /// Remove an image from the cache
+    pub fn remove(&self, id: &str) -> Result<CachedImage, ContainerError> {
+        let _guard = self.manifest_lock.lock().unwrap();
+        let mut manifest = self.read_manifest()?;
+
+        let pos = manifest
+            .iter()
+            .position(|image| image.id == id)
+            .ok_or_else(|| ContainerError::ImageNotFound(id.to_string()))?;
+        let image = manifest.remove(pos);
+        self.delete_files(&image)?;
+        self.write_manifest(&manifest)?;
+
+        info!("Removed cached image {}", image.id);
+        Ok(image)
+    }
+
+    /// Evict least recently used images until the cache fits its size cap;
+    /// returns the evicted images
+    pub fn evict(&self) -> Result<Vec<CachedImage>, ContainerError> {
+        let _guard = self.manifest_lock.lock().unwrap();
+        let mut manifest = self.read_manifest()?;
+        manifest.sort_by_key(|image| image.last_used);
+
+        let mut total: u64 = manifest.iter().map(|image| image.size_bytes).sum();
+        let mut evicted = Vec::new();
+        while total > self.max_bytes && !manifest.is_empty() {
+            let image = manifest.remove(0);
+            if let Err(e) = self.delete_files(&image) {
+                warn!("Failed to evict cached image {}: {}", image.id, e);
+                manifest.insert(0, image);
+                break;
+            }
+            total -= image.size_bytes;
+            info!(
+                "Evicted cached image {} ({} bytes, last used {})",
+                image.id, image.size_bytes, image.last_used
+            );
+            evicted.push(image);
+        }
+
+        if !evicted.is_empty() {
+            self.write_manifest(&manifest)?;
+        }
+        Ok(evicted)
+    }
+
+    fn delete_files(&self, image: &CachedImage) -> Result<(), ContainerError> {
+        let dir = self.image_dir(&image.distro, &image.release, &image.arch)?;
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(ContainerError::Io(e)),
+        }
+    }
+
+    fn read_manifest(&self) -> Result<Vec<CachedImage>, ContainerError> {
+        match std::fs::read(self.root.join(MANIFEST_FILE)) {
+            Ok(bytes) => {
+                let mut manifest: Vec<CachedImage> = serde_json::from_slice(&bytes)
+                    .map_err(|e| ContainerError::Parse(e.to_string()))?;
+                // Entries written with the earlier `-` separated ids
+                for image in &mut manifest {
+                    image.id = format!("{}:{}:{}", image.distro, image.release, image.arch);
+                }
+                Ok(manifest)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(ContainerError::Io(e)),
+        }
+    }
+
+    fn write_manifest(&self, manifest: &[CachedImage]) -> Result<(), ContainerError> {
+        std::fs::create_dir_all(&self.root)?;
+        let json = serde_json::to_vec_pretty(manifest)
+            .map_err(|e| ContainerError::Parse(e.to_string()))?;
+        let path = self.root.join(MANIFEST_FILE);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    fn checksum(path: &Path) -> std::io::Result<String> {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buf = [0u8; 64 * 1024];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+        Ok(hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect())
+    }
+}
+
+/// Arguments for the download template after `--`
+///
+/// `force_cache` makes the template use a cached image even if it has
+/// expired, so a cache hit never touches the network.
+pub(crate) fn download_template_args(
+    spec: &ImageSpec,
+    server: Option<&str>,
+    force_cache: bool,
+) -> Vec<String> {
+    let mut args = vec![
+        "-d".to_string(),
+        spec.distro.clone(),
+        "-r".to_string(),
+        spec.release.clone(),
+        "-a".to_string(),
+        spec.arch.clone(),
+    ];
+    if let Some(server) = server {
+        args.extend(["--server".to_string(), server.to_string()]);
+    }
+    if force_cache {
+        args.push("--force-cache".to_string());
+    }
+    args
+}
+
+/// Parse the image index printed by `lxc-create -t download -- --list`
+///
+/// Only the `default` variant is returned, since that is what gets cached.
+pub(crate) fn parse_image_list(output: &str) -> Vec<ImageSpec> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [distro, release, arch, "default", ..] if *distro != "DIST" => Some(ImageSpec {
+                    distro: distro.to_string(),
+                    release: release.to_string(),
+                    arch: arch.to_string(),
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(distro: &str, release: &str) -> ImageSpec {
+        ImageSpec {
+            distro: distro.to_string(),
+            release: release.to_string(),
+            arch: "arm64".to_string(),
+        }
+    }
+
+    /// Simulate the download template populating the cache
+    fn download(cache: &ImageCache, spec: &ImageSpec, size: usize) {
+        let dir = cache
+            .image_dir(&spec.distro, &spec.release, &spec.arch)
+            .unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(ROOTFS_TARBALL), vec![0u8; size]).unwrap();
+    }
+
+    fn temp_cache(max_bytes: u64) -> ImageCache {
+        let root = std::env::temp_dir().join(format!("image_cache_{}", uuid::Uuid::new_v4()));
+        ImageCache::new(root, max_bytes)
+    }
+
+    #[test]
+    fn test_record_use_and_list() {
+        let cache = temp_cache(u64::MAX);
+        let alpine = spec("alpine", "3.19");
+        assert!(!cache.is_cached(&alpine));
+
+        download(&cache, &alpine, 100);
+        assert!(cache.is_cached(&alpine));
+
+        let image = cache.record_use(&alpine).unwrap();
+        assert_eq!(image.id, "alpine:3.19:arm64");
+        assert_eq!(image.size_bytes, 100);
+        assert_eq!(image.checksum.as_deref().map(str::len), Some(64));
+
+        // A second use refreshes the entry instead of duplicating it
+        cache.record_use(&alpine).unwrap();
+        assert_eq!(cache.list().unwrap().len(), 1);
+
+        cache.remove("alpine:3.19:arm64").unwrap();
+        assert!(!cache.is_cached(&alpine));
+        assert!(cache.list().unwrap().is_empty());
+        assert!(matches!(
+            cache.remove("alpine:3.19:arm64"),
+            Err(ContainerError::ImageNotFound(_))
+        ));
+
+        let _ = std::fs::remove_dir_all(cache.path());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_over_cap() {
+        let cache = temp_cache(250);
+        let (alpine, debian, ubuntu) = (
+            spec("alpine", "3.19"),
+            spec("debian", "bookworm"),
+            spec("ubuntu", "noble"),
+        );
+        for image in [&alpine, &debian, &ubuntu] {
+            download(&cache, image, 100);
+            cache.record_use(image).unwrap();
+        }
+        // alpine becomes the most recently used
+        cache.record_use(&alpine).unwrap();
+
+        let evicted: Vec<String> = cache.evict().unwrap().into_iter().map(|i| i.id).collect();
+        assert_eq!(evicted, ["debian:bookworm:arm64"]);
+        assert!(!cache.is_cached(&debian));
+        assert!(cache.is_cached(&alpine));
+        assert!(cache.is_cached(&ubuntu));
+
+        // Within the cap: nothing more to evict
+        assert!(cache.evict().unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(cache.path());
+    }
+
+    #[test]
+    fn test_manifest_entries_cannot_walk_out_of_the_cache() {
+        let cache = temp_cache(0);
+        // `<root>/download/../..` is the directory holding the cache
+        let sibling = format!("image_cache_outside_{}", uuid::Uuid::new_v4().simple());
+        let outside = cache.path().parent().unwrap().join(&sibling);
+        std::fs::create_dir_all(outside.join("default")).unwrap();
+        std::fs::create_dir_all(cache.path()).unwrap();
+        std::fs::write(
+            cache.path().join(MANIFEST_FILE),
+            serde_json::json!([{
+                "id": "..-..-arm64",
+                "distro": "..",
+                "release": "..",
+                "arch": sibling,
+                "size_bytes": 100,
+                "last_used": Utc::now(),
+                "checksum": null
+            }])
+            .to_string(),
+        )
+        .unwrap();
+
+        // Ids are rebuilt from the components, with the new separator
+        let id = format!("..:..:{}", sibling);
+        assert_eq!(cache.list().unwrap()[0].id, id);
+        assert!(matches!(
+            cache.remove(&id),
+            Err(ContainerError::InvalidConfig(_))
+        ));
+        assert!(cache.evict().unwrap().is_empty());
+        assert!(outside.join("default").is_dir());
+        assert!(!cache.is_cached(&spec("..", "..")));
+
+        let _ = std::fs::remove_dir_all(cache.path());
+        let _ = std::fs::remove_dir_all(&outside);
+    }
+
+    #[test]
+    fn test_download_args_and_image_list() {
+        let alpine = spec("alpine", "3.19");
+        assert_eq!(
+            download_template_args(&alpine, Some("https://mirror.lan"), true),
+            [
+                "-d",
+                "alpine",
+                "-r",
+                "3.19",
+                "-a",
+                "arm64",
+                "--server",
+                "https://mirror.lan",
+                "--force-cache"
+            ]
+        );
+
+        let output = "Downloading the image index\n\
+                      ---\n\
+                      DIST\tRELEASE\tARCH\tVARIANT\tBUILD\n\
+                      ---\n\
+                      alpine\t3.19\tarm64\tdefault\t20240101_13:00\n\
+                      alpine\t3.19\tarm64\tcloud\t20240101_13:00\n\
+                      debian\tbookworm\tamd64\tdefault\t20240101_05:24\n\
+                      ---\n";
+        let images = parse_image_list(output);
+        assert_eq!(
+            images,
+            vec![alpine, {
+                let mut debian = spec("debian", "bookworm");
+                debian.arch = "amd64".to_string();
+                debian
+            }]
+        );
+    }
+}
@@ -1,11 +1,24 @@
+pub mod cgroup;
 pub mod config;
 pub mod container;
+pub mod dependencies;
+pub mod downloads;
 pub mod error;
+pub mod events;
+pub mod fake;
+pub mod image_cache;
+pub mod locks;
 pub mod lxc;
+pub mod monitor;
+pub mod rootfs;
 pub mod snapshot;
 
 pub use container::*;
+pub use dependencies::{DependencyError, DependencyGraph};
 pub use error::*;
+pub use image_cache::*;
+pub use lxc::{LxcVersion, PrivilegeMode, PrivilegeProbe};
+pub use monitor::{LxcMonitor, MonitorHealth, MonitorMode};
 pub use snapshot::*;
 
 #[cfg(test)]
@@ -18,6 +31,8 @@ mod tests {
         let request = CreateContainerRequest {
             name: "test-container".to_string(),
             template: "alpine".to_string(),
+            image: None,
+            template_options: Default::default(),
             config: ContainerConfig {
                 cpu_limit: Some(2),
                 memory_limit: Some(1024 * 1024 * 1024), // 1GB
@@ -28,12 +43,16 @@ mod tests {
                     ipv4: Some("192.168.1.100/24".to_string()),
                     ipv6: None,
                     mac: None,
+                    gateway_v4: None,
+                    gateway_v6: None,
+                    routes: vec![],
                 }],
                 rootfs_path: "/var/lib/lxc/test-container/rootfs".to_string(),
                 environment: vec![
                     ("USER".to_string(), "root".to_string()),
                     ("HOME".to_string(), "/root".to_string()),
                 ],
+                ..Default::default()
             },
         };
 
@@ -43,36 +62,6 @@ mod tests {
         assert!(request.config.memory_limit.is_some());
     }
 
-    #[tokio::test]
-    async fn test_container_name_validation() {
-        // Valid container names
-        let valid_names = vec!["test", "test-container", "test123", "web-server"];
-        for name in valid_names {
-            assert!(
-                is_valid_container_name(name),
-                "Name '{}' should be valid",
-                name
-            );
-        }
-
-        // Invalid container names
-        let invalid_names = vec![
-            "",
-            "Test",
-            "test_container",
-            "test container",
-            "test.",
-            ".test",
-        ];
-        for name in invalid_names {
-            assert!(
-                !is_valid_container_name(name),
-                "Name '{}' should be invalid",
-                name
-            );
-        }
-    }
-
     #[test]
     fn test_lxc_command_parsing() {
         let command = ["list", "-1"];
@@ -97,22 +86,6 @@ mod tests {
         }
     }
 
-    // Helper function for container name validation
-    fn is_valid_container_name(name: &str) -> bool {
-        if name.is_empty() || name.len() > 64 {
-            return false;
-        }
-
-        // Must start with alphanumeric
-        if !name.chars().next().unwrap_or('_').is_ascii_alphanumeric() {
-            return false;
-        }
-
-        // Can only contain lowercase letters, numbers, and hyphens
-        name.chars()
-            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
-    }
-
     // Helper function for parsing container states
     fn parse_container_state(state: &str) -> models::ContainerStatus {
         match state.to_lowercase().as_str() {
@@ -0,0 +1,322 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard, OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, warn};
+
+use crate::error::ContainerError;
+
+/// Heavy container operations allowed to run at once unless configured
+pub const DEFAULT_MAX_CONCURRENT_OPS: usize = 4;
+
+/// How long a heavy operation waits for a slot unless configured
+pub const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Per-container locks serializing lifecycle operations that must not race
+/// (existence check followed by create or destroy)
+pub(crate) static CONTAINER_LOCKS: LazyLock<KeyedLock> = LazyLock::new(KeyedLock::default);
+
+/// Limit on concurrent heavy operations (create, clone, snapshot) across all
+/// containers
+pub(crate) static OPERATION_LIMIT: LazyLock<OperationLimit> = LazyLock::new(|| {
+    let limit = OperationLimit::new(DEFAULT_MAX_CONCURRENT_OPS);
+    limit.set_queue_timeout(Some(DEFAULT_QUEUE_TIMEOUT));
+    limit
+});
+
+/// A map of async mutexes keyed by name
+///
+/// Operations on the same key serialize; different keys proceed in parallel.
+/// Entries are removed once no holder or waiter remains, so the map only
+/// grows with the number of names being operated on concurrently.
+#[derive(Default)]
+pub struct KeyedLock {
+    locks: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+}
+
+impl KeyedLock {
+    /// Wait for exclusive access to `key`
+    pub async fn lock(&self, key: &str) -> KeyedLockGuard<'_> {
+        let entry = self
+            .locks
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+
+        KeyedLockGuard {
+            owner: self,
+            key: key.to_string(),
+            guard: Some(entry.lock_owned().await),
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.locks.lock().unwrap().len()
+    }
+}
+
+pub struct KeyedLockGuard<'a> {
+    owner: &'a KeyedLock,
+    key: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for KeyedLockGuard<'_> {
+    fn drop(&mut self) {
+        // Release the mutex first so its Arc no longer counts as a user
+        drop(self.guard.take());
+
+        let mut locks = self.owner.locks.lock().unwrap();
+        if let Some(entry) = locks.get(&self.key) {
+            if Arc::strong_count(entry) == 1 {
+                locks.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Caps how many operations run at once; excess callers queue in order
+///
+/// Only heavy operations go through the limit. Cheap ones such as info and
+/// list never wait on it, so a long queue cannot starve them.
+pub struct OperationLimit {
+    semaphore: RwLock<Arc<Semaphore>>,
+    max: AtomicUsize,
+    queue_timeout: RwLock<Option<Duration>>,
+    next_id: AtomicU64,
+    running: Arc<Mutex<BTreeMap<u64, QueuedOperation>>>,
+    queued: Mutex<BTreeMap<u64, QueuedOperation>>,
+}
+
+/// An operation holding or waiting for a slot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueuedOperation {
+    pub operation: String,
+    /// When it started running, or started waiting if queued
+    pub since: DateTime<Utc>,
+}
+
+/// Running and queued operations, oldest first
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationQueue {
+    pub max_concurrent: usize,
+    pub queue_timeout_secs: Option<u64>,
+    pub running: Vec<QueuedOperation>,
+    pub queued: Vec<QueuedOperation>,
+}
+
+impl OperationLimit {
+    pub fn new(max: usize) -> Self {
+        Self {
+            semaphore: RwLock::new(Arc::new(Semaphore::new(max))),
+            max: AtomicUsize::new(max),
+            queue_timeout: RwLock::new(None),
+            next_id: AtomicU64::new(0),
+            running: Default::default(),
+            queued: Default::default(),
+        }
+    }
+
+    /// Allow `max` operations at once
+    ///
+    /// Meant for startup; operations already running keep their permit
+    /// and are not counted against the new limit.
+    pub fn set_max(&self, max: usize) {
+        *self.semaphore.write().unwrap() = Arc::new(Semaphore::new(max));
+        self.max.store(max, Ordering::SeqCst);
+    }
+
+    /// Give up on operations that waited `timeout` for a slot; without
+    /// one they wait as long as it takes
+    pub fn set_queue_timeout(&self, timeout: Option<Duration>) {
+        *self.queue_timeout.write().unwrap() = timeout;
+    }
+
+    /// Wait until `operation` may run; it holds its slot until the permit
+    /// is dropped
+    ///
+    /// Fails with [`ContainerError::QueueTimeout`] if a queue timeout is set
+    /// and no slot frees up within it.
+    pub async fn acquire(&self, operation: &str) -> Result<OperationPermit, ContainerError> {
+        let semaphore = self.semaphore.read().unwrap().clone();
+        let timeout = *self.queue_timeout.read().unwrap();
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+
+        let permit = match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                debug!("Queueing {} until another operation finishes", operation);
+                let _queued = QueueEntry::new(&self.queued, id, operation);
+                let wait = semaphore.acquire_owned();
+                match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, wait).await.map_err(|_| {
+                        warn!(
+                            "Gave up on {} after waiting {}s for a slot",
+                            operation,
+                            timeout.as_secs()
+                        );
+                        ContainerError::QueueTimeout {
+                            operation: operation.to_string(),
+                            waited_secs: timeout.as_secs(),
+                        }
+                    })?,
+                    None => wait.await,
+                }
+                .expect("operation semaphore is never closed")
+            }
+        };
+        self.running.lock().unwrap().insert(
+            id,
+            QueuedOperation {
+                operation: operation.to_string(),
+                since: Utc::now(),
+            },
+        );
+
+        Ok(OperationPermit {
+            _permit: permit,
+            id,
+            running: self.running.clone(),
+        })
+    }
+
+    /// Operations currently holding a permit
+    pub fn in_flight(&self) -> usize {
+        self.running.lock().unwrap().len()
+    }
+
+    /// Operations waiting for a permit
+    pub fn queued(&self) -> usize {
+        self.queued.lock().unwrap().len()
+    }
+
+    pub fn queue(&self) -> OperationQueue {
+        OperationQueue {
+            max_concurrent: self.max.load(Ordering::SeqCst),
+            queue_timeout_secs: self.queue_timeout.read().unwrap().map(|t| t.as_secs()),
+            running: self.running.lock().unwrap().values().cloned().collect(),
+            queued: self.queued.lock().unwrap().values().cloned().collect(),
+        }
+    }
+}
+
+/// Listing of a waiting operation, removed when it stops waiting for any
+/// reason, including the caller giving up
+struct QueueEntry<'a> {
+    queued: &'a Mutex<BTreeMap<u64, QueuedOperation>>,
+    id: u64,
+}
+
+impl<'a> QueueEntry<'a> {
+    fn new(queued: &'a Mutex<BTreeMap<u64, QueuedOperation>>, id: u64, operation: &str) -> Self {
+        queued.lock().unwrap().insert(
+            id,
+            QueuedOperation {
+                operation: operation.to_string(),
+                since: Utc::now(),
+            },
+        );
+        Self { queued, id }
+    }
+}
+
+impl Drop for QueueEntry<'_> {
+    fn drop(&mut self) {
+        self.queued.lock().unwrap().remove(&self.id);
+    }
+}
+
+pub struct OperationPermit {
+    _permit: OwnedSemaphorePermit,
+    id: u64,
+    running: Arc<Mutex<BTreeMap<u64, QueuedOperation>>>,
+}
+
+impl Drop for OperationPermit {
+    fn drop(&mut self) {
+        self.running.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_same_key_serializes() {
+        let locks = Arc::new(KeyedLock::default());
+        let guard = locks.lock("web").await;
+
+        let waiter = {
+            let locks = locks.clone();
+            tokio::spawn(async move {
+                let _guard = locks.lock("web").await;
+            })
+        };
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiter.is_finished());
+
+        drop(guard);
+        waiter.await.unwrap();
+        assert_eq!(locks.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_different_keys_do_not_block() {
+        let locks = KeyedLock::default();
+        let _web = locks.lock("web").await;
+        let _db = tokio::time::timeout(Duration::from_secs(1), locks.lock("db"))
+            .await
+            .expect("lock on a different key should not wait");
+        assert_eq!(locks.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_operation_limit_queues_excess() {
+        let limit = Arc::new(OperationLimit::new(1));
+        let permit = limit.acquire("create web").await.unwrap();
+        assert_eq!(limit.in_flight(), 1);
+
+        let waiter = {
+            let limit = limit.clone();
+            tokio::spawn(async move {
+                let _permit = limit.acquire("create db").await.unwrap();
+            })
+        };
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiter.is_finished());
+        let queue = limit.queue();
+        assert_eq!(queue.running[0].operation, "create web");
+        assert_eq!(queue.queued[0].operation, "create db");
+
+        drop(permit);
+        waiter.await.unwrap();
+        assert_eq!(limit.in_flight(), 0);
+        assert_eq!(limit.queued(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_queued_operation_times_out() {
+        let limit = OperationLimit::new(1);
+        limit.set_queue_timeout(Some(Duration::from_millis(50)));
+        let _permit = limit.acquire("create web").await.unwrap();
+
+        let result = limit.acquire("destroy db").await;
+        assert!(matches!(
+            result,
+            Err(ContainerError::QueueTimeout { ref operation, .. }) if operation == "destroy db"
+        ));
+        // The timed-out operation is no longer listed as waiting
+        assert_eq!(limit.queued(), 0);
+        assert_eq!(limit.in_flight(), 1);
+    }
+}
@@ -1,17 +1,148 @@
 use anyhow::{Context, Result};
+use models::{metrics, ContainerStatus, ContainerUsage};
+use std::net::IpAddr;
 use std::process::Command;
+use std::sync::{Arc, RwLock};
 use tracing::{debug, error, warn};
 
+use crate::fake::FakeLxc;
+
+/// How LXC commands can be run by this process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrivilegeMode {
+    /// Running as root
+    Root,
+    /// Not root, but passwordless sudo works
+    Sudo,
+    /// Neither; every LXC operation will fail
+    None,
+    /// LXC is not used; containers live in the in-process fake backend
+    Fake,
+}
+
+/// Outcome of [`LxcCommand::probe_privileges`]
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PrivilegeProbe {
+    pub mode: PrivilegeMode,
+    /// Why the probe failed, when it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Version of the installed LXC tools
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+pub struct LxcVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl LxcVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parse `lxc-ls --version` output such as `4.0.12` or `3.0.3-0ubuntu1`
+    pub fn parse(output: &str) -> Option<Self> {
+        let version = output.split_whitespace().next()?;
+        let mut parts = version.split('.').map(|part| {
+            part.split(|c: char| !c.is_ascii_digit())
+                .next()
+                .unwrap_or("")
+        });
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+        let patch = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+        Some(Self::new(major, minor, patch))
+    }
+}
+
+impl std::fmt::Display for LxcVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Version recorded by [`LxcCommand::detect_version`] at startup
+static DETECTED_VERSION: RwLock<Option<LxcVersion>> = RwLock::new(None);
+
+/// Backend every command goes to instead of LXC, in development
+static FAKE_BACKEND: RwLock<Option<Arc<FakeLxc>>> = RwLock::new(None);
+
 pub struct LxcCommand;
 
 impl LxcCommand {
+    /// Find out whether LXC can be driven directly, through sudo, or not at
+    /// all by running a harmless `lxc-ls`
+    pub fn probe_privileges() -> PrivilegeProbe {
+        if Self::is_fake() {
+            return PrivilegeProbe {
+                mode: PrivilegeMode::Fake,
+                error: None,
+            };
+        }
+        let probe = if Self::is_root() {
+            Self::execute_direct("lxc-ls", &["--line"], &[]).map(|_| PrivilegeMode::Root)
+        } else {
+            Self::execute_with_sudo("lxc-ls", &["--line"], &[]).map(|_| PrivilegeMode::Sudo)
+        };
+
+        match probe {
+            Ok(mode) => PrivilegeProbe { mode, error: None },
+            Err(e) => PrivilegeProbe {
+                mode: PrivilegeMode::None,
+                error: Some(format!("{:#}", e)),
+            },
+        }
+    }
+
+    /// Run `lxc-ls --version` and remember the result for [`Self::version`]
+    pub fn detect_version() -> Result<LxcVersion> {
+        let output = Self::execute(&["ls", "--version"])?;
+        let version = LxcVersion::parse(&output)
+            .ok_or_else(|| anyhow::anyhow!("Unrecognised LXC version: {}", output.trim()))?;
+        *DETECTED_VERSION.write().unwrap() = Some(version);
+        Ok(version)
+    }
+
+    /// The installed LXC version: the one detected at startup, else asked now
+    pub fn version() -> Result<LxcVersion> {
+        if let Some(version) = *DETECTED_VERSION.read().unwrap() {
+            return Ok(version);
+        }
+        let output = Self::execute(&["ls", "--version"])?;
+        LxcVersion::parse(&output)
+            .ok_or_else(|| anyhow::anyhow!("Unrecognised LXC version: {}", output.trim()))
+    }
+
+    /// Answer every LXC command from an in-process [`FakeLxc`] from now on,
+    /// instead of running the `lxc-*` tools
+    pub fn use_fake_backend() {
+        *FAKE_BACKEND.write().unwrap() = Some(Arc::new(FakeLxc::new()));
+    }
+
+    /// Whether [`Self::use_fake_backend`] is in effect
+    pub fn is_fake() -> bool {
+        FAKE_BACKEND.read().unwrap().is_some()
+    }
+
     /// Check if running as root
-    fn is_root() -> bool {
+    pub(crate) fn is_root() -> bool {
         nix::unistd::getuid().is_root()
     }
 
     /// Execute an LXC command with smart privilege escalation
     pub fn execute(args: &[&str]) -> Result<String> {
+        Self::execute_with_env(args, &[])
+    }
+
+    /// Execute an LXC command with extra environment variables
+    pub fn execute_with_env(args: &[&str], env: &[(&str, &str)]) -> Result<String> {
         if args.is_empty() {
             return Err(anyhow::anyhow!("No command specified"));
         }
@@ -19,13 +150,18 @@ impl LxcCommand {
         let cmd_name = format!("lxc-{}", args[0]);
         debug!("Executing: {}", cmd_name);
 
+        let fake = FAKE_BACKEND.read().unwrap().clone();
+        if let Some(fake) = fake {
+            return fake.execute(args);
+        }
+
         // Try direct execution first (works if running as root)
         if Self::is_root() {
-            return Self::execute_direct(&cmd_name, &args[1..]);
+            return Self::execute_direct(&cmd_name, &args[1..], env);
         }
 
         // Try with passwordless sudo
-        match Self::execute_with_sudo(&cmd_name, &args[1..]) {
+        match Self::execute_with_sudo(&cmd_name, &args[1..], env) {
             Ok(output) => Ok(output),
             Err(e) => {
                 warn!("Sudo execution failed: {}", e);
@@ -35,11 +171,13 @@ impl LxcCommand {
     }
 
     /// Execute command directly (when running as root)
-    fn execute_direct(cmd_name: &str, args: &[&str]) -> Result<String> {
-        let output = Command::new(cmd_name)
-            .args(args)
-            .output()
-            .context(format!("Failed to execute LXC command: {}", cmd_name))?;
+    fn execute_direct(cmd_name: &str, args: &[&str], env: &[(&str, &str)]) -> Result<String> {
+        metrics::record_privilege_path(false);
+        let output = metrics::command_output(
+            cmd_name,
+            Command::new(cmd_name).args(args).envs(env.iter().copied()),
+        )
+        .context(format!("Failed to execute LXC command: {}", cmd_name))?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -51,16 +189,22 @@ impl LxcCommand {
     }
 
     /// Execute command with sudo (assumes passwordless sudo configured)
-    fn execute_with_sudo(cmd_name: &str, args: &[&str]) -> Result<String> {
-        let output = Command::new("sudo")
-            .arg("-n") // non-interactive mode
-            .arg(cmd_name)
-            .args(args)
-            .output()
-            .context(format!(
-                "Failed to execute LXC command with sudo: {}",
-                cmd_name
-            ))?;
+    fn execute_with_sudo(cmd_name: &str, args: &[&str], env: &[(&str, &str)]) -> Result<String> {
+        metrics::record_privilege_path(true);
+        // Timed under the LXC command's name; the sudo overhead is part of it
+        let output = metrics::command_output(
+            cmd_name,
+            Command::new("sudo")
+                .arg("-n") // non-interactive mode
+                // sudo resets the environment; VAR=value arguments pass variables through
+                .args(env.iter().map(|(key, value)| format!("{}={}", key, value)))
+                .arg(cmd_name)
+                .args(args),
+        )
+        .context(format!(
+            "Failed to execute LXC command with sudo: {}",
+            cmd_name
+        ))?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -79,21 +223,35 @@ impl LxcCommand {
     }
 
     /// Check if a container exists
+    ///
+    /// A failed listing reads as "does not exist"; it is logged so it is not
+    /// mistaken for an empty host.
     pub fn exists(name: &str) -> bool {
-        Self::list()
-            .unwrap_or_default()
-            .iter()
-            .any(|container| container == name)
+        match Self::list() {
+            Ok(containers) => containers.iter().any(|container| container == name),
+            Err(e) => {
+                warn!("Could not list containers to find {}: {}", name, e);
+                false
+            }
+        }
     }
 
     /// List all containers
+    ///
+    /// `lxc-ls` succeeding without output means there are no containers, so
+    /// the list is empty; only a failed command is an error.
     pub fn list() -> Result<Vec<String>> {
         let output = Self::execute(&["ls", "--line"])?;
-        Ok(output
+        Ok(Self::parse_list(&output))
+    }
+
+    /// Parse `lxc-ls --line` output, one container name per line
+    pub fn parse_list(output: &str) -> Vec<String> {
+        output
             .lines()
             .map(|s| s.trim().to_string())
             .filter(|s| !s.is_empty())
-            .collect())
+            .collect()
     }
 
     /// Get container state
@@ -107,4 +265,224 @@ impl LxcCommand {
         }
         Err(anyhow::anyhow!("Could not parse container state"))
     }
+
+    /// Map an LXC state name, in any case, to a container status
+    pub fn parse_state(state: &str) -> ContainerStatus {
+        match state.to_lowercase().as_str() {
+            "running" => ContainerStatus::Running,
+            "stopped" => ContainerStatus::Stopped,
+            "starting" => ContainerStatus::Starting,
+            "stopping" => ContainerStatus::Stopping,
+            "frozen" => ContainerStatus::Frozen,
+            _ => ContainerStatus::Error,
+        }
+    }
+
+    /// Get raw resource usage counters for a running container
+    pub fn usage(name: &str) -> Result<ContainerUsage> {
+        // -H prints raw byte/nanosecond values instead of human-readable units
+        let output = Self::execute(&["info", "-H", name])?;
+        let mut usage = Self::parse_usage(&output);
+        usage.uptime_seconds = Self::parse_pid(&output).and_then(process_uptime);
+        Ok(usage)
+    }
+
+    /// Seconds since a container's init process started, `None` when it is
+    /// not running
+    pub fn uptime(name: &str) -> Result<Option<u64>> {
+        let output = Self::execute(&["info", name])?;
+        Ok(Self::parse_pid(&output).and_then(process_uptime))
+    }
+
+    /// Host-side interface names of a running container's network links
+    pub fn links(name: &str) -> Result<Vec<String>> {
+        let output = Self::execute(&["info", name])?;
+        Ok(Self::parse_links(&output))
+    }
+
+    /// Addresses of a running container, as `lxc-info` reports them
+    pub fn ips(name: &str) -> Result<Vec<IpAddr>> {
+        let output = Self::execute(&["info", "-i", name])?;
+        Ok(Self::parse_ips(&output))
+    }
+
+    /// Run `command` inside a running container, failing unless it exits 0
+    pub fn attach(name: &str, command: &[&str]) -> Result<String> {
+        let mut args = vec!["attach", "-n", name, "--"];
+        args.extend_from_slice(command);
+        Self::execute(&args)
+    }
+
+    /// PID of a running container's init process
+    pub fn pid(name: &str) -> Result<u32> {
+        let output = Self::execute(&["info", name])?;
+        Self::parse_pid(&output).ok_or_else(|| anyhow::anyhow!("Container {} is not running", name))
+    }
+
+    /// Parse the `PID:` line of `lxc-info` output
+    pub fn parse_pid(output: &str) -> Option<u32> {
+        output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("PID:"))
+            .and_then(|pid| pid.trim().parse().ok())
+    }
+
+    /// Parse the start time, in clock ticks since boot, from `/proc/<pid>/stat`
+    pub fn parse_start_ticks(stat: &str) -> Option<u64> {
+        // The command name may contain spaces and parentheses, so fields are
+        // counted after its last `)`; starttime is field 22 of the line
+        stat.rsplit_once(')')?
+            .1
+            .split_whitespace()
+            .nth(19)?
+            .parse()
+            .ok()
+    }
+
+    /// Parse the `IP:` lines of `lxc-info` output, skipping unparsable ones
+    pub fn parse_ips(output: &str) -> Vec<IpAddr> {
+        output
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("IP:"))
+            .filter_map(|ip| ip.trim().parse().ok())
+            .collect()
+    }
+
+    /// Parse the `Link:` lines of `lxc-info` output
+    pub fn parse_links(output: &str) -> Vec<String> {
+        output
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("Link:"))
+            .map(|link| link.trim().to_string())
+            .filter(|link| !link.is_empty())
+            .collect()
+    }
+
+    /// Parse the counters section of `lxc-info -H` output
+    pub fn parse_usage(output: &str) -> ContainerUsage {
+        let mut usage = ContainerUsage::default();
+
+        for line in output.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().parse::<u64>().ok();
+
+            match key.trim() {
+                "CPU use" => usage.cpu_time_ns = value,
+                "Memory use" => usage.memory_bytes = value,
+                "KMem use" => usage.kmem_bytes = value,
+                // Counters are summed when the container has several links
+                "TX bytes" => usage.tx_bytes = sum(usage.tx_bytes, value),
+                "RX bytes" => usage.rx_bytes = sum(usage.rx_bytes, value),
+                _ => {}
+            }
+        }
+
+        usage
+    }
+}
+
+/// Clock ticks per second in `/proc/<pid>/stat`, fixed at 100 on Linux
+const USER_HZ: u64 = 100;
+
+/// Seconds since process `pid` started, from `/proc`
+fn process_uptime(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let started = LxcCommand::parse_start_ticks(&stat)? / USER_HZ;
+    let since_boot = std::fs::read_to_string("/proc/uptime").ok()?;
+    let since_boot = since_boot.split_whitespace().next()?.parse::<f64>().ok()? as u64;
+    Some(since_boot.saturating_sub(started))
+}
+
+fn sum(current: Option<u64>, value: Option<u64>) -> Option<u64> {
+    match (current, value) {
+        (Some(a), Some(b)) => Some(a.saturating_add(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lxc_version() {
+        assert_eq!(
+            LxcVersion::parse("4.0.12\n"),
+            Some(LxcVersion::new(4, 0, 12))
+        );
+        assert_eq!(
+            LxcVersion::parse("3.0.3-0ubuntu1~18.04.1"),
+            Some(LxcVersion::new(3, 0, 3))
+        );
+        assert_eq!(
+            LxcVersion::parse("5.0.0~git2209"),
+            Some(LxcVersion::new(5, 0, 0))
+        );
+        assert_eq!(LxcVersion::parse("2.1"), Some(LxcVersion::new(2, 1, 0)));
+        assert!(LxcVersion::parse("").is_none());
+        assert!(LxcVersion::parse("unknown").is_none());
+        assert!(LxcVersion::new(1, 0, 8) < LxcVersion::new(2, 0, 0));
+    }
+
+    #[test]
+    fn test_parse_usage() {
+        let output = "Name:           web\n\
+                      State:          RUNNING\n\
+                      PID:            1234\n\
+                      IP:             10.0.3.15\n\
+                      CPU use:        5623412345\n\
+                      Memory use:     52428800\n\
+                      KMem use:       1048576\n\
+                      Link:           vethA1B2C3\n\
+                       TX bytes:      1000\n\
+                       RX bytes:      2000\n\
+                      Link:           vethD4E5F6\n\
+                       TX bytes:      24\n\
+                       RX bytes:      48\n";
+
+        let usage = LxcCommand::parse_usage(output);
+        assert_eq!(usage.cpu_time_ns, Some(5_623_412_345));
+        assert_eq!(usage.memory_bytes, Some(52_428_800));
+        assert_eq!(usage.kmem_bytes, Some(1_048_576));
+        assert_eq!(usage.tx_bytes, Some(1024));
+        assert_eq!(usage.rx_bytes, Some(2048));
+        assert_eq!(
+            LxcCommand::parse_links(output),
+            ["vethA1B2C3", "vethD4E5F6"]
+        );
+        assert_eq!(LxcCommand::parse_pid(output), Some(1234));
+        assert_eq!(
+            LxcCommand::parse_ips(output),
+            ["10.0.3.15".parse::<IpAddr>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_parse_usage_stopped_container() {
+        let usage = LxcCommand::parse_usage("Name:           web\nState:          STOPPED\n");
+        assert_eq!(
+            LxcCommand::parse_pid("Name:           web\nState:          STOPPED\n"),
+            None
+        );
+        assert_eq!(usage, ContainerUsage::default());
+    }
+
+    #[test]
+    fn test_parse_start_ticks() {
+        let stat = "1234 (my (init) x) S 1 1234 1234 0 -1 4194560 1502 0 12 0 \
+                    3 5 0 0 20 0 1 0 987654 17801216 1024 18446744073709551615";
+        assert_eq!(LxcCommand::parse_start_ticks(stat), Some(987_654));
+        assert_eq!(LxcCommand::parse_start_ticks("1234 (init) S 1"), None);
+
+        assert!(process_uptime(std::process::id()).is_some());
+    }
+
+    #[test]
+    fn test_parse_list() {
+        assert!(LxcCommand::parse_list("").is_empty());
+        assert!(LxcCommand::parse_list("\n  \n").is_empty());
+        assert_eq!(LxcCommand::parse_list(" web \n\ndb\n"), ["web", "db"]);
+    }
 }
@@ -0,0 +1,322 @@
+/// Container state changes from `lxc-monitor`
+///
+/// One long-lived `lxc-monitor` child reports every state transition on the
+/// host. Its lines are parsed into [`ContainerStateChange`]s and published on
+/// a broadcast channel, so consumers no longer have to poll `lxc-info` for
+/// each container. If the child dies it is restarted with exponential
+/// backoff, and a full poll picks up whatever changed while it was down.
+/// Without an `lxc-monitor` binary the monitor falls back to polling.
+use chrono::{DateTime, Utc};
+use models::{ContainerStateChange, ContainerStatus};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::lxc::LxcCommand;
+
+/// Changes buffered per subscriber before a slow one starts missing them
+const EVENT_CAPACITY: usize = 256;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A child that ran at least this long resets the backoff
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+/// How often states are polled when `lxc-monitor` is not installed
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MonitorMode {
+    /// Taking the initial snapshot
+    Starting,
+    /// `lxc-monitor` is running
+    Watching,
+    /// No `lxc-monitor` binary; states are polled
+    Polling,
+    /// `lxc-monitor` exited and is waiting to be restarted
+    Restarting,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorHealth {
+    pub mode: MonitorMode,
+    /// Times `lxc-monitor` exited and had to be restarted
+    pub restarts: u64,
+    pub last_error: Option<String>,
+    pub last_change: Option<DateTime<Utc>>,
+}
+
+impl MonitorHealth {
+    /// Whether the known states can be trusted to be current
+    pub fn is_current(&self) -> bool {
+        matches!(self.mode, MonitorMode::Watching | MonitorMode::Polling)
+    }
+}
+
+pub struct LxcMonitor {
+    events: broadcast::Sender<ContainerStateChange>,
+    states: Mutex<HashMap<String, ContainerStatus>>,
+    health: RwLock<MonitorHealth>,
+}
+
+impl LxcMonitor {
+    pub fn new() -> Self {
+        Self {
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            states: Mutex::new(HashMap::new()),
+            health: RwLock::new(MonitorHealth {
+                mode: MonitorMode::Starting,
+                restarts: 0,
+                last_error: None,
+                last_change: None,
+            }),
+        }
+    }
+
+    /// Receive every state change published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ContainerStateChange> {
+        self.events.subscribe()
+    }
+
+    pub fn health(&self) -> MonitorHealth {
+        self.health.read().unwrap().clone()
+    }
+
+    /// Last known state of each container
+    pub fn states(&self) -> HashMap<String, ContainerStatus> {
+        self.states.lock().unwrap().clone()
+    }
+
+    /// Last known state of one container
+    pub fn state(&self, name: &str) -> Option<ContainerStatus> {
+        self.states.lock().unwrap().get(name).cloned()
+    }
+
+    /// Record `state` for `name`, publishing a change when it differs from
+    /// the last state seen
+    pub fn observe(&self, name: &str, state: ContainerStatus) -> Option<ContainerStateChange> {
+        let old_state = self
+            .states
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), state.clone());
+        if old_state.as_ref() == Some(&state) {
+            return None;
+        }
+
+        crate::events::EVENT_HISTORY.record_state(
+            name,
+            &state,
+            old_state.is_some(),
+            Some("observed by lxc-monitor".to_string()),
+        );
+        let change = ContainerStateChange {
+            name: name.to_string(),
+            old_state,
+            new_state: state,
+            timestamp: Utc::now(),
+        };
+        self.health.write().unwrap().last_change = Some(change.timestamp);
+        // Having no subscribers is not an error
+        let _ = self.events.send(change.clone());
+        Some(change)
+    }
+
+    /// Parse a `'name' changed state to [STATE]` line of `lxc-monitor`
+    ///
+    /// Transitional states LXC has no status for are skipped; a thawed
+    /// container is running again.
+    pub fn parse_line(line: &str) -> Option<(String, ContainerStatus)> {
+        let (name, rest) = line
+            .trim()
+            .strip_prefix('\'')?
+            .split_once("' changed state to [")?;
+        let status = match rest.strip_suffix(']')? {
+            "THAWED" => ContainerStatus::Running,
+            "FREEZING" | "ABORTING" => return None,
+            state => LxcCommand::parse_state(state),
+        };
+        Some((name.to_string(), status))
+    }
+
+    /// Watch container states until the task is dropped
+    pub async fn run(self: Arc<Self>, poll_interval: Duration) {
+        self.poll_once().await;
+
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            let started = Instant::now();
+            let reason = match self.watch().await {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    warn!(
+                        "lxc-monitor is not available, polling container states every {}s",
+                        poll_interval.as_secs()
+                    );
+                    self.set_mode(MonitorMode::Polling, Some(e.to_string()));
+                    loop {
+                        tokio::time::sleep(poll_interval).await;
+                        self.poll_once().await;
+                    }
+                }
+                Err(e) => format!("lxc-monitor failed: {}", e),
+                Ok(status) => format!("lxc-monitor exited: {}", status),
+            };
+
+            if started.elapsed() >= HEALTHY_RUN {
+                backoff = INITIAL_BACKOFF;
+            }
+            warn!("{}; restarting in {}s", reason, backoff.as_secs());
+            {
+                let mut health = self.health.write().unwrap();
+                health.mode = MonitorMode::Restarting;
+                health.restarts += 1;
+                health.last_error = Some(reason);
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+
+            // Pick up whatever changed while nobody was watching
+            self.poll_once().await;
+        }
+    }
+
+    /// Run `lxc-monitor` until it exits
+    async fn watch(&self) -> io::Result<ExitStatus> {
+        // The fake backend has no events to watch; it is polled instead
+        if LxcCommand::is_fake() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "lxc-monitor is not used with the fake backend",
+            ));
+        }
+        let mut command = if LxcCommand::is_root() {
+            Command::new("lxc-monitor")
+        } else {
+            let mut command = Command::new("sudo");
+            command.args(["-n", "lxc-monitor"]);
+            command
+        };
+        let mut child = command
+            .args(["-n", ".*"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| io::Error::other("lxc-monitor has no stdout"))?;
+
+        info!("Watching container state changes with lxc-monitor");
+        self.set_mode(MonitorMode::Watching, None);
+        let mut lines = BufReader::new(stdout).lines();
+        while let Some(line) = lines.next_line().await? {
+            if let Some((name, state)) = Self::parse_line(&line) {
+                self.observe(&name, state);
+            }
+        }
+        child.wait().await
+    }
+
+    /// Ask LXC for the state of every container, forgetting removed ones
+    async fn poll_once(&self) {
+        // LXC commands block; keep them off the runtime's worker threads
+        let snapshot = tokio::task::spawn_blocking(|| {
+            LxcCommand::list().map(|names| {
+                names
+                    .into_iter()
+                    .filter_map(|name| {
+                        let state = LxcCommand::state(&name).ok()?;
+                        Some((name, LxcCommand::parse_state(&state)))
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .await;
+
+        let snapshot = match snapshot {
+            Ok(Ok(snapshot)) => snapshot,
+            Ok(Err(e)) => {
+                warn!("Could not poll container states: {}", e);
+                return;
+            }
+            Err(e) => {
+                warn!("Container state poll panicked: {}", e);
+                return;
+            }
+        };
+        for (name, state) in &snapshot {
+            self.observe(name, state.clone());
+        }
+        self.states
+            .lock()
+            .unwrap()
+            .retain(|name, _| snapshot.iter().any(|(known, _)| known == name));
+    }
+
+    fn set_mode(&self, mode: MonitorMode, error: Option<String>) {
+        let mut health = self.health.write().unwrap();
+        health.mode = mode;
+        if error.is_some() {
+            health.last_error = error;
+        }
+    }
+}
+
+impl Default for LxcMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_monitor_lines() {
+        assert_eq!(
+            LxcMonitor::parse_line("'web' changed state to [RUNNING]"),
+            Some(("web".to_string(), ContainerStatus::Running))
+        );
+        assert_eq!(
+            LxcMonitor::parse_line("'db-1' changed state to [STOPPED]\n"),
+            Some(("db-1".to_string(), ContainerStatus::Stopped))
+        );
+        assert_eq!(
+            LxcMonitor::parse_line("'web' changed state to [THAWED]"),
+            Some(("web".to_string(), ContainerStatus::Running))
+        );
+        assert_eq!(
+            LxcMonitor::parse_line("'web' changed state to [FREEZING]"),
+            None
+        );
+        assert_eq!(LxcMonitor::parse_line("'web' exited with status [0]"), None);
+        assert_eq!(LxcMonitor::parse_line("garbage"), None);
+    }
+
+    #[tokio::test]
+    async fn test_observe_publishes_changes_only() {
+        let monitor = LxcMonitor::new();
+        let mut events = monitor.subscribe();
+
+        let first = monitor.observe("web", ContainerStatus::Stopped).unwrap();
+        assert_eq!(first.old_state, None);
+        assert!(monitor.observe("web", ContainerStatus::Stopped).is_none());
+        monitor.observe("web", ContainerStatus::Running).unwrap();
+
+        assert_eq!(events.recv().await.unwrap(), first);
+        let change = events.recv().await.unwrap();
+        assert_eq!(change.old_state, Some(ContainerStatus::Stopped));
+        assert_eq!(change.new_state, ContainerStatus::Running);
+        assert!(events.try_recv().is_err());
+        assert_eq!(monitor.states()["web"], ContainerStatus::Running);
+        assert_eq!(monitor.health().last_change, Some(change.timestamp));
+    }
+}
@@ -0,0 +1,253 @@
+/// Writing files into a container's rootfs from the host
+///
+/// Everything below the rootfs is controlled by the container, and the
+/// host writes there as root. Paths are therefore walked one component at
+/// a time relative to an open directory, refusing symlinks at every step,
+/// so a link planted by the container can't point a write or delete at the
+/// host's own files.
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+
+use nix::dir::Dir;
+use nix::errno::Errno;
+use nix::fcntl::{openat, OFlag};
+use nix::sys::stat::{fchmod, mkdirat, Mode};
+use nix::unistd::{unlinkat, UnlinkatFlags};
+
+use crate::error::ContainerError;
+
+/// A directory inside a container's rootfs, opened without following links
+pub struct RootfsDir {
+    fd: OwnedFd,
+}
+
+impl RootfsDir {
+    /// Open `rootfs/<components...>`, creating missing directories with
+    /// `mode`; fails if any component is a symlink or not a directory
+    pub fn open(rootfs: &Path, components: &[&str], mode: u32) -> Result<Self, ContainerError> {
+        // The rootfs itself is configured by the host, so it may be followed
+        let fd = nix::fcntl::open(
+            rootfs,
+            OFlag::O_RDONLY | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        )
+        .map_err(|e| ContainerError::Io(e.into()))?;
+        let mut dir = Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        };
+        for component in components {
+            dir = dir.subdir(component, mode)?;
+        }
+        Ok(dir)
+    }
+
+    /// Open the directory `name` below this one, creating it when missing
+    pub fn subdir(&self, name: &str, mode: u32) -> Result<Self, ContainerError> {
+        self.child(&component(name)?, mode)
+    }
+
+    fn child(&self, name: &CStr, mode: u32) -> Result<Self, ContainerError> {
+        let flags = OFlag::O_RDONLY | OFlag::O_DIRECTORY | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC;
+        let opened = match openat(self.fd.as_raw_fd(), name, flags, Mode::empty()) {
+            Err(Errno::ENOENT) => {
+                match mkdirat(self.fd.as_raw_fd(), name, Mode::from_bits_truncate(mode)) {
+                    Ok(()) | Err(Errno::EEXIST) => {}
+                    Err(e) => return Err(ContainerError::Io(e.into())),
+                }
+                openat(self.fd.as_raw_fd(), name, flags, Mode::empty())
+            }
+            other => other,
+        };
+        match opened {
+            Ok(fd) => Ok(Self {
+                fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            }),
+            Err(Errno::ELOOP | Errno::ENOTDIR) => Err(not_a_directory(name)),
+            Err(e) => Err(ContainerError::Io(e.into())),
+        }
+    }
+
+    /// Set this directory's permission bits
+    pub fn set_mode(&self, mode: u32) -> Result<(), ContainerError> {
+        fchmod(self.fd.as_raw_fd(), Mode::from_bits_truncate(mode))
+            .map_err(|e| ContainerError::Io(e.into()))
+    }
+
+    /// Remove everything in this directory; links are removed, never followed
+    pub fn clear(&self) -> Result<(), ContainerError> {
+        let mut listing = Dir::openat(
+            self.fd.as_raw_fd(),
+            ".",
+            OFlag::O_RDONLY | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        )
+        .map_err(|e| ContainerError::Io(e.into()))?;
+        let mut names = Vec::new();
+        for entry in listing.iter() {
+            let entry = entry.map_err(|e| ContainerError::Io(e.into()))?;
+            let name = entry.file_name();
+            if name.to_bytes() != b"." && name.to_bytes() != b".." {
+                names.push(name.to_owned());
+            }
+        }
+
+        for name in names {
+            match unlinkat(
+                Some(self.fd.as_raw_fd()),
+                name.as_c_str(),
+                UnlinkatFlags::NoRemoveDir,
+            ) {
+                Ok(()) | Err(Errno::ENOENT) => {}
+                Err(Errno::EISDIR) => {
+                    self.child(&name, 0o700)?.clear()?;
+                    unlinkat(
+                        Some(self.fd.as_raw_fd()),
+                        name.as_c_str(),
+                        UnlinkatFlags::RemoveDir,
+                    )
+                    .map_err(|e| ContainerError::Io(e.into()))?;
+                }
+                Err(e) => return Err(ContainerError::Io(e.into())),
+            }
+        }
+        Ok(())
+    }
+
+    /// Create the file `name` with `mode` and write `content` to it; fails
+    /// if anything, including a symlink, already has that name
+    pub fn create_file(&self, name: &str, content: &[u8], mode: u32) -> Result<(), ContainerError> {
+        let name = component(name)?;
+        let fd = openat(
+            self.fd.as_raw_fd(),
+            name.as_c_str(),
+            OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_EXCL | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC,
+            Mode::from_bits_truncate(mode),
+        )
+        .map_err(|e| ContainerError::Io(e.into()))?;
+        let mut file = File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+        file.write_all(content).map_err(ContainerError::Io)
+    }
+
+    /// Replace the file `name` with `content`, removing whatever was there
+    /// first (a symlink is removed rather than written through)
+    pub fn replace_file(
+        &self,
+        name: &str,
+        content: &[u8],
+        mode: u32,
+    ) -> Result<(), ContainerError> {
+        let c_name = component(name)?;
+        match unlinkat(
+            Some(self.fd.as_raw_fd()),
+            c_name.as_c_str(),
+            UnlinkatFlags::NoRemoveDir,
+        ) {
+            Ok(()) | Err(Errno::ENOENT) => {}
+            Err(Errno::EISDIR) => return Err(not_a_directory(&c_name)),
+            Err(e) => return Err(ContainerError::Io(e.into())),
+        }
+        self.create_file(name, content, mode)
+    }
+}
+
+/// A single path component; anything that could walk elsewhere is refused
+fn component(name: &str) -> Result<CString, ContainerError> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(ContainerError::InvalidConfig(format!(
+            "'{}' is not a valid file name",
+            name
+        )));
+    }
+    CString::new(name).map_err(|_| {
+        ContainerError::InvalidConfig(format!(
+            "'{}' is not a valid file name",
+            name.escape_default()
+        ))
+    })
+}
+
+fn not_a_directory(name: &CStr) -> ContainerError {
+    ContainerError::InvalidConfig(format!(
+        "refusing to write through '{}' in the container's rootfs: it is a symlink or not a directory",
+        name.to_string_lossy()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    fn temp_dir() -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("orchestrator_rootfs_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_creates_directories_and_files() {
+        let rootfs = temp_dir();
+        let dir = RootfsDir::open(&rootfs, &["etc", "orchestrator", "secrets"], 0o755).unwrap();
+        dir.set_mode(0o700).unwrap();
+        dir.create_file("token", b"value", 0o400).unwrap();
+
+        let path = rootfs.join("etc/orchestrator/secrets/token");
+        assert_eq!(fs::read(&path).unwrap(), b"value");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o400
+        );
+        assert!(dir.create_file("token", b"again", 0o400).is_err());
+
+        let _ = fs::remove_dir_all(&rootfs);
+    }
+
+    #[test]
+    fn test_refuses_symlinked_components() {
+        let rootfs = temp_dir();
+        let host = temp_dir();
+        fs::write(host.join("keep"), b"host file").unwrap();
+        fs::create_dir_all(rootfs.join("etc")).unwrap();
+        symlink(&host, rootfs.join("etc/orchestrator")).unwrap();
+
+        let result = RootfsDir::open(&rootfs, &["etc", "orchestrator", "secrets"], 0o755);
+        assert!(matches!(result, Err(ContainerError::InvalidConfig(_))));
+        assert!(!host.join("secrets").exists());
+
+        // A link in place of a file is removed, not written through
+        let etc = RootfsDir::open(&rootfs, &["etc"], 0o755).unwrap();
+        symlink(host.join("keep"), rootfs.join("etc/resolv.conf")).unwrap();
+        assert!(etc.create_file("resolv.conf", b"x", 0o644).is_err());
+        etc.replace_file("resolv.conf", b"nameserver 1.1.1.1\n", 0o644)
+            .unwrap();
+        assert_eq!(fs::read(host.join("keep")).unwrap(), b"host file");
+        assert!(!fs::symlink_metadata(rootfs.join("etc/resolv.conf"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+
+        // Clearing removes the link itself and leaves its target alone
+        etc.clear().unwrap();
+        assert!(fs::read_dir(rootfs.join("etc")).unwrap().next().is_none());
+        assert_eq!(fs::read(host.join("keep")).unwrap(), b"host file");
+
+        let _ = fs::remove_dir_all(&rootfs);
+        let _ = fs::remove_dir_all(&host);
+    }
+
+    #[test]
+    fn test_rejects_names_that_walk() {
+        let rootfs = temp_dir();
+        let dir = RootfsDir::open(&rootfs, &[], 0o755).unwrap();
+        for name in ["", ".", "..", "a/b"] {
+            assert!(dir.create_file(name, b"x", 0o600).is_err());
+            assert!(dir.subdir(name, 0o700).is_err());
+        }
+        let _ = fs::remove_dir_all(&rootfs);
+    }
+}
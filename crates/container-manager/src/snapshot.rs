@@ -1,12 +1,91 @@
 /// Container snapshot management
-use anyhow::Result;
-use chrono::Utc;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use tracing::info;
+use std::process::Command;
+use std::sync::{LazyLock, Mutex};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+use crate::container::{blocking, Provenance};
 use crate::error::ContainerError;
-use crate::lxc::LxcCommand;
+use crate::events::EVENT_HISTORY;
+use crate::locks::OPERATION_LIMIT;
+use crate::lxc::{LxcCommand, LxcVersion};
+use models::ContainerEventKind;
+
+/// Sidecar file in each snapshot directory holding the last computed size
+const SIZE_METADATA_FILE: &str = "orchestrator-size.json";
+
+/// Sidecar file in each snapshot directory holding the checksum taken at creation
+const CHECKSUM_METADATA_FILE: &str = "orchestrator-checksum.json";
+
+/// Snapshot directories with a size computation currently running
+static SIZE_JOBS: LazyLock<Mutex<HashSet<PathBuf>>> = LazyLock::new(Default::default);
+
+/// Generated snapshot names, as (container, snapshot), whose `lxc-snapshot`
+/// has not finished yet
+static RESERVED_NAMES: LazyLock<Mutex<HashSet<(String, String)>>> = LazyLock::new(Default::default);
+
+/// Oldest LXC able to turn a snapshot into a new container
+pub const MIN_CLONE_VERSION: LxcVersion = LxcVersion::new(1, 0, 0);
+
+/// How a snapshot becomes a new container on the installed LXC
+///
+/// A container's snapshots live in `<lxc root>/<container>/snaps`, which LXC
+/// treats as an lxcpath of its own, so the snapshot is copied as a container
+/// from there. `lxc-copy -s` would instead snapshot the copy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloneStrategy {
+    /// LXC 1.x, which only ships `lxc-clone`
+    LxcClone,
+    /// LXC 2.0 and later, where `lxc-copy` replaced `lxc-clone`
+    LxcCopy,
+}
+
+impl CloneStrategy {
+    pub fn for_version(version: LxcVersion) -> Result<Self, ContainerError> {
+        if version < MIN_CLONE_VERSION {
+            Err(ContainerError::Unsupported(format!(
+                "LXC >= {}.{} required for snapshot clone, found {}",
+                MIN_CLONE_VERSION.major, MIN_CLONE_VERSION.minor, version
+            )))
+        } else if version.major < 2 {
+            Ok(CloneStrategy::LxcClone)
+        } else {
+            Ok(CloneStrategy::LxcCopy)
+        }
+    }
+
+    /// Arguments for [`LxcCommand::execute`]
+    pub fn args(
+        self,
+        lxc_root: &Path,
+        source_container: &str,
+        snapshot_name: &str,
+        new_container_name: &str,
+    ) -> Vec<String> {
+        let snaps = lxc_root.join(source_container).join("snaps");
+        let (command, name_flag, new_name_flag) = match self {
+            CloneStrategy::LxcClone => ("clone", "-o", "-n"),
+            CloneStrategy::LxcCopy => ("copy", "-n", "-N"),
+        };
+        vec![
+            command.to_string(),
+            "-P".to_string(),
+            snaps.display().to_string(),
+            name_flag.to_string(),
+            snapshot_name.to_string(),
+            "-p".to_string(),
+            lxc_root.display().to_string(),
+            new_name_flag.to_string(),
+            new_container_name.to_string(),
+        ]
+    }
+}
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Snapshot {
@@ -16,6 +95,71 @@ pub struct Snapshot {
     pub comment: Option<String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub size_bytes: Option<u64>,
+    #[serde(default)]
+    pub size_state: SizeState,
+    /// Checksum recorded when the snapshot was created
+    #[serde(default)]
+    pub checksum: Option<String>,
+}
+
+/// Whether `size_bytes` reflects a finished computation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeState {
+    Computed,
+    #[default]
+    Pending,
+}
+
+/// Persisted result of a snapshot size computation
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct SizeMetadata {
+    pub size_bytes: u64,
+    /// True when the backend reported usage exclusive to this snapshot
+    pub exclusive: bool,
+    pub computed_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Persisted checksum of a snapshot's data
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct ChecksumMetadata {
+    /// SHA-256 over the path, size and SHA-256 of every entry
+    pub checksum: String,
+    pub files: u64,
+    pub computed_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Result of comparing a snapshot against its recorded checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrityStatus {
+    /// The data still matches the recorded checksum
+    Intact,
+    /// Files were changed, added or removed since the snapshot was taken
+    Corrupted,
+    /// No checksum was recorded, e.g. for snapshots taken before checksums
+    Unrecorded,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SnapshotVerification {
+    pub container_name: String,
+    pub snapshot_name: String,
+    pub status: IntegrityStatus,
+    pub expected: Option<String>,
+    pub actual: String,
+    pub files: u64,
+}
+
+/// Storage backend of a snapshot rootfs, taken from its LXC config
+#[derive(Debug, PartialEq, Eq)]
+enum RootfsBackend {
+    Dir(PathBuf),
+    /// Only the upper layer belongs to the snapshot
+    Overlay {
+        upper: PathBuf,
+    },
+    Btrfs(PathBuf),
 }
 
 pub struct SnapshotManager;
@@ -32,9 +176,20 @@ impl SnapshotManager {
             return Err(ContainerError::NotFound(container_name.to_string()));
         }
 
-        // Generate snapshot name if not provided
-        let snap_name = snapshot_name
-            .unwrap_or_else(|| format!("snap_{}", chrono::Utc::now().format("%Y%m%d_%H%M%S")));
+        // Generate snapshot name if not provided; the reservation keeps a
+        // concurrent create from picking the same one until ours exists
+        let (snap_name, _reservation) = match snapshot_name {
+            Some(name) => (name, None),
+            None => {
+                let existing = Self::names(container_name)?;
+                let reservation = NameReservation::new(container_name, Utc::now(), &existing);
+                (reservation.name.clone(), Some(reservation))
+            }
+        };
+
+        let _permit = OPERATION_LIMIT
+            .acquire(&format!("snapshot of {}", container_name))
+            .await?;
 
         info!(
             "Creating snapshot '{}' for container '{}'",
@@ -42,67 +197,158 @@ impl SnapshotManager {
         );
 
         // Use lxc-snapshot to create the snapshot
-        let args = if let Some(ref c) = comment {
-            vec!["snapshot", "-n", &snap_name, "-c", c, container_name]
-        } else {
-            vec!["snapshot", "-n", &snap_name, container_name]
-        };
-
-        LxcCommand::execute(&args).map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+        let mut args = vec!["snapshot".to_string(), "-n".to_string(), snap_name.clone()];
+        if let Some(ref c) = comment {
+            args.extend(["-c".to_string(), c.clone()]);
+        }
+        args.push(container_name.to_string());
+        blocking(move || {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            LxcCommand::execute(&args).map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))
+        })
+        .await?;
 
-        // Get snapshot size
+        // Walking a large rootfs takes a while; the size shows up in later listings
         let snapshot_path = Self::get_snapshot_path(container_name, &snap_name);
-        let size_bytes = Self::get_directory_size(&snapshot_path).ok();
-
+        Self::schedule_size_computation(snapshot_path.clone());
+
+        // The checksum has to describe the snapshot as taken, so wait for it
+        let checksum =
+            match tokio::task::spawn_blocking(move || compute_and_store_checksum(&snapshot_path))
+                .await
+            {
+                Ok(Ok(metadata)) => Some(metadata.checksum),
+                Ok(Err(e)) => {
+                    warn!("Failed to checksum snapshot '{}': {}", snap_name, e);
+                    None
+                }
+                Err(e) => {
+                    warn!("Snapshot checksum task failed: {}", e);
+                    None
+                }
+            };
+
+        EVENT_HISTORY.record(
+            container_name,
+            ContainerEventKind::Snapshotted,
+            Some(snap_name.clone()),
+        );
         Ok(Snapshot {
             id: Uuid::new_v4(),
             container_name: container_name.to_string(),
             name: snap_name,
             comment,
             created_at: Utc::now(),
-            size_bytes,
+            size_bytes: None,
+            size_state: SizeState::Pending,
+            checksum,
         })
     }
 
     /// List all snapshots for a container
-    pub async fn list(container_name: &str) -> Result<Vec<Snapshot>, ContainerError> {
+    ///
+    /// Sizes come from the cached metadata; snapshots without one are
+    /// reported as pending and computed in the background. With
+    /// `refresh_sizes` every size is recomputed before returning.
+    pub async fn list(
+        container_name: &str,
+        refresh_sizes: bool,
+    ) -> Result<Vec<Snapshot>, ContainerError> {
         if !LxcCommand::exists(container_name) {
             return Err(ContainerError::NotFound(container_name.to_string()));
         }
 
         info!("Listing snapshots for container '{}'", container_name);
 
-        // Use lxc-snapshot to list snapshots
+        let mut snapshots = Vec::new();
+
+        for snap_name in Self::names(container_name)? {
+            let snapshot_path = Self::get_snapshot_path(container_name, &snap_name);
+            let checksum = read_checksum_metadata(&snapshot_path).map(|m| m.checksum);
+            let metadata = if refresh_sizes {
+                Self::refresh_size(snapshot_path).await
+            } else {
+                let cached = read_size_metadata(&snapshot_path);
+                if cached.is_none() {
+                    Self::schedule_size_computation(snapshot_path);
+                }
+                cached
+            };
+
+            snapshots.push(Snapshot {
+                id: Uuid::new_v4(),
+                container_name: container_name.to_string(),
+                name: snap_name,
+                comment: None,
+                created_at: Utc::now(), // Would need to parse from metadata
+                size_bytes: metadata.as_ref().map(|m| m.size_bytes),
+                size_state: if metadata.is_some() {
+                    SizeState::Computed
+                } else {
+                    SizeState::Pending
+                },
+                checksum,
+            });
+        }
+
+        Ok(snapshots)
+    }
+
+    /// Names of the snapshots `lxc-snapshot -L` lists for a container
+    fn names(container_name: &str) -> Result<Vec<String>, ContainerError> {
         let output = LxcCommand::execute(&["snapshot", "-L", container_name])
             .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+        Ok(parse_snapshot_names(&output))
+    }
 
-        let mut snapshots = Vec::new();
+    /// Recompute the checksum of a snapshot and compare it with the one
+    /// recorded at creation
+    pub async fn verify(
+        container_name: &str,
+        snapshot_name: &str,
+    ) -> Result<SnapshotVerification, ContainerError> {
+        if !LxcCommand::exists(container_name) {
+            return Err(ContainerError::NotFound(container_name.to_string()));
+        }
+        let snapshot_path = Self::get_snapshot_path(container_name, snapshot_name);
+        if !snapshot_path.is_dir() {
+            return Err(ContainerError::NotFound(format!(
+                "{}/{}",
+                container_name, snapshot_name
+            )));
+        }
 
-        // Parse the snapshot list output
-        // LXC snapshot output format varies, but typically shows snapshot names
-        for line in output.lines() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with("List of") || line.starts_with("---") {
-                continue;
-            }
+        info!(
+            "Verifying snapshot '{}' of container '{}'",
+            snapshot_name, container_name
+        );
 
-            // Parse snapshot name (first word in the line)
-            if let Some(snap_name) = line.split_whitespace().next() {
-                let snapshot_path = Self::get_snapshot_path(container_name, snap_name);
-                let size_bytes = Self::get_directory_size(&snapshot_path).ok();
-
-                snapshots.push(Snapshot {
-                    id: Uuid::new_v4(),
-                    container_name: container_name.to_string(),
-                    name: snap_name.to_string(),
-                    comment: None,
-                    created_at: Utc::now(), // Would need to parse from metadata
-                    size_bytes,
-                });
-            }
+        let path = snapshot_path.clone();
+        let (actual, files) = tokio::task::spawn_blocking(move || compute_checksum(&path))
+            .await
+            .map_err(|e| ContainerError::Io(std::io::Error::other(e)))??;
+
+        let expected = read_checksum_metadata(&snapshot_path).map(|m| m.checksum);
+        let status = match &expected {
+            None => IntegrityStatus::Unrecorded,
+            Some(expected) if *expected == actual => IntegrityStatus::Intact,
+            Some(_) => IntegrityStatus::Corrupted,
+        };
+        if status == IntegrityStatus::Corrupted {
+            warn!(
+                "Snapshot '{}' of container '{}' does not match its checksum",
+                snapshot_name, container_name
+            );
         }
 
-        Ok(snapshots)
+        Ok(SnapshotVerification {
+            container_name: container_name.to_string(),
+            snapshot_name: snapshot_name.to_string(),
+            status,
+            expected,
+            actual,
+            files,
+        })
     }
 
     /// Restore a container from a snapshot
@@ -111,6 +357,10 @@ impl SnapshotManager {
             return Err(ContainerError::NotFound(container_name.to_string()));
         }
 
+        let _permit = OPERATION_LIMIT
+            .acquire(&format!("restore of {}", container_name))
+            .await?;
+
         info!(
             "Restoring container '{}' from snapshot '{}'",
             container_name, snapshot_name
@@ -157,23 +407,44 @@ impl SnapshotManager {
             ));
         }
 
+        let version =
+            LxcCommand::version().map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+        let strategy = CloneStrategy::for_version(version)?;
+
+        let _permit = OPERATION_LIMIT
+            .acquire(&format!("clone of {}", source_container))
+            .await?;
+
         info!(
-            "Cloning container '{}' from snapshot '{}' to '{}'",
-            source_container, snapshot_name, new_container_name
+            "Cloning container '{}' from snapshot '{}' to '{}' ({:?}, LXC {})",
+            source_container, snapshot_name, new_container_name, strategy, version
         );
 
-        // Use lxc-copy to clone from snapshot
-        // Format: lxc-copy -n source -s snapshot -N new_name
-        LxcCommand::execute(&[
-            "copy",
-            "-n",
+        let args = strategy.args(
+            &crate::config::LxcConfig::lxc_root(),
             source_container,
-            "-s",
             snapshot_name,
-            "-N",
             new_container_name,
-        ])
-        .map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+        );
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        LxcCommand::execute(&args).map_err(|e| ContainerError::LxcCommandFailed(e.to_string()))?;
+
+        if let Some(provenance) = Provenance::read(source_container) {
+            if let Err(e) = provenance.write(new_container_name) {
+                warn!(
+                    "Failed to record provenance of {}: {}",
+                    new_container_name, e
+                );
+            }
+        }
+        EVENT_HISTORY.record(
+            new_container_name,
+            ContainerEventKind::Created,
+            Some(format!(
+                "cloned from snapshot {} of {}",
+                snapshot_name, source_container
+            )),
+        );
 
         Ok(())
     }
@@ -187,8 +458,49 @@ impl SnapshotManager {
             .join(snapshot_name)
     }
 
+    /// Compute a snapshot size in the background unless one is already running
+    fn schedule_size_computation(snapshot_path: PathBuf) {
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        if !SIZE_JOBS.lock().unwrap().insert(snapshot_path.clone()) {
+            return;
+        }
+
+        handle.spawn_blocking(move || {
+            if let Err(e) = compute_and_store_size(&snapshot_path) {
+                warn!(
+                    "Failed to compute size of snapshot {}: {}",
+                    snapshot_path.display(),
+                    e
+                );
+            }
+            SIZE_JOBS.lock().unwrap().remove(&snapshot_path);
+        });
+    }
+
+    /// Recompute a snapshot size off the async executor and wait for it
+    async fn refresh_size(snapshot_path: PathBuf) -> Option<SizeMetadata> {
+        let path = snapshot_path.clone();
+        match tokio::task::spawn_blocking(move || compute_and_store_size(&path)).await {
+            Ok(Ok(metadata)) => Some(metadata),
+            Ok(Err(e)) => {
+                warn!(
+                    "Failed to compute size of snapshot {}: {}",
+                    snapshot_path.display(),
+                    e
+                );
+                read_size_metadata(&snapshot_path)
+            }
+            Err(e) => {
+                warn!("Snapshot size task failed: {}", e);
+                read_size_metadata(&snapshot_path)
+            }
+        }
+    }
+
     /// Calculate the size of a directory recursively
-    fn get_directory_size(path: &Path) -> Result<u64> {
+    pub(crate) fn get_directory_size(path: &Path) -> Result<u64> {
         let mut size = 0u64;
 
         if !path.exists() {
@@ -214,10 +526,283 @@ impl SnapshotManager {
     }
 }
 
+/// Snapshot names in `lxc-snapshot -L` output, the first word of each entry
+fn parse_snapshot_names(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("List of") && !line.starts_with("---"))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_string)
+        .collect()
+}
+
+/// `snap_<timestamp>` at second resolution, with `_2`, `_3`, ... appended
+/// while the name is `taken`
+fn unique_snapshot_name(now: DateTime<Utc>, taken: impl Fn(&str) -> bool) -> String {
+    let base = format!("snap_{}", now.format("%Y%m%d_%H%M%S"));
+    std::iter::once(base.clone())
+        .chain((2..).map(|n| format!("{}_{}", base, n)))
+        .find(|name| !taken(name))
+        .expect("unbounded suffixes")
+}
+
+/// A generated snapshot name held in [`RESERVED_NAMES`] until dropped
+struct NameReservation {
+    container: String,
+    name: String,
+}
+
+impl NameReservation {
+    /// Pick a name for a new snapshot of `container` that is neither in
+    /// `existing` nor reserved by another create
+    fn new(container: &str, now: DateTime<Utc>, existing: &[String]) -> Self {
+        let mut reserved = RESERVED_NAMES.lock().unwrap();
+        let name = unique_snapshot_name(now, |name| {
+            existing.iter().any(|existing| existing == name)
+                || reserved.contains(&(container.to_string(), name.to_string()))
+        });
+        reserved.insert((container.to_string(), name.clone()));
+        NameReservation {
+            container: container.to_string(),
+            name,
+        }
+    }
+}
+
+impl Drop for NameReservation {
+    fn drop(&mut self) {
+        RESERVED_NAMES.lock().unwrap().remove(&(
+            std::mem::take(&mut self.container),
+            std::mem::take(&mut self.name),
+        ));
+    }
+}
+
+fn read_size_metadata(snapshot_path: &Path) -> Option<SizeMetadata> {
+    let content = std::fs::read_to_string(snapshot_path.join(SIZE_METADATA_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn write_size_metadata(snapshot_path: &Path, metadata: &SizeMetadata) -> Result<()> {
+    let path = snapshot_path.join(SIZE_METADATA_FILE);
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(metadata)?)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+fn read_checksum_metadata(snapshot_path: &Path) -> Option<ChecksumMetadata> {
+    let content = std::fs::read_to_string(snapshot_path.join(CHECKSUM_METADATA_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Checksum a snapshot and persist the result next to it
+///
+/// Reads every file of the snapshot; run it on a blocking thread.
+fn compute_and_store_checksum(snapshot_path: &Path) -> std::io::Result<ChecksumMetadata> {
+    let (checksum, files) = compute_checksum(snapshot_path)?;
+    let metadata = ChecksumMetadata {
+        checksum,
+        files,
+        computed_at: Utc::now(),
+    };
+    let path = snapshot_path.join(CHECKSUM_METADATA_FILE);
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(&metadata)?)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(metadata)
+}
+
+/// Top-level hash of a snapshot directory and the number of entries in it
+///
+/// Every entry contributes its relative path, size and content hash (the
+/// target for symlinks), in path order, so any changed, added or removed
+/// file changes the result. The orchestrator's own sidecar files are left
+/// out.
+fn compute_checksum(snapshot_path: &Path) -> std::io::Result<(String, u64)> {
+    let mut entries = Vec::new();
+    collect_entries(snapshot_path, snapshot_path, &mut entries)?;
+    entries.sort();
+
+    let mut top = Sha256::new();
+    for entry in &entries {
+        top.update(entry.as_bytes());
+        top.update(b"\n");
+    }
+    Ok((to_hex(&top.finalize()), entries.len() as u64))
+}
+
+fn collect_entries(root: &Path, dir: &Path, entries: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_string_lossy();
+        // Also skips the sidecars' temporary files
+        if dir == root
+            && [SIZE_METADATA_FILE, CHECKSUM_METADATA_FILE]
+                .iter()
+                .any(|sidecar| relative.starts_with(sidecar))
+        {
+            continue;
+        }
+
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            entries.push(format!("{}/\0dir", relative));
+            collect_entries(root, &path, entries)?;
+        } else if file_type.is_symlink() {
+            let target = std::fs::read_link(&path)?;
+            entries.push(format!("{}\0link\0{}", relative, target.display()));
+        } else if file_type.is_file() {
+            let (size, hash) = hash_file(&path)?;
+            entries.push(format!("{}\0{}\0{}", relative, size, hash));
+        } else {
+            // Device nodes, fifos and sockets have no content to hash
+            entries.push(format!("{}\0special", relative));
+        }
+    }
+    Ok(())
+}
+
+fn hash_file(path: &Path) -> std::io::Result<(u64, String)> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    let mut size = 0u64;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        size += n as u64;
+        hasher.update(&buf[..n]);
+    }
+    Ok((size, to_hex(&hasher.finalize())))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Measure a snapshot and persist the result next to it
+///
+/// Blocks for as long as the backend needs; run it on a blocking thread.
+fn compute_and_store_size(snapshot_path: &Path) -> Result<SizeMetadata> {
+    let config = std::fs::read_to_string(snapshot_path.join("config")).unwrap_or_default();
+    let backend = parse_rootfs_backend(&config, snapshot_path);
+    debug!(
+        "Computing size of snapshot {} ({:?})",
+        snapshot_path.display(),
+        backend
+    );
+
+    let (size_bytes, exclusive) = match backend {
+        RootfsBackend::Dir(path) => (SnapshotManager::get_directory_size(&path)?, false),
+        RootfsBackend::Overlay { upper } => (SnapshotManager::get_directory_size(&upper)?, true),
+        RootfsBackend::Btrfs(path) => match btrfs_exclusive_size(&path) {
+            Ok(size) => (size, true),
+            Err(e) => {
+                debug!("btrfs usage unavailable for {}: {}", path.display(), e);
+                (SnapshotManager::get_directory_size(&path)?, false)
+            }
+        },
+    };
+
+    let metadata = SizeMetadata {
+        size_bytes,
+        exclusive,
+        computed_at: Utc::now(),
+    };
+    write_size_metadata(snapshot_path, &metadata)?;
+    Ok(metadata)
+}
+
+/// Work out where a snapshot's data lives from its LXC config
+///
+/// Falls back to the whole snapshot directory when the config names no
+/// rootfs.
+fn parse_rootfs_backend(config: &str, snapshot_path: &Path) -> RootfsBackend {
+    let rootfs = config.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        matches!(key.trim(), "lxc.rootfs.path" | "lxc.rootfs").then(|| value.trim())
+    });
+
+    let Some(rootfs) = rootfs else {
+        return RootfsBackend::Dir(snapshot_path.to_path_buf());
+    };
+
+    if let Some(layers) = rootfs
+        .strip_prefix("overlay:")
+        .or_else(|| rootfs.strip_prefix("overlayfs:"))
+    {
+        // overlay:<lower>:<upper>
+        if let Some((_, upper)) = layers.rsplit_once(':') {
+            return RootfsBackend::Overlay {
+                upper: PathBuf::from(upper),
+            };
+        }
+    }
+    if let Some(path) = rootfs.strip_prefix("btrfs:") {
+        return RootfsBackend::Btrfs(PathBuf::from(path));
+    }
+
+    let path = rootfs.strip_prefix("dir:").unwrap_or(rootfs);
+    RootfsBackend::Dir(PathBuf::from(path))
+}
+
+fn btrfs_exclusive_size(path: &Path) -> Result<u64> {
+    let output = Command::new("btrfs")
+        .args(["filesystem", "du", "-s", "--raw"])
+        .arg(path)
+        .output()
+        .context("failed to run btrfs")?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    parse_btrfs_exclusive(&String::from_utf8_lossy(&output.stdout))
+        .context("unexpected btrfs filesystem du output")
+}
+
+/// Parse the Exclusive column from `btrfs filesystem du -s --raw`
+fn parse_btrfs_exclusive(output: &str) -> Option<u64> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("Total"))
+        .nth(1)?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_clone_strategy_per_lxc_version() {
+        assert_eq!(
+            CloneStrategy::for_version(LxcVersion::new(1, 0, 8)).unwrap(),
+            CloneStrategy::LxcClone
+        );
+        assert_eq!(
+            CloneStrategy::for_version(LxcVersion::new(2, 0, 0)).unwrap(),
+            CloneStrategy::LxcCopy
+        );
+        assert_eq!(
+            CloneStrategy::for_version(LxcVersion::new(5, 0, 3)).unwrap(),
+            CloneStrategy::LxcCopy
+        );
+        match CloneStrategy::for_version(LxcVersion::new(0, 9, 0)) {
+            Err(ContainerError::Unsupported(message)) => assert_eq!(
+                message,
+                "LXC >= 1.0 required for snapshot clone, found 0.9.0"
+            ),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
     #[test]
     fn test_snapshot_path() {
         let path = SnapshotManager::get_snapshot_path("test-container", "snap1");
@@ -230,10 +815,125 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_rootfs_backend() {
+        let snap = Path::new("/var/lib/lxc/web/snaps/snap0");
+
+        assert_eq!(
+            parse_rootfs_backend(
+                "lxc.rootfs.path = overlay:/var/lib/lxc/base/rootfs:/var/lib/lxc/web/snaps/snap0/delta0\n",
+                snap
+            ),
+            RootfsBackend::Overlay {
+                upper: PathBuf::from("/var/lib/lxc/web/snaps/snap0/delta0")
+            }
+        );
+        assert_eq!(
+            parse_rootfs_backend(
+                "lxc.rootfs.path = btrfs:/var/lib/lxc/web/snaps/snap0/rootfs",
+                snap
+            ),
+            RootfsBackend::Btrfs(PathBuf::from("/var/lib/lxc/web/snaps/snap0/rootfs"))
+        );
+        assert_eq!(
+            parse_rootfs_backend(
+                "lxc.rootfs.path = dir:/var/lib/lxc/web/snaps/snap0/rootfs",
+                snap
+            ),
+            RootfsBackend::Dir(PathBuf::from("/var/lib/lxc/web/snaps/snap0/rootfs"))
+        );
+        assert_eq!(
+            parse_rootfs_backend("lxc.uts.name = web\n", snap),
+            RootfsBackend::Dir(snap.to_path_buf())
+        );
+    }
+
+    #[test]
+    fn test_parse_btrfs_exclusive() {
+        let output = "     Total   Exclusive  Set shared  Filename\n\
+                      5368709120    1048576  5367660544  /var/lib/lxc/web/snaps/snap0/rootfs\n";
+        assert_eq!(parse_btrfs_exclusive(output), Some(1048576));
+        assert_eq!(parse_btrfs_exclusive("ERROR: not a btrfs filesystem"), None);
+    }
+
+    #[test]
+    fn test_size_metadata_round_trip() {
+        let dir = std::env::temp_dir().join(format!("snapshot_size_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("rootfs")).unwrap();
+        std::fs::write(dir.join("rootfs/file"), vec![0u8; 4096]).unwrap();
+        std::fs::write(
+            dir.join("config"),
+            format!("lxc.rootfs.path = dir:{}\n", dir.join("rootfs").display()),
+        )
+        .unwrap();
+
+        assert!(read_size_metadata(&dir).is_none());
+        let computed = compute_and_store_size(&dir).unwrap();
+        assert_eq!(computed.size_bytes, 4096);
+        assert!(!computed.exclusive);
+        assert_eq!(read_size_metadata(&dir), Some(computed));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_checksum_detects_tampering() {
+        let dir = std::env::temp_dir().join(format!("snapshot_checksum_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("rootfs/etc")).unwrap();
+        std::fs::write(dir.join("config"), "lxc.uts.name = web\n").unwrap();
+        std::fs::write(dir.join("rootfs/etc/hostname"), "web\n").unwrap();
+
+        let recorded = compute_and_store_checksum(&dir).unwrap();
+        assert_eq!(read_checksum_metadata(&dir), Some(recorded.clone()));
+        // Sidecar files don't count towards the checksum
+        compute_and_store_size(&dir).unwrap();
+        assert_eq!(compute_checksum(&dir).unwrap().0, recorded.checksum);
+
+        std::fs::write(dir.join("rootfs/etc/hostname"), "wab\n").unwrap();
+        assert_ne!(compute_checksum(&dir).unwrap().0, recorded.checksum);
+
+        std::fs::write(dir.join("rootfs/etc/hostname"), "web\n").unwrap();
+        assert_eq!(compute_checksum(&dir).unwrap().0, recorded.checksum);
+        std::fs::write(dir.join("rootfs/etc/extra"), "").unwrap();
+        assert_ne!(compute_checksum(&dir).unwrap().0, recorded.checksum);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_snapshot_name_generation() {
-        let name = format!("snap_{}", chrono::Utc::now().format("%Y%m%d_%H%M%S"));
+        let name = unique_snapshot_name(Utc::now(), |_| false);
         assert!(name.starts_with("snap_"));
         assert!(name.len() > 5);
     }
+
+    #[test]
+    fn test_generated_names_within_one_second_are_distinct() {
+        let now = DateTime::parse_from_rfc3339("2026-10-16T12:00:00.250Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let container = format!("web-{}", Uuid::new_v4());
+        let listed = parse_snapshot_names("snap_20261016_115959\nsnap_20261016_120000\n");
+
+        let first = NameReservation::new(&container, now, &listed);
+        assert_eq!(first.name, "snap_20261016_120000_2");
+        let second = NameReservation::new(
+            &container,
+            now + chrono::Duration::milliseconds(500),
+            &listed,
+        );
+        assert_eq!(second.name, "snap_20261016_120000_3");
+
+        // Once created, the first snapshot is listed instead of reserved
+        let mut listed = listed;
+        listed.push(first.name.clone());
+        drop(first);
+        let third = NameReservation::new(&container, now, &listed);
+        assert_eq!(third.name, "snap_20261016_120000_4");
+
+        // Other containers are unaffected
+        let other = NameReservation::new(&format!("db-{}", Uuid::new_v4()), now, &[]);
+        assert_eq!(other.name, "snap_20261016_120000");
+        drop((second, third, other));
+    }
 }
@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,9 +12,33 @@ pub struct Container {
     pub node_id: Option<Uuid>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Seconds since the init process started, while running
+    #[serde(default)]
+    pub uptime_seconds: Option<u64>,
+    /// Options the container was created with, e.g. `release`
+    #[serde(default)]
+    pub template_options: HashMap<String, String>,
+    /// Architecture the container was built for, absent for containers
+    /// created before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    /// Outcome of the container's health check
+    #[serde(default)]
+    pub health: HealthStatus,
     pub config: ContainerConfig,
 }
 
+/// Whether the application inside a container answers its health check
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Healthy,
+    Unhealthy,
+    /// No check configured, not running, or not probed yet
+    #[default]
+    Unknown,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum ContainerStatus {
@@ -25,23 +50,219 @@ pub enum ContainerStatus {
     Error,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ContainerConfig {
     pub cpu_limit: Option<u32>,
     pub memory_limit: Option<u64>, // in bytes
-    pub disk_limit: Option<u64>,   // in bytes
+    /// Memory plus swap in bytes; at least `memory_limit`, or 0 to disable
+    /// swap. `None` leaves swap unbounded.
+    #[serde(default)]
+    pub memory_swap_limit: Option<u64>,
+    pub disk_limit: Option<u64>, // in bytes
     pub network_interfaces: Vec<ContainerNetworkInterface>,
     pub rootfs_path: String,
     pub environment: Vec<(String, String)>,
+    /// Secrets made available to the container; values are never part of the config
+    #[serde(default)]
+    pub secrets: Vec<SecretRef>,
+    /// Start with the host (`lxc.start.auto`)
+    #[serde(default)]
+    pub autostart: bool,
+    /// Lower values start earlier and stop later (`lxc.start.order`)
+    #[serde(default)]
+    pub start_order: i32,
+    /// Restrict outbound traffic; `None` leaves egress unrestricted
+    #[serde(default)]
+    pub egress_policy: Option<EgressPolicy>,
+    /// Adjustment of the OOM killer's badness score for the container's
+    /// processes (`lxc.proc.oom_score_adj`, -1000..=1000); higher values are
+    /// killed first
+    #[serde(default)]
+    pub oom_score_adj: Option<i32>,
+    /// Nameservers written to the container's `/etc/resolv.conf` on start;
+    /// empty falls back to the host's `network.dns_servers`
+    #[serde(default)]
+    pub dns_servers: Vec<String>,
+    #[serde(default)]
+    pub search_domains: Vec<String>,
+    /// Containers that must be running before this one starts
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Signal sent to the container's init on a clean stop
+    /// (`lxc.signal.stop`, e.g. `SIGINT`); `None` keeps LXC's default
+    #[serde(default)]
+    pub stop_signal: Option<String>,
+    /// Probe of the application inside, run while the container is running
+    #[serde(default)]
+    pub health_check: Option<HealthCheck>,
+    /// Mount the root filesystem read-only (`lxc.rootfs.options = ro`)
+    #[serde(default)]
+    pub readonly_rootfs: bool,
+    /// Directories kept writable on a read-only rootfs, each an empty tmpfs
+    /// on every start; they must already exist in the image
+    #[serde(default)]
+    pub writable_paths: Vec<String>,
+}
+
+/// Periodic probe telling whether a running container's application works
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HealthCheck {
+    #[serde(flatten)]
+    pub probe: HealthProbe,
+    #[serde(default = "default_health_interval")]
+    pub interval_secs: u64,
+    #[serde(default = "default_health_timeout")]
+    pub timeout_secs: u64,
+    /// Consecutive failed probes before the container is unhealthy
+    #[serde(default = "default_health_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Restart the container when it turns unhealthy
+    #[serde(default)]
+    pub restart_on_unhealthy: bool,
+}
+
+fn default_health_interval() -> u64 {
+    30
+}
+
+fn default_health_timeout() -> u64 {
+    5
+}
+
+fn default_health_failure_threshold() -> u32 {
+    3
 }
 
+/// How a health check probes the container
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum HealthProbe {
+    /// Run `command` inside the container with `lxc-attach`; exit status 0
+    /// is healthy
+    Exec { command: Vec<String> },
+    /// Connect to `port` on the container's first address
+    Tcp { port: u16 },
+}
+
+/// Outbound traffic policy enforced by the host firewall on a container's links
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EgressPolicy {
+    /// Drop all traffic that no `allow` entry matches
+    #[serde(default)]
+    pub default_drop: bool,
+    #[serde(default)]
+    pub allow: Vec<CidrPort>,
+}
+
+/// An allowed IPv4 destination network, optionally limited to one port
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CidrPort {
+    /// Address or network in CIDR notation, e.g. `10.0.0.0/8`
+    pub cidr: String,
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// `tcp` or `udp`; with a port and no protocol both are allowed
+    #[serde(default)]
+    pub protocol: Option<String>,
+}
+
+/// Reference to a secret whose value is stored encrypted outside the LXC config
+///
+/// At start time the value is written to `/etc/orchestrator/secrets/<name>`
+/// inside the container, readable only by root.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SecretRef {
+    pub name: String,
+}
+
+/// `ipv4` value asking for the next free address of the node's range
+pub const AUTO_ADDRESS: &str = "auto";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContainerNetworkInterface {
     pub name: String,
     pub bridge: String,
+    /// Address in CIDR notation, or [`AUTO_ADDRESS`]
     pub ipv4: Option<String>,
     pub ipv6: Option<String>,
     pub mac: Option<String>,
+    /// Default IPv4 gateway, inside the `ipv4` subnet, or [`AUTO_ADDRESS`]
+    /// for the bridge's address
+    #[serde(default)]
+    pub gateway_v4: Option<String>,
+    /// Default IPv6 gateway, inside the `ipv6` subnet, or [`AUTO_ADDRESS`]
+    #[serde(default)]
+    pub gateway_v6: Option<String>,
+    /// Static routes added on start, as `<cidr>` or `<cidr> via <address>`
+    #[serde(default)]
+    pub routes: Vec<String>,
+}
+
+/// A bind mount (`lxc.mount.entry`) attached to a container
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContainerMount {
+    pub source: String,
+    pub target: String,
+    pub fs_type: String,
+    pub options: String,
+}
+
+/// Point-in-time resource usage of a running container, as reported by LXC
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContainerUsage {
+    pub cpu_time_ns: Option<u64>,
+    pub memory_bytes: Option<u64>,
+    pub kmem_bytes: Option<u64>,
+    pub tx_bytes: Option<u64>,
+    pub rx_bytes: Option<u64>,
+    /// Seconds since the init process started
+    #[serde(default)]
+    pub uptime_seconds: Option<u64>,
+}
+
+/// A container moving from one state to another, as seen by the LXC monitor
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContainerStateChange {
+    pub name: String,
+    /// `None` for a container the monitor had not seen before
+    pub old_state: Option<ContainerStatus>,
+    pub new_state: ContainerStatus,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// What happened to a container in its lifecycle
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerEventKind {
+    Created,
+    Started,
+    Stopped,
+    Restarted,
+    Snapshotted,
+    /// The kernel killed a process of the container for exceeding its
+    /// memory limit
+    Oom,
+    Deleted,
+}
+
+/// An entry in a container's event history
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContainerEvent {
+    pub timestamp: DateTime<Utc>,
+    pub kind: ContainerEventKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Outcome of stopping every running container on a node
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StopAllSummary {
+    /// Shut down within the timeout
+    pub stopped: Vec<String>,
+    /// Still running after the timeout and killed
+    pub killed: Vec<String>,
+    /// Container name to error, for containers that could not be stopped
+    pub failed: std::collections::BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +270,48 @@ pub struct CreateContainerRequest {
     pub name: String,
     pub template: String,
     pub config: ContainerConfig,
+    /// Image to fetch with the `download` template; when set, `template` is ignored
+    #[serde(default)]
+    pub image: Option<ImageSpec>,
+    /// Template-specific options passed to `lxc-create` as `--key value`,
+    /// e.g. `release` for the debian template
+    #[serde(default)]
+    pub template_options: HashMap<String, String>,
+}
+
+/// Changes to an existing container; absent fields are left as they are
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateContainerRequest {
+    #[serde(default)]
+    pub oom_score_adj: Option<i32>,
+    /// Memory limit in bytes, applied on the next start
+    #[serde(default)]
+    pub memory_limit: Option<u64>,
+    /// Memory plus swap in bytes, or 0 to disable swap; applied on the next
+    /// start
+    #[serde(default)]
+    pub memory_swap_limit: Option<u64>,
+}
+
+/// Where an update's limits take effect, from `?apply=` on
+/// `PATCH /containers/{id}`
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApplyLimits {
+    /// The config file only, read on the next start
+    #[default]
+    Persistent,
+    /// The running container's cgroup only, lost on restart
+    Live,
+    Both,
+}
+
+/// A distribution image as understood by the LXC `download` template
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ImageSpec {
+    pub distro: String,
+    pub release: String,
+    pub arch: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1,19 +1,31 @@
 pub mod cluster;
 pub mod container;
+pub mod metrics;
 pub mod network;
 pub mod node;
 pub mod storage;
+pub mod validate;
 
 pub use cluster::*;
 pub use container::{
-    Container, ContainerConfig, ContainerListResponse, ContainerNetworkInterface,
-    ContainerResponse, ContainerStatus, CreateContainerRequest,
+    ApplyLimits, CidrPort, Container, ContainerConfig, ContainerEvent, ContainerEventKind,
+    ContainerListResponse, ContainerMount, ContainerNetworkInterface, ContainerResponse,
+    ContainerStateChange, ContainerStatus, ContainerUsage, CreateContainerRequest, EgressPolicy,
+    HealthCheck, HealthProbe, HealthStatus, ImageSpec, SecretRef, StopAllSummary,
+    UpdateContainerRequest, AUTO_ADDRESS,
 };
 pub use network::{
     Bridge, CreateBridgeRequest, InterfaceStatus, InterfaceType, NetworkInterface,
     NetworkListResponse,
 };
-pub use node::{JoinClusterRequest, Node, NodeListResponse, NodeResources, NodeStatus};
+pub use node::{
+    host_arch, normalize_arch, ContainerSummary, ContainerSummaryDelta, JoinClusterRequest, Node,
+    NodeHeartbeat, NodeListResponse, NodeResources, NodeStatus, NodeStoragePool, PeerLatency,
+};
 pub use storage::{
-    CreateStoragePoolRequest, StoragePool, StoragePoolListResponse, StorageType, Volume,
+    parse_cifs_path, parse_nfs_path, CifsPath, CreateStoragePoolRequest, NfsPath, PoolHealth,
+    PoolState, SetPoolStateRequest, StorageConnectionTestRequest, StorageConnectionTestResult,
+    StoragePool, StoragePoolBackend, StoragePoolListResponse, StorageType, Volume,
+    VolumeProvisioning,
 };
+pub use validate::{FieldError, Validate, ValidationErrors};
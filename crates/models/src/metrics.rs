@@ -0,0 +1,90 @@
+//! Metrics hook for the library crates
+//!
+//! Libraries report through the free functions here; nothing is recorded
+//! until the binary installs a [`MetricsSink`] with [`install`].
+
+use std::io;
+use std::process::{Command, Output};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Where the metrics reported by library crates end up
+pub trait MetricsSink: Send + Sync {
+    /// An external command finished, or failed to start
+    fn record_command(&self, command: &str, duration: Duration, success: bool);
+
+    /// A privileged command ran directly as root or through `sudo`
+    fn record_privilege_path(&self, sudo: bool);
+}
+
+static SINK: OnceLock<Arc<dyn MetricsSink>> = OnceLock::new();
+
+/// Send metrics to `sink`; only the first call has an effect
+pub fn install(sink: Arc<dyn MetricsSink>) -> bool {
+    SINK.set(sink).is_ok()
+}
+
+pub fn record_command(command: &str, duration: Duration, success: bool) {
+    if let Some(sink) = SINK.get() {
+        sink.record_command(command, duration, success);
+    }
+}
+
+pub fn record_privilege_path(sudo: bool) {
+    if let Some(sink) = SINK.get() {
+        sink.record_privilege_path(sudo);
+    }
+}
+
+/// `command.output()`, timed and recorded as `name`
+///
+/// Commands that exit non-zero or cannot be started count as failures.
+pub fn command_output(name: &str, command: &mut Command) -> io::Result<Output> {
+    let started = Instant::now();
+    let output = command.output();
+    let success = output.as_ref().is_ok_and(|o| o.status.success());
+    record_command(name, started.elapsed(), success);
+    output
+}
+
+/// [`command_output`] recorded under the name of the program run
+pub fn output(command: &mut Command) -> io::Result<Output> {
+    let name = command.get_program().to_string_lossy().into_owned();
+    command_output(&name, command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorded(Mutex<Vec<(String, bool)>>);
+
+    impl MetricsSink for Recorded {
+        fn record_command(&self, command: &str, _duration: Duration, success: bool) {
+            self.0.lock().unwrap().push((command.to_string(), success));
+        }
+
+        fn record_privilege_path(&self, _sudo: bool) {}
+    }
+
+    #[test]
+    fn test_command_output_is_recorded() {
+        let sink = Arc::new(Recorded::default());
+        assert!(install(sink.clone()));
+
+        assert!(command_output("true", &mut Command::new("true")).is_ok());
+        assert!(command_output("false", &mut Command::new("false")).is_ok());
+        assert!(command_output("missing", &mut Command::new("/nonexistent/cmd")).is_err());
+
+        assert_eq!(
+            *sink.0.lock().unwrap(),
+            [
+                ("true".to_string(), true),
+                ("false".to_string(), false),
+                ("missing".to_string(), false)
+            ]
+        );
+    }
+}
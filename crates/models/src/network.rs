@@ -7,6 +7,9 @@ pub struct NetworkInterface {
     pub status: InterfaceStatus,
     pub ip_addresses: Vec<String>,
     pub mac_address: Option<String>,
+    /// Bridge this interface is attached to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub master: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -16,6 +19,7 @@ pub enum InterfaceType {
     Physical,
     Vlan,
     Veth,
+    Loopback,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -1,7 +1,11 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
 
+use crate::container::{ContainerStatus, HealthStatus};
+use crate::storage::{PoolHealth, PoolState, StorageType};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Node {
     pub id: Uuid,
@@ -13,6 +17,30 @@ pub struct Node {
     pub resources: NodeResources,
     pub joined_at: DateTime<Utc>,
     pub last_seen: DateTime<Utc>,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// Cordoned nodes keep running their containers but receive no new placements
+    #[serde(default)]
+    pub cordoned: bool,
+    /// Result of the last ping from this node, absent until one has run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency: Option<PeerLatency>,
+    /// Pools the node reported in its last heartbeat
+    #[serde(default)]
+    pub storage_pools: Vec<NodeStoragePool>,
+}
+
+/// Round-trip measurement to a cluster peer
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PeerLatency {
+    /// Address the ping was sent to
+    pub address: String,
+    pub reachable: bool,
+    /// Round-trip time of the last successful ping
+    pub rtt_seconds: Option<f64>,
+    pub last_checked: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -31,6 +59,81 @@ pub struct NodeResources {
     pub memory_used: u64,  // in bytes
     pub disk_total: u64,   // in bytes
     pub disk_used: u64,    // in bytes
+    /// Cores already pinned to containers with exclusive CPU placement
+    #[serde(default)]
+    pub exclusive_cpus_allocated: u32,
+    /// CPU architecture as LXC names it, e.g. `arm64`; absent until the
+    /// node has sent a heartbeat
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+}
+
+/// The LXC name for a CPU architecture, so `aarch64` and `arm64` compare
+/// equal; unknown names are only lowercased
+pub fn normalize_arch(arch: &str) -> String {
+    let arch = arch.trim().to_ascii_lowercase();
+    match arch.as_str() {
+        "aarch64" | "arm64" => "arm64",
+        "x86_64" | "amd64" => "amd64",
+        "arm" | "armhf" | "armv7l" => "armhf",
+        "x86" | "i386" | "i686" => "i386",
+        "powerpc64" | "ppc64le" | "ppc64el" => "ppc64el",
+        _ => return arch,
+    }
+    .to_string()
+}
+
+/// Architecture of the host this process runs on
+pub fn host_arch() -> String {
+    normalize_arch(std::env::consts::ARCH)
+}
+
+/// A storage pool mounted on a node
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NodeStoragePool {
+    pub name: String,
+    pub storage_type: StorageType,
+    pub health: PoolHealth,
+    /// Read-only pools are reported so placements on them can be explained
+    #[serde(default)]
+    pub state: PoolState,
+}
+
+/// What a node reports about itself in reply to every cluster ping
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeHeartbeat {
+    pub resources: NodeResources,
+    #[serde(default)]
+    pub storage_pools: Vec<NodeStoragePool>,
+    /// Container changes since the version the asking node holds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub containers: Option<ContainerSummaryDelta>,
+}
+
+/// A container as reported on heartbeats
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerSummary {
+    pub name: String,
+    pub status: ContainerStatus,
+    #[serde(default)]
+    pub health: HealthStatus,
+}
+
+/// How a node's containers changed between two versions
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerSummaryDelta {
+    /// Version the containers are at once this is applied
+    pub version: u64,
+    /// Version this applies on top of; None when `updated` lists every
+    /// container
+    #[serde(default)]
+    pub base: Option<u64>,
+    /// Containers added or changed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub updated: Vec<ContainerSummary>,
+    /// Names of containers that are gone
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,4 +146,22 @@ pub struct JoinClusterRequest {
     pub cluster_name: String,
     pub node_address: String,
     pub node_port: u16,
+    /// The cluster join token, or one issued by `POST /cluster/join-tokens`
+    #[serde(default)]
+    pub join_token: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arch_aliases_normalize() {
+        assert_eq!(normalize_arch("aarch64"), "arm64");
+        assert_eq!(normalize_arch("ARM64"), "arm64");
+        assert_eq!(normalize_arch("x86_64"), "amd64");
+        assert_eq!(normalize_arch("i686"), "i386");
+        assert_eq!(normalize_arch("riscv64"), "riscv64");
+        assert_eq!(host_arch(), normalize_arch(&host_arch()));
+    }
 }
@@ -1,7 +1,9 @@
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
 use uuid::Uuid;
 
+use crate::validate::Validate;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoragePool {
     pub id: Uuid,
@@ -12,6 +14,58 @@ pub struct StoragePool {
     pub used_size: u64,      // in bytes
     pub available_size: u64, // in bytes
     pub created_at: DateTime<Utc>,
+    /// Degraded while usage is above the pool's warning threshold
+    #[serde(default)]
+    pub health: PoolHealth,
+    /// Used for volumes that do not ask for a mode of their own
+    #[serde(default)]
+    pub provisioning: VolumeProvisioning,
+    #[serde(default)]
+    pub state: PoolState,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PoolHealth {
+    #[default]
+    Healthy,
+    Degraded,
+}
+
+/// Whether a pool takes new data, set by an administrator
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolState {
+    #[default]
+    Active,
+    /// Existing volumes can be read and deleted; nothing new is created
+    ReadOnly,
+    /// Read-only, and left out of health alerts and scheduling
+    Maintenance,
+}
+
+impl PoolState {
+    /// Whether new volumes and root filesystems may be created on the pool
+    pub fn accepts_new_data(self) -> bool {
+        self == PoolState::Active
+    }
+}
+
+/// Body of `PUT /storage/{name}/state`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetPoolStateRequest {
+    pub state: PoolState,
+}
+
+/// How the space of a volume's backing file is allocated
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeProvisioning {
+    /// A sparse file; space is taken as data is written
+    #[default]
+    Thin,
+    /// The full size is reserved when the volume is created
+    Thick,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -29,7 +83,12 @@ pub struct Volume {
     pub pool_id: Uuid,
     pub size: u64, // in bytes
     pub used: u64, // in bytes
+    /// When `used` was measured; None while the first measurement is pending
+    #[serde(default)]
+    pub used_measured_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub provisioning: VolumeProvisioning,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,9 +96,242 @@ pub struct StoragePoolListResponse {
     pub pools: Vec<StoragePool>,
 }
 
+/// An NFS export, written `server:/export`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NfsPath {
+    pub server: String,
+    pub export: String,
+}
+
+/// A CIFS share, written `//server/share`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CifsPath {
+    pub server: String,
+    pub share: String,
+}
+
+/// Parse `server:/export`; `None` unless both parts are valid
+pub fn parse_nfs_path(path: &str) -> Option<NfsPath> {
+    let (server, export) = path.split_once(':')?;
+    let nfs = NfsPath {
+        server: server.to_string(),
+        export: export.to_string(),
+    };
+    nfs.validate().is_ok().then_some(nfs)
+}
+
+/// Parse `//server/share`; `None` unless both parts are valid
+pub fn parse_cifs_path(path: &str) -> Option<CifsPath> {
+    let (server, share) = path.strip_prefix("//")?.split_once('/')?;
+    let cifs = CifsPath {
+        server: server.to_string(),
+        share: share.to_string(),
+    };
+    cifs.validate().is_ok().then_some(cifs)
+}
+
+/// Type-specific settings of a new storage pool
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "storage_type", rename_all = "lowercase")]
+pub enum StoragePoolBackend {
+    Local {
+        path: String,
+    },
+    Nfs {
+        server: String,
+        export: String,
+        /// Mount options such as `vers=4.1` or `ro`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        options: Vec<String>,
+    },
+    Cifs {
+        server: String,
+        share: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        password: Option<String>,
+    },
+}
+
+impl StoragePoolBackend {
+    pub fn storage_type(&self) -> StorageType {
+        match self {
+            StoragePoolBackend::Local { .. } => StorageType::Local,
+            StoragePoolBackend::Nfs { .. } => StorageType::Nfs,
+            StoragePoolBackend::Cifs { .. } => StorageType::Cifs,
+        }
+    }
+}
+
+/// Request to check that a shared storage server can be reached, without
+/// creating a pool
+///
+/// `path` is written as in the flat pool request: `server:/export` for NFS
+/// and `//server/share` for CIFS.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CreateStoragePoolRequest {
-    pub name: String,
+pub struct StorageConnectionTestRequest {
     pub storage_type: StorageType,
     pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+/// Outcome of a connection test; `message` says what was found or why the
+/// server could not be used
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StorageConnectionTestResult {
+    pub success: bool,
+    pub message: String,
+}
+
+/// Request to create a storage pool
+///
+/// Besides the typed form (`storage_type` plus that type's fields) the
+/// older flat form with a single `path` is still accepted: `server:/export`
+/// for NFS and `//server/share` for CIFS.
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateStoragePoolRequest {
+    pub name: String,
+    #[serde(flatten)]
+    pub backend: StoragePoolBackend,
+    /// Default for the pool's volumes
+    #[serde(default)]
+    pub provisioning: VolumeProvisioning,
+}
+
+impl<'de> Deserialize<'de> for CreateStoragePoolRequest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Raw {
+            name: String,
+            storage_type: StorageType,
+            path: Option<String>,
+            server: Option<String>,
+            export: Option<String>,
+            #[serde(default)]
+            options: Vec<String>,
+            share: Option<String>,
+            username: Option<String>,
+            password: Option<String>,
+            #[serde(default)]
+            provisioning: VolumeProvisioning,
+        }
+
+        let raw = Raw::deserialize(deserializer)?;
+        let backend = match raw.storage_type {
+            StorageType::Local => StoragePoolBackend::Local {
+                path: raw.path.ok_or_else(|| D::Error::missing_field("path"))?,
+            },
+            StorageType::Nfs => {
+                let (server, export) = match (raw.server, raw.export, raw.path) {
+                    (Some(server), Some(export), _) => (server, export),
+                    (None, None, Some(path)) => parse_nfs_path(&path)
+                        .map(|nfs| (nfs.server, nfs.export))
+                        .ok_or_else(|| {
+                            D::Error::custom("invalid NFS path, expected server:/export")
+                        })?,
+                    _ => return Err(D::Error::custom("NFS pools need a server and an export")),
+                };
+                StoragePoolBackend::Nfs {
+                    server,
+                    export,
+                    options: raw.options,
+                }
+            }
+            StorageType::Cifs => {
+                let (server, share) = match (raw.server, raw.share, raw.path) {
+                    (Some(server), Some(share), _) => (server, share),
+                    (None, None, Some(path)) => parse_cifs_path(&path)
+                        .map(|cifs| (cifs.server, cifs.share))
+                        .ok_or_else(|| {
+                            D::Error::custom("invalid CIFS path, expected //server/share")
+                        })?,
+                    _ => return Err(D::Error::custom("CIFS pools need a server and a share")),
+                };
+                StoragePoolBackend::Cifs {
+                    server,
+                    share,
+                    username: raw.username,
+                    password: raw.password,
+                }
+            }
+        };
+
+        Ok(CreateStoragePoolRequest {
+            name: raw.name,
+            backend,
+            provisioning: raw.provisioning,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nfs_path_parsing() {
+        for path in [
+            "192.168.1.100:/exports/storage",
+            "storage.example.com:/data",
+            "nfs-server:/var/nfs/storage",
+        ] {
+            let nfs = parse_nfs_path(path).unwrap_or_else(|| panic!("{:?} should parse", path));
+            assert!(!nfs.server.is_empty());
+            assert!(nfs.export.starts_with('/'));
+        }
+        assert_eq!(
+            parse_nfs_path("nas:/pool"),
+            Some(NfsPath {
+                server: "nas".to_string(),
+                export: "/pool".to_string(),
+            })
+        );
+
+        for path in [
+            "invalid-path",
+            "/local/path",
+            "server-only",
+            ":export-only",
+            "nas:relative",
+            "my nas:/pool",
+        ] {
+            assert!(
+                parse_nfs_path(path).is_none(),
+                "{:?} should not parse",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_cifs_path_parsing() {
+        for path in [
+            "//192.168.1.100/share",
+            "//server.example.com/storage",
+            "//fileserver/data",
+        ] {
+            let cifs = parse_cifs_path(path).unwrap_or_else(|| panic!("{:?} should parse", path));
+            assert!(!cifs.server.is_empty());
+            assert!(!cifs.share.is_empty());
+        }
+
+        for path in [
+            "invalid-path",
+            "/local/path",
+            "//server-only",
+            "//server/",
+            "///share",
+            "//server/share/sub",
+        ] {
+            assert!(
+                parse_cifs_path(path).is_none(),
+                "{:?} should not parse",
+                path
+            );
+        }
+    }
 }
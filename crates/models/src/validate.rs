@@ -0,0 +1,997 @@
+//! Validation of names, paths and addresses taken from API requests
+//!
+//! The API rejects bad requests up front through [`Validate`]; the crates
+//! that pass these values to `lxc-*`, `ip` and `mount` call the same
+//! functions again before running anything.
+
+use crate::storage::{parse_cifs_path, parse_nfs_path, CifsPath, NfsPath};
+use crate::{
+    ContainerNetworkInterface, CreateBridgeRequest, CreateContainerRequest,
+    CreateStoragePoolRequest, ImageSpec, JoinClusterRequest, StorageConnectionTestRequest,
+    StoragePoolBackend, StorageType,
+};
+use serde::Serialize;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Directories that cannot hold storage
+const FORBIDDEN_STORAGE_ROOTS: [&str; 4] = ["/tmp", "/proc", "/sys", "/dev"];
+
+/// Options each `lxc-create` template is known to take as `--key value`
+pub const TEMPLATE_OPTIONS: [(&str, &[&str]); 5] = [
+    (
+        "download",
+        &["dist", "release", "arch", "variant", "server", "keyserver"],
+    ),
+    ("alpine", &["release", "arch", "repository"]),
+    (
+        "debian",
+        &["release", "arch", "mirror", "security-mirror", "packages"],
+    ),
+    (
+        "ubuntu",
+        &["release", "arch", "mirror", "security-mirror", "packages"],
+    ),
+    ("busybox", &[]),
+];
+
+/// Longest environment variable name accepted
+pub const MAX_ENV_KEY_LEN: usize = 256;
+
+/// Longest environment variable value accepted; LXC reads its config a line
+/// at a time and the container's environment block is small
+pub const MAX_ENV_VALUE_LEN: usize = 4096;
+
+/// A problem with one field of a request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    /// Path of the field, e.g. `config.network_interfaces[0].bridge`
+    pub field: String,
+    pub message: String,
+}
+
+/// Every problem found in a request
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    /// Record the error of `result`, if any, against `field`
+    pub fn check(&mut self, field: impl Into<String>, result: Result<(), String>) {
+        if let Err(message) = result {
+            self.errors.push(FieldError {
+                field: field.into(),
+                message,
+            });
+        }
+    }
+
+    /// Add the errors of a nested check
+    pub fn merge(&mut self, result: Result<(), ValidationErrors>) {
+        if let Err(other) = result {
+            self.errors.extend(other.errors);
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}: {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Requests that can be checked before anything is done with them
+pub trait Validate {
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+/// 1-64 lowercase letters, digits and hyphens, starting with a letter or digit
+pub fn container_name(name: &str) -> Result<(), String> {
+    slug(name, 64, |c| c.is_ascii_alphanumeric())
+}
+
+/// 1-32 lowercase letters, digits and hyphens, starting with a letter or digit
+pub fn pool_name(name: &str) -> Result<(), String> {
+    slug(name, 32, |c| c.is_ascii_alphanumeric())
+}
+
+/// 1-15 lowercase letters, digits and hyphens, starting with a letter; 15 is
+/// the kernel's limit for interface names
+pub fn bridge_name(name: &str) -> Result<(), String> {
+    slug(name, 15, |c| c.is_ascii_alphabetic())
+}
+
+/// Container network interface names follow the same rules as bridges
+pub fn interface_name(name: &str) -> Result<(), String> {
+    slug(name, 15, |c| c.is_ascii_alphabetic())
+}
+
+/// An absolute path outside `/tmp`, `/proc`, `/sys` and `/dev`
+pub fn storage_path(path: &str) -> Result<(), String> {
+    let path = Path::new(path);
+    if !path.is_absolute() {
+        return Err("must be an absolute path".to_string());
+    }
+    if FORBIDDEN_STORAGE_ROOTS
+        .iter()
+        .any(|root| path.starts_with(root))
+    {
+        return Err("is not suitable for storage".to_string());
+    }
+    Ok(())
+}
+
+/// An environment variable name: letters, digits and underscores, not
+/// starting with a digit, so it cannot carry `=` or a line break into
+/// `lxc.environment`
+pub fn env_key(key: &str) -> Result<(), String> {
+    if key.is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    if key.len() > MAX_ENV_KEY_LEN {
+        return Err(format!("must be at most {} characters", MAX_ENV_KEY_LEN));
+    }
+    let valid = !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!(
+            "{:?} must be letters, digits and underscores, not starting with a digit",
+            key
+        ));
+    }
+    Ok(())
+}
+
+/// An environment variable value; `=` and `#` are fine, control characters
+/// such as line breaks would end the config line early
+pub fn env_value(value: &str) -> Result<(), String> {
+    if value.len() > MAX_ENV_VALUE_LEN {
+        return Err(format!("must be at most {} bytes", MAX_ENV_VALUE_LEN));
+    }
+    if value.contains(char::is_control) {
+        return Err("must not contain control characters".to_string());
+    }
+    Ok(())
+}
+
+/// A MAC address as six colon-separated hex pairs; LXC fills in `x` digits
+/// randomly, e.g. `00:16:3e:xx:xx:xx`
+pub fn mac_address(mac: &str) -> Result<(), String> {
+    let octets: Vec<&str> = mac.split(':').collect();
+    let valid = octets.len() == 6
+        && octets.iter().all(|octet| {
+            octet.len() == 2
+                && octet
+                    .chars()
+                    .all(|c| c.is_ascii_hexdigit() || c == 'x' || c == 'X')
+        });
+    if !valid {
+        return Err(format!("{:?} is not a MAC address", mac));
+    }
+    Ok(())
+}
+
+/// A directory inside the container other than `/`, absolute and without
+/// `.`/`..` components or whitespace, which would split an `lxc.mount.entry`
+pub fn writable_path(path: &str) -> Result<(), String> {
+    if !path.starts_with('/') {
+        return Err("must be an absolute path".to_string());
+    }
+    if path.contains(|c: char| c.is_whitespace() || c.is_control()) {
+        return Err("must not contain whitespace".to_string());
+    }
+    let components: Vec<&str> = path.split('/').skip(1).collect();
+    if components.iter().all(|c| c.is_empty()) {
+        return Err("cannot be the root directory".to_string());
+    }
+    if components
+        .iter()
+        .any(|c| c.is_empty() || *c == "." || *c == "..")
+    {
+        return Err(format!("{:?} must be a normalized path", path));
+    }
+    Ok(())
+}
+
+/// An IPv4 or IPv6 address with a prefix length, e.g. `10.0.0.1/24`
+pub fn cidr(value: &str) -> Result<(), String> {
+    let invalid = || format!("{:?} is not an address in CIDR notation", value);
+    let (addr, prefix) = value.split_once('/').ok_or_else(invalid)?;
+    let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+    let prefix: u8 = prefix.parse().map_err(|_| invalid())?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    if prefix > max {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// A gateway for an interface addressed with `address` (CIDR or `auto`):
+/// `auto`, or an address inside the interface's subnet
+pub fn gateway(address: Option<&str>, gateway: &str) -> Result<(), String> {
+    if gateway == crate::AUTO_ADDRESS {
+        return Ok(());
+    }
+    let parsed: IpAddr = gateway
+        .parse()
+        .map_err(|_| format!("{:?} is not an address or \"auto\"", gateway))?;
+    match address {
+        None => Err("requires an address on the interface".to_string()),
+        // The subnet is only known once an address has been allocated
+        Some(crate::AUTO_ADDRESS) => Ok(()),
+        Some(address) if in_subnet(address, parsed) => Ok(()),
+        Some(address) => Err(format!("{} is outside {}", gateway, address)),
+    }
+}
+
+/// A static route: `<cidr>`, or `<cidr> via <address>` of the same family
+pub fn route(value: &str) -> Result<(), String> {
+    let invalid = || format!("{:?} is not \"<cidr>\" or \"<cidr> via <address>\"", value);
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    let (destination, via) = match fields.as_slice() {
+        [destination] => (*destination, None),
+        [destination, "via", via] => (*destination, Some(*via)),
+        _ => return Err(invalid()),
+    };
+    cidr(destination)?;
+    if let Some(via) = via {
+        let via: IpAddr = via.parse().map_err(|_| invalid())?;
+        let destination: IpAddr = destination
+            .split_once('/')
+            .unwrap_or_default()
+            .0
+            .parse()
+            .map_err(|_| invalid())?;
+        if via.is_ipv4() != destination.is_ipv4() {
+            return Err(format!("{:?} mixes IPv4 and IPv6", value));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `address` lies in the subnet of `network`, in CIDR notation
+fn in_subnet(network: &str, address: IpAddr) -> bool {
+    let Some((network, prefix)) = network.split_once('/') else {
+        return false;
+    };
+    let (Ok(network), Ok(prefix)) = (network.parse::<IpAddr>(), prefix.parse::<u32>()) else {
+        return false;
+    };
+    match (network, address) {
+        (IpAddr::V4(network), IpAddr::V4(address)) if prefix <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(network) & mask == u32::from(address) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(address)) if prefix <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            u128::from(network) & mask == u128::from(address) & mask
+        }
+        _ => false,
+    }
+}
+
+/// 802.1Q VLAN ids 1-4094
+pub fn vlan_id(id: u16) -> Result<(), String> {
+    if !(1..=4094).contains(&id) {
+        return Err(format!("{} is outside 1-4094", id));
+    }
+    Ok(())
+}
+
+/// A host name or address, without paths or whitespace
+pub fn host(host: &str) -> Result<(), String> {
+    if host.is_empty() || host.contains(|c: char| c == '/' || c.is_whitespace()) {
+        return Err(format!("{:?} is not a host", host));
+    }
+    Ok(())
+}
+
+/// 1-64 letters, digits, dots, underscores and hyphens, starting with a
+/// letter or digit: an image's distro, release or arch, each of which names
+/// a directory in the image cache, so `.` and `..` are refused
+pub fn image_component(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    if value.len() > 64 {
+        return Err("must be at most 64 characters".to_string());
+    }
+    let valid = value.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if !valid {
+        return Err(format!(
+            "{:?} must be letters, digits, '.', '_' and '-', starting with a letter or digit",
+            value
+        ));
+    }
+    Ok(())
+}
+
+/// A memory+swap limit covers the memory limit it is added to; 0 disables
+/// swap and needs no memory limit
+pub fn memory_swap_limit(memory_limit: Option<u64>, memory_swap_limit: u64) -> Result<(), String> {
+    match memory_limit {
+        _ if memory_swap_limit == 0 => Ok(()),
+        None => Err("requires memory_limit, or 0 to disable swap".to_string()),
+        Some(memory_limit) if memory_swap_limit < memory_limit => Err(format!(
+            "{} is below memory_limit ({}); use 0 to disable swap",
+            memory_swap_limit, memory_limit
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+/// An option `template` accepts, with a value that cannot be mistaken for
+/// another option
+pub fn template_option(template: &str, key: &str, value: &str) -> Result<(), String> {
+    let allowed = TEMPLATE_OPTIONS
+        .iter()
+        .find(|(name, _)| *name == template)
+        .map(|(_, keys)| *keys)
+        .unwrap_or_default();
+    if !allowed.contains(&key) {
+        return Err(format!(
+            "{:?} is not an option of the {} template",
+            key, template
+        ));
+    }
+    if value.is_empty()
+        || value.starts_with('-')
+        || value.contains(|c: char| c.is_whitespace() || c.is_control())
+    {
+        return Err(format!(
+            "{:?} must not be empty, start with '-' or contain whitespace",
+            value
+        ));
+    }
+    Ok(())
+}
+
+fn slug(name: &str, max_len: usize, first: impl Fn(char) -> bool) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    if name.len() > max_len {
+        return Err(format!("must be at most {} characters", max_len));
+    }
+    let valid = name.starts_with(first)
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !valid {
+        return Err(format!(
+            "{:?} must be lowercase letters, digits and hyphens, starting with a letter or digit",
+            name
+        ));
+    }
+    Ok(())
+}
+
+impl Validate for CreateContainerRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("name", container_name(&self.name));
+        for (i, (key, value)) in self.config.environment.iter().enumerate() {
+            errors.check(
+                format!("config.environment[{}]", i),
+                env_key(key).and_then(|()| env_value(value)),
+            );
+        }
+        for (i, interface) in self.config.network_interfaces.iter().enumerate() {
+            check_interface(
+                interface,
+                Some(&format!("config.network_interfaces[{}]", i)),
+                &mut errors,
+            );
+        }
+        if let Some(limit) = self.config.memory_swap_limit {
+            errors.check(
+                "config.memory_swap_limit",
+                memory_swap_limit(self.config.memory_limit, limit),
+            );
+        }
+        if !self.config.writable_paths.is_empty() && !self.config.readonly_rootfs {
+            errors.check(
+                "config.writable_paths",
+                Err("requires readonly_rootfs; the rootfs is writable".to_string()),
+            );
+        }
+        for (i, path) in self.config.writable_paths.iter().enumerate() {
+            let field = format!("config.writable_paths[{}]", i);
+            if self.config.writable_paths[..i].contains(path) {
+                errors.check(field, Err(format!("{} is listed twice", path)));
+            } else {
+                errors.check(field, writable_path(path));
+            }
+        }
+        for (i, dependency) in self.config.depends_on.iter().enumerate() {
+            let field = format!("config.depends_on[{}]", i);
+            if *dependency == self.name {
+                errors.check(field, Err("container cannot depend on itself".to_string()));
+            } else {
+                errors.check(field, container_name(dependency));
+            }
+        }
+        if let Some(ref image) = self.image {
+            check_image(image, Some("image"), &mut errors);
+        }
+        if self.image.is_some() && !self.template_options.is_empty() {
+            errors.check(
+                "template_options",
+                Err("cannot be combined with image, which sets the download options".to_string()),
+            );
+        } else {
+            let mut keys: Vec<&String> = self.template_options.keys().collect();
+            keys.sort();
+            for key in keys {
+                errors.check(
+                    format!("template_options.{}", key),
+                    template_option(&self.template, key, &self.template_options[key]),
+                );
+            }
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for ImageSpec {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        check_image(self, None, &mut errors);
+        errors.into_result()
+    }
+}
+
+fn check_image(image: &ImageSpec, parent: Option<&str>, errors: &mut ValidationErrors) {
+    for (name, value) in [
+        ("distro", &image.distro),
+        ("release", &image.release),
+        ("arch", &image.arch),
+    ] {
+        let field = match parent {
+            Some(parent) => format!("{}.{}", parent, name),
+            None => name.to_string(),
+        };
+        errors.check(field, image_component(value));
+    }
+}
+
+impl Validate for ContainerNetworkInterface {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        check_interface(self, None, &mut errors);
+        errors.into_result()
+    }
+}
+
+/// Check the interface's fields, named below `parent` when it is nested
+fn check_interface(
+    interface: &ContainerNetworkInterface,
+    parent: Option<&str>,
+    errors: &mut ValidationErrors,
+) {
+    let field = |name: &str| match parent {
+        Some(parent) => format!("{}.{}", parent, name),
+        None => name.to_string(),
+    };
+    errors.check(field("name"), interface_name(&interface.name));
+    errors.check(field("bridge"), bridge_name(&interface.bridge));
+    if let Some(ref mac) = interface.mac {
+        errors.check(field("mac"), mac_address(mac));
+    }
+    for (name, address) in [("ipv4", &interface.ipv4), ("ipv6", &interface.ipv6)] {
+        if let Some(address) = address {
+            if name == "ipv4" && address == crate::AUTO_ADDRESS {
+                continue;
+            }
+            errors.check(field(name), cidr(address));
+        }
+    }
+    for (name, address, gateway_of) in [
+        ("gateway_v4", &interface.ipv4, &interface.gateway_v4),
+        ("gateway_v6", &interface.ipv6, &interface.gateway_v6),
+    ] {
+        if let Some(value) = gateway_of {
+            errors.check(field(name), gateway(address.as_deref(), value));
+        }
+    }
+    for (i, value) in interface.routes.iter().enumerate() {
+        errors.check(field(&format!("routes[{}]", i)), route(value));
+    }
+}
+
+impl Validate for CreateBridgeRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("name", bridge_name(&self.name));
+        if let Some(ref ip) = self.ip_address {
+            errors.check("ip_address", cidr(ip));
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for CreateStoragePoolRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("name", pool_name(&self.name));
+        match self.backend {
+            StoragePoolBackend::Local { ref path } => errors.check("path", storage_path(path)),
+            StoragePoolBackend::Nfs {
+                ref server,
+                ref export,
+                ref options,
+            } => {
+                errors.merge(
+                    NfsPath {
+                        server: server.clone(),
+                        export: export.clone(),
+                    }
+                    .validate(),
+                );
+                for (i, option) in options.iter().enumerate() {
+                    if option.is_empty()
+                        || option.contains(',')
+                        || option.contains(char::is_whitespace)
+                    {
+                        errors.check(
+                            format!("options[{}]", i),
+                            Err(format!("{:?} is not a single mount option", option)),
+                        );
+                    }
+                }
+            }
+            StoragePoolBackend::Cifs {
+                ref server,
+                ref share,
+                ref username,
+                ref password,
+            } => {
+                errors.merge(
+                    CifsPath {
+                        server: server.clone(),
+                        share: share.clone(),
+                    }
+                    .validate(),
+                );
+                if password.is_some() && username.is_none() {
+                    errors.check("password", Err("requires a username".to_string()));
+                }
+            }
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for StorageConnectionTestRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        match self.storage_type {
+            StorageType::Local => errors.check(
+                "storage_type",
+                Err("only nfs and cifs storage can be tested".to_string()),
+            ),
+            StorageType::Nfs if parse_nfs_path(&self.path).is_none() => errors.check(
+                "path",
+                Err(format!("{:?} is not of the form server:/export", self.path)),
+            ),
+            StorageType::Cifs if parse_cifs_path(&self.path).is_none() => errors.check(
+                "path",
+                Err(format!("{:?} is not of the form //server/share", self.path)),
+            ),
+            _ => {}
+        }
+        if self.password.is_some() && self.username.is_none() {
+            errors.check("password", Err("requires a username".to_string()));
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for NfsPath {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("server", host(&self.server));
+        if !self.export.starts_with('/') {
+            errors.check("export", Err(format!("{:?} is not absolute", self.export)));
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for CifsPath {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.check("server", host(&self.server));
+        if self.share.is_empty() || self.share.contains('/') {
+            errors.check(
+                "share",
+                Err(format!("{:?} is not a share name", self.share)),
+            );
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for JoinClusterRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if self.cluster_name.trim().is_empty() {
+            errors.check("cluster_name", Err("must not be empty".to_string()));
+        }
+        errors.check("node_address", host(&self.node_address));
+        if self.node_port == 0 {
+            errors.check("node_port", Err("must not be 0".to_string()));
+        }
+        errors.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ContainerConfig;
+
+    #[test]
+    fn test_container_names() {
+        for name in ["test", "test-container", "test123", "web-server"] {
+            assert!(container_name(name).is_ok(), "{:?} should be valid", name);
+        }
+        for name in [
+            "",
+            "Test",
+            "test_container",
+            "test container",
+            "test.",
+            ".test",
+        ] {
+            assert!(
+                container_name(name).is_err(),
+                "{:?} should be invalid",
+                name
+            );
+        }
+        assert!(container_name(&"a".repeat(64)).is_ok());
+        assert!(container_name(&"a".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn test_pool_names() {
+        for name in ["pool1", "storage-pool", "test123", "vm-storage"] {
+            assert!(pool_name(name).is_ok(), "{:?} should be valid", name);
+        }
+        for name in [
+            "",
+            "Pool1",
+            "pool_name",
+            "pool name",
+            "pool.",
+            ".pool",
+            "pool-name-that-is-way-too-long-for-storage-pools-and-exceeds-reasonable-limits",
+        ] {
+            assert!(pool_name(name).is_err(), "{:?} should be invalid", name);
+        }
+    }
+
+    #[test]
+    fn test_bridge_names() {
+        for name in ["br0", "lxcbr0", "docker0", "test-bridge"] {
+            assert!(bridge_name(name).is_ok(), "{:?} should be valid", name);
+        }
+        for name in [
+            "",
+            "br0.",
+            ".br0",
+            "br 0",
+            "BR0",
+            "0br",
+            "bridge-name-that-is-way-too-long-for-linux-interfaces",
+        ] {
+            assert!(bridge_name(name).is_err(), "{:?} should be invalid", name);
+        }
+    }
+
+    #[test]
+    fn test_storage_paths() {
+        for path in [
+            "/var/lib/storage",
+            "/mnt/storage",
+            "/opt/storage/pools",
+            "/tmpfs",
+        ] {
+            assert!(storage_path(path).is_ok(), "{:?} should be valid", path);
+        }
+        for path in [
+            "",
+            "relative/path",
+            "/tmp",
+            "/tmp/pool",
+            "/proc/something",
+            "/sys/something",
+        ] {
+            assert!(storage_path(path).is_err(), "{:?} should be invalid", path);
+        }
+    }
+
+    #[test]
+    fn test_cidrs() {
+        for value in [
+            "192.168.1.1/24",
+            "10.0.0.1/8",
+            "172.16.0.1/16",
+            "fd00::1/64",
+        ] {
+            assert!(cidr(value).is_ok(), "{:?} should be valid", value);
+        }
+        for value in [
+            "192.168.1.1",
+            "192.168.1.256/24",
+            "not.an.ip/24",
+            "192.168.1.1/33",
+            "fd00::1/129",
+        ] {
+            assert!(cidr(value).is_err(), "{:?} should be invalid", value);
+        }
+    }
+
+    #[test]
+    fn test_gateways_must_be_inside_the_subnet() {
+        assert!(gateway(Some("10.0.3.10/24"), "10.0.3.1").is_ok());
+        assert!(gateway(Some("10.0.3.10/24"), "auto").is_ok());
+        assert!(gateway(Some("auto"), "10.0.3.1").is_ok());
+        assert!(gateway(Some("fd00::10/64"), "fd00::1").is_ok());
+        assert_eq!(
+            gateway(Some("10.0.3.10/24"), "10.0.4.1"),
+            Err("10.0.4.1 is outside 10.0.3.10/24".to_string())
+        );
+        assert!(gateway(Some("10.0.3.10/24"), "fd00::1").is_err());
+        assert!(gateway(Some("10.0.3.10/24"), "gateway").is_err());
+        assert!(gateway(None, "10.0.3.1").is_err());
+    }
+
+    #[test]
+    fn test_routes() {
+        for value in [
+            "10.1.0.0/16",
+            "10.1.0.0/16 via 10.0.3.254",
+            "fd01::/48 via fd00::fe",
+        ] {
+            assert!(route(value).is_ok(), "{:?} should be valid", value);
+        }
+        for value in [
+            "",
+            "10.1.0.0",
+            "10.1.0.0/16 via",
+            "10.1.0.0/16 dev eth0",
+            "10.1.0.0/16 via fd00::fe",
+            "10.1.0.0/16 via 10.0.3.254 extra",
+        ] {
+            assert!(route(value).is_err(), "{:?} should be invalid", value);
+        }
+    }
+
+    #[test]
+    fn test_vlan_ids() {
+        for id in [1, 100, 4094] {
+            assert!(vlan_id(id).is_ok());
+        }
+        for id in [0, 4095] {
+            assert!(vlan_id(id).is_err());
+        }
+    }
+
+    #[test]
+    fn test_image_components() {
+        for value in ["ubuntu", "22.04", "arm64", "9-Stream", "current_x"] {
+            assert!(
+                image_component(value).is_ok(),
+                "{:?} should be valid",
+                value
+            );
+        }
+        for value in ["", ".", "..", ".hidden", "-x", "a/b", "a:b", "a b"] {
+            assert!(
+                image_component(value).is_err(),
+                "{:?} should be invalid",
+                value
+            );
+        }
+
+        let image = ImageSpec {
+            distro: "..".to_string(),
+            release: "22.04".to_string(),
+            arch: "../../etc".to_string(),
+        };
+        let errors = image.validate().unwrap_err();
+        let fields: Vec<_> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["distro", "arch"]);
+    }
+
+    #[test]
+    fn test_template_options() {
+        assert!(template_option("debian", "release", "bookworm").is_ok());
+        assert!(template_option("download", "variant", "cloud").is_ok());
+        assert!(template_option("debian", "variant", "cloud").is_err());
+        assert!(template_option("busybox", "release", "1.36").is_err());
+        assert!(template_option("custom", "release", "1").is_err());
+        for value in ["", "--force", "a b", "x\n--y"] {
+            assert!(template_option("debian", "release", value).is_err());
+        }
+    }
+
+    #[test]
+    fn test_request_reports_every_bad_field() {
+        let mut request = CreateContainerRequest {
+            name: "Web_1".to_string(),
+            template: "alpine".to_string(),
+            image: None,
+            template_options: Default::default(),
+            config: ContainerConfig {
+                network_interfaces: vec![ContainerNetworkInterface {
+                    name: "eth0".to_string(),
+                    bridge: "lxcbr0".to_string(),
+                    ipv4: Some("10.0.3.300/24".to_string()),
+                    ipv6: None,
+                    mac: None,
+                    gateway_v4: None,
+                    gateway_v6: None,
+                    routes: vec![],
+                }],
+                ..Default::default()
+            },
+        };
+
+        let errors = request.validate().unwrap_err();
+        let fields: Vec<_> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["name", "config.network_interfaces[0].ipv4"]);
+
+        // IPv4 may be left to the node's address range
+        request.config.network_interfaces[0].ipv4 = Some(crate::AUTO_ADDRESS.to_string());
+        let errors = request.validate().unwrap_err();
+        assert_eq!(errors.errors.len(), 1);
+
+        request.name = "web".to_string();
+        request.config.depends_on = vec!["db".to_string(), "web".to_string()];
+        let errors = request.validate().unwrap_err();
+        assert_eq!(errors.errors[0].field, "config.depends_on[1]");
+
+        request.config.depends_on = vec![];
+        request.config.memory_limit = Some(1024);
+        request.config.memory_swap_limit = Some(512);
+        let errors = request.validate().unwrap_err();
+        assert_eq!(errors.errors[0].field, "config.memory_swap_limit");
+
+        request.config.memory_swap_limit = None;
+        request.config.environment = vec![
+            ("APP_ENV".to_string(), "prod".to_string()),
+            ("A".to_string(), "x\nlxc.hook.start = /bin/sh".to_string()),
+        ];
+        request.config.network_interfaces[0].mac = Some("00:16:3e".to_string());
+        let errors = request.validate().unwrap_err();
+        let fields: Vec<_> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            ["config.environment[1]", "config.network_interfaces[0].mac"]
+        );
+
+        request.config.environment = vec![];
+        request.config.network_interfaces[0].mac = None;
+        request.config.writable_paths = vec!["/var/log".to_string()];
+        let errors = request.validate().unwrap_err();
+        assert_eq!(errors.errors[0].field, "config.writable_paths");
+        request.config.readonly_rootfs = true;
+        assert!(request.validate().is_ok());
+    }
+
+    #[test]
+    fn test_writable_paths() {
+        for path in ["/tmp", "/var/log", "/run/app"] {
+            assert!(writable_path(path).is_ok(), "{} rejected", path);
+        }
+        for path in [
+            "",
+            "/",
+            "//",
+            "tmp",
+            "/var/../etc",
+            "/var//log",
+            "/var/log/",
+            "/a b",
+        ] {
+            assert!(writable_path(path).is_err(), "{} accepted", path);
+        }
+    }
+
+    #[test]
+    fn test_environment() {
+        for key in ["PATH", "_private", "APP_ENV2"] {
+            assert!(env_key(key).is_ok(), "{:?} rejected", key);
+        }
+        for key in ["", "2FA", "A=B", "A B", "A\nB", "É"] {
+            assert!(env_key(key).is_err(), "{:?} accepted", key);
+        }
+        assert!(env_key(&"K".repeat(MAX_ENV_KEY_LEN + 1)).is_err());
+
+        for value in ["", "a=b=c", "# not a comment", "with spaces"] {
+            assert!(env_value(value).is_ok(), "{:?} rejected", value);
+        }
+        for value in ["a\nlxc.hook.start = /bin/sh", "a\rb", "a\tb", "a\0b"] {
+            assert!(env_value(value).is_err(), "{:?} accepted", value);
+        }
+        assert!(env_value(&"v".repeat(MAX_ENV_VALUE_LEN)).is_ok());
+        assert!(env_value(&"v".repeat(MAX_ENV_VALUE_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_mac_addresses() {
+        for mac in [
+            "00:16:3e:00:00:01",
+            "00:16:3E:AB:cd:ef",
+            "00:16:3e:xx:xx:xx",
+        ] {
+            assert!(mac_address(mac).is_ok(), "{:?} rejected", mac);
+        }
+        for mac in [
+            "",
+            "00:16:3e:00:00",
+            "00:16:3e:00:00:01:02",
+            "00-16-3e-00-00-01",
+            "00:16:3e:00:00:0g",
+            "00:16:3e:00:00:01\nlxc.mount.auto = sys:rw",
+        ] {
+            assert!(mac_address(mac).is_err(), "{:?} accepted", mac);
+        }
+    }
+
+    #[test]
+    fn test_swap_limit_below_memory_is_rejected() {
+        const GIB: u64 = 1024 * 1024 * 1024;
+        assert!(memory_swap_limit(Some(GIB), 2 * GIB).is_ok());
+        assert!(memory_swap_limit(Some(GIB), GIB).is_ok());
+        assert!(memory_swap_limit(Some(GIB), 0).is_ok());
+        assert!(memory_swap_limit(None, 0).is_ok());
+        assert_eq!(
+            memory_swap_limit(Some(GIB), GIB / 2),
+            Err("536870912 is below memory_limit (1073741824); use 0 to disable swap".to_string())
+        );
+        assert!(memory_swap_limit(None, GIB).is_err());
+    }
+
+    #[test]
+    fn test_interface_fields_are_not_nested() {
+        let interface = ContainerNetworkInterface {
+            name: "eth 1".to_string(),
+            bridge: "lxcbr0".to_string(),
+            ipv4: Some("10.0.3.20".to_string()),
+            ipv6: None,
+            mac: None,
+            gateway_v4: None,
+            gateway_v6: None,
+            routes: vec![],
+        };
+
+        let errors = interface.validate().unwrap_err();
+        let fields: Vec<_> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["name", "ipv4"]);
+    }
+}
@@ -1,14 +1,57 @@
 use crate::error::NetworkError;
+use crate::interfaces::roll_back_link;
 use anyhow::{Context, Result};
-use models::{Bridge, CreateBridgeRequest};
+use models::{metrics, Bridge, CreateBridgeRequest, Validate};
+use std::collections::BTreeMap;
+use std::path::Path;
 use std::process::Command;
+use std::sync::Mutex;
 use tracing::{error, info};
 
+/// Bridges, and the interfaces attached to each, kept in memory instead of
+/// on the host, in development
+static FAKE_BRIDGES: Mutex<Option<BTreeMap<String, Vec<String>>>> = Mutex::new(None);
+
 pub struct BridgeManager;
 
 impl BridgeManager {
+    /// Keep bridges in memory from now on, starting with `bridges`, instead
+    /// of creating them on the host
+    pub fn use_fake_backend(bridges: &[&str]) {
+        let bridges = bridges
+            .iter()
+            .map(|bridge| (bridge.to_string(), Vec::new()))
+            .collect();
+        *FAKE_BRIDGES.lock().unwrap() = Some(bridges);
+    }
+
+    /// Run `f` on the fake bridges, if [`Self::use_fake_backend`] is in effect
+    fn with_fake<T>(f: impl FnOnce(&mut BTreeMap<String, Vec<String>>) -> T) -> Option<T> {
+        FAKE_BRIDGES.lock().unwrap().as_mut().map(f)
+    }
+
     /// Create a new Linux bridge
     pub async fn create(request: CreateBridgeRequest) -> Result<Bridge, NetworkError> {
+        request
+            .validate()
+            .map_err(|e| NetworkError::InvalidRequest(e.to_string()))?;
+        let faked = Self::with_fake(|bridges| {
+            if bridges.contains_key(&request.name) {
+                return Err(NetworkError::BridgeExists(request.name.clone()));
+            }
+            bridges.insert(request.name.clone(), Vec::new());
+            Ok(())
+        });
+        if let Some(result) = faked {
+            result?;
+            return Ok(Bridge {
+                name: request.name,
+                interfaces: vec![],
+                ip_address: request.ip_address,
+                stp_enabled: request.stp_enabled,
+            });
+        }
+        crate::capabilities::ensure_net_admin()?;
         info!("Creating bridge: {}", request.name);
 
         // Check if bridge already exists
@@ -17,10 +60,15 @@ impl BridgeManager {
         }
 
         // Create bridge using ip command
-        let output = Command::new("ip")
-            .args(["link", "add", "name", &request.name, "type", "bridge"])
-            .output()
-            .context("Failed to execute ip command")?;
+        let output = metrics::output(Command::new("ip").args([
+            "link",
+            "add",
+            "name",
+            &request.name,
+            "type",
+            "bridge",
+        ]))
+        .context("Failed to execute ip command")?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -28,25 +76,10 @@ impl BridgeManager {
             return Err(NetworkError::CommandFailed(stderr.to_string()));
         }
 
-        // Set STP if requested
-        if request.stp_enabled {
-            let output = Command::new("ip")
-                .args(["link", "set", &request.name, "type", "bridge", "stp", "on"])
-                .output()
-                .context("Failed to set STP")?;
-
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                error!("Failed to enable STP: {}", stderr);
-            }
-        }
-
-        // Bring bridge up
-        Self::set_up(&request.name).await?;
-
-        // Set IP address if provided
-        if let Some(ref ip) = request.ip_address {
-            Self::set_ip(&request.name, ip).await?;
+        // Anything failing from here on would leave a half-configured bridge
+        // behind that makes the next attempt fail with BridgeExists
+        if let Err(e) = Self::configure(&request).await {
+            return Err(roll_back_link(&request.name, e));
         }
 
         Ok(Bridge {
@@ -59,6 +92,12 @@ impl BridgeManager {
 
     /// Delete a bridge
     pub async fn delete(name: &str) -> Result<(), NetworkError> {
+        if let Some(removed) = Self::with_fake(|bridges| bridges.remove(name)) {
+            return removed
+                .map(|_| ())
+                .ok_or_else(|| NetworkError::InterfaceNotFound(name.to_string()));
+        }
+        crate::capabilities::ensure_net_admin()?;
         info!("Deleting bridge: {}", name);
 
         if !Self::exists(name)? {
@@ -69,9 +108,7 @@ impl BridgeManager {
         let _ = Self::set_down(name).await;
 
         // Delete bridge
-        let output = Command::new("ip")
-            .args(["link", "delete", name])
-            .output()
+        let output = metrics::output(Command::new("ip").args(["link", "delete", name]))
             .context("Failed to delete bridge")?;
 
         if !output.status.success() {
@@ -84,9 +121,10 @@ impl BridgeManager {
 
     /// Check if bridge exists
     pub fn exists(name: &str) -> Result<bool, NetworkError> {
-        let output = Command::new("ip")
-            .args(["link", "show", name])
-            .output()
+        if let Some(exists) = Self::with_fake(|bridges| bridges.contains_key(name)) {
+            return Ok(exists);
+        }
+        let output = metrics::output(Command::new("ip").args(["link", "show", name]))
             .context("Failed to check bridge")?;
 
         Ok(output.status.success())
@@ -94,10 +132,12 @@ impl BridgeManager {
 
     /// List all bridges
     pub async fn list() -> Result<Vec<String>, NetworkError> {
-        let output = Command::new("ip")
-            .args(["-br", "link", "show", "type", "bridge"])
-            .output()
-            .context("Failed to list bridges")?;
+        if let Some(bridges) = Self::with_fake(|bridges| bridges.keys().cloned().collect()) {
+            return Ok(bridges);
+        }
+        let output =
+            metrics::output(Command::new("ip").args(["-br", "link", "show", "type", "bridge"]))
+                .context("Failed to list bridges")?;
 
         if !output.status.success() {
             return Err(NetworkError::CommandFailed(
@@ -115,14 +155,55 @@ impl BridgeManager {
         Ok(bridges)
     }
 
+    /// Record `request` in the desired-state file at `path`, replacing any
+    /// earlier entry for the same bridge
+    pub fn record_desired(path: &Path, request: &CreateBridgeRequest) -> Result<(), NetworkError> {
+        let mut desired = Self::desired(path)?;
+        desired.retain(|existing| existing.name != request.name);
+        desired.push(request.clone());
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_vec_pretty(&desired)
+            .map_err(|e| NetworkError::OperationFailed(e.to_string()))?;
+        // Write then rename so an interrupted save keeps the previous file
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, content)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Bridges recorded with `record_desired`; a missing file means none
+    pub fn desired(path: &Path) -> Result<Vec<CreateBridgeRequest>, NetworkError> {
+        let content = match std::fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&content).map_err(|e| {
+            NetworkError::OperationFailed(format!("Invalid bridge state {}: {}", path.display(), e))
+        })
+    }
+
     /// Add interface to bridge
     pub async fn add_interface(bridge: &str, interface: &str) -> Result<(), NetworkError> {
+        let faked = Self::with_fake(|bridges| match bridges.get_mut(bridge) {
+            Some(interfaces) => {
+                interfaces.push(interface.to_string());
+                Ok(())
+            }
+            None => Err(NetworkError::InterfaceNotFound(bridge.to_string())),
+        });
+        if let Some(result) = faked {
+            return result;
+        }
+        crate::capabilities::ensure_net_admin()?;
         info!("Adding interface {} to bridge {}", interface, bridge);
 
-        let output = Command::new("ip")
-            .args(["link", "set", interface, "master", bridge])
-            .output()
-            .context("Failed to add interface to bridge")?;
+        let output =
+            metrics::output(Command::new("ip").args(["link", "set", interface, "master", bridge]))
+                .context("Failed to add interface to bridge")?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -134,12 +215,22 @@ impl BridgeManager {
 
     /// Remove interface from bridge
     pub async fn remove_interface(bridge: &str, interface: &str) -> Result<(), NetworkError> {
+        let faked = Self::with_fake(|bridges| match bridges.get_mut(bridge) {
+            Some(interfaces) => {
+                interfaces.retain(|i| i != interface);
+                Ok(())
+            }
+            None => Err(NetworkError::InterfaceNotFound(bridge.to_string())),
+        });
+        if let Some(result) = faked {
+            return result;
+        }
+        crate::capabilities::ensure_net_admin()?;
         info!("Removing interface {} from bridge {}", interface, bridge);
 
-        let output = Command::new("ip")
-            .args(["link", "set", interface, "nomaster"])
-            .output()
-            .context("Failed to remove interface from bridge")?;
+        let output =
+            metrics::output(Command::new("ip").args(["link", "set", interface, "nomaster"]))
+                .context("Failed to remove interface from bridge")?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -149,10 +240,38 @@ impl BridgeManager {
         Ok(())
     }
 
+    /// Configure a freshly created bridge as `request` asks
+    async fn configure(request: &CreateBridgeRequest) -> Result<(), NetworkError> {
+        if request.stp_enabled {
+            let output = metrics::output(Command::new("ip").args([
+                "link",
+                "set",
+                &request.name,
+                "type",
+                "bridge",
+                "stp",
+                "on",
+            ]))
+            .context("Failed to set STP")?;
+
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                error!("Failed to enable STP: {}", stderr);
+                return Err(NetworkError::CommandFailed(stderr.to_string()));
+            }
+        }
+
+        Self::set_up(&request.name).await?;
+
+        if let Some(ref ip) = request.ip_address {
+            Self::set_ip(&request.name, ip).await?;
+        }
+
+        Ok(())
+    }
+
     async fn set_up(name: &str) -> Result<(), NetworkError> {
-        let output = Command::new("ip")
-            .args(["link", "set", name, "up"])
-            .output()
+        let output = metrics::output(Command::new("ip").args(["link", "set", name, "up"]))
             .context("Failed to bring interface up")?;
 
         if !output.status.success() {
@@ -164,9 +283,7 @@ impl BridgeManager {
     }
 
     async fn set_down(name: &str) -> Result<(), NetworkError> {
-        let output = Command::new("ip")
-            .args(["link", "set", name, "down"])
-            .output()
+        let output = metrics::output(Command::new("ip").args(["link", "set", name, "down"]))
             .context("Failed to bring interface down")?;
 
         if !output.status.success() {
@@ -178,9 +295,7 @@ impl BridgeManager {
     }
 
     async fn set_ip(name: &str, ip: &str) -> Result<(), NetworkError> {
-        let output = Command::new("ip")
-            .args(["addr", "add", ip, "dev", name])
-            .output()
+        let output = metrics::output(Command::new("ip").args(["addr", "add", ip, "dev", name]))
             .context("Failed to set IP address")?;
 
         if !output.status.success() {
@@ -191,3 +306,83 @@ impl BridgeManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vlan::VlanManager;
+    use std::fs;
+    use test_support::FakeHost;
+
+    /// Existing links are files in `$IP_LINKS`
+    fn install_fake_ip(host: &FakeHost) {
+        host.script(
+            "ip",
+            r#"#!/bin/sh
+case "$*" in
+  *"$IP_FAIL"*) echo "injected failure: $*" >&2; exit 2 ;;
+esac
+case "$1 $2" in
+  "link show") [ -f "$IP_LINKS/$3" ] ;;
+  "link delete") rm "$IP_LINKS/$3" ;;
+  "link add")
+    while [ $# -gt 0 ]; do
+      if [ "$1" = name ]; then touch "$IP_LINKS/$2"; fi
+      shift
+    done ;;
+esac
+"#,
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_setup_removes_link() {
+        if !crate::capabilities::has_net_admin() {
+            eprintln!("skipping: CAP_NET_ADMIN is required before any command runs");
+            return;
+        }
+
+        let host = FakeHost::new("link_rollback");
+        let base = &host.base;
+        let links = base.join("links");
+        fs::create_dir_all(&links).unwrap();
+        install_fake_ip(&host);
+
+        host.env("IP_LINKS", &links);
+
+        let request = CreateBridgeRequest {
+            name: "hvbr0".to_string(),
+            ip_address: Some("10.0.0.1/24".to_string()),
+            stp_enabled: true,
+        };
+        for step in ["stp on", "hvbr0 up", "addr add"] {
+            host.env("IP_FAIL", step);
+            let err = BridgeManager::create(request.clone()).await.unwrap_err();
+            assert!(
+                matches!(err, NetworkError::CommandFailed(ref msg) if msg.contains(step)),
+                "{}: {}",
+                step,
+                err
+            );
+            assert!(!links.join("hvbr0").exists(), "{} left the bridge", step);
+        }
+
+        // The retry is not blocked by a leftover bridge
+        host.env("IP_FAIL", "never matches");
+        let bridge = BridgeManager::create(request).await.unwrap();
+        assert_eq!(bridge.name, "hvbr0");
+        assert!(links.join("hvbr0").exists());
+
+        host.env("IP_FAIL", "eth0.100 up");
+        let err = VlanManager::create("eth0", 100, None).await.unwrap_err();
+        assert!(matches!(err, NetworkError::CommandFailed(_)), "{}", err);
+        assert!(!links.join("eth0.100").exists());
+
+        host.env("IP_FAIL", "never matches");
+        assert_eq!(
+            VlanManager::create("eth0", 100, None).await.unwrap(),
+            "eth0.100"
+        );
+        assert!(links.join("eth0.100").exists());
+    }
+}
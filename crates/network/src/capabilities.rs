@@ -0,0 +1,56 @@
+/// Capability checks for host network changes
+///
+/// Network commands (`ip`, `iptables`) are run directly, never through sudo,
+/// so the process itself needs CAP_NET_ADMIN.
+use crate::error::NetworkError;
+
+/// Bit of CAP_NET_ADMIN in the capability sets
+pub const CAP_NET_ADMIN: u32 = 12;
+
+/// Whether this process may change host networking
+///
+/// Assumes yes when the effective set cannot be read, e.g. off Linux.
+pub fn has_net_admin() -> bool {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| effective_capabilities(&status))
+        .is_none_or(|caps| caps & (1 << CAP_NET_ADMIN) != 0)
+}
+
+/// Fail with a clear error before attempting a network change without
+/// CAP_NET_ADMIN
+pub fn ensure_net_admin() -> Result<(), NetworkError> {
+    if has_net_admin() {
+        Ok(())
+    } else {
+        Err(NetworkError::PermissionDenied(
+            "CAP_NET_ADMIN is required to change host networking; run as root or grant \
+             the capability (e.g. AmbientCapabilities=CAP_NET_ADMIN in the systemd unit)"
+                .to_string(),
+        ))
+    }
+}
+
+/// Parse the `CapEff` line of `/proc/<pid>/status`
+fn effective_capabilities(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|hex| u64::from_str_radix(hex.trim(), 16).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_capabilities() {
+        let root = "Name:\tapi-server\nCapInh:\t0000000000000000\nCapEff:\t000001ffffffffff\n";
+        let caps = effective_capabilities(root).unwrap();
+        assert_ne!(caps & (1 << CAP_NET_ADMIN), 0);
+
+        let user = "Name:\tapi-server\nCapEff:\t0000000000000000\n";
+        assert_eq!(effective_capabilities(user), Some(0));
+        assert_eq!(effective_capabilities("Name:\tapi-server\n"), None);
+    }
+}
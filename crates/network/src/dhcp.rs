@@ -0,0 +1,78 @@
+/// DHCP leases handed out on bridges by dnsmasq
+///
+/// LXC's `lxc-net` and most bridge setups run one dnsmasq per bridge with a
+/// lease file named after it. Leases are only read; the orchestrator does
+/// not run the DHCP server itself.
+use crate::error::NetworkError;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Directory dnsmasq keeps its per-bridge lease files in
+pub const LEASE_DIR: &str = "/var/lib/misc";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DhcpLease {
+    /// Unix time the lease expires, 0 for infinite leases
+    pub expires: u64,
+    pub mac_address: String,
+    pub ip_address: String,
+    pub hostname: Option<String>,
+}
+
+pub struct DhcpLeases;
+
+impl DhcpLeases {
+    pub fn lease_file(bridge: &str) -> PathBuf {
+        PathBuf::from(LEASE_DIR).join(format!("dnsmasq.{}.leases", bridge))
+    }
+
+    /// Active leases on `bridge`; a bridge without a lease file has none
+    pub fn for_bridge(bridge: &str) -> Result<Vec<DhcpLease>, NetworkError> {
+        match std::fs::read_to_string(Self::lease_file(bridge)) {
+            Ok(content) => Ok(parse_dnsmasq_leases(&content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Parse a dnsmasq lease file: `<expiry> <mac> <ip> <hostname|*> <client-id|*>`
+pub fn parse_dnsmasq_leases(content: &str) -> Vec<DhcpLease> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let expires = fields.next()?.parse().ok()?;
+            let mac_address = fields.next()?.to_string();
+            let ip_address = fields.next()?.to_string();
+            let hostname = fields
+                .next()
+                .filter(|name| *name != "*")
+                .map(str::to_string);
+            Some(DhcpLease {
+                expires,
+                mac_address,
+                ip_address,
+                hostname,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dnsmasq_leases() {
+        let content = "1700000600 00:16:3e:aa:bb:cc 10.0.3.57 web 01:00:16:3e:aa:bb:cc\n\
+                       0 00:16:3e:dd:ee:ff 10.0.3.58 * *\n\
+                       garbage\n";
+        let leases = parse_dnsmasq_leases(content);
+        assert_eq!(leases.len(), 2);
+        assert_eq!(leases[0].expires, 1_700_000_600);
+        assert_eq!(leases[0].ip_address, "10.0.3.57");
+        assert_eq!(leases[0].hostname.as_deref(), Some("web"));
+        assert_eq!(leases[1].hostname, None);
+    }
+}
@@ -8,6 +8,18 @@ pub enum NetworkError {
     #[error("Bridge already exists: {0}")]
     BridgeExists(String),
 
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("No free address left in {0}")]
+    AddressPoolExhausted(String),
+
+    #[error("Invalid firewall policy: {0}")]
+    InvalidPolicy(String),
+
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+
     #[error("Network operation failed: {0}")]
     OperationFailed(String),
 
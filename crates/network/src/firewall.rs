@@ -1,11 +1,297 @@
 use crate::error::NetworkError;
 use anyhow::Context;
-use std::process::Command;
-use tracing::{info, warn};
+use models::{metrics, CidrPort, EgressPolicy};
+use serde::Serialize;
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::{debug, info, warn};
+
+/// Chain holding every rule the orchestrator manages, jumped to from FORWARD
+///
+/// Keeping our rules in one chain means save/restore only ever touches them
+/// and leaves rules owned by other tools alone.
+pub const MANAGED_CHAIN: &str = "ARM-HYPERVISOR";
+
+/// Prefix of the per-interface chains implementing container egress policies
+pub const EGRESS_CHAIN_PREFIX: &str = "ARM-EGRESS-";
+
+/// Where the managed chain is saved after every change, if anywhere
+static RULES_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
 
 pub struct FirewallManager;
 
+/// One rule of the nat table, with the matches used to relate it to bridges
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NatRule {
+    pub chain: String,
+    /// The rule as `iptables-save` prints it, without `-A <chain>`
+    pub rule: String,
+    pub in_interface: Option<String>,
+    pub out_interface: Option<String>,
+    pub source: Option<String>,
+    pub destination: Option<String>,
+    /// DNAT target of a port forward
+    pub to_destination: Option<String>,
+}
+
+impl NatRule {
+    /// Whether the rule names `bridge` or matches addresses of one of its
+    /// `subnets` (CIDRs such as `10.0.3.1/24`)
+    pub fn applies_to(&self, bridge: &str, subnets: &[String]) -> bool {
+        if [&self.in_interface, &self.out_interface]
+            .into_iter()
+            .flatten()
+            .any(|interface| interface == bridge)
+        {
+            return true;
+        }
+        let subnets: Vec<(u32, u32)> = subnets.iter().filter_map(|s| ipv4_network(s)).collect();
+        [&self.source, &self.destination, &self.to_destination]
+            .into_iter()
+            .flatten()
+            .filter_map(|address| {
+                // Drop the port of `10.0.3.5:80` and a prefix length
+                let host = address.split([':', '/']).next()?;
+                host.parse::<Ipv4Addr>().ok().map(u32::from)
+            })
+            .any(|address| {
+                subnets
+                    .iter()
+                    .any(|(network, mask)| address & mask == *network)
+            })
+    }
+}
+
+/// Network and mask of an IPv4 CIDR
+fn ipv4_network(cidr: &str) -> Option<(u32, u32)> {
+    let (address, prefix) = cidr.split_once('/')?;
+    let address = u32::from(address.parse::<Ipv4Addr>().ok()?);
+    let prefix: u32 = prefix.parse().ok().filter(|p| *p <= 32)?;
+    let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+    Some((address & mask, mask))
+}
+
 impl FirewallManager {
+    /// Create the managed chain and the FORWARD jump into it, if missing
+    pub async fn ensure_managed_chain() -> Result<(), NetworkError> {
+        // -N fails if the chain already exists, which is fine
+        let _ = metrics::output(Command::new("iptables").args(["-N", MANAGED_CHAIN]))
+            .context("Failed to execute iptables command")?;
+
+        let jump_exists =
+            metrics::output(Command::new("iptables").args(["-C", "FORWARD", "-j", MANAGED_CHAIN]))
+                .context("Failed to execute iptables command")?
+                .status
+                .success();
+        if !jump_exists {
+            let output = metrics::output(Command::new("iptables").args([
+                "-I",
+                "FORWARD",
+                "-j",
+                MANAGED_CHAIN,
+            ]))
+            .context("Failed to execute iptables command")?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(NetworkError::CommandFailed(stderr.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Save the managed chain to `path` after every change made through
+    /// this manager, so rules survive a crash as well as a clean shutdown
+    pub fn persist_to(path: Option<PathBuf>) {
+        *RULES_FILE.lock().unwrap() = path;
+    }
+
+    /// Save the managed chain to the file set by [`Self::persist_to`], if any;
+    /// the change itself already took effect, so failures are only logged
+    async fn persist() {
+        let path = RULES_FILE.lock().unwrap().clone();
+        if let Some(path) = path {
+            if let Err(e) = Self::save(&path).await {
+                warn!("Failed to save firewall rules to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Save the managed chain's rules to `path` in iptables-restore format
+    pub async fn save(path: &Path) -> Result<(), NetworkError> {
+        info!(
+            "Saving firewall rules for chain {} to {}",
+            MANAGED_CHAIN,
+            path.display()
+        );
+
+        let output = metrics::output(Command::new("iptables-save").args(["-t", "filter"]))
+            .context("Failed to execute iptables-save")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(NetworkError::CommandFailed(stderr.to_string()));
+        }
+
+        let rules = Self::extract_managed_rules(&String::from_utf8_lossy(&output.stdout));
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Write then rename so an interrupted save keeps the previous file
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, rules)?;
+        std::fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+
+    /// Restore the managed chain from a file written by `save`
+    ///
+    /// Uses `--noflush` so only the managed chain is replaced.
+    pub async fn restore(path: &Path) -> Result<(), NetworkError> {
+        info!(
+            "Restoring firewall rules for chain {} from {}",
+            MANAGED_CHAIN,
+            path.display()
+        );
+
+        let rules = std::fs::read_to_string(path)?;
+        Self::ensure_managed_chain().await?;
+
+        let started = Instant::now();
+        let mut child = Command::new("iptables-restore")
+            .arg("--noflush")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to execute iptables-restore")?;
+        child
+            .stdin
+            .take()
+            .ok_or_else(|| NetworkError::OperationFailed("iptables-restore stdin".to_string()))?
+            .write_all(rules.as_bytes())?;
+
+        let output = child
+            .wait_with_output()
+            .context("Failed to wait for iptables-restore")?;
+        metrics::record_command(
+            "iptables-restore",
+            started.elapsed(),
+            output.status.success(),
+        );
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            warn!("Failed to restore firewall rules: {}", stderr);
+            return Err(NetworkError::CommandFailed(stderr.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Reduce `iptables-save` output to a filter table containing only the
+    /// managed chain and the egress chains it jumps to
+    pub fn extract_managed_rules(save_output: &str) -> String {
+        let is_managed =
+            |chain: &str| chain == MANAGED_CHAIN || chain.starts_with(EGRESS_CHAIN_PREFIX);
+
+        let mut egress_chains = Vec::new();
+        let mut appends = String::new();
+        let mut table = "";
+        for line in save_output.lines() {
+            if let Some(name) = line.strip_prefix('*') {
+                table = name;
+                continue;
+            }
+            if table != "filter" {
+                continue;
+            }
+            if let Some(declaration) = line.strip_prefix(':') {
+                let chain = declaration.split_whitespace().next().unwrap_or("");
+                if chain != MANAGED_CHAIN && is_managed(chain) {
+                    egress_chains.push(chain.to_string());
+                }
+                continue;
+            }
+            let chain = line
+                .strip_prefix("-A ")
+                .and_then(|rest| rest.split_whitespace().next());
+            if chain.is_some_and(is_managed) {
+                appends.push_str(line);
+                appends.push('\n');
+            }
+        }
+
+        let mut rules = format!("*filter\n:{} - [0:0]\n", MANAGED_CHAIN);
+        for chain in egress_chains {
+            rules.push_str(&format!(":{} - [0:0]\n", chain));
+        }
+        rules.push_str(&appends);
+        rules.push_str("COMMIT\n");
+
+        debug!("Extracted managed firewall rules:\n{}", rules);
+        rules
+    }
+
+    /// Rules of the nat table (masquerading and port forwards)
+    pub async fn nat_rules() -> Result<Vec<NatRule>, NetworkError> {
+        let output = metrics::output(Command::new("iptables-save").args(["-t", "nat"]))
+            .context("Failed to execute iptables-save")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(NetworkError::CommandFailed(stderr.to_string()));
+        }
+        Ok(Self::parse_nat_rules(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
+    }
+
+    /// Parse the `-A` lines of `iptables-save -t nat` output
+    pub fn parse_nat_rules(save_output: &str) -> Vec<NatRule> {
+        save_output
+            .lines()
+            .filter_map(|line| {
+                let (chain, rule) = line.strip_prefix("-A ")?.split_once(' ')?;
+                let words: Vec<&str> = rule.split_whitespace().collect();
+                // Negated matches (`! -d ...`) don't say where traffic goes
+                let option = |names: &[&str]| {
+                    words.windows(2).enumerate().find_map(|(i, pair)| {
+                        let negated = i > 0 && words[i - 1] == "!";
+                        (names.contains(&pair[0]) && !negated).then(|| pair[1].to_string())
+                    })
+                };
+                Some(NatRule {
+                    chain: chain.to_string(),
+                    rule: rule.to_string(),
+                    in_interface: option(&["-i", "--in-interface"]),
+                    out_interface: option(&["-o", "--out-interface"]),
+                    source: option(&["-s", "--source"]),
+                    destination: option(&["-d", "--destination"]),
+                    to_destination: option(&["--to-destination"]),
+                })
+            })
+            .collect()
+    }
+
+    /// Delete a rule listed by [`Self::nat_rules`]
+    pub async fn delete_nat_rule(rule: &NatRule) -> Result<(), NetworkError> {
+        info!("Deleting nat rule from chain {}: {}", rule.chain, rule.rule);
+
+        let mut args = vec!["-t", "nat", "-D", rule.chain.as_str()];
+        args.extend(rule.rule.split_whitespace());
+        let output = metrics::output(Command::new("iptables").args(&args))
+            .context("Failed to execute iptables command")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(NetworkError::CommandFailed(stderr.to_string()));
+        }
+        Ok(())
+    }
+
     /// Add an iptables rule
     pub async fn add_rule(chain: &str, rule: &[&str]) -> Result<(), NetworkError> {
         info!("Adding iptables rule to chain {}: {:?}", chain, rule);
@@ -13,9 +299,7 @@ impl FirewallManager {
         let mut args = vec!["-A", chain];
         args.extend(rule);
 
-        let output = Command::new("iptables")
-            .args(&args)
-            .output()
+        let output = metrics::output(Command::new("iptables").args(&args))
             .context("Failed to execute iptables command")?;
 
         if !output.status.success() {
@@ -24,6 +308,7 @@ impl FirewallManager {
             return Err(NetworkError::CommandFailed(stderr.to_string()));
         }
 
+        Self::persist().await;
         Ok(())
     }
 
@@ -34,9 +319,7 @@ impl FirewallManager {
         let mut args = vec!["-D", chain];
         args.extend(rule);
 
-        let output = Command::new("iptables")
-            .args(&args)
-            .output()
+        let output = metrics::output(Command::new("iptables").args(&args))
             .context("Failed to execute iptables command")?;
 
         if !output.status.success() {
@@ -44,20 +327,427 @@ impl FirewallManager {
             return Err(NetworkError::CommandFailed(stderr.to_string()));
         }
 
+        Self::persist().await;
         Ok(())
     }
 
     /// Allow traffic from a container interface
     pub async fn allow_container_interface(interface: &str) -> Result<(), NetworkError> {
-        Self::add_rule("FORWARD", &["-i", interface, "-j", "ACCEPT"]).await?;
-        Self::add_rule("FORWARD", &["-o", interface, "-j", "ACCEPT"]).await?;
+        Self::ensure_managed_chain().await?;
+        Self::add_rule(MANAGED_CHAIN, &["-i", interface, "-j", "ACCEPT"]).await?;
+        Self::add_rule(MANAGED_CHAIN, &["-o", interface, "-j", "ACCEPT"]).await?;
+        Ok(())
+    }
+
+    /// Name of the egress chain for a container's host-side interface
+    pub fn egress_chain(interface: &str) -> String {
+        format!("{}{}", EGRESS_CHAIN_PREFIX, interface)
+    }
+
+    /// Build the `iptables` argument lists that fill an egress chain
+    ///
+    /// Allowed destinations are accepted in order; with `default_drop` the
+    /// chain ends in a DROP, otherwise unmatched traffic returns to the
+    /// managed chain.
+    pub fn egress_rules(
+        interface: &str,
+        policy: &EgressPolicy,
+    ) -> Result<Vec<Vec<String>>, NetworkError> {
+        let chain = Self::egress_chain(interface);
+        let mut rules = Vec::new();
+
+        for allow in &policy.allow {
+            Self::validate_allow(allow)?;
+            let protocols: Vec<&str> = match (allow.protocol.as_deref(), allow.port) {
+                (Some(protocol), _) => vec![protocol],
+                (None, Some(_)) => vec!["tcp", "udp"],
+                (None, None) => vec![],
+            };
+
+            let mut rule = vec!["-A".to_string(), chain.clone(), "-d".to_string()];
+            rule.push(allow.cidr.clone());
+            if protocols.is_empty() {
+                rule.extend(["-j".to_string(), "ACCEPT".to_string()]);
+                rules.push(rule);
+                continue;
+            }
+            for protocol in protocols {
+                let mut rule = rule.clone();
+                rule.extend(["-p".to_string(), protocol.to_string()]);
+                if let Some(port) = allow.port {
+                    rule.extend(["--dport".to_string(), port.to_string()]);
+                }
+                rule.extend(["-j".to_string(), "ACCEPT".to_string()]);
+                rules.push(rule);
+            }
+        }
+
+        if policy.default_drop {
+            rules.push(vec![
+                "-A".to_string(),
+                chain,
+                "-j".to_string(),
+                "DROP".to_string(),
+            ]);
+        }
+
+        Ok(rules)
+    }
+
+    /// Check that every allow entry of a policy can be turned into rules
+    pub fn validate_egress(policy: &EgressPolicy) -> Result<(), NetworkError> {
+        policy.allow.iter().try_for_each(Self::validate_allow)
+    }
+
+    fn validate_allow(allow: &CidrPort) -> Result<(), NetworkError> {
+        let (address, prefix) = match allow.cidr.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (allow.cidr.as_str(), None),
+        };
+        let prefix_valid = prefix.is_none_or(|p| p.parse::<u8>().is_ok_and(|p| p <= 32));
+        if address.parse::<Ipv4Addr>().is_err() || !prefix_valid {
+            return Err(NetworkError::InvalidPolicy(format!(
+                "'{}' is not an IPv4 address or CIDR",
+                allow.cidr
+            )));
+        }
+
+        if let Some(ref protocol) = allow.protocol {
+            if protocol != "tcp" && protocol != "udp" {
+                return Err(NetworkError::InvalidPolicy(format!(
+                    "unsupported protocol '{}'",
+                    protocol
+                )));
+            }
+        }
+        if allow.port == Some(0) {
+            return Err(NetworkError::InvalidPolicy(
+                "port 0 is not valid".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Install an egress policy for traffic leaving a container through
+    /// `interface`, replacing any previous policy on it
+    pub async fn apply_egress(interface: &str, policy: &EgressPolicy) -> Result<(), NetworkError> {
+        crate::capabilities::ensure_net_admin()?;
+        let rules = Self::egress_rules(interface, policy)?;
+        let chain = Self::egress_chain(interface);
+        info!(
+            "Applying egress policy to {} ({} rule(s))",
+            interface,
+            rules.len()
+        );
+
+        Self::ensure_managed_chain().await?;
+        // -N fails if the chain already exists; it is flushed below either way
+        let _ = metrics::output(Command::new("iptables").args(["-N", &chain]))
+            .context("Failed to execute iptables command")?;
+        Self::iptables(&["-F", &chain])?;
+        for rule in &rules {
+            let args: Vec<&str> = rule.iter().map(String::as_str).collect();
+            Self::iptables(&args)?;
+        }
+
+        // Inserted first so the policy is evaluated before the interface's ACCEPT rules
+        let jump = ["-i", interface, "-j", chain.as_str()];
+        let jump_exists = metrics::output(
+            Command::new("iptables")
+                .args(["-C", MANAGED_CHAIN])
+                .args(jump),
+        )
+        .context("Failed to execute iptables command")?
+        .status
+        .success();
+        if !jump_exists {
+            Self::iptables(&[&["-I", MANAGED_CHAIN, "1"][..], &jump[..]].concat())?;
+        }
+
+        Self::persist().await;
+        Ok(())
+    }
+
+    /// Remove the egress policy for `interface`, if there is one
+    pub async fn remove_egress(interface: &str) -> Result<(), NetworkError> {
+        crate::capabilities::ensure_net_admin()?;
+        let chain = Self::egress_chain(interface);
+        info!("Removing egress policy from {}", interface);
+
+        // The jump and chain may already be gone; only a failing -X is an error
+        let _ = Self::iptables(&["-D", MANAGED_CHAIN, "-i", interface, "-j", &chain]);
+        let _ = Self::iptables(&["-F", &chain]);
+        let result = match Self::iptables(&["-X", &chain]) {
+            Err(NetworkError::CommandFailed(stderr)) if stderr.contains("No chain") => Ok(()),
+            result => result,
+        };
+        Self::persist().await;
+        result
+    }
+
+    fn iptables(args: &[&str]) -> Result<(), NetworkError> {
+        let output = metrics::output(Command::new("iptables").args(args))
+            .context("Failed to execute iptables command")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(NetworkError::CommandFailed(stderr.to_string()));
+        }
+
         Ok(())
     }
 
     /// Block traffic from a container interface
     pub async fn block_container_interface(interface: &str) -> Result<(), NetworkError> {
-        Self::delete_rule("FORWARD", &["-i", interface, "-j", "ACCEPT"]).await?;
-        Self::delete_rule("FORWARD", &["-o", interface, "-j", "ACCEPT"]).await?;
+        Self::delete_rule(MANAGED_CHAIN, &["-i", interface, "-j", "ACCEPT"]).await?;
+        Self::delete_rule(MANAGED_CHAIN, &["-o", interface, "-j", "ACCEPT"]).await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_support::FakeHost;
+
+    #[test]
+    fn test_nat_rules_are_tagged_to_bridges() {
+        let output = "*nat\n\
+                      :POSTROUTING ACCEPT [0:0]\n\
+                      -A POSTROUTING -s 10.0.3.0/24 ! -d 10.0.3.0/24 -j MASQUERADE\n\
+                      -A PREROUTING -i eth0 -p tcp -m tcp --dport 8080 -j DNAT --to-destination 10.0.3.5:80\n\
+                      -A POSTROUTING -o hvbr1 -j MASQUERADE\n\
+                      -A POSTROUTING -s 192.168.1.0/24 -j MASQUERADE\n\
+                      COMMIT\n";
+        let rules = FirewallManager::parse_nat_rules(output);
+        assert_eq!(rules.len(), 4);
+        assert_eq!(rules[0].destination, None);
+        assert_eq!(rules[1].to_destination.as_deref(), Some("10.0.3.5:80"));
+
+        let lxcbr0 = ["10.0.3.1/24".to_string()];
+        let tagged: Vec<bool> = rules
+            .iter()
+            .map(|rule| rule.applies_to("lxcbr0", &lxcbr0))
+            .collect();
+        assert_eq!(tagged, [true, true, false, false]);
+        assert!(rules[2].applies_to("hvbr1", &[]));
+    }
+
+    #[test]
+    fn test_extract_managed_rules_ignores_foreign_rules() {
+        let save_output = "# Generated by iptables-save\n\
+                           *nat\n\
+                           :PREROUTING ACCEPT [0:0]\n\
+                           -A ARM-HYPERVISOR -j RETURN\n\
+                           COMMIT\n\
+                           *filter\n\
+                           :INPUT ACCEPT [0:0]\n\
+                           :FORWARD ACCEPT [0:0]\n\
+                           :ARM-HYPERVISOR - [12:3456]\n\
+                           :DOCKER - [0:0]\n\
+                           -A FORWARD -j ARM-HYPERVISOR\n\
+                           -A FORWARD -j DOCKER\n\
+                           -A ARM-HYPERVISOR -i veth0 -j ACCEPT\n\
+                           -A ARM-HYPERVISOR -o veth0 -j ACCEPT\n\
+                           -A DOCKER -j RETURN\n\
+                           COMMIT\n";
+
+        assert_eq!(
+            FirewallManager::extract_managed_rules(save_output),
+            "*filter\n\
+             :ARM-HYPERVISOR - [0:0]\n\
+             -A ARM-HYPERVISOR -i veth0 -j ACCEPT\n\
+             -A ARM-HYPERVISOR -o veth0 -j ACCEPT\n\
+             COMMIT\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_managed_rules_keeps_egress_chains() {
+        let save_output = "*filter\n\
+                           :FORWARD ACCEPT [0:0]\n\
+                           :ARM-HYPERVISOR - [0:0]\n\
+                           :ARM-EGRESS-veth0 - [4:240]\n\
+                           -A ARM-HYPERVISOR -i veth0 -j ARM-EGRESS-veth0\n\
+                           -A ARM-EGRESS-veth0 -d 10.0.0.0/8 -j ACCEPT\n\
+                           -A ARM-EGRESS-veth0 -j DROP\n\
+                           COMMIT\n";
+
+        assert_eq!(
+            FirewallManager::extract_managed_rules(save_output),
+            "*filter\n\
+             :ARM-HYPERVISOR - [0:0]\n\
+             :ARM-EGRESS-veth0 - [0:0]\n\
+             -A ARM-HYPERVISOR -i veth0 -j ARM-EGRESS-veth0\n\
+             -A ARM-EGRESS-veth0 -d 10.0.0.0/8 -j ACCEPT\n\
+             -A ARM-EGRESS-veth0 -j DROP\n\
+             COMMIT\n"
+        );
+    }
+
+    #[test]
+    fn test_egress_rules_allow_list_with_default_drop() {
+        let policy = EgressPolicy {
+            default_drop: true,
+            allow: vec![
+                CidrPort {
+                    cidr: "10.0.0.0/8".to_string(),
+                    port: None,
+                    protocol: None,
+                },
+                CidrPort {
+                    cidr: "192.168.1.10".to_string(),
+                    port: Some(443),
+                    protocol: Some("tcp".to_string()),
+                },
+            ],
+        };
+
+        let rules: Vec<String> = FirewallManager::egress_rules("veth0", &policy)
+            .unwrap()
+            .iter()
+            .map(|rule| rule.join(" "))
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                "-A ARM-EGRESS-veth0 -d 10.0.0.0/8 -j ACCEPT",
+                "-A ARM-EGRESS-veth0 -d 192.168.1.10 -p tcp --dport 443 -j ACCEPT",
+                "-A ARM-EGRESS-veth0 -j DROP",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_egress_rules_port_without_protocol_and_validation() {
+        let policy = EgressPolicy {
+            default_drop: false,
+            allow: vec![CidrPort {
+                cidr: "1.1.1.1/32".to_string(),
+                port: Some(53),
+                protocol: None,
+            }],
+        };
+        let rules = FirewallManager::egress_rules("veth0", &policy).unwrap();
+        assert_eq!(rules.len(), 2);
+        assert!(rules[0].contains(&"tcp".to_string()));
+        assert!(rules[1].contains(&"udp".to_string()));
+
+        for cidr in ["10.0.0.0/33", "example.com", "fd00::/8"] {
+            let policy = EgressPolicy {
+                default_drop: true,
+                allow: vec![CidrPort {
+                    cidr: cidr.to_string(),
+                    port: None,
+                    protocol: None,
+                }],
+            };
+            assert!(
+                FirewallManager::egress_rules("veth0", &policy).is_err(),
+                "{} should be rejected",
+                cidr
+            );
+        }
+    }
+
+    #[test]
+    fn test_extract_managed_rules_empty_chain() {
+        assert_eq!(
+            FirewallManager::extract_managed_rules("*filter\n:FORWARD ACCEPT [0:0]\nCOMMIT\n"),
+            "*filter\n:ARM-HYPERVISOR - [0:0]\nCOMMIT\n"
+        );
+    }
+
+    /// Fake iptables binaries that keep their rules in `$IPTABLES_STATE`
+    fn install_fake_iptables(host: &FakeHost) {
+        // iptables: -N is a no-op, -C/-I/-A/-D operate on "-A <chain> <rule>" lines
+        host.script("iptables", r#"#!/bin/sh
+touch "$IPTABLES_STATE"
+op=$1; shift
+case "$op" in
+  -N) exit 0 ;;
+  -C) grep -qxF -- "-A $*" "$IPTABLES_STATE" ;;
+  -I|-A) echo "-A $*" >> "$IPTABLES_STATE" ;;
+  -D) grep -vxF -- "-A $*" "$IPTABLES_STATE" > "$IPTABLES_STATE.new"; mv "$IPTABLES_STATE.new" "$IPTABLES_STATE" ;;
+esac
+"#,
+        );
+        host.script(
+            "iptables-save",
+            r#"#!/bin/sh
+touch "$IPTABLES_STATE"
+echo "*filter"
+echo ":FORWARD ACCEPT [0:0]"
+echo ":ARM-HYPERVISOR - [3:180]"
+cat "$IPTABLES_STATE"
+echo "COMMIT"
+"#,
+        );
+        // Declaring a chain with --noflush flushes just that chain
+        host.script("iptables-restore", r#"#!/bin/sh
+touch "$IPTABLES_STATE"
+input=$(cat)
+echo "$input" | grep -q '^:ARM-HYPERVISOR ' && grep -v '^-A ARM-HYPERVISOR ' "$IPTABLES_STATE" > "$IPTABLES_STATE.new" && mv "$IPTABLES_STATE.new" "$IPTABLES_STATE"
+echo "$input" | grep '^-A ' >> "$IPTABLES_STATE"
+exit 0
+"#,
+        );
+    }
+
+    #[tokio::test]
+    async fn test_save_restore_round_trips_managed_chain() {
+        let host = FakeHost::new("fw");
+        let base = &host.base;
+        install_fake_iptables(&host);
+
+        let state = base.join("iptables.state");
+        host.env("IPTABLES_STATE", &state);
+
+        // Another tool's rule that must never end up in our saved file
+        std::fs::write(&state, "-A FORWARD -j DOCKER\n").unwrap();
+
+        FirewallManager::allow_container_interface("veth1")
+            .await
+            .unwrap();
+
+        let saved = base.join("firewall.rules");
+        FirewallManager::save(&saved).await.unwrap();
+        let contents = std::fs::read_to_string(&saved).unwrap();
+        assert!(contents.contains(&format!("-A {} -i veth1 -j ACCEPT", MANAGED_CHAIN)));
+        assert!(!contents.contains("DOCKER"));
+
+        // Simulate a reboot: all rules gone, other tools re-add theirs first
+        std::fs::write(&state, "-A FORWARD -j DOCKER\n").unwrap();
+
+        FirewallManager::restore(&saved).await.unwrap();
+        let after = std::fs::read_to_string(&state).unwrap();
+        assert!(after.contains("-A FORWARD -j DOCKER"));
+        assert!(after.contains(&format!("-A FORWARD -j {}", MANAGED_CHAIN)));
+        assert!(after.contains(&format!("-A {} -i veth1 -j ACCEPT", MANAGED_CHAIN)));
+        assert!(after.contains(&format!("-A {} -o veth1 -j ACCEPT", MANAGED_CHAIN)));
+
+        // Restoring again is idempotent
+        FirewallManager::restore(&saved).await.unwrap();
+        let again = std::fs::read_to_string(&state).unwrap();
+        assert_eq!(again.matches("-i veth1").count(), 1);
+        assert_eq!(again.matches(&format!("-j {}", MANAGED_CHAIN)).count(), 1);
+
+        // Once persisting, each change is saved as it happens, so a crash
+        // before shutdown keeps it
+        FirewallManager::persist_to(Some(saved.clone()));
+        FirewallManager::allow_container_interface("veth2")
+            .await
+            .unwrap();
+        let contents = std::fs::read_to_string(&saved).unwrap();
+        assert!(contents.contains(&format!("-A {} -o veth2 -j ACCEPT", MANAGED_CHAIN)));
+
+        FirewallManager::block_container_interface("veth2")
+            .await
+            .unwrap();
+        let contents = std::fs::read_to_string(&saved).unwrap();
+        assert!(!contents.contains("veth2"));
+        assert!(contents.contains("-i veth1"));
+        FirewallManager::persist_to(None);
+    }
+}
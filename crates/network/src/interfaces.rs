@@ -0,0 +1,155 @@
+use crate::error::NetworkError;
+use anyhow::Context;
+use models::{metrics, InterfaceStatus, InterfaceType, NetworkInterface};
+use serde::Deserialize;
+use std::process::Command;
+use tracing::{error, warn};
+
+pub struct InterfaceManager;
+
+/// Delete link `name` after a later step of creating it failed with `cause`
+///
+/// Returns `cause`, so the caller reports the step that failed rather than
+/// the cleanup. A failed cleanup is only logged.
+pub(crate) fn roll_back_link(name: &str, cause: NetworkError) -> NetworkError {
+    warn!("Removing link {} after failed setup: {}", name, cause);
+    match metrics::output(Command::new("ip").args(["link", "delete", name])) {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => error!(
+            "Could not remove link {}: {}",
+            name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => error!("Could not remove link {}: {}", name, e),
+    }
+    cause
+}
+
+/// One entry of `ip -j -d addr show`
+#[derive(Debug, Deserialize)]
+struct IpLink {
+    ifname: String,
+    #[serde(default)]
+    operstate: String,
+    #[serde(default)]
+    link_type: String,
+    address: Option<String>,
+    master: Option<String>,
+    linkinfo: Option<IpLinkInfo>,
+    #[serde(default)]
+    addr_info: Vec<IpAddr>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IpLinkInfo {
+    info_kind: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IpAddr {
+    local: String,
+    prefixlen: u8,
+}
+
+impl InterfaceManager {
+    /// List the host's network interfaces with their addresses
+    ///
+    /// An empty list means the host really has no (non-loopback) interfaces;
+    /// failing to query them is an error.
+    pub async fn list(include_loopback: bool) -> Result<Vec<NetworkInterface>, NetworkError> {
+        let output = metrics::output(Command::new("ip").args(["-j", "-d", "addr", "show"]))
+            .context("Failed to execute ip command")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            error!("Failed to list interfaces: {}", stderr);
+            return Err(NetworkError::CommandFailed(stderr.to_string()));
+        }
+
+        parse_ip_addr_json(&String::from_utf8_lossy(&output.stdout), include_loopback)
+    }
+}
+
+/// Parse the JSON output of `ip -j -d addr show`, skipping loopback unless
+/// `include_loopback` is set
+pub fn parse_ip_addr_json(
+    output: &str,
+    include_loopback: bool,
+) -> Result<Vec<NetworkInterface>, NetworkError> {
+    let links: Vec<IpLink> = serde_json::from_str(output)
+        .map_err(|e| NetworkError::OperationFailed(format!("Unexpected ip output: {}", e)))?;
+
+    Ok(links
+        .into_iter()
+        .filter(|link| include_loopback || link.link_type != "loopback")
+        .map(|link| NetworkInterface {
+            interface_type: match link
+                .linkinfo
+                .as_ref()
+                .and_then(|info| info.info_kind.as_deref())
+            {
+                _ if link.link_type == "loopback" => InterfaceType::Loopback,
+                Some("bridge") => InterfaceType::Bridge,
+                Some("vlan") => InterfaceType::Vlan,
+                Some("veth") => InterfaceType::Veth,
+                _ => InterfaceType::Physical,
+            },
+            status: match link.operstate.as_str() {
+                "UP" => InterfaceStatus::Up,
+                "DOWN" => InterfaceStatus::Down,
+                _ => InterfaceStatus::Unknown,
+            },
+            ip_addresses: link
+                .addr_info
+                .iter()
+                .map(|addr| format!("{}/{}", addr.local, addr.prefixlen))
+                .collect(),
+            mac_address: link.address,
+            master: link.master,
+            name: link.ifname,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ip_addr_json() {
+        let output = r#"[
+            {"ifname":"lo","operstate":"UNKNOWN","link_type":"loopback",
+             "address":"00:00:00:00:00:00",
+             "addr_info":[{"family":"inet","local":"127.0.0.1","prefixlen":8}]},
+            {"ifname":"eth0","operstate":"UP","link_type":"ether",
+             "address":"dc:a6:32:00:00:01",
+             "addr_info":[{"family":"inet","local":"10.0.0.2","prefixlen":24},
+                          {"family":"inet6","local":"fe80::1","prefixlen":64}]},
+            {"ifname":"lxcbr0","operstate":"DOWN","link_type":"ether",
+             "address":"00:16:3e:00:00:00","linkinfo":{"info_kind":"bridge"},
+             "addr_info":[]},
+            {"ifname":"veth1A2B3C","operstate":"UP","link_type":"ether","master":"lxcbr0",
+             "linkinfo":{"info_kind":"veth"},"addr_info":[]}
+        ]"#;
+
+        let interfaces = parse_ip_addr_json(output, false).unwrap();
+        assert_eq!(interfaces.len(), 3);
+        assert_eq!(interfaces[0].name, "eth0");
+        assert_eq!(interfaces[0].interface_type, InterfaceType::Physical);
+        assert_eq!(interfaces[0].status, InterfaceStatus::Up);
+        assert_eq!(interfaces[0].ip_addresses, ["10.0.0.2/24", "fe80::1/64"]);
+        assert_eq!(interfaces[1].interface_type, InterfaceType::Bridge);
+        assert_eq!(interfaces[1].status, InterfaceStatus::Down);
+        assert_eq!(interfaces[2].interface_type, InterfaceType::Veth);
+        assert_eq!(interfaces[2].master.as_deref(), Some("lxcbr0"));
+
+        let interfaces = parse_ip_addr_json(output, true).unwrap();
+        assert_eq!(interfaces.len(), 4);
+        assert_eq!(interfaces[0].name, "lo");
+        assert_eq!(interfaces[0].interface_type, InterfaceType::Loopback);
+        assert_eq!(interfaces[0].ip_addresses, ["127.0.0.1/8"]);
+
+        assert!(parse_ip_addr_json("[]", false).unwrap().is_empty());
+        assert!(parse_ip_addr_json("Object \"addr\" is unknown", false).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+/// IPv4 address management for container interfaces
+///
+/// Addresses are handed out from the node's `ip_range`, lowest free first,
+/// and recorded against the container they were given to. The network and
+/// broadcast addresses and the first host address, which the bridge uses as
+/// gateway, are never handed out. Allocations are saved after every change
+/// so they survive restarts.
+use crate::error::NetworkError;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::info;
+
+/// How much of the range is in use
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IpamUtilization {
+    pub range: String,
+    /// Addresses that can be handed out at all
+    pub total: u64,
+    pub allocated: u64,
+    pub available: u64,
+}
+
+pub struct Ipam {
+    network: u32,
+    prefix: u8,
+    path: PathBuf,
+    /// Address to the container holding it
+    allocations: Mutex<BTreeMap<Ipv4Addr, String>>,
+}
+
+impl Ipam {
+    /// Manage `range` (e.g. `192.168.100.0/24`), with allocations kept in
+    /// the file at `path`
+    pub fn open(range: &str, path: &Path) -> Result<Self, NetworkError> {
+        let (network, prefix) = parse_range(range)?;
+        let allocations: BTreeMap<Ipv4Addr, String> = match std::fs::read(path) {
+            Ok(content) => serde_json::from_slice(&content).map_err(|e| {
+                NetworkError::OperationFailed(format!(
+                    "Invalid IPAM state {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let ipam = Self {
+            network,
+            prefix,
+            path: path.to_path_buf(),
+            allocations: Mutex::new(BTreeMap::new()),
+        };
+        // Entries outside a range that has since changed are dropped
+        let allocations = allocations
+            .into_iter()
+            .filter(|(address, _)| ipam.assignable(*address))
+            .collect();
+        *ipam.allocations.lock().unwrap() = allocations;
+        Ok(ipam)
+    }
+
+    /// Give `owner` the lowest free address
+    pub fn allocate(&self, owner: &str) -> Result<Ipv4Addr, NetworkError> {
+        let mut allocations = self.allocations.lock().unwrap();
+        let address = self
+            .hosts()
+            .map(Ipv4Addr::from)
+            .find(|address| !allocations.contains_key(address))
+            .ok_or_else(|| NetworkError::AddressPoolExhausted(self.range()))?;
+
+        allocations.insert(address, owner.to_string());
+        if let Err(e) = self.save(&allocations) {
+            allocations.remove(&address);
+            return Err(e);
+        }
+        info!("Allocated {} to {}", address, owner);
+        Ok(address)
+    }
+
+    /// Free every address held by `owner`, returning them
+    pub fn release(&self, owner: &str) -> Result<Vec<Ipv4Addr>, NetworkError> {
+        let released = self.remove_where(|_, holder| holder == owner)?;
+        if !released.is_empty() {
+            info!("Released {:?} held by {}", released, owner);
+        }
+        Ok(released)
+    }
+
+    /// Free `addresses` whoever holds them, e.g. after the container they
+    /// were allocated for could not be created
+    pub fn free(&self, addresses: &[Ipv4Addr]) -> Result<(), NetworkError> {
+        self.remove_where(|address, _| addresses.contains(address))
+            .map(|_| ())
+    }
+
+    fn remove_where(
+        &self,
+        remove: impl Fn(&Ipv4Addr, &str) -> bool,
+    ) -> Result<Vec<Ipv4Addr>, NetworkError> {
+        let mut allocations = self.allocations.lock().unwrap();
+        let (removed, remaining): (BTreeMap<_, _>, BTreeMap<_, _>) = allocations
+            .iter()
+            .map(|(address, holder)| (*address, holder.clone()))
+            .partition(|(address, holder)| remove(address, holder));
+        if removed.is_empty() {
+            return Ok(vec![]);
+        }
+        self.save(&remaining)?;
+        *allocations = remaining;
+        Ok(removed.into_keys().collect())
+    }
+
+    pub fn utilization(&self) -> IpamUtilization {
+        let hosts = self.hosts();
+        let total = (hosts.end() - hosts.start() + 1) as u64;
+        let allocated = self.allocations.lock().unwrap().len() as u64;
+        IpamUtilization {
+            range: self.range(),
+            total,
+            allocated,
+            available: total.saturating_sub(allocated),
+        }
+    }
+
+    /// Current allocations, by address
+    pub fn allocations(&self) -> BTreeMap<Ipv4Addr, String> {
+        self.allocations.lock().unwrap().clone()
+    }
+
+    /// Prefix length of the range, for writing allocations in CIDR notation
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// The first host address, which the bridge holds and containers use
+    /// as their gateway
+    pub fn gateway(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.network + 1)
+    }
+
+    pub fn range(&self) -> String {
+        format!("{}/{}", Ipv4Addr::from(self.network), self.prefix)
+    }
+
+    /// Addresses that may be handed out, lowest first
+    fn hosts(&self) -> std::ops::RangeInclusive<u32> {
+        let broadcast = self.network | (u32::MAX >> self.prefix);
+        (self.network + 2)..=(broadcast - 1)
+    }
+
+    fn assignable(&self, address: Ipv4Addr) -> bool {
+        self.hosts().contains(&u32::from(address))
+    }
+
+    fn save(&self, allocations: &BTreeMap<Ipv4Addr, String>) -> Result<(), NetworkError> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_vec_pretty(allocations)
+            .map_err(|e| NetworkError::OperationFailed(e.to_string()))?;
+        // Write then rename so an interrupted save keeps the previous file
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, content)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Check that `range` can be managed by [`Ipam`]
+pub fn validate_range(range: &str) -> Result<(), NetworkError> {
+    parse_range(range).map(|_| ())
+}
+
+/// Network address and prefix length of an IPv4 range with room for at
+/// least one container besides the gateway
+fn parse_range(range: &str) -> Result<(u32, u8), NetworkError> {
+    let invalid =
+        |reason: &str| NetworkError::InvalidRequest(format!("IP range {:?} {}", range, reason));
+    let (address, prefix) = range
+        .split_once('/')
+        .ok_or_else(|| invalid("is not in CIDR notation"))?;
+    let address: Ipv4Addr = address
+        .parse()
+        .map_err(|_| invalid("is not an IPv4 range"))?;
+    let prefix: u8 = prefix
+        .parse()
+        .map_err(|_| invalid("has an invalid prefix length"))?;
+    if prefix > 29 {
+        return Err(invalid("is too small; use a /29 or larger"));
+    }
+    let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+    Ok((u32::from(address) & mask, prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_until_exhausted() {
+        let dir = std::env::temp_dir().join(format!("ipam-{}", std::process::id()));
+        let path = dir.join("ipam.json");
+        let ipam = Ipam::open("10.0.3.0/29", &path).unwrap();
+
+        // .0 is the network, .1 the gateway and .7 the broadcast address
+        let addresses: Vec<Ipv4Addr> = (2..7)
+            .map(|n| ipam.allocate(&format!("c{}", n)).unwrap())
+            .collect();
+        assert_eq!(addresses[0], Ipv4Addr::new(10, 0, 3, 2));
+        assert_eq!(addresses[4], Ipv4Addr::new(10, 0, 3, 6));
+        assert_eq!(ipam.gateway(), Ipv4Addr::new(10, 0, 3, 1));
+        assert!(matches!(
+            ipam.allocate("late"),
+            Err(NetworkError::AddressPoolExhausted(_))
+        ));
+
+        assert_eq!(ipam.release("c4").unwrap(), [Ipv4Addr::new(10, 0, 3, 4)]);
+        assert!(ipam.release("c4").unwrap().is_empty());
+        assert_eq!(ipam.allocate("late").unwrap(), Ipv4Addr::new(10, 0, 3, 4));
+        ipam.free(&[Ipv4Addr::new(10, 0, 3, 4)]).unwrap();
+        assert!(!ipam.allocations().values().any(|owner| owner == "late"));
+        ipam.allocate("late").unwrap();
+
+        // Allocations survive a restart
+        let reopened = Ipam::open("10.0.3.0/29", &path).unwrap();
+        assert_eq!(reopened.allocations(), ipam.allocations());
+        assert!(reopened.allocate("another").is_err());
+        let utilization = reopened.utilization();
+        assert_eq!((utilization.total, utilization.available), (5, 0));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            parse_range("192.168.100.17/24").unwrap(),
+            (u32::from(Ipv4Addr::new(192, 168, 100, 0)), 24)
+        );
+        for invalid in ["192.168.100.0", "10.0.0.0/30", "10.0.0.0/33", "fd00::/64"] {
+            assert!(parse_range(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Hot-plugging veth interfaces into running containers
+//!
+//! LXC only creates a container's interfaces when it starts. To add one to a
+//! running container a veth pair is created on the host, its host side
+//! enslaved to the bridge and its peer moved into the container's network
+//! namespace, where it is renamed and configured through `nsenter`.
+use crate::bridge::BridgeManager;
+use crate::error::NetworkError;
+use crate::interfaces::roll_back_link;
+use anyhow::Context;
+use models::{metrics, ContainerNetworkInterface, AUTO_ADDRESS};
+use serde::Deserialize;
+use std::process::Command;
+use tracing::info;
+use uuid::Uuid;
+
+pub struct VethManager;
+
+/// One entry of `ip -j link show`
+#[derive(Debug, Deserialize)]
+struct IpLink {
+    ifindex: u32,
+    ifname: String,
+    /// Index of the peer of a veth, in the peer's namespace
+    link_index: Option<u32>,
+}
+
+impl VethManager {
+    /// Plug `interface` into the running container whose init process is
+    /// `pid`; returns the name of the host side of the new veth pair
+    ///
+    /// If any step after creating the pair fails the pair is deleted again,
+    /// so no orphaned veth is left on the host.
+    pub async fn attach(
+        pid: u32,
+        interface: &ContainerNetworkInterface,
+    ) -> Result<String, NetworkError> {
+        crate::capabilities::ensure_net_admin()?;
+        if !BridgeManager::exists(&interface.bridge)? {
+            return Err(NetworkError::InterfaceNotFound(interface.bridge.clone()));
+        }
+
+        // Prefixed like LXC's own names; the peer is renamed inside the container
+        let suffix = &Uuid::new_v4().simple().to_string()[..8];
+        let host = format!("veth{}", suffix);
+        let peer = format!("vpeer{}", suffix);
+        info!(
+            "Attaching {} on bridge {} to container with PID {} (host side {})",
+            interface.name, interface.bridge, pid, host
+        );
+
+        let mut steps = Self::attach_steps(pid, &host, &peer, interface).into_iter();
+        if let Some(create) = steps.next() {
+            Self::run(&create)?;
+        }
+        for step in steps {
+            if let Err(e) = Self::run(&step) {
+                // Deleting the host side removes the peer too, wherever it is
+                return Err(roll_back_link(&host, e));
+            }
+        }
+        Ok(host)
+    }
+
+    /// Remove the interface called `name` from the running container whose
+    /// init process is `pid`; returns the host side of the removed pair when
+    /// it could be identified
+    pub async fn detach(pid: u32, name: &str) -> Result<Option<String>, NetworkError> {
+        crate::capabilities::ensure_net_admin()?;
+        info!("Detaching {} from container with PID {}", name, pid);
+
+        let pid = pid.to_string();
+        let inside: Vec<IpLink> = Self::json(&Self::nsenter(&pid, &["-j", "link", "show", name]))?;
+        let host = match inside.first().and_then(|link| link.link_index) {
+            Some(index) => Self::json::<Vec<IpLink>>(&Self::ip(&["-j", "link", "show"]))?
+                .into_iter()
+                .find(|link| link.ifindex == index)
+                .map(|link| link.ifname),
+            None => None,
+        };
+
+        Self::run(&Self::nsenter(&pid, &["link", "delete", name]))?;
+        Ok(host)
+    }
+
+    /// Delete a veth pair through its host side, taking the container's end
+    /// with it
+    pub async fn delete(host: &str) -> Result<(), NetworkError> {
+        crate::capabilities::ensure_net_admin()?;
+        info!("Deleting veth {}", host);
+        Self::run(&Self::ip(&["link", "delete", host]))
+    }
+
+    /// Commands plugging in `interface`, in order; the first creates the pair
+    pub fn attach_steps(
+        pid: u32,
+        host: &str,
+        peer: &str,
+        interface: &ContainerNetworkInterface,
+    ) -> Vec<Vec<String>> {
+        let pid = pid.to_string();
+        let name = interface.name.as_str();
+        let mut steps = vec![
+            Self::ip(&["link", "add", host, "type", "veth", "peer", "name", peer]),
+            Self::ip(&["link", "set", host, "master", &interface.bridge]),
+            Self::ip(&["link", "set", host, "up"]),
+            Self::ip(&["link", "set", peer, "netns", &pid]),
+        ];
+        let mut rename = vec!["link", "set", peer, "name", name];
+        if let Some(ref mac) = interface.mac {
+            rename.extend(["address", mac]);
+        }
+        steps.push(Self::nsenter(&pid, &rename));
+        for address in [&interface.ipv4, &interface.ipv6].into_iter().flatten() {
+            steps.push(Self::nsenter(&pid, &["addr", "add", address, "dev", name]));
+        }
+        steps.push(Self::nsenter(&pid, &["link", "set", name, "up"]));
+        // `auto` gateways need the bridge address and are left to LXC on
+        // the next start
+        for (family, gateway) in [("-4", &interface.gateway_v4), ("-6", &interface.gateway_v6)] {
+            if let Some(gateway) = gateway.as_deref().filter(|g| *g != AUTO_ADDRESS) {
+                steps.push(Self::nsenter(
+                    &pid,
+                    &[
+                        family, "route", "replace", "default", "via", gateway, "dev", name,
+                    ],
+                ));
+            }
+        }
+        for route in &interface.routes {
+            let mut args = vec!["route", "replace"];
+            args.extend(route.split_whitespace());
+            args.extend(["dev", name]);
+            steps.push(Self::nsenter(&pid, &args));
+        }
+        steps
+    }
+
+    fn ip(args: &[&str]) -> Vec<String> {
+        std::iter::once("ip")
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    /// `ip` run in the network namespace of `pid`
+    fn nsenter(pid: &str, args: &[&str]) -> Vec<String> {
+        ["nsenter", "-t", pid, "-n", "ip"]
+            .into_iter()
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    fn output(command: &[String]) -> Result<String, NetworkError> {
+        let output = metrics::output(Command::new(&command[0]).args(&command[1..]))
+            .with_context(|| format!("Failed to execute {}", command[0]))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(NetworkError::CommandFailed(stderr.to_string()));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn run(command: &[String]) -> Result<(), NetworkError> {
+        Self::output(command).map(drop)
+    }
+
+    fn json<T: serde::de::DeserializeOwned>(command: &[String]) -> Result<T, NetworkError> {
+        serde_json::from_str(&Self::output(command)?)
+            .map_err(|e| NetworkError::OperationFailed(format!("Unexpected ip output: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attach_steps() {
+        let interface = ContainerNetworkInterface {
+            name: "eth1".to_string(),
+            bridge: "hvbr1".to_string(),
+            ipv4: Some("10.1.0.5/24".to_string()),
+            ipv6: None,
+            mac: Some("00:16:3e:00:00:05".to_string()),
+            gateway_v4: Some("10.1.0.1".to_string()),
+            gateway_v6: None,
+            routes: vec!["10.2.0.0/16 via 10.1.0.254".to_string()],
+        };
+
+        let steps: Vec<String> =
+            VethManager::attach_steps(4242, "vethab12", "vpeerab12", &interface)
+                .into_iter()
+                .map(|step| step.join(" "))
+                .collect();
+        assert_eq!(
+            steps,
+            [
+                "ip link add vethab12 type veth peer name vpeerab12",
+                "ip link set vethab12 master hvbr1",
+                "ip link set vethab12 up",
+                "ip link set vpeerab12 netns 4242",
+                "nsenter -t 4242 -n ip link set vpeerab12 name eth1 address 00:16:3e:00:00:05",
+                "nsenter -t 4242 -n ip addr add 10.1.0.5/24 dev eth1",
+                "nsenter -t 4242 -n ip link set eth1 up",
+                "nsenter -t 4242 -n ip -4 route replace default via 10.1.0.1 dev eth1",
+                "nsenter -t 4242 -n ip route replace 10.2.0.0/16 via 10.1.0.254 dev eth1",
+            ]
+        );
+    }
+}
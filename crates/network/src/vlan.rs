@@ -1,5 +1,7 @@
 use crate::error::NetworkError;
+use crate::interfaces::roll_back_link;
 use anyhow::Context;
+use models::metrics;
 use std::process::Command;
 use tracing::info;
 
@@ -12,25 +14,25 @@ impl VlanManager {
         vlan_id: u16,
         name: Option<&str>,
     ) -> Result<String, NetworkError> {
+        models::validate::vlan_id(vlan_id).map_err(NetworkError::InvalidRequest)?;
+        crate::capabilities::ensure_net_admin()?;
         let default_name = format!("{}.{}", parent, vlan_id);
         let vlan_name = name.unwrap_or(&default_name);
         info!("Creating VLAN {} on interface {}", vlan_id, parent);
 
-        let output = Command::new("ip")
-            .args([
-                "link",
-                "add",
-                "link",
-                parent,
-                "name",
-                vlan_name,
-                "type",
-                "vlan",
-                "id",
-                &vlan_id.to_string(),
-            ])
-            .output()
-            .context("Failed to create VLAN")?;
+        let output = metrics::output(Command::new("ip").args([
+            "link",
+            "add",
+            "link",
+            parent,
+            "name",
+            vlan_name,
+            "type",
+            "vlan",
+            "id",
+            &vlan_id.to_string(),
+        ]))
+        .context("Failed to create VLAN")?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -38,14 +40,9 @@ impl VlanManager {
         }
 
         // Bring VLAN interface up
-        let output = Command::new("ip")
-            .args(["link", "set", vlan_name, "up"])
-            .output()
-            .context("Failed to bring VLAN up")?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(NetworkError::CommandFailed(stderr.to_string()));
+        // A VLAN that exists but is down would make the next attempt fail
+        if let Err(e) = Self::set_up(vlan_name) {
+            return Err(roll_back_link(vlan_name, e));
         }
 
         Ok(vlan_name.to_string())
@@ -53,11 +50,10 @@ impl VlanManager {
 
     /// Delete a VLAN interface
     pub async fn delete(name: &str) -> Result<(), NetworkError> {
+        crate::capabilities::ensure_net_admin()?;
         info!("Deleting VLAN: {}", name);
 
-        let output = Command::new("ip")
-            .args(["link", "delete", name])
-            .output()
+        let output = metrics::output(Command::new("ip").args(["link", "delete", name]))
             .context("Failed to delete VLAN")?;
 
         if !output.status.success() {
@@ -67,4 +63,16 @@ impl VlanManager {
 
         Ok(())
     }
+
+    fn set_up(name: &str) -> Result<(), NetworkError> {
+        let output = metrics::output(Command::new("ip").args(["link", "set", name, "up"]))
+            .context("Failed to bring VLAN up")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(NetworkError::CommandFailed(stderr.to_string()));
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,341 @@
+/// Reachability checks for NFS and CIFS servers, run before a pool is
+/// created so a wrong export or bad credentials show up with a useful
+/// message instead of a failed mount
+use std::future::Future;
+use std::io;
+use std::process::{Output, Stdio};
+use std::time::Duration;
+
+use models::{
+    parse_cifs_path, parse_nfs_path, StorageConnectionTestRequest, StorageConnectionTestResult,
+    StorageType, Validate,
+};
+use tracing::info;
+
+use crate::error::StorageError;
+
+/// How long a probe may take before the server is reported unreachable
+pub const CONNECTION_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A command that checks one server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeCommand {
+    pub program: &'static str,
+    pub args: Vec<String>,
+    /// Passed in the environment so credentials stay out of the process list
+    pub env: Vec<(&'static str, String)>,
+}
+
+/// What a probe looks for in the command's output
+enum Expectation {
+    /// `showmount -e` lists the export
+    Export { server: String, export: String },
+    /// `smbclient` connected to the share
+    Share { server: String, share: String },
+}
+
+/// Check the server `request` points at with `showmount` or `smbclient`
+pub async fn test_connection(
+    request: &StorageConnectionTestRequest,
+) -> Result<StorageConnectionTestResult, StorageError> {
+    test_connection_with(request, CONNECTION_TEST_TIMEOUT, run_probe).await
+}
+
+/// [`test_connection`] with the command runner and timeout supplied by the
+/// caller
+pub async fn test_connection_with<F, Fut>(
+    request: &StorageConnectionTestRequest,
+    timeout: Duration,
+    run: F,
+) -> Result<StorageConnectionTestResult, StorageError>
+where
+    F: FnOnce(ProbeCommand) -> Fut,
+    Fut: Future<Output = io::Result<Output>>,
+{
+    let (command, expectation) = probe_for(request)?;
+    let program = command.program;
+    info!("Testing storage connection to {}", request.path);
+
+    let result = match tokio::time::timeout(timeout, run(command)).await {
+        Err(_) => failure(format!(
+            "{} got no answer within {}s",
+            program,
+            timeout.as_secs()
+        )),
+        Ok(Err(e)) if e.kind() == io::ErrorKind::NotFound => {
+            failure(format!("{} is not installed on this node", program))
+        }
+        Ok(Err(e)) => failure(format!("Could not run {}: {}", program, e)),
+        Ok(Ok(output)) => interpret(&expectation, &output),
+    };
+    Ok(result)
+}
+
+fn probe_for(
+    request: &StorageConnectionTestRequest,
+) -> Result<(ProbeCommand, Expectation), StorageError> {
+    request
+        .validate()
+        .map_err(|e| StorageError::InvalidRequest(e.to_string()))?;
+    let invalid = || StorageError::InvalidRequest(format!("Invalid path: {}", request.path));
+
+    match request.storage_type {
+        StorageType::Nfs => {
+            let nfs = parse_nfs_path(&request.path).ok_or_else(invalid)?;
+            let command = ProbeCommand {
+                program: "showmount",
+                args: vec![
+                    "--exports".to_string(),
+                    "--no-headers".to_string(),
+                    "--".to_string(),
+                    nfs.server.clone(),
+                ],
+                env: vec![],
+            };
+            Ok((
+                command,
+                Expectation::Export {
+                    server: nfs.server,
+                    export: nfs.export,
+                },
+            ))
+        }
+        StorageType::Cifs => {
+            let cifs = parse_cifs_path(&request.path).ok_or_else(invalid)?;
+            let mut args = vec![format!("//{}/{}", cifs.server, cifs.share)];
+            let mut env = vec![];
+            match request.username {
+                Some(ref username) => {
+                    args.extend(["--user".to_string(), username.clone()]);
+                    env.push(("PASSWD", request.password.clone().unwrap_or_default()));
+                }
+                None => args.push("--no-pass".to_string()),
+            }
+            args.extend(["--command".to_string(), "exit".to_string()]);
+            let command = ProbeCommand {
+                program: "smbclient",
+                args,
+                env,
+            };
+            Ok((
+                command,
+                Expectation::Share {
+                    server: cifs.server,
+                    share: cifs.share,
+                },
+            ))
+        }
+        StorageType::Local => Err(StorageError::InvalidRequest(
+            "Only nfs and cifs storage can be tested".to_string(),
+        )),
+    }
+}
+
+fn interpret(expectation: &Expectation, output: &Output) -> StorageConnectionTestResult {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match expectation {
+        Expectation::Export { server, export } => {
+            if !output.status.success() {
+                return failure(format!(
+                    "{} did not list its exports: {}",
+                    server,
+                    diagnostic(output)
+                ));
+            }
+            let wanted = export.trim_end_matches('/');
+            let exports: Vec<&str> = stdout
+                .lines()
+                .filter_map(|line| line.split_whitespace().next())
+                .collect();
+            if exports
+                .iter()
+                .any(|listed| listed.trim_end_matches('/') == wanted)
+            {
+                success(format!("{} exports {}", server, export))
+            } else if exports.is_empty() {
+                failure(format!("{} has no exports", server))
+            } else {
+                failure(format!(
+                    "{} does not export {}; exports: {}",
+                    server,
+                    export,
+                    exports.join(", ")
+                ))
+            }
+        }
+        Expectation::Share { server, share } => {
+            if output.status.success() {
+                success(format!("Connected to //{}/{}", server, share))
+            } else {
+                failure(format!(
+                    "Could not connect to //{}/{}: {}",
+                    server,
+                    share,
+                    diagnostic(output)
+                ))
+            }
+        }
+    }
+}
+
+/// The last line the command printed about the failure; `smbclient` reports
+/// on stdout, `showmount` on stderr
+fn diagnostic(output: &Output) -> String {
+    [&output.stderr, &output.stdout]
+        .into_iter()
+        .filter_map(|stream| {
+            String::from_utf8_lossy(stream)
+                .lines()
+                .map(str::trim)
+                .rfind(|line| !line.is_empty())
+                .map(str::to_string)
+        })
+        .next()
+        .unwrap_or_else(|| format!("exited with {}", output.status))
+}
+
+fn success(message: String) -> StorageConnectionTestResult {
+    StorageConnectionTestResult {
+        success: true,
+        message,
+    }
+}
+
+fn failure(message: String) -> StorageConnectionTestResult {
+    StorageConnectionTestResult {
+        success: false,
+        message,
+    }
+}
+
+/// Run `command` on the host; it is killed if the caller stops waiting
+async fn run_probe(command: ProbeCommand) -> io::Result<Output> {
+    tokio::process::Command::new(command.program)
+        .args(&command.args)
+        .envs(command.env)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    fn request(storage_type: StorageType, path: &str) -> StorageConnectionTestRequest {
+        StorageConnectionTestRequest {
+            storage_type,
+            path: path.to_string(),
+            username: None,
+            password: None,
+        }
+    }
+
+    fn output(code: i32, stdout: &str, stderr: &str) -> Output {
+        Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    async fn test(
+        request: &StorageConnectionTestRequest,
+        result: io::Result<Output>,
+    ) -> StorageConnectionTestResult {
+        test_connection_with(request, CONNECTION_TEST_TIMEOUT, |_| async { result })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_nfs_export_is_found() {
+        let nfs = request(StorageType::Nfs, "nas:/exports/pool");
+        let listed = output(0, "/exports/pool 10.0.0.0/24\n/exports/media *\n", "");
+        let result = test(&nfs, Ok(listed)).await;
+        assert!(result.success, "{}", result.message);
+
+        let missing = output(0, "/exports/media *\n", "");
+        let result = test(&nfs, Ok(missing)).await;
+        assert!(!result.success);
+        assert!(
+            result.message.contains("/exports/media"),
+            "{}",
+            result.message
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_nfs_server() {
+        let nfs = request(StorageType::Nfs, "nas:/exports/pool");
+        let refused = output(
+            1,
+            "",
+            "clnt_create: RPC: Port mapper failure - Unable to receive: errno 111\n",
+        );
+        let result = test(&nfs, Ok(refused)).await;
+        assert!(!result.success);
+        assert!(
+            result.message.contains("Port mapper failure"),
+            "{}",
+            result.message
+        );
+
+        let result = test_connection_with(&nfs, Duration::from_millis(10), |_| async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(output(0, "/exports/pool *\n", ""))
+        })
+        .await
+        .unwrap();
+        assert!(!result.success);
+        assert!(result.message.contains("no answer"), "{}", result.message);
+
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        let result = test(&nfs, Err(missing)).await;
+        assert!(
+            result.message.contains("not installed"),
+            "{}",
+            result.message
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cifs_probe() {
+        let mut cifs = request(StorageType::Cifs, "//fileserver/data");
+        cifs.username = Some("backup".to_string());
+        cifs.password = Some("secret".to_string());
+
+        let (command, _) = probe_for(&cifs).unwrap();
+        assert_eq!(command.program, "smbclient");
+        assert!(!command.args.iter().any(|arg| arg.contains("secret")));
+        assert_eq!(command.env, vec![("PASSWD", "secret".to_string())]);
+
+        assert!(test(&cifs, Ok(output(0, "", ""))).await.success);
+        let denied = output(1, "session setup failed: NT_STATUS_LOGON_FAILURE\n", "");
+        let result = test(&cifs, Ok(denied)).await;
+        assert!(!result.success);
+        assert!(
+            result.message.contains("NT_STATUS_LOGON_FAILURE"),
+            "{}",
+            result.message
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_requests_are_rejected() {
+        for request in [
+            request(StorageType::Local, "/srv/pool"),
+            request(StorageType::Nfs, "//fileserver/data"),
+            request(StorageType::Cifs, "nas:/exports/pool"),
+        ] {
+            let result = test_connection_with(&request, CONNECTION_TEST_TIMEOUT, |_| async {
+                panic!("nothing should run for {:?}", request.path)
+            })
+            .await;
+            assert!(matches!(result, Err(StorageError::InvalidRequest(_))));
+        }
+    }
+}
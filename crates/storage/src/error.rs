@@ -5,6 +5,9 @@ pub enum StorageError {
     #[error("Storage pool not found: {0}")]
     PoolNotFound(String),
 
+    #[error("Invalid storage pool request: {0}")]
+    InvalidRequest(String),
+
     #[error("Volume not found: {0}")]
     VolumeNotFound(String),
 
@@ -1,6 +1,7 @@
 use anyhow::Result;
 use chrono::Utc;
-use models::{StoragePool, StorageType};
+use models::{PoolHealth, PoolState, StoragePool, StorageType, VolumeProvisioning};
+use nix::sys::statvfs::statvfs;
 use std::fs;
 use std::path::Path;
 use tracing::info;
@@ -8,6 +9,26 @@ use uuid::Uuid;
 
 use crate::error::StorageError;
 
+/// Space on a filesystem, as `df` reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilesystemUsage {
+    pub total_bytes: u64,
+    pub used_bytes: u64,
+    /// Free space usable without root; excludes the reserved blocks
+    pub available_bytes: u64,
+}
+
+impl FilesystemUsage {
+    /// Share of the usable space that is taken, like the `Use%` column of `df`
+    pub fn used_percent(&self) -> f64 {
+        let usable = self.used_bytes + self.available_bytes;
+        if usable == 0 {
+            return 0.0;
+        }
+        self.used_bytes as f64 * 100.0 / usable as f64
+    }
+}
+
 pub struct LocalStorageManager;
 
 impl LocalStorageManager {
@@ -20,58 +41,35 @@ impl LocalStorageManager {
         // Create directory if it doesn't exist
         fs::create_dir_all(pool_path).map_err(StorageError::Io)?;
 
-        // Get filesystem statistics
-        let (total_size, used_size) = Self::get_filesystem_stats(pool_path)?;
-        let available_size = total_size.saturating_sub(used_size);
+        let usage = Self::filesystem_usage(pool_path)?;
 
         Ok(StoragePool {
             id: Uuid::new_v4(),
             name: name.to_string(),
             storage_type: StorageType::Local,
             path: path.to_string(),
-            total_size,
-            used_size,
-            available_size,
+            total_size: usage.total_bytes,
+            used_size: usage.used_bytes,
+            available_size: usage.available_bytes,
             created_at: Utc::now(),
+            health: PoolHealth::Healthy,
+            provisioning: VolumeProvisioning::default(),
+            state: PoolState::default(),
         })
     }
 
-    /// Get filesystem statistics for a path
-    fn get_filesystem_stats(path: &Path) -> Result<(u64, u64), StorageError> {
-        // This is a simplified version - in production, use statvfs or similar
-        // For now, we'll use a basic approach
-        let _metadata = fs::metadata(path).map_err(StorageError::Io)?;
-
-        // Note: This doesn't give actual filesystem stats, but it's a placeholder
-        // In production, you'd use libc::statvfs or similar
-        let total_size = 100_000_000_000; // 100GB placeholder
-        let used_size = Self::calculate_directory_size(path)?;
-
-        Ok((total_size, used_size))
-    }
-
-    /// Calculate directory size recursively
-    fn calculate_directory_size(path: &Path) -> Result<u64, StorageError> {
-        let mut total = 0u64;
-
-        if path.is_dir() {
-            let entries = fs::read_dir(path).map_err(StorageError::Io)?;
-
-            for entry in entries {
-                let entry = entry.map_err(StorageError::Io)?;
-                let path = entry.path();
-
-                if path.is_dir() {
-                    total += Self::calculate_directory_size(&path)?;
-                } else {
-                    total += entry.metadata().map_err(StorageError::Io)?.len();
-                }
-            }
-        } else {
-            total = fs::metadata(path).map_err(StorageError::Io)?.len();
-        }
-
-        Ok(total)
+    /// Space on the filesystem holding `path`
+    pub fn filesystem_usage(path: &Path) -> Result<FilesystemUsage, StorageError> {
+        let stats = statvfs(path).map_err(|e| StorageError::Io(e.into()))?;
+        let fragment_size = stats.fragment_size() as u64;
+        let total_bytes = stats.blocks() as u64 * fragment_size;
+        let free_bytes = stats.blocks_free() as u64 * fragment_size;
+
+        Ok(FilesystemUsage {
+            total_bytes,
+            used_bytes: total_bytes.saturating_sub(free_bytes),
+            available_bytes: stats.blocks_available() as u64 * fragment_size,
+        })
     }
 
     /// Delete a storage pool
@@ -87,3 +85,34 @@ impl LocalStorageManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_used_percent_ignores_reserved_blocks() {
+        let usage = FilesystemUsage {
+            total_bytes: 100,
+            used_bytes: 45,
+            available_bytes: 45,
+        };
+        assert_eq!(usage.used_percent(), 50.0);
+
+        let empty = FilesystemUsage {
+            total_bytes: 0,
+            used_bytes: 0,
+            available_bytes: 0,
+        };
+        assert_eq!(empty.used_percent(), 0.0);
+    }
+
+    #[test]
+    fn test_filesystem_usage_of_existing_path() {
+        let usage = LocalStorageManager::filesystem_usage(&std::env::temp_dir()).unwrap();
+        assert!(usage.total_bytes > 0);
+        assert!(usage.used_bytes + usage.available_bytes <= usage.total_bytes);
+
+        assert!(LocalStorageManager::filesystem_usage(Path::new("/nonexistent/pool")).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+/// Mount options of storage pools
+///
+/// Options are checked against the keys each storage type understands, so a
+/// typo fails at config load instead of at mount time, and keys that would
+/// let a share run setuid binaries, expose device nodes or remount other
+/// filesystems are refused outright. Shared pools are always mounted
+/// `nosuid,nodev`.
+use std::path::Path;
+
+use models::StorageType;
+use tracing::info;
+
+use crate::error::StorageError;
+
+/// Options understood by `mount.nfs`
+const NFS_OPTIONS: &[&str] = &[
+    "vers",
+    "nfsvers",
+    "proto",
+    "port",
+    "mountport",
+    "rsize",
+    "wsize",
+    "timeo",
+    "retrans",
+    "retry",
+    "hard",
+    "soft",
+    "intr",
+    "nointr",
+    "ro",
+    "rw",
+    "noatime",
+    "relatime",
+    "nodiratime",
+    "lookupcache",
+    "lock",
+    "nolock",
+    "local_lock",
+    "ac",
+    "noac",
+    "actimeo",
+    "acregmin",
+    "acregmax",
+    "acdirmin",
+    "acdirmax",
+    "sec",
+    "nconnect",
+    "fsc",
+    "sync",
+    "async",
+    "noresvport",
+    "clientaddr",
+    "_netdev",
+];
+
+/// Options understood by `mount.cifs`; the password belongs in a
+/// `credentials` file, where it does not show up in the process list
+const CIFS_OPTIONS: &[&str] = &[
+    "vers",
+    "sec",
+    "username",
+    "user",
+    "domain",
+    "dom",
+    "credentials",
+    "uid",
+    "gid",
+    "forceuid",
+    "forcegid",
+    "file_mode",
+    "dir_mode",
+    "port",
+    "rsize",
+    "wsize",
+    "ro",
+    "rw",
+    "noatime",
+    "relatime",
+    "cache",
+    "iocharset",
+    "seal",
+    "nobrl",
+    "hard",
+    "soft",
+    "actimeo",
+    "serverino",
+    "noserverino",
+    "nounix",
+    "mfsymlinks",
+    "_netdev",
+];
+
+/// Hints for the filesystem backing a local pool
+const LOCAL_OPTIONS: &[&str] = &[
+    "noatime",
+    "relatime",
+    "strictatime",
+    "lazytime",
+    "nodiratime",
+    "discard",
+    "nodiscard",
+    "ro",
+    "rw",
+];
+
+/// Refused for every storage type
+const DANGEROUS_OPTIONS: &[&str] = &[
+    "suid", "dev", "exec", "remount", "bind", "rbind", "move", "helper", "password", "pass",
+];
+
+/// Check `options` (`key` or `key=value` each) against what `storage_type`
+/// understands
+pub fn validate_mount_options(
+    storage_type: &StorageType,
+    options: &[String],
+) -> Result<(), StorageError> {
+    let known = match storage_type {
+        StorageType::Local => LOCAL_OPTIONS,
+        StorageType::Nfs => NFS_OPTIONS,
+        StorageType::Cifs => CIFS_OPTIONS,
+    };
+    for option in options {
+        let (key, value) = match option.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (option.as_str(), None),
+        };
+        let invalid = |reason: String| {
+            StorageError::InvalidRequest(format!("Mount option {:?} {}", option, reason))
+        };
+        if DANGEROUS_OPTIONS.contains(&key) {
+            return Err(invalid("is not allowed".to_string()));
+        }
+        if !known.contains(&key) {
+            return Err(invalid(format!(
+                "is not a known {:?} mount option",
+                storage_type
+            )));
+        }
+        if value.is_some_and(|value| {
+            value.is_empty()
+                || value.contains(|c: char| c == ',' || c.is_whitespace() || c.is_control())
+        }) {
+            return Err(invalid("has an invalid value".to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// The `-o` argument for mounting a pool with `options`
+pub fn mount_option_string(
+    storage_type: &StorageType,
+    options: &[String],
+) -> Result<String, StorageError> {
+    validate_mount_options(storage_type, options)?;
+    let mut all: Vec<&str> = options.iter().map(String::as_str).collect();
+    if matches!(storage_type, StorageType::Nfs | StorageType::Cifs) {
+        all.extend(["nosuid", "nodev"]);
+    }
+    Ok(all.join(","))
+}
+
+/// `mount` arguments for mounting the share `source` on `target`
+pub fn mount_args(
+    storage_type: &StorageType,
+    source: &str,
+    target: &Path,
+    options: &[String],
+) -> Result<Vec<String>, StorageError> {
+    let fs_type = match storage_type {
+        StorageType::Nfs => "nfs",
+        StorageType::Cifs => "cifs",
+        StorageType::Local => {
+            return Err(StorageError::InvalidRequest(
+                "Local pools are not mounted".to_string(),
+            ))
+        }
+    };
+    Ok(vec![
+        "-t".to_string(),
+        fs_type.to_string(),
+        "-o".to_string(),
+        mount_option_string(storage_type, options)?,
+        "--".to_string(),
+        source.to_string(),
+        target.display().to_string(),
+    ])
+}
+
+/// Mount the share `source` on `target`, creating `target` first
+pub async fn mount_pool(
+    storage_type: &StorageType,
+    source: &str,
+    target: &Path,
+    options: &[String],
+) -> Result<(), StorageError> {
+    let args = mount_args(storage_type, source, target, options)?;
+    std::fs::create_dir_all(target)?;
+    info!("Mounting {} on {}", source, target.display());
+    let output = tokio::process::Command::new("mount")
+        .args(&args)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(StorageError::OperationFailed(format!(
+            "mount {} failed: {}",
+            source,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Whether something is mounted exactly on `path`
+pub fn is_mount_point(path: &Path) -> bool {
+    std::fs::read_to_string("/proc/self/mounts")
+        .map(|mounts| {
+            mounts
+                .lines()
+                .filter_map(|line| line.split_whitespace().nth(1))
+                .any(|target| Path::new(target) == path)
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(options: &[&str]) -> Vec<String> {
+        options.iter().map(|o| o.to_string()).collect()
+    }
+
+    #[test]
+    fn test_nfs_options_are_assembled() {
+        let args = mount_args(
+            &StorageType::Nfs,
+            "nas:/exports/pool",
+            Path::new("/srv/pools/shared"),
+            &options(&["vers=4.1", "hard", "rsize=1048576"]),
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            [
+                "-t",
+                "nfs",
+                "-o",
+                "vers=4.1,hard,rsize=1048576,nosuid,nodev",
+                "--",
+                "nas:/exports/pool",
+                "/srv/pools/shared"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_options_are_checked_per_type() {
+        assert!(validate_mount_options(&StorageType::Cifs, &options(&["domain=corp"])).is_ok());
+        assert!(validate_mount_options(&StorageType::Local, &options(&["noatime"])).is_ok());
+        // A CIFS option on an NFS pool, and a typo
+        assert!(validate_mount_options(&StorageType::Nfs, &options(&["domain=corp"])).is_err());
+        assert!(validate_mount_options(&StorageType::Nfs, &options(&["vesr=4"])).is_err());
+
+        for dangerous in ["suid", "exec", "dev", "password=hunter2", "remount"] {
+            let result = validate_mount_options(&StorageType::Cifs, &options(&[dangerous]));
+            assert!(
+                matches!(result, Err(StorageError::InvalidRequest(ref e)) if e.contains("not allowed")),
+                "{} should be refused",
+                dangerous
+            );
+        }
+        // Values cannot smuggle in further options
+        assert!(validate_mount_options(&StorageType::Nfs, &options(&["vers=4,suid"])).is_err());
+        assert!(validate_mount_options(&StorageType::Nfs, &options(&["vers="])).is_err());
+        assert!(mount_args(&StorageType::Local, "/dev/sdb", Path::new("/srv"), &[]).is_err());
+    }
+}
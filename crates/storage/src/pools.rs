@@ -0,0 +1,149 @@
+use crate::error::StorageError;
+use crate::local::LocalStorageManager;
+use crate::mount::validate_mount_options;
+use crate::shared::SharedStorageManager;
+use models::{CreateStoragePoolRequest, PoolState, StoragePool, StoragePoolBackend, Validate};
+
+/// Check a pool request before anything is created or mounted
+pub fn validate_pool_request(request: &CreateStoragePoolRequest) -> Result<(), StorageError> {
+    request
+        .validate()
+        .map_err(|e| StorageError::InvalidRequest(e.to_string()))?;
+    if let StoragePoolBackend::Nfs { ref options, .. } = request.backend {
+        validate_mount_options(&request.backend.storage_type(), options)?;
+    }
+    Ok(())
+}
+
+/// Validate the request and create the pool with the backend it selects
+pub async fn create_pool(request: &CreateStoragePoolRequest) -> Result<StoragePool, StorageError> {
+    validate_pool_request(request)?;
+
+    let pool = match request.backend {
+        StoragePoolBackend::Local { ref path } => {
+            LocalStorageManager::create_pool(&request.name, path).await
+        }
+        StoragePoolBackend::Nfs {
+            ref server,
+            ref export,
+            ref options,
+        } => SharedStorageManager::create_nfs_pool(&request.name, server, export, options).await,
+        StoragePoolBackend::Cifs {
+            ref server,
+            ref share,
+            ref username,
+            ref password,
+        } => {
+            SharedStorageManager::create_cifs_pool(
+                &request.name,
+                server,
+                share,
+                username.as_deref(),
+                password.as_deref(),
+            )
+            .await
+        }
+    }?;
+    Ok(StoragePool {
+        provisioning: request.provisioning,
+        state: PoolState::default(),
+        ..pool
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: serde_json::Value) -> CreateStoragePoolRequest {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_legacy_flat_requests_are_accepted() {
+        let nfs = parse(serde_json::json!({
+            "name": "shared",
+            "storage_type": "nfs",
+            "path": "10.0.0.5:/exports/pool"
+        }));
+        assert_eq!(
+            nfs.backend,
+            StoragePoolBackend::Nfs {
+                server: "10.0.0.5".to_string(),
+                export: "/exports/pool".to_string(),
+                options: vec![],
+            }
+        );
+
+        let cifs = parse(serde_json::json!({
+            "name": "office",
+            "storage_type": "cifs",
+            "path": "//fileserver/data"
+        }));
+        assert_eq!(
+            cifs.backend,
+            StoragePoolBackend::Cifs {
+                server: "fileserver".to_string(),
+                share: "data".to_string(),
+                username: None,
+                password: None,
+            }
+        );
+
+        for (storage_type, path) in [("cifs", "fileserver/data"), ("nfs", ":/export")] {
+            let malformed = serde_json::from_value::<CreateStoragePoolRequest>(serde_json::json!({
+                "name": "office",
+                "storage_type": storage_type,
+                "path": path
+            }));
+            assert!(malformed.is_err(), "{} should be rejected", path);
+        }
+    }
+
+    #[test]
+    fn test_typed_request_round_trips() {
+        let request = parse(serde_json::json!({
+            "name": "shared",
+            "storage_type": "nfs",
+            "server": "nas",
+            "export": "/pool",
+            "options": ["vers=4.1", "ro"]
+        }));
+        assert!(validate_pool_request(&request).is_ok());
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["storage_type"], "nfs");
+        assert_eq!(parse(json).backend, request.backend);
+    }
+
+    #[test]
+    fn test_validation_rejects_bad_backends() {
+        let cases = [
+            serde_json::json!({"name": "p", "storage_type": "local", "path": "relative"}),
+            serde_json::json!({"name": "p", "storage_type": "local", "path": "/proc/pool"}),
+            serde_json::json!({"name": "Bad_Name", "storage_type": "local", "path": "/srv/p"}),
+            serde_json::json!({"name": "p", "storage_type": "nfs", "server": "nas",
+                "export": "/pool", "options": ["ro,soft"]}),
+            serde_json::json!({"name": "p", "storage_type": "nfs", "server": "nas",
+                "export": "/pool", "options": ["suid"]}),
+            serde_json::json!({"name": "p", "storage_type": "cifs", "server": "nas",
+                "share": "data", "password": "secret"}),
+        ];
+        for case in cases {
+            let request = parse(case.clone());
+            assert!(
+                matches!(
+                    validate_pool_request(&request),
+                    Err(StorageError::InvalidRequest(_))
+                ),
+                "{} should be rejected",
+                case
+            );
+        }
+
+        let local = parse(serde_json::json!({
+            "name": "p", "storage_type": "local", "path": "/srv/pools/p"
+        }));
+        assert!(validate_pool_request(&local).is_ok());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::error::StorageError;
+use crate::mount::mount_option_string;
 use chrono::Utc;
-use models::{StoragePool, StorageType};
+use models::{PoolHealth, PoolState, StoragePool, StorageType, VolumeProvisioning};
 use tracing::info;
 use uuid::Uuid;
 
@@ -12,8 +13,13 @@ impl SharedStorageManager {
         name: &str,
         server: &str,
         path: &str,
+        options: &[String],
     ) -> Result<StoragePool, StorageError> {
-        info!("Creating NFS storage pool: {} at {}:{}", name, server, path);
+        let options = mount_option_string(&StorageType::Nfs, options)?;
+        info!(
+            "Creating NFS storage pool: {} at {}:{} (options: {})",
+            name, server, path, options
+        );
 
         // In production, this would mount the NFS share and verify it
         // For now, we'll create a placeholder pool
@@ -27,6 +33,9 @@ impl SharedStorageManager {
             used_size: 0,
             available_size: 0,
             created_at: Utc::now(),
+            health: PoolHealth::Healthy,
+            provisioning: VolumeProvisioning::default(),
+            state: PoolState::default(),
         })
     }
 
@@ -36,6 +45,7 @@ impl SharedStorageManager {
         server: &str,
         share: &str,
         _username: Option<&str>,
+        _password: Option<&str>,
     ) -> Result<StoragePool, StorageError> {
         info!(
             "Creating CIFS storage pool: {} at {}:{}",
@@ -52,6 +62,9 @@ impl SharedStorageManager {
             used_size: 0,
             available_size: 0,
             created_at: Utc::now(),
+            health: PoolHealth::Healthy,
+            provisioning: VolumeProvisioning::default(),
+            state: PoolState::default(),
         })
     }
 }
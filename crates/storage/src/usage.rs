@@ -0,0 +1,218 @@
+/// Used space of volumes and container root filesystems
+///
+/// A volume that is a filesystem of its own, such as an image or subvolume
+/// mounted into the pool, is measured like `df`, which is cheap. Plain
+/// directories have to be walked, so callers get the last measurement with
+/// its timestamp and a new walk starts in the background once that is older
+/// than [`MAX_USAGE_AGE`]. The pool usage monitor refreshes every volume on
+/// its own schedule, so the cache is usually warm.
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::error::StorageError;
+use crate::local::LocalStorageManager;
+
+/// How long a directory walk is served before it is redone
+pub const MAX_USAGE_AGE: Duration = Duration::from_secs(300);
+
+/// Last finished walk of each directory
+static MEASUREMENTS: LazyLock<Mutex<HashMap<PathBuf, Measurement>>> =
+    LazyLock::new(Default::default);
+
+/// Directories currently being walked in the background
+static WALKS: LazyLock<Mutex<HashSet<PathBuf>>> = LazyLock::new(Default::default);
+
+#[derive(Debug, Clone, Copy)]
+struct Measurement {
+    used_bytes: u64,
+    measured_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DiskUsage {
+    /// None until the first measurement has finished
+    pub used_bytes: Option<u64>,
+    pub measured_at: Option<DateTime<Utc>>,
+    /// The value is older than [`MAX_USAGE_AGE`] or missing, and a new
+    /// measurement is under way
+    pub stale: bool,
+}
+
+/// Used space under `path`, without blocking on a directory walk
+pub fn disk_usage(path: &Path) -> DiskUsage {
+    if is_mount_point(path) {
+        match LocalStorageManager::filesystem_usage(path) {
+            Ok(usage) => {
+                return DiskUsage {
+                    used_bytes: Some(usage.used_bytes),
+                    measured_at: Some(Utc::now()),
+                    stale: false,
+                }
+            }
+            Err(e) => warn!("Could not stat {}: {}", path.display(), e),
+        }
+    }
+
+    let cached = MEASUREMENTS.lock().unwrap().get(path).copied();
+    let fresh = cached.is_some_and(|cached| {
+        (Utc::now() - cached.measured_at)
+            .to_std()
+            .map_or(true, |age| age < MAX_USAGE_AGE)
+    });
+    if !fresh {
+        schedule_walk(path.to_path_buf());
+    }
+    DiskUsage {
+        used_bytes: cached.map(|cached| cached.used_bytes),
+        measured_at: cached.map(|cached| cached.measured_at),
+        stale: !fresh,
+    }
+}
+
+/// Walk `path` now and cache the result; blocks for the whole walk
+pub fn refresh_disk_usage(path: &Path) -> Result<u64, StorageError> {
+    let used_bytes = directory_size(path)?;
+    MEASUREMENTS.lock().unwrap().insert(
+        path.to_path_buf(),
+        Measurement {
+            used_bytes,
+            measured_at: Utc::now(),
+        },
+    );
+    Ok(used_bytes)
+}
+
+/// Refresh every volume in the pool at `pool_path`; returns how many were
+/// measured
+///
+/// Volumes that are mount points are skipped, as they are measured on
+/// request.
+pub fn refresh_volume_usage(pool_path: &Path) -> Result<usize, StorageError> {
+    let mut measured = 0;
+    for entry in fs::read_dir(pool_path).map_err(StorageError::Io)? {
+        let path = entry.map_err(StorageError::Io)?.path();
+        if !path.is_dir() || is_mount_point(&path) {
+            continue;
+        }
+        match refresh_disk_usage(&path) {
+            Ok(_) => measured += 1,
+            Err(e) => warn!("Could not measure volume {}: {}", path.display(), e),
+        }
+    }
+    Ok(measured)
+}
+
+fn schedule_walk(path: PathBuf) {
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    if !WALKS.lock().unwrap().insert(path.clone()) {
+        return;
+    }
+
+    handle.spawn_blocking(move || {
+        if let Err(e) = refresh_disk_usage(&path) {
+            warn!("Could not measure {}: {}", path.display(), e);
+        }
+        WALKS.lock().unwrap().remove(&path);
+    });
+}
+
+/// True when `path` is on a different filesystem than its parent
+fn is_mount_point(path: &Path) -> bool {
+    let Some(parent) = path.parent() else {
+        return false;
+    };
+    match (fs::metadata(path), fs::metadata(parent)) {
+        (Ok(own), Ok(parent)) => own.dev() != parent.dev(),
+        _ => false,
+    }
+}
+
+/// Total size of the files under `path`, not following symlinks
+pub(crate) fn directory_size(path: &Path) -> Result<u64, StorageError> {
+    let metadata = fs::symlink_metadata(path).map_err(StorageError::Io)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut total = 0u64;
+    for entry in fs::read_dir(path).map_err(StorageError::Io)? {
+        total += directory_size(&entry.map_err(StorageError::Io)?.path())?;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn volume(files: &[(&str, usize)]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("orchestrator_usage_{}", Uuid::new_v4()));
+        fs::create_dir_all(path.join("nested")).unwrap();
+        for (name, size) in files {
+            fs::write(path.join(name), vec![0u8; *size]).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn test_refresh_caches_the_walk() {
+        let path = volume(&[("a", 100), ("nested/b", 50)]);
+        std::os::unix::fs::symlink("/", path.join("root")).unwrap();
+
+        // The link counts with its own length, not with what it points to
+        assert_eq!(refresh_disk_usage(&path).unwrap(), 151);
+        let usage = disk_usage(&path);
+        assert_eq!(usage.used_bytes, Some(151));
+        assert!(!usage.stale);
+
+        let _ = fs::remove_dir_all(&path);
+    }
+
+    #[tokio::test]
+    async fn test_unmeasured_directory_is_walked_in_the_background() {
+        let path = volume(&[("a", 10)]);
+
+        let usage = disk_usage(&path);
+        assert_eq!(usage.used_bytes, None);
+        assert!(usage.stale);
+
+        let mut usage = usage;
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            usage = disk_usage(&path);
+            if usage.used_bytes.is_some() {
+                break;
+            }
+        }
+        assert_eq!(usage.used_bytes, Some(10));
+        assert!(!usage.stale);
+        assert!(usage.measured_at.is_some());
+
+        let _ = fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_pool_refresh_measures_each_volume() {
+        let pool = volume(&[]);
+        fs::remove_dir(pool.join("nested")).unwrap();
+        for name in ["data", "logs"] {
+            fs::create_dir_all(pool.join(name)).unwrap();
+            fs::write(pool.join(name).join("file"), b"12345").unwrap();
+        }
+
+        assert_eq!(refresh_volume_usage(&pool).unwrap(), 2);
+        assert_eq!(disk_usage(&pool.join("logs")).used_bytes, Some(5));
+
+        let _ = fs::remove_dir_all(&pool);
+    }
+}
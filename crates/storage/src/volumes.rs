@@ -1,46 +1,100 @@
 use crate::error::StorageError;
+use crate::local::LocalStorageManager;
+use crate::usage::disk_usage;
 use anyhow::Result;
 use chrono::Utc;
-use models::Volume;
+use container_manager::config::LxcConfig;
+use models::{Volume, VolumeProvisioning};
+use nix::fcntl::posix_fallocate;
 use std::fs;
-use std::path::Path;
-use tracing::info;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
 use uuid::Uuid;
 
+/// Backing file inside a volume's directory, holding its data
+pub const VOLUME_IMAGE: &str = "volume.img";
+
 pub struct VolumeManager;
 
 impl VolumeManager {
     /// Create a new volume in a storage pool
+    ///
+    /// The volume is a directory with a backing file of `size` bytes. A thin
+    /// file is sparse; a thick one is preallocated, so it is refused up front
+    /// when the pool's filesystem does not have `size` bytes free.
     pub async fn create_volume(
         pool_path: &str,
         name: &str,
         size: u64,
+        provisioning: VolumeProvisioning,
     ) -> Result<Volume, StorageError> {
         info!(
-            "Creating volume: {} in pool {} (size: {} bytes)",
-            name, pool_path, size
+            "Creating {:?} volume: {} in pool {} (size: {} bytes)",
+            provisioning, name, pool_path, size
         );
 
-        let volume_path = Path::new(pool_path).join(name);
+        if provisioning == VolumeProvisioning::Thick {
+            let available =
+                LocalStorageManager::filesystem_usage(Path::new(pool_path))?.available_bytes;
+            if size > available {
+                return Err(StorageError::InsufficientSpace(size, available));
+            }
+        }
 
-        // Create volume directory
+        let volume_path = Path::new(pool_path).join(name);
         fs::create_dir_all(&volume_path).map_err(StorageError::Io)?;
 
-        // In production, you might create a sparse file or use other volume management
-        // For now, we'll just create the directory
+        let image_path = volume_path.join(VOLUME_IMAGE);
+        if let Err(e) = Self::create_image(&image_path, size, provisioning) {
+            let _ = fs::remove_dir_all(&volume_path);
+            return Err(e);
+        }
 
         Ok(Volume {
             id: Uuid::new_v4(),
             name: name.to_string(),
             pool_id: Uuid::new_v4(), // In production, get from pool
             size,
-            used: 0,
+            used: allocated_bytes(&image_path)?,
+            used_measured_at: Some(Utc::now()),
             created_at: Utc::now(),
+            provisioning,
         })
     }
 
+    /// Create the backing file at `path`, sparse or preallocated
+    fn create_image(
+        path: &Path,
+        size: u64,
+        provisioning: VolumeProvisioning,
+    ) -> Result<(), StorageError> {
+        let file = fs::File::create(path).map_err(StorageError::Io)?;
+        match provisioning {
+            VolumeProvisioning::Thin => file.set_len(size).map_err(StorageError::Io),
+            VolumeProvisioning::Thick => {
+                let len = i64::try_from(size).map_err(|_| {
+                    StorageError::InvalidRequest(format!("volume size {} is too large", size))
+                })?;
+                if len == 0 {
+                    return Ok(());
+                }
+                posix_fallocate(file.as_raw_fd(), 0, len).map_err(|e| StorageError::Io(e.into()))
+            }
+        }
+    }
+
     /// Delete a volume
-    pub async fn delete_volume(pool_path: &str, name: &str) -> Result<(), StorageError> {
+    ///
+    /// A volume bind-mounted into a container is refused unless `force` is
+    /// set, as removing it would take the data from under that container.
+    /// Nothing is removed before the check, so a refused call can be retried.
+    pub async fn delete_volume(
+        pool_path: &str,
+        name: &str,
+        force: bool,
+    ) -> Result<(), StorageError> {
         info!("Deleting volume: {} from pool {}", name, pool_path);
 
         let volume_path = Path::new(pool_path).join(name);
@@ -49,11 +103,54 @@ impl VolumeManager {
             return Err(StorageError::VolumeNotFound(name.to_string()));
         }
 
+        let containers = Self::mounted_by(&volume_path)?;
+        if !containers.is_empty() {
+            if !force {
+                return Err(StorageError::OperationFailed(format!(
+                    "volume in use by {}",
+                    containers.join(", ")
+                )));
+            }
+            warn!(
+                "Force-deleting volume {} still mounted by {}",
+                name,
+                containers.join(", ")
+            );
+        }
+
         fs::remove_dir_all(&volume_path).map_err(StorageError::Io)?;
 
         Ok(())
     }
 
+    /// Containers whose config bind-mounts `volume_path` or a directory
+    /// inside it, running or not
+    pub fn mounted_by(volume_path: &Path) -> Result<Vec<String>, StorageError> {
+        let entries = match fs::read_dir(LxcConfig::lxc_root()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::Io(e)),
+        };
+        let volume = canonical(volume_path);
+
+        let mut containers = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(StorageError::Io)?;
+            // Anything without a readable config is not a container
+            let Ok(content) = fs::read_to_string(entry.path().join("config")) else {
+                continue;
+            };
+            if LxcConfig::parse_mount_entries(&content)
+                .iter()
+                .any(|mount| canonical(Path::new(&mount.source)).starts_with(&volume))
+            {
+                containers.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        containers.sort();
+        Ok(containers)
+    }
+
     /// Get volume information
     pub async fn get_volume(pool_path: &str, name: &str) -> Result<Volume, StorageError> {
         let volume_path = Path::new(pool_path).join(name);
@@ -62,39 +159,156 @@ impl VolumeManager {
             return Err(StorageError::VolumeNotFound(name.to_string()));
         }
 
-        // Calculate used space
-        let used = Self::calculate_directory_size(&volume_path)?;
+        let image = fs::metadata(volume_path.join(VOLUME_IMAGE)).ok();
+        let (size, used, used_measured_at, provisioning) = match image {
+            // The backing file's blocks are what the volume takes; a thin
+            // file that has been written in full reads as thick
+            Some(image) => {
+                let allocated = image.blocks() * 512;
+                let provisioning = if allocated >= image.len() {
+                    VolumeProvisioning::Thick
+                } else {
+                    VolumeProvisioning::Thin
+                };
+                (image.len(), allocated, Some(Utc::now()), provisioning)
+            }
+            // Walks run in the background; until one finishes `used` is 0
+            None => {
+                let usage = disk_usage(&volume_path);
+                (
+                    0, // In production, get from metadata
+                    usage.used_bytes.unwrap_or(0),
+                    usage.measured_at,
+                    VolumeProvisioning::default(),
+                )
+            }
+        };
 
         Ok(Volume {
             id: Uuid::new_v4(), // In production, get from database
             name: name.to_string(),
             pool_id: Uuid::new_v4(),
-            size: 0, // In production, get from metadata
+            size,
             used,
+            used_measured_at,
             created_at: Utc::now(),
+            provisioning,
         })
     }
+}
 
-    fn calculate_directory_size(path: &Path) -> Result<u64, StorageError> {
-        let mut total = 0u64;
+/// Bytes actually allocated for the file at `path`
+fn allocated_bytes(path: &Path) -> Result<u64, StorageError> {
+    // `st_blocks` counts 512-byte units whatever the filesystem's block size
+    Ok(fs::metadata(path).map_err(StorageError::Io)?.blocks() * 512)
+}
 
-        if path.is_dir() {
-            let entries = fs::read_dir(path).map_err(StorageError::Io)?;
+/// `path` with symlinks and `..` resolved where it exists, so differently
+/// spelled mount sources still match
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
 
-            for entry in entries {
-                let entry = entry.map_err(StorageError::Io)?;
-                let path = entry.path();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_support::FakeHost;
 
-                if path.is_dir() {
-                    total += Self::calculate_directory_size(&path)?;
-                } else {
-                    total += entry.metadata().map_err(StorageError::Io)?.len();
-                }
+    #[tokio::test]
+    async fn test_thick_volumes_reserve_their_size() {
+        let pool = std::env::temp_dir().join(format!("orchestrator_pool_{}", Uuid::new_v4()));
+        fs::create_dir_all(&pool).unwrap();
+        let pool_path = pool.to_str().unwrap();
+        let size = 8 * 1024 * 1024;
+
+        let thick =
+            VolumeManager::create_volume(pool_path, "thick", size, VolumeProvisioning::Thick)
+                .await
+                .unwrap();
+        assert!(thick.used >= size, "thick volume only took {}", thick.used);
+        let thin = VolumeManager::create_volume(pool_path, "thin", size, VolumeProvisioning::Thin)
+            .await
+            .unwrap();
+        assert!(thin.used < size / 100, "thin volume took {}", thin.used);
+
+        for (name, provisioning) in [
+            ("thick", VolumeProvisioning::Thick),
+            ("thin", VolumeProvisioning::Thin),
+        ] {
+            let volume = VolumeManager::get_volume(pool_path, name).await.unwrap();
+            assert_eq!(volume.size, size);
+            assert_eq!(volume.provisioning, provisioning);
+        }
+
+        // More than the filesystem has free is refused before anything is made
+        match VolumeManager::create_volume(
+            pool_path,
+            "huge",
+            u64::MAX / 2,
+            VolumeProvisioning::Thick,
+        )
+        .await
+        {
+            Err(StorageError::InsufficientSpace(requested, _)) => {
+                assert_eq!(requested, u64::MAX / 2)
             }
-        } else {
-            total = fs::metadata(path).map_err(StorageError::Io)?.len();
+            other => panic!("expected insufficient space, got {:?}", other),
+        }
+        assert!(!pool.join("huge").exists());
+
+        let _ = fs::remove_dir_all(&pool);
+    }
+
+    #[tokio::test]
+    async fn test_mounted_volume_is_protected_from_deletion() {
+        let host = FakeHost::new("volumes");
+        let base = &host.base;
+        let pool = base.join("pool");
+        let lxc_root = base.join("lxc");
+        fs::create_dir_all(&pool).unwrap();
+        fs::create_dir_all(lxc_root.join("web")).unwrap();
+        host.env("LXC_ROOT", &lxc_root);
+        let pool_path = pool.to_str().unwrap();
+
+        for name in ["data", "data2", "scratch"] {
+            VolumeManager::create_volume(pool_path, name, 1024, VolumeProvisioning::Thin)
+                .await
+                .unwrap();
         }
+        // A subdirectory of data is mounted; data2 only shares its prefix
+        fs::create_dir_all(pool.join("data/uploads")).unwrap();
+        fs::write(
+            lxc_root.join("web/config"),
+            format!(
+                "lxc.uts.name = web\n\
+                 lxc.mount.entry = {}/data/uploads srv/uploads none bind,create=dir 0 0\n",
+                pool.display()
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(
+            VolumeManager::mounted_by(&pool.join("data")).unwrap(),
+            ["web"]
+        );
+        match VolumeManager::delete_volume(pool_path, "data", false).await {
+            Err(StorageError::OperationFailed(message)) => {
+                assert_eq!(message, "volume in use by web");
+            }
+            other => panic!("expected the volume to be in use, got {:?}", other),
+        }
+        assert!(pool.join("data/uploads").exists());
+
+        VolumeManager::delete_volume(pool_path, "data2", false)
+            .await
+            .unwrap();
+        VolumeManager::delete_volume(pool_path, "scratch", false)
+            .await
+            .unwrap();
 
-        Ok(total)
+        VolumeManager::delete_volume(pool_path, "data", true)
+            .await
+            .unwrap();
+        assert!(!pool.join("data").exists());
     }
 }
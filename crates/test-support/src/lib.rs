@@ -0,0 +1,101 @@
+/// Fake host tools for the workspace's tests
+///
+/// A [`FakeHost`] puts a directory of fake `lxc-*`, `ip` or `iptables`
+/// scripts first on `PATH` and points `LXC_ROOT` at a temporary directory.
+/// Both are process-wide, so a `FakeHost` holds a lock for as long as it
+/// lives: tests using one run one at a time, even within a single test binary.
+use std::cell::RefCell;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use uuid::Uuid;
+
+static HOST: Mutex<()> = Mutex::new(());
+
+/// A temporary LXC root with a `bin/` of fake tools first on `PATH`;
+/// dropping it restores the environment and removes the directory
+pub struct FakeHost {
+    pub base: PathBuf,
+    pub bin: PathBuf,
+    /// Variables to put back on drop, with their previous values
+    saved: RefCell<Vec<(String, Option<OsString>)>>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl FakeHost {
+    /// Wait for any other `FakeHost` to go, then create
+    /// `<tmp>/orchestrator_<prefix>_<uuid>/bin`, put it first on `PATH` and
+    /// point `LXC_ROOT` at its parent
+    pub fn new(prefix: &str) -> Self {
+        // A test that panicked while holding the lock still restored the
+        // environment when its host dropped
+        let lock = HOST.lock().unwrap_or_else(PoisonError::into_inner);
+        let base = std::env::temp_dir().join(format!("orchestrator_{}_{}", prefix, Uuid::new_v4()));
+        let bin = base.join("bin");
+        fs::create_dir_all(&bin).expect("create bin dir");
+        let host = Self {
+            base,
+            bin,
+            saved: RefCell::new(Vec::new()),
+            _lock: lock,
+        };
+        let path = std::env::var("PATH").unwrap_or_default();
+        host.env("PATH", format!("{}:{}", host.bin.display(), path));
+        host.env("LXC_ROOT", &host.base);
+        host
+    }
+
+    /// Set `key` until the host drops; fake scripts read their state and
+    /// failure switches from such variables
+    pub fn env(&self, key: &str, value: impl AsRef<OsStr>) {
+        self.save_env(key);
+        std::env::set_var(key, value);
+    }
+
+    /// Unset `key` until the host drops
+    pub fn remove_env(&self, key: &str) {
+        self.save_env(key);
+        std::env::remove_var(key);
+    }
+
+    fn save_env(&self, key: &str) {
+        let mut saved = self.saved.borrow_mut();
+        if !saved.iter().any(|(saved_key, _)| saved_key == key) {
+            saved.push((key.to_string(), std::env::var_os(key)));
+        }
+    }
+
+    /// Write the executable `bin/<name>`, replacing any earlier version
+    pub fn script(&self, name: &str, content: &str) {
+        write_script(&self.bin.join(name), content);
+    }
+
+    /// Write `bin/<name>` as a shell script running `body`
+    pub fn sh(&self, name: &str, body: &str) {
+        self.script(name, &format!("#!/bin/sh\n{}\n", body));
+    }
+}
+
+impl Drop for FakeHost {
+    fn drop(&mut self) {
+        for (key, value) in self.saved.get_mut().drain(..).rev() {
+            match value {
+                Some(value) => std::env::set_var(key, value),
+                None => std::env::remove_var(key),
+            }
+        }
+        let _ = fs::remove_dir_all(&self.base);
+    }
+}
+
+/// Write an executable script to `path`
+pub fn write_script(path: &Path, content: &str) {
+    fs::write(path, content).expect("write script");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}